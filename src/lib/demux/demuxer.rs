@@ -0,0 +1,3323 @@
+use std::ffi::{c_char, c_void, CStr};
+use std::io::{self, Cursor};
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::time::{Duration, SystemTime};
+
+use crate::ffi;
+use crate::ffi::parser::{BlockEntryPtr, ClusterPtr, ResultCode};
+use crate::mux::{ColorSubsampling, TrackNum};
+use crate::validate::{SyncOptions, SyncReport, SyncTracker};
+
+use super::ebml::{self, Element};
+use super::limits;
+use super::reader::{self, MkvRead};
+use super::{
+    AttachmentInfo, AudioTrackInfo, ChapterDisplay, ChapterInfo, Chromaticity, ColourInfo, CueEntry, DemuxError,
+    DemuxFrame, DemuxFrameRef, DemuxOptions, DemuxTrackInfo, DemuxWarning, DurationStrategy, EbmlHeaderInfo,
+    KeyframeEntry, MasteringMetadata, ProjectionInfo, ProjectionType, SeekPoint, SegmentInfo, SimpleTagInfo, TagInfo,
+    TagTarget, TagValue, TrackType, VideoTrackInfo,
+};
+#[cfg(feature = "bytes")]
+use super::DemuxFrameBytes;
+
+/// RAII semantics for an FFI parser. This is simpler than implementing `Drop` on [`Demuxer`], which
+/// prevents destructuring.
+//
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl Send for OwnedParserPtr {}
+
+struct OwnedParserPtr {
+    parser: ffi::parser::ParserNonNullPtr,
+}
+
+impl OwnedParserPtr {
+    /// ## Safety
+    /// `parser` must be a valid, non-dangling pointer to an FFI parser created with [`ffi::parser::new_parser`].
+    /// After construction, `parser` must not be used by the caller, except via [`Self::as_ptr`].
+    /// The latter also must not be passed to [`ffi::parser::delete_parser`].
+    unsafe fn new(parser: ffi::parser::ParserNonNullPtr) -> Self {
+        Self { parser }
+    }
+
+    fn as_ptr(&self) -> ffi::parser::ParserMutPtr {
+        self.parser.as_ptr()
+    }
+}
+
+impl Drop for OwnedParserPtr {
+    fn drop(&mut self) {
+        // SAFETY: We are assumed to be the only one allowed to delete this parser (per the requirements of [`Self::new`]).
+        unsafe {
+            ffi::parser::delete_parser(self.parser.as_ptr());
+        }
+    }
+}
+
+struct ReaderData<R> {
+    source: R,
+
+    /// How many bytes of `source` can be read. Unless `complete` is set, more may become available later.
+    length: u64,
+    complete: bool,
+
+    /// The largest read `libwebm` may make, from [`DemuxOptions::max_total_buffered`].
+    max_read: usize,
+
+    /// The last error hit while `libwebm` was reading. `libwebm` only knows that the read failed,
+    /// so we keep the details here to report them once control returns to us.
+    error: Option<DemuxError>,
+    _marker: PhantomPinned,
+}
+
+impl<R: MkvRead> ReaderData<R> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<()> {
+        reader::read_exact_at(&mut self.source, pos, buf)
+    }
+}
+
+/// Where the next call to [`Demuxer::frames`] will pick up reading.
+#[derive(Clone, Copy)]
+enum Position {
+    Start,
+    /// `entry` is null once all entries of `cluster` have been visited.
+    At {
+        cluster: ClusterPtr,
+        entry: BlockEntryPtr,
+        frame: i32,
+    },
+    End,
+}
+
+/// Where a frame's payload, a block addition or an attachment is stored in the source.
+#[derive(Clone, Copy)]
+struct Span {
+    pos: u64,
+    len: u64,
+}
+
+impl Span {
+    fn new(pos: i64, len: i64) -> Result<Self, DemuxError> {
+        Ok(Self {
+            pos: u64::try_from(pos).map_err(|_| DemuxError::InvalidFile)?,
+            len: u64::try_from(len).map_err(|_| DemuxError::InvalidFile)?,
+        })
+    }
+}
+
+/// A frame whose payload and block additions have been found, but not read yet.
+struct LocatedFrame {
+    /// The frame, with `data` and `additions` left empty.
+    frame: DemuxFrame,
+    data: Span,
+    additions: Vec<(u64, Span)>,
+}
+
+/// Reads the tracks and frames of an existing WebM file.
+///
+/// Parsing is done by `libwebm`'s `mkvparser`. Only the headers are read when the [`Demuxer`] is created; frame
+/// payloads are read from the source on demand as you iterate over them with [`Demuxer::frames`]. The source may be
+/// anything implementing [`MkvRead`], which includes everything that is [`Read`](std::io::Read) +
+/// [`Seek`](std::io::Seek).
+pub struct Demuxer<R> {
+    // Declared before `reader_data` so the parser (which holds a pointer to the latter) is dropped first
+    parser: OwnedParserPtr,
+    reader_data: Pin<Box<ReaderData<R>>>,
+    ebml_header: EbmlHeaderInfo,
+    segment_info: SegmentInfo,
+    tracks: Vec<DemuxTrackInfo>,
+    // These are read on first use, as they may be anywhere in the file
+    chapters: Option<Vec<ChapterInfo>>,
+    tags: Option<Vec<TagInfo>>,
+    attachments: Option<Vec<AttachmentInfo>>,
+    /// Where the data of each of `attachments` is, in the same order.
+    attachment_data: Vec<Span>,
+    /// Read on first use, as the `Cues` element is usually at the end of the file.
+    cues: Option<Vec<CueEntry>>,
+    position: Position,
+    options: DemuxOptions,
+    warnings: Vec<DemuxWarning>,
+}
+
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// The cluster and block pointers we hold are owned by the parser, which moves along with us.
+// Thus, safety is only conditional on the source `R`, hence the `Send` bound on it.
+//
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl<R: Send> Send for Demuxer<R> {}
+
+impl<R> Demuxer<R>
+where
+    R: MkvRead,
+{
+    /// Creates a [`Demuxer`] reading from `source`, and parses the file headers and track information.
+    pub fn new(source: R) -> Result<Self, DemuxError> {
+        Self::with_options(source, DemuxOptions::default())
+    }
+
+    /// Like [`Demuxer::new`], but with the given options rather than the defaults.
+    pub fn with_options(mut source: R, options: DemuxOptions) -> Result<Self, DemuxError> {
+        let length = source.len().map_err(DemuxError::Io)?;
+        let mut demuxer = Self::unopened(source, length, true, options)?;
+        demuxer.open()?;
+        Ok(demuxer)
+    }
+
+    /// Creates a [`Demuxer`] without parsing anything yet. `length` and `complete` are as in [`ReaderData`].
+    pub(super) fn unopened(source: R, length: u64, complete: bool, options: DemuxOptions) -> Result<Self, DemuxError> {
+        extern "C" fn read_fn<R>(data: *mut c_void, pos: u64, len: usize, buf: *mut u8) -> bool
+        where
+            R: MkvRead,
+        {
+            if buf.is_null() {
+                return false;
+            }
+            let data = unsafe { data.cast::<ReaderData<R>>().as_mut().unwrap() };
+            let buf = unsafe { std::slice::from_raw_parts_mut(buf, len) };
+
+            // Reads past the end are a normal part of probing for libwebm, and are not worth reporting
+            let in_bounds = u64::try_from(len)
+                .ok()
+                .and_then(|len| pos.checked_add(len))
+                .is_some_and(|end| end <= data.length);
+            if !in_bounds {
+                return false;
+            }
+            if let Err(err) = limits::check("Read", len as u64, data.max_read) {
+                data.error = Some(err);
+                return false;
+            }
+
+            match data.read_at(pos, buf) {
+                Ok(()) => true,
+                Err(err) => {
+                    data.error = Some(DemuxError::Io(err));
+                    false
+                },
+            }
+        }
+        extern "C" fn length_fn<R>(data: *mut c_void, total: *mut i64, available: *mut i64) -> bool {
+            let data = unsafe { data.cast::<ReaderData<R>>().as_mut().unwrap() };
+            let Ok(length) = i64::try_from(data.length) else {
+                return false;
+            };
+            unsafe {
+                *total = if data.complete { length } else { -1 };
+                *available = length;
+            }
+            true
+        }
+
+        let mut reader_data = Box::pin(ReaderData {
+            source,
+            length,
+            complete,
+            max_read: options.max_total_buffered,
+            error: None,
+            _marker: PhantomPinned,
+        });
+
+        let parser = unsafe {
+            ffi::parser::new_parser(
+                Some(read_fn::<R>),
+                Some(length_fn::<R>),
+                std::ptr::from_mut(reader_data.as_mut().get_unchecked_mut()).cast(),
+            )
+        };
+        let parser = NonNull::new(parser)
+            .map(|ptr| unsafe { OwnedParserPtr::new(ptr) })
+            .ok_or(DemuxError::Unknown)?;
+
+        Ok(Demuxer {
+            parser,
+            reader_data,
+            ebml_header: EbmlHeaderInfo::default(),
+            segment_info: SegmentInfo::default(),
+            tracks: Vec::new(),
+            chapters: None,
+            tags: None,
+            attachments: None,
+            attachment_data: Vec::new(),
+            cues: None,
+            position: Position::Start,
+            options,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Parses the file headers and track information. If the source is incomplete, this may be retried once more
+    /// data is available.
+    ///
+    /// Elements that may be anywhere in the file, such as `Cues` and `Tags`, are left to be found on first use.
+    pub(super) fn open(&mut self) -> Result<(), DemuxError> {
+        let options = self.options;
+        limits::check_headers(self.source_mut(), &options)?;
+
+        let result = unsafe { ffi::parser::open(self.parser.as_ptr()) };
+        self.check(result)?;
+        self.ebml_header = self.read_ebml_header()?;
+        self.segment_info = self.read_segment_info()?;
+        self.tracks = self.read_tracks()?;
+        Ok(())
+    }
+
+    /// Returns an iterator over the frames in the file, in the order they are stored.
+    ///
+    /// Iteration starts where the previous iterator from this [`Demuxer`] left off, so you may stop and resume at
+    /// will. Use [`Demuxer::rewind`] to start over from the first frame. Each frame's payload is read from the
+    /// source only once the iterator reaches it.
+    ///
+    /// If an error is encountered, it is returned and iteration ends.
+    pub fn frames(&mut self) -> Frames<'_, R> {
+        Frames {
+            demuxer: self,
+            track_filter: None,
+        }
+    }
+
+    /// Returns an iterator over only the frames of the specified track, in the order they are stored.
+    ///
+    /// Frames of other tracks are skipped based on their block headers alone, so their payloads are never read from
+    /// the source. This makes it considerably cheaper than filtering the output of [`Demuxer::frames`].
+    ///
+    /// Both kinds of iterator share a single read position: whichever one you create next starts after the last
+    /// frame (of any track) that was visited. Frames of other tracks skipped by this iterator are thus not returned
+    /// by a later call to [`Demuxer::frames`] unless you [`rewind`](Demuxer::rewind) first.
+    ///
+    /// If there is no track with the given number, the iterator yields nothing.
+    pub fn track_frames(&mut self, track: impl Into<TrackNum>) -> Frames<'_, R> {
+        Frames {
+            demuxer: self,
+            track_filter: Some(track.into()),
+        }
+    }
+
+    /// The chapters of this file, with nested chapters as children of their parent. The chapters of all editions are
+    /// returned in the order they are stored, one edition after the other. Empty if the file has no chapters.
+    ///
+    /// The `Chapters` element is read on first use, and the result is kept for later calls. See
+    /// [`Demuxer::tags`] for how it is found.
+    pub fn chapters(&mut self) -> Result<&[ChapterInfo], DemuxError> {
+        if self.chapters.is_none() {
+            self.chapters = Some(self.read_chapters()?);
+        }
+        Ok(self.chapters.as_deref().unwrap_or_default())
+    }
+
+    /// The tags of this file, in the order they are stored. Empty if the file has no tags.
+    ///
+    /// The `Tags` element is read on first use, and the result is kept for later calls. It is found without reading
+    /// the rest of the file if it precedes the first cluster or is listed in the `SeekHead`, as in files written by
+    /// `libwebm` and mkvmerge. Files without a `SeekHead` are scanned from the start, one top-level element at a
+    /// time, for a bounded number of elements.
+    pub fn tags(&mut self) -> Result<&[TagInfo], DemuxError> {
+        if self.tags.is_none() {
+            self.tags = Some(self.read_tags()?);
+        }
+        Ok(self.tags.as_deref().unwrap_or_default())
+    }
+
+    /// The UTC time of media time zero, for files anchored with
+    /// [`SegmentBuilder::anchor_wall_clock`](crate::mux::SegmentBuilder::anchor_wall_clock): a frame at
+    /// `timestamp_ns` was captured `timestamp_ns` after it. Read from the whole-file tag named
+    /// [`TIMECODE_OFFSET_TAG`](crate::time::TIMECODE_OFFSET_TAG), and `None` if there is none or it isn't a time this
+    /// platform can represent.
+    ///
+    /// The `DateUTC` of [`Demuxer::segment_info`] holds the same time in anchored files, but other muxers set it to
+    /// when the file was written, so it isn't used here.
+    pub fn wall_clock_anchor(&mut self) -> Result<Option<SystemTime>, DemuxError> {
+        let anchor = self
+            .tags()?
+            .iter()
+            .filter(|tag| tag.targets.is_empty())
+            .flat_map(|tag| &tag.simple_tags)
+            .find(|simple_tag| simple_tag.name == crate::time::TIMECODE_OFFSET_TAG)
+            .and_then(|simple_tag| match &simple_tag.value {
+                TagValue::String(value) => value.parse().ok(),
+                _ => None,
+            })
+            .and_then(crate::time::from_unix_ns);
+        Ok(anchor)
+    }
+
+    /// The files attached to this file, such as fonts or cover art, in the order they are stored. Only their
+    /// metadata is read; use [`Demuxer::read_attachment`] for their data.
+    ///
+    /// Always empty for WebM files, which may not have attachments. Otherwise, the `Attachments` element is read on
+    /// first use, and found as described for [`Demuxer::tags`].
+    pub fn attachments(&mut self) -> Result<&[AttachmentInfo], DemuxError> {
+        if self.attachments.is_none() {
+            let (attachments, data) = self.read_attachments()?.into_iter().unzip();
+            self.attachments = Some(attachments);
+            self.attachment_data = data;
+        }
+        Ok(self.attachments.as_deref().unwrap_or_default())
+    }
+
+    /// Reads the data of the attachment with the given [`AttachmentInfo::uid`].
+    ///
+    /// Returns [`DemuxError::BadParam`] if there is no such attachment, and [`DemuxError::LimitExceeded`] if it is
+    /// larger than [`DemuxOptions::max_attachment_size`].
+    pub fn read_attachment(&mut self, uid: u64) -> Result<Vec<u8>, DemuxError> {
+        let attachments = self.attachments()?;
+        let index = attachments.iter().position(|attachment| attachment.uid == uid).ok_or(DemuxError::BadParam)?;
+        let span = self.attachment_data[index];
+        limits::check("Attachment", span.len, self.options.max_attachment_size)?;
+        self.read_unchecked(span)
+    }
+
+    fn next_frame(&mut self, track_filter: Option<TrackNum>) -> Result<Option<DemuxFrame>, DemuxError> {
+        let result = self.advance(track_filter);
+        if result.is_err() {
+            self.position = Position::End;
+        }
+        result
+    }
+
+    pub(super) fn advance(&mut self, track_filter: Option<TrackNum>) -> Result<Option<DemuxFrame>, DemuxError> {
+        let Some(LocatedFrame {
+            mut frame,
+            data,
+            additions,
+        }) = self.locate_next_frame(track_filter)?
+        else {
+            return Ok(None);
+        };
+
+        frame.data = self.read_span(data)?;
+        for (id, span) in additions {
+            let data = self.read_span(span)?;
+            frame.additions.push((id, data));
+        }
+        Ok(Some(frame))
+    }
+
+    fn locate_next_frame(&mut self, track_filter: Option<TrackNum>) -> Result<Option<LocatedFrame>, DemuxError> {
+        loop {
+            match self.find_next_frame(track_filter) {
+                Err(DemuxError::InvalidFile) if self.options.lenient => self.resync()?,
+                result => return result,
+            }
+        }
+    }
+
+    fn find_next_frame(&mut self, track_filter: Option<TrackNum>) -> Result<Option<LocatedFrame>, DemuxError> {
+        loop {
+            let Some((cluster, entry, frame)) = self.current_entry()? else {
+                return Ok(None);
+            };
+            let block = self.block_info(cluster, entry)?;
+
+            let wanted = track_filter.is_none_or(|track| track == block.track_number);
+            if frame >= block.frame_count || !wanted {
+                self.skip_entry(cluster, entry)?;
+                continue;
+            }
+
+            let data = self.frame_span(entry, frame)?;
+            let additions = self.addition_spans(cluster, entry)?;
+            let discard_padding_ns = self.read_discard_padding(cluster, entry)?;
+            self.position = Position::At {
+                cluster,
+                entry,
+                frame: frame + 1,
+            };
+
+            // `frame_count` is at least 1 for any block with frames, and `frame` is within it
+            let lace_index = frame as usize;
+            let frame_count = block.frame_count as usize;
+            let block_duration = u64::try_from(block.duration_ns).ok();
+            let default_duration = self.track(block.track_number).and_then(|track| track.default_duration_ns);
+            let last_frame = lace_index + 1 == frame_count;
+            let discard_padding_ns = discard_padding_ns.filter(|&padding| {
+                if padding < 0 {
+                    lace_index == 0
+                } else {
+                    last_frame
+                }
+            });
+
+            let frame = DemuxFrame {
+                track_num: block.track_number,
+                timestamp_ns: lace_timestamp(block.time_ns, block_duration, default_duration, lace_index, frame_count),
+                is_keyframe: block.keyframe,
+                duration_ns: block_duration.map(|duration| duration / frame_count as u64),
+                laced: frame_count > 1,
+                lace_index,
+                data: Vec::new(),
+                additions: Vec::new(),
+                discard_padding_ns,
+            };
+            return Ok(Some(LocatedFrame {
+                frame,
+                data,
+                additions,
+            }));
+        }
+    }
+
+    /// Moves past the damaged cluster at the current position, to the next cluster that can be parsed (or the end of
+    /// the file), and records a warning about it.
+    fn resync(&mut self) -> Result<(), DemuxError> {
+        let damaged = match self.position {
+            Position::At { cluster, entry, .. } => Some((cluster, entry)),
+            Position::Start => None,
+            Position::End => return Ok(()),
+        };
+        // If a cluster was fully read, it's whatever follows it that is damaged
+        let from_offset = match damaged {
+            Some((cluster, entry)) => {
+                let info = self.cluster_info(cluster)?;
+                let size = if entry.is_null() { info.element_size.max(0) } else { 0 };
+                u64::try_from(info.element_start + size).map_err(|_| DemuxError::InvalidFile)?
+            },
+            None => self.element_payload(ffi::parser::ELEMENT_SEGMENT)?.ok_or(DemuxError::InvalidFile)?.0,
+        };
+
+        // If only the blocks of the cluster are damaged, its size is still intact and mkvparser can find the next
+        // cluster itself. Otherwise, look for the next cluster ID.
+        let mut next: ClusterPtr = std::ptr::null();
+        let found_next = match damaged {
+            Some((cluster, entry)) if !entry.is_null() => {
+                let result = unsafe { ffi::parser::next_cluster(self.parser.as_ptr(), cluster, &mut next) };
+                match self.check(result) {
+                    Ok(()) => true,
+                    Err(DemuxError::InvalidFile) => false,
+                    Err(err) => return Err(err),
+                }
+            },
+            _ => false,
+        };
+        if !found_next {
+            next = std::ptr::null();
+            if let Some(pos) = self.find_cluster_after(from_offset)? {
+                let pos = i64::try_from(pos).map_err(|_| DemuxError::InvalidFile)?;
+                let result = unsafe { ffi::parser::cluster_at(self.parser.as_ptr(), pos, &mut next) };
+                self.check(result)?;
+            }
+        }
+
+        let to_offset = if next.is_null() {
+            self.reader_data.length
+        } else {
+            u64::try_from(self.cluster_info(next)?.element_start).map_err(|_| DemuxError::InvalidFile)?
+        };
+        self.warnings.push(DemuxWarning::ResyncSkipped { from_offset, to_offset });
+        self.position = self.enter_cluster(next)?;
+        Ok(())
+    }
+
+    /// Scans the source for the first plausible `Cluster` element after `pos`: its ID, a valid size, and then a
+    /// `Timecode` element, as every cluster starts with one.
+    fn find_cluster_after(&mut self, pos: u64) -> Result<Option<u64>, DemuxError> {
+        const CHUNK_SIZE: u64 = 64 * 1024;
+        let cluster_id = CLUSTER_ID.to_be_bytes();
+        let length = self.reader_data.length;
+
+        let mut chunk_start = pos + 1;
+        let mut chunk = Vec::new();
+        while chunk_start < length {
+            let chunk_len = usize::try_from(CHUNK_SIZE.min(length - chunk_start)).unwrap_or(usize::MAX);
+            chunk.resize(chunk_len, 0);
+            self.reader_data_mut().read_at(chunk_start, &mut chunk).map_err(DemuxError::Io)?;
+
+            for (index, window) in chunk.windows(cluster_id.len()).enumerate() {
+                let candidate = chunk_start + index as u64;
+                if window == cluster_id && self.is_cluster_header(candidate)? {
+                    return Ok(Some(candidate));
+                }
+            }
+
+            // Overlap the chunks, so that an ID spanning two of them is still found
+            if chunk_start + chunk_len as u64 >= length {
+                break;
+            }
+            chunk_start += chunk_len as u64 - (cluster_id.len() as u64 - 1);
+        }
+        Ok(None)
+    }
+
+    /// Like [`Demuxer::find_cluster_after`], but scans backwards for the last plausible `Cluster` element that starts
+    /// before `end`.
+    fn find_cluster_before(&mut self, end: u64) -> Result<Option<u64>, DemuxError> {
+        const CHUNK_SIZE: u64 = 64 * 1024;
+        let cluster_id = CLUSTER_ID.to_be_bytes();
+
+        let mut chunk_end = end.min(self.reader_data.length);
+        let mut chunk = Vec::new();
+        while chunk_end >= cluster_id.len() as u64 {
+            let chunk_start = chunk_end.saturating_sub(CHUNK_SIZE);
+            chunk.resize((chunk_end - chunk_start) as usize, 0);
+            self.reader_data_mut().read_at(chunk_start, &mut chunk).map_err(DemuxError::Io)?;
+
+            for (index, window) in chunk.windows(cluster_id.len()).enumerate().rev() {
+                let candidate = chunk_start + index as u64;
+                if window == cluster_id && self.is_cluster_header(candidate)? {
+                    return Ok(Some(candidate));
+                }
+            }
+
+            if chunk_start == 0 {
+                break;
+            }
+            // Overlap the chunks, so that an ID spanning two of them is still found
+            chunk_end = chunk_start + (cluster_id.len() as u64 - 1);
+        }
+        Ok(None)
+    }
+
+    fn is_cluster_header(&mut self, pos: u64) -> Result<bool, DemuxError> {
+        // The ID, a size of up to 8 bytes, and the ID of the first child
+        let mut header = [0; 13];
+        let available = self.reader_data.length.saturating_sub(pos).min(header.len() as u64) as usize;
+        let header = &mut header[..available];
+        self.reader_data_mut().read_at(pos, header).map_err(DemuxError::Io)?;
+
+        let Some(&size_start) = header.get(4) else {
+            return Ok(false);
+        };
+        let size_len = size_start.leading_zeros() as usize + 1;
+        Ok(size_len <= 8 && header.get(4 + size_len) == Some(&CLUSTER_TIMECODE_ID))
+    }
+
+    /// Resolves the current position to a block entry, loading clusters as needed. Returns `None` at the end of the
+    /// file.
+    fn current_entry(&mut self) -> Result<Option<(ClusterPtr, BlockEntryPtr, i32)>, DemuxError> {
+        loop {
+            match self.position {
+                Position::Start => {
+                    let mut cluster: ClusterPtr = std::ptr::null();
+                    let result = unsafe { ffi::parser::first_cluster(self.parser.as_ptr(), &mut cluster) };
+                    self.check(result)?;
+                    self.position = self.enter_cluster(cluster)?;
+                },
+                Position::At { cluster, entry, .. } if entry.is_null() => {
+                    let mut next: ClusterPtr = std::ptr::null();
+                    let result =
+                        unsafe { ffi::parser::next_cluster(self.parser.as_ptr(), cluster, &mut next) };
+                    self.check(result)?;
+                    self.position = self.enter_cluster(next)?;
+                },
+                Position::At { cluster, entry, frame } => return Ok(Some((cluster, entry, frame))),
+                Position::End => return Ok(None),
+            }
+        }
+    }
+
+    /// Moves the position past the given block entry.
+    fn skip_entry(&mut self, cluster: ClusterPtr, entry: BlockEntryPtr) -> Result<(), DemuxError> {
+        let mut next: BlockEntryPtr = std::ptr::null();
+        let result = unsafe { ffi::parser::cluster_next_entry(self.parser.as_ptr(), cluster, entry, &mut next) };
+        self.check(result)?;
+        self.position = Position::At {
+            cluster,
+            entry: next,
+            frame: 0,
+        };
+        Ok(())
+    }
+
+    fn enter_cluster(&mut self, cluster: ClusterPtr) -> Result<Position, DemuxError> {
+        if cluster.is_null() {
+            return Ok(Position::End);
+        }
+
+        let mut entry: BlockEntryPtr = std::ptr::null();
+        let result = unsafe { ffi::parser::cluster_first_entry(self.parser.as_ptr(), cluster, &mut entry) };
+        self.check(result)?;
+
+        Ok(Position::At {
+            cluster,
+            entry,
+            frame: 0,
+        })
+    }
+
+    fn block_info(
+        &mut self,
+        cluster: ClusterPtr,
+        entry: BlockEntryPtr,
+    ) -> Result<ffi::parser::BlockInfo, DemuxError> {
+        let mut block = ffi::parser::BlockInfo {
+            track_number: 0,
+            time_ns: 0,
+            keyframe: false,
+            frame_count: 0,
+            duration_ns: -1,
+        };
+        let result = unsafe { ffi::parser::block_info(self.parser.as_ptr(), cluster, entry, &mut block) };
+        self.check(result)?;
+        Ok(block)
+    }
+
+    pub(super) fn cluster_info(&mut self, cluster: ClusterPtr) -> Result<ffi::parser::ClusterInfo, DemuxError> {
+        let mut info = ffi::parser::ClusterInfo {
+            time_ns: 0,
+            element_start: 0,
+            element_size: 0,
+        };
+        let result = unsafe { ffi::parser::cluster_info(self.parser.as_ptr(), cluster, &mut info) };
+        self.check(result)?;
+        Ok(info)
+    }
+
+    /// Positions the demuxer at the last keyframe of the given track at or before `timestamp_ns`, so that the next
+    /// frame returned by [`Demuxer::frames`] or [`Demuxer::track_frames`] is that keyframe. If the track has no
+    /// keyframe that early, the demuxer is positioned at its first keyframe instead.
+    ///
+    /// The file's `Cues` element is used to find the keyframe, and is loaded on first use. Files without cues (or
+    /// without a cue for this track) are scanned from the start instead, reading block headers but not payloads.
+    /// This is reported via [`SeekPoint::linear_scan`], but is not an error.
+    ///
+    /// Returns [`DemuxError::BadParam`] if there is no such track, or it has no keyframes at all.
+    pub fn seek(&mut self, timestamp_ns: u64, track: impl Into<TrackNum>) -> Result<SeekPoint, DemuxError> {
+        let track = track.into();
+        if self.track(track).is_none() {
+            return Err(DemuxError::BadParam);
+        }
+        let target = i64::try_from(timestamp_ns).unwrap_or(i64::MAX);
+
+        // Files without a `SeekHead` have to be scanned for their cues before `mkvparser` can use them
+        if let Err(err) = self.cue_index() {
+            self.position = Position::End;
+            return Err(err);
+        }
+        let mut cluster: ClusterPtr = std::ptr::null();
+        let mut entry: BlockEntryPtr = std::ptr::null();
+        let result = unsafe { ffi::parser::cue_seek(self.parser.as_ptr(), track, target, &mut cluster, &mut entry) };
+        if let Err(err) = self.check(result) {
+            self.position = Position::End;
+            return Err(err);
+        }
+
+        let linear_scan = entry.is_null();
+        if linear_scan {
+            let found = self.scan_for_keyframe(track, target);
+            match found {
+                Ok(Some(found)) => (cluster, entry) = found,
+                Ok(None) => {
+                    self.position = Position::End;
+                    return Err(DemuxError::BadParam);
+                },
+                Err(err) => {
+                    self.position = Position::End;
+                    return Err(err);
+                },
+            }
+        }
+
+        self.position = Position::At {
+            cluster,
+            entry,
+            frame: 0,
+        };
+        let block = self.block_info(cluster, entry)?;
+        let cluster_info = self.cluster_info(cluster)?;
+
+        Ok(SeekPoint {
+            timestamp_ns: clamp_timestamp(block.time_ns),
+            cluster_offset: u64::try_from(cluster_info.element_start).map_err(|_| DemuxError::InvalidFile)?,
+            linear_scan,
+        })
+    }
+
+    /// The cue points of the file's `Cues` element, one per track position, in the order they are stored. Empty if
+    /// the file has no cues.
+    ///
+    /// The `Cues` element is read directly on first use, and the result is kept for later calls. This is much
+    /// cheaper than [`Demuxer::keyframe_index`], but cue points are usually only written for one track, and at most
+    /// once per cluster.
+    pub fn cue_index(&mut self) -> Result<&[CueEntry], DemuxError> {
+        if self.cues.is_none() {
+            self.cues = Some(self.read_cues()?);
+        }
+        Ok(self.cues.as_deref().unwrap_or_default())
+    }
+
+    /// Lists every keyframe of the given track, in the order they are stored.
+    ///
+    /// This scans the block headers of the whole file, as [`Demuxer::seek`] does without cues, but never reads frame
+    /// payloads. The position of [`Demuxer::frames`] is left unchanged.
+    ///
+    /// Returns [`DemuxError::BadParam`] if there is no such track.
+    pub fn keyframe_index(&mut self, track: impl Into<TrackNum>) -> Result<Vec<KeyframeEntry>, DemuxError> {
+        let track = track.into();
+        if self.track(track).is_none() {
+            return Err(DemuxError::BadParam);
+        }
+
+        let position = self.position;
+        self.position = Position::Start;
+        let keyframes = self.scan_keyframes(track);
+        self.position = position;
+        keyframes
+    }
+
+    /// Works out the duration of the file from its frames, in nanoseconds, for files whose `Info` lacks a `Duration`
+    /// (see [`SegmentInfo::duration_ns`]), as is common for live captures. A frame ends after its block's
+    /// `BlockDuration` or, failing that, its track's default duration. See [`DurationStrategy`] for what each
+    /// strategy reads. The position of [`Demuxer::frames`] is left unchanged.
+    ///
+    /// A file without frames has a duration of 0. Returns [`DemuxError::BadParam`] for [`DurationStrategy::FromCues`]
+    /// if the file has no cues.
+    pub fn compute_duration(&mut self, strategy: DurationStrategy) -> Result<u64, DemuxError> {
+        let start = match strategy {
+            DurationStrategy::FromCues => {
+                let last_cue = self.cue_index()?.iter().max_by_key(|cue| cue.timestamp_ns).copied();
+                let last_cue = last_cue.ok_or(DemuxError::BadParam)?;
+                let default_duration = self.track(last_cue.track_num).and_then(|track| track.default_duration_ns);
+                return Ok(last_cue.timestamp_ns.saturating_add(default_duration.unwrap_or(0)));
+            },
+            DurationStrategy::FromLastCluster => {
+                let cluster = self.find_last_cluster()?;
+                self.enter_cluster(cluster)?
+            },
+            DurationStrategy::FullScan => Position::Start,
+        };
+
+        let position = self.position;
+        self.position = start;
+        let end_ns = self.scan_end_time();
+        self.position = position;
+        end_ns.map(clamp_timestamp)
+    }
+
+    /// How far the tracks drift apart over the whole file, with the default [`SyncOptions`].
+    ///
+    /// See [`SyncReport`] for how drift is measured. This scans the block headers of the whole file, as
+    /// [`Demuxer::keyframe_index`] does, without reading frame payloads. The position of [`Demuxer::frames`] is left
+    /// unchanged.
+    pub fn sync_report(&mut self) -> Result<SyncReport, DemuxError> {
+        self.sync_report_with_options(&SyncOptions::default())
+    }
+
+    /// Like [`Demuxer::sync_report`], but with the thresholds given.
+    pub fn sync_report_with_options(&mut self, options: &SyncOptions) -> Result<SyncReport, DemuxError> {
+        let position = self.position;
+        self.position = Position::Start;
+        let tracker = self.scan_sync();
+        self.position = position;
+        Ok(tracker?.report(options))
+    }
+
+    fn scan_sync(&mut self) -> Result<SyncTracker, DemuxError> {
+        let mut tracker = SyncTracker::default();
+        while let Some((cluster, entry, _)) = self.current_entry()? {
+            let block = self.block_info(cluster, entry)?;
+            let frame_count = usize::try_from(block.frame_count).unwrap_or(0);
+            let block_duration = u64::try_from(block.duration_ns).ok();
+            let default_duration = self.track(block.track_number).and_then(|track| track.default_duration_ns);
+            for index in 0..frame_count {
+                let timestamp_ns = lace_timestamp(block.time_ns, block_duration, default_duration, index, frame_count);
+                let duration_ns = block_duration.map(|duration| duration / frame_count as u64);
+                tracker.add_frame(block.track_number, timestamp_ns, duration_ns, default_duration);
+            }
+            self.skip_entry(cluster, entry)?;
+        }
+        Ok(tracker)
+    }
+
+    /// The latest end of a frame from the current position to the end of the file.
+    fn scan_end_time(&mut self) -> Result<i64, DemuxError> {
+        let mut end_ns = 0;
+        while let Some((cluster, entry, _)) = self.current_entry()? {
+            let block = self.block_info(cluster, entry)?;
+            let duration_ns = if block.duration_ns >= 0 {
+                block.duration_ns
+            } else {
+                let default_duration = self.track(block.track_number).and_then(|track| track.default_duration_ns);
+                let default_duration = i64::try_from(default_duration.unwrap_or(0)).unwrap_or(i64::MAX);
+                default_duration.saturating_mul(i64::from(block.frame_count))
+            };
+            end_ns = end_ns.max(block.time_ns.saturating_add(duration_ns));
+            self.skip_entry(cluster, entry)?;
+        }
+        Ok(end_ns)
+    }
+
+    /// Finds the last cluster of the file, via the `SeekHead` if it lists clusters, and otherwise by scanning
+    /// backwards from the end of the file. The cluster found may not be the last if the file is damaged, but none
+    /// after it are missed when iterating from it. Returns null if there are no clusters.
+    fn find_last_cluster(&mut self) -> Result<ClusterPtr, DemuxError> {
+        let mut indexed: i64 = -1;
+        let result = unsafe { ffi::parser::last_indexed_cluster(self.parser.as_ptr(), &mut indexed) };
+        self.check(result)?;
+        let pos = match u64::try_from(indexed) {
+            Ok(pos) => Some(pos),
+            Err(_) => self.find_cluster_before(self.reader_data.length)?,
+        };
+
+        let mut cluster: ClusterPtr = std::ptr::null();
+        if let Some(pos) = pos {
+            let pos = i64::try_from(pos).map_err(|_| DemuxError::InvalidFile)?;
+            let result = unsafe { ffi::parser::cluster_at(self.parser.as_ptr(), pos, &mut cluster) };
+            self.check(result)?;
+        }
+        Ok(cluster)
+    }
+
+    fn scan_keyframes(&mut self, track: TrackNum) -> Result<Vec<KeyframeEntry>, DemuxError> {
+        let mut keyframes = Vec::new();
+        while let Some((cluster, entry, _)) = self.current_entry()? {
+            let block = self.block_info(cluster, entry)?;
+            if block.track_number == track && block.keyframe {
+                let cluster_info = self.cluster_info(cluster)?;
+                keyframes.push(KeyframeEntry {
+                    timestamp_ns: clamp_timestamp(block.time_ns),
+                    track_num: track,
+                    cluster_offset: u64::try_from(cluster_info.element_start).map_err(|_| DemuxError::InvalidFile)?,
+                });
+            }
+            self.skip_entry(cluster, entry)?;
+        }
+        Ok(keyframes)
+    }
+
+    /// Scans block headers from the start of the file for the last keyframe of `track` at or before `target`, or
+    /// failing that, the first keyframe of `track`.
+    fn scan_for_keyframe(
+        &mut self,
+        track: TrackNum,
+        target: i64,
+    ) -> Result<Option<(ClusterPtr, BlockEntryPtr)>, DemuxError> {
+        self.position = Position::Start;
+        let mut best = None;
+
+        while let Some((cluster, entry, _)) = self.current_entry()? {
+            let block = self.block_info(cluster, entry)?;
+            if block.track_number == track && block.keyframe {
+                if block.time_ns > target {
+                    if best.is_none() {
+                        best = Some((cluster, entry));
+                    }
+                    break;
+                }
+                best = Some((cluster, entry));
+            }
+            self.skip_entry(cluster, entry)?;
+        }
+
+        Ok(best)
+    }
+
+    fn frame_span(&mut self, entry: BlockEntryPtr, frame: i32) -> Result<Span, DemuxError> {
+        let mut pos: i64 = 0;
+        let mut len: i64 = 0;
+        let result = unsafe { ffi::parser::block_frame(self.parser.as_ptr(), entry, frame, &mut pos, &mut len) };
+        self.check(result)?;
+        Span::new(pos, len)
+    }
+
+    fn addition_spans(&mut self, cluster: ClusterPtr, entry: BlockEntryPtr) -> Result<Vec<(u64, Span)>, DemuxError> {
+        let mut count: u64 = 0;
+        let result =
+            unsafe { ffi::parser::block_addition_count(self.parser.as_ptr(), cluster, entry, &mut count) };
+        self.check(result)?;
+
+        let mut additions = Vec::new();
+        for index in 0..count {
+            let mut addition = ffi::parser::BlockAddition { id: 0, pos: 0, len: 0 };
+            let result =
+                unsafe { ffi::parser::block_addition(self.parser.as_ptr(), cluster, entry, index, &mut addition) };
+            self.check(result)?;
+
+            additions.push((addition.id, Span::new(addition.pos, addition.len)?));
+        }
+
+        Ok(additions)
+    }
+
+    fn read_discard_padding(&mut self, cluster: ClusterPtr, entry: BlockEntryPtr) -> Result<Option<i64>, DemuxError> {
+        let mut present = false;
+        let mut padding: i64 = 0;
+        let result = unsafe {
+            ffi::parser::block_discard_padding(self.parser.as_ptr(), cluster, entry, &mut present, &mut padding)
+        };
+        self.check(result)?;
+        Ok(present.then_some(padding))
+    }
+
+    fn read_chapters(&mut self) -> Result<Vec<ChapterInfo>, DemuxError> {
+        let Some((start, end)) = self.locate_element(ffi::parser::ELEMENT_CHAPTERS, CHAPTERS_ID)? else {
+            return Ok(Vec::new());
+        };
+        limits::check("Header element", end - start, self.options.max_header_element)?;
+        let source = &mut self.reader_data_mut().source;
+
+        let mut chapters = Vec::new();
+        for edition in ebml::children(source, start, end)? {
+            if edition.id != EDITION_ENTRY_ID {
+                continue;
+            }
+            for atom in ebml::children(source, edition.pos, edition.end())? {
+                if atom.id == CHAPTER_ATOM_ID {
+                    chapters.push(read_chapter_atom(source, &atom)?);
+                }
+            }
+        }
+        Ok(chapters)
+    }
+
+    fn read_cues(&mut self) -> Result<Vec<CueEntry>, DemuxError> {
+        let Some((start, end)) = self.locate_cues()? else {
+            return Ok(Vec::new());
+        };
+        let (segment_start, _) = self.element_payload(ffi::parser::ELEMENT_SEGMENT)?.ok_or(DemuxError::InvalidFile)?;
+        let timecode_scale = self.segment_info.timecode_scale;
+        let source = &mut self.reader_data_mut().source;
+
+        let mut cues = Vec::new();
+        for point in ebml::children(source, start, end)? {
+            if point.id != CUE_POINT_ID {
+                continue;
+            }
+
+            let mut time = 0;
+            let mut positions = Vec::new();
+            for child in ebml::children(source, point.pos, point.end())? {
+                match child.id {
+                    CUE_TIME_ID => time = ebml::read_uint(source, &child)?,
+                    CUE_TRACK_POSITIONS_ID => {
+                        let mut track_num = 0;
+                        let mut cluster_position = 0;
+                        for child in ebml::children(source, child.pos, child.end())? {
+                            match child.id {
+                                CUE_TRACK_ID => track_num = ebml::read_uint(source, &child)?,
+                                CUE_CLUSTER_POSITION_ID => cluster_position = ebml::read_uint(source, &child)?,
+                                _ => {},
+                            }
+                        }
+                        positions.push((track_num, cluster_position));
+                    },
+                    _ => {},
+                }
+            }
+
+            for (track_num, cluster_position) in positions {
+                cues.push(CueEntry {
+                    timestamp_ns: time.saturating_mul(timecode_scale),
+                    track_num,
+                    cluster_offset: segment_start.checked_add(cluster_position).ok_or(DemuxError::InvalidFile)?,
+                });
+            }
+        }
+        Ok(cues)
+    }
+
+    fn read_tags(&mut self) -> Result<Vec<TagInfo>, DemuxError> {
+        let Some((start, end)) = self.locate_element(ffi::parser::ELEMENT_TAGS, TAGS_ID)? else {
+            return Ok(Vec::new());
+        };
+        limits::check("Header element", end - start, self.options.max_header_element)?;
+        let source = &mut self.reader_data_mut().source;
+
+        let mut tags = Vec::new();
+        for tag in ebml::children(source, start, end)? {
+            if tag.id != TAG_ID {
+                continue;
+            }
+
+            let mut info = TagInfo {
+                targets: Vec::new(),
+                target_type_value: 50,
+                simple_tags: Vec::new(),
+            };
+            for child in ebml::children(source, tag.pos, tag.end())? {
+                match child.id {
+                    TARGETS_ID => {
+                        for target in ebml::children(source, child.pos, child.end())? {
+                            let target = match target.id {
+                                TARGET_TYPE_VALUE_ID => {
+                                    info.target_type_value = ebml::read_uint(source, &target)?;
+                                    continue;
+                                },
+                                TAG_TRACK_UID_ID => TagTarget::Track(ebml::read_uint(source, &target)?),
+                                TAG_EDITION_UID_ID => TagTarget::Edition(ebml::read_uint(source, &target)?),
+                                TAG_CHAPTER_UID_ID => TagTarget::Chapter(ebml::read_uint(source, &target)?),
+                                TAG_ATTACHMENT_UID_ID => TagTarget::Attachment(ebml::read_uint(source, &target)?),
+                                _ => continue,
+                            };
+                            info.targets.push(target);
+                        }
+                    },
+                    SIMPLE_TAG_ID => info.simple_tags.push(read_simple_tag(source, &child)?),
+                    _ => {},
+                }
+            }
+            // A UID of 0 explicitly means "everything"
+            info.targets.retain(|target| {
+                !matches!(
+                    target,
+                    TagTarget::Track(0) | TagTarget::Edition(0) | TagTarget::Chapter(0) | TagTarget::Attachment(0)
+                )
+            });
+            tags.push(info);
+        }
+        Ok(tags)
+    }
+
+    fn read_attachments(&mut self) -> Result<Vec<(AttachmentInfo, Span)>, DemuxError> {
+        // WebM doesn't allow attachments
+        if self.ebml_header.doc_type == "webm" {
+            return Ok(Vec::new());
+        }
+        let Some((start, end)) = self.locate_element(ffi::parser::ELEMENT_ATTACHMENTS, ATTACHMENTS_ID)? else {
+            return Ok(Vec::new());
+        };
+        let max_header_element = self.options.max_header_element;
+        let source = &mut self.reader_data_mut().source;
+
+        let mut attachments = Vec::new();
+        for attached_file in ebml::children(source, start, end)? {
+            if attached_file.id != ATTACHED_FILE_ID {
+                continue;
+            }
+
+            let mut info = AttachmentInfo {
+                name: String::new(),
+                mime: String::new(),
+                uid: 0,
+                size: 0,
+                description: None,
+            };
+            let mut data = None;
+            for child in ebml::children(source, attached_file.pos, attached_file.end())? {
+                if child.id == FILE_DATA_ID {
+                    info.size = child.size;
+                    data = Some(Span {
+                        pos: child.pos,
+                        len: child.size,
+                    });
+                    continue;
+                }
+
+                limits::check("Header element", child.size, max_header_element)?;
+                match child.id {
+                    FILE_NAME_ID => info.name = ebml::read_string(source, &child)?,
+                    FILE_MEDIA_TYPE_ID => info.mime = ebml::read_string(source, &child)?,
+                    FILE_DESCRIPTION_ID => info.description = Some(ebml::read_string(source, &child)?),
+                    FILE_UID_ID => info.uid = ebml::read_uint(source, &child)?,
+                    _ => {},
+                }
+            }
+            // An attachment without data has nothing to read
+            if let Some(data) = data {
+                attachments.push((info, data));
+            }
+        }
+        Ok(attachments)
+    }
+
+    /// Finds the payload of an optional top-level element, given by its `element` constant for
+    /// [`ffi::parser::element_payload`] and its ID. Files without a `SeekHead` are scanned for it.
+    fn locate_element(&mut self, element: u64, id: u32) -> Result<Option<(u64, u64)>, DemuxError> {
+        if let Some(payload) = self.element_payload(element)? {
+            return Ok(Some(payload));
+        }
+        if self.element_payload(ffi::parser::ELEMENT_SEEK_HEAD)?.is_some() {
+            return Ok(None);
+        }
+        Ok(self.scan_top_level(id)?.map(|element| (element.pos, element.end())))
+    }
+
+    /// Like [`Demuxer::locate_element`] for the `Cues`, but also makes sure that `mkvparser` knows of them, for
+    /// [`Demuxer::seek`].
+    fn locate_cues(&mut self) -> Result<Option<(u64, u64)>, DemuxError> {
+        if let Some(payload) = self.element_payload(ffi::parser::ELEMENT_CUES)? {
+            return Ok(Some(payload));
+        }
+        if self.element_payload(ffi::parser::ELEMENT_SEEK_HEAD)?.is_some() {
+            return Ok(None);
+        }
+        let Some(cues) = self.scan_top_level(CUES_ID)? else {
+            return Ok(None);
+        };
+
+        let pos = i64::try_from(cues.start).map_err(|_| DemuxError::InvalidFile)?;
+        let result = unsafe { ffi::parser::cues_at(self.parser.as_ptr(), pos) };
+        self.check(result)?;
+        self.element_payload(ffi::parser::ELEMENT_CUES)
+    }
+
+    /// Looks for a top-level element with the given ID by walking the children of the segment from its start,
+    /// reading only their headers. The walk gives up after [`MAX_SCANNED_ELEMENTS`] elements, or at one that can't be
+    /// read or has an unknown size, such as the clusters of a live recording.
+    fn scan_top_level(&mut self, id: u32) -> Result<Option<Element>, DemuxError> {
+        let (start, end) = self.element_payload(ffi::parser::ELEMENT_SEGMENT)?.ok_or(DemuxError::InvalidFile)?;
+        // The segment's size may be unknown
+        let end = if end > start { end } else { self.reader_data.length };
+        let source = &mut self.reader_data_mut().source;
+
+        let mut pos = start;
+        for _ in 0..MAX_SCANNED_ELEMENTS {
+            if pos >= end {
+                break;
+            }
+            let Ok((element_id, payload, Some(size))) = ebml::read_declared_header(source, pos) else {
+                break;
+            };
+            if element_id == id {
+                return Ok(Some(Element {
+                    id,
+                    start: pos,
+                    pos: payload,
+                    size,
+                }));
+            }
+            pos = payload.saturating_add(size);
+        }
+        Ok(None)
+    }
+
+    fn read_span(&mut self, span: Span) -> Result<Vec<u8>, DemuxError> {
+        limits::check("Frame", span.len, self.options.max_frame_size)?;
+        self.read_unchecked(span)
+    }
+
+    /// Reads `span` without checking its length against any limit.
+    fn read_unchecked(&mut self, span: Span) -> Result<Vec<u8>, DemuxError> {
+        let len = usize::try_from(span.len).map_err(|_| DemuxError::InvalidFile)?;
+
+        let mut data = vec![0; len];
+        self.reader_data_mut().read_at(span.pos, &mut data).map_err(DemuxError::Io)?;
+        Ok(data)
+    }
+}
+
+impl<'a> Demuxer<Cursor<&'a [u8]>> {
+    /// Creates a [`Demuxer`] reading from a file that is already in memory, such as a muxer's output buffer.
+    ///
+    /// Besides [`Demuxer::frames`], this allows iterating with [`Demuxer::borrowed_frames`], which returns frames
+    /// borrowing from `data` rather than copies. For an owned buffer that the [`Demuxer`] should keep alive, wrap an
+    /// `Arc<[u8]>` in a [`Cursor`] and use [`Demuxer::new`].
+    pub fn from_slice(data: &'a [u8]) -> Result<Self, DemuxError> {
+        Self::new(Cursor::new(data))
+    }
+
+    /// Like [`Demuxer::frames`], but returns frames whose payloads borrow from the slice the file is read from,
+    /// without copying them.
+    pub fn borrowed_frames(&mut self) -> BorrowedFrames<'_, 'a> {
+        BorrowedFrames { demuxer: self }
+    }
+
+    fn next_borrowed_frame(&mut self) -> Result<Option<DemuxFrameRef<'a>>, DemuxError> {
+        let result = self.advance_borrowed();
+        if result.is_err() {
+            self.position = Position::End;
+        }
+        result
+    }
+
+    fn advance_borrowed(&mut self) -> Result<Option<DemuxFrameRef<'a>>, DemuxError> {
+        let Some(LocatedFrame {
+            frame,
+            data,
+            additions,
+        }) = self.locate_next_frame(None)?
+        else {
+            return Ok(None);
+        };
+
+        let source: &'a [u8] = self.reader_data.source.get_ref();
+        let slice = |span: Span| {
+            let start = usize::try_from(span.pos).ok()?;
+            source.get(start..start.checked_add(usize::try_from(span.len).ok()?)?)
+        };
+        let missing = || DemuxError::Io(io::ErrorKind::UnexpectedEof.into());
+
+        Ok(Some(DemuxFrameRef {
+            track_num: frame.track_num,
+            timestamp_ns: frame.timestamp_ns,
+            is_keyframe: frame.is_keyframe,
+            duration_ns: frame.duration_ns,
+            laced: frame.laced,
+            lace_index: frame.lace_index,
+            data: slice(data).ok_or_else(missing)?,
+            additions: additions
+                .into_iter()
+                .map(|(id, span)| Ok((id, slice(span).ok_or_else(missing)?)))
+                .collect::<Result<_, DemuxError>>()?,
+            discard_padding_ns: frame.discard_padding_ns,
+        }))
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Demuxer<Cursor<bytes::Bytes>> {
+    /// Creates a [`Demuxer`] reading from a file held in a [`bytes::Bytes`], such as one received from the network.
+    ///
+    /// Besides [`Demuxer::frames`], this allows iterating with [`Demuxer::bytes_frames`], which returns frames whose
+    /// payloads are slices of `data` rather than copies.
+    pub fn from_bytes(data: bytes::Bytes) -> Result<Self, DemuxError> {
+        Self::new(Cursor::new(data))
+    }
+
+    /// Like [`Demuxer::frames`], but returns frames whose payloads share the buffer the file is read from, without
+    /// copying them.
+    pub fn bytes_frames(&mut self) -> BytesFrames<'_> {
+        BytesFrames { demuxer: self }
+    }
+
+    fn next_bytes_frame(&mut self) -> Result<Option<DemuxFrameBytes>, DemuxError> {
+        let result = self.advance_bytes();
+        if result.is_err() {
+            self.position = Position::End;
+        }
+        result
+    }
+
+    fn advance_bytes(&mut self) -> Result<Option<DemuxFrameBytes>, DemuxError> {
+        let Some(LocatedFrame {
+            frame,
+            data,
+            additions,
+        }) = self.locate_next_frame(None)?
+        else {
+            return Ok(None);
+        };
+
+        let source = self.reader_data.source.get_ref();
+        let slice = |span: Span| {
+            let start = usize::try_from(span.pos).ok()?;
+            let end = start.checked_add(usize::try_from(span.len).ok()?)?;
+            (end <= source.len()).then(|| source.slice(start..end))
+        };
+        let missing = || DemuxError::Io(io::ErrorKind::UnexpectedEof.into());
+
+        Ok(Some(DemuxFrameBytes {
+            track_num: frame.track_num,
+            timestamp_ns: frame.timestamp_ns,
+            is_keyframe: frame.is_keyframe,
+            duration_ns: frame.duration_ns,
+            laced: frame.laced,
+            lace_index: frame.lace_index,
+            data: slice(data).ok_or_else(missing)?,
+            additions: additions
+                .into_iter()
+                .map(|(id, span)| Ok((id, slice(span).ok_or_else(missing)?)))
+                .collect::<Result<_, DemuxError>>()?,
+            discard_padding_ns: frame.discard_padding_ns,
+        }))
+    }
+}
+
+impl<R> Demuxer<R> {
+    /// The EBML header of this file, including its `DocType`. Check this before anything else to reject kinds of
+    /// files you don't support.
+    #[must_use]
+    pub fn ebml_header(&self) -> &EbmlHeaderInfo {
+        &self.ebml_header
+    }
+
+    /// File-level information from the `Info` element, such as the duration and `SegmentUID`.
+    #[must_use]
+    pub fn segment_info(&self) -> &SegmentInfo {
+        &self.segment_info
+    }
+
+    /// The tracks in this file, in the order they appear in the `Tracks` element.
+    #[must_use]
+    pub fn tracks(&self) -> &[DemuxTrackInfo] {
+        &self.tracks
+    }
+
+    /// Looks up a track by its track number.
+    #[must_use]
+    pub fn track(&self, track_num: TrackNum) -> Option<&DemuxTrackInfo> {
+        self.tracks.iter().find(|track| track.track_num == track_num)
+    }
+
+    /// The contents of the `Colour` element of the given video track. Returns `None` if there is no such video
+    /// track, or it has no `Colour` element.
+    #[must_use]
+    pub fn colour(&self, track_num: TrackNum) -> Option<ColourInfo> {
+        match &self.track(track_num)?.track_type {
+            TrackType::Video(video) => video.colour.as_deref().copied(),
+            _ => None,
+        }
+    }
+
+    /// The contents of the `Projection` element of the given video track. Returns `None` if there is no such video
+    /// track, or it has no `Projection` element (as is the case for ordinary 2D video).
+    #[must_use]
+    pub fn projection(&self, track_num: TrackNum) -> Option<ProjectionInfo> {
+        match &self.track(track_num)?.track_type {
+            TrackType::Video(video) => video.projection.clone(),
+            _ => None,
+        }
+    }
+
+    /// The problems with the file that were worked around so far, in the order they were found. Always empty unless
+    /// [`DemuxOptions::lenient`] is set.
+    #[must_use]
+    pub fn warnings(&self) -> &[DemuxWarning] {
+        &self.warnings
+    }
+
+    /// Makes the next call to [`Demuxer::frames`] start again from the first frame in the file.
+    pub fn rewind(&mut self) {
+        self.position = Position::Start;
+    }
+
+    /// Consumes this [`Demuxer`], and returns the source that it was created with.
+    #[must_use]
+    pub fn into_inner(self) -> R {
+        let Self {
+            parser,
+            reader_data,
+            ..
+        } = self;
+        drop(parser);
+        unsafe { Pin::into_inner_unchecked(reader_data).source }
+    }
+
+    /// The cluster that the last frame returned by [`Demuxer::frames`] belongs to.
+    pub(super) fn current_cluster(&self) -> Option<ClusterPtr> {
+        match self.position {
+            Position::At { cluster, .. } => Some(cluster),
+            Position::Start | Position::End => None,
+        }
+    }
+
+    pub(super) fn options(&self) -> &DemuxOptions {
+        &self.options
+    }
+
+    /// The absolute position where the segment ends, as declared in its header, or `None` if its size is unknown.
+    pub(super) fn segment_end(&mut self) -> Result<Option<u64>, DemuxError> {
+        let (start, end) = self.element_payload(ffi::parser::ELEMENT_SEGMENT)?.ok_or(DemuxError::InvalidFile)?;
+        Ok((end > start).then_some(end))
+    }
+
+    pub(super) fn source_mut(&mut self) -> &mut R {
+        &mut self.reader_data_mut().source
+    }
+
+    /// Updates how many bytes of the source can be read, and whether that is all of it.
+    pub(super) fn set_length(&mut self, length: u64, complete: bool) {
+        let data = self.reader_data_mut();
+        data.length = length;
+        data.complete = complete;
+    }
+
+    fn reader_data_mut(&mut self) -> &mut ReaderData<R> {
+        // SAFETY: We never move out of the pinned data, and `libwebm` is not running while we hold the reference.
+        unsafe { self.reader_data.as_mut().get_unchecked_mut() }
+    }
+
+    /// Turns an FFI result into a [`DemuxError`], preferring any I/O error hit while `libwebm` was reading.
+    ///
+    /// Running out of data is reported as [`io::ErrorKind::WouldBlock`] if more may become available later, and as
+    /// an invalid (truncated) file otherwise.
+    fn check(&mut self, result: ResultCode) -> Result<(), DemuxError> {
+        let complete = self.reader_data.complete;
+        let error = self.reader_data_mut().error.take();
+        match (result, error) {
+            (ResultCode::Ok, _) => Ok(()),
+            (_, Some(err)) => Err(err),
+            (ResultCode::BadParam, None) => Err(DemuxError::BadParam),
+            (ResultCode::BufferNotFull, None) if !complete => Err(DemuxError::Io(io::ErrorKind::WouldBlock.into())),
+            (_, None) => Err(DemuxError::InvalidFile),
+        }
+    }
+
+    fn read_ebml_header(&mut self) -> Result<EbmlHeaderInfo, DemuxError> {
+        let mut header = ffi::parser::EbmlHeader {
+            version: 0,
+            read_version: 0,
+            max_id_length: 0,
+            max_size_length: 0,
+            doc_type: std::ptr::null(),
+            doc_type_version: 0,
+            doc_type_read_version: 0,
+        };
+        let result = unsafe { ffi::parser::ebml_header(self.parser.as_ptr(), &mut header) };
+        self.check(result)?;
+
+        let unsigned = |value: i64| u64::try_from(value).map_err(|_| DemuxError::InvalidFile);
+        Ok(EbmlHeaderInfo {
+            version: unsigned(header.version)?,
+            read_version: unsigned(header.read_version)?,
+            max_id_length: unsigned(header.max_id_length)?,
+            max_size_length: unsigned(header.max_size_length)?,
+            doc_type: unsafe { owned_string(header.doc_type) }.unwrap_or_default(),
+            doc_type_version: unsigned(header.doc_type_version)?,
+            doc_type_read_version: unsigned(header.doc_type_read_version)?,
+        })
+    }
+
+    fn read_segment_info(&mut self) -> Result<SegmentInfo, DemuxError> {
+        let mut info = ffi::parser::SegmentInfo {
+            timecode_scale: 0,
+            duration_ns: -1,
+            muxing_app: std::ptr::null(),
+            writing_app: std::ptr::null(),
+            title: std::ptr::null(),
+            has_date_utc: false,
+            date_utc: 0,
+            has_segment_uid: false,
+            segment_uid: [0; 16],
+        };
+        let result = unsafe { ffi::parser::segment_info(self.parser.as_ptr(), &mut info) };
+        self.check(result)?;
+
+        Ok(SegmentInfo {
+            timecode_scale: u64::try_from(info.timecode_scale).map_err(|_| DemuxError::InvalidFile)?,
+            duration_ns: u64::try_from(info.duration_ns).ok(),
+            muxing_app: unsafe { owned_string(info.muxing_app) },
+            writing_app: unsafe { owned_string(info.writing_app) },
+            title: unsafe { owned_string(info.title) },
+            date_utc: info.has_date_utc.then(|| matroska_date(info.date_utc)).flatten(),
+            segment_uid: info.has_segment_uid.then_some(info.segment_uid),
+        })
+    }
+
+    fn read_colour(&mut self, track_num: TrackNum) -> Result<Option<ColourInfo>, DemuxError> {
+        let mut info = ffi::parser::ColourInfo {
+            matrix_coefficients: -1,
+            bits_per_channel: -1,
+            chroma_subsampling_horz: -1,
+            chroma_subsampling_vert: -1,
+            cb_subsampling_horz: -1,
+            cb_subsampling_vert: -1,
+            chroma_siting_horz: -1,
+            chroma_siting_vert: -1,
+            range: -1,
+            transfer_characteristics: -1,
+            primaries: -1,
+            max_cll: -1,
+            max_fall: -1,
+            has_mastering_metadata: false,
+            chromaticities: [[-1.0; 2]; 4],
+            luminance_max: -1.0,
+            luminance_min: -1.0,
+        };
+        let mut present = false;
+        let result = unsafe { ffi::parser::track_colour(self.parser.as_ptr(), track_num, &mut info, &mut present) };
+        self.check(result)?;
+        if !present {
+            return Ok(None);
+        }
+
+        let value = |value: i64| u64::try_from(value).ok();
+        let float = |value: f32| (value >= 0.0).then_some(value);
+        let subsampling = |horz: i64, vert: i64| {
+            if horz < 0 && vert < 0 {
+                return None;
+            }
+            Some(ColorSubsampling {
+                chroma_horizontal: u8::try_from(horz).unwrap_or(0),
+                chroma_vertical: u8::try_from(vert).unwrap_or(0),
+            })
+        };
+        let chromaticity = |[x, y]: [f32; 2]| {
+            Some(Chromaticity {
+                x: float(x)?,
+                y: float(y)?,
+            })
+        };
+
+        Ok(Some(ColourInfo {
+            bits_per_channel: u8::try_from(info.bits_per_channel).ok(),
+            chroma_subsampling: subsampling(info.chroma_subsampling_horz, info.chroma_subsampling_vert),
+            cb_subsampling: subsampling(info.cb_subsampling_horz, info.cb_subsampling_vert),
+            chroma_siting_horz: value(info.chroma_siting_horz),
+            chroma_siting_vert: value(info.chroma_siting_vert),
+            range: value(info.range),
+            matrix_coefficients: value(info.matrix_coefficients),
+            transfer_characteristics: value(info.transfer_characteristics),
+            primaries: value(info.primaries),
+            max_cll: value(info.max_cll),
+            max_fall: value(info.max_fall),
+            mastering_metadata: info.has_mastering_metadata.then(|| MasteringMetadata {
+                primary_r: chromaticity(info.chromaticities[0]),
+                primary_g: chromaticity(info.chromaticities[1]),
+                primary_b: chromaticity(info.chromaticities[2]),
+                white_point: chromaticity(info.chromaticities[3]),
+                luminance_max: float(info.luminance_max),
+                luminance_min: float(info.luminance_min),
+            }),
+        }))
+    }
+
+    fn read_projection(&mut self, track_num: TrackNum) -> Result<Option<ProjectionInfo>, DemuxError> {
+        let mut info = ffi::parser::ProjectionInfo {
+            projection_type: 0,
+            private_data: std::ptr::null(),
+            private_data_len: 0,
+            has_pose_yaw: false,
+            pose_yaw: 0.0,
+            has_pose_pitch: false,
+            pose_pitch: 0.0,
+            has_pose_roll: false,
+            pose_roll: 0.0,
+        };
+        let mut present = false;
+        let result =
+            unsafe { ffi::parser::track_projection(self.parser.as_ptr(), track_num, &mut info, &mut present) };
+        self.check(result)?;
+        if !present {
+            return Ok(None);
+        }
+
+        let private_data = if info.private_data.is_null() {
+            Vec::new()
+        } else {
+            unsafe { std::slice::from_raw_parts(info.private_data, info.private_data_len) }.to_vec()
+        };
+
+        Ok(Some(ProjectionInfo {
+            projection_type: match info.projection_type {
+                0 => ProjectionType::Rectangular,
+                1 => ProjectionType::Equirectangular,
+                2 => ProjectionType::CubeMap,
+                3 => ProjectionType::Mesh,
+                other => ProjectionType::Other(other),
+            },
+            private_data,
+            pose_yaw: info.has_pose_yaw.then_some(info.pose_yaw),
+            pose_pitch: info.has_pose_pitch.then_some(info.pose_pitch),
+            pose_roll: info.has_pose_roll.then_some(info.pose_roll),
+        }))
+    }
+
+    /// Finds the payload range of a top-level element, as one of the `ffi::parser::ELEMENT_*` constants.
+    fn element_payload(&mut self, element: u64) -> Result<Option<(u64, u64)>, DemuxError> {
+        let mut start: i64 = -1;
+        let mut size: i64 = 0;
+        let result = unsafe { ffi::parser::element_payload(self.parser.as_ptr(), element, &mut start, &mut size) };
+        self.check(result)?;
+
+        let Ok(start) = u64::try_from(start) else {
+            return Ok(None);
+        };
+        let size = u64::try_from(size).map_err(|_| DemuxError::InvalidFile)?;
+        Ok(Some((start, start + size)))
+    }
+
+    fn read_tracks(&mut self) -> Result<Vec<DemuxTrackInfo>, DemuxError>
+    where
+        R: MkvRead,
+    {
+        let count = unsafe { ffi::parser::track_count(self.parser.as_ptr()) };
+        let mut tracks = Vec::new();
+
+        for index in 0..count {
+            let mut info = ffi::parser::TrackInfo {
+                number: 0,
+                uid: 0,
+                track_type: 0,
+                codec_id: std::ptr::null(),
+                codec_private: std::ptr::null(),
+                codec_private_len: 0,
+                default_duration: 0,
+                codec_delay: 0,
+                seek_pre_roll: 0,
+                max_block_addition_id: 0,
+                name: std::ptr::null(),
+                language: std::ptr::null(),
+                language_ietf_pos: -1,
+                language_ietf_len: -1,
+                width: 0,
+                height: 0,
+                display_width: 0,
+                display_height: 0,
+                sampling_rate: 0.0,
+                channels: 0,
+                bit_depth: 0,
+            };
+            let result = unsafe { ffi::parser::track_info(self.parser.as_ptr(), index, &mut info) };
+            self.check(result)?;
+
+            let codec_id = unsafe { owned_string(info.codec_id) }.unwrap_or_default();
+            let mut invalid_utf8 = false;
+            let name = unsafe { checked_string(info.name, &mut invalid_utf8) };
+            let language = unsafe { checked_string(info.language, &mut invalid_utf8) };
+            let language_bcp47 = if info.language_ietf_pos < 0 {
+                None
+            } else {
+                let mut data = self.read_span(Span::new(info.language_ietf_pos, info.language_ietf_len)?)?;
+                // Strings may be padded with nul bytes
+                data.truncate(data.iter().position(|&byte| byte == 0).unwrap_or(data.len()));
+                Some(decode_utf8(data, &mut invalid_utf8))
+            };
+            let codec_private = if info.codec_private.is_null() {
+                Vec::new()
+            } else {
+                unsafe { std::slice::from_raw_parts(info.codec_private, info.codec_private_len) }.to_vec()
+            };
+
+            let track_type = match info.track_type {
+                ffi::parser::TRACK_TYPE_VIDEO => TrackType::Video(VideoTrackInfo {
+                    width: u32::try_from(info.width).unwrap_or(0),
+                    height: u32::try_from(info.height).unwrap_or(0),
+                    display_width: u32::try_from(info.display_width).unwrap_or(0),
+                    display_height: u32::try_from(info.display_height).unwrap_or(0),
+                    colour: self.read_colour(info.number)?.map(Box::new),
+                    projection: self.read_projection(info.number)?,
+                }),
+                ffi::parser::TRACK_TYPE_AUDIO => TrackType::Audio(AudioTrackInfo {
+                    sample_rate: info.sampling_rate,
+                    channels: u32::try_from(info.channels).unwrap_or(0),
+                    bit_depth: u32::try_from(info.bit_depth).ok().filter(|&depth| depth != 0),
+                }),
+                other => TrackType::Other(other),
+            };
+
+            tracks.push(DemuxTrackInfo {
+                track_num: info.number,
+                uid: info.uid,
+                track_type,
+                codec_id,
+                codec_private,
+                default_duration_ns: (info.default_duration != 0).then_some(info.default_duration),
+                codec_delay_ns: info.codec_delay,
+                seek_pre_roll_ns: info.seek_pre_roll,
+                max_block_addition_id: info.max_block_addition_id,
+                name,
+                language,
+                language_bcp47,
+                invalid_utf8,
+            });
+        }
+
+        Ok(tracks)
+    }
+}
+
+/// How many top-level elements [`Demuxer::scan_top_level`] looks at before giving up.
+const MAX_SCANNED_ELEMENTS: usize = 4096;
+
+const CHAPTERS_ID: u32 = 0x1043_A770;
+const TAGS_ID: u32 = 0x1254_C367;
+const CUES_ID: u32 = 0x1C53_BB6B;
+const ATTACHMENTS_ID: u32 = 0x1941_A469;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const CLUSTER_TIMECODE_ID: u8 = 0xE7;
+const CUE_POINT_ID: u32 = 0xBB;
+const CUE_TIME_ID: u32 = 0xB3;
+const CUE_TRACK_POSITIONS_ID: u32 = 0xB7;
+const CUE_TRACK_ID: u32 = 0xF7;
+const CUE_CLUSTER_POSITION_ID: u32 = 0xF1;
+const EDITION_ENTRY_ID: u32 = 0x45B9;
+const CHAPTER_ATOM_ID: u32 = 0xB6;
+const CHAPTER_UID_ID: u32 = 0x73C4;
+const CHAPTER_STRING_UID_ID: u32 = 0x5654;
+const CHAPTER_TIME_START_ID: u32 = 0x91;
+const CHAPTER_TIME_END_ID: u32 = 0x92;
+const CHAPTER_FLAG_HIDDEN_ID: u32 = 0x98;
+const CHAPTER_FLAG_ENABLED_ID: u32 = 0x4598;
+const CHAPTER_DISPLAY_ID: u32 = 0x80;
+const CHAP_STRING_ID: u32 = 0x85;
+const CHAP_LANGUAGE_ID: u32 = 0x437C;
+const CHAP_COUNTRY_ID: u32 = 0x437E;
+
+fn read_chapter_atom<S: MkvRead>(source: &mut S, atom: &Element) -> Result<ChapterInfo, DemuxError> {
+    let mut chapter = ChapterInfo {
+        uid: 0,
+        string_uid: None,
+        start_ns: 0,
+        end_ns: None,
+        hidden: false,
+        enabled: true,
+        displays: Vec::new(),
+        children: Vec::new(),
+    };
+
+    for child in ebml::children(source, atom.pos, atom.end())? {
+        match child.id {
+            CHAPTER_UID_ID => chapter.uid = ebml::read_uint(source, &child)?,
+            CHAPTER_STRING_UID_ID => chapter.string_uid = Some(ebml::read_string(source, &child)?),
+            CHAPTER_TIME_START_ID => chapter.start_ns = ebml::read_uint(source, &child)?,
+            CHAPTER_TIME_END_ID => chapter.end_ns = Some(ebml::read_uint(source, &child)?),
+            CHAPTER_FLAG_HIDDEN_ID => chapter.hidden = ebml::read_uint(source, &child)? != 0,
+            CHAPTER_FLAG_ENABLED_ID => chapter.enabled = ebml::read_uint(source, &child)? != 0,
+            CHAPTER_DISPLAY_ID => {
+                let mut display = ChapterDisplay {
+                    string: String::new(),
+                    languages: Vec::new(),
+                    countries: Vec::new(),
+                };
+                for field in ebml::children(source, child.pos, child.end())? {
+                    match field.id {
+                        CHAP_STRING_ID => display.string = ebml::read_string(source, &field)?,
+                        CHAP_LANGUAGE_ID => display.languages.push(ebml::read_string(source, &field)?),
+                        CHAP_COUNTRY_ID => display.countries.push(ebml::read_string(source, &field)?),
+                        _ => {},
+                    }
+                }
+                if display.languages.is_empty() {
+                    display.languages.push("eng".to_owned());
+                }
+                chapter.displays.push(display);
+            },
+            CHAPTER_ATOM_ID => chapter.children.push(read_chapter_atom(source, &child)?),
+            _ => {},
+        }
+    }
+
+    Ok(chapter)
+}
+
+const TAG_ID: u32 = 0x7373;
+const TARGETS_ID: u32 = 0x63C0;
+const TARGET_TYPE_VALUE_ID: u32 = 0x68CA;
+const TAG_TRACK_UID_ID: u32 = 0x63C5;
+const TAG_EDITION_UID_ID: u32 = 0x63C9;
+const TAG_CHAPTER_UID_ID: u32 = 0x63C4;
+const TAG_ATTACHMENT_UID_ID: u32 = 0x63C6;
+const SIMPLE_TAG_ID: u32 = 0x67C8;
+const TAG_NAME_ID: u32 = 0x45A3;
+const TAG_LANGUAGE_ID: u32 = 0x447A;
+const TAG_DEFAULT_ID: u32 = 0x4484;
+const TAG_STRING_ID: u32 = 0x4487;
+const TAG_BINARY_ID: u32 = 0x4485;
+const ATTACHED_FILE_ID: u32 = 0x61A7;
+const FILE_DESCRIPTION_ID: u32 = 0x467E;
+const FILE_NAME_ID: u32 = 0x466E;
+const FILE_MEDIA_TYPE_ID: u32 = 0x4660;
+const FILE_DATA_ID: u32 = 0x465C;
+const FILE_UID_ID: u32 = 0x46AE;
+
+fn read_simple_tag<S: MkvRead>(source: &mut S, simple_tag: &Element) -> Result<SimpleTagInfo, DemuxError> {
+    let mut tag = SimpleTagInfo {
+        name: String::new(),
+        value: TagValue::Empty,
+        language: "und".to_owned(),
+        default: true,
+        children: Vec::new(),
+    };
+
+    for child in ebml::children(source, simple_tag.pos, simple_tag.end())? {
+        match child.id {
+            TAG_NAME_ID => tag.name = ebml::read_string(source, &child)?,
+            TAG_LANGUAGE_ID => tag.language = ebml::read_string(source, &child)?,
+            TAG_DEFAULT_ID => tag.default = ebml::read_uint(source, &child)? != 0,
+            TAG_STRING_ID => tag.value = TagValue::String(ebml::read_string(source, &child)?),
+            TAG_BINARY_ID => tag.value = TagValue::Binary(ebml::read_bytes(source, &child)?),
+            SIMPLE_TAG_ID => tag.children.push(read_simple_tag(source, &child)?),
+            _ => {},
+        }
+    }
+
+    Ok(tag)
+}
+
+/// ## Safety
+/// `ptr` must be null, or point to a valid nul-terminated string.
+unsafe fn owned_string(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
+}
+
+/// Like [`owned_string`], but strings that aren't valid UTF-8 are decoded lossily and set `invalid_utf8`.
+///
+/// ## Safety
+/// As for [`owned_string`].
+unsafe fn checked_string(ptr: *const c_char, invalid_utf8: &mut bool) -> Option<String> {
+    (!ptr.is_null()).then(|| decode_utf8(unsafe { CStr::from_ptr(ptr) }.to_bytes().to_vec(), invalid_utf8))
+}
+
+/// Decodes `data` as UTF-8, replacing anything invalid and setting `invalid_utf8` if there is any.
+fn decode_utf8(data: Vec<u8>, invalid_utf8: &mut bool) -> String {
+    String::from_utf8(data).unwrap_or_else(|err| {
+        *invalid_utf8 = true;
+        String::from_utf8_lossy(err.as_bytes()).into_owned()
+    })
+}
+
+/// Seconds from the Unix epoch to the Matroska epoch, 2001-01-01T00:00:00 UTC.
+const MATROSKA_EPOCH_UNIX_SECS: u64 = 978_307_200;
+
+/// Converts a `DateUTC` value, in nanoseconds relative to the Matroska epoch, to a [`SystemTime`]. Returns `None`
+/// if it can't be represented on this platform.
+fn matroska_date(date_utc: i64) -> Option<SystemTime> {
+    let epoch = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(MATROSKA_EPOCH_UNIX_SECS))?;
+    let offset = Duration::from_nanos(date_utc.unsigned_abs());
+    if date_utc >= 0 {
+        epoch.checked_add(offset)
+    } else {
+        epoch.checked_sub(offset)
+    }
+}
+
+/// Negative timestamps can only come from blocks placed before the start of their cluster, which we can't represent.
+pub(super) fn clamp_timestamp(time_ns: i64) -> u64 {
+    u64::try_from(time_ns).unwrap_or(0)
+}
+
+/// The timestamp of frame `index` of a block of `count` frames starting at `block_time_ns`.
+fn lace_timestamp(
+    block_time_ns: i64,
+    block_duration_ns: Option<u64>,
+    default_duration_ns: Option<u64>,
+    index: usize,
+    count: usize,
+) -> u64 {
+    let start = clamp_timestamp(block_time_ns);
+    let offset = match (default_duration_ns, block_duration_ns) {
+        (Some(frame_duration), _) => frame_duration.saturating_mul(index as u64),
+        (None, Some(block_duration)) => {
+            (u128::from(block_duration) * index as u128 / count.max(1) as u128).try_into().unwrap_or(u64::MAX)
+        },
+        (None, None) => 0,
+    };
+    start.saturating_add(offset)
+}
+
+impl<R> std::fmt::Debug for Demuxer<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We can't/shouldn't crawl into our FFI pointers for debug printing, and we don't require `R: Debug`, but we
+        // should still have even a primitive Debug impl to avoid friction with user structs that #[derive(Debug)]
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// An iterator over the frames of a [`Demuxer`], created by [`Demuxer::frames`] or [`Demuxer::track_frames`].
+pub struct Frames<'a, R> {
+    demuxer: &'a mut Demuxer<R>,
+    track_filter: Option<TrackNum>,
+}
+
+impl<R> Iterator for Frames<'_, R>
+where
+    R: MkvRead,
+{
+    type Item = Result<DemuxFrame, DemuxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.demuxer.next_frame(self.track_filter).transpose()
+    }
+}
+
+impl<R> std::fmt::Debug for Frames<'_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// An iterator over the frames of a [`Demuxer`] reading from a slice, created by [`Demuxer::borrowed_frames`].
+pub struct BorrowedFrames<'d, 'a> {
+    demuxer: &'d mut Demuxer<Cursor<&'a [u8]>>,
+}
+
+impl<'a> Iterator for BorrowedFrames<'_, 'a> {
+    type Item = Result<DemuxFrameRef<'a>, DemuxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.demuxer.next_borrowed_frame().transpose()
+    }
+}
+
+impl std::fmt::Debug for BorrowedFrames<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// An iterator over the frames of a [`Demuxer`] reading from a [`bytes::Bytes`], created by
+/// [`Demuxer::bytes_frames`].
+#[cfg(feature = "bytes")]
+pub struct BytesFrames<'d> {
+    demuxer: &'d mut Demuxer<Cursor<bytes::Bytes>>,
+}
+
+#[cfg(feature = "bytes")]
+impl Iterator for BytesFrames<'_> {
+    type Item = Result<DemuxFrameBytes, DemuxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.demuxer.next_bytes_frame().transpose()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl std::fmt::Debug for BytesFrames<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, ColorRange, FrameExtras, SegmentBuilder, VideoCodecId, Writer};
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    /// (is_video, timestamp_ns, keyframe, payload)
+    type TestFrame = (bool, u64, bool, Vec<u8>);
+
+    fn test_frames() -> Vec<TestFrame> {
+        // Timestamps are whole milliseconds, matching the default timecode scale, and strictly increasing
+        // so the storage order is unambiguous.
+        (0..40u64)
+            .map(|i| {
+                let is_video = i % 2 == 0;
+                let keyframe = !is_video || i % 20 == 0;
+                let payload = vec![i as u8; 10 + i as usize];
+                (is_video, i * 10_000_000, keyframe, payload)
+            })
+            .collect()
+    }
+
+    fn mux_test_file(frames: &[TestFrame]) -> (Vec<u8>, TrackNum, TrackNum) {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for (is_video, timestamp, keyframe, payload) in frames {
+            let track: TrackNum = if *is_video { video.into() } else { audio.into() };
+            segment.add_frame(track, payload, *timestamp, *keyframe).unwrap();
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        (writer.into_inner().into_inner(), video.into(), audio.into())
+    }
+
+    #[test]
+    fn reads_tracks() {
+        let (file, video, audio) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        assert_eq!(demuxer.tracks().len(), 2);
+
+        let video_info = demuxer.track(video).unwrap();
+        assert_eq!(video_info.codec_id, "V_VP9");
+        assert!(matches!(
+            video_info.track_type,
+            TrackType::Video(VideoTrackInfo {
+                width: 64,
+                height: 48,
+                display_width: 64,
+                display_height: 48,
+                colour: None,
+                projection: None,
+            })
+        ));
+
+        let audio_info = demuxer.track(audio).unwrap();
+        assert_eq!(audio_info.codec_id, "A_OPUS");
+        let TrackType::Audio(audio_info) = &audio_info.track_type else {
+            panic!("Expected an audio track");
+        };
+        assert_eq!(audio_info.sample_rate, 48000.0);
+        assert_eq!(audio_info.channels, 2);
+    }
+
+    #[test]
+    fn reads_track_names_and_languages() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_track_name(audio, "Commentaire — réalisateur").unwrap();
+        let mut segment = builder.set_track_language(audio, "fre").unwrap().build();
+        segment.add_frame(video, &[1; 16], 0, true).unwrap();
+        segment.add_frame(audio, &[2; 8], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let file = writer.into_inner().into_inner();
+
+        let demuxer = Demuxer::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!(demuxer.track(video.into()).unwrap().name, None);
+        let info = demuxer.track(audio.into()).unwrap();
+        assert_eq!(info.name.as_deref(), Some("Commentaire — réalisateur"));
+        assert_eq!(info.language.as_deref(), Some("fre"));
+        assert_eq!(info.language_bcp47, None);
+        assert!(!info.invalid_utf8);
+
+        // Turn the `Language` into a `LanguageIETF`, and break the UTF-8 of the name
+        let mut patched = file;
+        let language = patched.windows(4).position(|window| window == [0x22, 0xB5, 0x9C, 0x83]).unwrap();
+        patched[language + 2] = 0x9D;
+        let name = patched.windows(11).position(|window| window == b"Commentaire").unwrap();
+        patched[name] = 0xFF;
+
+        let demuxer = Demuxer::new(Cursor::new(patched)).unwrap();
+        let info = demuxer.track(audio.into()).unwrap();
+        assert_eq!(info.name.as_deref(), Some("\u{FFFD}ommentaire — réalisateur"));
+        assert_eq!(info.language, None);
+        assert_eq!(info.language_bcp47.as_deref(), Some("fre"));
+        assert!(info.invalid_utf8);
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        let frames = test_frames();
+        let (file, video, audio) = mux_test_file(&frames);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let demuxed: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(demuxed.len(), frames.len());
+
+        for (expected, actual) in frames.iter().zip(&demuxed) {
+            let (is_video, timestamp, keyframe, payload) = expected;
+            assert_eq!(actual.track_num, if *is_video { video } else { audio });
+            assert_eq!(actual.timestamp_ns, *timestamp);
+            assert_eq!(actual.is_keyframe, *keyframe);
+            assert!(actual.additions.is_empty());
+            assert_eq!(actual.discard_padding_ns, None);
+            assert_eq!(actual.duration_ns, None);
+            assert_eq!(&actual.data, payload);
+        }
+    }
+
+    #[test]
+    fn resumes_and_rewinds() {
+        let frames = test_frames();
+        let (file, ..) = mux_test_file(&frames);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let first: Vec<DemuxFrame> = demuxer.frames().take(5).collect::<Result<_, _>>().unwrap();
+        let rest: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(first.len() + rest.len(), frames.len());
+        assert_eq!(rest[0].timestamp_ns, frames[5].1);
+        assert!(demuxer.frames().next().is_none());
+
+        demuxer.rewind();
+        assert_eq!(demuxer.frames().next().unwrap().unwrap().data, first[0].data);
+    }
+
+    /// Turns the `SimpleBlock` whose payload is `payload` into a laced one, by setting the lacing `flags` and
+    /// overwriting the start of the payload with `lace_header`.
+    fn lace_block(file: &mut [u8], payload: &[u8], flags: u8, lace_header: &[u8]) {
+        let start = file.windows(payload.len()).position(|window| window == payload).unwrap();
+        file[start - 1] |= flags;
+        file[start..start + lace_header.len()].copy_from_slice(lace_header);
+    }
+
+    #[test]
+    fn splits_laced_blocks() {
+        let payload: Vec<u8> = (0..16).collect();
+        // libwebm holds audio back until there is a video frame to start the cluster with
+        let audio_frames = [vec![0xEE; 16], payload.clone(), vec![0xEE; 16]]
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| (false, i as u64 * 20_000_000, true, payload));
+        let frames: Vec<TestFrame> = std::iter::once((true, 0, true, vec![0xDD; 16])).chain(audio_frames).collect();
+        let (file, _, audio) = mux_test_file(&frames);
+
+        // Xiph and EBML lacing with frames of 4, 5 and 4 bytes, and fixed-size lacing with 3 frames of 5 bytes
+        let lacings = [
+            (0x02, &[2, 4, 5][..], [4, 5, 4]),
+            (0x06, &[2, 0x84, 0xC0][..], [4, 5, 4]),
+            (0x04, &[2][..], [5, 5, 5]),
+        ];
+        for (flags, lace_header, sizes) in lacings {
+            let mut file = file.clone();
+            lace_block(&mut file, &payload, flags, lace_header);
+            let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+            let frames: Vec<DemuxFrame> = demuxer.track_frames(audio).collect::<Result<_, _>>().unwrap();
+            assert_eq!(frames.len(), 5);
+            assert!(!frames[0].laced && !frames[4].laced);
+            let mut start = lace_header.len();
+            for (index, (frame, size)) in frames[1..4].iter().zip(sizes).enumerate() {
+                assert!(frame.laced);
+                assert_eq!(frame.lace_index, index);
+                assert_eq!(frame.data, payload[start..start + size]);
+                start += size;
+
+                // Without any durations, there is nothing to interpolate with
+                assert_eq!(frame.timestamp_ns, 20_000_000);
+            }
+
+            let track = demuxer.tracks.iter_mut().find(|track| track.track_num == audio).unwrap();
+            track.default_duration_ns = Some(5_000_000);
+            demuxer.rewind();
+            let timestamps: Vec<u64> =
+                demuxer.track_frames(audio).map(|frame| frame.unwrap().timestamp_ns / 1_000_000).collect();
+            assert_eq!(timestamps, [0, 20, 25, 30, 40]);
+        }
+    }
+
+    #[test]
+    fn interpolates_lace_timestamps() {
+        assert_eq!(lace_timestamp(1000, Some(300), None, 2, 3), 1200);
+        assert_eq!(lace_timestamp(1000, Some(300), Some(50), 2, 3), 1100);
+        assert_eq!(lace_timestamp(1000, None, None, 2, 3), 1000);
+        assert_eq!(lace_timestamp(-5, None, Some(50), 1, 3), 50);
+    }
+
+    /// Wraps a reader, counting how many bytes are read through it.
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: std::rc::Rc<std::cell::Cell<u64>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let num_bytes = self.inner.read(buf)?;
+            self.bytes_read.set(self.bytes_read.get() + num_bytes as u64);
+            Ok(num_bytes)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn filters_by_track() {
+        let frames = test_frames();
+        let (file, video, audio) = mux_test_file(&frames);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let audio_frames: Vec<DemuxFrame> = demuxer.track_frames(audio).collect::<Result<_, _>>().unwrap();
+        let expected: Vec<&TestFrame> = frames.iter().filter(|(is_video, ..)| !is_video).collect();
+        assert_eq!(audio_frames.len(), expected.len());
+        for (expected, actual) in expected.iter().zip(&audio_frames) {
+            assert_eq!(actual.track_num, audio);
+            assert_eq!(actual.timestamp_ns, expected.1);
+            assert_eq!(actual.data, expected.3);
+        }
+
+        // The shared position is at the end now
+        assert!(demuxer.track_frames(video).next().is_none());
+        demuxer.rewind();
+        assert!(demuxer.track_frames(video).all(|frame| frame.unwrap().track_num == video));
+
+        demuxer.rewind();
+        assert!(demuxer.track_frames(12345u64).next().is_none());
+    }
+
+    #[test]
+    fn skipped_payloads_are_not_read() {
+        const VIDEO_FRAME_SIZE: usize = 64 * 1024;
+
+        let frames: Vec<TestFrame> = (0..20u64)
+            .map(|i| {
+                let is_video = i % 2 == 0;
+                let payload = if is_video { vec![0xAA; VIDEO_FRAME_SIZE] } else { vec![0x55; 16] };
+                (is_video, i * 10_000_000, true, payload)
+            })
+            .collect();
+        let (file, _, audio) = mux_test_file(&frames);
+
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(file),
+            bytes_read: bytes_read.clone(),
+        };
+        let mut demuxer = Demuxer::new(reader).unwrap();
+
+        let before = bytes_read.get();
+        let audio_frames = demuxer.track_frames(audio).count();
+        let during = bytes_read.get() - before;
+
+        assert_eq!(audio_frames, 10);
+        // Reading even one video frame's payload would blow this budget
+        assert!(during < VIDEO_FRAME_SIZE as u64, "read {during} bytes to get the audio frames");
+    }
+
+    /// One video track at 10fps with a keyframe every second, plus an audio frame every 50ms.
+    fn seekable_test_frames() -> Vec<TestFrame> {
+        (0..100u64)
+            .map(|i| {
+                let is_video = i % 2 == 0;
+                let keyframe = !is_video || i % 20 == 0;
+                (is_video, i * 50_000_000, keyframe, vec![i as u8; 32])
+            })
+            .collect()
+    }
+
+    fn assert_lands_on(demuxer: &mut Demuxer<Cursor<Vec<u8>>>, video: TrackNum, target: u64, expected: u64) {
+        let point = demuxer.seek(target, video).unwrap();
+        assert_eq!(point.timestamp_ns, expected);
+
+        let frame = demuxer.track_frames(video).next().unwrap().unwrap();
+        assert!(frame.is_keyframe);
+        assert_eq!(frame.timestamp_ns, expected);
+    }
+
+    #[test]
+    fn seeks_with_cues() {
+        let (file, video, _) = mux_test_file(&seekable_test_frames());
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let point = demuxer.seek(2_500_000_000, video).unwrap();
+        assert!(!point.linear_scan);
+        assert!(point.cluster_offset > 0);
+
+        assert_lands_on(&mut demuxer, video, 2_500_000_000, 2_000_000_000);
+        assert_lands_on(&mut demuxer, video, 3_000_000_000, 3_000_000_000);
+        assert_lands_on(&mut demuxer, video, 999_999_999, 0);
+        assert_lands_on(&mut demuxer, video, u64::MAX, 4_000_000_000);
+
+        // Seeking backwards works too, and iteration continues in storage order
+        demuxer.seek(1_200_000_000, video).unwrap();
+        let next: Vec<DemuxFrame> = demuxer.frames().take(3).collect::<Result<_, _>>().unwrap();
+        assert_eq!(next[0].timestamp_ns, 1_000_000_000);
+        assert!(next.windows(2).all(|pair| pair[0].timestamp_ns < pair[1].timestamp_ns));
+
+        assert!(matches!(demuxer.seek(0, 12345u64), Err(DemuxError::BadParam)));
+    }
+
+    #[test]
+    fn seeks_without_cues() {
+        // Without the ability to seek, libwebm can't add the cues to the SeekHead
+        let writer = Writer::new_non_seek(Vec::new());
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for (_, timestamp, keyframe, payload) in seekable_test_frames().iter().filter(|frame| frame.0) {
+            segment.add_frame(video, payload, *timestamp, *keyframe).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let mut demuxer = Demuxer::new(Cursor::new(writer.into_inner())).unwrap();
+        let point = demuxer.seek(2_500_000_000, video).unwrap();
+        assert!(point.linear_scan);
+        assert_eq!(point.timestamp_ns, 2_000_000_000);
+
+        let frame = demuxer.frames().next().unwrap().unwrap();
+        assert!(frame.is_keyframe);
+        assert_eq!(frame.timestamp_ns, 2_000_000_000);
+    }
+
+    #[test]
+    fn indexes_cues_and_keyframes() {
+        let (file, video, audio) = mux_test_file(&seekable_test_frames());
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let cues = demuxer.cue_index().unwrap().to_vec();
+        let cue_times: Vec<u64> = cues.iter().map(|cue| cue.timestamp_ns).collect();
+        assert_eq!(cue_times, [0, 1_000_000_000, 2_000_000_000, 3_000_000_000, 4_000_000_000]);
+        assert!(cues.iter().all(|cue| cue.track_num == video));
+
+        // Reading the index doesn't disturb iteration
+        let first = demuxer.frames().next().unwrap().unwrap();
+        let keyframes = demuxer.keyframe_index(video).unwrap();
+        assert_eq!(demuxer.frames().next().unwrap().unwrap().timestamp_ns, 50_000_000);
+        assert_eq!(first.timestamp_ns, 0);
+
+        // Every video keyframe starts a cluster, so both indexes agree
+        let from_cues: Vec<(u64, u64)> = cues.iter().map(|cue| (cue.timestamp_ns, cue.cluster_offset)).collect();
+        let from_scan: Vec<(u64, u64)> =
+            keyframes.iter().map(|keyframe| (keyframe.timestamp_ns, keyframe.cluster_offset)).collect();
+        assert_eq!(from_cues, from_scan);
+        assert_eq!(demuxer.seek(2_500_000_000, video).unwrap().cluster_offset, from_cues[2].1);
+
+        assert_eq!(demuxer.keyframe_index(audio).unwrap().len(), 50);
+        assert!(matches!(demuxer.keyframe_index(12345u64), Err(DemuxError::BadParam)));
+    }
+
+    #[test]
+    fn keyframe_index_skips_payloads() {
+        const VIDEO_FRAME_SIZE: usize = 64 * 1024;
+
+        // Written without cues, as by a live recorder
+        let writer = Writer::new_non_seek(Vec::new());
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..20u64 {
+            segment.add_frame(video, &vec![0xAA; VIDEO_FRAME_SIZE], i * 100_000_000, i % 5 == 0).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(writer.into_inner()),
+            bytes_read: bytes_read.clone(),
+        };
+        let mut demuxer = Demuxer::new(reader).unwrap();
+        assert!(demuxer.cue_index().unwrap().is_empty());
+
+        let before = bytes_read.get();
+        let keyframes = demuxer.keyframe_index(video).unwrap();
+        let during = bytes_read.get() - before;
+
+        let times: Vec<u64> = keyframes.iter().map(|keyframe| keyframe.timestamp_ns).collect();
+        assert_eq!(times, [0, 500_000_000, 1_000_000_000, 1_500_000_000]);
+        assert!(during < VIDEO_FRAME_SIZE as u64, "read {during} bytes to index the keyframes");
+    }
+
+    #[test]
+    fn reports_sync() {
+        // Ten seconds of video at 25fps and 20ms audio frames, with the audio's timestamps running `audio_ppm` fast
+        let mux = |audio_ppm: u64| {
+            let writer = Writer::new(Cursor::new(Vec::new()));
+            let builder = SegmentBuilder::new(writer).unwrap();
+            let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+            let video = (0..250).map(|i| (TrackNum::from(video), i * 40_000_000, 40_000_000, i % 25 == 0));
+            let audio_gap = 20_000_000 + 20 * audio_ppm;
+            let audio = (0..500).map(|i| (TrackNum::from(audio), i * audio_gap, 20_000_000, true));
+            let mut frames: Vec<_> = video.chain(audio).collect();
+            frames.sort_by_key(|&(_, timestamp_ns, _, _)| timestamp_ns);
+
+            let mut segment = builder.build();
+            for (track, timestamp_ns, duration_ns, keyframe) in frames {
+                let extras = FrameExtras {
+                    duration_ns: Some(duration_ns),
+                    ..FrameExtras::default()
+                };
+                segment.add_frame_with_extras(track, &[0; 10], timestamp_ns, keyframe, extras).unwrap();
+            }
+            let report = segment.sync_report();
+            let Ok(writer) = segment.finalize(None) else {
+                panic!("Finalization unexpectedly failed")
+            };
+            (report, writer.into_inner().into_inner())
+        };
+
+        let (muxed, file) = mux(0);
+        assert!(muxed.is_in_sync(), "{muxed}");
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let report = demuxer.sync_report().unwrap();
+        assert!(report.is_in_sync(), "{report}");
+        assert_eq!(report.tracks.iter().map(|track| track.frames).collect::<Vec<_>>(), [250, 500]);
+
+        // 1% over ten seconds is 100ms, though the timecode scale cuts each timestamp to the millisecond
+        let (muxed, file) = mux(10_000);
+        assert!(!muxed.is_in_sync());
+        assert_eq!(muxed.pairs[0].max_skew_ns, -499 * 200_000);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        demuxer.frames().next().unwrap().unwrap();
+        let report = demuxer.sync_report().unwrap();
+        assert!(!report.is_in_sync(), "{report}");
+        let audio = report.tracks[1];
+        assert!(audio.drift_ns.abs_diff(499 * 200_000) < 1_000_000, "{audio:?}");
+        assert!((report.pairs[0].trend_ppm + 9_900.0).abs() < 50.0, "{report}");
+
+        // The position is kept
+        assert_eq!(demuxer.frames().count(), 749);
+    }
+
+    #[test]
+    fn open_reads_only_headers() {
+        const VIDEO_FRAME_SIZE: usize = 64 * 1024;
+
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..200u64 {
+            segment.add_frame(video, &vec![0xAA; VIDEO_FRAME_SIZE], i * 40_000_000, i % 25 == 0).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let file = writer.into_inner().into_inner();
+
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(file),
+            bytes_read: bytes_read.clone(),
+        };
+        let mut demuxer = Demuxer::new(reader).unwrap();
+        let during_open = bytes_read.get();
+        assert!(during_open < 8 * 1024, "read {during_open} bytes to open the file");
+
+        // The `Cues` at the end are found via the `SeekHead`, and the file has no tags or chapters to look for
+        assert_eq!(demuxer.cue_index().unwrap().first().map(|cue| cue.timestamp_ns), Some(0));
+        assert!(demuxer.tags().unwrap().is_empty());
+        assert!(demuxer.chapters().unwrap().is_empty());
+        let total = bytes_read.get();
+        assert!(total < 2 * VIDEO_FRAME_SIZE as u64, "read {total} bytes in total");
+    }
+
+    #[test]
+    fn computes_duration() {
+        // The last video frame is a keyframe, so that the last cue is close to the end
+        let frames: Vec<TestFrame> = test_frames()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (is_video, timestamp, keyframe, payload))| (is_video, timestamp, keyframe || i == 38, payload))
+            .collect();
+        let (mut file, _, _) = mux_test_file(&frames);
+
+        // Turn the `Duration` into a `Void` element of the same size
+        let duration = file.windows(2).position(|window| window == [0x44, 0x89]).unwrap();
+        let len = 3 + usize::from(file[duration + 2] & 0x7F);
+        file[duration..duration + 3].copy_from_slice(&[0xEC, 0x40, len as u8 - 3]);
+        file[duration + 3..duration + len].fill(0);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.segment_info().duration_ns, None);
+        let first = demuxer.frames().next().unwrap().unwrap();
+        assert_eq!(first.timestamp_ns, 0);
+
+        // The last frame starts at 390ms, and each track has a frame every 20ms
+        let truth = 400_000_000;
+        for strategy in [DurationStrategy::FromCues, DurationStrategy::FromLastCluster, DurationStrategy::FullScan] {
+            let duration = demuxer.compute_duration(strategy).unwrap();
+            assert!(truth - duration <= 20_000_000, "{strategy:?} gave {duration}ns");
+        }
+        assert_eq!(demuxer.compute_duration(DurationStrategy::FullScan).unwrap(), 390_000_000);
+        assert_eq!(demuxer.compute_duration(DurationStrategy::FromLastCluster).unwrap(), 390_000_000);
+
+        // Iteration carries on where it left off
+        assert_eq!(demuxer.frames().count(), frames.len() - 1);
+    }
+
+    #[test]
+    fn resyncs_after_damaged_cluster() {
+        let frames = seekable_test_frames();
+        let (mut file, video, _) = mux_test_file(&frames);
+        let clusters: Vec<u64> = Demuxer::new(Cursor::new(file.clone()))
+            .unwrap()
+            .cue_index()
+            .unwrap()
+            .iter()
+            .map(|cue| cue.cluster_offset)
+            .collect();
+        assert_eq!(clusters.len(), 5);
+
+        // Break the track number of the block at 2.3s, in the third cluster
+        let payload = file.windows(32).position(|window| window == [46; 32]).unwrap();
+        file[payload - 4] = 0;
+
+        let mut strict = Demuxer::new(Cursor::new(file.clone())).unwrap();
+        assert!(matches!(strict.frames().collect::<Result<Vec<_>, _>>(), Err(DemuxError::InvalidFile)));
+
+        let options = DemuxOptions {
+            lenient: true,
+            ..DemuxOptions::default()
+        };
+        let mut lenient = Demuxer::with_options(Cursor::new(file), options).unwrap();
+        let demuxed: Vec<DemuxFrame> = lenient.frames().collect::<Result<_, _>>().unwrap();
+
+        // Everything from the damage to the end of its cluster is lost, but nothing else. libwebm holds audio back
+        // until the next video frame, so the audio at 2.95s is in the fourth cluster, with the video at 3s.
+        let expected: Vec<u64> = frames
+            .iter()
+            .map(|frame| frame.1)
+            .filter(|&timestamp| !(2_300_000_000..2_950_000_000).contains(&timestamp))
+            .collect();
+        let timestamps: Vec<u64> = demuxed.iter().map(|frame| frame.timestamp_ns).collect();
+        assert_eq!(timestamps, expected);
+        assert!(demuxed.iter().any(|frame| frame.track_num == video && frame.timestamp_ns == 4_000_000_000));
+        assert_eq!(
+            lenient.warnings(),
+            [DemuxWarning::ResyncSkipped {
+                from_offset: clusters[2],
+                to_offset: clusters[3],
+            }]
+        );
+    }
+
+    #[test]
+    fn resyncs_after_truncated_block() {
+        let frames = seekable_test_frames();
+        let (mut file, ..) = mux_test_file(&frames);
+        let last_payload = file.windows(32).position(|window| window == [99; 32]).unwrap();
+        file.truncate(last_payload + 16);
+        let length = file.len() as u64;
+
+        let options = DemuxOptions {
+            lenient: true,
+            ..DemuxOptions::default()
+        };
+        let mut demuxer = Demuxer::with_options(Cursor::new(file), options).unwrap();
+        let demuxed: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(demuxed.len(), frames.len() - 1);
+        assert_eq!(demuxed.last().unwrap().data, frames[98].3);
+        assert!(matches!(
+            demuxer.warnings().last(),
+            Some(DemuxWarning::ResyncSkipped { to_offset, .. }) if *to_offset == length
+        ));
+    }
+
+    #[test]
+    fn limits_frame_size() {
+        let frames = [(true, 0, true, vec![1; 100]), (true, 40_000_000, false, vec![2; 1000])];
+        let (file, ..) = mux_test_file(&frames);
+        let options = DemuxOptions {
+            max_frame_size: 500,
+            ..DemuxOptions::default()
+        };
+
+        let mut demuxer = Demuxer::with_options(Cursor::new(file.clone()), options).unwrap();
+        let mut demuxed = demuxer.frames();
+        assert_eq!(demuxed.next().unwrap().unwrap().data, frames[0].3);
+        assert!(matches!(
+            demuxed.next(),
+            Some(Err(DemuxError::LimitExceeded {
+                what: "Frame",
+                requested: 1000,
+                limit: 500,
+            }))
+        ));
+
+        let mut demuxer = Demuxer::with_options(Cursor::new(file), DemuxOptions::unlimited()).unwrap();
+        assert_eq!(demuxer.frames().count(), 2);
+    }
+
+    #[test]
+    fn limits_codec_private_and_reads() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.set_codec_private(audio, &[7; 300]).unwrap().build();
+        segment.add_frame(audio, &[1; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let file = writer.into_inner().into_inner();
+
+        let options = DemuxOptions {
+            max_codec_private: 200,
+            ..DemuxOptions::default()
+        };
+        let result = Demuxer::with_options(Cursor::new(file.clone()), options);
+        assert!(matches!(
+            result,
+            Err(DemuxError::LimitExceeded {
+                what: "CodecPrivate",
+                requested: 300,
+                limit: 200,
+            })
+        ));
+
+        // Even without a `CodecPrivate` limit, libwebm may not read it in one go
+        let options = DemuxOptions {
+            max_total_buffered: 200,
+            ..DemuxOptions::unlimited()
+        };
+        let result = Demuxer::with_options(Cursor::new(file.clone()), options);
+        assert!(matches!(result, Err(DemuxError::LimitExceeded { what: "Read", .. })));
+
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.tracks()[0].codec_private, [7; 300]);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn shares_frames_from_bytes() {
+        let (file, ..) = mux_test_file(&test_frames());
+        let mut owning = Demuxer::new(Cursor::new(file.clone())).unwrap();
+        let expected: Vec<DemuxFrame> = owning.frames().collect::<Result<_, _>>().unwrap();
+
+        let source = bytes::Bytes::from(file);
+        let range = source.as_ptr_range();
+        let mut demuxer = Demuxer::from_bytes(source.clone()).unwrap();
+        assert_eq!(demuxer.tracks(), owning.tracks());
+        let frames: Vec<DemuxFrameBytes> = demuxer.bytes_frames().collect::<Result<_, _>>().unwrap();
+
+        // The payloads point into the buffer itself, and keep it alive after the demuxer is gone
+        assert!(frames.iter().all(|frame| range.contains(&frame.data.as_ptr())));
+        drop(demuxer);
+        let source = source.try_into_mut().unwrap_err();
+        let frames: Vec<DemuxFrame> = frames.into_iter().map(DemuxFrame::from).collect();
+        assert_eq!(frames, expected);
+
+        // Once the frames are gone too, nothing else holds on to it
+        assert!(source.try_into_mut().is_ok());
+    }
+
+    #[test]
+    fn borrows_frames_from_slice() {
+        let (file, ..) = mux_test_file(&test_frames());
+        let mut owning = Demuxer::new(Cursor::new(file.clone())).unwrap();
+        let expected: Vec<DemuxFrame> = owning.frames().collect::<Result<_, _>>().unwrap();
+
+        let mut demuxer = Demuxer::from_slice(&file).unwrap();
+        assert_eq!(demuxer.tracks(), owning.tracks());
+        let frames: Vec<DemuxFrameRef> = demuxer.borrowed_frames().collect::<Result<_, _>>().unwrap();
+
+        // The payloads point into the file itself, and outlive the borrow of the demuxer
+        let range = file.as_ptr_range();
+        assert!(frames.iter().all(|frame| range.contains(&frame.data.as_ptr())));
+        demuxer.rewind();
+        assert_eq!(demuxer.frames().count(), expected.len());
+
+        let frames: Vec<DemuxFrame> = frames.into_iter().map(DemuxFrameRef::into_owned).collect();
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn reads_ebml_header() {
+        let (file, ..) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file.clone())).unwrap();
+        let header = demuxer.ebml_header();
+        assert_eq!(header.doc_type, "webm");
+        assert_eq!((header.version, header.read_version), (1, 1));
+        assert_eq!((header.max_id_length, header.max_size_length), (4, 8));
+        assert!(header.doc_type_version >= 2);
+        assert_eq!(header.doc_type_read_version, 2);
+
+        // Swap in a handcrafted header with a DocType of our own
+        let (_, pos, size) = ebml::read_declared_header(&mut Cursor::new(&file), 0).unwrap();
+        let mut custom = vec![0x1A, 0x45, 0xDF, 0xA3, 0xA3];
+        custom.extend_from_slice(&[0x42, 0x86, 0x81, 0x01, 0x42, 0xF7, 0x81, 0x01]);
+        custom.extend_from_slice(&[0x42, 0xF2, 0x81, 0x04, 0x42, 0xF3, 0x81, 0x08]);
+        custom.extend_from_slice(&[0x42, 0x82, 0x88]);
+        custom.extend_from_slice(b"x-custom");
+        custom.extend_from_slice(&[0x42, 0x87, 0x81, 0x03, 0x42, 0x85, 0x81, 0x01]);
+        custom.extend_from_slice(&file[(pos + size.unwrap()) as usize..]);
+
+        let mut demuxer = Demuxer::new(Cursor::new(custom)).unwrap();
+        assert_eq!(
+            *demuxer.ebml_header(),
+            EbmlHeaderInfo {
+                version: 1,
+                read_version: 1,
+                max_id_length: 4,
+                max_size_length: 8,
+                doc_type: "x-custom".to_string(),
+                doc_type_version: 3,
+                doc_type_read_version: 1,
+            }
+        );
+        assert_eq!(demuxer.frames().count(), test_frames().len());
+    }
+
+    #[test]
+    fn reads_segment_info() {
+        let (file, _, _) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+
+        let info = demuxer.segment_info();
+        assert_eq!(info.timecode_scale, 1_000_000);
+        assert!(info.duration_ns.is_some());
+        assert!(info.muxing_app.as_deref().is_some_and(|app| app.starts_with("libwebm")));
+        assert_eq!(info.title, None);
+        assert_eq!(info.date_utc, None);
+        assert_eq!(info.segment_uid, None);
+    }
+
+    #[test]
+    fn reports_libwebm_version() {
+        let version = crate::libwebm_version();
+        assert_ne!(version.muxer, crate::ComponentVersion::default());
+        assert_ne!(version.parser, crate::ComponentVersion::default());
+        assert_eq!(version.crate_version, env!("CARGO_PKG_VERSION"));
+
+        // mkvmuxer writes its own version as the `MuxingApp`
+        let (file, _, _) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let expected = format!("libwebm-{}", version.muxer);
+        assert_eq!(demuxer.segment_info().muxing_app.as_deref(), Some(expected.as_str()));
+    }
+
+    /// Encodes an EBML element, always using an 8-byte size so that we don't have to care about size limits.
+    fn ebml(id: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x01);
+        element.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+        element.extend_from_slice(payload);
+        element
+    }
+
+    /// A minimal file with a single video track numbered 1, of the kind written by mkvmerge. `track` and `video` are
+    /// appended to the children of the `TrackEntry` and `Video` elements respectively, and `rest` to the segment.
+    fn handmade_file(info: &[u8], track: &[u8], video: &[u8], rest: &[u8]) -> Vec<u8> {
+        let header = [
+            ebml(&[0x42, 0x86], &[1]),
+            ebml(&[0x42, 0xF7], &[1]),
+            ebml(&[0x42, 0xF2], &[4]),
+            ebml(&[0x42, 0xF3], &[8]),
+            ebml(&[0x42, 0x82], b"webm"),
+            ebml(&[0x42, 0x87], &[4]),
+            ebml(&[0x42, 0x85], &[2]),
+        ]
+        .concat();
+        let video = [ebml(&[0xB0], &[64]), ebml(&[0xBA], &[48]), video.to_vec()].concat();
+        let track_entry = [
+            ebml(&[0xD7], &[1]),
+            ebml(&[0x73, 0xC5], &[1]),
+            ebml(&[0x83], &[1]),
+            ebml(&[0x86], b"V_VP9"),
+            ebml(&[0xE0], &video),
+            track.to_vec(),
+        ]
+        .concat();
+        let tracks = ebml(&[0x16, 0x54, 0xAE, 0x6B], &ebml(&[0xAE], &track_entry));
+        let segment = [ebml(&[0x15, 0x49, 0xA9, 0x66], info), tracks, rest.to_vec()].concat();
+
+        [ebml(&[0x1A, 0x45, 0xDF, 0xA3], &header), ebml(&[0x18, 0x53, 0x80, 0x67], &segment)].concat()
+    }
+
+    #[test]
+    fn reads_full_segment_info() {
+        let uid: [u8; 16] = std::array::from_fn(|i| i as u8 * 17);
+        // 2020-01-01T00:00:00 UTC
+        let date_secs: i64 = 1_577_836_800 - MATROSKA_EPOCH_UNIX_SECS as i64;
+        let info = [
+            ebml(&[0x73, 0xA4], &uid),
+            ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes()),
+            ebml(&[0x44, 0x89], &1500.0f64.to_be_bytes()),
+            ebml(&[0x44, 0x61], &(date_secs * 1_000_000_000).to_be_bytes()),
+            ebml(&[0x7B, 0xA9], b"A title"),
+            ebml(&[0x4D, 0x80], b"libebml v1.4.2 + libmatroska v1.6.4"),
+            ebml(&[0x57, 0x41], b"mkvmerge v65.0.0"),
+        ]
+        .concat();
+
+        let demuxer = Demuxer::new(Cursor::new(handmade_file(&info, &[], &[], &[]))).unwrap();
+        let info = demuxer.segment_info();
+        assert_eq!(info.timecode_scale, 1_000_000);
+        assert_eq!(info.duration_ns, Some(1_500_000_000));
+        assert_eq!(info.title.as_deref(), Some("A title"));
+        assert_eq!(info.muxing_app.as_deref(), Some("libebml v1.4.2 + libmatroska v1.6.4"));
+        assert_eq!(info.writing_app.as_deref(), Some("mkvmerge v65.0.0"));
+        assert_eq!(info.date_utc, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_577_836_800)));
+        assert_eq!(info.segment_uid, Some(uid));
+    }
+
+    #[test]
+    fn converts_dates_before_the_matroska_epoch() {
+        let unix_epoch = -(MATROSKA_EPOCH_UNIX_SECS as i64) * 1_000_000_000;
+        assert_eq!(matroska_date(unix_epoch), Some(SystemTime::UNIX_EPOCH));
+        assert_eq!(matroska_date(0), Some(SystemTime::UNIX_EPOCH + Duration::from_secs(MATROSKA_EPOCH_UNIX_SECS)));
+    }
+
+    #[test]
+    fn round_trips_colour() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let subsampling = ColorSubsampling {
+            chroma_horizontal: 1,
+            chroma_vertical: 1,
+        };
+        let builder = builder.set_color(video, 10, subsampling, ColorRange::Full).unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(video, &[0; 16], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        let colour = demuxer.colour(video.into()).unwrap();
+        assert_eq!(
+            colour,
+            ColourInfo {
+                bits_per_channel: Some(10),
+                chroma_subsampling: Some(subsampling),
+                range: Some(2),
+                ..ColourInfo::default()
+            }
+        );
+        assert_eq!(demuxer.colour(audio.into()), None);
+    }
+
+    #[test]
+    fn reads_hdr10_colour() {
+        let uint = |id: [u8; 2], value: u16| ebml(&id, &value.to_be_bytes());
+        let float = |id: [u8; 2], value: f32| ebml(&id, &value.to_be_bytes());
+        let mastering = [
+            float([0x55, 0xD1], 0.708),
+            float([0x55, 0xD2], 0.292),
+            float([0x55, 0xD3], 0.170),
+            float([0x55, 0xD4], 0.797),
+            float([0x55, 0xD5], 0.131),
+            float([0x55, 0xD6], 0.046),
+            float([0x55, 0xD7], 0.3127),
+            float([0x55, 0xD8], 0.3290),
+            float([0x55, 0xD9], 1000.0),
+            float([0x55, 0xDA], 0.0001),
+        ]
+        .concat();
+        let colour = [
+            uint([0x55, 0xB1], 9),
+            uint([0x55, 0xB2], 10),
+            uint([0x55, 0xB3], 1),
+            uint([0x55, 0xB4], 1),
+            uint([0x55, 0xB7], 1),
+            uint([0x55, 0xB8], 2),
+            uint([0x55, 0xB9], 1),
+            uint([0x55, 0xBA], 16),
+            uint([0x55, 0xBB], 9),
+            uint([0x55, 0xBC], 1000),
+            uint([0x55, 0xBD], 400),
+            ebml(&[0x55, 0xD0], &mastering),
+        ]
+        .concat();
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &[], &ebml(&[0x55, 0xB0], &colour), &[]);
+
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let colour = demuxer.colour(1).unwrap();
+        assert_eq!(colour.bits_per_channel, Some(10));
+        assert_eq!(
+            colour.chroma_subsampling,
+            Some(ColorSubsampling {
+                chroma_horizontal: 1,
+                chroma_vertical: 1
+            })
+        );
+        assert_eq!(colour.cb_subsampling, None);
+        assert_eq!((colour.chroma_siting_horz, colour.chroma_siting_vert), (Some(1), Some(2)));
+        assert_eq!(colour.range, Some(1));
+        assert_eq!(colour.matrix_coefficients, Some(9));
+        assert_eq!(colour.transfer_characteristics, Some(16));
+        assert_eq!(colour.primaries, Some(9));
+        assert_eq!((colour.max_cll, colour.max_fall), (Some(1000), Some(400)));
+
+        let mastering = colour.mastering_metadata.unwrap();
+        assert_eq!(mastering.primary_r, Some(Chromaticity { x: 0.708, y: 0.292 }));
+        assert_eq!(mastering.primary_g, Some(Chromaticity { x: 0.170, y: 0.797 }));
+        assert_eq!(mastering.primary_b, Some(Chromaticity { x: 0.131, y: 0.046 }));
+        assert_eq!(mastering.white_point, Some(Chromaticity { x: 0.3127, y: 0.3290 }));
+        assert_eq!(mastering.luminance_max, Some(1000.0));
+        assert_eq!(mastering.luminance_min, Some(0.0001));
+    }
+
+    #[test]
+    fn reads_projection() {
+        let float = |id: [u8; 2], value: f32| ebml(&id, &value.to_be_bytes());
+        let private_data = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let projection = [
+            ebml(&[0x76, 0x71], &[1]),
+            ebml(&[0x76, 0x72], &private_data),
+            float([0x76, 0x73], -90.5),
+            float([0x76, 0x75], 0.25),
+        ]
+        .concat();
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &[], &ebml(&[0x76, 0x70], &projection), &[]);
+
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let projection = demuxer.projection(1).unwrap();
+        assert_eq!(projection.projection_type, ProjectionType::Equirectangular);
+        assert_eq!(projection.private_data, private_data);
+        assert_eq!(projection.pose_yaw, Some(-90.5));
+        assert_eq!(projection.pose_pitch, None);
+        assert_eq!(projection.pose_roll, Some(0.25));
+    }
+
+    #[test]
+    fn plain_video_has_no_projection() {
+        let (file, video, audio) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.projection(video), None);
+        assert_eq!(demuxer.projection(audio), None);
+        assert_eq!(demuxer.projection(12345), None);
+    }
+
+    #[test]
+    fn reads_block_additions() {
+        let block = |timecode: i16, payload: &[u8]| {
+            let mut block = vec![0x81];
+            block.extend_from_slice(&timecode.to_be_bytes());
+            block.push(0x00);
+            block.extend_from_slice(payload);
+            block
+        };
+        let block_more = |id: Option<u8>, payload: &[u8]| {
+            let id = id.map(|id| ebml(&[0xEE], &[id])).unwrap_or_default();
+            ebml(&[0xA6], &[id, ebml(&[0xA5], payload)].concat())
+        };
+
+        let alpha = [0xA1, 0xA2, 0xA3];
+        let extra = [0xE1; 5];
+        let cluster = [
+            ebml(&[0xE7], &[0]),
+            ebml(
+                &[0xA0],
+                &[
+                    ebml(&[0xA1], &block(0, &[1, 2, 3])),
+                    ebml(&[0x75, 0xA1], &[block_more(Some(1), &alpha), block_more(Some(2), &extra)].concat()),
+                ]
+                .concat(),
+            ),
+            // Additions may come before the block, and the ID defaults to 1
+            ebml(
+                &[0xA0],
+                &[ebml(&[0x75, 0xA1], &block_more(None, &alpha[1..])), ebml(&[0xA1], &block(10, &[4, 5]))].concat(),
+            ),
+            ebml(&[0xA3], &block(20, &[6])),
+        ]
+        .concat();
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(
+            &info,
+            &ebml(&[0x55, 0xEE], &[2]),
+            &[],
+            &ebml(&[0x1F, 0x43, 0xB6, 0x75], &cluster),
+        );
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.track(1).unwrap().max_block_addition_id, 2);
+
+        let frames: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].data, [1, 2, 3]);
+        assert_eq!(frames[0].additions, [(1, alpha.to_vec()), (2, extra.to_vec())]);
+        assert_eq!(frames[1].data, [4, 5]);
+        assert_eq!(frames[1].additions, [(1, alpha[1..].to_vec())]);
+        assert_eq!(frames[2].data, [6]);
+        assert!(frames[2].additions.is_empty());
+
+        let (file, video, _) = mux_test_file(&test_frames());
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.track(video).unwrap().max_block_addition_id, 0);
+    }
+
+    #[test]
+    fn reads_discard_padding() {
+        let block_group = |timecode: i16, padding: Option<&[u8]>| {
+            let block = [&[0x81][..], &timecode.to_be_bytes(), &[0x00, 0xFC]].concat();
+            let padding = padding.map(|padding| ebml(&[0x75, 0xA2], padding)).unwrap_or_default();
+            ebml(&[0xA0], &[ebml(&[0xA1], &block), padding].concat())
+        };
+        let cluster = [
+            ebml(&[0xE7], &[0]),
+            block_group(0, None),
+            block_group(20, Some(&[0])),
+            block_group(40, Some(&6_500_000i32.to_be_bytes())),
+            block_group(60, Some(&(-2_500_000i32).to_be_bytes())),
+        ]
+        .concat();
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &[], &[], &ebml(&[0x1F, 0x43, 0xB6, 0x75], &cluster));
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let padding: Vec<Option<i64>> = demuxer.frames().map(|frame| frame.unwrap().discard_padding_ns).collect();
+        assert_eq!(padding, [None, Some(0), Some(6_500_000), Some(-2_500_000)]);
+    }
+
+    #[test]
+    fn reads_block_durations() {
+        let block = |timecode: i16| [&[0x81][..], &timecode.to_be_bytes(), &[0x00, 0xFC]].concat();
+        let cluster = [
+            ebml(&[0xE7], &[0]),
+            ebml(&[0xA0], &[ebml(&[0xA1], &block(0)), ebml(&[0x9B], &[40])].concat()),
+            ebml(&[0xA0], &ebml(&[0xA1], &block(40))),
+            ebml(&[0xA3], &block(80)),
+            ebml(&[0xA0], &[ebml(&[0x9B], &1500u16.to_be_bytes()), ebml(&[0xA1], &block(120))].concat()),
+        ]
+        .concat();
+        // A timecode scale of 100µs, to check that durations are scaled like timestamps
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &100_000u32.to_be_bytes());
+        let track = ebml(&[0x23, 0xE3, 0x83], &40_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &track, &[], &ebml(&[0x1F, 0x43, 0xB6, 0x75], &cluster));
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.track(1).unwrap().default_duration_ns, Some(40_000_000));
+
+        let frames: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        let timestamps: Vec<u64> = frames.iter().map(|frame| frame.timestamp_ns).collect();
+        assert_eq!(timestamps, [0, 4_000_000, 8_000_000, 12_000_000]);
+        let durations: Vec<Option<u64>> = frames.iter().map(|frame| frame.duration_ns).collect();
+        assert_eq!(durations, [Some(4_000_000), None, None, Some(150_000_000)]);
+    }
+
+    #[test]
+    fn reads_nested_chapters() {
+        let display = |string: &str, languages: &[&str]| {
+            let languages = languages.iter().map(|language| ebml(&[0x43, 0x7C], language.as_bytes()));
+            ebml(&[0x80], &[ebml(&[0x85], string.as_bytes())].into_iter().chain(languages).collect::<Vec<_>>().concat())
+        };
+        let atom = |uid: u8, start: u32, end: u32, rest: &[Vec<u8>]| {
+            let fields = [
+                ebml(&[0x73, 0xC4], &[uid]),
+                ebml(&[0x91], &start.to_be_bytes()),
+                ebml(&[0x92], &end.to_be_bytes()),
+            ];
+            ebml(&[0xB6], &fields.iter().chain(rest).cloned().collect::<Vec<_>>().concat())
+        };
+
+        let part_1 = atom(
+            1,
+            0,
+            60_000,
+            &[
+                display("Part 1", &["eng"]),
+                display("Teil 1", &["ger", "gsw"]),
+                atom(11, 0, 30_000, &[display("Intro", &[])]),
+                atom(12, 30_000, 60_000, &[ebml(&[0x98], &[1]), ebml(&[0x56, 0x54], b"verse")]),
+            ],
+        );
+        let part_2 = atom(2, 60_000, 90_000, &[display("Part 2", &["eng"])]);
+        let chapters = ebml(&[0x10, 0x43, 0xA7, 0x70], &ebml(&[0x45, 0xB9], &[part_1, part_2].concat()));
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &[], &[], &chapters);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let chapters = demuxer.chapters().unwrap();
+        assert_eq!(chapters.len(), 2);
+
+        let part_1 = &chapters[0];
+        assert_eq!((part_1.uid, part_1.start_ns, part_1.end_ns), (1, 0, Some(60_000)));
+        assert_eq!(part_1.displays.len(), 2);
+        assert_eq!(part_1.displays[0].string, "Part 1");
+        assert_eq!(part_1.displays[1].string, "Teil 1");
+        assert_eq!(part_1.displays[1].languages, ["ger", "gsw"]);
+        assert_eq!(part_1.children.len(), 2);
+
+        let intro = &part_1.children[0];
+        assert_eq!((intro.uid, intro.start_ns, intro.end_ns), (11, 0, Some(30_000)));
+        assert_eq!(intro.displays[0].languages, ["eng"]);
+        assert!(!intro.hidden && intro.enabled);
+
+        let verse = &part_1.children[1];
+        assert!(verse.hidden);
+        assert_eq!(verse.string_uid.as_deref(), Some("verse"));
+        assert!(verse.displays.is_empty());
+
+        assert_eq!(chapters[1].displays[0].string, "Part 2");
+        assert!(chapters[1].children.is_empty());
+    }
+
+    #[test]
+    fn muxed_files_have_no_chapters() {
+        let (file, _, _) = mux_test_file(&test_frames());
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert!(demuxer.chapters().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_tags() {
+        let simple_tag = |name: &str, value: Vec<u8>, rest: &[Vec<u8>]| {
+            let fields = [ebml(&[0x45, 0xA3], name.as_bytes()), value];
+            ebml(&[0x67, 0xC8], &fields.iter().chain(rest).cloned().collect::<Vec<_>>().concat())
+        };
+        let string = |value: &str| ebml(&[0x44, 0x87], value.as_bytes());
+
+        let global = ebml(
+            &[0x73, 0x73],
+            &[
+                ebml(&[0x63, 0xC0], &ebml(&[0x68, 0xCA], &[50])),
+                simple_tag("TITLE", string("A movie"), &[]),
+                simple_tag("TITLE", string("Ein Film"), &[ebml(&[0x44, 0x7A], b"ger"), ebml(&[0x44, 0x84], &[0])]),
+            ]
+            .concat(),
+        );
+        let track = ebml(
+            &[0x73, 0x73],
+            &[
+                ebml(&[0x63, 0xC0], &[ebml(&[0x68, 0xCA], &[30]), ebml(&[0x63, 0xC5], &[1])].concat()),
+                simple_tag("ENCODER", string("libvpx"), &[]),
+                simple_tag("COVER", ebml(&[0x44, 0x85], &[0xFF, 0xD8]), &[]),
+                simple_tag("ACTOR", vec![], &[simple_tag("CHARACTER", string("Narrator"), &[])]),
+            ]
+            .concat(),
+        );
+        let tags = ebml(&[0x12, 0x54, 0xC3, 0x67], &[global, track].concat());
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let file = handmade_file(&info, &[], &[], &tags);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let tags = demuxer.tags().unwrap().to_vec();
+        assert_eq!(tags.len(), 2);
+
+        let global = &tags[0];
+        assert!(global.targets.is_empty());
+        assert_eq!(global.target_type_value, 50);
+        assert_eq!(global.simple_tags[0].value, TagValue::String("A movie".to_owned()));
+        assert_eq!((global.simple_tags[0].language.as_str(), global.simple_tags[0].default), ("und", true));
+        assert_eq!((global.simple_tags[1].language.as_str(), global.simple_tags[1].default), ("ger", false));
+
+        let track = &tags[1];
+        assert_eq!(track.target_type_value, 30);
+        assert_eq!(track.targets, [TagTarget::Track(1)]);
+        let TagTarget::Track(track_uid) = track.targets[0] else {
+            unreachable!()
+        };
+        assert_eq!(demuxer.tracks().iter().find(|info| info.uid == track_uid).unwrap().track_num, 1);
+
+        assert_eq!(track.simple_tags[0].name, "ENCODER");
+        assert_eq!(track.simple_tags[0].value, TagValue::String("libvpx".to_owned()));
+        assert_eq!(track.simple_tags[1].value, TagValue::Binary(vec![0xFF, 0xD8]));
+        assert_eq!(track.simple_tags[2].value, TagValue::Empty);
+        assert_eq!(track.simple_tags[2].children[0].name, "CHARACTER");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_info_with_serde() {
+        fn round_trip<T>(value: &T) -> String
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let json = serde_json::to_string(value).unwrap();
+            assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value, "{json}");
+            json
+        }
+
+        let (file, video, _) = mux_test_file(&test_frames());
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        round_trip(demuxer.ebml_header());
+        round_trip(demuxer.segment_info());
+        let tracks = round_trip(&demuxer.tracks().to_vec());
+        assert!(tracks.contains(r#""track_type":{"video":{"#), "{tracks}");
+        round_trip(&demuxer.cue_index().unwrap().to_vec());
+        round_trip(&demuxer.keyframe_index(video).unwrap());
+        round_trip(&demuxer.seek(200_000_000, video).unwrap());
+        round_trip(&demuxer.sync_report().unwrap());
+
+        let chapter = ChapterInfo {
+            uid: 1,
+            string_uid: Some("intro".into()),
+            start_ns: 0,
+            end_ns: Some(100_000_000),
+            hidden: false,
+            enabled: true,
+            displays: vec![ChapterDisplay {
+                string: "Intro".into(),
+                languages: vec!["eng".into()],
+                countries: vec!["us".into()],
+            }],
+            children: Vec::new(),
+        };
+        round_trip(&chapter);
+        let cover = SimpleTagInfo {
+            name: "COVER".into(),
+            value: TagValue::Binary(vec![0xFF, 0xD8]),
+            language: "und".into(),
+            default: true,
+            children: Vec::new(),
+        };
+        let tag = TagInfo {
+            targets: vec![TagTarget::Track(1), TagTarget::Attachment(2)],
+            target_type_value: 30,
+            simple_tags: vec![SimpleTagInfo {
+                name: "ACTOR".into(),
+                value: TagValue::Empty,
+                language: "und".into(),
+                default: true,
+                children: vec![cover],
+            }],
+        };
+        assert!(round_trip(&tag).contains(r#""targets":[{"track":1},{"attachment":2}]"#));
+        round_trip(&AttachmentInfo {
+            name: "cover.jpg".into(),
+            mime: "image/jpeg".into(),
+            uid: 0x1234_5678,
+            size: 13,
+            description: None,
+        });
+
+        let projection = ProjectionInfo {
+            projection_type: ProjectionType::CubeMap,
+            private_data: vec![0; 20],
+            pose_yaw: Some(-90.5),
+            pose_pitch: None,
+            pose_roll: Some(0.25),
+        };
+        assert!(round_trip(&projection).contains(r#""projection_type":"cube_map""#));
+        round_trip(&ProjectionType::Other(7));
+        let chromaticity = Some(Chromaticity { x: 0.3127, y: 0.329 });
+        round_trip(&ColourInfo {
+            range: Some(1),
+            mastering_metadata: Some(MasteringMetadata {
+                primary_r: chromaticity,
+                primary_g: None,
+                primary_b: None,
+                white_point: chromaticity,
+                luminance_max: Some(1000.0),
+                luminance_min: Some(0.0001),
+            }),
+            ..ColourInfo::default()
+        });
+        round_trip(&TrackType::Other(0x11));
+    }
+
+    #[test]
+    fn scans_for_elements_without_seek_head() {
+        let title = ebml(&[0x67, 0xC8], &[ebml(&[0x45, 0xA3], b"TITLE"), ebml(&[0x44, 0x87], b"A movie")].concat());
+        let tags = ebml(&[0x12, 0x54, 0xC3, 0x67], &ebml(&[0x73, 0x73], &title));
+        let chapter = ebml(&[0xB6], &[ebml(&[0x73, 0xC4], &[1]), ebml(&[0x91], &[0])].concat());
+        let chapters = ebml(&[0x10, 0x43, 0xA7, 0x70], &ebml(&[0x45, 0xB9], &chapter));
+        let cluster = ebml(&[0x1F, 0x43, 0xB6, 0x75], &ebml(&[0xE7], &[0]));
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+
+        // `mkvparser` stops looking at the first cluster, and there is no `SeekHead` listing what follows it
+        let file = handmade_file(&info, &[], &[], &[cluster, tags, chapters].concat());
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let tags = demuxer.tags().unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].simple_tags[0].value, TagValue::String("A movie".to_owned()));
+        let chapters = demuxer.chapters().unwrap();
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].uid, 1);
+        assert!(demuxer.cue_index().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_attachments() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00, 0xFF, 0xD9];
+        let attached_file = [
+            ebml(&[0x46, 0x7E], b"Front cover"),
+            ebml(&[0x46, 0x6E], b"cover.jpg"),
+            ebml(&[0x46, 0x60], b"image/jpeg"),
+            ebml(&[0x46, 0x5C], &jpeg),
+            ebml(&[0x46, 0xAE], &0x1234_5678u32.to_be_bytes()),
+        ]
+        .concat();
+        let attachments = ebml(&[0x19, 0x41, 0xA4, 0x69], &ebml(&[0x61, 0xA7], &attached_file));
+        // The position of the `Attachments` element is filled in below, once it is known
+        let seek = [ebml(&[0x53, 0xAB], &[0x19, 0x41, 0xA4, 0x69]), ebml(&[0x53, 0xAC], &0u64.to_be_bytes())].concat();
+        let seek_head = ebml(&[0x11, 0x4D, 0x9B, 0x74], &ebml(&[0x4D, 0xBB], &seek));
+        let info = ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes());
+        let mut webm = handmade_file(&info, &[], &[], &[seek_head, attachments].concat());
+
+        let (_, header_pos, header_size) = ebml::read_declared_header(&mut Cursor::new(&webm), 0).unwrap();
+        let header_end = (header_pos + header_size.unwrap()) as usize;
+        let (_, segment_pos, _) = ebml::read_declared_header(&mut Cursor::new(&webm), header_end as u64).unwrap();
+        let found: Vec<usize> = (0..webm.len() - 4).filter(|&i| webm[i..i + 4] == [0x19, 0x41, 0xA4, 0x69]).collect();
+        // The first match is the `SeekID`, whose `SeekPosition` follows it
+        let seek_position = found[0] + 4 + ebml(&[0x53, 0xAC], &[]).len();
+        let attachments_pos = found[1];
+        let relative_pos = attachments_pos as u64 - segment_pos;
+        webm[seek_position..seek_position + 8].copy_from_slice(&relative_pos.to_be_bytes());
+
+        // WebM doesn't allow attachments, so they are only read with a `matroska` DocType
+        let mut demuxer = Demuxer::new(Cursor::new(webm.clone())).unwrap();
+        assert!(demuxer.attachments().unwrap().is_empty());
+
+        let header = [
+            ebml(&[0x42, 0x86], &[1]),
+            ebml(&[0x42, 0xF7], &[1]),
+            ebml(&[0x42, 0xF2], &[4]),
+            ebml(&[0x42, 0xF3], &[8]),
+            ebml(&[0x42, 0x82], b"matroska"),
+            ebml(&[0x42, 0x87], &[4]),
+            ebml(&[0x42, 0x85], &[2]),
+        ]
+        .concat();
+        let matroska = [ebml(&[0x1A, 0x45, 0xDF, 0xA3], &header), webm[header_end..].to_vec()].concat();
+
+        let mut demuxer = Demuxer::new(Cursor::new(matroska.clone())).unwrap();
+        assert_eq!(
+            demuxer.attachments().unwrap(),
+            [AttachmentInfo {
+                name: "cover.jpg".to_owned(),
+                mime: "image/jpeg".to_owned(),
+                uid: 0x1234_5678,
+                size: jpeg.len() as u64,
+                description: Some("Front cover".to_owned()),
+            }]
+        );
+        assert_eq!(demuxer.read_attachment(0x1234_5678).unwrap(), jpeg);
+        assert!(matches!(demuxer.read_attachment(1), Err(DemuxError::BadParam)));
+
+        let options = DemuxOptions {
+            max_attachment_size: 8,
+            ..DemuxOptions::default()
+        };
+        let mut demuxer = Demuxer::with_options(Cursor::new(matroska), options).unwrap();
+        assert!(matches!(
+            demuxer.read_attachment(0x1234_5678),
+            Err(DemuxError::LimitExceeded {
+                what: "Attachment",
+                requested: 13,
+                limit: 8,
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let garbage = vec![0x42u8; 1024];
+        assert!(Demuxer::new(Cursor::new(garbage)).is_err());
+        assert!(Demuxer::new(Cursor::new(Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! A minimal EBML reader for the elements that `mkvparser` skips over or flattens.
+
+use super::reader::{self, MkvRead};
+use super::DemuxError;
+
+/// An element header, with the position and size of its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Element {
+    /// The element ID, including its length marker bits (i.e. as written in the Matroska specification).
+    pub id: u32,
+    /// The position of the element's header.
+    pub start: u64,
+    pub pos: u64,
+    pub size: u64,
+}
+
+impl Element {
+    pub fn end(&self) -> u64 {
+        self.pos + self.size
+    }
+}
+
+pub(crate) fn read_exact_at<R: MkvRead>(source: &mut R, pos: u64, buf: &mut [u8]) -> Result<(), DemuxError> {
+    reader::read_exact_at(source, pos, buf).map_err(DemuxError::Io)
+}
+
+/// Reads a variable-length integer, returning its raw value (length marker included) and length in bytes.
+fn read_vint<R: MkvRead>(source: &mut R, pos: u64, max_len: u32) -> Result<(u64, u32), DemuxError> {
+    let mut first = [0];
+    read_exact_at(source, pos, &mut first)?;
+
+    let len = first[0].leading_zeros() + 1;
+    if len > max_len {
+        return Err(DemuxError::InvalidFile);
+    }
+
+    let mut rest = [0; 7];
+    let rest = &mut rest[..len as usize - 1];
+    read_exact_at(source, pos + 1, rest)?;
+
+    let value = rest.iter().fold(u64::from(first[0]), |value, &byte| (value << 8) | u64::from(byte));
+    Ok((value, len))
+}
+
+/// Reads the header of the element at `pos` as declared, returning its ID, the position of its payload, and its size
+/// (`None` if unknown). Nothing is checked against the enclosing element.
+pub(crate) fn read_declared_header<R: MkvRead>(
+    source: &mut R,
+    pos: u64,
+) -> Result<(u32, u64, Option<u64>), DemuxError> {
+    let (id, id_len) = read_vint(source, pos, 4)?;
+    let (size, size_len) = read_vint(source, pos + u64::from(id_len), 8)?;
+
+    let id = u32::try_from(id).map_err(|_| DemuxError::InvalidFile)?;
+    let payload = pos + u64::from(id_len) + u64::from(size_len);
+    let marker = 1 << (7 * size_len);
+    let size = size & (marker - 1);
+    Ok((id, payload, (size != marker - 1).then_some(size)))
+}
+
+/// Reads the header of the element at `pos`. An unknown size is taken to extend to `parent_end`.
+fn read_header<R: MkvRead>(source: &mut R, pos: u64, parent_end: u64) -> Result<Element, DemuxError> {
+    let (id, payload, size) = read_declared_header(source, pos)?;
+    let size = match size {
+        Some(size) => size,
+        None => parent_end.checked_sub(payload).ok_or(DemuxError::InvalidFile)?,
+    };
+
+    let element = Element {
+        id,
+        start: pos,
+        pos: payload,
+        size,
+    };
+    if element.pos.checked_add(element.size).is_none_or(|end| end > parent_end) {
+        return Err(DemuxError::InvalidFile);
+    }
+    Ok(element)
+}
+
+/// Reads the headers of all child elements in the given payload range.
+pub(crate) fn children<R: MkvRead>(source: &mut R, start: u64, end: u64) -> Result<Vec<Element>, DemuxError> {
+    let mut children = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        let child = read_header(source, pos, end)?;
+        pos = child.end();
+        children.push(child);
+    }
+    Ok(children)
+}
+
+pub(crate) fn read_bytes<R: MkvRead>(source: &mut R, element: &Element) -> Result<Vec<u8>, DemuxError> {
+    let len = usize::try_from(element.size).map_err(|_| DemuxError::InvalidFile)?;
+    let mut data = vec![0; len];
+    read_exact_at(source, element.pos, &mut data)?;
+    Ok(data)
+}
+
+pub(crate) fn read_uint<R: MkvRead>(source: &mut R, element: &Element) -> Result<u64, DemuxError> {
+    if element.size > 8 {
+        return Err(DemuxError::InvalidFile);
+    }
+    let data = read_bytes(source, element)?;
+    Ok(data.iter().fold(0, |value, &byte| (value << 8) | u64::from(byte)))
+}
+
+/// Reads a 4 or 8 byte float element.
+pub(crate) fn read_float<R: MkvRead>(source: &mut R, element: &Element) -> Result<f64, DemuxError> {
+    let data = read_bytes(source, element)?;
+    match *data.as_slice() {
+        [] => Ok(0.0),
+        [a, b, c, d] => Ok(f64::from(f32::from_be_bytes([a, b, c, d]))),
+        [a, b, c, d, e, f, g, h] => Ok(f64::from_be_bytes([a, b, c, d, e, f, g, h])),
+        _ => Err(DemuxError::InvalidFile),
+    }
+}
+
+/// Reads a string element, dropping any trailing nul padding.
+pub(crate) fn read_string<R: MkvRead>(source: &mut R, element: &Element) -> Result<String, DemuxError> {
+    let mut data = read_bytes(source, element)?;
+    let len = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+    data.truncate(len);
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_elements() {
+        // ChapterTimeStart (1-byte size) = 0x0102, then ChapLanguage (unknown size) = "eng"
+        let data = [0x91, 0x82, 0x01, 0x02, 0x43, 0x7C, 0xFF, b'e', b'n', b'g'];
+        let mut source = Cursor::new(data);
+
+        let elements = children(&mut source, 0, data.len() as u64).unwrap();
+        assert_eq!(
+            elements,
+            [
+                Element {
+                    id: 0x91,
+                    start: 0,
+                    pos: 2,
+                    size: 2
+                },
+                Element {
+                    id: 0x437C,
+                    start: 4,
+                    pos: 7,
+                    size: 3
+                },
+            ]
+        );
+        assert_eq!(read_uint(&mut source, &elements[0]).unwrap(), 0x0102);
+        assert_eq!(read_string(&mut source, &elements[1]).unwrap(), "eng");
+    }
+
+    #[test]
+    fn rejects_overlong_children() {
+        let data = [0x91, 0x84, 0x01, 0x02];
+        let mut source = Cursor::new(data);
+        assert!(matches!(children(&mut source, 0, data.len() as u64), Err(DemuxError::InvalidFile)));
+    }
+}
@@ -0,0 +1,178 @@
+//! Checks of declared sizes against the memory limits in [`DemuxOptions`].
+//!
+//! `mkvparser` allocates whatever an element declares before reading it, so the headers of a file are walked here
+//! first, and anything too large is rejected before `mkvparser` gets to it.
+
+use super::ebml;
+use super::reader::MkvRead;
+use super::{DemuxError, DemuxOptions};
+
+const EBML_ID: u32 = 0x1A45_DFA3;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const SEEK_HEAD_ID: u32 = 0x114D_9B74;
+const INFO_ID: u32 = 0x1549_A966;
+const TRACKS_ID: u32 = 0x1654_AE6B;
+const TRACK_ENTRY_ID: u32 = 0xAE;
+const CODEC_PRIVATE_ID: u32 = 0x63A2;
+const CHAPTERS_ID: u32 = 0x1043_A770;
+const TAGS_ID: u32 = 0x1254_C367;
+
+/// Fails with [`DemuxError::LimitExceeded`] if `requested` is over `limit`.
+pub(super) fn check(what: &'static str, requested: u64, limit: usize) -> Result<(), DemuxError> {
+    if u64::try_from(limit).is_ok_and(|limit| requested > limit) {
+        return Err(DemuxError::LimitExceeded {
+            what,
+            requested,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the declared sizes of the headers before the first cluster, and of each track's `CodecPrivate`.
+///
+/// Only what `source` holds so far is checked, so this may be repeated as more of a file arrives. Anything that can't
+/// be read, is malformed or has an unknown size ends the walk early, and is left for `mkvparser` to judge.
+pub(super) fn check_headers<R: MkvRead>(source: &mut R, options: &DemuxOptions) -> Result<(), DemuxError> {
+    let Ok((id, pos, Some(size))) = ebml::read_declared_header(source, 0) else {
+        return Ok(());
+    };
+    if id != EBML_ID {
+        return Ok(());
+    }
+    check("EBML header", size, options.max_header_element)?;
+
+    let Ok((id, segment_pos, segment_size)) = ebml::read_declared_header(source, pos + size) else {
+        return Ok(());
+    };
+    if id != SEGMENT_ID {
+        return Ok(());
+    }
+    let segment_end = segment_size.map_or(u64::MAX, |size| segment_pos.saturating_add(size));
+
+    visit_children(source, segment_pos, segment_end, |source, id, pos, size| {
+        match id {
+            CLUSTER_ID => return Ok(false),
+            SEEK_HEAD_ID | INFO_ID | CHAPTERS_ID | TAGS_ID => {
+                check("Header element", size, options.max_header_element)?;
+            },
+            TRACKS_ID => {
+                check("Header element", size, options.max_header_element)?;
+                visit_children(source, pos, pos.saturating_add(size), |source, id, pos, size| {
+                    if id == TRACK_ENTRY_ID {
+                        visit_children(source, pos, pos.saturating_add(size), |_, id, _, size| {
+                            if id == CODEC_PRIVATE_ID {
+                                check("CodecPrivate", size, options.max_codec_private)?;
+                            }
+                            Ok(true)
+                        })?;
+                    }
+                    Ok(true)
+                })?;
+            },
+            _ => {},
+        }
+        Ok(true)
+    })
+}
+
+/// Calls `visit` with the ID, payload position and size of each child element in `start..end`, until it returns
+/// false or a child can't be read or has an unknown size.
+fn visit_children<R: MkvRead>(
+    source: &mut R,
+    start: u64,
+    end: u64,
+    mut visit: impl FnMut(&mut R, u32, u64, u64) -> Result<bool, DemuxError>,
+) -> Result<(), DemuxError> {
+    let mut pos = start;
+    while pos < end {
+        let Ok((id, payload, Some(size))) = ebml::read_declared_header(source, pos) else {
+            break;
+        };
+        if !visit(source, id, payload, size)? {
+            break;
+        }
+        let Some(next) = payload.checked_add(size) else {
+            break;
+        };
+        pos = next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Encodes an element with a size field of 8 bytes, so that sizes can be declared without providing the data.
+    fn element(id: u32, size: u64, payload: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = id.to_be_bytes().into_iter().skip_while(|&byte| byte == 0).collect();
+        data.push(0x01);
+        data.extend_from_slice(&size.to_be_bytes()[1..]);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn file(headers: &[Vec<u8>]) -> Vec<u8> {
+        let ebml = element(EBML_ID, 4, &[0x42, 0x82, 0x81, b'w']);
+        let segment = element(SEGMENT_ID, 1 << 40, &headers.concat());
+        [ebml, segment].concat()
+    }
+
+    fn options() -> DemuxOptions {
+        DemuxOptions {
+            max_codec_private: 100,
+            max_header_element: 1000,
+            ..DemuxOptions::default()
+        }
+    }
+
+    #[test]
+    fn checks_header_elements() {
+        let data = file(&[element(INFO_ID, 2, &[0xEC, 0x80]), element(TAGS_ID, 1 << 30, &[])]);
+        let result = check_headers(&mut Cursor::new(data.clone()), &options());
+        assert!(matches!(
+            result,
+            Err(DemuxError::LimitExceeded {
+                what: "Header element",
+                requested: 0x4000_0000,
+                limit: 1000,
+            })
+        ));
+        assert!(check_headers(&mut Cursor::new(data), &DemuxOptions::unlimited()).is_ok());
+
+        // Anything after the first cluster isn't a header
+        let data = file(&[element(CLUSTER_ID, 2, &[0xE7, 0x80]), element(TAGS_ID, 1 << 30, &[])]);
+        assert!(check_headers(&mut Cursor::new(data), &options()).is_ok());
+    }
+
+    #[test]
+    fn checks_codec_private() {
+        let entry = [element(0xD7, 1, &[1]), element(CODEC_PRIVATE_ID, 10, &[0; 10])].concat();
+        let small = element(TRACK_ENTRY_ID, entry.len() as u64, &entry);
+
+        // The second track is cut off after its `CodecPrivate` header, but its `Tracks` element is within its limit
+        let entry = [element(0xD7, 1, &[2]), element(CODEC_PRIVATE_ID, 200, &[])].concat();
+        let large = element(TRACK_ENTRY_ID, entry.len() as u64 + 200, &entry);
+        let tracks = [small, large].concat();
+        let data = file(&[element(TRACKS_ID, tracks.len() as u64 + 200, &tracks)]);
+        let result = check_headers(&mut Cursor::new(data), &options());
+        assert!(matches!(
+            result,
+            Err(DemuxError::LimitExceeded {
+                what: "CodecPrivate",
+                requested: 200,
+                limit: 100,
+            })
+        ));
+    }
+
+    #[test]
+    fn checks_ebml_header() {
+        let data = element(EBML_ID, 5000, &[0x42, 0x82, 0x81, b'w']);
+        let result = check_headers(&mut Cursor::new(data), &options());
+        assert!(matches!(result, Err(DemuxError::LimitExceeded { what: "EBML header", .. })));
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A source that a [`Demuxer`](super::Demuxer) can read a WebM file from.
+///
+/// This is implemented for everything that is [`Read`] + [`Seek`], such as files. Sources that can read at arbitrary
+/// offsets, such as memory maps or range requests against an object store, can implement it directly instead. Byte
+/// slices are read through a [`std::io::Cursor`], since a separate impl for `&[u8]` would overlap with the one for
+/// [`Read`] + [`Seek`].
+pub trait MkvRead {
+    /// Reads bytes starting at the absolute position `offset` into `buf`, returning how many were read.
+    ///
+    /// As with [`Read::read`], this may read fewer bytes than requested, in which case it is called again for the
+    /// rest. Returning 0 means that `offset` is at or past the end of the source.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// The total length of the source, in bytes. This is called once, when the [`Demuxer`](super::Demuxer) is
+    /// created, and takes `&mut self` so that [`Seek`] sources can find their end.
+    fn len(&mut self) -> io::Result<u64>;
+
+    /// Whether the source is empty (and thus certainly not a valid WebM file).
+    fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl<T: Read + Seek> MkvRead for T {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read(buf)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        self.seek(SeekFrom::End(0))
+    }
+}
+
+/// Fills `buf` from `offset`, calling [`MkvRead::read_at`] as many times as needed.
+pub(super) fn read_exact_at<R: MkvRead>(source: &mut R, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match source.read_at(offset, buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(count) => {
+                let count = count.min(buf.len());
+                buf = &mut buf[count..];
+                offset += count as u64;
+            },
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::{DemuxError, Demuxer};
+    use crate::mux::{AudioCodecId, SegmentBuilder, TrackNum, VideoCodecId, Writer};
+    use std::io::Cursor;
+
+    /// Serves range reads of at most `max_read` bytes, like an object store client would, and records them.
+    struct RangeReader {
+        data: Vec<u8>,
+        claimed_len: u64,
+        max_read: usize,
+        reads: usize,
+        out_of_range: usize,
+    }
+
+    impl MkvRead for RangeReader {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            let Some(available) = usize::try_from(offset).ok().and_then(|offset| self.data.get(offset..)) else {
+                self.out_of_range += 1;
+                return Ok(0);
+            };
+            let count = buf.len().min(available.len()).min(self.max_read);
+            buf[..count].copy_from_slice(&available[..count]);
+            Ok(count)
+        }
+
+        fn len(&mut self) -> io::Result<u64> {
+            Ok(self.claimed_len)
+        }
+    }
+
+    fn mux_test_file() -> Vec<u8> {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for i in 0..40u64 {
+            let track: TrackNum = if i % 2 == 0 { video.into() } else { audio.into() };
+            segment.add_frame(track, &vec![i as u8; 100 + i as usize], i * 10_000_000, true).unwrap();
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner().into_inner()
+    }
+
+    #[test]
+    fn reads_through_short_range_reads() {
+        let file = mux_test_file();
+        let reader = RangeReader {
+            claimed_len: file.len() as u64,
+            data: file.clone(),
+            max_read: 7,
+            reads: 0,
+            out_of_range: 0,
+        };
+
+        let mut demuxer = Demuxer::new(reader).unwrap();
+        let frames: Vec<_> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        let reader = demuxer.into_inner();
+
+        let mut expected = Demuxer::new(Cursor::new(file.as_slice())).unwrap();
+        let expected: Vec<_> = expected.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames, expected);
+
+        // The payloads alone take hundreds of 7-byte reads, none of which should be out of range
+        assert!(reader.reads > 500);
+        assert_eq!(reader.out_of_range, 0);
+    }
+
+    #[test]
+    fn reports_missing_data() {
+        let mut file = mux_test_file();
+        let claimed_len = file.len() as u64;
+        file.truncate(file.len() / 2);
+        let reader = RangeReader {
+            data: file,
+            claimed_len,
+            max_read: usize::MAX,
+            reads: 0,
+            out_of_range: 0,
+        };
+
+        let mut demuxer = Demuxer::new(reader).unwrap();
+        let result: Result<Vec<_>, _> = demuxer.frames().collect();
+        assert!(matches!(result, Err(DemuxError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -0,0 +1,349 @@
+use std::io;
+
+use crate::ffi::parser::ClusterPtr;
+
+use super::demuxer::clamp_timestamp;
+use super::limits;
+use super::reader::MkvRead;
+use super::{
+    DemuxError, DemuxEvent, DemuxFrame, DemuxOptions, DemuxTrackInfo, Demuxer, EbmlHeaderInfo, SegmentInfo,
+};
+
+/// The bytes fed to a [`StreamingDemuxer`] that may still be needed, addressed by their absolute position in the
+/// file.
+struct StreamBuffer {
+    data: Vec<u8>,
+
+    /// The absolute position of `data[0]`. Everything before it has been discarded.
+    base: u64,
+}
+
+impl StreamBuffer {
+    fn end(&self) -> u64 {
+        self.base + self.data.len() as u64
+    }
+
+    /// Drops the buffered bytes before the absolute position `pos`.
+    fn discard_before(&mut self, pos: u64) {
+        let count = pos.saturating_sub(self.base).min(self.data.len() as u64);
+        self.data.drain(..count as usize);
+        self.base += count;
+    }
+}
+
+impl MkvRead for StreamBuffer {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(offset) = offset.checked_sub(self.base) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "data was already discarded"));
+        };
+        let available = usize::try_from(offset).ok().and_then(|offset| self.data.get(offset..)).unwrap_or_default();
+        let count = buf.len().min(available.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        Ok(count)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.end())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Clusters,
+    Ended,
+    Failed,
+}
+
+/// Reads a WebM file as it arrives, for sources that can't seek such as pipes or network streams.
+///
+/// Data is passed in with [`StreamingDemuxer::feed`], in chunks of any size, and read back as [`DemuxEvent`]s with
+/// [`StreamingDemuxer::next_event`]. Only data that may still be needed is kept, so memory use is bounded by the size
+/// of a cluster rather than the whole file.
+///
+/// Since nothing after the current position is known, `Cues` and any chapters or tags stored after the first cluster
+/// are not read, and seeking is not possible. Live files written with
+/// [`Writer::new_non_seek`](crate::mux::Writer::new_non_seek), whose segment and clusters have unknown sizes, are
+/// supported; their end is only known once [`StreamingDemuxer::finish`] is called.
+pub struct StreamingDemuxer {
+    demuxer: Demuxer<StreamBuffer>,
+    state: State,
+
+    /// The last cluster that a [`DemuxEvent::ClusterStart`] was returned for.
+    announced_cluster: ClusterPtr,
+
+    /// A frame read from the start of a new cluster, to be returned after its [`DemuxEvent::ClusterStart`].
+    pending_frame: Option<DemuxFrame>,
+
+    /// The end of the segment, once the header has been read, if its size is known.
+    segment_end: Option<u64>,
+}
+
+impl StreamingDemuxer {
+    /// Creates a [`StreamingDemuxer`] with no data fed to it yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_options(DemuxOptions::default())
+    }
+
+    /// Like [`StreamingDemuxer::new`], but with the given options rather than the defaults.
+    ///
+    /// [`DemuxOptions::lenient`] is ignored, as a damaged cluster can't be told apart from one that hasn't fully
+    /// arrived yet.
+    #[must_use]
+    pub fn with_options(options: DemuxOptions) -> Self {
+        let buffer = StreamBuffer {
+            data: Vec::new(),
+            base: 0,
+        };
+        let options = DemuxOptions {
+            lenient: false,
+            ..options
+        };
+        let demuxer = Demuxer::unopened(buffer, 0, false, options).expect("Failed to create parser");
+        Self {
+            demuxer,
+            state: State::Header,
+            announced_cluster: std::ptr::null(),
+            pending_frame: None,
+            segment_end: None,
+        }
+    }
+
+    /// Appends the next part of the file.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.demuxer.source_mut().data.extend_from_slice(bytes);
+        self.update_length();
+    }
+
+    /// Passes on how much data there is. mkvparser only stops at the end of a segment of known size once it knows
+    /// the length of the file, so once the whole segment is here, that is where the file ends.
+    fn update_length(&mut self) {
+        let end = self.demuxer.source_mut().end();
+        match self.segment_end {
+            Some(segment_end) if end >= segment_end => self.demuxer.set_length(segment_end, true),
+            _ => self.demuxer.set_length(end, false),
+        }
+    }
+
+    /// Marks the end of the input, after which no more data may be fed. Any remaining events become available from
+    /// [`StreamingDemuxer::next_event`], and a file that is cut short is reported as [`DemuxError::InvalidFile`].
+    pub fn finish(&mut self) {
+        let end = self.demuxer.source_mut().end();
+        self.demuxer.set_length(end, true);
+    }
+
+    /// Returns the next event, or `None` if more data must be fed first (or the segment has ended).
+    ///
+    /// Once an error is returned, no further events are produced.
+    pub fn next_event(&mut self) -> Result<Option<DemuxEvent>, DemuxError> {
+        match self.read_event() {
+            Ok(event) => Ok(event),
+            Err(DemuxError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            },
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<DemuxEvent>, DemuxError> {
+        let max_buffered = self.demuxer.options().max_total_buffered;
+        limits::check("Buffered data", self.demuxer.source_mut().data.len() as u64, max_buffered)?;
+
+        match self.state {
+            State::Header => {
+                self.demuxer.open()?;
+                self.segment_end = self.demuxer.segment_end()?;
+                self.update_length();
+                self.state = State::Clusters;
+                Ok(Some(DemuxEvent::TrackInfoReady))
+            },
+            State::Clusters => {
+                if let Some(frame) = self.pending_frame.take() {
+                    return Ok(Some(DemuxEvent::Frame(frame)));
+                }
+
+                let Some(frame) = self.demuxer.advance(None)? else {
+                    self.state = State::Ended;
+                    return Ok(Some(DemuxEvent::EndOfSegment));
+                };
+
+                let cluster = self.demuxer.current_cluster().ok_or(DemuxError::Unknown)?;
+                if cluster == self.announced_cluster {
+                    return Ok(Some(DemuxEvent::Frame(frame)));
+                }
+
+                // We never go back to an earlier cluster, so the data before this one is no longer needed.
+                let info = self.demuxer.cluster_info(cluster)?;
+                let start = u64::try_from(info.element_start).map_err(|_| DemuxError::InvalidFile)?;
+                self.demuxer.source_mut().discard_before(start);
+
+                self.announced_cluster = cluster;
+                self.pending_frame = Some(frame);
+                Ok(Some(DemuxEvent::ClusterStart(clamp_timestamp(info.time_ns))))
+            },
+            State::Ended | State::Failed => Ok(None),
+        }
+    }
+
+    /// Information about all tracks in the file. Empty until [`DemuxEvent::TrackInfoReady`] has been returned.
+    #[must_use]
+    pub fn tracks(&self) -> &[DemuxTrackInfo] {
+        self.demuxer.tracks()
+    }
+
+    /// The EBML header of the file. Only meaningful once [`DemuxEvent::TrackInfoReady`] has been returned.
+    #[must_use]
+    pub fn ebml_header(&self) -> &EbmlHeaderInfo {
+        self.demuxer.ebml_header()
+    }
+
+    /// Information about the file as a whole. Only meaningful once [`DemuxEvent::TrackInfoReady`] has been returned.
+    #[must_use]
+    pub fn segment_info(&self) -> &SegmentInfo {
+        self.demuxer.segment_info()
+    }
+}
+
+impl Default for StreamingDemuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for StreamingDemuxer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, TrackNum, VideoCodecId, Writer};
+    use std::io::{Cursor, Write};
+
+    fn mux_test_file<W: Write>(writer: Writer<W>) -> W {
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        // Long enough to span several clusters
+        for i in 0..400u64 {
+            let is_video = i % 2 == 0;
+            let track: TrackNum = if is_video { video.into() } else { audio.into() };
+            let payload = vec![i as u8; 10 + (i as usize % 50)];
+            segment.add_frame(track, &payload, i * 20_000_000, !is_video || i % 100 == 0).unwrap();
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner()
+    }
+
+    /// Feeds `file` in pseudo-random chunk sizes, and collects all events.
+    fn stream_events(file: &[u8], finish: bool) -> (Vec<DemuxEvent>, StreamingDemuxer) {
+        let mut demuxer = StreamingDemuxer::new();
+        let mut events = Vec::new();
+        let mut seed = 12345u32;
+        let mut rest = file;
+        while !rest.is_empty() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let len = (seed >> 16) as usize % 700 + 1;
+            let (chunk, remaining) = rest.split_at(len.min(rest.len()));
+            demuxer.feed(chunk);
+            rest = remaining;
+
+            while let Some(event) = demuxer.next_event().unwrap() {
+                events.push(event);
+            }
+        }
+        if finish {
+            demuxer.finish();
+            while let Some(event) = demuxer.next_event().unwrap() {
+                events.push(event);
+            }
+        }
+        (events, demuxer)
+    }
+
+    fn check_against_seekable(file: Vec<u8>, finish: bool) {
+        let (events, streaming) = stream_events(&file, finish);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(streaming.tracks(), demuxer.tracks());
+        let expected: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+
+        assert_eq!(events.first(), Some(&DemuxEvent::TrackInfoReady));
+        assert_eq!(events.last(), Some(&DemuxEvent::EndOfSegment));
+        assert!(matches!(events[1], DemuxEvent::ClusterStart(0)));
+
+        let clusters = events.iter().filter(|event| matches!(event, DemuxEvent::ClusterStart(_))).count();
+        assert!(clusters > 1);
+
+        let frames: Vec<_> = events
+            .into_iter()
+            .filter_map(|event| match event {
+                DemuxEvent::Frame(frame) => Some(frame),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn streams_seekable_file() {
+        let file = mux_test_file(Writer::new(Cursor::new(Vec::new()))).into_inner();
+        check_against_seekable(file, false);
+    }
+
+    #[test]
+    fn streams_live_file() {
+        let file = mux_test_file(Writer::new_non_seek(Vec::new()));
+        check_against_seekable(file, true);
+    }
+
+    #[test]
+    fn reports_truncated_file() {
+        let file = mux_test_file(Writer::new(Cursor::new(Vec::new()))).into_inner();
+        let mut demuxer = StreamingDemuxer::new();
+        demuxer.feed(&file[..file.len() / 2]);
+        while let Some(event) = demuxer.next_event().unwrap() {
+            assert_ne!(event, DemuxEvent::EndOfSegment);
+        }
+
+        demuxer.finish();
+        let result = std::iter::from_fn(|| demuxer.next_event().transpose()).find_map(Result::err);
+        assert!(matches!(result, Some(DemuxError::InvalidFile)));
+    }
+
+    #[test]
+    fn limits_buffered_data() {
+        let file = mux_test_file(Writer::new_non_seek(Vec::new()));
+        let options = DemuxOptions {
+            max_total_buffered: 8000,
+            ..DemuxOptions::default()
+        };
+
+        // Everything before the current cluster is discarded, so feeding a little at a time stays within the limit
+        let mut demuxer = StreamingDemuxer::with_options(options);
+        for chunk in file.chunks(100) {
+            demuxer.feed(chunk);
+            while demuxer.next_event().unwrap().is_some() {}
+        }
+
+        let mut demuxer = StreamingDemuxer::with_options(options);
+        demuxer.feed(&file);
+        assert!(matches!(
+            demuxer.next_event(),
+            Err(DemuxError::LimitExceeded {
+                what: "Buffered data",
+                limit: 8000,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::demux::ebml::{self, Element};
+use crate::demux::{DemuxError, MkvRead};
+
+use super::{ElementDetail, LayoutElement, LayoutSummary};
+
+const EBML_ID: u32 = 0x1A45_DFA3;
+const DOC_TYPE_ID: u32 = 0x4282;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const SEEK_HEAD_ID: u32 = 0x114D_9B74;
+const SEEK_ID: u32 = 0x4DBB;
+const INFO_ID: u32 = 0x1549_A966;
+const TIMECODE_SCALE_ID: u32 = 0x2A_D7B1;
+const DURATION_ID: u32 = 0x4489;
+const TRACKS_ID: u32 = 0x1654_AE6B;
+const TRACK_ENTRY_ID: u32 = 0xAE;
+const TRACK_NUMBER_ID: u32 = 0xD7;
+const CODEC_ID_ID: u32 = 0x86;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const TIMECODE_ID: u32 = 0xE7;
+const SIMPLE_BLOCK_ID: u32 = 0xA3;
+const BLOCK_GROUP_ID: u32 = 0xA0;
+const CUES_ID: u32 = 0x1C53_BB6B;
+const CUE_POINT_ID: u32 = 0xBB;
+const CHAPTERS_ID: u32 = 0x1043_A770;
+const TAGS_ID: u32 = 0x1254_C367;
+const ATTACHMENTS_ID: u32 = 0x1941_A469;
+const VOID_ID: u32 = 0xEC;
+const CRC32_ID: u32 = 0xBF;
+
+const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+/// Lists the elements of a WebM file read from `source`, with their offsets and sizes: the EBML header, then the
+/// `Segment` and the elements in it, such as the `SeekHead`, `Info`, `Tracks`, each `Cluster` with its timecode and
+/// number of blocks, and the `Cues`.
+///
+/// As with [`validate`](crate::validate::validate), the file is read as EBML rather than through a
+/// [`Demuxer`](crate::demux::Demuxer), so only the element structure has to be intact. Fails with
+/// [`DemuxError::InvalidFile`] if an element header is malformed or extends past its parent.
+pub fn summarize<R: MkvRead>(mut source: R) -> Result<LayoutSummary, DemuxError> {
+    let file_len = source.len().map_err(DemuxError::Io)?;
+    let elements = ebml::children(&mut source, 0, file_len)?
+        .iter()
+        .map(|element| summarize_element(&mut source, element))
+        .collect::<Result<_, _>>()?;
+    Ok(LayoutSummary { file_len, elements })
+}
+
+fn summarize_element<R: MkvRead>(source: &mut R, element: &Element) -> Result<LayoutElement, DemuxError> {
+    let mut detail = None;
+    let mut children = Vec::new();
+    match element.id {
+        SEGMENT_ID | TRACKS_ID => {
+            for child in ebml::children(source, element.pos, element.end())? {
+                children.push(summarize_element(source, &child)?);
+            }
+        },
+        EBML_ID => {
+            // The DocType defaults to `matroska` when the header leaves it out
+            let mut doc_type = "matroska".to_string();
+            for child in ebml::children(source, element.pos, element.end())? {
+                if child.id == DOC_TYPE_ID {
+                    doc_type = ebml::read_string(source, &child)?;
+                }
+            }
+            detail = Some(ElementDetail::EbmlHeader { doc_type });
+        },
+        SEEK_HEAD_ID => {
+            let entries = count_children(source, element, SEEK_ID)?;
+            detail = Some(ElementDetail::SeekHead { entries });
+        },
+        INFO_ID => {
+            let mut timecode_scale = DEFAULT_TIMECODE_SCALE;
+            let mut duration = None;
+            for child in ebml::children(source, element.pos, element.end())? {
+                match child.id {
+                    TIMECODE_SCALE_ID => timecode_scale = ebml::read_uint(source, &child)?,
+                    DURATION_ID => duration = Some(ebml::read_float(source, &child)?),
+                    _ => {},
+                }
+            }
+            let duration_ns = duration.map(|duration| (duration * timecode_scale as f64).round() as u64);
+            detail = Some(ElementDetail::Info {
+                timecode_scale,
+                duration_ns,
+            });
+        },
+        TRACK_ENTRY_ID => {
+            let mut track_num = 0;
+            let mut codec_id = String::new();
+            for child in ebml::children(source, element.pos, element.end())? {
+                match child.id {
+                    TRACK_NUMBER_ID => track_num = ebml::read_uint(source, &child)?,
+                    CODEC_ID_ID => codec_id = ebml::read_string(source, &child)?,
+                    _ => {},
+                }
+            }
+            detail = Some(ElementDetail::Track { track_num, codec_id });
+        },
+        CLUSTER_ID => {
+            let mut timecode = 0;
+            let mut blocks = 0;
+            for child in ebml::children(source, element.pos, element.end())? {
+                match child.id {
+                    TIMECODE_ID => timecode = ebml::read_uint(source, &child)?,
+                    SIMPLE_BLOCK_ID | BLOCK_GROUP_ID => blocks += 1,
+                    _ => {},
+                }
+            }
+            detail = Some(ElementDetail::Cluster { timecode, blocks });
+        },
+        CUES_ID => {
+            let cue_points = count_children(source, element, CUE_POINT_ID)?;
+            detail = Some(ElementDetail::Cues { cue_points });
+        },
+        _ => {},
+    }
+
+    Ok(LayoutElement {
+        id: element.id,
+        offset: element.start,
+        header_size: element.pos - element.start,
+        size: element.size,
+        detail,
+        children,
+    })
+}
+
+fn count_children<R: MkvRead>(source: &mut R, element: &Element, id: u32) -> Result<u64, DemuxError> {
+    let children = ebml::children(source, element.pos, element.end())?;
+    Ok(children.iter().filter(|child| child.id == id).count() as u64)
+}
+
+/// The names of the elements that a [`LayoutSummary`] can hold.
+pub(super) fn element_name(id: u32) -> Option<&'static str> {
+    let name = match id {
+        EBML_ID => "EBML",
+        SEGMENT_ID => "Segment",
+        SEEK_HEAD_ID => "SeekHead",
+        INFO_ID => "Info",
+        TRACKS_ID => "Tracks",
+        TRACK_ENTRY_ID => "TrackEntry",
+        CLUSTER_ID => "Cluster",
+        CUES_ID => "Cues",
+        CHAPTERS_ID => "Chapters",
+        TAGS_ID => "Tags",
+        ATTACHMENTS_ID => "Attachments",
+        VOID_ID => "Void",
+        CRC32_ID => "CRC-32",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl std::fmt::Display for LayoutSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for element in &self.elements {
+            write_element(f, element, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `element` and its children as `mkvinfo` does, with a `|` and a space of indentation for each level it is
+/// nested, then `+ ` and the element.
+fn write_element(f: &mut std::fmt::Formatter<'_>, element: &LayoutElement, depth: usize) -> std::fmt::Result {
+    if depth > 0 {
+        write!(f, "|{:1$}", "", depth - 1)?;
+    }
+    match element.name() {
+        Some(name) => write!(f, "+ {name}")?,
+        None => write!(f, "+ Unknown element {:#X}", element.id)?,
+    }
+    write!(f, " at {}, size {} (header {})", element.offset, element.size, element.header_size)?;
+    match &element.detail {
+        Some(ElementDetail::EbmlHeader { doc_type }) => write!(f, ": DocType {doc_type}")?,
+        Some(ElementDetail::SeekHead { entries }) => write!(f, ": {entries} entries")?,
+        Some(ElementDetail::Info {
+            timecode_scale,
+            duration_ns,
+        }) => {
+            write!(f, ": timecode scale {timecode_scale}")?;
+            if let Some(duration_ns) = duration_ns {
+                write!(f, ", duration {:.3}s", *duration_ns as f64 / 1e9)?;
+            }
+        },
+        Some(ElementDetail::Track { track_num, codec_id }) => write!(f, ": track {track_num}, {codec_id}")?,
+        Some(ElementDetail::Cluster { timecode, blocks }) => write!(f, ": timecode {timecode}, {blocks} blocks")?,
+        Some(ElementDetail::Cues { cue_points }) => write!(f, ": {cue_points} cue points")?,
+        None => {},
+    }
+    writeln!(f)?;
+    for child in &element.children {
+        write_element(f, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId, Writer};
+    use std::io::Cursor;
+
+    #[test]
+    fn summarizes_layout() {
+        // A keyframe every half second starts a new cluster
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, video) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..10 {
+            segment.add_frame(video, &[0; 20], i * 100_000_000, i % 5 == 0).unwrap();
+            segment.add_frame(audio, &[0; 10], i * 100_000_000, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(Some(1000)) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+
+        let summary = summarize(Cursor::new(&file)).unwrap();
+        assert_eq!(summary.file_len, file.len() as u64);
+        let names: Vec<_> = summary.elements.iter().map(LayoutElement::name).collect();
+        assert_eq!(names, [Some("EBML"), Some("Segment")]);
+        let detail = summary.elements[0].detail.clone();
+        assert_eq!(detail, Some(ElementDetail::EbmlHeader { doc_type: "webm".into() }));
+        let segment = &summary.elements[1];
+        assert_eq!(segment.end(), file.len() as u64);
+        let names = segment.children.iter().filter_map(LayoutElement::name);
+        let names: Vec<_> = names.filter(|&name| name != "Void").collect();
+        assert_eq!(names, ["SeekHead", "Info", "Tracks", "Cluster", "Cluster", "Cues"]);
+
+        let info = segment.children.iter().find(|element| element.id == INFO_ID).unwrap();
+        let expected = ElementDetail::Info {
+            timecode_scale: 1_000_000,
+            duration_ns: Some(1_000_000_000),
+        };
+        assert_eq!(info.detail, Some(expected));
+        let tracks: Vec<_> = summary
+            .iter()
+            .filter_map(|element| match &element.detail {
+                Some(ElementDetail::Track { track_num, codec_id }) => Some((*track_num, codec_id.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tracks, [(1, "V_VP9"), (2, "A_OPUS")]);
+        let clusters: Vec<_> = summary.clusters().map(|cluster| cluster.detail.clone().unwrap()).collect();
+        // The audio frame at 400ms is held back until the keyframe after it, so it starts the second cluster
+        let cluster = |timecode, blocks| ElementDetail::Cluster { timecode, blocks };
+        assert_eq!(clusters, [cluster(0, 9), cluster(400, 11)]);
+
+        // Offsets only increase, and the elements of a level follow each other without gaps
+        let offsets: Vec<_> = summary.iter().map(|element| element.offset).collect();
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]), "{offsets:?}");
+        for level in [&summary.elements, &segment.children] {
+            assert!(level.windows(2).all(|pair| pair[0].end() == pair[1].offset), "{level:?}");
+        }
+        assert_eq!(segment.children.last().unwrap().end(), segment.end());
+
+        let dump = summary.to_string();
+        assert!(dump.starts_with("+ EBML at 0, size "), "{dump}");
+        for line in ["|+ SeekHead at ", "| + TrackEntry at ", ": track 2, A_OPUS", ": timecode 400, 11 blocks"] {
+            assert!(dump.contains(line), "{line} is missing from {dump}");
+        }
+        assert!(dump.contains(": timecode scale 1000000, duration 1.000s\n"), "{dump}");
+        assert!(dump.contains(": 2 cue points\n"), "{dump}");
+        assert_eq!(dump.lines().count(), summary.iter().count());
+    }
+
+    #[test]
+    fn rejects_broken_elements() {
+        // A Segment that claims more than the file holds
+        let data = [0x18, 0x53, 0x80, 0x67, 0x84, 0xEC, 0x80];
+        assert!(matches!(summarize(Cursor::new(data)), Err(DemuxError::InvalidFile)));
+
+        // Elements that aren't used in WebM are listed under their ID
+        let data = [0x18, 0x53, 0x80, 0x67, 0x83, 0x4F, 0xFF, 0x80];
+        let summary = summarize(Cursor::new(data)).unwrap();
+        assert_eq!(summary.elements[0].children[0].name(), None);
+        let dump = "+ Segment at 0, size 3 (header 5)\n|+ Unknown element 0x4FFF at 5, size 0 (header 3)\n";
+        assert_eq!(summary.to_string(), dump);
+    }
+}
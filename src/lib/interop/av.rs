@@ -0,0 +1,368 @@
+use std::io::{self, Cursor, Write};
+use std::sync::Arc;
+
+use av_data::packet::Packet;
+use av_data::params::MediaKind;
+use av_data::rational::Rational64;
+use av_data::value::Value;
+use av_format::common::GlobalInfo;
+use av_format::muxer::{Muxer, Writer as AvWriter};
+use av_format::stream::Stream;
+
+use super::AvMuxError;
+use crate::mux::{AudioCodecId, Segment, SegmentBuilder, TrackNum, VideoCodecId, Writer};
+use crate::time::{Timebase, Timestamp};
+
+/// A muxer for rust-av's `av-format`, writing WebM through a [`Segment`], so that rust-av pipelines can produce
+/// WebM with a `muxer::Context`.
+///
+/// The streams come from [`Muxer::set_global_info`], which must be called before the header is written. Each
+/// becomes a track, in order; the codec is taken from its codec ID, and the codec private data from its extradata.
+/// Packet timestamps are their `pts`, in the packet's timebase or else the stream's.
+///
+/// `libwebm` goes back to fill in the cues, duration and sizes when the segment is finalized, but the writers of
+/// `av-format` can't seek, so the file is kept in memory and written out by [`Muxer::write_trailer`]. With the
+/// `live` option set to `true`, the segment is written for live streaming instead, without cues or duration, and
+/// each call writes out what it can.
+///
+/// The errors passed to `av-format` carry an [`AvMuxError`].
+pub struct AvWebmMuxer {
+    info: Option<GlobalInfo>,
+    live: bool,
+    writing_app: Option<String>,
+    state: State,
+}
+
+/// How far [`AvWebmMuxer`] has gotten.
+enum State {
+    /// The header hasn't been written yet
+    Configuring,
+
+    /// Frames are being written to a segment
+    Muxing { output: Box<Output>, tracks: Tracks },
+
+    /// The trailer has been written
+    Done,
+}
+
+/// The tracks by stream index, along with the streams' timebases if they are usable
+type Tracks = Vec<(TrackNum, Option<Timebase>)>;
+
+enum Output {
+    File(Segment<Cursor<Vec<u8>>>),
+    Live(Segment<Vec<u8>>),
+}
+
+impl AvWebmMuxer {
+    /// A muxer without streams, which are set with [`Muxer::set_global_info`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            info: None,
+            live: false,
+            writing_app: None,
+            state: State::Configuring,
+        }
+    }
+
+    fn start(&mut self) -> Result<(Output, Tracks), AvMuxError> {
+        let info = self.info.as_ref().ok_or(AvMuxError::OutOfOrder("The global info must be set before the header"))?;
+        if self.live {
+            let builder = SegmentBuilder::new(Writer::new_non_seek(Vec::new()))?.set_live(true)?;
+            let (builder, tracks) = self.add_tracks(builder, &info.streams)?;
+            Ok((Output::Live(builder.build()), tracks))
+        } else {
+            let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new())))?;
+            let (builder, tracks) = self.add_tracks(builder, &info.streams)?;
+            Ok((Output::File(builder.build()), tracks))
+        }
+    }
+
+    fn add_tracks<W: Write>(
+        &self,
+        mut builder: SegmentBuilder<W>,
+        streams: &[Stream],
+    ) -> Result<(SegmentBuilder<W>, Tracks), AvMuxError> {
+        if let Some(app) = &self.writing_app {
+            builder = builder.set_writing_app(app)?;
+        }
+
+        let mut tracks = Vec::with_capacity(streams.len());
+        for (index, stream) in streams.iter().enumerate() {
+            let params = &stream.params;
+            let unsupported = || AvMuxError::UnsupportedCodec {
+                stream: index,
+                codec_id: params.codec_id.clone(),
+            };
+            let missing = |param| AvMuxError::MissingParams { stream: index, param };
+
+            let track: TrackNum = match params.kind.as_ref().ok_or_else(|| missing("media kind"))? {
+                MediaKind::Video(video) => {
+                    let codec = match params.codec_id.as_deref() {
+                        Some("vp8") => VideoCodecId::VP8,
+                        Some("vp9") => VideoCodecId::VP9,
+                        Some("av1") => VideoCodecId::AV1,
+                        _ => return Err(unsupported()),
+                    };
+                    let width = u32::try_from(video.width).map_err(|_| missing("valid width"))?;
+                    let height = u32::try_from(video.height).map_err(|_| missing("valid height"))?;
+                    let (next, track) = builder.add_video_track(width, height, codec, None)?;
+                    builder = next;
+                    track.into()
+                },
+                MediaKind::Audio(audio) => {
+                    let codec = match params.codec_id.as_deref() {
+                        Some("opus") => AudioCodecId::Opus,
+                        Some("vorbis") => AudioCodecId::Vorbis,
+                        _ => return Err(unsupported()),
+                    };
+                    let channels = audio.map.as_ref().map_or(0, |map| map.len());
+                    let channels = u32::try_from(channels).ok().filter(|&channels| channels != 0);
+                    let channels = channels.ok_or_else(|| missing("channel map"))?;
+                    let rate = u32::try_from(audio.rate).map_err(|_| missing("valid sample rate"))?;
+                    let (next, track) = builder.add_audio_track(rate, channels, codec, None)?;
+                    builder = next;
+                    track.into()
+                },
+            };
+            if let Some(extradata) = &params.extradata {
+                builder = builder.set_codec_private(track, extradata)?;
+            }
+            tracks.push((track, timebase(&stream.timebase)));
+        }
+        Ok((builder, tracks))
+    }
+
+    fn add_packet<W: Write>(&mut self, out: &mut AvWriter<W>, packet: &Packet) -> Result<(), AvMuxError> {
+        let State::Muxing { output, tracks } = &mut self.state else {
+            return Err(AvMuxError::OutOfOrder("Packets must be written after the header and before the trailer"));
+        };
+        let stream = usize::try_from(packet.stream_index).map_err(|_| AvMuxError::UnknownStream(packet.stream_index))?;
+        let &(track, stream_timebase) = tracks.get(stream).ok_or(AvMuxError::UnknownStream(packet.stream_index))?;
+
+        let time = &packet.t;
+        let timebase = time.timebase.as_ref().map_or(stream_timebase, timebase);
+        let ticks = time.pts.and_then(|pts| u64::try_from(pts).ok());
+        let (Some(ticks), Some(timebase)) = (ticks, timebase) else {
+            return Err(AvMuxError::BadTimestamp { stream });
+        };
+        let timestamp_ns = Timestamp { ticks, timebase }.to_ns().map_err(|_| AvMuxError::BadTimestamp { stream })?;
+
+        match &mut **output {
+            Output::File(segment) => segment.add_frame(track, &packet.data, timestamp_ns, packet.is_key)?,
+            Output::Live(segment) => {
+                segment.add_frame(track, &packet.data, timestamp_ns, packet.is_key)?;
+                write_out(out, segment.writer_mut().dest_mut())?;
+            },
+        }
+        Ok(())
+    }
+
+    fn finish<W: Write>(&mut self, out: &mut AvWriter<W>) -> Result<(), AvMuxError> {
+        let State::Muxing { output, .. } = std::mem::replace(&mut self.state, State::Done) else {
+            return Err(AvMuxError::OutOfOrder("The trailer must be written after the header, and only once"));
+        };
+        let output = match *output {
+            Output::File(segment) => segment.finalize(None).ok().map(|writer| writer.into_inner().into_inner()),
+            Output::Live(segment) => segment.finalize(None).ok().map(Writer::into_inner),
+        };
+        let mut output = output.ok_or(AvMuxError::Mux(crate::mux::Error::Unknown))?;
+        write_out(out, &mut output)
+    }
+}
+
+impl Default for AvWebmMuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Muxer for AvWebmMuxer {
+    fn configure(&mut self) -> av_format::error::Result<()> {
+        Ok(())
+    }
+
+    fn write_header<W: Write>(&mut self, _out: &mut AvWriter<W>) -> av_format::error::Result<()> {
+        if !matches!(self.state, State::Configuring) {
+            return Err(av_error(AvMuxError::OutOfOrder("The header must be written only once")));
+        }
+        let (output, tracks) = self.start().map_err(av_error)?;
+        self.state = State::Muxing {
+            output: Box::new(output),
+            tracks,
+        };
+        Ok(())
+    }
+
+    fn write_packet<W: Write>(&mut self, out: &mut AvWriter<W>, pkt: Arc<Packet>) -> av_format::error::Result<()> {
+        self.add_packet(out, &pkt).map_err(av_error)
+    }
+
+    fn write_trailer<W: Write>(&mut self, out: &mut AvWriter<W>) -> av_format::error::Result<()> {
+        self.finish(out).map_err(av_error)
+    }
+
+    fn set_global_info(&mut self, info: GlobalInfo) -> av_format::error::Result<()> {
+        if !matches!(self.state, State::Configuring) {
+            return Err(av_error(AvMuxError::OutOfOrder("The global info must be set before the header")));
+        }
+        self.info = Some(info);
+        Ok(())
+    }
+
+    /// Takes `live`, a boolean, and `writing_app`, a string for the `WritingApp` element.
+    fn set_option(&mut self, key: &str, val: Value) -> av_format::error::Result<()> {
+        if !matches!(self.state, State::Configuring) {
+            return Err(av_error(AvMuxError::OutOfOrder("Options must be set before the header")));
+        }
+        match (key, val) {
+            ("live", Value::Bool(live)) => self.live = live,
+            ("writing_app", Value::Str(app)) => self.writing_app = Some(app.to_owned()),
+            _ => return Err(av_error(AvMuxError::BadOption(key.to_owned()))),
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for AvWebmMuxer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// The [`Timebase`] of a rust-av rational, if it is positive and fits.
+fn timebase(rational: &Rational64) -> Option<Timebase> {
+    let num = u32::try_from(*rational.numer()).ok().filter(|&num| num != 0)?;
+    let den = u32::try_from(*rational.denom()).ok().filter(|&den| den != 0)?;
+    Some(Timebase::new(num, den))
+}
+
+/// Moves what has been written to `buffer` so far to `out`.
+fn write_out<W: Write>(out: &mut AvWriter<W>, buffer: &mut Vec<u8>) -> Result<(), AvMuxError> {
+    out.write_all(buffer).map_err(|err| AvMuxError::Mux(crate::mux::Error::Io(err)))?;
+    buffer.clear();
+    Ok(())
+}
+
+fn av_error(err: AvMuxError) -> av_format::error::Error {
+    let kind = match err {
+        AvMuxError::UnsupportedCodec { .. } => io::ErrorKind::Unsupported,
+        AvMuxError::Mux(crate::mux::Error::Io(_)) => io::ErrorKind::Other,
+        _ => io::ErrorKind::InvalidInput,
+    };
+    av_format::error::Error::Io(io::Error::new(kind, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use av_data::audiosample::ChannelMap;
+    use av_data::params::{AudioInfo, CodecParams, VideoInfo};
+    use av_format::muxer::Context;
+
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    fn params(codec_id: &str, kind: MediaKind, extradata: Option<&[u8]>) -> CodecParams {
+        CodecParams {
+            kind: Some(kind),
+            codec_id: Some(codec_id.into()),
+            extradata: extradata.map(<[u8]>::to_vec),
+            bit_rate: 0,
+            convergence_window: 0,
+            delay: 0,
+        }
+    }
+
+    fn global_info(video_codec: &str) -> GlobalInfo {
+        let video = VideoInfo {
+            width: 64,
+            height: 48,
+            format: None,
+        };
+        let audio = AudioInfo {
+            rate: 48_000,
+            map: Some(ChannelMap::default_map(2)),
+            format: None,
+        };
+        let mut info = GlobalInfo {
+            duration: None,
+            timebase: None,
+            streams: Vec::new(),
+        };
+        let video = params(video_codec, MediaKind::Video(video), None);
+        info.add_stream(Stream::from_params(&video, Rational64::new(1, 1000)));
+        let audio = params("opus", MediaKind::Audio(audio), Some(OPUS_HEAD));
+        info.add_stream(Stream::from_params(&audio, Rational64::new(1, 48_000)));
+        info
+    }
+
+    fn packet(stream_index: isize, pts: i64, is_key: bool) -> Arc<Packet> {
+        let mut packet = Packet::zeroed(20);
+        packet.stream_index = stream_index;
+        packet.t.pts = Some(pts);
+        packet.is_key = is_key;
+        Arc::new(packet)
+    }
+
+    fn mux(live: bool) -> Vec<u8> {
+        let mut context = Context::new(AvWebmMuxer::new(), AvWriter::new(Vec::new()));
+        context.set_global_info(global_info("vp9")).unwrap();
+        context.set_option("live", live).unwrap();
+        context.configure().unwrap();
+        context.write_header().unwrap();
+        for i in 0..50 {
+            context.write_packet(packet(0, i * 40, i % 25 == 0)).unwrap();
+            context.write_packet(packet(1, i * 1920, true)).unwrap();
+        }
+        if live {
+            assert!(!context.writer().as_ref().0.is_empty());
+        }
+        context.write_trailer().unwrap();
+        context.into_writer().as_ref().0.clone()
+    }
+
+    #[test]
+    fn muxes_packets() {
+        for live in [false, true] {
+            let mut demuxer = Demuxer::new(Cursor::new(mux(live))).unwrap();
+            let tracks = demuxer.tracks();
+            assert_eq!((tracks[0].codec_id.as_str(), tracks[1].codec_id.as_str()), ("V_VP9", "A_OPUS"));
+            assert_eq!(tracks[1].codec_private, OPUS_HEAD);
+            assert_eq!(demuxer.segment_info().duration_ns.is_some(), !live);
+
+            let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap()).collect();
+            assert_eq!(frames.len(), 100);
+            let video: Vec<_> = frames.iter().filter(|frame| frame.track_num == 1).collect();
+            assert!(video.iter().enumerate().all(|(i, frame)| frame.timestamp_ns == i as u64 * 40_000_000));
+            assert_eq!(video.iter().filter(|frame| frame.is_keyframe).count(), 2);
+            let audio = frames.iter().filter(|frame| frame.track_num == 2).map(|frame| frame.timestamp_ns);
+            assert!(audio.enumerate().all(|(i, timestamp_ns)| timestamp_ns == i as u64 * 40_000_000));
+        }
+    }
+
+    #[test]
+    fn reports_clear_errors() {
+        fn error(result: av_format::error::Result<()>) -> (io::ErrorKind, String) {
+            let Err(av_format::error::Error::Io(err)) = result else {
+                panic!("Unexpectedly didn't fail with an I/O error");
+            };
+            assert!(err.get_ref().unwrap().downcast_ref::<AvMuxError>().is_some());
+            (err.kind(), err.to_string())
+        }
+
+        let mut context = Context::new(AvWebmMuxer::new(), AvWriter::new(Vec::new()));
+        context.set_global_info(global_info("h264")).unwrap();
+        let (kind, message) = error(context.write_header());
+        assert_eq!(kind, io::ErrorKind::Unsupported);
+        assert_eq!(message, r#"Stream 0 has codec "h264", which WebM can't hold"#);
+
+        let mut context = Context::new(AvWebmMuxer::new(), AvWriter::new(Vec::new()));
+        assert_eq!(error(context.set_option("live", 1u64)).1, r#"Invalid option "live""#);
+        assert_eq!(error(context.write_packet(packet(0, 0, true))).0, io::ErrorKind::InvalidInput);
+        context.set_global_info(global_info("vp8")).unwrap();
+        context.write_header().unwrap();
+        assert_eq!(error(context.write_packet(packet(2, 0, true))).1, "There is no stream 2");
+        assert_eq!(error(context.write_packet(packet(0, -1, true))).1, "A packet of stream 0 has an invalid timestamp");
+    }
+}
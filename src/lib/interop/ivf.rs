@@ -0,0 +1,413 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::mux::{sniff, Av1SequenceHeader, Error, MuxSummary, SegmentBuilder, VideoCodecId, Writer};
+use crate::time::{ticks_to_ns, TimeError, Timebase};
+
+/// The length of the fields of the file header, which may declare itself longer.
+const HEADER_LEN: usize = 32;
+
+/// The length of the header of each frame: the size of its data, then its timestamp.
+const FRAME_HEADER_LEN: usize = 12;
+
+/// The header of an IVF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IvfHeader {
+    /// The codec, from the header's FourCC: `VP80`, `VP90` or `AV01`.
+    pub codec: VideoCodecId,
+
+    pub width: u16,
+    pub height: u16,
+
+    /// The timebase of the frames' timestamps.
+    pub timebase: Timebase,
+
+    /// How many frames the header says there are. Encoders that can't go back to the header once they are done leave
+    /// it as 0 or as an estimate, so the frames are read until the file ends instead.
+    pub frame_count: u32,
+}
+
+/// A frame read by an [`IvfReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IvfFrame {
+    pub data: Vec<u8>,
+
+    /// The timestamp as written in the file, in the header's timebase.
+    pub pts: u64,
+
+    /// The timestamp converted to nanoseconds.
+    pub timestamp_ns: u64,
+}
+
+/// The error type for reading and remuxing IVF files.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IvfError {
+    /// Reading the file failed, or writing the WebM file did.
+    Io(io::Error),
+
+    /// The file doesn't start with the `DKIF` signature of IVF.
+    NotIvf,
+
+    /// The file ends within its header.
+    TruncatedHeader,
+
+    /// The header's `field` is invalid: a `version` other than 0, a `header_size` shorter than its fields, or a
+    /// `timebase` with a zero in it.
+    BadHeader { field: &'static str },
+
+    /// The header's FourCC isn't of a codec that WebM can hold.
+    UnsupportedCodec { fourcc: [u8; 4] },
+
+    /// The file ends partway through the frame at `index`, counting from 0, after `read` of the `expected` bytes of
+    /// its header and data.
+    TruncatedFrame { index: u64, expected: u64, read: u64 },
+
+    /// The timestamp of the frame at `index` can't be converted to nanoseconds.
+    Time { index: u64, error: TimeError },
+
+    /// Muxing the frames failed.
+    Mux(Error),
+}
+
+impl std::fmt::Display for IvfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IvfError::Io(err) => write!(f, "I/O error: {err}"),
+            IvfError::NotIvf => f.write_str("Not an IVF file: no DKIF signature"),
+            IvfError::TruncatedHeader => f.write_str("The IVF file ends within its header"),
+            IvfError::BadHeader { field } => write!(f, "The IVF header has an invalid {field}"),
+            IvfError::UnsupportedCodec { fourcc } => {
+                write!(f, "The IVF FourCC {:?} is not VP80, VP90 or AV01", String::from_utf8_lossy(fourcc))
+            },
+            IvfError::TruncatedFrame { index, expected, read } => {
+                write!(f, "The IVF file ends within frame {index}, after {read} of its {expected} bytes")
+            },
+            IvfError::Time { index, error } => write!(f, "Frame {index} has an invalid timestamp: {error}"),
+            IvfError::Mux(err) => write!(f, "Muxing failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for IvfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IvfError::Io(err) => Some(err),
+            IvfError::Time { error, .. } => Some(error),
+            IvfError::Mux(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for IvfError {
+    fn from(err: Error) -> Self {
+        IvfError::Mux(err)
+    }
+}
+
+/// Reads the frames of an IVF file, the container that `vpxenc`, `aomenc` and other encoder tools write raw VP8,
+/// VP9 and AV1 streams to: a 32-byte header, then each frame prefixed with its size and timestamp.
+///
+/// This is an iterator over the frames. Once it has returned an error, it returns nothing more.
+#[derive(Debug)]
+pub struct IvfReader<R> {
+    reader: R,
+    header: IvfHeader,
+
+    /// The index of the next frame, or `None` once the file has ended or failed to read
+    next: Option<u64>,
+}
+
+impl<R: Read> IvfReader<R> {
+    /// Reads the header from `reader`, which is left at the first frame.
+    pub fn new(mut reader: R) -> Result<Self, IvfError> {
+        let mut header = [0; HEADER_LEN];
+        let len = read_full(&mut reader, &mut header)?;
+        if len < 4 || &header[..4] != b"DKIF" {
+            return Err(IvfError::NotIvf);
+        }
+        if len < HEADER_LEN {
+            return Err(IvfError::TruncatedHeader);
+        }
+
+        let u16_at = |pos: usize| u16::from_le_bytes([header[pos], header[pos + 1]]);
+        let u32_at = |pos: usize| u32::from_le_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]);
+        if u16_at(4) != 0 {
+            return Err(IvfError::BadHeader { field: "version" });
+        }
+        let header_size = usize::from(u16_at(6));
+        if header_size < HEADER_LEN {
+            return Err(IvfError::BadHeader { field: "header_size" });
+        }
+        let codec = match &header[8..12] {
+            b"VP80" => VideoCodecId::VP8,
+            b"VP90" => VideoCodecId::VP9,
+            b"AV01" => VideoCodecId::AV1,
+            _ => {
+                let fourcc = [header[8], header[9], header[10], header[11]];
+                return Err(IvfError::UnsupportedCodec { fourcc });
+            },
+        };
+        // The rate, then the scale, so that a tick lasts scale / rate seconds
+        let timebase = Timebase::new(u32_at(20), u32_at(16));
+        if timebase.num == 0 || timebase.den == 0 {
+            return Err(IvfError::BadHeader { field: "timebase" });
+        }
+
+        // Anything past the fields is skipped
+        let extra = (header_size - HEADER_LEN) as u64;
+        let skipped = io::copy(&mut (&mut reader).take(extra), &mut io::sink()).map_err(IvfError::Io)?;
+        if skipped < extra {
+            return Err(IvfError::TruncatedHeader);
+        }
+
+        Ok(Self {
+            reader,
+            header: IvfHeader {
+                codec,
+                width: u16_at(12),
+                height: u16_at(14),
+                timebase,
+                frame_count: u32_at(24),
+            },
+            next: Some(0),
+        })
+    }
+
+    #[must_use]
+    pub fn header(&self) -> &IvfHeader {
+        &self.header
+    }
+
+    fn read_frame(&mut self, index: u64) -> Result<Option<IvfFrame>, IvfError> {
+        let mut frame_header = [0; FRAME_HEADER_LEN];
+        let len = read_full(&mut self.reader, &mut frame_header)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        if len < FRAME_HEADER_LEN {
+            return Err(IvfError::TruncatedFrame {
+                index,
+                expected: FRAME_HEADER_LEN as u64,
+                read: len as u64,
+            });
+        }
+
+        let [s0, s1, s2, s3, p0, p1, p2, p3, p4, p5, p6, p7] = frame_header;
+        let size = u64::from(u32::from_le_bytes([s0, s1, s2, s3]));
+        let pts = u64::from_le_bytes([p0, p1, p2, p3, p4, p5, p6, p7]);
+        // The data is read as it comes rather than allocated up front, so that a corrupt size can't exhaust memory
+        let mut data = Vec::new();
+        let read = (&mut self.reader).take(size).read_to_end(&mut data).map_err(IvfError::Io)? as u64;
+        if read < size {
+            return Err(IvfError::TruncatedFrame {
+                index,
+                expected: FRAME_HEADER_LEN as u64 + size,
+                read: FRAME_HEADER_LEN as u64 + read,
+            });
+        }
+        let timestamp_ns = ticks_to_ns(pts, self.header.timebase).map_err(|error| IvfError::Time { index, error })?;
+        Ok(Some(IvfFrame {
+            data,
+            pts,
+            timestamp_ns,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for IvfReader<R> {
+    type Item = Result<IvfFrame, IvfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next.take()?;
+        match self.read_frame(index) {
+            Ok(Some(frame)) => {
+                self.next = Some(index + 1);
+                Some(Ok(frame))
+            },
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Reads into `buf` until it is full or the reader ends, returning how much was read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, IvfError> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(IvfError::Io(err)),
+        }
+    }
+    Ok(len)
+}
+
+/// Remuxes the IVF file read from `reader` into a WebM file written to `dest`, with a single video track of the
+/// header's codec and size. Pass `&mut` to a destination to keep it, e.g. to take the file out of a `Cursor`.
+///
+/// Whether each frame is a keyframe is read from its bitstream with [`sniff::is_keyframe`]; a frame that can't be
+/// told apart is taken to be a keyframe only if it is the first. AV1 tracks get the sequence header of the first
+/// frame as their `CodecPrivate`. As with [`mux_to_file`](crate::mux::mux_to_file), the `Duration` runs to the end
+/// of the last frame, taking it to last as long as the gap before it.
+pub fn remux_ivf<R: Read, W: Write + Seek>(reader: R, dest: W) -> Result<MuxSummary, IvfError> {
+    let mut frames = IvfReader::new(reader)?;
+    let header = *frames.header();
+    let builder = SegmentBuilder::new(Writer::new(dest))?;
+    let (mut builder, track) =
+        builder.add_video_track(header.width.into(), header.height.into(), header.codec, None)?;
+
+    // The first frame is read ahead, as AV1 tracks need its sequence header before the segment is built
+    let first = frames.next().transpose()?;
+    if header.codec == VideoCodecId::AV1 {
+        if let Some(codec_private) = first.as_ref().and_then(|frame| Av1SequenceHeader::codec_private(&frame.data)) {
+            builder = builder.set_codec_private(track, &codec_private)?;
+        }
+    }
+    let mut segment = builder.build();
+
+    let (mut count, mut end_ns, mut last_ns) = (0, 0, None);
+    for frame in first.map(Ok).into_iter().chain(frames) {
+        let frame = frame?;
+        let keyframe = sniff::is_keyframe(header.codec, &frame.data).unwrap_or(count == 0);
+        segment.add_frame(track, &frame.data, frame.timestamp_ns, keyframe)?;
+        let gap = last_ns.map_or(0, |last| frame.timestamp_ns.saturating_sub(last));
+        end_ns = end_ns.max(frame.timestamp_ns.saturating_add(gap));
+        last_ns = Some(frame.timestamp_ns);
+        count += 1;
+    }
+
+    let writer = segment.finalize_with_duration_ns(end_ns).map_err(|_| Error::Unknown)?;
+    let mut dest = writer.into_inner();
+    dest.flush().map_err(IvfError::Io)?;
+    let bytes_written = dest.seek(SeekFrom::End(0)).map_err(IvfError::Io)?;
+    Ok(MuxSummary {
+        bytes_written,
+        duration_ns: end_ns,
+        frames_per_track: vec![count],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use std::io::Cursor;
+
+    /// A 16x16 VP9 stream at 30 fps: a keyframe, two interframes, then the same again.
+    const VP9_IVF: &[u8] = include_bytes!("testdata/vp9_16x16.ivf");
+
+    #[test]
+    fn reads_ivf_files() {
+        let mut reader = IvfReader::new(VP9_IVF).unwrap();
+        let header = IvfHeader {
+            codec: VideoCodecId::VP9,
+            width: 16,
+            height: 16,
+            timebase: Timebase::FPS_30,
+            frame_count: 6,
+        };
+        assert_eq!(*reader.header(), header);
+
+        let frames: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(frames.iter().map(|frame| frame.pts).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+        let timestamps: Vec<_> = frames.iter().map(|frame| frame.timestamp_ns).collect();
+        assert_eq!(timestamps, [0, 33_333_333, 66_666_667, 100_000_000, 133_333_333, 166_666_667]);
+        assert_eq!(frames[0].data.len(), 14);
+        assert_eq!(frames[1].data.len(), 10);
+        assert!(reader.next().is_none());
+
+        // A longer header has its extra bytes skipped
+        let mut longer = VP9_IVF[..HEADER_LEN].to_vec();
+        longer[6] = 36;
+        longer.extend_from_slice(b"skip");
+        longer.extend_from_slice(&VP9_IVF[HEADER_LEN..]);
+        assert_eq!(IvfReader::new(longer.as_slice()).unwrap().count(), 6);
+    }
+
+    #[test]
+    fn remuxes_ivf_files() {
+        let mut file = Cursor::new(Vec::new());
+        let summary = remux_ivf(VP9_IVF, &mut file).unwrap();
+        assert_eq!(summary.frames_per_track, [6]);
+        assert_eq!(summary.duration_ns, 200_000_001);
+        assert_eq!(summary.bytes_written, file.get_ref().len() as u64);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file.into_inner())).unwrap();
+        assert_eq!(demuxer.tracks()[0].codec_id, "V_VP9");
+        assert_eq!(demuxer.segment_info().duration_ns, Some(200_000_000));
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        let keyframes: Vec<_> = frames.iter().map(|frame| frame.is_keyframe).collect();
+        assert_eq!(keyframes, [true, false, false, true, false, false]);
+        let timestamps: Vec<_> = frames.iter().map(|frame| frame.timestamp_ns).collect();
+        // libwebm truncates timestamps to whole milliseconds
+        assert_eq!(timestamps, [0, 33_000_000, 66_000_000, 100_000_000, 133_000_000, 166_000_000]);
+    }
+
+    #[test]
+    fn remuxes_av1_ivf_files() {
+        // 1920x1080 in milliseconds, without a frame count. A temporal unit with a sequence header and a keyframe,
+        // then one with an interframe
+        const SEQUENCE_HEADER: &[u8] = b"\x0A\x0E\x00\x00\x00\x42\xAB\xBF\xC3\x73\xFF\xE6\x40\x40\x40\x49";
+        let keyframe = [b"\x12\x00", SEQUENCE_HEADER, b"\x32\x03\x10\x00\x00"].concat();
+        let interframe = b"\x12\x00\x32\x03\x30\x00\x00";
+        let mut ivf = b"DKIF\x00\x00\x20\x00AV01\x80\x07\x38\x04\xE8\x03\x00\x00\x01\x00\x00\x00\
+            \x00\x00\x00\x00\x00\x00\x00\x00"
+            .to_vec();
+        for (pts, frame) in [(0u64, &keyframe[..]), (40, interframe)] {
+            ivf.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            ivf.extend_from_slice(&pts.to_le_bytes());
+            ivf.extend_from_slice(frame);
+        }
+
+        let mut file = Cursor::new(Vec::new());
+        remux_ivf(ivf.as_slice(), &mut file).unwrap();
+        let mut demuxer = Demuxer::new(Cursor::new(file.into_inner())).unwrap();
+        let track = &demuxer.tracks()[0];
+        assert_eq!(track.codec_id, "V_AV1");
+        assert_eq!(track.codec_private, [b"\x81\x08\x0D\x00", SEQUENCE_HEADER].concat());
+        let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap()).collect();
+        let frames: Vec<_> = frames.iter().map(|frame| (frame.timestamp_ns, frame.is_keyframe)).collect();
+        assert_eq!(frames, [(0, true), (40_000_000, false)]);
+    }
+
+    #[test]
+    fn rejects_malformed_ivf() {
+        let with = |pos: usize, bytes: &[u8]| {
+            let mut ivf = VP9_IVF.to_vec();
+            ivf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            ivf
+        };
+
+        assert!(matches!(IvfReader::new(&b"RIFF"[..]), Err(IvfError::NotIvf)));
+        assert!(matches!(IvfReader::new(&VP9_IVF[..20]), Err(IvfError::TruncatedHeader)));
+        assert!(matches!(IvfReader::new(&with(6, b"\x24")[..HEADER_LEN + 2]), Err(IvfError::TruncatedHeader)));
+        assert!(matches!(IvfReader::new(&with(4, b"\x01")[..]), Err(IvfError::BadHeader { field: "version" })));
+        assert!(matches!(IvfReader::new(&with(6, b"\x10")[..]), Err(IvfError::BadHeader { field: "header_size" })));
+        assert!(matches!(IvfReader::new(&with(20, b"\x00")[..]), Err(IvfError::BadHeader { field: "timebase" })));
+        let h264 = with(8, b"H264");
+        let result = IvfReader::new(h264.as_slice());
+        assert!(matches!(result, Err(IvfError::UnsupportedCodec { fourcc: [b'H', b'2', b'6', b'4'] })));
+        let message = result.unwrap_err().to_string();
+        assert_eq!(message, "The IVF FourCC \"H264\" is not VP80, VP90 or AV01");
+
+        // Frames cut off in their data or their header fail at that frame, after which there are no more
+        let mut reader = IvfReader::new(&VP9_IVF[..VP9_IVF.len() - 3]).unwrap();
+        assert_eq!(reader.by_ref().take(5).filter(Result::is_ok).count(), 5);
+        let Some(Err(err)) = reader.next() else {
+            panic!("The truncated frame should have failed");
+        };
+        assert!(matches!(err, IvfError::TruncatedFrame { index: 5, expected: 22, read: 19 }), "{err:?}");
+        assert_eq!(err.to_string(), "The IVF file ends within frame 5, after 19 of its 22 bytes");
+        assert!(reader.next().is_none());
+        let mut reader = IvfReader::new(&VP9_IVF[..HEADER_LEN + 5]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(IvfError::TruncatedFrame { index: 0, expected: 12, read: 5 }))));
+
+        let result = remux_ivf(&VP9_IVF[..VP9_IVF.len() - 3], Cursor::new(Vec::new()));
+        assert!(matches!(result, Err(IvfError::TruncatedFrame { index: 5, .. })));
+    }
+}
@@ -1,4 +1,4 @@
-//! A crate for muxing one or more video/audio streams into a WebM file.
+//! A crate for muxing one or more video/audio streams into a WebM file, and for reading them back out again.
 //!
 //! Note that this crate is only for muxing media that has already been encoded with the appropriate codec.
 //! Consider a crate such as `vpx` if you need encoding as well.
@@ -31,25 +31,153 @@
 //! // Done writing frames, finish off the file
 //! _ = segment.finalize(None).inspect_err(|_| eprintln!("Could not finalize WebM file"));
 //! ```
+//!
+//! When all the frames are at hand, [`mux::mux_to_file`] does the above in one call. For a file of a single track,
+//! [`mux::SimpleVideoWriter`] and [`mux::SimpleAudioWriter`] take care of the segment and track for you, and
+//! [`mux::presets`] has them set up for common kinds of file, such as Opus podcasts. To describe a whole output as
+//! data, such as a job's configuration, build a [`mux::MuxSpec`] and apply it with
+//! [`mux::SegmentBuilder::apply_spec`]. Timestamps counted in a codec's own ticks can be converted to nanoseconds
+//! with the [`time`] module, or passed as they are to [`mux::Segment::add_frame_at`]. Recordings that can be paused
+//! can pass wall-clock times to [`mux::Segment::add_frame_wall`], which leaves the pauses out.
+//!
+//! With the `serde` feature, the configuration types such as [`mux::MuxSpec`] implement `Serialize` and
+//! `Deserialize`, so that they can be read from a job's JSON or TOML, and so do the information types returned by a
+//! [`demux::Demuxer`]. Codecs and other enumerations are written as lowercase names, e.g. `"vp9"`. The `bytes`
+//! feature adds `Demuxer::from_bytes`, which reads frames out of a `bytes::Bytes` without copying them, and the
+//! `av-format` feature adds `interop::AvWebmMuxer`, through which rust-av pipelines can write WebM.
+//! Streams that an encoder wrote to an IVF file can be remuxed with [`interop::ivf::remux_ivf`].
+//!
+//! Existing WebM files can be read with a [`demux::Demuxer`], which gives you the file's tracks and lets you iterate
+//! over its frames:
+//! ```no_run
+//! use std::fs::File;
+//! use webm::demux::Demuxer;
+//!
+//! let file = File::open("./my-cool-file.webm").unwrap();
+//! let mut demuxer = Demuxer::new(file).unwrap();
+//!
+//! for track in demuxer.tracks() {
+//!     println!("Track {}: {}", track.track_num, track.codec_id);
+//! }
+//!
+//! for frame in demuxer.frames() {
+//!     let frame = frame.unwrap();
+//!     println!("{} bytes on track {} at {}ns", frame.data.len(), frame.track_num, frame.timestamp_ns);
+//! }
+//! ```
+//!
+//! To remux a file, [`remux::clone_tracks`] recreates a [`demux::Demuxer`]'s tracks in a [`mux::SegmentBuilder`].
+//! [`remux::concatenate`], [`remux::extract_range`] and [`remux::extract_track`] build on it to join files and to cut
+//! out part of one. For other edits, [`remux::pipe_frames`] copies frames to the new segment through a filter of your
+//! own.
+//!
+//! A recording that was never finalized, e.g. because of a crash or power loss, lacks the `Cues`, `Duration` and
+//! `SeekHead` that players rely on. [`repair::finalize_in_place`] adds them, and [`repair::repair_to`] writes a
+//! repaired copy of files that have no room for them. To see where everything ended up in a file, e.g. for a support
+//! ticket, [`inspect::summarize`] lists its elements with their offsets and sizes.
 
 use webm_sys as ffi;
 
+/// A version of one of `libwebm`'s libraries, displayed as `major.minor.build.revision`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComponentVersion {
+    pub major: i32,
+    pub minor: i32,
+    pub build: i32,
+    pub revision: i32,
+}
+
+impl std::fmt::Display for ComponentVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.build, self.revision)
+    }
+}
+
+/// The versions of the bundled `libwebm` this crate was built with, as returned by [`libwebm_version`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LibwebmVersion {
+    /// The version of `mkvmuxer`, which is also written as the `MuxingApp` of muxed files.
+    pub muxer: ComponentVersion,
+
+    /// The version of `mkvparser`, which `libwebm` versions separately from `mkvmuxer`.
+    pub parser: ComponentVersion,
+
+    /// The version of this crate.
+    pub crate_version: &'static str,
+}
+
+impl std::fmt::Display for LibwebmVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "libwebm muxer {}, parser {} (webm {})", self.muxer, self.parser, self.crate_version)
+    }
+}
+
+/// The versions of `libwebm` and of this crate, for telling apart builds when behavior differs between environments.
+#[must_use]
+pub fn libwebm_version() -> LibwebmVersion {
+    let version = |get: unsafe extern "C" fn(*mut i32, *mut i32, *mut i32, *mut i32)| {
+        let mut version = ComponentVersion::default();
+        unsafe { get(&mut version.major, &mut version.minor, &mut version.build, &mut version.revision) };
+        version
+    };
+
+    LibwebmVersion {
+        muxer: version(ffi::mux::libwebm_version),
+        parser: version(ffi::parser::libwebm_version),
+        crate_version: env!("CARGO_PKG_VERSION"),
+    }
+}
+
 pub mod mux {
+    mod file;
+    mod resume;
+    mod rotating;
     mod segment;
+    mod simple;
+    mod spec;
+    mod streaming;
+    mod thread_check;
     mod writer;
 
+    /// The `CodecPrivate` of tracks, built from the headers of their codecs.
+    pub mod codec_private;
+
+    /// The layout of frames of encrypted tracks, for content protected with Encrypted Media Extensions.
+    pub mod encryption;
+
+    /// Writers set up for common kinds of file, with the settings they need to be played and seeked correctly.
+    pub mod presets;
+
+    /// What can be told about a frame from the first few bytes of its bitstream.
+    pub mod sniff;
+
     pub use {
+        crate::demux::{Chromaticity, MasteringMetadata, ProjectionType},
         crate::ffi::mux::TrackNum,
+        file::{mux_to_file, mux_to_file_with_options},
+        resume::ResumedFile,
+        rotating::RotatingMuxer,
         segment::{Segment, SegmentBuilder},
-        writer::Writer,
+        simple::{SimpleAudioWriter, SimpleVideoWriter},
+        streaming::StreamingSegment,
+        writer::{Truncate, Writer},
     };
 
+    pub(crate) use codec_private::{Av1SequenceHeader, OpusHead, VorbisIdentification, OPUS_SEEK_PRE_ROLL_NS};
+    pub(crate) use segment::{color_format_is_valid, is_bcp_47, is_iso_639_2, FrameExtras};
+
     use crate::ffi;
+    use std::cell::Cell;
+    use std::ffi::c_uint;
     use std::num::NonZeroU64;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::SystemTime;
 
-    /// This is a copyable handle equivalent to a track number
+    /// This is a copyable handle equivalent to a track number. It also remembers which segment it was added to (by an
+    /// ID unique within the process), so that [`Segment::add_video_frame`] can reject handles of other segments.
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-    pub struct VideoTrack(NonZeroU64);
+    pub struct VideoTrack(NonZeroU64, u64);
 
     impl From<VideoTrack> for TrackNum {
         fn from(track: VideoTrack) -> Self {
@@ -57,9 +185,10 @@ pub mod mux {
         }
     }
 
-    /// This is a copyable handle equivalent to a track number
+    /// This is a copyable handle equivalent to a track number. It also remembers which segment it was added to (by an
+    /// ID unique within the process), so that [`Segment::add_audio_frame`] can reject handles of other segments.
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-    pub struct AudioTrack(NonZeroU64);
+    pub struct AudioTrack(NonZeroU64, u64);
 
     impl From<AudioTrack> for TrackNum {
         fn from(track: AudioTrack) -> Self {
@@ -83,30 +212,121 @@ pub mod mux {
     }
 
     impl Track for VideoTrack {
-        #[must_use]
         fn is_video(&self) -> bool {
             true
         }
 
-        #[must_use]
         fn track_number(&self) -> TrackNum {
             self.0.get()
         }
     }
 
     impl Track for AudioTrack {
-        #[must_use]
         fn is_audio(&self) -> bool {
             true
         }
 
-        #[must_use]
         fn track_number(&self) -> TrackNum {
             self.0.get()
         }
     }
 
+    /// A handle to a track of any kind, for lists of tracks that are set up at runtime, such as those added with
+    /// [`SegmentBuilder::add_track`]. It can be passed to [`Segment::add_frame`] like the handle it holds.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[non_exhaustive]
+    pub enum TrackHandle {
+        Video(VideoTrack),
+        Audio(AudioTrack),
+    }
+
+    impl TrackHandle {
+        /// The video track, or `None` if this is another kind of track.
+        #[must_use]
+        pub fn as_video(&self) -> Option<VideoTrack> {
+            match *self {
+                TrackHandle::Video(track) => Some(track),
+                TrackHandle::Audio(_) => None,
+            }
+        }
+
+        /// The audio track, or `None` if this is another kind of track.
+        #[must_use]
+        pub fn as_audio(&self) -> Option<AudioTrack> {
+            match *self {
+                TrackHandle::Audio(track) => Some(track),
+                TrackHandle::Video(_) => None,
+            }
+        }
+    }
+
+    impl From<VideoTrack> for TrackHandle {
+        fn from(track: VideoTrack) -> Self {
+            TrackHandle::Video(track)
+        }
+    }
+
+    impl From<AudioTrack> for TrackHandle {
+        fn from(track: AudioTrack) -> Self {
+            TrackHandle::Audio(track)
+        }
+    }
+
+    impl From<TrackHandle> for TrackNum {
+        fn from(track: TrackHandle) -> Self {
+            match track {
+                TrackHandle::Video(track) => track.into(),
+                TrackHandle::Audio(track) => track.into(),
+            }
+        }
+    }
+
+    impl Track for TrackHandle {
+        fn is_audio(&self) -> bool {
+            matches!(self, TrackHandle::Audio(_))
+        }
+
+        fn is_video(&self) -> bool {
+            matches!(self, TrackHandle::Video(_))
+        }
+
+        fn track_number(&self) -> TrackNum {
+            (*self).into()
+        }
+    }
+
+    thread_local! {
+        static UID_SEED: Cell<c_uint> = Cell::new(uid_seed());
+    }
+
+    /// Seeds each thread's UIDs differently, even for threads started within the same clock tick.
+    fn uid_seed() -> c_uint {
+        static THREADS: AtomicU32 = AtomicU32::new(0);
+        let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        time.subsec_nanos() ^ (time.as_secs() as u32) ^ THREADS.fetch_add(0x9E37_79B9, Ordering::Relaxed)
+    }
+
+    /// Generates a random UID with `libwebm`'s generator, suitable for a `TrackUID`, `ChapterUID` or other Matroska
+    /// UID.
+    ///
+    /// The UIDs are 56 bits wide, as `libwebm` makes them for its own tracks. Each thread draws from its own generator,
+    /// seeded from the clock; the UIDs are unique enough for a file, but aren't cryptographically random.
+    #[must_use]
+    pub fn generate_uid() -> NonZeroU64 {
+        UID_SEED.with(|seed| {
+            let mut state = seed.get();
+            let uid = loop {
+                if let Some(uid) = NonZeroU64::new(unsafe { ffi::mux::make_uid(&mut state) }) {
+                    break uid;
+                }
+            };
+            seed.set(state);
+            uid
+        })
+    }
+
     #[derive(Eq, PartialEq, Clone, Copy, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
     #[repr(u32)]
     pub enum AudioCodecId {
         Opus = ffi::mux::OPUS_CODEC_ID,
@@ -120,6 +340,7 @@ pub mod mux {
     }
 
     #[derive(Eq, PartialEq, Clone, Copy, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
     #[repr(u32)]
     pub enum VideoCodecId {
         VP8 = ffi::mux::VP8_CODEC_ID,
@@ -145,6 +366,88 @@ pub mod mux {
         /// incorrect parameters to methods, an internal error in libwebm is
         /// also possible.
         Unknown,
+
+        /// A call into libwebm failed, for the reason given.
+        Libwebm {
+            /// Which step failed, and how, e.g. `AddFrame: Segment::AddFrame returned false: the write callback
+            /// failed`.
+            message: String,
+        },
+
+        /// The segment has no track of the expected kind with this number.
+        UnknownTrack { track_num: TrackNum },
+
+        /// The track handle was created for a different segment.
+        ForeignTrack { track_num: TrackNum },
+
+        /// Creating or writing a file failed.
+        Io(std::io::Error),
+
+        /// Adding a track given to [`mux_to_file`] failed. Tracks are numbered from 0, in the order they were given.
+        Track { index: usize, error: Box<Error> },
+
+        /// Writing a frame given to [`mux_to_file`] failed. Frames are numbered from 0, in the order they were given.
+        Frame { index: usize, error: Box<Error> },
+
+        /// A frame given to [`mux_to_file`] is earlier than the one before it, and [`MuxFileOptions::sort_frames`]
+        /// isn't set.
+        UnorderedFrame {
+            index: usize,
+            timestamp_ns: u64,
+            previous_ns: u64,
+        },
+
+        /// Converting a timestamp to nanoseconds failed.
+        Time(crate::time::TimeError),
+
+        /// A field of a [`MuxSpec`] or [`TrackSpec`] is invalid or unsupported. The path names the field, e.g.
+        /// `tracks[1].color`.
+        Spec { path: String, error: Box<Error> },
+
+        /// The file given to [`Segment::resume`] can't be finalized in place, so it can't be resumed either.
+        Repair(crate::repair::RepairError),
+
+        /// The file given to [`Segment::resume`] doesn't match the [`MuxSpec`] at the field the path names, e.g.
+        /// `tracks[0].media` for a track of another codec or size.
+        ResumeMismatch { path: String },
+
+        /// [`SegmentBuilder::build_strict`] found more than one default track of a kind: these are that kind's
+        /// default tracks.
+        DefaultTracks { track_nums: Vec<TrackNum> },
+
+        /// A segment set up with [`SegmentBuilder::dash_live`] was given a frame at `timestamp_ns`, past the tolerance
+        /// for the keyframe that starts the media segment due at `boundary_ns`. The frame wasn't written.
+        KeyframeOverdue { boundary_ns: u64, timestamp_ns: u64 },
+
+        /// [`SegmentBuilder::build_strict`] found that the `CodecDelay` set for an Opus track isn't the pre-skip of
+        /// its `OpusHead`, so players would drop the wrong number of samples from its start.
+        CodecDelayMismatch {
+            track_num: TrackNum,
+            codec_delay_ns: u64,
+            pre_skip_ns: u64,
+        },
+
+        /// A segment set up with [`SegmentBuilder::verify_keyframe_flags`] was given a frame at `timestamp_ns` whose
+        /// keyframe flag isn't what its bitstream says, which is `keyframe`. The frame wasn't written.
+        KeyframeFlag {
+            track_num: TrackNum,
+            timestamp_ns: u64,
+            keyframe: bool,
+        },
+
+        /// [`SegmentBuilder::build_strict`] found that the `CodecPrivate` of a track disagrees with how the track was
+        /// added about `field`, which is one of `channels`, `sample_rate`, `width` and `height`. See
+        /// [`MuxWarning::CodecPrivateMismatch`].
+        CodecPrivateMismatch {
+            track_num: TrackNum,
+            field: &'static str,
+            track_value: u64,
+            private_value: u64,
+        },
+
+        /// The segment is WebM, which forbids the `element`, e.g. the `Attachments` that
+        /// [`SegmentBuilder::add_attachment`] would write, or the `FlagInterlaced` of interlaced video.
+        NotInWebm { element: &'static str },
     }
 
     impl std::fmt::Display for Error {
@@ -152,11 +455,76 @@ pub mod mux {
             match self {
                 Error::BadParam => f.write_str("Bad parameter"),
                 Error::Unknown => f.write_str("Unknown error"),
+                Error::Libwebm { message } => write!(f, "libwebm error: {message}"),
+                Error::UnknownTrack { track_num } => write!(f, "The segment has no track {track_num} of this kind"),
+                Error::ForeignTrack { track_num } => write!(f, "Track {track_num} belongs to a different segment"),
+                Error::Io(err) => write!(f, "I/O error: {err}"),
+                Error::Track { index, error } => write!(f, "Failed to add track {index}: {error}"),
+                Error::Frame { index, error } => write!(f, "Failed to write frame {index}: {error}"),
+                Error::UnorderedFrame {
+                    index,
+                    timestamp_ns,
+                    previous_ns,
+                } => write!(f, "Frame {index} at {timestamp_ns}ns comes after a frame at {previous_ns}ns"),
+                Error::Time(err) => write!(f, "Time error: {err}"),
+                Error::Spec { path, error } => write!(f, "Invalid {path}: {error}"),
+                Error::Repair(err) => write!(f, "Cannot resume the file: {err}"),
+                Error::ResumeMismatch { path } => write!(f, "The file does not match the spec's {path}"),
+                Error::DefaultTracks { track_nums } => {
+                    write!(f, "Tracks {track_nums:?} are all default tracks of the same kind")
+                },
+                Error::KeyframeOverdue {
+                    boundary_ns,
+                    timestamp_ns,
+                } => write!(f, "The frame at {timestamp_ns}ns is too late for the keyframe due at {boundary_ns}ns"),
+                Error::CodecDelayMismatch {
+                    track_num,
+                    codec_delay_ns,
+                    pre_skip_ns,
+                } => write!(
+                    f,
+                    "Track {track_num} has a CodecDelay of {codec_delay_ns}ns, but its OpusHead has a pre-skip of \
+                     {pre_skip_ns}ns"
+                ),
+                Error::KeyframeFlag {
+                    track_num,
+                    timestamp_ns,
+                    keyframe,
+                } => {
+                    let not = if *keyframe { "" } else { "not " };
+                    write!(f, "The frame at {timestamp_ns}ns on track {track_num} is {not}a keyframe, unlike its flag")
+                },
+                Error::CodecPrivateMismatch {
+                    track_num,
+                    field,
+                    track_value,
+                    private_value,
+                } => write!(
+                    f,
+                    "Track {track_num} has a {field} of {track_value}, but its CodecPrivate says {private_value}"
+                ),
+                Error::NotInWebm { element } => write!(f, "WebM files cannot contain {element}"),
+            }
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                Error::Io(err) => Some(err),
+                Error::Time(err) => Some(err),
+                Error::Repair(err) => Some(err),
+                Error::Track { error, .. } | Error::Frame { error, .. } | Error::Spec { error, .. } => Some(error),
+                _ => None,
             }
         }
     }
 
-    impl std::error::Error for Error {}
+    impl From<crate::time::TimeError> for Error {
+        fn from(err: crate::time::TimeError) -> Self {
+            Error::Time(err)
+        }
+    }
 
     /// A specification for how pixels in written video frames are subsampled in chroma channels.
     ///
@@ -167,6 +535,7 @@ pub mod mux {
     ///
     /// You may use [`ColorSubsampling::default()`] to get a specification of no subsampling in any dimension.
     #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
     pub struct ColorSubsampling {
         /// The subsampling factor for both chroma channels in the horizontal direction.
         pub chroma_horizontal: u8,
@@ -175,11 +544,28 @@ pub mod mux {
         pub chroma_vertical: u8,
     }
 
+    /// Where subsampled chroma samples sit in one direction, as written to `ChromaSitingHorz` or `ChromaSitingVert`.
+    /// 4:2:0 video from MPEG-2 and later codecs usually has its chroma collocated horizontally and halfway vertically.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    pub enum ChromaSiting {
+        /// No claim is made, and nothing is written.
+        #[default]
+        Unspecified = 0,
+
+        /// Collocated with the leftmost or topmost luma sample.
+        Collocated = 1,
+
+        /// Halfway between the luma samples.
+        Half = 2,
+    }
+
     /// A specification of how the range of colors in the input video frames has been clipped.
     ///
     /// Certain screens struggle with the full range of available colors, and video content is thus sometimes tuned to
     /// a restricted range.
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
     pub enum ColorRange {
         /// No claim is made as to how colors have been restricted.
         #[default]
@@ -191,4 +577,2557 @@ pub mod mux {
         /// No color clipping is performed.
         Full = 2,
     }
+
+    /// How the colors of a video track are encoded, as given to [`SegmentBuilder::set_color_preset`]: the color
+    /// primaries, transfer characteristics and matrix coefficients as ISO/IEC 23091-4 (ITU-T H.273) code points, along
+    /// with the range and chroma siting.
+    ///
+    /// The associated constants describe the common standards, with the range and siting that content following them
+    /// conventionally has. Other combinations can be made with [`ColorDescription::new`] or
+    /// [`ColorDescription::from_cicp`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct ColorDescription {
+        /// The color primaries, e.g. 1 for BT.709 or 9 for BT.2020.
+        pub primaries: u8,
+
+        /// The transfer characteristics, e.g. 1 for BT.709, 16 for SMPTE ST 2084 (PQ) or 18 for HLG.
+        pub transfer_characteristics: u8,
+
+        /// The matrix coefficients, e.g. 0 for RGB, 1 for BT.709 or 9 for BT.2020 non-constant luminance.
+        pub matrix_coefficients: u8,
+
+        /// Whether the values are restricted to broadcast range.
+        pub range: ColorRange,
+
+        /// How chroma samples are positioned horizontally, relative to the leftmost luma sample.
+        pub chroma_siting_horizontal: ChromaSiting,
+
+        /// How chroma samples are positioned vertically, relative to the topmost luma sample.
+        pub chroma_siting_vertical: ChromaSiting,
+    }
+
+    impl ColorDescription {
+        /// HD video per ITU-R BT.709, in broadcast range with MPEG-2 chroma siting.
+        pub const BT709: Self = Self::with_mpeg2_siting(1, 1, 1);
+
+        /// 625-line (PAL) SD video per ITU-R BT.601, in broadcast range with MPEG-2 chroma siting.
+        pub const BT601_625: Self = Self::with_mpeg2_siting(5, 6, 5);
+
+        /// 525-line (NTSC) SD video per ITU-R BT.601, in broadcast range with MPEG-2 chroma siting.
+        pub const BT601_525: Self = Self::with_mpeg2_siting(6, 6, 6);
+
+        /// HDR video per ITU-R BT.2100 with the PQ transfer function and non-constant luminance, in broadcast range
+        /// with MPEG-2 chroma siting.
+        pub const BT2020_NCL_PQ: Self = Self::with_mpeg2_siting(9, 16, 9);
+
+        /// HDR video per ITU-R BT.2100 with the HLG transfer function and non-constant luminance, in broadcast range
+        /// with MPEG-2 chroma siting.
+        pub const BT2020_NCL_HLG: Self = Self::with_mpeg2_siting(9, 18, 9);
+
+        /// RGB video per IEC 61966-2-1, in full range. There is no chroma to site.
+        pub const SRGB: Self = Self::from_cicp(1, 13, 0, true);
+
+        /// A description with the given primaries, transfer characteristics and matrix coefficients and unspecified
+        /// chroma siting, in full range if `full_range` and in broadcast range otherwise.
+        #[must_use]
+        pub const fn new(
+            primaries: ColourPrimaries,
+            transfer_characteristics: TransferCharacteristics,
+            matrix_coefficients: MatrixCoefficients,
+            full_range: bool,
+        ) -> Self {
+            Self::from_cicp(primaries as u8, transfer_characteristics as u8, matrix_coefficients as u8, full_range)
+        }
+
+        /// A description with the given code points and unspecified chroma siting, in full range if `full_range` and
+        /// in broadcast range otherwise.
+        #[must_use]
+        pub const fn from_cicp(
+            primaries: u8,
+            transfer_characteristics: u8,
+            matrix_coefficients: u8,
+            full_range: bool,
+        ) -> Self {
+            Self {
+                primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+                range: if full_range { ColorRange::Full } else { ColorRange::Broadcast },
+                chroma_siting_horizontal: ChromaSiting::Unspecified,
+                chroma_siting_vertical: ChromaSiting::Unspecified,
+            }
+        }
+
+        /// Broadcast-range YUV, with chroma collocated with the left luma sample and halfway between the rows, as
+        /// MPEG-2 and later codecs place it by default.
+        const fn with_mpeg2_siting(primaries: u8, transfer_characteristics: u8, matrix_coefficients: u8) -> Self {
+            Self {
+                chroma_siting_horizontal: ChromaSiting::Collocated,
+                chroma_siting_vertical: ChromaSiting::Half,
+                ..Self::from_cicp(primaries, transfer_characteristics, matrix_coefficients, false)
+            }
+        }
+    }
+
+    /// The color primaries of a video track, as written to its `Primaries` by
+    /// [`SegmentBuilder::set_color_description`]. The discriminants are the ISO/IEC 23091-4 code points that `libwebm`
+    /// can write, leaving out the reserved ones.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum ColourPrimaries {
+        /// ITU-R BT.709, also used by sRGB.
+        Bt709 = 1,
+        /// No claim is made, which [`SegmentBuilder::set_color_description`] writes as nothing at all.
+        #[default]
+        Unspecified = 2,
+        /// ITU-R BT.470 System M.
+        Bt470M = 4,
+        /// ITU-R BT.470 System B and G, and 625-line BT.601.
+        Bt470Bg = 5,
+        /// SMPTE 170M, and 525-line BT.601.
+        Smpte170M = 6,
+        /// SMPTE 240M.
+        Smpte240M = 7,
+        /// Generic film, with illuminant C.
+        Film = 8,
+        /// ITU-R BT.2020 and BT.2100.
+        Bt2020 = 9,
+        /// SMPTE ST 428-1 (CIE 1931 XYZ).
+        SmpteSt428 = 10,
+        /// EBU Tech. 3213-E (JEDEC P22 phosphors).
+        JedecP22 = 22,
+    }
+
+    /// The transfer characteristics of a video track, as written to its `TransferCharacteristics` by
+    /// [`SegmentBuilder::set_color_description`]. The discriminants are the ISO/IEC 23091-4 code points that
+    /// `libwebm` can write, leaving out the reserved ones.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum TransferCharacteristics {
+        /// ITU-R BT.709, and BT.601.
+        Bt709 = 1,
+        /// No claim is made, which [`SegmentBuilder::set_color_description`] writes as nothing at all.
+        #[default]
+        Unspecified = 2,
+        /// A gamma of 2.2, per ITU-R BT.470 System M.
+        Gamma22 = 4,
+        /// A gamma of 2.8, per ITU-R BT.470 System B and G.
+        Gamma28 = 5,
+        /// SMPTE 170M.
+        Smpte170M = 6,
+        /// SMPTE 240M.
+        Smpte240M = 7,
+        /// Linear light.
+        Linear = 8,
+        /// Logarithmic, over a range of 100:1.
+        Log = 9,
+        /// Logarithmic, over a range of 100 * sqrt(10):1.
+        LogSqrt = 10,
+        /// IEC 61966-2-4 (xvYCC).
+        Iec61966_2_4 = 11,
+        /// ITU-R BT.1361 extended colour gamut.
+        Bt1361 = 12,
+        /// IEC 61966-2-1 (sRGB).
+        Srgb = 13,
+        /// ITU-R BT.2020, for 10-bit video.
+        Bt2020TenBit = 14,
+        /// ITU-R BT.2020, for 12-bit video.
+        Bt2020TwelveBit = 15,
+        /// SMPTE ST 2084 (PQ), as used by HDR10.
+        Pq = 16,
+        /// SMPTE ST 428-1.
+        SmpteSt428 = 17,
+        /// ARIB STD-B67 (HLG).
+        Hlg = 18,
+    }
+
+    /// The matrix coefficients of a video track, as written to its `MatrixCoefficients` by
+    /// [`SegmentBuilder::set_color_description`]. The discriminants are the ISO/IEC 23091-4 code points that
+    /// `libwebm` can write, leaving out the reserved ones.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum MatrixCoefficients {
+        /// No matrix, for RGB (or GBR) video.
+        Identity = 0,
+        /// ITU-R BT.709.
+        Bt709 = 1,
+        /// No claim is made, which [`SegmentBuilder::set_color_description`] writes as nothing at all.
+        #[default]
+        Unspecified = 2,
+        /// US FCC 73.682.
+        Fcc = 4,
+        /// ITU-R BT.470 System B and G, and 625-line BT.601.
+        Bt470Bg = 5,
+        /// SMPTE 170M, and 525-line BT.601.
+        Smpte170M = 6,
+        /// SMPTE 240M.
+        Smpte240M = 7,
+        /// YCgCo.
+        YCgCo = 8,
+        /// ITU-R BT.2020 non-constant luminance.
+        Bt2020Ncl = 9,
+        /// ITU-R BT.2020 constant luminance.
+        Bt2020Cl = 10,
+    }
+
+    /// How the two views of stereoscopic 3D video are packed into its frames, as written to the track's `StereoMode`
+    /// by [`SegmentBuilder::set_stereo_mode`]. The discriminants are the Matroska values.
+    ///
+    /// WebM only allows [`Mono`](Self::Mono) and the side-by-side and top-bottom layouts; `libwebm` refuses the
+    /// others.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum StereoMode {
+        /// A single view, which is what a track without a `StereoMode` has.
+        #[default]
+        Mono = 0,
+        SideBySideLeftFirst = 1,
+        TopBottomRightFirst = 2,
+        TopBottomLeftFirst = 3,
+        CheckerboardRightFirst = 4,
+        CheckerboardLeftFirst = 5,
+        RowInterleavedRightFirst = 6,
+        RowInterleavedLeftFirst = 7,
+        ColumnInterleavedRightFirst = 8,
+        ColumnInterleavedLeftFirst = 9,
+        AnaglyphCyanRed = 10,
+        SideBySideRightFirst = 11,
+        AnaglyphGreenMagenta = 12,
+        /// Both views laced together in each block, the left one first.
+        LacedLeftFirst = 13,
+        /// Both views laced together in each block, the right one first.
+        LacedRightFirst = 14,
+    }
+
+    /// How players may resize a video track's frames, as written to its `AspectRatioType` by
+    /// [`SegmentBuilder::set_aspect_ratio_type`]. The discriminants are the Matroska values.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum AspectRatioType {
+        /// The frames may be stretched to any shape, which is what a track without an `AspectRatioType` allows.
+        #[default]
+        FreeResizing = 0,
+        /// The frames may be scaled, but keep the aspect ratio of their display size.
+        KeepAspectRatio = 1,
+        /// The frames are shown at their display size and not resized at all.
+        Fixed = 2,
+    }
+
+    /// Whether a video track is interlaced, as written to its `FlagInterlaced` by
+    /// [`SegmentBuilder::set_interlacing`]. The discriminants are the Matroska values.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum InterlaceMode {
+        /// No claim is made, which is what a track without a `FlagInterlaced` has.
+        #[default]
+        Undetermined = 0,
+        /// Each frame holds two interlaced fields.
+        Interlaced = 1,
+        /// Each frame is a whole picture.
+        Progressive = 2,
+    }
+
+    /// The order of the fields of an interlaced video track, as written to its `FieldOrder` by
+    /// [`SegmentBuilder::set_interlacing`]. The discriminants are the Matroska values.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    pub enum FieldOrder {
+        /// The frames aren't made of fields.
+        Progressive = 0,
+        TopFieldFirst = 1,
+        /// No claim is made, which is what a track without a `FieldOrder` has.
+        #[default]
+        Undetermined = 2,
+        BottomFieldFirst = 6,
+        /// The bottom field is shown first, but stored second.
+        BottomFieldFirstSwapped = 9,
+        /// The top field is shown first, but stored second.
+        TopFieldFirstSwapped = 14,
+    }
+
+    /// How a 360° or VR video track is projected, as written to its `Projection` element by
+    /// [`SegmentBuilder::set_projection`].
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct Projection {
+        /// The projection used. [`ProjectionType::Other`] can't be written.
+        pub projection_type: ProjectionType,
+
+        /// The `ProjectionPrivate` data, e.g. the body of the `equi` or `cbmp` box from the Spherical Video V2
+        /// specification for an equirectangular or cubemap projection. Empty if there is none.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub private_data: Vec<u8>,
+
+        /// Yaw rotation to apply to the projection, in degrees from -180 to 180.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub pose_yaw: f32,
+
+        /// Pitch rotation to apply to the projection, in degrees from -90 to 90.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub pose_pitch: f32,
+
+        /// Roll rotation to apply to the projection, in degrees from -180 to 180.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub pose_roll: f32,
+    }
+
+    impl Projection {
+        /// A projection of the given type, without private data or rotation.
+        #[must_use]
+        pub fn new(projection_type: ProjectionType) -> Self {
+            Self {
+                projection_type,
+                private_data: Vec::new(),
+                pose_yaw: 0.0,
+                pose_pitch: 0.0,
+                pose_roll: 0.0,
+            }
+        }
+    }
+
+    /// The flags of a track that tell players whether to pick it, as set by [`SegmentBuilder::set_track_flags`].
+    /// A flag that is `None` is left as it is.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    pub struct TrackFlags {
+        /// The `FlagDefault`, whether players should pick the track over other tracks of its kind when the user
+        /// hasn't asked for one. Matroska takes a track without the flag to be a default track.
+        pub default: Option<bool>,
+
+        /// The `FlagEnabled`, whether players may play the track at all. Matroska takes a track without the flag to
+        /// be enabled.
+        pub enabled: Option<bool>,
+
+        /// The `FlagForced`, whether players must play the track even if the user has turned its kind off, as for
+        /// subtitles translating foreign dialogue. Matroska takes a track without the flag not to be forced.
+        pub forced: Option<bool>,
+    }
+
+    /// What [`SegmentBuilder::verify_keyframe_flags`] does with a frame whose keyframe flag is wrong.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    #[non_exhaustive]
+    pub enum KeyframeFlagPolicy {
+        /// The frame fails with [`Error::KeyframeFlag`], and isn't written.
+        #[default]
+        Reject,
+
+        /// The frame is written with the flag its bitstream calls for.
+        Correct,
+    }
+
+    /// Something that a [`SegmentBuilder`] was given which `libwebm` writes without complaint, but which players may
+    /// trip over. See [`SegmentBuilder::warnings`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum MuxWarning {
+        /// The `CodecPrivate` of a track says that its `field` is `private_value`, but the track was added with
+        /// `track_value`. The fields compared are the `channels` of Opus and Vorbis tracks, their `sample_rate`, which
+        /// for Opus is the input sample rate that its `OpusHead` records unless that is unspecified, and the `width`
+        /// and `height` of AV1 tracks, which mismatch if they are larger than the maximum frame size of the sequence
+        /// header.
+        CodecPrivateMismatch {
+            track_num: TrackNum,
+            field: &'static str,
+            track_value: u64,
+            private_value: u64,
+        },
+    }
+
+    /// A track for [`mux_to_file`], [`SegmentBuilder::add_track`] or [`SegmentBuilder::apply_spec`] to add.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    pub struct TrackSpec {
+        /// The name that [`TrackHandles`] knows the track by. Only [`SegmentBuilder::apply_spec`] uses it, and
+        /// requires it to be unique and nonempty.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub name: String,
+
+        /// The kind of track, and its codec.
+        pub media: MediaSpec,
+
+        /// The track number to use. Defaults to the next free one.
+        pub number: Option<TrackNum>,
+
+        /// The track's `CodecPrivate` data, e.g. an Opus identification header. Empty if the codec needs none.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub codec_private: Vec<u8>,
+
+        /// The human-readable title of the track, written as its `Name`.
+        pub title: Option<String>,
+
+        /// The `Language` of the track, as an ISO 639-2 code such as `eng`.
+        pub language: Option<String>,
+
+        /// The built-in delay of the codec, in nanoseconds, e.g. an Opus stream's pre-skip. Zero if there is none, or
+        /// for an Opus track, to have it derived from the pre-skip of its `OpusHead`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub codec_delay_ns: u64,
+
+        /// How far before a seek target decoding must start for correct output, in nanoseconds. Zero if it needn't.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub seek_pre_roll_ns: u64,
+
+        /// The color information of a video track. Audio tracks can't have any.
+        pub color: Option<ColorSpec>,
+    }
+
+    impl TrackSpec {
+        /// A video track without `CodecPrivate` data or other settings.
+        #[must_use]
+        pub fn video(width: u32, height: u32, codec: VideoCodecId) -> Self {
+            Self::new(MediaSpec::Video { width, height, codec })
+        }
+
+        /// An audio track without `CodecPrivate` data or other settings.
+        #[must_use]
+        pub fn audio(sample_rate: u32, channels: u32, codec: AudioCodecId) -> Self {
+            Self::new(MediaSpec::Audio {
+                sample_rate,
+                channels,
+                codec,
+            })
+        }
+
+        fn new(media: MediaSpec) -> Self {
+            Self {
+                name: String::new(),
+                media,
+                number: None,
+                codec_private: Vec::new(),
+                title: None,
+                language: None,
+                codec_delay_ns: 0,
+                seek_pre_roll_ns: 0,
+                color: None,
+            }
+        }
+
+        /// Sets the [`name`](Self::name) of the track.
+        #[must_use]
+        pub fn named(mut self, name: impl Into<String>) -> Self {
+            self.name = name.into();
+            self
+        }
+    }
+
+    /// The color information of a video [`TrackSpec`], as given to [`SegmentBuilder::set_color`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    pub struct ColorSpec {
+        /// The number of bits per color channel, e.g. 8 or 10.
+        pub bit_depth: u8,
+        pub subsampling: ColorSubsampling,
+        pub range: ColorRange,
+    }
+
+    /// The kind of a [`TrackSpec`], with the parameters of its [`SegmentBuilder`] method.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)
+    )]
+    pub enum MediaSpec {
+        Video { width: u32, height: u32, codec: VideoCodecId },
+        Audio { sample_rate: u32, channels: u32, codec: AudioCodecId },
+    }
+
+    /// A frame for [`mux_to_file`] to write.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FrameSpec {
+        /// The index of the frame's track in the tracks given to [`mux_to_file`].
+        pub track: usize,
+
+        /// The encoded frame.
+        pub data: Vec<u8>,
+
+        /// When the frame is presented, in nanoseconds.
+        pub timestamp_ns: u64,
+
+        pub keyframe: bool,
+    }
+
+    /// Options for [`mux_to_file_with_options`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct MuxFileOptions {
+        /// Whether frames are sorted by timestamp before they are written. Otherwise, a frame that is earlier than the
+        /// one before it fails with [`Error::UnorderedFrame`]. Sorting holds every frame in memory until the last one
+        /// is given. Defaults to false.
+        pub sort_frames: bool,
+    }
+
+    /// When a [`RotatingMuxer`] moves on to a new file. By default it never does.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct RotationPolicy {
+        /// How long a file may run for, in nanoseconds from its first frame's timestamp.
+        pub max_duration_ns: Option<u64>,
+
+        /// How many bytes of frame data a file may hold. The container's own overhead isn't counted.
+        pub max_bytes: Option<u64>,
+
+        /// How long past a limit to wait for a keyframe to start the next file with, in nanoseconds, before starting
+        /// it with whatever frame comes next. Such a file can't be played from its start until its first keyframe.
+        /// Defaults to waiting for as long as it takes.
+        pub max_keyframe_wait_ns: Option<u64>,
+
+        /// Whether each file keeps the timestamps the frames were given with, instead of starting from zero.
+        pub keep_absolute_timestamps: bool,
+    }
+
+    /// A video track for [`SegmentBuilder::add_video_track_with`] to add, with its settings.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct VideoTrackConfig {
+        /// The codec of the track.
+        pub codec: VideoCodecId,
+
+        /// The width of the frames, in pixels.
+        pub width: u32,
+
+        /// The height of the frames, in pixels.
+        pub height: u32,
+
+        /// The track number to use. Defaults to the next free one.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub track_num: Option<TrackNum>,
+
+        /// The track's `CodecPrivate` data, e.g. an `AV1CodecConfigurationRecord`. Empty if the codec needs none.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub codec_private: Vec<u8>,
+
+        /// How the colors are encoded, as set by [`SegmentBuilder::set_color_preset`]. Defaults to leaving it out.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub color: Option<ColorDescription>,
+
+        /// The bits per color channel. If this is set, it is written along with `chroma_subsampling` and
+        /// `color_range`, as [`SegmentBuilder::set_color`] writes them; otherwise none of the three are.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub bit_depth: Option<u8>,
+
+        /// The chroma subsampling written with `bit_depth`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub chroma_subsampling: ColorSubsampling,
+
+        /// The color range written with `bit_depth`. Left unspecified, the range of `color` is kept if there is one.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub color_range: ColorRange,
+
+        /// The human-readable `Name` of the track, e.g. `Camera 2`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub name: Option<String>,
+
+        /// The `Language` of the track, as an ISO 639-2 code such as `eng`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub language: Option<String>,
+
+        /// Whether the track is a default track, which players pick over tracks without the flag. Defaults to true,
+        /// which Matroska takes a track without a `FlagDefault` to be.
+        #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+        pub default: bool,
+    }
+
+    impl VideoTrackConfig {
+        /// A default track of the given codec and size, without any other settings.
+        #[must_use]
+        pub fn new(codec: VideoCodecId, width: u32, height: u32) -> Self {
+            Self {
+                codec,
+                width,
+                height,
+                track_num: None,
+                codec_private: Vec::new(),
+                color: None,
+                bit_depth: None,
+                chroma_subsampling: ColorSubsampling::default(),
+                color_range: ColorRange::default(),
+                name: None,
+                language: None,
+                default: true,
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn default_true() -> bool {
+        true
+    }
+
+    /// An audio track for [`SegmentBuilder::add_audio_track_with`] or [`SegmentBuilder::add_audio_tracks`] to add,
+    /// with its settings.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct AudioTrackConfig {
+        /// The codec of the track.
+        pub codec: AudioCodecId,
+
+        /// The sample rate, in Hz.
+        pub sample_rate: u32,
+
+        /// The number of channels.
+        pub channels: u32,
+
+        /// The track number to use. Defaults to the next free one.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub track_num: Option<TrackNum>,
+
+        /// The track's `CodecPrivate` data, e.g. an Opus identification header. Empty if the codec needs none.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub codec_private: Vec<u8>,
+
+        /// The human-readable `Name` of the track, e.g. `English (dubbed)`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub name: Option<String>,
+
+        /// The `Language` of the track, as an ISO 639-2 code such as `eng`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub language: Option<String>,
+
+        /// The built-in delay of the codec, in nanoseconds, e.g. an Opus stream's pre-skip. Zero if there is none, or
+        /// for an Opus track, to have it derived from the pre-skip of its `OpusHead`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub codec_delay_ns: u64,
+
+        /// How far before a seek target decoding must start for correct output, in nanoseconds. Zero if it needn't.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub seek_pre_roll_ns: u64,
+
+        /// The number of bits per sample, as written to the `BitDepth`. Usually only given for PCM audio.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub bit_depth: Option<u32>,
+
+        /// Whether the track is a default track, which players pick over tracks without the flag. Defaults to true,
+        /// which Matroska takes a track without a `FlagDefault` to be.
+        #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+        pub default: bool,
+    }
+
+    impl AudioTrackConfig {
+        /// A default track of the given codec, sample rate and channel count, without any other settings.
+        #[must_use]
+        pub fn new(codec: AudioCodecId, sample_rate: u32, channels: u32) -> Self {
+            Self {
+                codec,
+                sample_rate,
+                channels,
+                track_num: None,
+                codec_private: Vec::new(),
+                name: None,
+                language: None,
+                codec_delay_ns: 0,
+                seek_pre_roll_ns: 0,
+                bit_depth: None,
+                default: true,
+            }
+        }
+    }
+
+    /// Options for [`SimpleVideoWriter`] and [`SimpleAudioWriter`].
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct SimpleWriterOptions {
+        /// The track's `CodecPrivate` data. Empty (the default) if the codec needs none.
+        pub codec_private: Vec<u8>,
+
+        /// The name of the writing application, written as the `WritingApp`. Defaults to none.
+        pub writing_app: Option<String>,
+    }
+
+    /// The AES cipher mode of an encrypted track, written as its `AESSettingsCipherMode`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    #[non_exhaustive]
+    pub enum AesCipherMode {
+        /// Counter mode, which WebM encryption and the `cenc` scheme of Common Encryption use.
+        #[default]
+        Ctr,
+    }
+
+    impl AesCipherMode {
+        fn get_id(self) -> u32 {
+            match self {
+                AesCipherMode::Ctr => ffi::mux::AES_CTR_CIPHER_MODE,
+            }
+        }
+    }
+
+    /// How the frames of a track are encrypted, for [`SegmentBuilder::add_content_encryption`]. WebM only allows whole
+    /// frames encrypted with AES, so only the cipher mode can be chosen.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct EncryptionSettings {
+        /// The AES cipher mode. Defaults to [`AesCipherMode::Ctr`].
+        pub cipher_mode: AesCipherMode,
+    }
+
+    /// A whole output, for [`SegmentBuilder::apply_spec`] to set up in one call: its segment information, tracks,
+    /// chapters and tags.
+    ///
+    /// Anything the spec gets wrong fails with [`Error::Spec`], naming the offending field, e.g. `tracks[1].color`.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct MuxSpec {
+        /// The name of the writing application, written as the `WritingApp`. Defaults to none.
+        pub writing_app: Option<String>,
+
+        /// How many nanoseconds each timestamp unit in the file represents. Defaults to 1 000 000, i.e. milliseconds.
+        pub timecode_scale: Option<u64>,
+
+        /// Whether the file is written for live streaming: without cues, and without going back to fill in its size
+        /// or duration. Defaults to false.
+        pub live: bool,
+
+        /// The tracks to add, in order.
+        pub tracks: Vec<TrackSpec>,
+
+        /// The name of the track to write cue points for. Defaults to the first video track.
+        pub cues_track: Option<String>,
+
+        /// The chapters, in order.
+        pub chapters: Vec<ChapterSpec>,
+
+        /// The tags, each targeting the whole segment. libwebm only has room to index both chapters and tags in
+        /// live files, so other files with chapters can't have tags, including the one added by
+        /// [`SegmentBuilder::anchor_wall_clock`].
+        pub tags: Vec<TagSpec>,
+    }
+
+    /// A chapter of a [`MuxSpec`], with one title.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct ChapterSpec {
+        /// The chapter's `ChapterStringUID`, as used by WebVTT cue identifiers. Defaults to its 1-based position
+        /// among the chapters.
+        pub id: Option<String>,
+
+        /// When the chapter starts, in nanoseconds.
+        pub start_ns: u64,
+
+        /// When the chapter ends, in nanoseconds. This must not be before the start.
+        pub end_ns: u64,
+
+        pub title: String,
+
+        /// The language of the title, as an ISO 639-2 code. Defaults to `eng`.
+        pub language: Option<String>,
+    }
+
+    impl ChapterSpec {
+        /// A chapter with the given title and times, without an ID or language.
+        #[must_use]
+        pub fn new(start_ns: u64, end_ns: u64, title: impl Into<String>) -> Self {
+            Self {
+                id: None,
+                start_ns,
+                end_ns,
+                title: title.into(),
+                language: None,
+            }
+        }
+    }
+
+    /// A tag of a [`MuxSpec`], which applies to the whole file.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct TagSpec {
+        /// The tag's name/value pairs, e.g. `("TITLE", "My Movie")`. There must be at least one.
+        pub simple_tags: Vec<(String, String)>,
+    }
+
+    impl TagSpec {
+        /// A tag with the given name/value pairs.
+        #[must_use]
+        pub fn new<N: Into<String>, V: Into<String>>(simple_tags: impl IntoIterator<Item = (N, V)>) -> Self {
+            Self {
+                simple_tags: simple_tags.into_iter().map(|(name, value)| (name.into(), value.into())).collect(),
+            }
+        }
+    }
+
+    /// What [`Segment::resume`] found in the file it resumes.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct ResumeInfo {
+        /// The handles of the resumed segment's tracks, as [`SegmentBuilder::apply_spec`] returns them.
+        pub tracks: TrackHandles,
+
+        /// The end of the last frame kept, in nanoseconds. New frames should continue from here.
+        pub end_ns: u64,
+
+        /// How many bytes at the end of the file were dropped, because they held a partly written block or cluster.
+        pub truncated_bytes: u64,
+    }
+
+    /// The tracks added by [`SegmentBuilder::apply_spec`], by their [`TrackSpec::name`]s.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct TrackHandles {
+        tracks: Vec<(String, TrackHandle)>,
+    }
+
+    impl TrackHandles {
+        /// The number of the track with the given name.
+        #[must_use]
+        pub fn track_num(&self, name: &str) -> Option<TrackNum> {
+            self.find(name).map(TrackNum::from)
+        }
+
+        /// The track with the given name, of whichever kind.
+        #[must_use]
+        pub fn track(&self, name: &str) -> Option<TrackHandle> {
+            self.find(name)
+        }
+
+        /// The video track with the given name, or `None` if there is no such track or it isn't a video track.
+        #[must_use]
+        pub fn video(&self, name: &str) -> Option<VideoTrack> {
+            self.find(name)?.as_video()
+        }
+
+        /// The audio track with the given name, or `None` if there is no such track or it isn't an audio track.
+        #[must_use]
+        pub fn audio(&self, name: &str) -> Option<AudioTrack> {
+            self.find(name)?.as_audio()
+        }
+
+        /// The names of the tracks, in the order they were added.
+        pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+            self.tracks.iter().map(|(name, _)| name.as_str())
+        }
+
+        #[must_use]
+        pub fn len(&self) -> usize {
+            self.tracks.len()
+        }
+
+        #[must_use]
+        pub fn is_empty(&self) -> bool {
+            self.tracks.is_empty()
+        }
+
+        fn find(&self, name: &str) -> Option<TrackHandle> {
+            self.tracks.iter().find(|(track_name, _)| track_name == name).map(|&(_, track)| track)
+        }
+    }
+
+    /// What [`mux_to_file`] wrote.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct MuxSummary {
+        /// The size of the file.
+        pub bytes_written: u64,
+
+        /// The `Duration` written to the file, in nanoseconds before it was rounded to milliseconds.
+        pub duration_ns: u64,
+
+        /// How many frames were written to each track, in the order the tracks were given.
+        pub frames_per_track: Vec<u64>,
+    }
+}
+
+pub mod demux {
+    mod demuxer;
+    pub(crate) mod ebml;
+    mod limits;
+    mod reader;
+    mod streaming;
+
+    pub use demuxer::{BorrowedFrames, Demuxer, Frames};
+    #[cfg(feature = "bytes")]
+    pub use demuxer::BytesFrames;
+    pub use reader::MkvRead;
+    pub use streaming::StreamingDemuxer;
+
+    use crate::mux::{ColorSubsampling, TrackNum};
+
+    /// A single frame read from a WebM file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct DemuxFrame {
+        /// The number of the track this frame belongs to.
+        pub track_num: TrackNum,
+
+        /// The presentation timestamp of this frame, in nanoseconds.
+        ///
+        /// Only the first frame of a laced block has a stored timestamp. Those of the others are interpolated from
+        /// the track's [`default_duration_ns`](DemuxTrackInfo::default_duration_ns) or, failing that, the block's
+        /// duration split evenly among its frames. If neither is known, all frames of the block share its timestamp.
+        pub timestamp_ns: u64,
+
+        /// Whether this frame is marked as a keyframe.
+        pub is_keyframe: bool,
+
+        /// The duration of this frame's block, from its `BlockDuration`, split evenly among its frames if it is
+        /// laced. This is only ever present for blocks stored in a `BlockGroup`; otherwise, fall back to the track's
+        /// [`default_duration_ns`](DemuxTrackInfo::default_duration_ns).
+        pub duration_ns: Option<u64>,
+
+        /// Whether this frame was packed into one block with others, using Xiph, EBML or fixed-size lacing.
+        pub laced: bool,
+
+        /// The index of this frame within its block, which is 0 unless it is [`laced`](Self::laced).
+        pub lace_index: usize,
+
+        /// The encoded frame data.
+        pub data: Vec<u8>,
+
+        /// The `BlockAdditional` payloads of this frame's block, along with their `BlockAddID`s, in the order they
+        /// are stored. For VP8/VP9 with an alpha channel, the encoded alpha plane has ID 1. Empty for most frames.
+        /// For laced blocks, these are given with every frame.
+        pub additions: Vec<(u64, Vec<u8>)>,
+
+        /// The `DiscardPadding` of this frame's block: how much audio to drop from the end of the decoded frame (or
+        /// from the start, if negative), in nanoseconds. `None` if the block doesn't specify it, which is distinct
+        /// from an explicit zero. For laced blocks, this is only given with the last frame (or the first, if
+        /// negative), as that is what it applies to.
+        pub discard_padding_ns: Option<i64>,
+    }
+
+    /// A frame read by [`Demuxer::borrowed_frames`], whose payload and block additions borrow from the slice the file
+    /// is read from rather than being copied out of it. The fields are as in [`DemuxFrame`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct DemuxFrameRef<'a> {
+        pub track_num: TrackNum,
+        pub timestamp_ns: u64,
+        pub is_keyframe: bool,
+        pub duration_ns: Option<u64>,
+        pub laced: bool,
+        pub lace_index: usize,
+        pub data: &'a [u8],
+        pub additions: Vec<(u64, &'a [u8])>,
+        pub discard_padding_ns: Option<i64>,
+    }
+
+    impl DemuxFrameRef<'_> {
+        /// Copies the borrowed data into an owned [`DemuxFrame`].
+        #[must_use]
+        pub fn into_owned(self) -> DemuxFrame {
+            DemuxFrame {
+                track_num: self.track_num,
+                timestamp_ns: self.timestamp_ns,
+                is_keyframe: self.is_keyframe,
+                duration_ns: self.duration_ns,
+                laced: self.laced,
+                lace_index: self.lace_index,
+                data: self.data.to_vec(),
+                additions: self.additions.into_iter().map(|(id, data)| (id, data.to_vec())).collect(),
+                discard_padding_ns: self.discard_padding_ns,
+            }
+        }
+    }
+
+    /// A frame read by [`Demuxer::bytes_frames`], whose payload and block additions share the [`bytes::Bytes`] the
+    /// file is read from rather than being copied out of it. The fields are as in [`DemuxFrame`].
+    #[cfg(feature = "bytes")]
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct DemuxFrameBytes {
+        pub track_num: TrackNum,
+        pub timestamp_ns: u64,
+        pub is_keyframe: bool,
+        pub duration_ns: Option<u64>,
+        pub laced: bool,
+        pub lace_index: usize,
+        pub data: bytes::Bytes,
+        pub additions: Vec<(u64, bytes::Bytes)>,
+        pub discard_padding_ns: Option<i64>,
+    }
+
+    #[cfg(feature = "bytes")]
+    impl From<DemuxFrameBytes> for DemuxFrame {
+        fn from(frame: DemuxFrameBytes) -> Self {
+            DemuxFrame {
+                track_num: frame.track_num,
+                timestamp_ns: frame.timestamp_ns,
+                is_keyframe: frame.is_keyframe,
+                duration_ns: frame.duration_ns,
+                laced: frame.laced,
+                lace_index: frame.lace_index,
+                data: frame.data.into(),
+                additions: frame.additions.into_iter().map(|(id, data)| (id, data.into())).collect(),
+                discard_padding_ns: frame.discard_padding_ns,
+            }
+        }
+    }
+
+    /// Something read by a [`StreamingDemuxer`], in file order.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum DemuxEvent {
+        /// The file headers have been read, so [`StreamingDemuxer::tracks`] and
+        /// [`StreamingDemuxer::segment_info`] are now available. This is always the first event.
+        TrackInfoReady,
+
+        /// A new cluster starts, with the given timestamp in nanoseconds. The frames that follow belong to it.
+        ClusterStart(u64),
+
+        /// A frame was read.
+        Frame(DemuxFrame),
+
+        /// The end of the segment was reached. This is always the last event.
+        EndOfSegment,
+    }
+
+    /// Where a [`Demuxer::seek`] landed.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct SeekPoint {
+        /// The timestamp of the keyframe the demuxer is now positioned at, in nanoseconds.
+        pub timestamp_ns: u64,
+
+        /// The absolute byte offset of the `Cluster` element containing that keyframe.
+        pub cluster_offset: u64,
+
+        /// Whether the file lacked usable cues, so that the keyframe had to be found by scanning the file from the
+        /// start. This is much slower for large files, but otherwise gives the same result.
+        pub linear_scan: bool,
+    }
+
+    /// A cue point for one track, as listed by [`Demuxer::cue_index`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct CueEntry {
+        /// The timestamp of the cue point, in nanoseconds.
+        pub timestamp_ns: u64,
+
+        pub track_num: TrackNum,
+
+        /// The absolute byte offset of the `Cluster` element that the cue point refers to.
+        pub cluster_offset: u64,
+    }
+
+    /// A keyframe found by [`Demuxer::keyframe_index`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct KeyframeEntry {
+        /// The timestamp of the keyframe, in nanoseconds.
+        pub timestamp_ns: u64,
+
+        pub track_num: TrackNum,
+
+        /// The absolute byte offset of the `Cluster` element containing the keyframe.
+        pub cluster_offset: u64,
+    }
+
+    /// How [`Demuxer::compute_duration`] works out the duration of a file, from cheapest to most accurate.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum DurationStrategy {
+        /// The timestamp of the last cue point, plus the default duration of its track. This only reads the `Cues`
+        /// element, but falls short of the true duration by however much follows the last cued keyframe.
+        FromCues,
+
+        /// The end of the last frame in the last cluster. The last cluster is found via the `SeekHead` if it lists
+        /// clusters, and otherwise by scanning backwards from the end of the file, so only its blocks are read.
+        FromLastCluster,
+
+        /// The end of the last frame, found by reading the block headers of the whole file.
+        FullScan,
+    }
+
+    /// Information about a whole WebM file, as read from its `Info` element.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct SegmentInfo {
+        /// How many nanoseconds each timestamp unit in the file represents.
+        pub timecode_scale: u64,
+
+        /// The duration of the file, if specified.
+        pub duration_ns: Option<u64>,
+
+        /// The name of the library that wrote the file, e.g. `libwebm-0.3.0.0`.
+        pub muxing_app: Option<String>,
+
+        /// The name of the application that wrote the file.
+        pub writing_app: Option<String>,
+
+        /// The title of the file, if specified.
+        pub title: Option<String>,
+
+        /// When the file was created, if specified.
+        pub date_utc: Option<std::time::SystemTime>,
+
+        /// The random 128-bit `SegmentUID` identifying this file, if specified.
+        pub segment_uid: Option<[u8; 16]>,
+    }
+
+    /// The contents of a file's EBML header, which says what kind of file it is.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct EbmlHeaderInfo {
+        /// The `EBMLVersion` the file was written with.
+        pub version: u64,
+
+        /// The `EBMLReadVersion`: the minimum EBML version needed to read the file.
+        pub read_version: u64,
+
+        /// The `EBMLMaxIDLength`: the longest element ID in the file, in bytes.
+        pub max_id_length: u64,
+
+        /// The `EBMLMaxSizeLength`: the longest element size in the file, in bytes.
+        pub max_size_length: u64,
+
+        /// The `DocType`, as stored. This is `webm` for WebM files and `matroska` for other Matroska files, but
+        /// anything that parses is accepted.
+        pub doc_type: String,
+
+        /// The `DocTypeVersion`: the version of the DocType specification the file was written with.
+        pub doc_type_version: u64,
+
+        /// The `DocTypeReadVersion`: the minimum version of the DocType specification needed to read the file.
+        pub doc_type_read_version: u64,
+    }
+
+    /// A chapter read from a WebM file's `Chapters` element.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct ChapterInfo {
+        /// The chapter's `ChapterUID`.
+        pub uid: u64,
+
+        /// The chapter's `ChapterStringUID`, used by WebVTT cue identifiers.
+        pub string_uid: Option<String>,
+
+        /// When the chapter starts, in nanoseconds.
+        pub start_ns: u64,
+
+        /// When the chapter ends, in nanoseconds, if specified.
+        pub end_ns: Option<u64>,
+
+        /// Whether the chapter should be hidden from the user.
+        pub hidden: bool,
+
+        /// Whether the chapter is enabled.
+        pub enabled: bool,
+
+        /// The chapter's titles, usually one per language.
+        pub displays: Vec<ChapterDisplay>,
+
+        /// Nested sub-chapters, in the order they are stored.
+        pub children: Vec<ChapterInfo>,
+    }
+
+    /// A title of a chapter, from a `ChapterDisplay` element.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct ChapterDisplay {
+        /// The title itself.
+        pub string: String,
+
+        /// The languages of the title, as ISO 639-2 codes. Defaults to `eng` if none are given.
+        pub languages: Vec<String>,
+
+        /// The countries the title applies to, as ISO 3166-1 codes. May be empty.
+        pub countries: Vec<String>,
+    }
+
+    /// A tag read from a WebM file's `Tags` element.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct TagInfo {
+        /// What the tag applies to. Empty if it applies to the whole file.
+        pub targets: Vec<TagTarget>,
+
+        /// The logical level of the target, e.g. 50 for an album or movie and 30 for a track or chapter.
+        pub target_type_value: u64,
+
+        /// The tag's name/value pairs, in the order they are stored.
+        pub simple_tags: Vec<SimpleTagInfo>,
+    }
+
+    /// Something a [`TagInfo`] applies to, identified by its UID.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    #[non_exhaustive]
+    pub enum TagTarget {
+        /// A track, by its [`DemuxTrackInfo::uid`] (not its track number).
+        Track(u64),
+        Edition(u64),
+        /// A chapter, by its [`ChapterInfo::uid`].
+        Chapter(u64),
+        /// An attachment, by its [`AttachmentInfo::uid`].
+        Attachment(u64),
+    }
+
+    /// A single name/value pair of a tag, from a `SimpleTag` element.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct SimpleTagInfo {
+        /// The name of the tag, e.g. `TITLE` or `ENCODER`.
+        pub name: String,
+
+        /// The value of the tag.
+        pub value: TagValue,
+
+        /// The language of the value, as an ISO 639-2 code. Defaults to `und`.
+        pub language: String,
+
+        /// Whether this is the default value for the tag, among its translations.
+        pub default: bool,
+
+        /// Nested tags that further describe this one.
+        pub children: Vec<SimpleTagInfo>,
+    }
+
+    /// The value of a [`SimpleTagInfo`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    pub enum TagValue {
+        String(String),
+        Binary(Vec<u8>),
+
+        /// The tag has no value, which is common for tags that only group nested tags.
+        Empty,
+    }
+
+    /// A file attached to a Matroska file, such as a font or cover art, from an `AttachedFile` element. Its data is
+    /// read separately, with [`Demuxer::read_attachment`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct AttachmentInfo {
+        /// The file name of the attachment.
+        pub name: String,
+
+        /// The media type of the attachment, e.g. `image/jpeg` or `font/ttf`.
+        pub mime: String,
+
+        /// The attachment's `FileUID`, which identifies it to [`Demuxer::read_attachment`] and [`TagTarget`].
+        pub uid: u64,
+
+        /// The size of the attachment's data, in bytes.
+        pub size: u64,
+
+        /// A human-readable description of the attachment, if any.
+        pub description: Option<String>,
+    }
+
+    /// Information about a track in a WebM file, as read from its `TrackEntry` element.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct DemuxTrackInfo {
+        /// The number used to identify this track's frames.
+        pub track_num: TrackNum,
+
+        /// The track's `TrackUID`.
+        pub uid: u64,
+
+        /// What kind of track this is, along with type-specific information.
+        pub track_type: TrackType,
+
+        /// The Matroska codec ID string, e.g. `V_VP9` or `A_OPUS`.
+        pub codec_id: String,
+
+        /// The `CodecPrivate` data, or an empty vector if there is none.
+        pub codec_private: Vec<u8>,
+
+        /// The nominal duration of each frame, if specified.
+        pub default_duration_ns: Option<u64>,
+
+        /// The built-in delay of the codec, in nanoseconds.
+        pub codec_delay_ns: u64,
+
+        /// How far before a seek target decoding must start for correct output, in nanoseconds.
+        pub seek_pre_roll_ns: u64,
+
+        /// The highest `BlockAddID` that frames of this track may carry in their additions, or 0 if there are none.
+        pub max_block_addition_id: u64,
+
+        /// The human-readable `Name` of the track, if specified.
+        pub name: Option<String>,
+
+        /// The `Language` of the track, as an ISO 639-2 code such as `eng` or `jpn`, if specified. Matroska's default
+        /// when it isn't is `eng`, unless [`language_bcp47`](Self::language_bcp47) is present.
+        pub language: Option<String>,
+
+        /// The `LanguageIETF` of the track, as a BCP 47 tag such as `en-US`, if specified. Players prefer it over
+        /// [`language`](Self::language) when both are present.
+        pub language_bcp47: Option<String>,
+
+        /// Whether [`name`](Self::name), [`language`](Self::language) or [`language_bcp47`](Self::language_bcp47)
+        /// was not valid UTF-8, in which case the invalid parts were replaced with U+FFFD.
+        pub invalid_utf8: bool,
+    }
+
+    /// The kind of a track, along with any information specific to it.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+    #[non_exhaustive]
+    pub enum TrackType {
+        Video(VideoTrackInfo),
+        Audio(AudioTrackInfo),
+
+        /// A track type this crate does not interpret (e.g. subtitles), with its raw `TrackType` value.
+        Other(i64),
+    }
+
+    /// Information specific to video tracks.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct VideoTrackInfo {
+        /// Width of the encoded frames, in pixels.
+        pub width: u32,
+
+        /// Height of the encoded frames, in pixels.
+        pub height: u32,
+
+        /// Width to display the frames at, from the track's `DisplayWidth`, or [`width`](Self::width) if it has
+        /// none. This is in pixels unless the track's `DisplayUnit` says otherwise.
+        pub display_width: u32,
+
+        /// Height to display the frames at, from the track's `DisplayHeight`, or [`height`](Self::height) if it has
+        /// none.
+        pub display_height: u32,
+
+        /// The contents of the track's `Colour` element, if it has one.
+        pub colour: Option<Box<ColourInfo>>,
+
+        /// The contents of the track's `Projection` element, if it has one.
+        pub projection: Option<ProjectionInfo>,
+    }
+
+    /// How a 360° or VR video track is projected, as read from its `Projection` element.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct ProjectionInfo {
+        /// The projection used.
+        pub projection_type: ProjectionType,
+
+        /// The projection-specific `ProjectionPrivate` data, or an empty vector if there is none. For
+        /// equirectangular and cubemap projections, this is the body of the corresponding box from the Spherical Video
+        /// V2 specification.
+        pub private_data: Vec<u8>,
+
+        /// Yaw rotation to apply to the projection, in degrees.
+        pub pose_yaw: Option<f32>,
+
+        /// Pitch rotation to apply to the projection, in degrees.
+        pub pose_pitch: Option<f32>,
+
+        /// Roll rotation to apply to the projection, in degrees.
+        pub pose_roll: Option<f32>,
+    }
+
+    /// The kind of projection used by a video track.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    #[non_exhaustive]
+    pub enum ProjectionType {
+        Rectangular,
+        Equirectangular,
+        CubeMap,
+        Mesh,
+
+        /// A projection type this crate does not interpret, with its raw `ProjectionType` value.
+        Other(i64),
+    }
+
+    /// Color information for a video track, as read from its `Colour` element.
+    ///
+    /// Each field is `None` if the corresponding element is absent. Enumerated values (matrix coefficients, transfer
+    /// characteristics, primaries, range and chroma siting) are the raw numbers from the file, as defined by the
+    /// Matroska specification.
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct ColourInfo {
+        /// Bits per channel of the encoded video.
+        pub bits_per_channel: Option<u8>,
+
+        /// The chroma subsampling factors. A dimension missing from the file is reported as 0.
+        pub chroma_subsampling: Option<ColorSubsampling>,
+
+        /// Additional subsampling of the Cb channel relative to the other chroma channel, e.g. for 4:1:0 video.
+        pub cb_subsampling: Option<ColorSubsampling>,
+
+        /// How chroma samples are positioned horizontally: 0 = unspecified, 1 = left collocated, 2 = half.
+        pub chroma_siting_horz: Option<u64>,
+
+        /// How chroma samples are positioned vertically: 0 = unspecified, 1 = top collocated, 2 = half.
+        pub chroma_siting_vert: Option<u64>,
+
+        /// The color range: 0 = unspecified, 1 = broadcast, 2 = full, 3 = defined by the matrix coefficients and
+        /// transfer characteristics. Values 0 to 2 correspond to
+        /// [`ColorRange`](crate::mux::ColorRange).
+        pub range: Option<u64>,
+
+        /// The matrix coefficients, per ISO/IEC 23091-4, e.g. 1 for BT.709 or 9 for BT.2020 non-constant luminance.
+        pub matrix_coefficients: Option<u64>,
+
+        /// The transfer characteristics, per ISO/IEC 23091-4, e.g. 16 for SMPTE ST 2084 (PQ) or 18 for HLG.
+        pub transfer_characteristics: Option<u64>,
+
+        /// The color primaries, per ISO/IEC 23091-4, e.g. 1 for BT.709 or 9 for BT.2020.
+        pub primaries: Option<u64>,
+
+        /// Maximum content light level, in cd/m².
+        pub max_cll: Option<u64>,
+
+        /// Maximum frame-average light level, in cd/m².
+        pub max_fall: Option<u64>,
+
+        /// SMPTE 2086 mastering display metadata.
+        pub mastering_metadata: Option<MasteringMetadata>,
+    }
+
+    /// SMPTE 2086 mastering display metadata. Each field is `None` if the corresponding element is absent, or is to be
+    /// left out by [`SegmentBuilder::set_mastering_metadata`](crate::mux::SegmentBuilder::set_mastering_metadata).
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct MasteringMetadata {
+        /// CIE 1931 chromaticity of the red primary.
+        pub primary_r: Option<Chromaticity>,
+
+        /// CIE 1931 chromaticity of the green primary.
+        pub primary_g: Option<Chromaticity>,
+
+        /// CIE 1931 chromaticity of the blue primary.
+        pub primary_b: Option<Chromaticity>,
+
+        /// CIE 1931 chromaticity of the white point.
+        pub white_point: Option<Chromaticity>,
+
+        /// Maximum luminance, in cd/m².
+        pub luminance_max: Option<f32>,
+
+        /// Minimum luminance, in cd/m².
+        pub luminance_min: Option<f32>,
+    }
+
+    /// A CIE 1931 xy chromaticity coordinate.
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Chromaticity {
+        pub x: f32,
+        pub y: f32,
+    }
+
+    /// Information specific to audio tracks.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct AudioTrackInfo {
+        /// Sampling frequency, in Hz.
+        pub sample_rate: f64,
+
+        /// Number of audio channels.
+        pub channels: u32,
+
+        /// Bits per sample, if specified. Usually only present for PCM audio.
+        pub bit_depth: Option<u32>,
+    }
+
+    /// Options for [`Demuxer::with_options`] and [`StreamingDemuxer::with_options`].
+    ///
+    /// The `max_*` limits guard against files that declare absurd sizes in order to exhaust memory, as may be sent by
+    /// anyone uploading a file. Sizes are checked as declared, before anything is allocated for them, and exceeding a
+    /// limit fails with [`DemuxError::LimitExceeded`]. The defaults are far above what real files need; use
+    /// [`DemuxOptions::unlimited`] to turn them off for trusted input.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct DemuxOptions {
+        /// Whether to skip over damaged clusters rather than failing, as happens with recordings that were cut short
+        /// by a crash. The frames that parse before the damage are still returned, and each skip is recorded in
+        /// [`Demuxer::warnings`]. Defaults to false.
+        pub lenient: bool,
+
+        /// The largest `CodecPrivate` of a track, in bytes. Defaults to 4 MiB.
+        pub max_codec_private: usize,
+
+        /// The largest frame or block addition, in bytes. Defaults to 64 MiB.
+        pub max_frame_size: usize,
+
+        /// The largest of the top-level elements that are read into memory whole (the EBML header, `SeekHead`,
+        /// `Info`, `Tracks`, `Chapters` and `Tags`), in bytes. Defaults to 16 MiB.
+        pub max_header_element: usize,
+
+        /// The most data held in memory at once, in bytes. This bounds the data a [`StreamingDemuxer`] keeps for the
+        /// current cluster, as well as any single read `libwebm` makes. Defaults to 256 MiB.
+        pub max_total_buffered: usize,
+
+        /// The largest attachment that [`Demuxer::read_attachment`] reads, in bytes. Defaults to 64 MiB.
+        pub max_attachment_size: usize,
+    }
+
+    impl DemuxOptions {
+        /// Options without any memory limits, for input that is trusted.
+        #[must_use]
+        pub fn unlimited() -> Self {
+            Self {
+                max_codec_private: usize::MAX,
+                max_frame_size: usize::MAX,
+                max_header_element: usize::MAX,
+                max_total_buffered: usize::MAX,
+                max_attachment_size: usize::MAX,
+                ..Self::default()
+            }
+        }
+    }
+
+    impl Default for DemuxOptions {
+        fn default() -> Self {
+            Self {
+                lenient: false,
+                max_codec_private: 4 << 20,
+                max_frame_size: 64 << 20,
+                max_header_element: 16 << 20,
+                max_total_buffered: 256 << 20,
+                max_attachment_size: 64 << 20,
+            }
+        }
+    }
+
+    /// A problem with a file that a lenient [`Demuxer`] worked around. See [`DemuxOptions::lenient`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum DemuxWarning {
+        /// The cluster starting at `from_offset` was damaged, so the demuxer skipped ahead to the next intact cluster,
+        /// at `to_offset`. If there was no such cluster, `to_offset` is the end of the file.
+        ResyncSkipped { from_offset: u64, to_offset: u64 },
+    }
+
+    /// The error type for reading WebM files.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum DemuxError {
+        /// Reading from the underlying source failed.
+        Io(std::io::Error),
+
+        /// The data is not a valid WebM file, or uses features that libwebm cannot parse.
+        InvalidFile,
+
+        /// An parameter with an invalid value was passed to a method.
+        BadParam,
+
+        /// An unknown error occurred inside libwebm.
+        Unknown,
+
+        /// The file declares something larger than the corresponding limit in [`DemuxOptions`]. `what` names it, and
+        /// `requested` is its declared size in bytes.
+        LimitExceeded {
+            what: &'static str,
+            requested: u64,
+            limit: usize,
+        },
+    }
+
+    impl std::fmt::Display for DemuxError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                DemuxError::Io(err) => write!(f, "I/O error: {err}"),
+                DemuxError::InvalidFile => f.write_str("Invalid or unsupported WebM file"),
+                DemuxError::BadParam => f.write_str("Bad parameter"),
+                DemuxError::Unknown => f.write_str("Unknown error"),
+                DemuxError::LimitExceeded {
+                    what,
+                    requested,
+                    limit,
+                } => write!(f, "{what} of {requested} bytes exceeds the limit of {limit} bytes"),
+            }
+        }
+    }
+
+    impl std::error::Error for DemuxError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                DemuxError::Io(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+}
+
+pub mod remux {
+    mod concat;
+    mod extract;
+    mod pipe;
+    mod tracks;
+
+    pub use concat::concatenate;
+    pub use extract::{extract_range, extract_track};
+    pub use pipe::pipe_frames;
+    pub use tracks::clone_tracks;
+
+    pub(crate) use tracks::{mux_codec, Codec};
+
+    use crate::demux::DemuxError;
+    use crate::mux::{AudioTrack, Error, Segment, TrackNum, VideoTrack, Writer};
+    use std::collections::BTreeMap;
+
+    /// The timecode scale of remuxed files, which is `libwebm`'s default.
+    const TIMECODE_SCALE_NS: u64 = 1_000_000;
+
+    /// Finalizes `segment` with the given `Duration`, rounded to the timecode scale.
+    fn finalize<W: std::io::Write>(segment: Segment<W>, duration_ns: u64) -> Result<Writer<W>, Error> {
+        let duration = (duration_ns + TIMECODE_SCALE_NS / 2) / TIMECODE_SCALE_NS;
+        segment.finalize((duration > 0).then_some(duration)).map_err(|_| Error::Unknown)
+    }
+
+    /// A track created by [`clone_tracks`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum ClonedTrack {
+        Video(VideoTrack),
+        Audio(AudioTrack),
+    }
+
+    impl From<ClonedTrack> for TrackNum {
+        fn from(track: ClonedTrack) -> Self {
+            match track {
+                ClonedTrack::Video(track) => track.into(),
+                ClonedTrack::Audio(track) => track.into(),
+            }
+        }
+    }
+
+    /// Maps the track numbers of a [`Demuxer`](crate::demux::Demuxer)'s tracks to the tracks [`clone_tracks`] created
+    /// for them.
+    #[derive(Debug, Default)]
+    pub struct TrackMap {
+        tracks: BTreeMap<TrackNum, ClonedTrack>,
+        skipped: Vec<RemuxError>,
+    }
+
+    impl TrackMap {
+        /// The track created for the source track with the given number, or `None` if it was skipped.
+        #[must_use]
+        pub fn get(&self, source_track: TrackNum) -> Option<ClonedTrack> {
+            self.tracks.get(&source_track).copied()
+        }
+
+        /// All cloned tracks, as (source track number, new track) pairs ordered by source track number.
+        pub fn iter(&self) -> impl Iterator<Item = (TrackNum, ClonedTrack)> + '_ {
+            self.tracks.iter().map(|(&source, &track)| (source, track))
+        }
+
+        /// Why each source track that could not be cloned was skipped. Frames of these tracks should be dropped, or
+        /// the remux abandoned if every track is needed.
+        #[must_use]
+        pub fn skipped(&self) -> &[RemuxError] {
+            &self.skipped
+        }
+    }
+
+    /// The error type for remuxing.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum RemuxError {
+        /// The source track uses a codec that the muxer cannot write.
+        UnsupportedCodec { track_num: TrackNum, codec_id: String },
+
+        /// The source track is neither a video nor an audio track.
+        UnsupportedTrackType { track_num: TrackNum },
+
+        /// Creating the new track failed.
+        Mux(Error),
+    }
+
+    impl std::fmt::Display for RemuxError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                RemuxError::UnsupportedCodec { track_num, codec_id } => {
+                    write!(f, "Track {track_num} uses unsupported codec {codec_id}")
+                },
+                RemuxError::UnsupportedTrackType { track_num } => {
+                    write!(f, "Track {track_num} is not a video or audio track")
+                },
+                RemuxError::Mux(err) => write!(f, "Muxing error: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for RemuxError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                RemuxError::Mux(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    /// Options for [`concatenate`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[non_exhaustive]
+    pub struct ConcatOptions {
+        /// How the tracks of each input are matched up with those of the first input.
+        pub track_matching: TrackMatching,
+
+        /// What to do when a matched track has different `CodecPrivate` data than in the first input.
+        pub codec_private_mismatch: CodecPrivateMismatch,
+
+        /// Whether the first frame of each track in every input after the first must be a keyframe, so that playback
+        /// across each join doesn't depend on frames of the previous input.
+        pub require_keyframe_joins: bool,
+    }
+
+    /// How [`concatenate`] matches the tracks of each input with those of the first input.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum TrackMatching {
+        /// Tracks are matched by their position in the file, and must have the same codec.
+        #[default]
+        ByIndex,
+
+        /// Each track is matched with the first track of the same codec that hasn't been matched yet.
+        ByCodec,
+    }
+
+    /// What [`concatenate`] does when a matched track has different `CodecPrivate` data than in the first input.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum CodecPrivateMismatch {
+        /// Fail with [`ConcatError::CodecPrivateMismatch`].
+        #[default]
+        Fail,
+
+        /// Keep the first input's `CodecPrivate` data. Only do this if the codec tolerates it, e.g. for Opus inputs
+        /// that differ only in their pre-skip.
+        KeepFirst,
+    }
+
+    /// The error type for [`concatenate`]. Inputs are numbered from 0, in the order they were given.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum ConcatError {
+        /// No inputs were given.
+        NoInputs,
+
+        /// Reading an input failed.
+        Demux { input: usize, error: DemuxError },
+
+        /// An input has no track matching the given track of the first input.
+        MissingTrack { input: usize, track_num: TrackNum },
+
+        /// A track of an input has different `CodecPrivate` data than the matching track of the first input.
+        CodecPrivateMismatch { input: usize, track_num: TrackNum },
+
+        /// A track of an input does not start with a keyframe, but [`ConcatOptions::require_keyframe_joins`] is set.
+        NoKeyframeAtJoin { input: usize, track_num: TrackNum },
+
+        /// Creating the output tracks failed.
+        Remux(RemuxError),
+
+        /// Writing the output failed.
+        Mux(Error),
+    }
+
+    impl std::fmt::Display for ConcatError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ConcatError::NoInputs => f.write_str("No inputs to concatenate"),
+                ConcatError::Demux { input, error } => write!(f, "Failed to read input {input}: {error}"),
+                ConcatError::MissingTrack { input, track_num } => {
+                    write!(f, "Input {input} has no track matching track {track_num} of the first input")
+                },
+                ConcatError::CodecPrivateMismatch { input, track_num } => {
+                    write!(f, "Track {track_num} of input {input} has mismatched codec private data")
+                },
+                ConcatError::NoKeyframeAtJoin { input, track_num } => {
+                    write!(f, "Track {track_num} of input {input} does not start with a keyframe")
+                },
+                ConcatError::Remux(err) => err.fmt(f),
+                ConcatError::Mux(err) => write!(f, "Muxing error: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for ConcatError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                ConcatError::Demux { error, .. } => Some(error),
+                ConcatError::Remux(err) => Some(err),
+                ConcatError::Mux(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    /// Options for [`extract_range`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct ExtractOptions {
+        /// How much earlier than the start of the range audio frames are copied from, so that decoders have converged
+        /// by the time the range starts. `None` uses each audio track's `SeekPreRoll`.
+        pub audio_pre_roll_ns: Option<u64>,
+
+        /// Whether audio frames that run past the end of the range get a `DiscardPadding` that trims them to it. This
+        /// needs the frame duration, so only frames with a `BlockDuration` or a track `DefaultDuration` are trimmed.
+        pub trim_audio_end: bool,
+    }
+
+    impl Default for ExtractOptions {
+        fn default() -> Self {
+            Self {
+                audio_pre_roll_ns: None,
+                trim_audio_end: true,
+            }
+        }
+    }
+
+    /// The error type for [`extract_range`] and [`extract_track`].
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum ExtractError {
+        /// The end of the range is not after its start.
+        BadRange,
+
+        /// The input has no frames in the range, or no tracks that can be copied.
+        NoFrames,
+
+        /// The input has no track with this number.
+        MissingTrack { track_num: TrackNum },
+
+        /// Reading the input failed.
+        Demux(DemuxError),
+
+        /// Creating the output tracks failed.
+        Remux(RemuxError),
+
+        /// Writing the output failed.
+        Mux(Error),
+    }
+
+    impl std::fmt::Display for ExtractError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ExtractError::BadRange => f.write_str("The end of the range is not after its start"),
+                ExtractError::NoFrames => f.write_str("No frames to extract"),
+                ExtractError::MissingTrack { track_num } => write!(f, "The input has no track {track_num}"),
+                ExtractError::Demux(err) => write!(f, "Failed to read input: {err}"),
+                ExtractError::Remux(err) => err.fmt(f),
+                ExtractError::Mux(err) => write!(f, "Muxing error: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for ExtractError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                ExtractError::Demux(err) => Some(err),
+                ExtractError::Remux(err) => Some(err),
+                ExtractError::Mux(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    /// What [`pipe_frames`] should do with a frame, as decided by its filter.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FrameAction {
+        /// Copy the frame unchanged.
+        Keep,
+
+        /// Leave the frame out.
+        Drop,
+
+        /// Copy the frame with a new timestamp (in nanoseconds) and keyframe flag.
+        Rewrite { timestamp_ns: u64, keyframe: bool },
+    }
+
+    /// The error type for [`pipe_frames`].
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum PipeError {
+        /// Reading a frame failed.
+        Demux(DemuxError),
+
+        /// Writing a frame failed, for instance because its timestamp is earlier than one already written.
+        Mux(Error),
+    }
+
+    impl std::fmt::Display for PipeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                PipeError::Demux(err) => write!(f, "Failed to read input: {err}"),
+                PipeError::Mux(err) => write!(f, "Muxing error: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for PipeError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                PipeError::Demux(err) => Some(err),
+                PipeError::Mux(err) => Some(err),
+            }
+        }
+    }
+}
+
+pub mod repair {
+    mod finalize;
+
+    pub use finalize::{finalize_in_place, repair_to};
+
+    pub(crate) use finalize::resumable;
+
+    use crate::demux::DemuxError;
+
+    /// What [`finalize_in_place`] or [`repair_to`] found and wrote.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct RepairReport {
+        /// The duration written to the `Info`, in nanoseconds: the end of the last frame that was kept.
+        pub duration_ns: u64,
+
+        /// How many clusters were kept.
+        pub clusters: u64,
+
+        /// How many cue points were written to the new `Cues`.
+        pub cue_points: u64,
+
+        /// How many bytes at the end of the source were dropped, because they held a partly written block or cluster.
+        pub truncated_bytes: u64,
+
+        /// The length of the repaired file. After [`finalize_in_place`], anything after this is covered by a `Void`
+        /// element, and the file can be truncated to this length.
+        pub file_len: u64,
+    }
+
+    /// The error type for repairing files.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum RepairError {
+        /// The file's `Segment` has a known size, so it was already finalized.
+        AlreadyFinalized,
+
+        /// The file is not a WebM file, or was cut off before its `Tracks` were complete.
+        InvalidFile,
+
+        /// The file has no complete frames to keep.
+        NoFrames,
+
+        /// There is no room in the file for `element` to be written in place. [`repair_to`] can repair it into a copy.
+        NoRoom { element: &'static str },
+
+        /// Reading the clusters failed.
+        Demux(DemuxError),
+
+        /// Reading or writing the file failed.
+        Io(std::io::Error),
+    }
+
+    impl std::fmt::Display for RepairError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                RepairError::AlreadyFinalized => f.write_str("The file is already finalized"),
+                RepairError::InvalidFile => f.write_str("Not a WebM file, or cut off before its tracks"),
+                RepairError::NoFrames => f.write_str("The file has no complete frames"),
+                RepairError::NoRoom { element } => write!(f, "No room in the file to write the {element} element"),
+                RepairError::Demux(err) => write!(f, "Failed to read the file: {err}"),
+                RepairError::Io(err) => write!(f, "I/O error: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for RepairError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                RepairError::Demux(err) => Some(err),
+                RepairError::Io(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Adapters between this crate and other media formats and frameworks. Those that depend on another crate are each
+/// behind a feature of their own.
+pub mod interop {
+    #[cfg(feature = "av-format")]
+    mod av;
+
+    /// Reading the IVF files that VP8, VP9 and AV1 encoders write, and remuxing them to WebM.
+    pub mod ivf;
+
+    #[cfg(feature = "av-format")]
+    pub use av::AvWebmMuxer;
+
+    /// The error type of [`AvWebmMuxer`]. `av-format`'s own error type has no room for details, so these are passed
+    /// on in its `Io` variant, and can be taken out of the [`std::io::Error`] with `get_ref` and `downcast_ref`.
+    #[cfg(feature = "av-format")]
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum AvMuxError {
+        /// The stream at index `stream` is of a codec WebM can't hold. Only VP8, VP9, AV1, Opus and Vorbis are
+        /// supported, under their rust-av names `vp8`, `vp9`, `av1`, `opus` and `vorbis`.
+        UnsupportedCodec { stream: usize, codec_id: Option<String> },
+
+        /// The stream at index `stream` lacks a parameter the track needs, such as its media kind or channel map.
+        MissingParams { stream: usize, param: &'static str },
+
+        /// A packet belongs to a stream that isn't in the global info.
+        UnknownStream(isize),
+
+        /// A packet of the stream at index `stream` has no presentation timestamp, or one that can't be converted
+        /// to nanoseconds: negative, or in a timebase that doesn't fit in 32 bits.
+        BadTimestamp { stream: usize },
+
+        /// An option the muxer doesn't have, or a value of the wrong kind for it.
+        BadOption(String),
+
+        /// A method was called out of order, e.g. a packet was written before the header.
+        OutOfOrder(&'static str),
+
+        /// Muxing failed.
+        Mux(crate::mux::Error),
+    }
+
+    #[cfg(feature = "av-format")]
+    impl std::fmt::Display for AvMuxError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                AvMuxError::UnsupportedCodec { stream, codec_id } => match codec_id {
+                    Some(codec_id) => write!(f, "Stream {stream} has codec {codec_id:?}, which WebM can't hold"),
+                    None => write!(f, "Stream {stream} has no codec ID"),
+                },
+                AvMuxError::MissingParams { stream, param } => write!(f, "Stream {stream} has no {param}"),
+                AvMuxError::UnknownStream(stream) => write!(f, "There is no stream {stream}"),
+                AvMuxError::BadTimestamp { stream } => {
+                    write!(f, "A packet of stream {stream} has an invalid timestamp")
+                },
+                AvMuxError::BadOption(key) => write!(f, "Invalid option {key:?}"),
+                AvMuxError::OutOfOrder(message) => f.write_str(message),
+                AvMuxError::Mux(err) => write!(f, "Muxing failed: {err}"),
+            }
+        }
+    }
+
+    #[cfg(feature = "av-format")]
+    impl std::error::Error for AvMuxError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                AvMuxError::Mux(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(feature = "av-format")]
+    impl From<crate::mux::Error> for AvMuxError {
+        fn from(err: crate::mux::Error) -> Self {
+            AvMuxError::Mux(err)
+        }
+    }
+}
+
+pub mod validate {
+    mod sync;
+    mod validator;
+
+    pub use validator::validate;
+
+    pub(crate) use sync::SyncTracker;
+
+    use crate::mux::TrackNum;
+
+    /// Options for [`validate`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct ValidationOptions {
+        /// Whether a DocType of `matroska` is accepted as well as `webm`. Defaults to false.
+        pub allow_matroska: bool,
+
+        /// The longest gap between the end of a frame and the start of the next on the same track before it is
+        /// reported, in nanoseconds. Frames without a known duration are taken to end where they start. Defaults to 1s.
+        pub max_gap_ns: u64,
+
+        /// How far a frame may start before the end of the previous frame on the same track before it is reported, in
+        /// nanoseconds. Defaults to 1ms, to allow for rounding to the timecode scale.
+        pub max_overlap_ns: u64,
+
+        /// How far the `Duration` may be from the end of the last frame before it is reported, in nanoseconds.
+        /// Defaults to 1s.
+        pub duration_tolerance_ns: u64,
+    }
+
+    impl Default for ValidationOptions {
+        fn default() -> Self {
+            Self {
+                allow_matroska: false,
+                max_gap_ns: 1_000_000_000,
+                max_overlap_ns: 1_000_000,
+                duration_tolerance_ns: 1_000_000_000,
+            }
+        }
+    }
+
+    /// How serious a [`Finding`] is.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum Severity {
+        /// The file plays, but not as well as it could (e.g. seeking is slow or imprecise).
+        Warning,
+        /// The file is not valid WebM, or will likely fail to play correctly.
+        Error,
+    }
+
+    /// The rule that a [`Finding`] is about.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[non_exhaustive]
+    pub enum Rule {
+        /// The file's elements are malformed or missing, so that part of it could not be checked.
+        Structure,
+        /// The DocType in the EBML header.
+        DocType,
+        /// Each track's codec must be one allowed in WebM.
+        Codec,
+        /// An Opus track's `CodecDelay` should match the pre-skip of its `OpusHead`.
+        CodecDelay,
+        /// Cluster timecodes must not decrease.
+        ClusterTimecodes,
+        /// Frame timestamps must not decrease within a track.
+        Timestamps,
+        /// Cue points must point at clusters.
+        Cues,
+        /// Each video track's first frame in a cluster should be a keyframe.
+        ClusterKeyframes,
+        /// Frames on a track should follow each other without large gaps or overlaps.
+        Continuity,
+        /// The `Duration` should match the frames.
+        Duration,
+    }
+
+    /// A problem found by [`validate`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Finding {
+        pub severity: Severity,
+        pub rule: Rule,
+
+        /// The position of the element that the problem is in, if it is specific to one.
+        pub offset: Option<u64>,
+
+        pub message: String,
+    }
+
+    impl std::fmt::Display for Finding {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let severity = match self.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            match self.offset {
+                Some(offset) => write!(f, "{severity} at byte {offset}: {}", self.message),
+                None => write!(f, "{severity}: {}", self.message),
+            }
+        }
+    }
+
+    /// Everything [`validate`] found wrong with a file, in the order it was found.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ValidationReport {
+        pub findings: Vec<Finding>,
+    }
+
+    impl ValidationReport {
+        /// Whether there are no errors. There may still be warnings.
+        #[must_use]
+        pub fn is_valid(&self) -> bool {
+            self.errors().next().is_none()
+        }
+
+        pub fn errors(&self) -> impl Iterator<Item = &Finding> + '_ {
+            self.findings.iter().filter(|finding| finding.severity == Severity::Error)
+        }
+
+        pub fn warnings(&self) -> impl Iterator<Item = &Finding> + '_ {
+            self.findings.iter().filter(|finding| finding.severity == Severity::Warning)
+        }
+    }
+
+    /// The thresholds of a [`SyncReport`], beyond which a pair of tracks is out of sync.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+    #[non_exhaustive]
+    pub struct SyncOptions {
+        /// How far two tracks may drift apart before they are out of sync, in nanoseconds. Defaults to 45ms, about
+        /// where viewers start to notice audio running ahead of video.
+        pub max_skew_ns: u64,
+
+        /// How fast two tracks may drift apart before they are out of sync, in parts per million of the elapsed time.
+        /// Defaults to 100, or 0.36s an hour.
+        pub max_trend_ppm: f64,
+    }
+
+    impl Default for SyncOptions {
+        fn default() -> Self {
+            Self {
+                max_skew_ns: 45_000_000,
+                max_trend_ppm: 100.0,
+            }
+        }
+    }
+
+    /// How the timestamps of each track compare to the durations of its frames, and how far pairs of tracks drift
+    /// apart as a result.
+    ///
+    /// Each frame lasts its nominal duration: its block's duration if it has one, or else its track's default
+    /// duration, or failing both, the gap between the first two frames of its track. A track's drift is how far the
+    /// timestamps have run ahead of the accumulated nominal durations since its first frame. A track whose timestamps
+    /// come from a clock that runs fast of its media, as with an audio device that is slower than the wall clock,
+    /// drifts steadily.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SyncReport {
+        /// Each track with frames, by track number.
+        pub tracks: Vec<TrackSync>,
+
+        /// Each pair of tracks with frames, by track numbers.
+        pub pairs: Vec<PairSync>,
+    }
+
+    impl SyncReport {
+        /// Whether no pair of tracks is out of sync.
+        #[must_use]
+        pub fn is_in_sync(&self) -> bool {
+            self.pairs.iter().all(|pair| !pair.out_of_sync)
+        }
+    }
+
+    impl std::fmt::Display for SyncReport {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            for (index, pair) in self.pairs.iter().enumerate() {
+                if index > 0 {
+                    f.write_str("\n")?;
+                }
+                write!(f, "{pair}")?;
+            }
+            Ok(())
+        }
+    }
+
+    /// The drift of one track in a [`SyncReport`].
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct TrackSync {
+        pub track_num: TrackNum,
+        pub frames: u64,
+
+        /// The timestamps of the first and last frames, in nanoseconds.
+        pub start_ns: u64,
+        pub end_ns: u64,
+
+        /// The nominal durations of all frames but the last, added up, in nanoseconds.
+        pub media_ns: u64,
+
+        /// How far the last frame's timestamp is past where the nominal durations put it, in nanoseconds. Negative if
+        /// it is before.
+        pub drift_ns: i64,
+
+        /// The drift furthest from zero of any frame, in nanoseconds.
+        pub max_drift_ns: i64,
+
+        /// How fast the drift grows, in parts per million of the elapsed time, fitted over all frames.
+        pub trend_ppm: f64,
+    }
+
+    /// How far apart two tracks of a [`SyncReport`] drift.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[non_exhaustive]
+    pub struct PairSync {
+        /// The track numbers, the lower first.
+        pub tracks: (TrackNum, TrackNum),
+
+        /// The drift of the first track less that of the second, as of the last frame of either, in nanoseconds.
+        pub skew_ns: i64,
+
+        /// The skew furthest from zero at any frame of either track, in nanoseconds.
+        pub max_skew_ns: i64,
+
+        /// How fast the skew grows, in parts per million of the elapsed time.
+        pub trend_ppm: f64,
+
+        /// Whether the skew or its trend is beyond the [`SyncOptions`].
+        pub out_of_sync: bool,
+    }
+
+    impl std::fmt::Display for PairSync {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let (first, second) = self.tracks;
+            write!(
+                f,
+                "Tracks {first} and {second}: skew {:.1}ms (at most {:.1}ms), trend {:.0}ppm",
+                self.skew_ns as f64 / 1e6,
+                self.max_skew_ns as f64 / 1e6,
+                self.trend_ppm
+            )?;
+            if self.out_of_sync {
+                f.write_str(", out of sync")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub mod inspect {
+    mod layout;
+
+    pub use layout::summarize;
+
+    /// Where each element of a file is, as [`summarize`] found it. Its `Display` is an indented dump of the elements
+    /// in the style of `mkvinfo`.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct LayoutSummary {
+        /// The length of the file, in bytes.
+        pub file_len: u64,
+
+        /// The top-level elements, which are the EBML header and the `Segment` in a WebM file.
+        pub elements: Vec<LayoutElement>,
+    }
+
+    impl LayoutSummary {
+        /// Every element, each before its children, in the order they are in the file.
+        pub fn iter(&self) -> impl Iterator<Item = &LayoutElement> + '_ {
+            let mut stack: Vec<&LayoutElement> = self.elements.iter().rev().collect();
+            std::iter::from_fn(move || {
+                let element = stack.pop()?;
+                stack.extend(element.children.iter().rev());
+                Some(element)
+            })
+        }
+
+        /// The clusters, in the order they are in the file.
+        pub fn clusters(&self) -> impl Iterator<Item = &LayoutElement> + '_ {
+            self.iter().filter(|element| matches!(element.detail, Some(ElementDetail::Cluster { .. })))
+        }
+    }
+
+    /// An element of a [`LayoutSummary`]. Only the `Segment` and its `Tracks` list their children; the children of
+    /// other elements are summarized in [`LayoutElement::detail`] instead.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct LayoutElement {
+        /// The element ID, including its length marker bits, e.g. `0x1F43B675` for a `Cluster`.
+        pub id: u32,
+
+        /// The position of the element's header in the file.
+        pub offset: u64,
+
+        /// The length of the element's ID and size.
+        pub header_size: u64,
+
+        /// The length of the element's payload. An element whose size is unknown, as in a file that was written live,
+        /// is taken to extend to the end of its parent.
+        pub size: u64,
+
+        pub detail: Option<ElementDetail>,
+        pub children: Vec<LayoutElement>,
+    }
+
+    impl LayoutElement {
+        /// The element's name in the Matroska specification, e.g. `Cluster`, or `None` for an element that isn't
+        /// used in WebM.
+        #[must_use]
+        pub fn name(&self) -> Option<&'static str> {
+            layout::element_name(self.id)
+        }
+
+        /// The position just past the end of the element.
+        #[must_use]
+        pub fn end(&self) -> u64 {
+            self.offset + self.header_size + self.size
+        }
+    }
+
+    /// What a [`LayoutElement`] holds, for the elements whose contents matter to where things are in the file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+    #[non_exhaustive]
+    pub enum ElementDetail {
+        /// The EBML header, with its `DocType`.
+        EbmlHeader { doc_type: String },
+
+        /// The `SeekHead`, with how many elements it points at.
+        SeekHead { entries: u64 },
+
+        /// The `Info`, with the `TimecodeScale` and the `Duration` converted to nanoseconds, if there is one.
+        Info {
+            timecode_scale: u64,
+            duration_ns: Option<u64>,
+        },
+
+        /// A `TrackEntry`, with its track number and codec.
+        Track { track_num: u64, codec_id: String },
+
+        /// A `Cluster`, with its `Timecode` in units of the `TimecodeScale`, and how many `SimpleBlock` and
+        /// `BlockGroup` elements it holds.
+        Cluster { timecode: u64, blocks: u64 },
+
+        /// The `Cues`, with how many cue points it holds.
+        Cues { cue_points: u64 },
+    }
+}
+
+pub mod time {
+    mod convert;
+    mod mapper;
+
+    pub use convert::{ns_to_ticks, ns_to_ticks_rounded, ticks_to_ns, ticks_to_ns_rounded};
+    pub use mapper::TimestampMapper;
+
+    pub(crate) use convert::{from_unix_ns, unix_ns};
+
+    /// The name of the tag [`SegmentBuilder::anchor_wall_clock`](crate::mux::SegmentBuilder::anchor_wall_clock)
+    /// writes for the whole file: the UTC time of media time zero, in nanoseconds from the Unix epoch as a decimal
+    /// number.
+    pub const TIMECODE_OFFSET_TAG: &str = "TIMECODE_OFFSET_NS";
+
+    /// The length of a tick of some clock, as a fraction of a second: `num / den` seconds.
+    ///
+    /// Codecs and transports count time in their own ticks, such as Opus's 48 kHz samples or RTP's 90 kHz clock,
+    /// while this crate takes nanoseconds. Both fields must be nonzero for conversions to succeed.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Timebase {
+        pub num: u32,
+        pub den: u32,
+    }
+
+    impl Timebase {
+        /// Nanoseconds, in which this crate takes timestamps.
+        pub const NANOSECONDS: Self = Self::new(1, 1_000_000_000);
+        /// Microseconds.
+        pub const MICROSECONDS: Self = Self::new(1, 1_000_000);
+        /// Milliseconds, the default timecode scale of WebM files.
+        pub const MILLISECONDS: Self = Self::new(1, 1000);
+        /// Samples at 48 kHz, in which Opus counts its pre-skip and granule positions.
+        pub const OPUS: Self = Self::new(1, 48_000);
+        /// Samples at 44.1 kHz.
+        pub const HZ_44100: Self = Self::new(1, 44_100);
+        /// The 90 kHz clock of RTP video and MPEG transport streams.
+        pub const RTP_VIDEO: Self = Self::new(1, 90_000);
+        /// Frames at 24 fps.
+        pub const FPS_24: Self = Self::new(1, 24);
+        /// Frames at 25 fps.
+        pub const FPS_25: Self = Self::new(1, 25);
+        /// Frames at 30 fps.
+        pub const FPS_30: Self = Self::new(1, 30);
+        /// Frames at 60 fps.
+        pub const FPS_60: Self = Self::new(1, 60);
+        /// Frames at the NTSC film rate of 24000/1001 (about 23.976) fps.
+        pub const NTSC_FILM: Self = Self::new(1001, 24_000);
+        /// Frames at the NTSC rate of 30000/1001 (about 29.97) fps.
+        pub const NTSC: Self = Self::new(1001, 30_000);
+        /// Frames at 60000/1001 (about 59.94) fps.
+        pub const NTSC_60: Self = Self::new(1001, 60_000);
+
+        /// A timebase of `num / den` seconds per tick.
+        #[must_use]
+        pub const fn new(num: u32, den: u32) -> Self {
+            Self { num, den }
+        }
+
+        /// The timebase of a clock running at `hz` ticks per second.
+        #[must_use]
+        pub const fn hz(hz: u32) -> Self {
+            Self::new(1, hz)
+        }
+    }
+
+    /// How a conversion rounds a result that falls between two whole units.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub enum Rounding {
+        /// Toward zero.
+        Down,
+
+        /// To the nearest unit, with halves rounded up.
+        #[default]
+        Nearest,
+
+        /// Away from zero.
+        Up,
+    }
+
+    /// A point in time counted in ticks of a [`Timebase`], as accepted by
+    /// [`Segment::add_frame_at`](crate::mux::Segment::add_frame_at).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Timestamp {
+        pub ticks: u64,
+        pub timebase: Timebase,
+    }
+
+    impl Timestamp {
+        /// A timestamp in nanoseconds.
+        #[must_use]
+        pub const fn from_ns(ns: u64) -> Self {
+            Self {
+                ticks: ns,
+                timebase: Timebase::NANOSECONDS,
+            }
+        }
+
+        /// This timestamp in nanoseconds, rounded to the nearest one.
+        pub fn to_ns(self) -> Result<u64, TimeError> {
+            ticks_to_ns(self.ticks, self.timebase)
+        }
+    }
+
+    impl From<(u64, Timebase)> for Timestamp {
+        fn from((ticks, timebase): (u64, Timebase)) -> Self {
+            Self { ticks, timebase }
+        }
+    }
+
+    /// The error type for time conversions.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum TimeError {
+        /// The numerator or denominator of the timebase is zero.
+        ZeroTimebase(Timebase),
+
+        /// The result doesn't fit in 64 bits.
+        Overflow,
+
+        /// A [`TimestampMapper`] is paused, so it can't be paused again or map a time after the pause.
+        Paused,
+
+        /// A [`TimestampMapper`] was resumed without being paused.
+        NotPaused,
+
+        /// A wall-clock time given to a [`TimestampMapper`] is before the last pause or resume, at `earliest_ns`.
+        Backwards { wall_ns: u64, earliest_ns: u64 },
+    }
+
+    impl std::fmt::Display for TimeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                TimeError::ZeroTimebase(timebase) => write!(f, "Invalid timebase {}/{}", timebase.num, timebase.den),
+                TimeError::Overflow => f.write_str("Time out of range"),
+                TimeError::Paused => f.write_str("The timestamp mapper is paused"),
+                TimeError::NotPaused => f.write_str("The timestamp mapper is not paused"),
+                TimeError::Backwards { wall_ns, earliest_ns } => {
+                    write!(f, "Wall-clock time {wall_ns}ns is before the last pause or resume at {earliest_ns}ns")
+                },
+            }
+        }
+    }
+
+    impl std::error::Error for TimeError {}
 }
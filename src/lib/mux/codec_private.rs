@@ -0,0 +1,540 @@
+use super::sniff::{bits, leb128, AV1_OBU_SEQUENCE_HEADER};
+use super::Error;
+use crate::time::{ticks_to_ns, Timebase};
+
+/// The `SeekPreRoll` of Opus tracks: decoding has to start this long before a seek target for the output to
+/// converge, as the Opus specification recommends.
+pub(crate) const OPUS_SEEK_PRE_ROLL_NS: u64 = 80_000_000;
+
+/// The fields of an Opus identification header (`OpusHead`) that matter to the track it is the `CodecPrivate` of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OpusHead {
+    pub channels: u8,
+
+    /// The number of samples at 48 kHz that the decoder drops from the start of its output.
+    pub pre_skip: u16,
+
+    /// The sample rate of the encoder's input, which is informational only, as Opus always decodes at 48 kHz. Zero
+    /// if it is unspecified.
+    pub input_sample_rate: u32,
+}
+
+impl OpusHead {
+    /// Parses the start of `data`, or returns `None` if it isn't an `OpusHead` of a version this understands.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        // Magic signature, version, channel count, pre-skip, input sample rate, output gain and channel mapping family.
+        // Versions that only change the minor half of the version byte stay compatible
+        if data.len() < 19 || !data.starts_with(b"OpusHead") || data[8] >> 4 != 0 {
+            return None;
+        }
+        Some(Self {
+            channels: data[9],
+            pre_skip: u16::from_le_bytes([data[10], data[11]]),
+            input_sample_rate: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+        })
+    }
+
+    /// The pre-skip in nanoseconds, which is what the track's `CodecDelay` should be.
+    pub fn codec_delay_ns(self) -> u64 {
+        // 65535 samples at 48 kHz can't overflow
+        ticks_to_ns(u64::from(self.pre_skip), Timebase::OPUS).unwrap_or(u64::MAX)
+    }
+}
+
+/// The fields of a Vorbis identification header that the track's `Audio` element repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct VorbisIdentification {
+    pub channels: u8,
+    pub sample_rate: u32,
+}
+
+impl VorbisIdentification {
+    /// Parses the identification header from `data`, a Vorbis `CodecPrivate`: the identification, comment and setup
+    /// headers in Xiph lacing. Returns `None` if that isn't what `data` holds.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        // The number of headers less one, then the sizes of all but the last, in runs of 255 ended by a smaller byte.
+        // Only the identification header is read, so only the size of the first one matters
+        if *data.first()? != 2 {
+            return None;
+        }
+        let mut pos = 1;
+        for _ in 0..2 {
+            while *data.get(pos)? == 255 {
+                pos += 1;
+            }
+            pos += 1;
+        }
+
+        // Packet type, the "vorbis" signature, the version, which must be 0, then the channels and the sample rate
+        let header = data.get(pos..pos + 16)?;
+        if !header.starts_with(b"\x01vorbis") || header[7..11] != [0; 4] {
+            return None;
+        }
+        Some(Self {
+            channels: header[11],
+            sample_rate: u32::from_le_bytes([header[12], header[13], header[14], header[15]]),
+        })
+    }
+}
+
+/// The fields of an `AV1CodecConfigurationRecord`, the `CodecPrivate` of AV1 tracks, that come from the stream's
+/// sequence header, for [`av1_config_from_params`]. Where the sequence header has several operating points, the level
+/// and tier are those of the first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+#[non_exhaustive]
+pub struct Av1ConfigParams {
+    /// `seq_profile`: 0 for the Main profile, 1 for High and 2 for Professional.
+    pub seq_profile: u8,
+    /// `seq_level_idx[0]`: from 0 for level 2.0 to 23 for level 7.3, or 31 for a stream without level constraints.
+    pub seq_level_idx: u8,
+    /// `seq_tier[0]`, whether the stream is in the high tier.
+    pub high_tier: bool,
+    pub high_bitdepth: bool,
+    /// Whether the stream has 12 bits per sample, which only the Professional profile allows. Needs `high_bitdepth`.
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    /// `chroma_sample_position`: 0 if it is unknown, 1 for chroma sited vertically with the luma samples and 2 for
+    /// chroma sited with the top-left luma sample. Only meaningful for 4:2:0.
+    pub chroma_sample_position: u8,
+    /// The number of frames, from 1 to 16, that a decoder has to buffer before presenting the first one, for streams
+    /// that signal it. Left out of the record if `None`.
+    pub initial_presentation_delay: Option<u8>,
+}
+
+impl Av1ConfigParams {
+    /// The second and third bytes of the record: `seq_profile` and `seq_level_idx_0`, then `seq_tier_0`, the bit
+    /// depth, `monochrome`, the chroma subsampling and `chroma_sample_position`.
+    fn config_bytes(&self) -> [u8; 2] {
+        let flags = u8::from(self.high_tier) << 7
+            | u8::from(self.high_bitdepth) << 6
+            | u8::from(self.twelve_bit) << 5
+            | u8::from(self.monochrome) << 4;
+        let subsampling = u8::from(self.chroma_subsampling_x) << 3 | u8::from(self.chroma_subsampling_y) << 2;
+        [self.seq_profile << 5 | self.seq_level_idx, flags | subsampling | self.chroma_sample_position]
+    }
+}
+
+/// The `CodecPrivate` for an AV1 track: an `AV1CodecConfigurationRecord` made from `seq_header_obu`, the stream's
+/// sequence header OBU, which the record then holds as its `configOBUs`. A sequence header without `obu_size` gets one,
+/// as Matroska requires.
+///
+/// Fails with [`Error::BadParam`] if `seq_header_obu` isn't a single, whole OBU, or if it is one of another type.
+pub fn av1_config(seq_header_obu: &[u8]) -> Result<Vec<u8>, Error> {
+    let (header_len, end) = next_obu(seq_header_obu).ok_or(Error::BadParam)?;
+    let header = seq_header_obu[0];
+    if end != seq_header_obu.len() || header & 0x80 != 0 || header >> 3 & 0x0F != AV1_OBU_SEQUENCE_HEADER {
+        return Err(Error::BadParam);
+    }
+    let payload = &seq_header_obu[header_len..];
+    let params = Av1SequenceHeader::parse_obu(payload).ok_or(Error::BadParam)?.params;
+    if header & 0x02 != 0 {
+        return av1_config_from_params(&params, seq_header_obu);
+    }
+
+    let mut obu = seq_header_obu[..header_len].to_vec();
+    obu[0] |= 0x02;
+    let mut size = payload.len();
+    loop {
+        let byte = (size & 0x7F) as u8;
+        size >>= 7;
+        if size == 0 {
+            obu.push(byte);
+            break;
+        }
+        obu.push(byte | 0x80);
+    }
+    obu.extend_from_slice(payload);
+    av1_config_from_params(&params, &obu)
+}
+
+/// Like [`av1_config`], but for when the fields of the record are known without the sequence header at hand.
+/// `config_obus` follow them as they are, and should be the sequence header OBU, with its `obu_size`, possibly followed
+/// by metadata OBUs. They may be empty, though players generally need the sequence header.
+///
+/// Fails with [`Error::BadParam`] if a field of `params` has a value the AV1 specification reserves, or one that the
+/// profile doesn't allow.
+pub fn av1_config_from_params(params: &Av1ConfigParams, config_obus: &[u8]) -> Result<Vec<u8>, Error> {
+    let valid = params.seq_profile <= 2
+        && (params.seq_level_idx <= 23 || params.seq_level_idx == 31)
+        && (!params.twelve_bit || params.seq_profile == 2 && params.high_bitdepth)
+        && (!params.monochrome || params.seq_profile != 1)
+        && params.chroma_sample_position <= 2
+        && params.initial_presentation_delay.is_none_or(|delay| (1..=16).contains(&delay));
+    if !valid {
+        return Err(Error::BadParam);
+    }
+
+    // marker and version, then initial_presentation_delay_present and initial_presentation_delay_minus_one
+    let [profile_and_level, flags] = params.config_bytes();
+    let delay = params.initial_presentation_delay.map_or(0, |delay| 0x10 | (delay - 1));
+    let mut config = Vec::with_capacity(4 + config_obus.len());
+    config.extend_from_slice(&[0x81, profile_and_level, flags, delay]);
+    config.extend_from_slice(config_obus);
+    Ok(config)
+}
+
+/// The fields of an AV1 sequence header that the track's `CodecPrivate` and `Video` element repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Av1SequenceHeader {
+    pub max_frame_width: u32,
+    pub max_frame_height: u32,
+    pub params: Av1ConfigParams,
+}
+
+impl Av1SequenceHeader {
+    /// Parses the sequence header from `data`, an AV1 `CodecPrivate`: an `AV1CodecConfigurationRecord`, whose
+    /// `configOBUs` hold the sequence header OBU. Returns `None` if there is none, or it is cut short.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        // marker and version, then seq_profile, seq_level_idx_0 and the flags, then the OBUs
+        if *data.first()? != 0x81 {
+            return None;
+        }
+        let (_, payload) = find_sequence_header(data.get(4..)?)?;
+        Self::parse_obu(payload)
+    }
+
+    /// The `CodecPrivate` for an AV1 track whose first temporal unit is `data`: an `AV1CodecConfigurationRecord` with
+    /// the sequence header OBU of the temporal unit. Returns `None` if there is none, or it is cut short.
+    pub fn codec_private(data: &[u8]) -> Option<Vec<u8>> {
+        let (obu, _) = find_sequence_header(data)?;
+        av1_config(obu).ok()
+    }
+
+    /// Reads `sequence_header_obu()` as far as `color_config()`, per section 5.5 of the AV1 specification.
+    fn parse_obu(payload: &[u8]) -> Option<Self> {
+        let mut reader = BitReader { data: payload, pos: 0 };
+        let seq_profile = reader.read(3)?;
+        // still_picture
+        reader.skip(1)?;
+        let reduced_still_picture_header = reader.flag()?;
+        let (level, tier);
+        if reduced_still_picture_header {
+            (level, tier) = (reader.read(5)?, 0);
+        } else {
+            let mut buffer_delay_length = 0;
+            let timing_info_present = reader.flag()?;
+            let mut decoder_model_info_present = false;
+            if timing_info_present {
+                // num_units_in_display_tick and time_scale, then equal_picture_interval and num_ticks_per_picture
+                reader.skip(64)?;
+                if reader.flag()? {
+                    reader.uvlc()?;
+                }
+                decoder_model_info_present = reader.flag()?;
+                if decoder_model_info_present {
+                    // buffer_delay_length_minus_1, then num_units_in_decoding_tick,
+                    // buffer_removal_time_length_minus_1 and frame_presentation_time_length_minus_1
+                    buffer_delay_length = reader.read(5)? as usize + 1;
+                    reader.skip(32 + 5 + 5)?;
+                }
+            }
+            let initial_display_delay_present = reader.flag()?;
+            let operating_points = reader.read(5)? + 1;
+            let mut first = None;
+            for _ in 0..operating_points {
+                // operating_point_idc, then seq_level_idx, and seq_tier for levels above 3.3
+                reader.skip(12)?;
+                let level = reader.read(5)?;
+                let tier = if level > 7 { reader.read(1)? } else { 0 };
+                first.get_or_insert((level, tier));
+                if decoder_model_info_present && reader.flag()? {
+                    // decoder_buffer_delay, encoder_buffer_delay and low_delay_mode_flag
+                    reader.skip(2 * buffer_delay_length + 1)?;
+                }
+                if initial_display_delay_present && reader.flag()? {
+                    reader.skip(4)?;
+                }
+            }
+            (level, tier) = first?;
+        }
+        let width_bits = reader.read(4)? as usize + 1;
+        let height_bits = reader.read(4)? as usize + 1;
+        let max_frame_width = reader.read(width_bits)?.checked_add(1)?;
+        let max_frame_height = reader.read(height_bits)?.checked_add(1)?;
+
+        if !reduced_still_picture_header && reader.flag()? {
+            // delta_frame_id_length_minus_2 and additional_frame_id_length_minus_1
+            reader.skip(7)?;
+        }
+        // use_128x128_superblock, enable_filter_intra and enable_intra_edge_filter
+        reader.skip(3)?;
+        if !reduced_still_picture_header {
+            // enable_interintra_compound, enable_masked_compound, enable_warped_motion and enable_dual_filter
+            reader.skip(4)?;
+            let enable_order_hint = reader.flag()?;
+            if enable_order_hint {
+                // enable_jnt_comp and enable_ref_frame_mvs
+                reader.skip(2)?;
+            }
+            // seq_choose_screen_content_tools, or else seq_force_screen_content_tools, then the same for integer
+            // motion vectors if screen content tools may be used
+            let screen_content_tools = reader.flag()? || reader.flag()?;
+            if screen_content_tools && !reader.flag()? {
+                reader.skip(1)?;
+            }
+            if enable_order_hint {
+                // order_hint_bits_minus_1
+                reader.skip(3)?;
+            }
+        }
+        // enable_superres, enable_cdef and enable_restoration
+        reader.skip(3)?;
+
+        // color_config()
+        let high_bitdepth = reader.read(1)?;
+        let twelve_bit = if seq_profile == 2 && high_bitdepth == 1 { reader.read(1)? } else { 0 };
+        let monochrome = if seq_profile == 1 { 0 } else { reader.read(1)? };
+        let (mut primaries, mut transfer, mut matrix) = (2, 2, 2);
+        if reader.flag()? {
+            (primaries, transfer, matrix) = (reader.read(8)?, reader.read(8)?, reader.read(8)?);
+        }
+        let (subsampling_x, subsampling_y, sample_position);
+        if monochrome == 1 {
+            (subsampling_x, subsampling_y, sample_position) = (1, 1, 0);
+        } else if (primaries, transfer, matrix) == (1, 13, 0) {
+            // sRGB is 4:4:4 in full range
+            (subsampling_x, subsampling_y, sample_position) = (0, 0, 0);
+        } else {
+            // color_range
+            reader.skip(1)?;
+            (subsampling_x, subsampling_y) = match seq_profile {
+                0 => (1, 1),
+                1 => (0, 0),
+                _ if twelve_bit == 1 => {
+                    let x = reader.read(1)?;
+                    (x, if x == 1 { reader.read(1)? } else { 0 })
+                },
+                _ => (1, 0),
+            };
+            sample_position = if subsampling_x == 1 && subsampling_y == 1 { reader.read(2)? } else { 0 };
+        }
+
+        let params = Av1ConfigParams {
+            seq_profile: seq_profile as u8,
+            seq_level_idx: level as u8,
+            high_tier: tier == 1,
+            high_bitdepth: high_bitdepth == 1,
+            twelve_bit: twelve_bit == 1,
+            monochrome: monochrome == 1,
+            chroma_subsampling_x: subsampling_x == 1,
+            chroma_subsampling_y: subsampling_y == 1,
+            chroma_sample_position: sample_position as u8,
+            initial_presentation_delay: None,
+        };
+        Some(Self {
+            max_frame_width,
+            max_frame_height,
+            params,
+        })
+    }
+}
+
+/// Finds the sequence header among the OBUs of `data`, returning it whole and its payload.
+fn find_sequence_header(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (header_len, end) = next_obu(rest)?;
+        if rest[0] >> 3 & 0x0F == AV1_OBU_SEQUENCE_HEADER {
+            return Some((&rest[..end], &rest[header_len..end]));
+        }
+        rest = &rest[end..];
+    }
+    None
+}
+
+/// The length of the header of the OBU at the start of `data`, and where the OBU ends. One without `obu_size` takes up
+/// the rest of `data`.
+fn next_obu(data: &[u8]) -> Option<(usize, usize)> {
+    // obu_type, obu_extension_flag and obu_has_size_field, then the extension header and the size
+    let header = *data.first()?;
+    let mut pos = 1 + usize::from(header & 0x04 != 0);
+    let size = if header & 0x02 != 0 {
+        let (size, len) = leb128(data.get(pos..)?)?;
+        pos += len;
+        usize::try_from(size).ok()?
+    } else {
+        data.len().checked_sub(pos)?
+    };
+    let end = pos.checked_add(size)?;
+    (end <= data.len()).then_some((pos, end))
+}
+
+/// Reads the bits of an AV1 header in order.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    /// The next `count` bits, which may be at most 32.
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let value = bits(self.data, self.pos, count)?;
+        self.pos += count;
+        Some(value)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.read(1).map(|bit| bit == 1)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.pos += count;
+        (self.pos <= self.data.len() * 8).then_some(())
+    }
+
+    /// An unsigned Exp-Golomb code, `uvlc()` in the AV1 specification.
+    fn uvlc(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+        }
+        if leading_zeros >= 32 {
+            return Some(u32::MAX);
+        }
+        Some(self.read(leading_zeros)? + ((1 << leading_zeros) - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_opus_heads() {
+        let head = OpusHead::parse(b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00").unwrap();
+        let expected = OpusHead {
+            channels: 2,
+            pre_skip: 312,
+            input_sample_rate: 48_000,
+        };
+        assert_eq!(head, expected);
+        assert_eq!(head.codec_delay_ns(), 6_500_000);
+        assert_eq!(OpusHead::parse(b"OpusHead\x0F\x01\x00\x00\x80\xbb\x00\x00\x00\x00\x00").unwrap().pre_skip, 0);
+
+        assert_eq!(OpusHead::parse(b"OpusHead\x10\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00"), None);
+        assert_eq!(OpusHead::parse(b"OpusTags\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00"), None);
+        assert_eq!(OpusHead::parse(b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00"), None);
+    }
+
+    #[test]
+    fn parses_vorbis_identification_headers() {
+        // The sizes of the identification and comment headers, the latter laced in runs of 255
+        let identification = b"\x01vorbis\x00\x00\x00\x00\x06\x44\xAC\x00\x00\
+            \x00\x00\x00\x00\x00\xF4\x01\x00\x00\x00\x00\x00\xB8\x01";
+        let mut data = vec![2, 30, 255, 255, 3];
+        data.extend_from_slice(identification);
+        let expected = VorbisIdentification {
+            channels: 6,
+            sample_rate: 44_100,
+        };
+        assert_eq!(VorbisIdentification::parse(&data), Some(expected));
+
+        // An identification header longer than 254 bytes has its size laced in runs of 255
+        let mut long = vec![2, 255, 255, 0, 30];
+        long.extend_from_slice(identification);
+        assert_eq!(VorbisIdentification::parse(&long), Some(expected));
+
+        assert_eq!(VorbisIdentification::parse(&identification[..]), None);
+        assert_eq!(VorbisIdentification::parse(&data[..20]), None);
+        data[5] = 3;
+        assert_eq!(VorbisIdentification::parse(&data), None);
+        data[5] = 1;
+        data[12] = 1;
+        assert_eq!(VorbisIdentification::parse(&data), None);
+    }
+
+    #[test]
+    fn parses_av1_sequence_headers() {
+        let fields = |header: Av1SequenceHeader| {
+            (header.max_frame_width, header.max_frame_height, header.params.config_bytes())
+        };
+
+        // Main profile at level 4.0 with 11-bit sizes, in 8-bit 4:2:0 BT.709 with chroma sited vertically
+        let av1c = b"\x81\x08\x0D\x00\x0A\x0E\x00\x00\x00\x42\xAB\xBF\xC3\x73\xFF\xE6\x40\x40\x40\x49";
+        assert_eq!(Av1SequenceHeader::parse(av1c).map(fields), Some((1920, 1080, [0x08, 0x0D])));
+
+        // The sequence header OBU needn't have a size, nor come first
+        let mut unsized_header = av1c[..4].to_vec();
+        unsized_header.extend_from_slice(b"\x7A\x02\xAA\xBB\x08");
+        unsized_header.extend_from_slice(&av1c[6..]);
+        assert_eq!(Av1SequenceHeader::parse(&unsized_header).map(fields), Some((1920, 1080, [0x08, 0x0D])));
+
+        // A reduced still picture header in the high profile leaves out the operating points and most tools
+        let still = b"\x81\x20\x40\x00\x0A\x06\x38\x26\x27\xFE\xFB\x75";
+        assert_eq!(Av1SequenceHeader::parse(still).map(fields), Some((640, 480, [0x20, 0x40])));
+
+        // Timing and decoder model information, and two operating points with decoder models and display delays, in
+        // the professional profile at 12 bits in the high tier
+        let timed = b"\x81\x49\xEC\x00\x0A\x24\x44\x00\x00\x0F\xA4\x00\x03\xA9\x82\xE4\x00\x01\x5F\x90\xFF\xE1\x10\x14\
+            \xE7\x4A\x62\x02\x59\xD2\x9D\x4C\xFF\xB3\xCF\xFF\x9E\x84\x88\x04\xB0\x80";
+        assert_eq!(Av1SequenceHeader::parse(timed).map(fields), Some((1280, 720, [0x49, 0xEC])));
+
+        // Without a sequence header, or with one cut short
+        assert_eq!(Av1SequenceHeader::parse(&av1c[..4]), None);
+        assert_eq!(Av1SequenceHeader::parse(&av1c[..12]), None);
+        assert_eq!(Av1SequenceHeader::parse(&timed[..36]), None);
+        assert_eq!(Av1SequenceHeader::parse(&av1c[6..]), None);
+
+        // The CodecPrivate of a stream is made of the sequence header in its first temporal unit, which here comes
+        // after a temporal delimiter and before a frame
+        for config in [&av1c[..], still, timed] {
+            let temporal_unit = [b"\x12\x00", &config[4..], b"\x32\x03\x10\x00\x00"].concat();
+            assert_eq!(Av1SequenceHeader::codec_private(&temporal_unit).as_deref(), Some(config));
+        }
+        assert_eq!(Av1SequenceHeader::codec_private(b"\x12\x00\x32\x03\x10\x00\x00"), None);
+    }
+
+    #[test]
+    fn builds_av1_configs() {
+        // Records as the AV1 ISOBMFF binding lays them out: no initial presentation delay, then the sequence header OBU
+        // with its size
+        let av1c_1080p = b"\x81\x08\x0D\x00\x0A\x0E\x00\x00\x00\x42\xAB\xBF\xC3\x73\xFF\xE6\x40\x40\x40\x49";
+        let av1c_still = b"\x81\x20\x40\x00\x0A\x06\x38\x26\x27\xFE\xFB\x75";
+        for av1c in [&av1c_1080p[..], av1c_still] {
+            assert_eq!(av1_config(&av1c[4..]).unwrap(), av1c);
+        }
+
+        // A sequence header without obu_size gets one
+        let unsized_header = [b"\x08", &av1c_1080p[6..]].concat();
+        assert_eq!(av1_config(&unsized_header).unwrap(), av1c_1080p);
+
+        // Other OBUs, more than one OBU, and sequence headers cut short are rejected
+        let two_obus = [&av1c_1080p[4..], b"\x12\x00"].concat();
+        for obu in [&b"\x12\x00"[..], &two_obus, &av1c_1080p[4..12], &av1c_1080p[4..5], b""] {
+            assert!(matches!(av1_config(obu), Err(Error::BadParam)), "{obu:02X?}");
+        }
+
+        let mut params = Av1ConfigParams {
+            seq_profile: 2,
+            seq_level_idx: 9,
+            high_tier: true,
+            high_bitdepth: true,
+            twelve_bit: true,
+            chroma_subsampling_x: true,
+            chroma_subsampling_y: true,
+            chroma_sample_position: 0,
+            initial_presentation_delay: Some(4),
+            ..Av1ConfigParams::default()
+        };
+        assert_eq!(av1_config_from_params(&params, b"\x0A\x00").unwrap(), b"\x81\x49\xEC\x13\x0A\x00");
+        params.initial_presentation_delay = None;
+        assert_eq!(av1_config_from_params(&params, &[]).unwrap(), b"\x81\x49\xEC\x00");
+
+        let invalid = [
+            Av1ConfigParams { seq_profile: 3, ..params },
+            Av1ConfigParams { seq_level_idx: 24, ..params },
+            Av1ConfigParams { seq_profile: 0, ..params },
+            Av1ConfigParams { high_bitdepth: false, ..params },
+            Av1ConfigParams { seq_profile: 1, twelve_bit: false, monochrome: true, ..params },
+            Av1ConfigParams { chroma_sample_position: 3, ..params },
+            Av1ConfigParams { initial_presentation_delay: Some(0), ..params },
+            Av1ConfigParams { initial_presentation_delay: Some(17), ..params },
+        ];
+        for params in invalid {
+            assert!(matches!(av1_config_from_params(&params, &[]), Err(Error::BadParam)), "{params:?}");
+        }
+    }
+}
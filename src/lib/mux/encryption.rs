@@ -0,0 +1,137 @@
+use super::Error;
+
+/// The flag of the signal byte that marks a frame as encrypted, in which case an IV follows.
+const SIGNAL_ENCRYPTED: u8 = 0x01;
+
+/// The flag of the signal byte that marks an encrypted frame as partitioned, in which case the partition offsets follow
+/// the IV.
+const SIGNAL_PARTITIONED: u8 = 0x02;
+
+/// Assembles a frame of a track marked with
+/// [`SegmentBuilder::add_content_encryption`](super::SegmentBuilder::add_content_encryption), as the WebM encryption
+/// specification lays it out: a signal byte, then for an encrypted frame its 8-byte IV, then for subsample
+/// encryption the partition offsets, then `payload`.
+///
+/// `iv` is `None` for a frame that is left in the clear, and is written big-endian otherwise. `partitions` are the
+/// offsets into `payload` where it switches between clear and encrypted bytes, starting in the clear; leave it empty
+/// to have the whole frame encrypted. `payload` is the frame as it is to be stored, with its encrypted parts already
+/// encrypted.
+///
+/// Fails with [`Error::BadParam`] if there are partitions for a clear frame, more than 255 of them, or if an offset
+/// is past the end of `payload` or before the offset before it.
+pub fn encrypted_frame_payload(iv: Option<u64>, partitions: &[u32], payload: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut frame = Vec::new();
+    encrypted_frame_payload_into(&mut frame, iv, partitions, payload)?;
+    Ok(frame)
+}
+
+/// Like [`encrypted_frame_payload`], but appends the frame to `out`, so that one buffer can be reused for every frame.
+/// Nothing is appended if it fails.
+pub fn encrypted_frame_payload_into(
+    out: &mut Vec<u8>,
+    iv: Option<u64>,
+    partitions: &[u32],
+    payload: &[u8],
+) -> Result<(), Error> {
+    let partition_count = u8::try_from(partitions.len()).map_err(|_| Error::BadParam)?;
+    if iv.is_none() && partition_count > 0 {
+        return Err(Error::BadParam);
+    }
+    let mut previous = 0;
+    for &offset in partitions {
+        if offset < previous || usize::try_from(offset).map_or(true, |offset| offset > payload.len()) {
+            return Err(Error::BadParam);
+        }
+        previous = offset;
+    }
+
+    let Some(iv) = iv else {
+        out.reserve(1 + payload.len());
+        out.push(0);
+        out.extend_from_slice(payload);
+        return Ok(());
+    };
+    out.reserve(1 + 8 + 1 + 4 * partitions.len() + payload.len());
+    if partitions.is_empty() {
+        out.push(SIGNAL_ENCRYPTED);
+        out.extend_from_slice(&iv.to_be_bytes());
+    } else {
+        out.push(SIGNAL_ENCRYPTED | SIGNAL_PARTITIONED);
+        out.extend_from_slice(&iv.to_be_bytes());
+        out.push(partition_count);
+        for offset in partitions {
+            out.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{EncryptionSettings, SegmentBuilder, VideoCodecId, Writer};
+    use std::io::Cursor;
+
+    const IV: u64 = 0x0123_4567_89AB_CDEF;
+
+    #[test]
+    fn lays_out_frames() {
+        // The layouts of the WebM encryption specification: a clear frame, an encrypted one, and one in partitions
+        let payload = b"\xAA\xBB\xCC\xDD\xEE\xFF\x11\x22";
+        assert_eq!(encrypted_frame_payload(None, &[], payload).unwrap(), b"\x00\xAA\xBB\xCC\xDD\xEE\xFF\x11\x22");
+        assert_eq!(
+            encrypted_frame_payload(Some(IV), &[], payload).unwrap(),
+            b"\x01\x01\x23\x45\x67\x89\xAB\xCD\xEF\xAA\xBB\xCC\xDD\xEE\xFF\x11\x22",
+        );
+        assert_eq!(
+            encrypted_frame_payload(Some(IV), &[2, 6], payload).unwrap(),
+            b"\x03\x01\x23\x45\x67\x89\xAB\xCD\xEF\x02\x00\x00\x00\x02\x00\x00\x00\x06\xAA\xBB\xCC\xDD\xEE\xFF\x11\x22",
+        );
+
+        // Empty partitions are allowed, up to and including the end of the payload
+        let frame = encrypted_frame_payload(Some(IV), &[0, 0, 8], payload).unwrap();
+        assert_eq!(&frame[9..22], b"\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x08");
+
+        // Frames are appended to what is already there
+        let mut out = b"previous".to_vec();
+        encrypted_frame_payload_into(&mut out, Some(IV), &[4], payload).unwrap();
+        assert_eq!(out.len(), 8 + 1 + 8 + 1 + 4 + payload.len());
+        assert!(out.starts_with(b"previous\x03"));
+    }
+
+    #[test]
+    fn rejects_bad_partitions() {
+        let payload = &[0; 8];
+        let mut out = b"previous".to_vec();
+        for (iv, partitions) in [(None, &[2][..]), (Some(IV), &[6, 2]), (Some(IV), &[2, 9]), (Some(IV), &[0; 256])] {
+            let result = encrypted_frame_payload_into(&mut out, iv, partitions, payload);
+            assert!(matches!(result, Err(Error::BadParam)), "{partitions:?}");
+        }
+        assert_eq!(out, b"previous");
+    }
+
+    #[test]
+    fn writes_encrypted_frames() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, video) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let builder = builder.add_content_encryption(video, &[7; 16], EncryptionSettings::default()).unwrap();
+        let mut segment = builder.build();
+        segment.add_encrypted_frame(video, None, &[], &[1; 10], 0, true).unwrap();
+        segment.add_encrypted_frame(video, Some(IV), &[], &[2; 10], 40_000_000, false).unwrap();
+        segment.add_encrypted_frame(video, Some(IV + 1), &[3, 5], &[3; 10], 80_000_000, false).unwrap();
+        let result = segment.add_encrypted_frame(video, Some(IV + 2), &[11], &[4; 10], 120_000_000, false);
+        assert!(matches!(result, Err(Error::BadParam)));
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap().data).collect();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], encrypted_frame_payload(None, &[], &[1; 10]).unwrap());
+        assert_eq!(frames[1], encrypted_frame_payload(Some(IV), &[], &[2; 10]).unwrap());
+        assert_eq!(frames[2], encrypted_frame_payload(Some(IV + 1), &[3, 5], &[3; 10]).unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::spec::add_track;
+use super::{Error, FrameSpec, MuxFileOptions, MuxSummary, SegmentBuilder, TrackNum, TrackSpec, Writer};
+
+/// Muxes `frames` into a new WebM file at `path` with the given tracks, replacing any file already there.
+///
+/// This is [`mux_to_file_with_options`] with the default options, so frames must be given in timestamp order.
+pub fn mux_to_file(
+    path: impl AsRef<Path>,
+    tracks: &[TrackSpec],
+    frames: impl IntoIterator<Item = FrameSpec>,
+) -> Result<MuxSummary, Error> {
+    mux_to_file_with_options(path, tracks, frames, &MuxFileOptions::default())
+}
+
+/// Muxes `frames` into a new WebM file at `path` with the given tracks, replacing any file already there.
+///
+/// Each track is added with all of its settings, though its [`TrackSpec::name`] is ignored. The file is written
+/// through a buffer, and finalized with a `Duration` that runs to the end of the last frame. As frames have no
+/// duration of their own, each is assumed to last as long as the gap since the previous frame of its track. On
+/// failure, the partly written file is left in place.
+pub fn mux_to_file_with_options(
+    path: impl AsRef<Path>,
+    tracks: &[TrackSpec],
+    frames: impl IntoIterator<Item = FrameSpec>,
+    options: &MuxFileOptions,
+) -> Result<MuxSummary, Error> {
+    let file = File::create(path).map_err(Error::Io)?;
+    let mut builder = SegmentBuilder::new(Writer::new(BufWriter::new(file)))?;
+
+    let mut track_nums: Vec<TrackNum> = Vec::with_capacity(tracks.len());
+    for (index, track) in tracks.iter().enumerate() {
+        let (next, track_num) = add_track(builder, track).map_err(|(_, error)| Error::Track {
+            index,
+            error: Box::new(error),
+        })?;
+        builder = next;
+        track_nums.push(track_num.into());
+    }
+    let mut segment = builder.build();
+
+    let frames: Box<dyn Iterator<Item = FrameSpec>> = if options.sort_frames {
+        let mut frames: Vec<_> = frames.into_iter().collect();
+        frames.sort_by_key(|frame| frame.timestamp_ns);
+        Box::new(frames.into_iter())
+    } else {
+        Box::new(frames.into_iter())
+    };
+
+    let mut frames_per_track = vec![0; tracks.len()];
+    let mut last_timestamps: Vec<Option<u64>> = vec![None; tracks.len()];
+    let (mut previous_ns, mut end_ns) = (0, 0);
+    for (index, frame) in frames.enumerate() {
+        let context = |error| Error::Frame {
+            index,
+            error: Box::new(error),
+        };
+        let Some(&track_num) = track_nums.get(frame.track) else {
+            return Err(context(Error::BadParam));
+        };
+        if frame.timestamp_ns < previous_ns {
+            return Err(Error::UnorderedFrame {
+                index,
+                timestamp_ns: frame.timestamp_ns,
+                previous_ns,
+            });
+        }
+
+        segment.add_frame(track_num, &frame.data, frame.timestamp_ns, frame.keyframe).map_err(context)?;
+        let gap = last_timestamps[frame.track].map_or(0, |last| frame.timestamp_ns - last);
+        end_ns = end_ns.max(frame.timestamp_ns.saturating_add(gap));
+        last_timestamps[frame.track] = Some(frame.timestamp_ns);
+        frames_per_track[frame.track] += 1;
+        previous_ns = frame.timestamp_ns;
+    }
+
+    let writer = segment.finalize_with_duration_ns(end_ns).map_err(|_| Error::Unknown)?;
+    let mut file = writer.into_inner();
+    file.flush().map_err(Error::Io)?;
+    let bytes_written = file.get_ref().metadata().map_err(Error::Io)?.len();
+
+    Ok(MuxSummary {
+        bytes_written,
+        duration_ns: end_ns,
+        frames_per_track,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{AudioCodecId, VideoCodecId};
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory that is removed on drop.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("webm-{}-{name}.webm", std::process::id())))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn tracks() -> Vec<TrackSpec> {
+        let mut audio = TrackSpec::audio(48_000, 2, AudioCodecId::Opus);
+        audio.codec_private = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00".to_vec();
+        vec![TrackSpec::video(64, 48, VideoCodecId::VP9), audio]
+    }
+
+    /// Video at 25 fps and audio every 20ms, for 200ms, interleaved in timestamp order.
+    fn frames() -> Vec<FrameSpec> {
+        let video = (0..5u64).map(|i| (0, i * 40_000_000, i == 0));
+        let audio = (0..10u64).map(|i| (1, i * 20_000_000, true));
+        let mut frames: Vec<_> = video
+            .chain(audio)
+            .map(|(track, timestamp_ns, keyframe)| FrameSpec {
+                track,
+                data: vec![track as u8; 20],
+                timestamp_ns,
+                keyframe,
+            })
+            .collect();
+        frames.sort_by_key(|frame| frame.timestamp_ns);
+        frames
+    }
+
+    #[test]
+    fn muxes_to_file() {
+        let path = TempPath::new("muxes_to_file");
+        let summary = mux_to_file(&path.0, &tracks(), frames()).unwrap();
+        assert_eq!(summary.frames_per_track, [5, 10]);
+        assert_eq!(summary.duration_ns, 200_000_000);
+        assert_eq!(summary.bytes_written, std::fs::metadata(&path.0).unwrap().len());
+
+        let mut demuxer = Demuxer::new(File::open(&path.0).unwrap()).unwrap();
+        assert_eq!(demuxer.segment_info().duration_ns, Some(200_000_000));
+        assert_eq!(demuxer.tracks().len(), 2);
+        assert_eq!(demuxer.tracks()[1].codec_private, tracks()[1].codec_private);
+        let timestamps: Vec<u64> = demuxer.frames().map(|frame| frame.unwrap().timestamp_ns).collect();
+        assert_eq!(timestamps, frames().iter().map(|frame| frame.timestamp_ns).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sorts_or_rejects_unordered_frames() {
+        let path = TempPath::new("sorts_or_rejects_unordered_frames");
+        let mut unordered = frames();
+        unordered.swap(3, 7);
+
+        let result = mux_to_file(&path.0, &tracks(), unordered.clone());
+        assert!(matches!(result, Err(Error::UnorderedFrame { index: 4, .. })), "{result:?}");
+
+        let options = MuxFileOptions { sort_frames: true };
+        let summary = mux_to_file_with_options(&path.0, &tracks(), unordered, &options).unwrap();
+        assert_eq!(summary.frames_per_track, [5, 10]);
+    }
+
+    #[test]
+    fn reports_what_failed() {
+        let path = TempPath::new("reports_what_failed");
+        let mut tracks = tracks();
+        tracks.push(TrackSpec::video(0, 48, VideoCodecId::VP9));
+        let result = mux_to_file(&path.0, &tracks, frames());
+        assert!(matches!(result, Err(Error::Track { index: 2, .. })), "{result:?}");
+
+        let mut unknown_track = frames();
+        unknown_track[2].track = 5;
+        let result = mux_to_file(&path.0, &tracks[..2], unknown_track);
+        let Err(Error::Frame { index: 2, error }) = result else {
+            panic!("A frame of an unknown track unexpectedly didn't fail: {result:?}");
+        };
+        assert!(matches!(*error, Error::BadParam));
+
+        let result = mux_to_file(path.0.join("not-a-directory"), &tracks[..2], frames());
+        assert!(matches!(result, Err(Error::Io(_))), "{result:?}");
+    }
+}
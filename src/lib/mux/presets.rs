@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use super::{
+    AudioCodecId, Error, OpusHead, SegmentBuilder, SimpleAudioWriter, SimpleWriterOptions, Writer,
+    OPUS_SEEK_PRE_ROLL_NS,
+};
+
+/// Starts a segment in `writer` with one Opus track, set up as audio-only WebM files such as podcasts should be.
+///
+/// `codec_private` is the stream's identification header (`OpusHead`). Its pre-skip is written as the track's
+/// `CodecDelay`, so that players drop the encoder's priming samples, and the track's `SeekPreRoll` is 80 ms. Cue
+/// points are written for the track, so that players can seek.
+///
+/// Write the packets with [`SimpleAudioWriter::write_frame`], then finish with
+/// [`SimpleAudioWriter::finish_with_padding`], giving how much of the last packet the encoder added as padding. The
+/// padding is written as the last frame's `DiscardPadding`, and left out of the file's `Duration`.
+///
+/// Fails with [`Error::BadParam`] if `codec_private` isn't a version 1 `OpusHead` for `channels` channels.
+pub fn opus_audio<W: Write>(
+    writer: Writer<W>,
+    sample_rate: u32,
+    channels: u32,
+    codec_private: &[u8],
+) -> Result<SimpleAudioWriter<W>, Error> {
+    let head = OpusHead::parse(codec_private).filter(|head| u32::from(head.channels) == channels);
+    let codec_delay_ns = head.ok_or(Error::BadParam)?.codec_delay_ns();
+
+    let (builder, track) =
+        SegmentBuilder::new(writer)?.add_audio_track(sample_rate, channels, AudioCodecId::Opus, None)?;
+    let builder = builder.set_codec_delay(track, codec_delay_ns)?.set_seek_pre_roll(track, OPUS_SEEK_PRE_ROLL_NS)?;
+    let options = SimpleWriterOptions {
+        codec_private: codec_private.to_vec(),
+        ..SimpleWriterOptions::default()
+    };
+    SimpleAudioWriter::from_builder(builder, track, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::validate::{validate, ValidationOptions};
+    use std::io::Cursor;
+
+    /// A stereo `OpusHead` with a pre-skip of 312 samples
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    #[test]
+    fn writes_opus_audio() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let mut audio = opus_audio(writer, 48_000, 2, OPUS_HEAD).unwrap();
+        let track = audio.track();
+
+        // 70 seconds of 20 ms packets spans a few clusters, so there is more than one cue point to seek to
+        for i in 0..3500u64 {
+            audio.write_frame(&i.to_le_bytes(), i * 20_000_000).unwrap();
+        }
+        let file = audio.finish_with_padding(7_000_000).unwrap().into_inner().into_inner();
+
+        let report = validate(Cursor::new(&file), ValidationOptions::default());
+        assert!(report.is_valid(), "{:?}", report.findings);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.segment_info().duration_ns, Some(70_000_000_000 - 7_000_000));
+        let info = &demuxer.tracks()[0];
+        assert_eq!(info.codec_delay_ns, 6_500_000);
+        assert_eq!(info.seek_pre_roll_ns, 80_000_000);
+
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 3500);
+        assert!(frames[..3499].iter().all(|frame| frame.discard_padding_ns.is_none()));
+        assert_eq!(frames[3499].discard_padding_ns, Some(7_000_000));
+
+        assert!(demuxer.cue_index().unwrap().len() > 1);
+        let point = demuxer.seek(50_000_000_000, track).unwrap();
+        assert!(!point.linear_scan);
+        assert!(point.timestamp_ns > 0 && point.timestamp_ns <= 50_000_000_000);
+        let frame = demuxer.frames().next().unwrap().unwrap();
+        assert_eq!(frame.timestamp_ns, point.timestamp_ns);
+        assert_eq!(frame.data, (point.timestamp_ns / 20_000_000).to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let bad = |channels, head: &[u8]| {
+            let writer = Writer::new(Cursor::new(Vec::new()));
+            matches!(opus_audio(writer, 48_000, channels, head), Err(Error::BadParam))
+        };
+        assert!(bad(1, OPUS_HEAD));
+        assert!(bad(2, &OPUS_HEAD[..18]));
+        assert!(bad(2, b"OpusTags\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00"));
+        assert!(bad(2, b"OpusHead\x10\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00"));
+    }
+}
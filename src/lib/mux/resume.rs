@@ -0,0 +1,324 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use crate::demux::{ebml, DemuxTrackInfo, TrackType};
+use crate::remux::{mux_codec, Codec};
+use crate::repair::{self, finalize_in_place};
+
+use super::{AudioCodecId, Error, MediaSpec, MuxSpec, OpusHead, ResumeInfo, Segment, SegmentBuilder, TrackSpec, Writer};
+
+const EBML_ID: u32 = 0x1A45_DFA3;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+
+impl<F: Read + Write + Seek> Segment<ResumedFile<F>> {
+    /// Resumes recording into `file`, a WebM file that was left unfinalized, e.g. because recording was stopped or
+    /// crashed. The file's tracks must match those of `expected`, or this fails with [`Error::ResumeMismatch`].
+    ///
+    /// The clusters in the file are kept up to the last complete block, as with [`repair::finalize_in_place`], and new
+    /// clusters are written after them. Frames should continue from [`ResumeInfo::end_ns`], starting with a keyframe
+    /// on each video track. [`Segment::finalize`] then finalizes the whole file, with cues for both the old frames and
+    /// the new. As the file's headers are kept as they are, the chapters and tags of `expected` aren't written.
+    ///
+    /// Files that [`repair::finalize_in_place`] can't finalize can't be resumed either, and fail with
+    /// [`Error::Repair`]. This includes live files, and files that have been finalized already.
+    pub fn resume(mut file: F, expected: &MuxSpec) -> Result<(Self, ResumeInfo), Error> {
+        let found = repair::resumable(&mut file).map_err(Error::Repair)?;
+        if expected.timecode_scale.unwrap_or(1_000_000) != found.timecode_scale {
+            return Err(mismatch("timecode_scale"));
+        }
+        if found.tracks.len() != expected.tracks.len() {
+            return Err(mismatch("tracks"));
+        }
+
+        let dest = ResumedFile {
+            file,
+            append_at: found.end,
+            pos: 0,
+            headers: Vec::new(),
+            first_cluster: None,
+        };
+        let (builder, tracks) = SegmentBuilder::new(Writer::new(dest))?.apply_spec(expected)?;
+        for (index, (spec, track)) in expected.tracks.iter().zip(&found.tracks).enumerate() {
+            let track_num = tracks.track_num(&spec.name);
+            let field = mismatched_field(spec, track).or((track_num != Some(track.track_num)).then_some("number"));
+            if let Some(field) = field {
+                return Err(mismatch(&format!("tracks[{index}].{field}")));
+            }
+        }
+
+        let mut segment = builder.build();
+        segment.finish = Some(ResumedFile::finish);
+        let info = ResumeInfo {
+            tracks,
+            end_ns: found.end_ns,
+            truncated_bytes: found.truncated_bytes,
+        };
+        Ok((segment, info))
+    }
+}
+
+fn mismatch(path: &str) -> Error {
+    Error::ResumeMismatch { path: path.into() }
+}
+
+/// The field of `spec` that `track` doesn't match, if any.
+fn mismatched_field(spec: &TrackSpec, track: &DemuxTrackInfo) -> Option<&'static str> {
+    let media_matches = match (&spec.media, &track.track_type, mux_codec(track)) {
+        (&MediaSpec::Video { width, height, codec }, TrackType::Video(info), Ok(found)) => {
+            found == Codec::Video(codec) && (info.width, info.height) == (width, height)
+        },
+        (&MediaSpec::Audio {
+            sample_rate,
+            channels,
+            codec,
+        }, TrackType::Audio(info), Ok(found)) => {
+            found == Codec::Audio(codec) && info.sample_rate == f64::from(sample_rate) && info.channels == channels
+        },
+        _ => false,
+    };
+
+    if !media_matches {
+        Some("media")
+    } else if spec.codec_private != track.codec_private {
+        Some("codec_private")
+    } else if spec_codec_delay_ns(spec) != track.codec_delay_ns {
+        Some("codec_delay_ns")
+    } else if spec.seek_pre_roll_ns != track.seek_pre_roll_ns {
+        Some("seek_pre_roll_ns")
+    } else {
+        None
+    }
+}
+
+/// The `CodecDelay` that the track of `spec` is written with: an Opus track without one of its own gets the pre-skip
+/// of its `OpusHead`.
+fn spec_codec_delay_ns(spec: &TrackSpec) -> u64 {
+    match spec.media {
+        MediaSpec::Audio {
+            codec: AudioCodecId::Opus,
+            ..
+        } if spec.codec_delay_ns == 0 => OpusHead::parse(&spec.codec_private).map_or(0, OpusHead::codec_delay_ns),
+        _ => spec.codec_delay_ns,
+    }
+}
+
+/// The write destination of a segment made by [`Segment::resume`]. `libwebm` writes a whole new segment to it, but
+/// only the clusters reach the file, after those already in it. Finalizing the segment then finalizes the file in
+/// place.
+pub struct ResumedFile<F> {
+    file: F,
+
+    /// Where the kept clusters of the file end
+    append_at: u64,
+
+    /// The position in the segment `libwebm` writes
+    pos: u64,
+
+    /// What `libwebm` wrote before its first cluster, which is dropped once it's clear where that starts
+    headers: Vec<u8>,
+
+    /// The position of `libwebm`'s first cluster
+    first_cluster: Option<u64>,
+}
+
+impl<F> ResumedFile<F> {
+    /// Returns the file.
+    #[must_use]
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+
+impl<F: Read + Write + Seek> ResumedFile<F> {
+    fn finish(&mut self) -> Result<(), Error> {
+        finalize_in_place(&mut self.file).map(drop).map_err(Error::Repair)
+    }
+
+    /// Looks for `libwebm`'s first cluster in the headers it has written so far: a `Cluster` ID where the next child
+    /// of the `Segment` starts.
+    fn find_first_cluster(&self) -> Option<u64> {
+        let mut headers = Cursor::new(&self.headers[..]);
+        let (id, payload, size) = ebml::read_declared_header(&mut headers, 0).ok()?;
+        let segment_start = payload.checked_add(size?).filter(|_| id == EBML_ID)?;
+        let (id, mut pos, _) = ebml::read_declared_header(&mut headers, segment_start).ok()?;
+        if id != SEGMENT_ID {
+            return None;
+        }
+        loop {
+            // `libwebm` writes a byte at a time, so the cluster's size may not have been written yet
+            let id = self.headers.get(usize::try_from(pos).ok()?..).and_then(|rest| rest.get(..4))?;
+            if id == CLUSTER_ID.to_be_bytes() {
+                return Some(pos);
+            }
+            let (_, payload, size) = ebml::read_declared_header(&mut headers, pos).ok()?;
+            pos = payload.checked_add(size?)?;
+        }
+    }
+}
+
+impl<F: Read + Write + Seek> Write for ResumedFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(first_cluster) = self.first_cluster else {
+            let end = self.pos as usize + buf.len();
+            if self.headers.len() < end {
+                self.headers.resize(end, 0);
+            }
+            self.headers[self.pos as usize..end].copy_from_slice(buf);
+            self.pos = end as u64;
+
+            // Once the first cluster is found, what has been written of it moves to the file
+            if let Some(first_cluster) = self.find_first_cluster() {
+                let cluster = self.headers.split_off(first_cluster as usize);
+                self.first_cluster = Some(first_cluster);
+                self.file.seek(SeekFrom::Start(self.append_at))?;
+                self.file.write_all(&cluster)?;
+            }
+            return Ok(buf.len());
+        };
+
+        // The headers are already in the file, and `libwebm`'s changes to them on finalizing are dropped
+        if self.pos < first_cluster {
+            let len = buf.len().min((first_cluster - self.pos) as usize);
+            self.pos += len as u64;
+            return Ok(len);
+        }
+        self.file.seek(SeekFrom::Start(self.append_at + self.pos - first_cluster))?;
+        let len = self.file.write(buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl<F: Read + Write + Seek> Seek for ResumedFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        };
+        self.pos = pos.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
+}
+
+impl<F> std::fmt::Debug for ResumedFile<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{AudioCodecId, TrackHandles, VideoCodecId};
+    use crate::validate::{validate, ValidationOptions};
+
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    fn spec() -> MuxSpec {
+        let mut audio = TrackSpec::audio(48_000, 2, AudioCodecId::Opus).named("audio");
+        audio.codec_private = OPUS_HEAD.to_vec();
+        MuxSpec {
+            tracks: vec![TrackSpec::video(64, 48, VideoCodecId::VP9).named("video"), audio],
+            ..MuxSpec::default()
+        }
+    }
+
+    /// Adds video at 25fps with a keyframe every second, and audio, in 20ms steps from `start_ns`.
+    fn add_frames<W: Write>(segment: &mut Segment<W>, tracks: &TrackHandles, start_ns: u64, steps: u64) {
+        let (video, audio) = (tracks.video("video").unwrap(), tracks.audio("audio").unwrap());
+        for i in 0..steps {
+            let timestamp_ns = start_ns + i * 20_000_000;
+            if i % 2 == 0 {
+                segment.add_video_frame(video, &[i as u8; 300], timestamp_ns, i % 50 == 0).unwrap();
+            }
+            segment.add_audio_frame(audio, &[i as u8; 30], timestamp_ns, true).unwrap();
+        }
+    }
+
+    /// Two seconds of recording, stopped without finalizing and with the last block cut off.
+    fn stopped_recording() -> Vec<u8> {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, tracks) = builder.apply_spec(&spec()).unwrap();
+        let mut segment = builder.build();
+        add_frames(&mut segment, &tracks, 0, 100);
+        let mut data = segment.writer_mut().dest_mut().get_ref().clone();
+        data.truncate(data.len() - 10);
+        data
+    }
+
+    #[test]
+    fn resumes_recording() {
+        let (mut segment, info) = Segment::resume(Cursor::new(stopped_recording()), &spec()).unwrap();
+        assert!(info.truncated_bytes > 0);
+        let resume_ns = info.end_ns.next_multiple_of(1_000_000_000);
+        add_frames(&mut segment, &info.tracks, resume_ns, 100);
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let data = writer.into_inner().into_inner().into_inner();
+
+        let validation = validate(Cursor::new(&data), ValidationOptions::default());
+        assert!(validation.is_valid(), "{validation:?}");
+        let mut demuxer = Demuxer::new(Cursor::new(&data)).unwrap();
+        let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap()).collect();
+        assert_eq!(frames.iter().filter(|frame| frame.timestamp_ns >= resume_ns).count(), 150);
+        assert!(frames.iter().filter(|frame| frame.timestamp_ns < resume_ns).count() > 100);
+        let last_ns = resume_ns + 99 * 20_000_000;
+        assert_eq!(frames.last().unwrap().timestamp_ns, last_ns);
+        assert_eq!(demuxer.segment_info().duration_ns, Some(last_ns));
+
+        // Both the old and the new frames have cues
+        let cues = demuxer.cue_index().unwrap().to_vec();
+        assert!(cues.iter().any(|cue| cue.timestamp_ns == 1_000_000_000), "{cues:?}");
+        assert!(cues.iter().any(|cue| cue.timestamp_ns == resume_ns + 1_000_000_000), "{cues:?}");
+        let point = demuxer.seek(resume_ns + 1_500_000_000, 1u64).unwrap();
+        assert_eq!(point.timestamp_ns, resume_ns + 1_000_000_000);
+    }
+
+    #[test]
+    fn rejects_mismatched_tracks() {
+        let data = stopped_recording();
+        let resume = |spec: &MuxSpec| match Segment::resume(Cursor::new(data.clone()), spec) {
+            Err(Error::ResumeMismatch { path }) => path,
+            other => panic!("{other:?}"),
+        };
+
+        let mut spec = spec();
+        spec.tracks[0].media = MediaSpec::Video {
+            width: 640,
+            height: 480,
+            codec: VideoCodecId::VP9,
+        };
+        assert_eq!(resume(&spec), "tracks[0].media");
+        spec.tracks[0].media = MediaSpec::Video {
+            width: 64,
+            height: 48,
+            codec: VideoCodecId::VP8,
+        };
+        assert_eq!(resume(&spec), "tracks[0].media");
+
+        let mut spec = self::spec();
+        spec.tracks[1].codec_private.clear();
+        assert_eq!(resume(&spec), "tracks[1].codec_private");
+        spec.tracks.pop();
+        assert_eq!(resume(&spec), "tracks");
+
+        let mut spec = self::spec();
+        spec.tracks.swap(0, 1);
+        assert_eq!(resume(&spec), "tracks[0].media");
+        spec.timecode_scale = Some(1000);
+        assert_eq!(resume(&spec), "timecode_scale");
+
+        // Finalized files can't be resumed
+        let mut file = Cursor::new(data);
+        crate::repair::finalize_in_place(&mut file).unwrap();
+        let resumed = Segment::resume(file, &self::spec());
+        assert!(matches!(resumed, Err(Error::Repair(crate::repair::RepairError::AlreadyFinalized))));
+    }
+}
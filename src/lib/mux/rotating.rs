@@ -0,0 +1,306 @@
+use std::io::Write;
+
+use super::spec::add_track;
+use super::{Error, MediaSpec, RotationPolicy, Segment, SegmentBuilder, TrackNum, TrackSpec, Writer};
+
+/// The file being written, and what it needs to be finalized.
+struct Output<W: Write> {
+    segment: Segment<W>,
+    track_nums: Vec<TrackNum>,
+
+    /// The timestamp of the file's first frame, if it has one
+    start_ns: Option<u64>,
+
+    /// The end of the last frame, taking it to last as long as the gap since the one before it on its track
+    end_ns: u64,
+
+    /// The frame data written so far
+    bytes: u64,
+}
+
+/// Writes a continuous stream of frames to a series of files, such as a dashcam's, moving on to a new file whenever
+/// one reaches a limit of its [`RotationPolicy`].
+///
+/// Each file is playable on its own: it has all the tracks, with the same settings, and is finalized with its
+/// duration before the next is started. Once a limit is reached, the next file starts at the next video keyframe, or
+/// the next keyframe of any track if there is no video, so the frames before it stay in the file they belong to.
+/// That includes an audio frame that spans the boundary, which is left at the end of the earlier file.
+///
+/// Files come from the factory, which is given their index, counting from 0. Frames are given by the index of their
+/// track in the [`TrackSpec`]s, and in timestamp order.
+pub struct RotatingMuxer<W: Write, F: FnMut(u32) -> Writer<W>> {
+    tracks: Vec<TrackSpec>,
+    policy: RotationPolicy,
+    factory: F,
+    files: u32,
+    output: Output<W>,
+    has_video: bool,
+
+    /// The timestamp of the last frame written to each track, in any file
+    last_timestamps: Vec<Option<u64>>,
+    previous_ns: u64,
+
+    /// The timestamp of the frame that found the current file past a limit, if one has
+    due_since_ns: Option<u64>,
+}
+
+impl<W: Write, F: FnMut(u32) -> Writer<W>> RotatingMuxer<W, F> {
+    /// Starts the first file, with the given tracks. Adding a track fails with [`Error::Track`], and their
+    /// [`TrackSpec::name`]s are ignored.
+    pub fn new(tracks: &[TrackSpec], policy: &RotationPolicy, mut factory: F) -> Result<Self, Error> {
+        let output = start_file(tracks, factory(0))?;
+        Ok(Self {
+            tracks: tracks.to_vec(),
+            policy: *policy,
+            factory,
+            files: 1,
+            output,
+            has_video: tracks.iter().any(|track| matches!(track.media, MediaSpec::Video { .. })),
+            last_timestamps: vec![None; tracks.len()],
+            previous_ns: 0,
+            due_since_ns: None,
+        })
+    }
+
+    /// The index of the file being written.
+    #[must_use]
+    pub fn file_index(&self) -> u32 {
+        self.files - 1
+    }
+
+    /// Writes a frame to the track with the given index, with its timestamp in nanoseconds. If this moves on to a new
+    /// file, the finished file's writer is returned.
+    ///
+    /// An unknown track, or a timestamp earlier than the last frame's, fails with [`Error::BadParam`].
+    pub fn add_frame(
+        &mut self,
+        track: usize,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<Option<Writer<W>>, Error> {
+        let Some(spec) = self.tracks.get(track) else {
+            return Err(Error::BadParam);
+        };
+        if timestamp_ns < self.previous_ns {
+            return Err(Error::BadParam);
+        }
+        let starts_file = keyframe && (!self.has_video || matches!(spec.media, MediaSpec::Video { .. }));
+
+        let finished = if self.is_due(timestamp_ns) {
+            let waited_ns = self.due_since_ns.map_or(0, |since| timestamp_ns - since);
+            if starts_file || self.policy.max_keyframe_wait_ns.is_some_and(|wait_ns| waited_ns >= wait_ns) {
+                Some(self.rotate()?)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let output = &mut self.output;
+        let start_ns = *output.start_ns.get_or_insert(timestamp_ns);
+        let file_timestamp_ns = if self.policy.keep_absolute_timestamps {
+            timestamp_ns
+        } else {
+            timestamp_ns - start_ns
+        };
+        output.segment.add_frame(output.track_nums[track], data, file_timestamp_ns, keyframe)?;
+
+        let gap = self.last_timestamps[track].map_or(0, |last| timestamp_ns - last);
+        output.end_ns = output.end_ns.max(timestamp_ns.saturating_add(gap));
+        output.bytes = output.bytes.saturating_add(data.len() as u64);
+        self.last_timestamps[track] = Some(timestamp_ns);
+        self.previous_ns = timestamp_ns;
+        Ok(finished)
+    }
+
+    /// Finalizes the last file, and returns its writer. Like [`Segment::finalize`], this fails if it has no frames.
+    pub fn finish(self) -> Result<Writer<W>, Error> {
+        finish_file(self.output, &self.policy)
+    }
+
+    /// Whether the current file has reached a limit, noting when it first did.
+    fn is_due(&mut self, timestamp_ns: u64) -> bool {
+        if self.due_since_ns.is_some() {
+            return true;
+        }
+        let Some(start_ns) = self.output.start_ns else {
+            return false;
+        };
+        let too_long = self.policy.max_duration_ns.is_some_and(|max| timestamp_ns - start_ns >= max);
+        let too_big = self.policy.max_bytes.is_some_and(|max| self.output.bytes >= max);
+        if too_long || too_big {
+            self.due_since_ns = Some(timestamp_ns);
+        }
+        too_long || too_big
+    }
+
+    /// Finalizes the current file and starts the next, returning the finished file's writer.
+    fn rotate(&mut self) -> Result<Writer<W>, Error> {
+        let next = start_file(&self.tracks, (self.factory)(self.files))?;
+        self.files += 1;
+        self.due_since_ns = None;
+        finish_file(std::mem::replace(&mut self.output, next), &self.policy)
+    }
+}
+
+impl<W: Write, F: FnMut(u32) -> Writer<W>> std::fmt::Debug for RotatingMuxer<W, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+fn start_file<W: Write>(tracks: &[TrackSpec], writer: Writer<W>) -> Result<Output<W>, Error> {
+    let mut builder = SegmentBuilder::new(writer)?;
+    let mut track_nums = Vec::with_capacity(tracks.len());
+    for (index, track) in tracks.iter().enumerate() {
+        let (next, track_num) = add_track(builder, track).map_err(|(_, error)| Error::Track {
+            index,
+            error: Box::new(error),
+        })?;
+        builder = next;
+        track_nums.push(track_num.into());
+    }
+
+    Ok(Output {
+        segment: builder.build(),
+        track_nums,
+        start_ns: None,
+        end_ns: 0,
+        bytes: 0,
+    })
+}
+
+fn finish_file<W: Write>(output: Output<W>, policy: &RotationPolicy) -> Result<Writer<W>, Error> {
+    let start_ns = if policy.keep_absolute_timestamps {
+        0
+    } else {
+        output.start_ns.unwrap_or(0)
+    };
+    let duration_ns = output.end_ns.saturating_sub(start_ns);
+    output.segment.finalize_with_duration_ns(duration_ns).map_err(|_| Error::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{AudioCodecId, VideoCodecId};
+    use crate::validate::{validate, ValidationOptions};
+    use std::io::Cursor;
+
+    fn tracks() -> Vec<TrackSpec> {
+        let mut audio = TrackSpec::audio(48_000, 2, AudioCodecId::Opus);
+        audio.codec_private = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00".to_vec();
+        vec![TrackSpec::video(64, 48, VideoCodecId::VP9), audio]
+    }
+
+    fn factory(index: u32) -> Writer<Cursor<Vec<u8>>> {
+        assert!(index < 10);
+        Writer::new(Cursor::new(Vec::new()))
+    }
+
+    /// Muxes video at 25fps with a keyframe every `keyframe_every` frames, and audio every 20ms, for `duration_ms`.
+    /// Returns the files, with their first video timestamp.
+    fn mux(policy: &RotationPolicy, keyframe_every: u64, duration_ms: u64) -> Vec<(Vec<u8>, usize)> {
+        let mut muxer = RotatingMuxer::new(&tracks(), policy, factory).unwrap();
+        let mut files = Vec::new();
+        for i in 0..duration_ms / 20 {
+            if i % 2 == 0 {
+                let keyframe = (i / 2) % keyframe_every == 0;
+                files.extend(muxer.add_frame(0, &[i as u8; 100], i * 20_000_000, keyframe).unwrap());
+            }
+            files.extend(muxer.add_frame(1, &[i as u8; 20], i * 20_000_000, true).unwrap());
+        }
+        assert_eq!(muxer.file_index() as usize, files.len());
+        files.push(muxer.finish().unwrap());
+
+        files
+            .into_iter()
+            .map(|writer| {
+                let file = writer.into_inner().into_inner();
+                let report = validate(Cursor::new(&file), ValidationOptions::default());
+                assert!(report.is_valid(), "{:?}", report.findings);
+                let frames = Demuxer::new(Cursor::new(&file)).unwrap().frames().count();
+                (file, frames)
+            })
+            .collect()
+    }
+
+    fn first_video_frame(file: &[u8]) -> (u64, bool) {
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let frame = demuxer.frames().map(Result::unwrap).find(|frame| frame.track_num == 1).unwrap();
+        (frame.timestamp_ns, frame.is_keyframe)
+    }
+
+    #[test]
+    fn rotates_at_keyframes() {
+        // 7.5s with a keyframe every second, into files of 3s
+        let policy = RotationPolicy {
+            max_duration_ns: Some(3_000_000_000),
+            ..RotationPolicy::default()
+        };
+        let files = mux(&policy, 25, 7500);
+        assert_eq!(files.len(), 3);
+        assert_eq!(files.iter().map(|(_, frames)| frames).sum::<usize>(), 375 + 188);
+
+        let durations: Vec<_> = files
+            .iter()
+            .map(|(file, _)| Demuxer::new(Cursor::new(file)).unwrap().segment_info().duration_ns)
+            .collect();
+        // The last video frame, at 7.48s, lasts until 7.52s
+        assert_eq!(durations, [Some(3_000_000_000), Some(3_000_000_000), Some(1_520_000_000)]);
+        for (file, _) in &files {
+            assert_eq!(first_video_frame(file), (0, true));
+        }
+
+        // Or with the timestamps as they were given
+        let absolute = RotationPolicy {
+            keep_absolute_timestamps: true,
+            ..policy
+        };
+        let files = mux(&absolute, 25, 7500);
+        assert_eq!(first_video_frame(&files[2].0), (6_000_000_000, true));
+
+        // Limiting the size to 1.5s of frames, each file ends at the keyframe after that
+        let policy = RotationPolicy {
+            max_bytes: Some(3500 * 3 / 2),
+            ..RotationPolicy::default()
+        };
+        assert_eq!(mux(&policy, 25, 7500).len(), 4);
+    }
+
+    #[test]
+    fn waits_for_a_keyframe_as_long_as_allowed() {
+        // Only the first video frame is a keyframe
+        let policy = RotationPolicy {
+            max_duration_ns: Some(1_000_000_000),
+            ..RotationPolicy::default()
+        };
+        assert_eq!(mux(&policy, u64::MAX, 3000).len(), 1);
+
+        let policy = RotationPolicy {
+            max_keyframe_wait_ns: Some(500_000_000),
+            ..policy
+        };
+        let files = mux(&policy, u64::MAX, 3000);
+        assert_eq!(files.len(), 2);
+        // The audio frame at 1.5s starts the second file
+        assert_eq!(first_video_frame(&files[1].0), (20_000_000, false));
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut muxer = RotatingMuxer::new(&tracks(), &RotationPolicy::default(), factory).unwrap();
+        assert!(matches!(muxer.add_frame(2, &[0; 10], 0, true), Err(Error::BadParam)));
+        muxer.add_frame(0, &[0; 10], 40_000_000, true).unwrap();
+        assert!(matches!(muxer.add_frame(1, &[0; 10], 20_000_000, true), Err(Error::BadParam)));
+        assert!(muxer.finish().is_ok());
+
+        let mut bad_tracks = tracks();
+        bad_tracks[1].color = Some(Default::default());
+        let result = RotatingMuxer::new(&bad_tracks, &RotationPolicy::default(), factory);
+        assert!(matches!(result, Err(Error::Track { index: 1, .. })), "{result:?}");
+    }
+}
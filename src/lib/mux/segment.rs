@@ -1,15 +1,34 @@
+use std::collections::VecDeque;
 use std::io::Write;
 use std::num::NonZeroU64;
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
 use crate::ffi;
 use crate::ffi::mux::{ResultCode, TrackNum};
+use crate::time::{ticks_to_ns, TimeError, Timebase, Timestamp, TimestampMapper, TIMECODE_OFFSET_TAG};
+use crate::validate::{SyncOptions, SyncReport, SyncTracker};
 
 use super::{
-    writer::Writer, AudioCodecId, AudioTrack, ColorRange, ColorSubsampling, Error, VideoCodecId,
-    VideoTrack,
+    sniff,
+    writer::{Truncate, Writer},
+    AspectRatioType, Av1SequenceHeader, AudioCodecId, AudioTrack, AudioTrackConfig, ChromaSiting, ColorDescription,
+    ColorRange, ColorSubsampling, ColourPrimaries, EncryptionSettings, Error, FieldOrder, InterlaceMode,
+    KeyframeFlagPolicy, MasteringMetadata, MatrixCoefficients, MediaSpec, MuxWarning, OpusHead, Projection,
+    ProjectionType, StereoMode, TrackFlags, TransferCharacteristics, VideoCodecId, VideoTrack, VideoTrackConfig,
+    VorbisIdentification, OPUS_SEEK_PRE_ROLL_NS,
 };
 
+/// The highest track number `libwebm` accepts, so that a block's track number always takes a single byte.
+const MAX_TRACK_NUM: TrackNum = 126;
+
+/// The length of an encryption key ID, as Common Encryption defines it.
+const KEY_ID_LEN: usize = 16;
+
+/// Nanoseconds from the Unix epoch to the Matroska epoch, 2001-01-01T00:00:00 UTC, from which `DateUTC` counts.
+const MATROSKA_EPOCH_UNIX_NS: i128 = 978_307_200_000_000_000;
+
 /// RAII semantics for an FFI segment. This is simpler than implementing `Drop` on [`Segment`], which
 /// prevents destructuring.
 struct OwnedSegmentPtr {
@@ -28,6 +47,18 @@ impl OwnedSegmentPtr {
     fn as_ptr(&self) -> ffi::mux::SegmentMutPtr {
         self.segment.as_ptr()
     }
+
+    /// The error for a call that failed inside `libwebm`, with the adapter's description of the failure if it left one.
+    fn libwebm_error(&self) -> Error {
+        let mut buffer = [0u8; 256];
+        let len = unsafe { ffi::mux::segment_last_error(self.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len()) };
+        match std::str::from_utf8(&buffer[..len.min(buffer.len() - 1)]) {
+            Ok(message) if !message.is_empty() => Error::Libwebm {
+                message: message.to_owned(),
+            },
+            _ => Error::Unknown,
+        }
+    }
 }
 
 impl Drop for OwnedSegmentPtr {
@@ -39,6 +70,126 @@ impl Drop for OwnedSegmentPtr {
     }
 }
 
+/// The kind of a track added to a segment, to check typed track handles against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrackKind {
+    Video,
+    Audio,
+}
+
+/// The delays of an Opus track, whose `CodecDelay` is derived from the pre-skip of its `OpusHead` unless set otherwise.
+#[derive(Debug, Clone, Copy)]
+struct OpusDelays {
+    track_num: TrackNum,
+
+    /// The `CodecDelay` that the pre-skip of the track's `OpusHead` calls for, once it has one
+    pre_skip_ns: Option<u64>,
+
+    /// The `CodecDelay` set explicitly, if any, which takes precedence
+    codec_delay_ns: Option<u64>,
+
+    /// Whether the `SeekPreRoll` has been set explicitly
+    seek_pre_roll: bool,
+}
+
+/// The tracks of a segment, and the ID that its track handles carry.
+#[derive(Debug)]
+struct Tracks {
+    segment_id: u64,
+    tracks: Vec<(TrackNum, TrackKind)>,
+
+    /// The tracks whose `FlagDefault` has been cleared. Every other track is a default track, as Matroska takes a
+    /// missing flag to be set
+    not_default: Vec<TrackNum>,
+}
+
+impl Tracks {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            segment_id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            tracks: Vec::new(),
+            not_default: Vec::new(),
+        }
+    }
+
+    /// The tracks of the given kind, in the order they were added.
+    fn of_kind(&self, kind: TrackKind) -> impl Iterator<Item = TrackNum> + '_ {
+        self.tracks.iter().filter(move |(_, track_kind)| *track_kind == kind).map(|(track_num, _)| *track_num)
+    }
+
+    /// Checks that a handle with the given segment ID refers to a track of this segment of the given kind.
+    fn check(&self, segment_id: u64, track_num: TrackNum, kind: TrackKind) -> Result<(), Error> {
+        if segment_id != self.segment_id {
+            return Err(Error::ForeignTrack { track_num });
+        }
+        if !self.tracks.contains(&(track_num, kind)) {
+            return Err(Error::UnknownTrack { track_num });
+        }
+        Ok(())
+    }
+}
+
+/// The state of a segment built with [`SegmentBuilder::target_cluster_latency`].
+#[derive(Debug)]
+struct Latency {
+    max_ns: u64,
+
+    /// The timestamp of the earliest frame added since the destination was last flushed, if any
+    unflushed_since_ns: Option<u64>,
+
+    /// The clusters started as of the last frame
+    clusters: u64,
+}
+
+/// The state of a segment built with [`SegmentBuilder::dash_live`].
+#[derive(Debug)]
+struct Dash {
+    segment_ns: u64,
+    tolerance_ns: u64,
+
+    /// The kind of track whose keyframes start media segments: video, or audio if the segment has no video track
+    kind: TrackKind,
+
+    /// Where the next media segment is due to start, once the first keyframe has started the first one
+    next_boundary_ns: Option<u64>,
+}
+
+/// The video tracks whose keyframe flags are checked, for [`SegmentBuilder::verify_keyframe_flags`].
+#[derive(Debug)]
+struct KeyframeCheck {
+    policy: KeyframeFlagPolicy,
+    codecs: Vec<(TrackNum, VideoCodecId)>,
+}
+
+/// A track set up with [`SegmentBuilder::vfr_video`].
+#[derive(Debug, Clone, Copy)]
+struct Vfr {
+    track_num: TrackNum,
+
+    /// The duration of the track's last frame that has one, which a held frame's is estimated from if it isn't given
+    last_duration_ns: Option<u64>,
+}
+
+/// A frame that waits to be written, because it is the last frame of a [`SegmentBuilder::vfr_video`] track or comes
+/// after one, as frames must be written in timestamp order.
+#[derive(Debug)]
+struct PendingFrame {
+    track_num: TrackNum,
+    data: Vec<u8>,
+    timestamp_ns: u64,
+    keyframe: bool,
+
+    /// The `BlockDuration` to write the frame with, which frames of other tracks don't get
+    duration_ns: Option<u64>,
+
+    /// The `DiscardPadding` to write the frame with, from [`Segment::add_final_audio_frame`]
+    discard_padding_ns: i64,
+
+    /// Whether this is the last frame of a variable frame rate track, which waits for the track's next frame
+    held: bool,
+}
+
 /// A builder for [`Segment`].
 ///
 /// Once you have a [`Writer`], you can use this to specify the tracks and track parameters you want, then build a
@@ -46,11 +197,43 @@ impl Drop for OwnedSegmentPtr {
 pub struct SegmentBuilder<W: Write> {
     segment: OwnedSegmentPtr,
     writer: Writer<W>,
+
+    /// Where the writer was when the segment was started, which [`Segment::cancel_and_truncate`] cuts it back to
+    start: u64,
+    tracks: Tracks,
+    latency_ns: Option<u64>,
+    anchor: Option<SystemTime>,
+
+    /// The nominal duration of media segments and the keyframe tolerance, for [`SegmentBuilder::dash_live`]
+    dash: Option<(u64, u64)>,
+
+    /// The tracks set up with [`SegmentBuilder::vfr_video`]
+    vfr: Vec<TrackNum>,
+
+    /// The Opus tracks, whose delays are checked against their `OpusHead`
+    opus: Vec<OpusDelays>,
+
+    /// The codec and size or sample format of each track, which its `CodecPrivate` is checked against
+    media: Vec<(TrackNum, MediaSpec)>,
+    warnings: Vec<MuxWarning>,
+
+    /// The tracks that are encrypted, and what to do with frames whose keyframe flag differs from their bitstream, if
+    /// they are checked
+    encrypted: Vec<TrackNum>,
+    verify_keyframes: bool,
+    keyframe_policy: KeyframeFlagPolicy,
+
+    /// Whether the segment is live, and whether it has chapters and tags, which a segment that isn't live has no
+    /// room in its `SeekHead` for both of
+    live: bool,
+    chapters: bool,
+    tags: bool,
 }
 
 impl<W: Write> SegmentBuilder<W> {
     /// Creates a new [`SegmentBuilder`] with default configuration, that writes to the specified [`Writer`].
-    pub fn new(writer: Writer<W>) -> Result<Self, Error> {
+    pub fn new(mut writer: Writer<W>) -> Result<Self, Error> {
+        let start = writer.position();
         let segment = unsafe { ffi::mux::new_segment() };
         let segment = NonNull::new(segment)
             .map(|ptr| unsafe { OwnedSegmentPtr::new(ptr) })
@@ -58,20 +241,40 @@ impl<W: Write> SegmentBuilder<W> {
         let result = unsafe { ffi::mux::initialize_segment(segment.as_ptr(), writer.mkv_writer()) };
 
         match result {
-            ResultCode::Ok => Ok(SegmentBuilder { segment, writer }),
+            ResultCode::Ok => Ok(SegmentBuilder {
+                segment,
+                writer,
+                start,
+                tracks: Tracks::new(),
+                latency_ns: None,
+                anchor: None,
+                dash: None,
+                vfr: Vec::new(),
+                opus: Vec::new(),
+                media: Vec::new(),
+                warnings: Vec::new(),
+                encrypted: Vec::new(),
+                verify_keyframes: false,
+                keyframe_policy: KeyframeFlagPolicy::default(),
+                live: false,
+                chapters: false,
+                tags: false,
+            }),
             ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+            _ => Err(segment.libwebm_error()),
         }
     }
 
     /// Sets the name of the writing application. This will show up under the `WritingApp` Matroska element.
     pub fn set_writing_app(self, app_name: &str) -> Result<Self, Error> {
         let name = std::ffi::CString::new(app_name).map_err(|_| Error::BadParam)?;
-        unsafe {
-            ffi::mux::mux_set_writing_app(self.segment.as_ptr(), name.as_ptr());
-        }
+        let result = unsafe { ffi::mux::mux_set_writing_app(self.segment.as_ptr(), name.as_ptr()) };
 
-        Ok(self)
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
     }
 
     /// Adds a new video track to this segment, returning its track number.
@@ -87,6 +290,18 @@ impl<W: Write> SegmentBuilder<W> {
         codec: VideoCodecId,
         desired_track_num: Option<TrackNum>,
     ) -> Result<(Self, VideoTrack), Error> {
+        let mut builder = self;
+        let track = builder.push_video_track(width, height, codec, desired_track_num)?;
+        Ok((builder, track))
+    }
+
+    fn push_video_track(
+        &mut self,
+        width: u32,
+        height: u32,
+        codec: VideoCodecId,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<VideoTrack, Error> {
         let mut track_num_out: TrackNum = 0;
 
         // Zero is not a valid track number, and to libwebm means "choose one for me".
@@ -96,6 +311,7 @@ impl<W: Write> SegmentBuilder<W> {
         }
 
         // libwebm requires i32 for these
+        let media = MediaSpec::Video { width, height, codec };
         let width: i32 = try_as_i32(width)?;
         let height: i32 = try_as_i32(height)?;
         if width == 0 || height == 0 {
@@ -125,11 +341,128 @@ impl<W: Write> SegmentBuilder<W> {
                     }
                 }
 
-                Ok((self, VideoTrack(track_num_out)))
+                self.tracks.tracks.push((track_num_out.get(), TrackKind::Video));
+                self.media.push((track_num_out.get(), media));
+                Ok(VideoTrack(track_num_out, self.tracks.segment_id))
             },
             ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Adds a video track for `config`, with all of its settings, returning its handle.
+    ///
+    /// The config is checked before the track is added. If it is invalid, the builder is returned as it was, with an
+    /// [`Error::Spec`] naming the offending field, e.g. `name` for one with a nul character, `color` for a
+    /// [`ColorDescription`] that `libwebm` can't write, or `track_num` for a track number that is taken. Should
+    /// `libwebm` still fail once the track has been added, the track is removed again, so the builder is returned as
+    /// it was then too.
+    // The builder is handed back on failure as on success, so the error is as large as the builder
+    #[allow(clippy::result_large_err)]
+    pub fn add_video_track_with(self, config: &VideoTrackConfig) -> Result<(Self, VideoTrack), (Self, Error)> {
+        let taken = |track_num| self.tracks.tracks.iter().any(|&(num, _)| num == track_num);
+        let track_num = match config.track_num {
+            Some(track_num) if track_num == 0 || track_num > MAX_TRACK_NUM || taken(track_num) => Err("track_num"),
+            _ => Ok(()),
+        };
+        if let Err(field) = check_video_config(config).and(track_num) {
+            let error = Error::Spec {
+                path: field.into(),
+                error: Box::new(Error::BadParam),
+            };
+            return Err((self, error));
+        }
+
+        let mut builder = self;
+        let track = match builder.push_video_track(config.width, config.height, config.codec, config.track_num) {
+            Ok(track) => track,
+            Err(error) => return Err((builder, error)),
+        };
+        if let Err(error) = builder.configure_video_track(track, config) {
+            builder.remove_last_track();
+            return Err((builder, error));
+        }
+
+        if !config.default {
+            builder.tracks.not_default.push(track.into());
+        }
+        if !config.codec_private.is_empty() {
+            builder.compare_codec_private(track.into(), &config.codec_private);
+        }
+        Ok((builder, track))
+    }
+
+    /// Applies the settings of `config` to the track just added for it, leaving what the builder records of them to
+    /// the caller, so that nothing is left to undo if one fails.
+    fn configure_video_track(&self, track: VideoTrack, config: &VideoTrackConfig) -> Result<(), Error> {
+        let segment = self.segment.as_ptr();
+        let track_num = TrackNum::from(track);
+
+        if !config.codec_private.is_empty() {
+            let data = &config.codec_private;
+            let len = try_as_i32(data.len())?;
+            self.check(unsafe { ffi::mux::segment_set_codec_private(segment, track_num, data.as_ptr(), len) })?;
         }
+        if let Some(preset) = config.color {
+            self.check(unsafe {
+                ffi::mux::mux_set_color_description(
+                    segment,
+                    track_num,
+                    preset.primaries,
+                    preset.transfer_characteristics,
+                    preset.matrix_coefficients,
+                    color_range_id(preset.range),
+                    preset.chroma_siting_horizontal as u8,
+                    preset.chroma_siting_vertical as u8,
+                )
+            })?;
+        }
+        if let Some(bit_depth) = config.bit_depth {
+            let subsampling = config.chroma_subsampling;
+            // mux_set_color writes the range too, which would otherwise undo that of the preset
+            let color_range = match (config.color, config.color_range) {
+                (Some(preset), ColorRange::Unspecified) => preset.range,
+                (_, color_range) => color_range,
+            };
+            self.check(unsafe {
+                ffi::mux::mux_set_color(
+                    segment,
+                    track_num,
+                    bit_depth,
+                    subsampling.chroma_horizontal,
+                    subsampling.chroma_vertical,
+                    color_range_id(color_range),
+                )
+            })?;
+        }
+        if let Some(name) = &config.name {
+            let name = std::ffi::CString::new(name.as_str()).map_err(|_| Error::BadParam)?;
+            self.check(unsafe { ffi::mux::segment_set_track_name(segment, track_num, name.as_ptr()) })?;
+        }
+        if let Some(language) = &config.language {
+            let language = std::ffi::CString::new(language.as_str()).map_err(|_| Error::BadParam)?;
+            self.check(unsafe { ffi::mux::segment_set_track_language(segment, track_num, language.as_ptr()) })?;
+        }
+        if !config.default {
+            self.check(unsafe { ffi::mux::segment_set_track_default(segment, track_num, false) })?;
+        }
+        Ok(())
+    }
+
+    /// Removes the track added last, both from `libwebm` and from what the builder records of it.
+    fn remove_last_track(&mut self) {
+        let Some((track_num, _)) = self.tracks.tracks.pop() else {
+            return;
+        };
+        // Only fails if the track isn't the one libwebm added last, which adding tracks one at a time rules out
+        let result = unsafe { ffi::mux::segment_remove_track(self.segment.as_ptr(), track_num) };
+        debug_assert_eq!(result, ResultCode::Ok);
+        self.media.retain(|&(num, _)| num != track_num);
+        self.opus.retain(|delays| delays.track_num != track_num);
+        self.tracks.not_default.retain(|&num| num != track_num);
+        self.warnings.retain(|warning| match warning {
+            MuxWarning::CodecPrivateMismatch { track_num: num, .. } => *num != track_num,
+        });
     }
 
     /// Adds a new audio track to this segment, returning its track number.
@@ -145,6 +478,18 @@ impl<W: Write> SegmentBuilder<W> {
         codec: AudioCodecId,
         desired_track_num: Option<TrackNum>,
     ) -> Result<(Self, AudioTrack), Error> {
+        let mut builder = self;
+        let track = builder.push_audio_track(sample_rate, channels, codec, desired_track_num)?;
+        Ok((builder, track))
+    }
+
+    fn push_audio_track(
+        &mut self,
+        sample_rate: u32,
+        channels: u32,
+        codec: AudioCodecId,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<AudioTrack, Error> {
         let mut track_num_out: TrackNum = 0;
 
         // Zero is not a valid track number, and to libwebm means "choose one for me".
@@ -154,6 +499,11 @@ impl<W: Write> SegmentBuilder<W> {
         }
 
         // libwebm requires i32 for these
+        let media = MediaSpec::Audio {
+            sample_rate,
+            channels,
+            codec,
+        };
         let sample_rate: i32 = try_as_i32(sample_rate)?;
         let channels: i32 = try_as_i32(channels)?;
         if sample_rate == 0 || channels == 0 {
@@ -183,211 +533,4227 @@ impl<W: Write> SegmentBuilder<W> {
                     }
                 }
 
-                Ok((self, AudioTrack(track_num_out)))
+                self.tracks.tracks.push((track_num_out.get(), TrackKind::Audio));
+                self.media.push((track_num_out.get(), media));
+                if codec == AudioCodecId::Opus {
+                    self.opus.push(OpusDelays {
+                        track_num: track_num_out.get(),
+                        pre_skip_ns: None,
+                        codec_delay_ns: None,
+                        seek_pre_roll: false,
+                    });
+                }
+                Ok(AudioTrack(track_num_out, self.tracks.segment_id))
             },
             ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+            _ => Err(self.segment.libwebm_error()),
         }
     }
 
-    /// Sets the `CodecPrivate` data for the specified track. If you have a [`VideoTrack`] or [`AudioTrack`], you
-    /// can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
-    pub fn set_codec_private(self, track: impl Into<TrackNum>, data: &[u8]) -> Result<Self, Error> {
-        unsafe {
-            let len: i32 = data.len().try_into().map_err(|_| Error::BadParam)?;
-            let result = ffi::mux::segment_set_codec_private(
-                self.segment.as_ptr(),
-                track.into(),
-                data.as_ptr(),
-                len,
-            );
+    /// Adds an audio track for `config`, with all of its settings, returning its handle. The `CodecPrivate` and
+    /// delays are in place before the track can be given any frames, and an Opus track's `CodecDelay` is derived from
+    /// its `OpusHead` unless `codec_delay_ns` is given.
+    ///
+    /// The config is checked before the track is added. If it is invalid, the builder is returned as it was, with an
+    /// [`Error::Spec`] naming the offending field, e.g. `language` for one that isn't an ISO 639-2 code, or
+    /// `track_num` for a track number that is taken. Should `libwebm` refuse a setting once the track has been added,
+    /// the track is removed again, so the builder is returned as it was then too.
+    // The builder is handed back on failure as on success, so the error is as large as the builder
+    #[allow(clippy::result_large_err)]
+    pub fn add_audio_track_with(self, config: &AudioTrackConfig) -> Result<(Self, AudioTrack), (Self, Error)> {
+        match self.add_audio_tracks(std::slice::from_ref(config)) {
+            Ok((builder, tracks)) => Ok((builder, tracks[0])),
+            Err((builder, error, _)) => Err((builder, error)),
+        }
+    }
+
+    /// Adds an audio track for each of `configs`, with all of their settings, returning their handles in the same
+    /// order. This suits files with many similar tracks, such as dubs into several languages.
+    ///
+    /// The configs are checked before any track is added. If one is invalid, the builder is returned as it was, with
+    /// an [`Error::Spec`] naming the offending field, e.g. `language` for one with a nul character, and the index of
+    /// the config, so that it can be fixed and the call retried. Requesting a track number that is taken, including
+    /// by an earlier config, fails with `track_num`. Should `libwebm` fail once tracks are being added, every track
+    /// this call added is removed again, so the builder is returned as it was then too.
+    // The builder is handed back on failure as on success, so the error is as large as the builder
+    #[allow(clippy::result_large_err)]
+    pub fn add_audio_tracks(
+        self,
+        configs: &[AudioTrackConfig],
+    ) -> Result<(Self, Vec<AudioTrack>), (Self, Error, usize)> {
+        // Track numbers are chosen as libwebm chooses them: the first free one, counting from one past the number of
+        // tracks
+        let mut taken: Vec<TrackNum> = self.tracks.tracks.iter().map(|&(track_num, _)| track_num).collect();
+        for (index, config) in configs.iter().enumerate() {
+            let track_num = match config.track_num {
+                Some(track_num) if track_num != 0 && track_num <= MAX_TRACK_NUM && !taken.contains(&track_num) => {
+                    Ok(track_num)
+                },
+                Some(_) => Err("track_num"),
+                None => Ok((taken.len() as TrackNum + 1..).find(|track_num| !taken.contains(track_num)).unwrap_or(0)),
+            };
+            match check_audio_config(config).and(track_num) {
+                Ok(track_num) => taken.push(track_num),
+                Err(field) => {
+                    let error = Error::Spec {
+                        path: field.into(),
+                        error: Box::new(Error::BadParam),
+                    };
+                    return Err((self, error, index));
+                },
+            }
+        }
 
-            match result {
-                ResultCode::Ok => Ok(self),
-                ResultCode::BadParam => Err(Error::BadParam),
-                _ => Err(Error::Unknown),
+        let mut builder = self;
+        let mut tracks = Vec::with_capacity(configs.len());
+        for (index, config) in configs.iter().enumerate() {
+            match builder.push_configured_audio_track(config) {
+                Ok(track) => tracks.push(track),
+                Err(error) => {
+                    // The failed config's own track is already gone; the tracks of the earlier ones are the last added
+                    for _ in 0..tracks.len() {
+                        builder.remove_last_track();
+                    }
+                    return Err((builder, error, index));
+                },
             }
         }
+        Ok((builder, tracks))
     }
 
-    /// Sets color information for the specified video track.
-    pub fn set_color(
-        self,
-        track: VideoTrack,
-        bit_depth: u8,
-        subsampling: ColorSubsampling,
-        color_range: ColorRange,
-    ) -> Result<Self, Error> {
-        let color_range = match color_range {
-            ColorRange::Unspecified => 0,
-            ColorRange::Broadcast => 1,
-            ColorRange::Full => 2,
-        };
+    /// Adds a track for `config` and applies its settings, removing the track again if one fails.
+    fn push_configured_audio_track(&mut self, config: &AudioTrackConfig) -> Result<AudioTrack, Error> {
+        let track = self.push_audio_track(config.sample_rate, config.channels, config.codec, config.track_num)?;
+        if let Err(error) = self.configure_audio_track(track, config) {
+            self.remove_last_track();
+            return Err(error);
+        }
 
-        let result = unsafe {
-            ffi::mux::mux_set_color(
-                self.segment.as_ptr(),
-                track.into(),
-                bit_depth,
-                subsampling.chroma_horizontal,
-                subsampling.chroma_vertical,
-                color_range,
-            )
+        let track_num = TrackNum::from(track);
+        if !config.codec_private.is_empty() {
+            let head = OpusHead::parse(&config.codec_private);
+            if let (Some(head), Some(delays)) = (head, self.opus_delays(track_num)) {
+                delays.pre_skip_ns = Some(head.codec_delay_ns());
+            }
+            self.compare_codec_private(track_num, &config.codec_private);
+        }
+        if let Some(delays) = self.opus_delays(track_num) {
+            delays.codec_delay_ns = Some(config.codec_delay_ns).filter(|&delay| delay != 0);
+            delays.seek_pre_roll = config.seek_pre_roll_ns != 0;
+        }
+        if !config.default {
+            self.tracks.not_default.push(track_num);
+        }
+        Ok(track)
+    }
+
+    /// Applies the settings of `config` to the audio track just added for it, the `CodecPrivate` and delays first.
+    /// Like `configure_video_track`, this leaves what the builder records of them to the caller.
+    fn configure_audio_track(&self, track: AudioTrack, config: &AudioTrackConfig) -> Result<(), Error> {
+        let segment = self.segment.as_ptr();
+        let track_num = TrackNum::from(track);
+
+        if !config.codec_private.is_empty() {
+            let data = &config.codec_private;
+            let len = try_as_i32(data.len())?;
+            self.check(unsafe { ffi::mux::segment_set_codec_private(segment, track_num, data.as_ptr(), len) })?;
+        }
+        let codec_delay_ns = match OpusHead::parse(&config.codec_private) {
+            Some(head) if config.codec_delay_ns == 0 && config.codec == AudioCodecId::Opus => head.codec_delay_ns(),
+            _ => config.codec_delay_ns,
         };
+        if codec_delay_ns != 0 {
+            self.check(unsafe { ffi::mux::segment_set_codec_delay(segment, track_num, codec_delay_ns) })?;
+        }
+        if config.seek_pre_roll_ns != 0 {
+            self.check(unsafe { ffi::mux::segment_set_seek_pre_roll(segment, track_num, config.seek_pre_roll_ns) })?;
+        }
+        if let Some(bit_depth) = config.bit_depth {
+            self.check(unsafe { ffi::mux::mux_set_audio_bit_depth(segment, track_num, bit_depth.into()) })?;
+        }
+        if let Some(name) = &config.name {
+            let name = std::ffi::CString::new(name.as_str()).map_err(|_| Error::BadParam)?;
+            self.check(unsafe { ffi::mux::segment_set_track_name(segment, track_num, name.as_ptr()) })?;
+        }
+        if let Some(language) = &config.language {
+            let language = std::ffi::CString::new(language.as_str()).map_err(|_| Error::BadParam)?;
+            self.check(unsafe { ffi::mux::segment_set_track_language(segment, track_num, language.as_ptr()) })?;
+        }
+        if !config.default {
+            self.check(unsafe { ffi::mux::segment_set_track_default(segment, track_num, false) })?;
+        }
+        Ok(())
+    }
 
+    /// The outcome of a call on the segment.
+    fn check(&self, result: ResultCode) -> Result<(), Error> {
         match result {
-            ResultCode::Ok => Ok(self),
+            ResultCode::Ok => Ok(()),
             ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+            _ => Err(self.segment.libwebm_error()),
         }
     }
 
-    /// Finalizes track information and makes the segment ready to accept video/audio frames.
+    /// Sets the `CodecPrivate` data for the specified track. If you have a [`VideoTrack`] or [`AudioTrack`], you
+    /// can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
+    ///
+    /// For an Opus track, `data` is its `OpusHead`, and the track's `CodecDelay` is set to the header's pre-skip so
+    /// that players drop the encoder's priming samples, unless a `CodecDelay` has been set explicitly.
+    /// [`SegmentBuilder::build_strict`] checks that one set explicitly agrees with the pre-skip.
+    ///
+    /// The channels and sample rate in the `OpusHead` or the Vorbis identification header, and the maximum frame size
+    /// in the sequence header of an AV1 track, are compared with what the track was added with. Any that disagree are
+    /// recorded as a [`MuxWarning::CodecPrivateMismatch`], and make [`SegmentBuilder::build_strict`] fail.
+    pub fn set_codec_private(self, track: impl Into<TrackNum>, data: &[u8]) -> Result<Self, Error> {
+        let track_num = track.into();
+        let len: i32 = data.len().try_into().map_err(|_| Error::BadParam)?;
+        let result =
+            unsafe { ffi::mux::segment_set_codec_private(self.segment.as_ptr(), track_num, data.as_ptr(), len) };
+        self.check(result)?;
+
+        let mut builder = self;
+        builder.derive_codec_delay(track_num, data)?;
+        builder.compare_codec_private(track_num, data);
+        Ok(builder)
+    }
+
+    /// The problems found with what the builder has been given so far, which [`SegmentBuilder::build_strict`] would
+    /// fail on. [`Segment::warnings`] carries them on once the segment is built.
     #[must_use]
-    pub fn build(self) -> Segment<W> {
-        let Self { segment, writer } = self;
-        Segment {
-            ffi: segment,
-            writer,
-        }
+    pub fn warnings(&self) -> &[MuxWarning] {
+        &self.warnings
     }
-}
 
-impl<W: Write> std::fmt::Debug for SegmentBuilder<W> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // We can't/shouldn't crawl into our FFI pointers for debug printing, and we don't require `W: Debug`, but we
-        // should still have even a primitive Debug impl to avoid friction with user structs that #[derive(Debug)]
-        f.write_str(std::any::type_name::<Self>())
+    /// Records a warning for each field in `codec_private` that disagrees with how `track_num` was added, replacing
+    /// those of any earlier `CodecPrivate` of the track.
+    fn compare_codec_private(&mut self, track_num: TrackNum, codec_private: &[u8]) {
+        self.warnings.retain(|warning| match warning {
+            MuxWarning::CodecPrivateMismatch { track_num: num, .. } => *num != track_num,
+        });
+        let Some(&(_, media)) = self.media.iter().find(|&&(num, _)| num == track_num) else {
+            return;
+        };
+        let fields: Vec<(&'static str, u32, Option<u32>)> = match media {
+            MediaSpec::Audio {
+                sample_rate,
+                channels,
+                codec: AudioCodecId::Opus,
+            } => match OpusHead::parse(codec_private) {
+                Some(head) => {
+                    let input_rate = Some(head.input_sample_rate).filter(|&rate| rate != 0);
+                    vec![("channels", channels, Some(head.channels.into())), ("sample_rate", sample_rate, input_rate)]
+                },
+                None => Vec::new(),
+            },
+            MediaSpec::Audio {
+                sample_rate,
+                channels,
+                codec: AudioCodecId::Vorbis,
+            } => match VorbisIdentification::parse(codec_private) {
+                Some(header) => vec![
+                    ("channels", channels, Some(header.channels.into())),
+                    ("sample_rate", sample_rate, Some(header.sample_rate)),
+                ],
+                None => Vec::new(),
+            },
+            MediaSpec::Video {
+                width,
+                height,
+                codec: VideoCodecId::AV1,
+            } => match Av1SequenceHeader::parse(codec_private) {
+                // Frames may be smaller than the maximum, but none can be larger
+                Some(header) => vec![
+                    ("width", width, Some(header.max_frame_width).filter(|&max| width > max)),
+                    ("height", height, Some(header.max_frame_height).filter(|&max| height > max)),
+                ],
+                None => Vec::new(),
+            },
+            MediaSpec::Video { .. } => Vec::new(),
+        };
+        for (field, track_value, private_value) in fields {
+            if let Some(private_value) = private_value.filter(|&value| value != track_value) {
+                self.warnings.push(MuxWarning::CodecPrivateMismatch {
+                    track_num,
+                    field,
+                    track_value: track_value.into(),
+                    private_value: private_value.into(),
+                });
+            }
+        }
     }
-}
 
-/// A fully-built Matroska segment. This is where actual video/audio frames are written.
-///
-/// This is created via [`SegmentBuilder`]. Once built in this way, the list of tracks and their parameters become
-/// immutable.
-///
-/// ## Finalization
-/// Once you are done writing frames to this segment, you must call [`Segment::finalize`] on it.
-/// This performs a few final writes, and the resulting WebM may not be playable without it.
-/// Notably, for memory safety reasons, just dropping a [`Segment`] will not finalize it!
-pub struct Segment<W: Write> {
-    ffi: OwnedSegmentPtr,
-    writer: Writer<W>,
-}
+    /// The delays of `track_num`, if it is an Opus track.
+    fn opus_delays(&mut self, track_num: TrackNum) -> Option<&mut OpusDelays> {
+        self.opus.iter_mut().find(|delays| delays.track_num == track_num)
+    }
 
-// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
-// Thus, safety is only conditional on the write destination `W`, hence the `Send` bound on it.
-//
-// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
-unsafe impl<W: Write + Send> Send for Segment<W> {}
+    /// Sets the `CodecDelay` of `track_num` from `codec_private`, if it is an Opus track with a valid `OpusHead` and
+    /// no explicit `CodecDelay`.
+    fn derive_codec_delay(&mut self, track_num: TrackNum, codec_private: &[u8]) -> Result<(), Error> {
+        let Some(head) = OpusHead::parse(codec_private) else {
+            return Ok(());
+        };
+        let Some(delays) = self.opus_delays(track_num) else {
+            return Ok(());
+        };
+        let pre_skip_ns = head.codec_delay_ns();
+        delays.pre_skip_ns = Some(pre_skip_ns);
+        if delays.codec_delay_ns.is_none() {
+            self.check(unsafe { ffi::mux::segment_set_codec_delay(self.segment.as_ptr(), track_num, pre_skip_ns) })?;
+        }
+        Ok(())
+    }
 
-impl<W: Write> Segment<W> {
-    /// Adds a frame to the track with the specified track number. If you have a [`VideoTrack`] or
-    /// [`AudioTrack`], you can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
+    /// Marks the frames of the specified track as encrypted, for playback through Encrypted Media Extensions: this
+    /// writes a `ContentEncoding` for the track with a `ContentEncryption` that holds `key_id`, the key ID that
+    /// players request the key by. The frames themselves must be encrypted before they are written.
     ///
-    /// The timestamp must be in nanosecond units, and must be monotonically increasing with respect to all other
-    /// timestamps written so far, including those of other tracks! Repeating the last written timestamp is allowed,
-    /// however players generally don't handle this well if both such frames are on the same track.
-    pub fn add_frame(
-        &mut self,
+    /// Fails with [`Error::BadParam`] unless `key_id` is 16 bytes long, as Common Encryption requires, or if the
+    /// track is already encrypted.
+    pub fn add_content_encryption(
+        self,
         track: impl Into<TrackNum>,
-        data: &[u8],
-        timestamp_ns: u64,
-        keyframe: bool,
-    ) -> Result<(), Error> {
+        key_id: &[u8],
+        settings: EncryptionSettings,
+    ) -> Result<Self, Error> {
+        if key_id.len() != KEY_ID_LEN {
+            return Err(Error::BadParam);
+        }
+        let track_num = track.into();
         let result = unsafe {
-            ffi::mux::segment_add_frame(
-                self.ffi.as_ptr(),
-                track.into(),
-                data.as_ptr(),
-                data.len(),
-                timestamp_ns,
-                keyframe,
+            ffi::mux::segment_add_content_encryption(
+                self.segment.as_ptr(),
+                track_num,
+                key_id.as_ptr(),
+                key_id.len(),
+                settings.cipher_mode.get_id(),
             )
         };
+        self.check(result)?;
+        let mut builder = self;
+        builder.encrypted.push(track_num);
+        Ok(builder)
+    }
+
+    /// Sets the `CodecDelay` of the specified track, in nanoseconds: how much of the decoder's output comes before
+    /// the first frame's timestamp and is dropped by players, such as the pre-skip of an Opus stream.
+    ///
+    /// An Opus track whose `CodecPrivate` is an `OpusHead` gets its pre-skip as the `CodecDelay` without this, and
+    /// [`SegmentBuilder::build_strict`] fails with [`Error::CodecDelayMismatch`] if the two differ.
+    pub fn set_codec_delay(self, track: impl Into<TrackNum>, codec_delay_ns: u64) -> Result<Self, Error> {
+        let track_num = track.into();
+        let result = unsafe { ffi::mux::segment_set_codec_delay(self.segment.as_ptr(), track_num, codec_delay_ns) };
 
         match result {
-            ResultCode::Ok => Ok(()),
+            ResultCode::Ok => {
+                let mut builder = self;
+                if let Some(delays) = builder.opus_delays(track_num) {
+                    delays.codec_delay_ns = Some(codec_delay_ns);
+                }
+                Ok(builder)
+            },
             ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+            _ => Err(self.segment.libwebm_error()),
         }
     }
 
-    /// Finalizes the segment and consumes it, returning the underlying writer. Note that the finalizing process will
-    /// itself trigger writes (such as to write seeking information).
-    ///
-    /// The resulting WebM may not be playable if you drop the [`Segment`] without calling this first!
-    ///
-    /// You may specify an explicit `duration` to be written to the segment's `Duration` element. However, this requires
-    /// seeking and thus will be ignored if the writer was not created with [`Seek`](std::io::Seek) support.
-    ///
-    /// Finalization is known to fail if no frames have been written.
-    pub fn finalize(self, duration: Option<u64>) -> Result<Writer<W>, Writer<W>> {
-        let Self { ffi, writer } = self;
-        let result = unsafe { ffi::mux::finalize_segment(ffi.as_ptr(), duration.unwrap_or(0)) };
+    /// Sets the `CodecDelay` of the specified Opus track from the pre-skip of its stream, the number of samples at
+    /// 48 kHz that the decoder drops from the start of its output, whatever the track's sample rate. See
+    /// [`SegmentBuilder::set_codec_delay`].
+    pub fn set_opus_pre_skip(self, track: AudioTrack, pre_skip: u16) -> Result<Self, Error> {
+        // 65535 samples at 48 kHz can't overflow
+        let codec_delay_ns = ticks_to_ns(u64::from(pre_skip), Timebase::OPUS).map_err(|_| Error::BadParam)?;
+        self.set_codec_delay(track, codec_delay_ns)
+    }
+
+    /// Sets the track that cue points are written for. By default, this is the first video track.
+    pub(crate) fn set_cues_track(self, track: impl Into<TrackNum>) -> Result<Self, Error> {
+        let result = unsafe { ffi::mux::segment_set_cues_track(self.segment.as_ptr(), track.into()) };
 
         match result {
-            ResultCode::Ok => Ok(writer),
-            _ => Err(writer),
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
         }
     }
-}
 
-impl<W: Write> std::fmt::Debug for Segment<W> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // We can't/shouldn't crawl into our FFI pointers for debug printing, and we don't require `W: Debug`, but we
-        // should still have even a primitive Debug impl to avoid friction with user structs that #[derive(Debug)]
-        f.write_str(std::any::type_name::<Self>())
+    /// Sets the `FlagDefault` of the specified track, which tells players to pick it over other tracks of its kind.
+    pub(crate) fn set_default_flag(self, track: impl Into<TrackNum>, default: bool) -> Result<Self, Error> {
+        let track_num = track.into();
+        let result = unsafe { ffi::mux::segment_set_track_default(self.segment.as_ptr(), track_num, default) };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                builder.tracks.not_default.retain(|&num| num != track_num);
+                if !default {
+                    builder.tracks.not_default.push(track_num);
+                }
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the flags of the specified track that are `Some` in `flags`. A flag is only written while it differs from
+    /// what Matroska takes a missing one to mean, so e.g. a `FlagForced` of false isn't written at all.
+    ///
+    /// Clearing `default` on all but one track of a kind is what [`SegmentBuilder::set_default_video_track`] and
+    /// [`SegmentBuilder::set_default_audio_track`] do; [`SegmentBuilder::build_strict`] checks the result either way.
+    pub fn set_track_flags(self, track: impl Into<TrackNum>, flags: TrackFlags) -> Result<Self, Error> {
+        let track_num = track.into();
+        let mut builder = self;
+        if let Some(default) = flags.default {
+            builder = builder.set_default_flag(track_num, default)?;
+        }
+        let segment = builder.segment.as_ptr();
+        if let Some(enabled) = flags.enabled {
+            builder.check(unsafe { ffi::mux::segment_set_track_enabled(segment, track_num, enabled) })?;
+        }
+        if let Some(forced) = flags.forced {
+            builder.check(unsafe { ffi::mux::segment_set_track_forced(segment, track_num, forced) })?;
+        }
+        Ok(builder)
+    }
+
+    /// Makes `track` the one video track that players pick by default, clearing `FlagDefault` on every other video
+    /// track of the segment.
+    ///
+    /// Matroska takes a track without a `FlagDefault` to be a default track, so until this is called, every track
+    /// is, and players differ in which of several default tracks they pick. Tracks added afterwards are default
+    /// tracks too, so call this once all tracks have been added; [`SegmentBuilder::build_strict`] checks for this.
+    pub fn set_default_video_track(self, track: VideoTrack) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        self.set_default_track(track.into(), TrackKind::Video)
+    }
+
+    /// Makes `track` the one audio track that players pick by default, clearing `FlagDefault` on every other audio
+    /// track of the segment. See [`SegmentBuilder::set_default_video_track`].
+    pub fn set_default_audio_track(self, track: AudioTrack) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Audio)?;
+        self.set_default_track(track.into(), TrackKind::Audio)
+    }
+
+    fn set_default_track(self, default_track: TrackNum, kind: TrackKind) -> Result<Self, Error> {
+        let track_nums: Vec<TrackNum> = self.tracks.of_kind(kind).collect();
+        let mut builder = self;
+        for track_num in track_nums {
+            builder = builder.set_default_flag(track_num, track_num == default_track)?;
+        }
+        Ok(builder)
+    }
+
+    /// Sets the `DefaultDuration` of the specified track, the nominal duration of each of its frames in nanoseconds,
+    /// e.g. 20 000 000 for Opus packets of 20 ms, or 1 000 000 000 / 30 for 30 fps video. Players use it to tell the
+    /// frame rate or packet cadence, and the duration of frames without a `BlockDuration`. Fails with
+    /// [`Error::BadParam`] if `duration_ns` is zero.
+    pub fn set_default_duration(self, track: impl Into<TrackNum>, duration_ns: u64) -> Result<Self, Error> {
+        if duration_ns == 0 {
+            return Err(Error::BadParam);
+        }
+        let result =
+            unsafe { ffi::mux::segment_set_default_duration(self.segment.as_ptr(), track.into(), duration_ns) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `TrackUID` of the specified track, in place of the one generated when it was added, so that the
+    /// track keeps its UID when a file is muxed again, e.g. for tags and chapters kept apart from it that refer to
+    /// it. Fails with [`Error::BadParam`] if `uid` is zero or another track of the segment has it.
+    pub fn set_track_uid(self, track: impl Into<TrackNum>, uid: u64) -> Result<Self, Error> {
+        if uid == 0 {
+            return Err(Error::BadParam);
+        }
+        let result = unsafe { ffi::mux::segment_set_track_uid(self.segment.as_ptr(), track.into(), uid) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// The `TrackUID` of the specified track: the one set with [`SegmentBuilder::set_track_uid`], or else the one
+    /// generated when it was added.
+    pub fn track_uid(&self, track: impl Into<TrackNum>) -> Result<u64, Error> {
+        let mut uid = 0;
+        let result = unsafe { ffi::mux::segment_get_track_uid(self.segment.as_ptr(), track.into(), &mut uid) };
+        self.check(result)?;
+        Ok(uid)
+    }
+
+    /// Sets the `SeekPreRoll` of the specified track, in nanoseconds: how long before a seek target players must
+    /// start decoding for the output to be right by then. The Opus specification recommends 80 ms, which
+    /// [`SegmentBuilder::build_strict`] gives Opus tracks whose `SeekPreRoll` hasn't been set.
+    pub fn set_seek_pre_roll(self, track: impl Into<TrackNum>, seek_pre_roll_ns: u64) -> Result<Self, Error> {
+        let track_num = track.into();
+        let result =
+            unsafe { ffi::mux::segment_set_seek_pre_roll(self.segment.as_ptr(), track_num, seek_pre_roll_ns) };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                if let Some(delays) = builder.opus_delays(track_num) {
+                    delays.seek_pre_roll = true;
+                }
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the human-readable `Name` of the specified track, e.g. `Commentary`, which players show in their track
+    /// menus. Fails with [`Error::BadParam`] if `name` contains a nul character, as [`SegmentBuilder::set_writing_app`]
+    /// does.
+    pub fn set_track_name(self, track: impl Into<TrackNum>, name: &str) -> Result<Self, Error> {
+        let name = std::ffi::CString::new(name).map_err(|_| Error::BadParam)?;
+        let result = unsafe { ffi::mux::segment_set_track_name(self.segment.as_ptr(), track.into(), name.as_ptr()) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `CodecName` of the specified track, a human-readable name for its codec such as `AV1 (SVT-AV1)`, which
+    /// some tools show alongside the `CodecID`. An empty name removes it. Fails with [`Error::BadParam`] if `name`
+    /// contains a NUL.
+    pub fn set_codec_name(self, track: impl Into<TrackNum>, name: &str) -> Result<Self, Error> {
+        let name = std::ffi::CString::new(name).map_err(|_| Error::BadParam)?;
+        let result = unsafe { ffi::mux::segment_set_codec_name(self.segment.as_ptr(), track.into(), name.as_ptr()) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `Language` of the specified track, as an ISO 639-2 code such as `eng`, or `und` if it's unknown.
+    /// Players use it to pick audio and subtitle tracks and to label them in their menus. Fails with
+    /// [`Error::BadParam`] unless `language` is three lowercase ASCII letters.
+    pub fn set_track_language(self, track: impl Into<TrackNum>, language: &str) -> Result<Self, Error> {
+        if !is_iso_639_2(language) {
+            return Err(Error::BadParam);
+        }
+        let language = std::ffi::CString::new(language).map_err(|_| Error::BadParam)?;
+        let result =
+            unsafe { ffi::mux::segment_set_track_language(self.segment.as_ptr(), track.into(), language.as_ptr()) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `LanguageBCP47` of the specified track, a BCP 47 language tag such as `en-GB` or `zh-Hant`, which
+    /// players that know it prefer to the [`Language`](SegmentBuilder::set_track_language). Fails with
+    /// [`Error::BadParam`] unless `language` is made of subtags of one to eight ASCII letters and digits, separated
+    /// by hyphens, the first of them letters only. Whether the subtags are registered isn't checked.
+    pub fn set_track_language_bcp47(self, track: impl Into<TrackNum>, language: &str) -> Result<Self, Error> {
+        if !is_bcp_47(language) {
+            return Err(Error::BadParam);
+        }
+        let language = std::ffi::CString::new(language).map_err(|_| Error::BadParam)?;
+        let result = unsafe {
+            ffi::mux::segment_set_track_language_bcp47(self.segment.as_ptr(), track.into(), language.as_ptr())
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Attaches a file, such as cover art or a font, with the given file name, media type (e.g. `image/jpeg`) and
+    /// `FileUID`. Fails with [`Error::BadParam`] if `name` or `mime` is empty, or if `uid` is zero.
+    ///
+    /// WebM forbids the `Attachments` element, and every segment is strict WebM: `libwebm` chooses the `DocType` from
+    /// the codecs, and all the codecs it writes are WebM ones. Otherwise valid attachments are therefore rejected with
+    /// [`Error::NotInWebm`], leaving nothing written.
+    pub fn add_attachment(self, name: &str, mime: &str, data: &[u8], uid: Option<u64>) -> Result<Self, Error> {
+        if name.is_empty() || mime.is_empty() || uid == Some(0) {
+            return Err(Error::BadParam);
+        }
+        let _ = data;
+        Err(Error::NotInWebm {
+            element: "Attachments",
+        })
+    }
+
+    /// Sets how many nanoseconds each timestamp unit in the file represents. The default is 1 000 000, i.e.
+    /// milliseconds.
+    pub(crate) fn set_timecode_scale(self, timecode_scale: u64) -> Result<Self, Error> {
+        let result = unsafe { ffi::mux::set_timecode_scale(self.segment.as_ptr(), timecode_scale) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets whether the segment is written for live streaming: without cues, and without going back to fill in its
+    /// size or duration.
+    pub(crate) fn set_live(self, live: bool) -> Result<Self, Error> {
+        let result = unsafe { ffi::mux::set_live(self.segment.as_ptr(), live) };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                builder.live = live;
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Bounds how long written frames can wait before their bytes reach the writer, for low-latency live streaming.
+    ///
+    /// A new cluster is started once a frame arrives `max_ns` or more after the first frame of the current cluster
+    /// that hasn't been flushed yet, and the writer's destination is flushed at the start of each cluster. `libwebm`
+    /// holds audio frames back until the next video frame, so that the audio at a keyframe is in the keyframe's
+    /// cluster; with sparse video that could be for much longer, so audio that has been held back for `max_ns` is
+    /// written out, with a new cluster started for the frame that arrives then.
+    ///
+    /// Latency is measured in the frames' timestamps, so it is only kept when frames are written as they are
+    /// captured. This fails with [`Error::BadParam`] if `max_ns` is zero, or if the segment is set up with
+    /// [`SegmentBuilder::dash_live`], whose clusters must only start at keyframes.
+    pub fn target_cluster_latency(self, max_ns: u64) -> Result<Self, Error> {
+        if max_ns == 0 || self.dash.is_some() {
+            return Err(Error::BadParam);
+        }
+        let result = unsafe { ffi::mux::segment_set_max_cluster_duration(self.segment.as_ptr(), max_ns) };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                builder.latency_ns = Some(max_ns);
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the segment up for live DASH, whose media segments are clusters of a fixed nominal duration that each
+    /// start with a keyframe, and which has no cues.
+    ///
+    /// The segment is written live, without going back to fill in its size. Media segments are due every
+    /// `segment_duration_ns` from the first keyframe, and a new cluster is started at the first keyframe within
+    /// `keyframe_tolerance_ns` of the boundary, or any keyframe after it. Keyframes are those of the video tracks, or
+    /// of the audio tracks if there is no video track. Frames written while a keyframe is overdue still go in the
+    /// current cluster, up to `keyframe_tolerance_ns` after the boundary; after that, [`Segment::add_frame`] fails
+    /// with [`Error::KeyframeOverdue`] for anything but a keyframe of those tracks, so ask the encoder for one. The
+    /// segment must start with such a keyframe too.
+    ///
+    /// Audio isn't held back for the next video frame as it is otherwise, so that the audio before a keyframe stays in
+    /// the cluster before it. `libwebm` starts a new cluster at every video keyframe, so the encoder should only
+    /// produce keyframes at media segment boundaries: one in the middle of a media segment splits it in two.
+    ///
+    /// Fails with [`Error::BadParam`] if `segment_duration_ns` is zero, if `keyframe_tolerance_ns` isn't less than
+    /// half of it, or if a [`SegmentBuilder::target_cluster_latency`] is set, as that would start clusters between
+    /// keyframes.
+    pub fn dash_live(self, segment_duration_ns: u64, keyframe_tolerance_ns: u64) -> Result<Self, Error> {
+        if segment_duration_ns == 0 || keyframe_tolerance_ns >= segment_duration_ns / 2 || self.latency_ns.is_some() {
+            return Err(Error::BadParam);
+        }
+        let mut builder = self.set_live(true)?;
+        builder.dash = Some((segment_duration_ns, keyframe_tolerance_ns));
+        Ok(builder)
+    }
+
+    /// Sets up a video track for variable frame rate, as from screen capture, where frames arrive at irregular
+    /// intervals. The track has no `DefaultDuration`; instead, each frame gets a `BlockDuration` of the time until the
+    /// track's next frame.
+    ///
+    /// To know that, [`Segment::add_frame`] holds each frame of the track until the next one, so the track's frames
+    /// must have increasing timestamps. Frames of any track written in the meantime wait behind it, to be written in
+    /// order. The track's last frame is written by [`Segment::finish_vfr`], or by [`Segment::finalize`] with an
+    /// estimated duration.
+    pub fn vfr_video(self, track: VideoTrack) -> Result<Self, Error> {
+        let track_num = TrackNum::from(track);
+        self.tracks.check(track.1, track_num, TrackKind::Video)?;
+        let mut builder = self;
+        if !builder.vfr.contains(&track_num) {
+            builder.vfr.push(track_num);
+        }
+        Ok(builder)
+    }
+
+    /// Checks the keyframe flag of each video frame against its bitstream, as [`sniff::is_keyframe`] reads it, so
+    /// that a wrong flag can't silently break seeking. What is done with a frame whose flag is wrong is set with
+    /// [`SegmentBuilder::keyframe_flag_policy`]; by default, it fails with [`Error::KeyframeFlag`].
+    ///
+    /// Frames that can't be sniffed, such as those of encrypted tracks, are written as flagged.
+    #[must_use]
+    pub fn verify_keyframe_flags(self, verify: bool) -> Self {
+        Self {
+            verify_keyframes: verify,
+            ..self
+        }
+    }
+
+    /// Sets what [`SegmentBuilder::verify_keyframe_flags`] does with a frame whose keyframe flag is wrong.
+    #[must_use]
+    pub fn keyframe_flag_policy(self, policy: KeyframeFlagPolicy) -> Self {
+        Self {
+            keyframe_policy: policy,
+            ..self
+        }
+    }
+
+    /// Anchors the segment to absolute time, for archives that must tell when each frame was captured: media time
+    /// zero is at `start`. This sets the `DateUTC` of the segment to `start`, and adds a tag for the whole file named
+    /// [`TIMECODE_OFFSET_TAG`] with `start` in nanoseconds from the Unix epoch, which
+    /// [`Demuxer::wall_clock_anchor`](crate::demux::Demuxer::wall_clock_anchor) reads back. [`Segment::media_to_utc`]
+    /// maps timestamps to UTC.
+    ///
+    /// Fails with [`Error::Time`] if `start` is too far from 2001 for a `DateUTC`, about 292 years. A segment that
+    /// isn't live can't have both tags and chapters, so this fails with [`Error::BadParam`] if chapters have been
+    /// added to one, and adding chapters fails afterwards.
+    pub fn anchor_wall_clock(self, start: SystemTime) -> Result<Self, Error> {
+        let unix_ns = crate::time::unix_ns(start);
+        let date_utc = i64::try_from(unix_ns - MATROSKA_EPOCH_UNIX_NS).map_err(|_| TimeError::Overflow)?;
+        let builder = self.add_tag(&[(TIMECODE_OFFSET_TAG.into(), unix_ns.to_string())])?;
+        let result = unsafe { ffi::mux::set_date_utc(builder.segment.as_ptr(), date_utc) };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = builder;
+                builder.anchor = Some(start);
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(builder.segment.libwebm_error()),
+        }
+    }
+
+    /// Adds a chapter with one title, identified by `id` as its `ChapterStringUID`.
+    pub(crate) fn add_chapter(
+        self,
+        id: &str,
+        start_ns: u64,
+        end_ns: u64,
+        title: &str,
+        language: Option<&str>,
+    ) -> Result<Self, Error> {
+        if self.tags && !self.live {
+            return Err(Error::BadParam);
+        }
+        let id = std::ffi::CString::new(id).map_err(|_| Error::BadParam)?;
+        let title = std::ffi::CString::new(title).map_err(|_| Error::BadParam)?;
+        let language = language.map(std::ffi::CString::new).transpose().map_err(|_| Error::BadParam)?;
+        let result = unsafe {
+            ffi::mux::segment_add_chapter(
+                self.segment.as_ptr(),
+                id.as_ptr(),
+                start_ns,
+                end_ns,
+                title.as_ptr(),
+                language.as_ref().map_or(std::ptr::null(), |language| language.as_ptr()),
+            )
+        };
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                builder.chapters = true;
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Adds a tag for the whole file, with the given name/value pairs.
+    pub(crate) fn add_tag(self, simple_tags: &[(String, String)]) -> Result<Self, Error> {
+        if self.chapters && !self.live {
+            return Err(Error::BadParam);
+        }
+        let simple_tags = simple_tags
+            .iter()
+            .map(|(name, value)| Ok((std::ffi::CString::new(name.as_str())?, std::ffi::CString::new(value.as_str())?)))
+            .collect::<Result<Vec<_>, std::ffi::NulError>>()
+            .map_err(|_| Error::BadParam)?;
+
+        let mut result = unsafe { ffi::mux::segment_add_tag(self.segment.as_ptr()) };
+        for (name, value) in &simple_tags {
+            if result != ResultCode::Ok {
+                break;
+            }
+            result = unsafe { ffi::mux::segment_add_simple_tag(self.segment.as_ptr(), name.as_ptr(), value.as_ptr()) };
+        }
+
+        match result {
+            ResultCode::Ok => {
+                let mut builder = self;
+                builder.tags = true;
+                Ok(builder)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets color information for the specified video track. This replaces the `Range` of a preset set with
+    /// [`SegmentBuilder::set_color_preset`], but keeps its other values.
+    ///
+    /// Fails with [`Error::BadParam`] unless `bit_depth` is 8, 10 or 12 and both subsampling factors are 0 or 1.
+    pub fn set_color(
+        self,
+        track: VideoTrack,
+        bit_depth: u8,
+        subsampling: ColorSubsampling,
+        color_range: ColorRange,
+    ) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        if !color_format_is_valid(bit_depth, &subsampling) {
+            return Err(Error::BadParam);
+        }
+
+        let result = unsafe {
+            ffi::mux::mux_set_color(
+                self.segment.as_ptr(),
+                track.into(),
+                bit_depth,
+                subsampling.chroma_horizontal,
+                subsampling.chroma_vertical,
+                color_range_id(color_range),
+            )
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets where the subsampled chroma samples of the specified video track sit, horizontally and vertically, as
+    /// written to `ChromaSitingHorz` and `ChromaSitingVert`. A direction that is
+    /// [`Unspecified`](ChromaSiting::Unspecified) is left out, replacing any siting set there by
+    /// [`SegmentBuilder::set_color_preset`].
+    pub fn set_chroma_siting(
+        self,
+        track: VideoTrack,
+        horizontal: ChromaSiting,
+        vertical: ChromaSiting,
+    ) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe {
+            ffi::mux::mux_set_chroma_siting(self.segment.as_ptr(), track.into(), horizontal as u8, vertical as u8)
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets how the colors of the specified video track are encoded, e.g. to [`ColorDescription::BT709`]. The bit
+    /// depth and subsampling that [`SegmentBuilder::set_color`] sets are kept, but both methods write the `Range`, so
+    /// whichever is called last sets it. Fails with [`Error::BadParam`] if a code point or chroma siting is one that
+    /// `libwebm` can't write.
+    pub fn set_color_preset(self, track: VideoTrack, preset: ColorDescription) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe {
+            ffi::mux::mux_set_color_description(
+                self.segment.as_ptr(),
+                track.into(),
+                preset.primaries,
+                preset.transfer_characteristics,
+                preset.matrix_coefficients,
+                color_range_id(preset.range),
+                preset.chroma_siting_horizontal as u8,
+                preset.chroma_siting_vertical as u8,
+            )
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `Primaries`, `TransferCharacteristics` and `MatrixCoefficients` of the specified video track, e.g.
+    /// [`ColourPrimaries::Bt2020`], [`TransferCharacteristics::Pq`] and [`MatrixCoefficients::Bt2020Ncl`] for HDR10.
+    /// Each that is `Unspecified` is left out. Unlike [`SegmentBuilder::set_color_preset`], this keeps the range and
+    /// chroma siting the track already has.
+    pub fn set_color_description(
+        self,
+        track: VideoTrack,
+        primaries: ColourPrimaries,
+        transfer_characteristics: TransferCharacteristics,
+        matrix_coefficients: MatrixCoefficients,
+    ) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe {
+            ffi::mux::mux_set_color_code_points(
+                self.segment.as_ptr(),
+                track.into(),
+                primaries as u8,
+                transfer_characteristics as u8,
+                matrix_coefficients as u8,
+            )
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `DisplayWidth` and `DisplayHeight` of the specified video track, the size players show its frames at
+    /// in place of their coded size, e.g. 853x480 for 720x480 anamorphic widescreen DVD video. Fails with
+    /// [`Error::BadParam`] if either is zero. [`SegmentBuilder::set_pixel_aspect_ratio`] works the display size out
+    /// from the shape of the pixels instead.
+    pub fn set_display_size(self, track: VideoTrack, display_width: u32, display_height: u32) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe {
+            ffi::mux::mux_set_display_size(self.segment.as_ptr(), track.into(), display_width, display_height)
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets how the two views of the specified stereoscopic video track are packed into its frames. Fails with
+    /// [`Error::BadParam`] for a mode that WebM doesn't allow, such as [`StereoMode::AnaglyphCyanRed`].
+    pub fn set_stereo_mode(self, track: VideoTrack, mode: StereoMode) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe { ffi::mux::mux_set_stereo_mode(self.segment.as_ptr(), track.into(), mode as u8) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `OutputSamplingFrequency` of the specified audio track, the rate in Hz that its decoder outputs at when
+    /// that differs from the rate of the input, which [`SegmentBuilder::add_audio_track`] writes as the
+    /// `SamplingFrequency`. Opus always decodes at 48 kHz, so the WebM guidelines for Opus have a track of 44.1 kHz
+    /// input declare an output rate of 48 000. Fails with [`Error::ForeignTrack`] or [`Error::UnknownTrack`] if
+    /// `track` isn't an audio track of this segment, and with [`Error::BadParam`] if `rate` isn't positive and finite.
+    pub fn set_output_sample_rate(self, track: AudioTrack, rate: f64) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Audio)?;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(Error::BadParam);
+        }
+        let result = unsafe { ffi::mux::mux_set_output_sample_rate(self.segment.as_ptr(), track.into(), rate) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `AspectRatioType` of the specified video track, which says whether players may stretch its frames,
+    /// must keep the aspect ratio of its display size, or must show them at that size. Fails with
+    /// [`Error::UnknownTrack`] if `track` isn't a video track.
+    pub fn set_aspect_ratio_type(self, track: VideoTrack, ty: AspectRatioType) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe { ffi::mux::mux_set_aspect_ratio_type(self.segment.as_ptr(), track.into(), ty as u8) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets whether the specified video track is interlaced and in which order its fields are, as its
+    /// `FlagInterlaced` and `FieldOrder`. Marking a track [`InterlaceMode::Progressive`] with
+    /// [`FieldOrder::Progressive`] tells deinterlacers to leave it alone, and [`InterlaceMode::Undetermined`] with
+    /// [`FieldOrder::Undetermined`] takes the marking away again.
+    ///
+    /// Fails with [`Error::NotInWebm`] for [`InterlaceMode::Interlaced`], since the WebM format doesn't allow
+    /// interlaced video and Matroska files, which do, can't be written yet. Fails with [`Error::BadParam`] for any
+    /// other combination.
+    pub fn set_interlacing(self, track: VideoTrack, mode: InterlaceMode, order: FieldOrder) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        match (mode, order) {
+            (InterlaceMode::Progressive, FieldOrder::Progressive) => {},
+            (InterlaceMode::Undetermined, FieldOrder::Undetermined) => {},
+            (InterlaceMode::Interlaced, _) => return Err(Error::NotInWebm { element: "FlagInterlaced" }),
+            _ => return Err(Error::BadParam),
+        }
+        let result =
+            unsafe { ffi::mux::mux_set_interlacing(self.segment.as_ptr(), track.into(), mode as u8, order as u8) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `Projection` of the specified video track, for 360° or VR video. Fails with [`Error::BadParam`] for
+    /// [`ProjectionType::Other`], or if a rotation of the pose isn't within its range: -180 to 180 degrees for yaw and
+    /// roll, and -90 to 90 for pitch.
+    pub fn set_projection(self, track: VideoTrack, proj: &Projection) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let projection_type = match proj.projection_type {
+            ProjectionType::Rectangular => 0,
+            ProjectionType::Equirectangular => 1,
+            ProjectionType::CubeMap => 2,
+            ProjectionType::Mesh => 3,
+            ProjectionType::Other(_) => return Err(Error::BadParam),
+        };
+        let in_range = |degrees: f32, limit: f32| (-limit..=limit).contains(&degrees);
+        if !(in_range(proj.pose_yaw, 180.0) && in_range(proj.pose_pitch, 90.0) && in_range(proj.pose_roll, 180.0)) {
+            return Err(Error::BadParam);
+        }
+        let private_data = if proj.private_data.is_empty() { std::ptr::null() } else { proj.private_data.as_ptr() };
+
+        let result = unsafe {
+            ffi::mux::mux_set_projection(
+                self.segment.as_ptr(),
+                track.into(),
+                projection_type,
+                private_data,
+                proj.private_data.len(),
+                proj.pose_yaw,
+                proj.pose_pitch,
+                proj.pose_roll,
+            )
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `FrameRate` of the specified video track, in frames per second. This is only a hint, which some
+    /// players and tools read to size their buffers; the timestamps of the frames are what times them. Fails with
+    /// [`Error::BadParam`] unless `fps` is finite and positive, also once narrowed to the `f32` that is written.
+    pub fn set_frame_rate(self, track: VideoTrack, fps: f64) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        if !fps.is_finite() || fps <= 0.0 || !(fps as f32).is_finite() || fps as f32 <= 0.0 {
+            return Err(Error::BadParam);
+        }
+        let result = unsafe { ffi::mux::mux_set_frame_rate(self.segment.as_ptr(), track.into(), fps) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Marks the specified video track as having an alpha channel, by setting its `AlphaMode` and a
+    /// `MaxBlockAdditionID` of at least 1. VP8 and VP9 carry the encoded alpha plane of each frame in a
+    /// `BlockAdditional` with ID 1, which browsers ignore unless the track says to look for it. Fails with
+    /// [`Error::ForeignTrack`] or [`Error::UnknownTrack`] if `track` isn't a video track of this segment.
+    pub fn enable_alpha(self, track: VideoTrack) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result = unsafe { ffi::mux::mux_enable_alpha(self.segment.as_ptr(), track.into()) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `PixelCrop` of the specified video track: how many pixels players cut off the top, bottom, left and
+    /// right of its frames before showing them, e.g. the padding an encoder adds to round the frame size up to whole
+    /// macroblocks. Fails with [`Error::BadParam`] if the crop would leave nothing of the frames in either direction.
+    pub fn set_pixel_crop(
+        self,
+        track: VideoTrack,
+        top: u32,
+        bottom: u32,
+        left: u32,
+        right: u32,
+    ) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result =
+            unsafe { ffi::mux::mux_set_pixel_crop(self.segment.as_ptr(), track.into(), top, bottom, left, right) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `MaxCLL` and `MaxFALL` of the specified video track: the brightest pixel of any frame, and the highest
+    /// average brightness of a frame, in cd/m². The track's other color information is kept, so this can be called
+    /// with or without [`SegmentBuilder::set_color`].
+    pub fn set_content_light_level(self, track: VideoTrack, max_cll: u16, max_fall: u16) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result =
+            unsafe { ffi::mux::mux_set_content_light_level(self.segment.as_ptr(), track.into(), max_cll, max_fall) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the SMPTE ST 2086 `MasteringMetadata` of the specified video track: the primaries, white point and
+    /// luminance range of the display that HDR content was mastered on, which players need to tone-map it. Fields that
+    /// are `None` are left out. The track's other color information is kept.
+    ///
+    /// Fails with [`Error::BadParam`] if a chromaticity coordinate is outside 0 to 1 or a luminance is negative, and if
+    /// the minimum luminance is above the maximum or either is above what `libwebm` can write (999.99 cd/m² for the
+    /// minimum and 9999.99 cd/m² for the maximum). `libwebm` also can't write a maximum luminance without a minimum.
+    pub fn set_mastering_metadata(self, track: VideoTrack, mm: &MasteringMetadata) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let mut chromaticities = [-1.0; 8];
+        for (i, chromaticity) in [mm.primary_r, mm.primary_g, mm.primary_b, mm.white_point].into_iter().enumerate() {
+            let Some(chromaticity) = chromaticity else {
+                continue;
+            };
+            if ![chromaticity.x, chromaticity.y].iter().all(|coordinate| (0.0..=1.0).contains(coordinate)) {
+                return Err(Error::BadParam);
+            }
+            chromaticities[2 * i..2 * i + 2].copy_from_slice(&[chromaticity.x, chromaticity.y]);
+        }
+        let mut luminances = [-1.0; 2];
+        for (luminance, out) in [mm.luminance_max, mm.luminance_min].into_iter().zip(&mut luminances) {
+            if let Some(luminance) = luminance {
+                if luminance.is_nan() || luminance < 0.0 {
+                    return Err(Error::BadParam);
+                }
+                *out = luminance;
+            }
+        }
+
+        let result = unsafe {
+            ffi::mux::mux_set_mastering_metadata(
+                self.segment.as_ptr(),
+                track.into(),
+                &chromaticities,
+                luminances[0],
+                luminances[1],
+            )
+        };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Sets the `DisplayWidth` and `DisplayHeight` of the specified video track from the aspect ratio of its pixels,
+    /// e.g. 10:11 for 4:3 NTSC video from a DV source. The width of the frames, less any crop, is scaled by
+    /// `par_num / par_den` and rounded to the nearest even number, halves up; the display height is the cropped height
+    /// of the frames. A 720x480 track with 10:11 pixels is thus displayed at 654x480. The crop is the one set by then
+    /// with [`SegmentBuilder::set_pixel_crop`].
+    ///
+    /// Fails with [`Error::BadParam`] if either side of the ratio is zero, or if the display width would round to zero.
+    pub fn set_pixel_aspect_ratio(self, track: VideoTrack, par_num: u32, par_den: u32) -> Result<Self, Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        let result =
+            unsafe { ffi::mux::mux_set_pixel_aspect_ratio(self.segment.as_ptr(), track.into(), par_num, par_den) };
+
+        match result {
+            ResultCode::Ok => Ok(self),
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.segment.libwebm_error()),
+        }
+    }
+
+    /// Like [`SegmentBuilder::build`], but first checks for mistakes that `libwebm` would write without complaint.
+    /// Fails with [`Error::DefaultTracks`] if more than one track of a kind is a default track, which is the case for
+    /// several tracks of a kind unless [`SegmentBuilder::set_default_video_track`] or
+    /// [`SegmentBuilder::set_default_audio_track`] has picked one, and with [`Error::CodecDelayMismatch`] if an Opus
+    /// track's `CodecDelay` was set to something other than the pre-skip of its `OpusHead`. Fails with
+    /// [`Error::CodecPrivateMismatch`] for the first of the [`SegmentBuilder::warnings`] about a `CodecPrivate`.
+    ///
+    /// Opus tracks whose `SeekPreRoll` hasn't been set get the 80 ms that the Opus specification recommends.
+    pub fn build_strict(self) -> Result<Segment<W>, Error> {
+        for kind in [TrackKind::Video, TrackKind::Audio] {
+            let defaults: Vec<TrackNum> =
+                self.tracks.of_kind(kind).filter(|track_num| !self.tracks.not_default.contains(track_num)).collect();
+            if defaults.len() > 1 {
+                return Err(Error::DefaultTracks { track_nums: defaults });
+            }
+        }
+        for delays in &self.opus {
+            if let (Some(pre_skip_ns), Some(codec_delay_ns)) = (delays.pre_skip_ns, delays.codec_delay_ns) {
+                if pre_skip_ns != codec_delay_ns {
+                    return Err(Error::CodecDelayMismatch {
+                        track_num: delays.track_num,
+                        codec_delay_ns,
+                        pre_skip_ns,
+                    });
+                }
+            }
+        }
+
+        if let Some(MuxWarning::CodecPrivateMismatch {
+            track_num,
+            field,
+            track_value,
+            private_value,
+        }) = self.warnings.first()
+        {
+            return Err(Error::CodecPrivateMismatch {
+                track_num: *track_num,
+                field,
+                track_value: *track_value,
+                private_value: *private_value,
+            });
+        }
+
+        let mut builder = self;
+        for delays in builder.opus.clone() {
+            if !delays.seek_pre_roll {
+                builder = builder.set_seek_pre_roll(delays.track_num, OPUS_SEEK_PRE_ROLL_NS)?;
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// Finalizes track information and makes the segment ready to accept video/audio frames.
+    #[must_use]
+    pub fn build(self) -> Segment<W> {
+        let Self {
+            segment,
+            writer,
+            start,
+            tracks,
+            latency_ns,
+            anchor,
+            dash,
+            vfr,
+            opus,
+            media,
+            warnings,
+            encrypted,
+            verify_keyframes,
+            keyframe_policy,
+            ..
+        } = self;
+        if dash.is_some() {
+            // Audio held back for the next video frame would go in the keyframe's cluster, ahead of the keyframe
+            unsafe { ffi::mux::segment_set_hold_audio(segment.as_ptr(), false) };
+        }
+        let dash = dash.map(|(segment_ns, tolerance_ns)| Dash {
+            segment_ns,
+            tolerance_ns,
+            kind: if tracks.of_kind(TrackKind::Video).next().is_some() {
+                TrackKind::Video
+            } else {
+                TrackKind::Audio
+            },
+            next_boundary_ns: None,
+        });
+        Segment {
+            ffi: segment,
+            writer,
+            start,
+            tracks,
+            sync: SyncTracker::default(),
+            latency: latency_ns.map(|max_ns| Latency {
+                max_ns,
+                unflushed_since_ns: None,
+                clusters: 0,
+            }),
+            dash,
+            vfr: vfr
+                .into_iter()
+                .map(|track_num| Vfr {
+                    track_num,
+                    last_duration_ns: None,
+                })
+                .collect(),
+            opus: opus.iter().map(|delays| delays.track_num).collect(),
+            pending: VecDeque::new(),
+            warnings,
+            keyframes: verify_keyframes.then(|| KeyframeCheck {
+                policy: keyframe_policy,
+                codecs: media
+                    .into_iter()
+                    .filter_map(|(track_num, media)| match media {
+                        MediaSpec::Video { codec, .. } if !encrypted.contains(&track_num) => Some((track_num, codec)),
+                        _ => None,
+                    })
+                    .collect(),
+            }),
+            timestamps: TimestampMapper::new(),
+            anchor,
+            finish: None,
+        }
+    }
+}
+
+impl<W: Write> std::fmt::Debug for SegmentBuilder<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We can't/shouldn't crawl into our FFI pointers for debug printing, and we don't require `W: Debug`, but we
+        // should still have even a primitive Debug impl to avoid friction with user structs that #[derive(Debug)]
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// Work done on the destination after `libwebm` has finalized the segment
+type Finish<W> = fn(&mut W) -> Result<(), Error>;
+
+/// A fully-built Matroska segment. This is where actual video/audio frames are written.
+///
+/// This is created via [`SegmentBuilder`]. Once built in this way, the list of tracks and their parameters become
+/// immutable.
+///
+/// ## Finalization
+/// Once you are done writing frames to this segment, you must call [`Segment::finalize`] on it.
+/// This performs a few final writes, and the resulting WebM may not be playable without it.
+/// Notably, for memory safety reasons, just dropping a [`Segment`] will not finalize it! To abandon a segment and get
+/// the writer back without finalizing it, call [`Segment::cancel`].
+pub struct Segment<W: Write> {
+    ffi: OwnedSegmentPtr,
+    writer: Writer<W>,
+    start: u64,
+    tracks: Tracks,
+    sync: SyncTracker,
+    latency: Option<Latency>,
+    dash: Option<Dash>,
+    vfr: Vec<Vfr>,
+
+    /// The Opus tracks, whose packets say how many samples they hold
+    opus: Vec<TrackNum>,
+    pending: VecDeque<PendingFrame>,
+    warnings: Vec<MuxWarning>,
+    keyframes: Option<KeyframeCheck>,
+    timestamps: TimestampMapper,
+    anchor: Option<SystemTime>,
+
+    /// What is left to do once `libwebm` has finalized the segment, for segments that aren't the whole file
+    pub(super) finish: Option<Finish<W>>,
+}
+
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// Thus, safety is only conditional on the write destination `W`, hence the `Send` bound on it.
+//
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl<W: Write + Send> Send for Segment<W> {}
+
+impl<W: Write> Segment<W> {
+    /// Adds a frame to the track with the specified track number. If you have a [`VideoTrack`] or
+    /// [`AudioTrack`], you can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
+    ///
+    /// The timestamp must be in nanosecond units, and must be monotonically increasing with respect to all other
+    /// timestamps written so far, including those of other tracks! Repeating the last written timestamp is allowed,
+    /// however players generally don't handle this well if both such frames are on the same track.
+    ///
+    /// The frames of a track set up with [`SegmentBuilder::vfr_video`] are held until the track's next frame, and fail
+    /// with [`Error::BadParam`] unless their timestamps increase. Frames written while one is held wait behind it.
+    pub fn add_frame(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        let track_num = track.into();
+        let keyframe = self.check_keyframe_flag(track_num, data, timestamp_ns, keyframe)?;
+        let vfr = self.vfr.iter().position(|vfr| vfr.track_num == track_num);
+        if vfr.is_none() && self.pending.is_empty() {
+            return self.write_frame(track_num, data, timestamp_ns, keyframe);
+        }
+
+        if let Some(index) = vfr {
+            let held = self.pending.iter().find(|frame| frame.held && frame.track_num == track_num);
+            if let Some(held_ns) = held.map(|frame| frame.timestamp_ns) {
+                let duration_ns = timestamp_ns.checked_sub(held_ns).filter(|&duration_ns| duration_ns > 0);
+                let duration_ns = duration_ns.ok_or(Error::BadParam)?;
+                self.vfr[index].last_duration_ns = Some(duration_ns);
+                self.release_held_frame(track_num, Some(duration_ns))?;
+            }
+        }
+        self.pending.push_back(PendingFrame {
+            track_num,
+            data: data.to_vec(),
+            timestamp_ns,
+            keyframe,
+            duration_ns: None,
+            discard_padding_ns: 0,
+            held: vfr.is_some(),
+        });
+        self.write_pending()
+    }
+
+    /// Writes a frame as [`Segment::add_frame`] does for a track without variable frame rate.
+    fn write_frame(
+        &mut self,
+        track_num: TrackNum,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.start_media_segment_if_due(track_num, timestamp_ns, keyframe)?;
+        self.start_cluster_if_due(timestamp_ns);
+        let _thread = self.writer.enter("Segment");
+        let result = unsafe {
+            ffi::mux::segment_add_frame(
+                self.ffi.as_ptr(),
+                track_num,
+                data.as_ptr(),
+                data.len(),
+                timestamp_ns,
+                keyframe,
+            )
+        };
+
+        match result {
+            ResultCode::Ok => {
+                drop(_thread);
+                self.frame_added(track_num, timestamp_ns, None)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.ffi.libwebm_error()),
+        }
+    }
+
+    /// Like [`Segment::add_frame`], but with the timestamp in ticks of any [`Timebase`](crate::time::Timebase), e.g.
+    /// `(960, Timebase::OPUS)`. It is rounded to the nearest nanosecond, and fails with [`Error::Time`] if it can't be
+    /// converted.
+    pub fn add_frame_at(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp: impl Into<Timestamp>,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        let timestamp_ns = timestamp.into().to_ns()?;
+        self.add_frame(track, data, timestamp_ns, keyframe)
+    }
+
+    /// Like [`Segment::add_frame`], but with a wall-clock time, which the segment's [`TimestampMapper`] maps to the
+    /// frame's timestamp so that pauses are left out. Pause and resume with [`Segment::timestamp_mapper_mut`].
+    ///
+    /// Fails with [`Error::Time`] if the mapper can't map the time: when it is before the last resume, or after the
+    /// current pause.
+    pub fn add_frame_wall(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        wall_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        let timestamp_ns = self.timestamps.map(wall_ns)?;
+        self.add_frame(track, data, timestamp_ns, keyframe)
+    }
+
+    /// The mapper [`Segment::add_frame_wall`] uses. Its origin is the first wall-clock time it is given.
+    #[must_use]
+    pub fn timestamp_mapper(&self) -> &TimestampMapper {
+        &self.timestamps
+    }
+
+    /// Like [`Segment::timestamp_mapper`], to pause and resume the recording, or to replace the mapper with one of
+    /// another origin.
+    pub fn timestamp_mapper_mut(&mut self) -> &mut TimestampMapper {
+        &mut self.timestamps
+    }
+
+    /// The UTC time of the media timestamp `timestamp_ns`, for a segment anchored with
+    /// [`SegmentBuilder::anchor_wall_clock`]. Returns `None` if it isn't anchored, or if the time can't be represented
+    /// on this platform.
+    #[must_use]
+    pub fn media_to_utc(&self, timestamp_ns: u64) -> Option<SystemTime> {
+        self.anchor?.checked_add(Duration::from_nanos(timestamp_ns))
+    }
+
+    /// Like [`Segment::add_frame`], but only for a video track of this segment. A handle of another segment fails with
+    /// [`Error::ForeignTrack`], even if this segment has a track with the same number, and an audio track doesn't
+    /// compile:
+    ///
+    /// ```compile_fail
+    /// # use webm::mux::{AudioCodecId, SegmentBuilder, Writer};
+    /// let builder = SegmentBuilder::new(Writer::new(std::io::Cursor::new(Vec::new()))).unwrap();
+    /// let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+    /// builder.build().add_video_frame(audio, &[0; 10], 0, true);
+    /// ```
+    pub fn add_video_frame(
+        &mut self,
+        track: VideoTrack,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Video)?;
+        self.add_frame(track, data, timestamp_ns, keyframe)
+    }
+
+    /// Like [`Segment::add_frame`], but only for an audio track of this segment. A handle of another segment fails with
+    /// [`Error::ForeignTrack`], even if this segment has a track with the same number.
+    pub fn add_audio_frame(
+        &mut self,
+        track: AudioTrack,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.tracks.check(track.1, track.into(), TrackKind::Audio)?;
+        self.add_frame(track, data, timestamp_ns, keyframe)
+    }
+
+    /// Like [`Segment::add_frame`], for a track marked with [`SegmentBuilder::add_content_encryption`]: the frame is
+    /// written as [`encrypted_frame_payload`](super::encryption::encrypted_frame_payload) lays it out, from `payload`
+    /// with its encrypted parts already encrypted, its IV, and its partitions for subsample encryption.
+    pub fn add_encrypted_frame(
+        &mut self,
+        track: impl Into<TrackNum>,
+        iv: Option<u64>,
+        partitions: &[u32],
+        payload: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        let frame = super::encryption::encrypted_frame_payload(iv, partitions, payload)?;
+        self.add_frame(track, &frame, timestamp_ns, keyframe)
+    }
+
+    /// Like [`Segment::add_frame`] for a video frame that isn't a keyframe, but written as a `BlockGroup` with a
+    /// `ReferenceBlock` for each frame it is decoded from, rather than as a `SimpleBlock`. This keeps the reference
+    /// structure of VP9 and AV1 streams whose frames don't only reference the one before them, e.g. those that use
+    /// alternate reference frames. `references_ns` are the offsets from `timestamp_ns` to the timestamps of the frames
+    /// referenced, which are negative, or zero for a frame with the same timestamp such as a hidden frame's. They are
+    /// written in timecode units, as the offsets between the frames' truncated timecodes.
+    ///
+    /// Fails with [`Error::BadParam`] if there are no references, if one is after the frame or before 0, if the track
+    /// isn't a video track, or if the segment has a track set up with [`SegmentBuilder::vfr_video`].
+    pub fn add_frame_with_references(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp_ns: u64,
+        references_ns: &[i64],
+    ) -> Result<(), Error> {
+        if references_ns.is_empty() || !self.vfr.is_empty() {
+            return Err(Error::BadParam);
+        }
+        let extras = FrameExtras {
+            references_ns,
+            ..FrameExtras::default()
+        };
+        self.add_frame_with_extras(track, data, timestamp_ns, false, extras)
+    }
+
+    /// Like [`Segment::add_audio_frame`], for the last packet of an Opus track, which encoders pad to a whole frame:
+    /// only its first `valid_samples`, counted at `sample_rate`, are kept. The rest is written as the frame's
+    /// `DiscardPadding`, so that players drop it, and the frame is written as any other if there is none. How many
+    /// samples the packet holds is read from its table of contents. Like other frames, it waits behind the held frame
+    /// of a track set up with [`SegmentBuilder::vfr_video`].
+    ///
+    /// Fails with [`Error::BadParam`] if the track isn't an Opus track, if `data` isn't a valid Opus packet, or if
+    /// `valid_samples` is more than the packet holds.
+    pub fn add_final_audio_frame(
+        &mut self,
+        track: AudioTrack,
+        data: &[u8],
+        timestamp_ns: u64,
+        valid_samples: u32,
+        sample_rate: u32,
+    ) -> Result<(), Error> {
+        let track_num = TrackNum::from(track);
+        self.tracks.check(track.1, track_num, TrackKind::Audio)?;
+        if !self.opus.contains(&track_num) {
+            return Err(Error::BadParam);
+        }
+        let packet_samples = opus_packet_samples(data).ok_or(Error::BadParam)?;
+        if u64::from(valid_samples) * 48_000 > u64::from(packet_samples) * u64::from(sample_rate) {
+            return Err(Error::BadParam);
+        }
+        let packet_ns = ticks_to_ns(packet_samples.into(), Timebase::OPUS).map_err(|_| Error::BadParam)?;
+        let valid_ns = ticks_to_ns(valid_samples.into(), Timebase::hz(sample_rate)).map_err(|_| Error::BadParam)?;
+
+        let padding_ns = i64::try_from(packet_ns.saturating_sub(valid_ns)).map_err(|_| Error::BadParam)?;
+        if padding_ns == 0 {
+            return self.add_frame(track, data, timestamp_ns, true);
+        }
+        if self.pending.is_empty() {
+            return self.add_frame_with_discard_padding(track, data, timestamp_ns, true, padding_ns);
+        }
+
+        let keyframe = self.check_keyframe_flag(track_num, data, timestamp_ns, true)?;
+        self.pending.push_back(PendingFrame {
+            track_num,
+            data: data.to_vec(),
+            timestamp_ns,
+            keyframe,
+            duration_ns: None,
+            discard_padding_ns: padding_ns,
+            held: false,
+        });
+        self.write_pending()
+    }
+
+    /// Like [`Segment::add_frame`], but also writes a `DiscardPadding` for the frame: how much audio to drop from the
+    /// end of the decoded frame (or from the start, if negative), in nanoseconds.
+    pub(crate) fn add_frame_with_discard_padding(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        let track_num = track.into();
+        let keyframe = self.check_keyframe_flag(track_num, data, timestamp_ns, keyframe)?;
+        self.start_media_segment_if_due(track_num, timestamp_ns, keyframe)?;
+        self.start_cluster_if_due(timestamp_ns);
+        let _thread = self.writer.enter("Segment");
+        let result = unsafe {
+            ffi::mux::segment_add_frame_with_discard_padding(
+                self.ffi.as_ptr(),
+                track_num,
+                data.as_ptr(),
+                data.len(),
+                timestamp_ns,
+                keyframe,
+                discard_padding_ns,
+            )
+        };
+
+        match result {
+            ResultCode::Ok => {
+                drop(_thread);
+                self.frame_added(track_num, timestamp_ns, None)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.ffi.libwebm_error()),
+        }
+    }
+
+    /// Like [`Segment::add_frame`], but also writes whichever of the optional parts in `extras` are set. The frame is
+    /// written as a `BlockGroup` if any of them are. Fails with [`Error::BadParam`] if a reference is after the frame
+    /// or before 0, or if there are references and a discard padding.
+    pub(crate) fn add_frame_with_extras(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        extras: FrameExtras<'_>,
+    ) -> Result<(), Error> {
+        let (add_id, additional) = extras.addition.unwrap_or((0, &[]));
+        let duration_ns = match extras.duration_ns {
+            Some(0) => return Err(Error::BadParam),
+            Some(duration_ns) => duration_ns,
+            None => 0,
+        };
+
+        let references = extras.references_ns;
+        let after_or_before_zero = |&reference_ns: &i64| {
+            reference_ns > 0 || timestamp_ns.checked_add_signed(reference_ns).is_none()
+        };
+        if !references.is_empty() && (extras.discard_padding_ns != 0 || references.iter().any(after_or_before_zero)) {
+            return Err(Error::BadParam);
+        }
+
+        let track_num = track.into();
+        let keyframe = self.check_keyframe_flag(track_num, data, timestamp_ns, keyframe)?;
+        self.start_media_segment_if_due(track_num, timestamp_ns, keyframe)?;
+        self.start_cluster_if_due(timestamp_ns);
+        let _thread = self.writer.enter("Segment");
+        // A keyframe references nothing, even if it was only found to be one by its bitstream
+        let result = if keyframe || references.is_empty() {
+            unsafe {
+                ffi::mux::segment_add_generic_frame(
+                    self.ffi.as_ptr(),
+                    track_num,
+                    data.as_ptr(),
+                    data.len(),
+                    timestamp_ns,
+                    keyframe,
+                    additional.as_ptr(),
+                    additional.len(),
+                    add_id,
+                    duration_ns,
+                    extras.discard_padding_ns,
+                )
+            }
+        } else {
+            unsafe {
+                ffi::mux::segment_add_referenced_frame(
+                    self.ffi.as_ptr(),
+                    track_num,
+                    data.as_ptr(),
+                    data.len(),
+                    timestamp_ns,
+                    additional.as_ptr(),
+                    additional.len(),
+                    add_id,
+                    duration_ns,
+                    references.as_ptr(),
+                    references.len(),
+                )
+            }
+        };
+
+        match result {
+            ResultCode::Ok => {
+                drop(_thread);
+                self.frame_added(track_num, timestamp_ns, extras.duration_ns)
+            },
+            ResultCode::BadParam => Err(Error::BadParam),
+            _ => Err(self.ffi.libwebm_error()),
+        }
+    }
+
+    /// Writes the last frame of a track set up with [`SegmentBuilder::vfr_video`], which is held until the next frame
+    /// gives its duration, with `last_frame_duration_ns` as its duration. If that is `None`, the frame lasts as long
+    /// as the one before it, or has no `BlockDuration` if it is the track's only frame. The frames waiting behind it
+    /// are written too, up to the next frame held for another track, and frames written to the track afterwards are
+    /// held as before.
+    ///
+    /// Fails with [`Error::BadParam`] if the track isn't set up for variable frame rate, or if the duration is zero.
+    pub fn finish_vfr(&mut self, track: VideoTrack, last_frame_duration_ns: Option<u64>) -> Result<(), Error> {
+        let track_num = TrackNum::from(track);
+        self.tracks.check(track.1, track_num, TrackKind::Video)?;
+        let vfr = self.vfr.iter().find(|vfr| vfr.track_num == track_num).ok_or(Error::BadParam)?;
+        if last_frame_duration_ns == Some(0) {
+            return Err(Error::BadParam);
+        }
+        let duration_ns = last_frame_duration_ns.or(vfr.last_duration_ns);
+        self.release_held_frame(track_num, duration_ns)
+    }
+
+    /// Gives the frame held for a variable frame rate track its duration, and writes the frames that no longer wait.
+    fn release_held_frame(&mut self, track_num: TrackNum, duration_ns: Option<u64>) -> Result<(), Error> {
+        if let Some(held) = self.pending.iter_mut().find(|frame| frame.held && frame.track_num == track_num) {
+            held.duration_ns = duration_ns;
+            held.held = false;
+        }
+        self.write_pending()
+    }
+
+    /// Writes the frames that wait to be written, up to the first one that is held.
+    fn write_pending(&mut self) -> Result<(), Error> {
+        while self.pending.front().is_some_and(|frame| !frame.held) {
+            let Some(frame) = self.pending.pop_front() else {
+                break;
+            };
+            let PendingFrame {
+                track_num,
+                data,
+                timestamp_ns,
+                keyframe,
+                duration_ns,
+                discard_padding_ns,
+                ..
+            } = frame;
+            match (duration_ns, discard_padding_ns) {
+                (None, 0) => self.write_frame(track_num, &data, timestamp_ns, keyframe)?,
+                _ => {
+                    let extras = FrameExtras {
+                        duration_ns,
+                        discard_padding_ns,
+                        ..FrameExtras::default()
+                    };
+                    self.add_frame_with_extras(track_num, &data, timestamp_ns, keyframe, extras)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// With [`SegmentBuilder::verify_keyframe_flags`], checks `keyframe` against what the bitstream of `data` says,
+    /// and returns the flag to write the frame with.
+    fn check_keyframe_flag(
+        &self,
+        track_num: TrackNum,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<bool, Error> {
+        let Some(check) = &self.keyframes else {
+            return Ok(keyframe);
+        };
+        let codec = check.codecs.iter().find(|&&(num, _)| num == track_num).map(|&(_, codec)| codec);
+        match codec.and_then(|codec| sniff::is_keyframe(codec, data)) {
+            Some(sniffed) if sniffed != keyframe => match check.policy {
+                KeyframeFlagPolicy::Reject => Err(Error::KeyframeFlag {
+                    track_num,
+                    timestamp_ns,
+                    keyframe: sniffed,
+                }),
+                KeyframeFlagPolicy::Correct => Ok(sniffed),
+            },
+            _ => Ok(keyframe),
+        }
+    }
+
+    /// With [`SegmentBuilder::dash_live`], starts a new cluster for a keyframe that starts the next media segment,
+    /// or fails if the frame is past the boundary and its tolerance while that keyframe is overdue.
+    fn start_media_segment_if_due(
+        &mut self,
+        track_num: TrackNum,
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        let Some(dash) = &mut self.dash else {
+            return Ok(());
+        };
+        let keyframe = keyframe && self.tracks.tracks.contains(&(track_num, dash.kind));
+        let Some(boundary_ns) = dash.next_boundary_ns else {
+            // The first frame starts the first cluster anyway
+            if !keyframe {
+                return Err(Error::KeyframeOverdue {
+                    boundary_ns: timestamp_ns,
+                    timestamp_ns,
+                });
+            }
+            dash.next_boundary_ns = Some(timestamp_ns + dash.segment_ns);
+            return Ok(());
+        };
+
+        let reach_ns = timestamp_ns.saturating_add(dash.tolerance_ns);
+        if keyframe && reach_ns >= boundary_ns {
+            // Media segments stay on their grid, whether the keyframe is early, on time or late
+            let skipped = (reach_ns - boundary_ns) / dash.segment_ns;
+            dash.next_boundary_ns = Some(boundary_ns + (skipped + 1) * dash.segment_ns);
+            let _thread = self.writer.enter("Segment");
+            unsafe { ffi::mux::segment_force_new_cluster(self.ffi.as_ptr()) };
+        } else if timestamp_ns > boundary_ns.saturating_add(dash.tolerance_ns) {
+            return Err(Error::KeyframeOverdue {
+                boundary_ns,
+                timestamp_ns,
+            });
+        }
+        Ok(())
+    }
+
+    /// With a [`SegmentBuilder::target_cluster_latency`], starts a new cluster for a frame at `timestamp_ns` if the
+    /// earliest frame that hasn't been flushed has waited as long as the target allows.
+    ///
+    /// `libwebm` only checks a cluster's duration when it writes a frame, and holds audio back instead of writing it
+    /// while there is video, so this is what bounds how long held back audio waits.
+    fn start_cluster_if_due(&mut self, timestamp_ns: u64) {
+        let Some(latency) = &self.latency else {
+            return;
+        };
+        if latency.unflushed_since_ns.is_some_and(|since| timestamp_ns.saturating_sub(since) >= latency.max_ns) {
+            let _thread = self.writer.enter("Segment");
+            unsafe { ffi::mux::segment_force_new_cluster(self.ffi.as_ptr()) };
+        }
+    }
+
+    /// Follows a frame that has been added, and with a [`SegmentBuilder::target_cluster_latency`], flushes the
+    /// destination if the frame started a new cluster. Everything held back has been written by then.
+    fn frame_added(&mut self, track_num: TrackNum, timestamp_ns: u64, duration_ns: Option<u64>) -> Result<(), Error> {
+        self.sync.add_frame(track_num, timestamp_ns, duration_ns, None);
+        let Some(latency) = &mut self.latency else {
+            return Ok(());
+        };
+
+        let clusters = {
+            let _thread = self.writer.enter("Segment");
+            unsafe { ffi::mux::segment_cluster_count(self.ffi.as_ptr()) }
+        };
+        if clusters == latency.clusters {
+            latency.unflushed_since_ns.get_or_insert(timestamp_ns);
+            return Ok(());
+        }
+        latency.clusters = clusters;
+        latency.unflushed_since_ns = None;
+        self.writer.dest_mut().flush().map_err(Error::Io)
+    }
+
+    /// The number of clusters started so far. `libwebm` starts a new cluster at each video keyframe, and whenever the
+    /// current one reaches its maximum duration or size.
+    #[must_use]
+    pub fn cluster_count(&self) -> u64 {
+        let _thread = self.writer.enter("Segment");
+        unsafe { ffi::mux::segment_cluster_count(self.ffi.as_ptr()) }
+    }
+
+    /// The number of bytes written to the current cluster so far, including its header, or `None` if no frames have
+    /// been written yet.
+    ///
+    /// Unlike the writer's position, this leaves out the segment's headers, so it can be checked against a size budget
+    /// to decide when to start a new cluster.
+    #[must_use]
+    pub fn current_cluster_bytes(&self) -> Option<u64> {
+        let _thread = self.writer.enter("Segment");
+        let mut size = 0;
+        let result = unsafe { ffi::mux::segment_current_cluster_size(self.ffi.as_ptr(), &mut size) };
+        (result == ResultCode::Ok).then_some(size)
+    }
+
+    /// How far the tracks have drifted apart in the frames written so far, with the default [`SyncOptions`].
+    ///
+    /// See [`SyncReport`] for how drift is measured. Frames written with [`Segment::add_frame`] have no duration of
+    /// their own, so each track's frames are taken to last as long as the gap between its first two.
+    #[must_use]
+    pub fn sync_report(&self) -> SyncReport {
+        self.sync_report_with_options(&SyncOptions::default())
+    }
+
+    /// Like [`Segment::sync_report`], but with the thresholds given.
+    #[must_use]
+    pub fn sync_report_with_options(&self, options: &SyncOptions) -> SyncReport {
+        self.sync.report(options)
+    }
+
+    /// The [`SegmentBuilder::warnings`] of the builder that built this segment.
+    #[must_use]
+    pub fn warnings(&self) -> &[MuxWarning] {
+        &self.warnings
+    }
+
+    pub(crate) fn writer_mut(&mut self) -> &mut Writer<W> {
+        &mut self.writer
+    }
+
+    /// Finalizes the segment and consumes it, returning the underlying writer. Note that the finalizing process will
+    /// itself trigger writes (such as to write seeking information).
+    ///
+    /// The resulting WebM may not be playable if you drop the [`Segment`] without calling this first!
+    ///
+    /// You may specify an explicit `duration` to be written to the segment's `Duration` element. However, this requires
+    /// seeking and thus will be ignored if the writer was not created with [`Seek`](std::io::Seek) support.
+    ///
+    /// Finalization is known to fail if no frames have been written.
+    ///
+    /// Frames still held for [`SegmentBuilder::vfr_video`] tracks are written first, as with [`Segment::finish_vfr`]
+    /// without a duration.
+    pub fn finalize(mut self, duration: Option<u64>) -> Result<Writer<W>, Writer<W>> {
+        let mut flushed = true;
+        for index in 0..self.vfr.len() {
+            let Vfr {
+                track_num,
+                last_duration_ns,
+            } = self.vfr[index];
+            flushed &= self.release_held_frame(track_num, last_duration_ns).is_ok();
+        }
+        let Self {
+            ffi,
+            mut writer,
+            finish,
+            ..
+        } = self;
+        let result = {
+            let _thread = writer.enter("Segment");
+            unsafe { ffi::mux::finalize_segment(ffi.as_ptr(), duration.unwrap_or(0)) }
+        };
+
+        match result {
+            ResultCode::Ok if !flushed => Err(writer),
+            ResultCode::Ok => match finish {
+                Some(finish) if finish(writer.dest_mut()).is_err() => Err(writer),
+                _ => Ok(writer),
+            },
+            _ => Err(writer),
+        }
+    }
+
+    /// Like [`Segment::finalize`], but with the duration in nanoseconds, which is rounded to the default timecode
+    /// scale of 1ms. No duration is written if that rounds to zero.
+    pub(crate) fn finalize_with_duration_ns(self, duration_ns: u64) -> Result<Writer<W>, Writer<W>> {
+        const TIMECODE_SCALE_NS: u64 = 1_000_000;
+        let duration = (duration_ns + TIMECODE_SCALE_NS / 2) / TIMECODE_SCALE_NS;
+        self.finalize((duration > 0).then_some(duration))
+    }
+
+    /// Abandons the segment without finalizing it, e.g. when an export is aborted, and returns the writer so that the
+    /// destination can be deleted or reused. Nothing more is written, so the destination is left as it is, with an
+    /// unfinished segment that may not be playable. Frames still held for [`SegmentBuilder::vfr_video`] tracks are
+    /// dropped.
+    #[must_use]
+    pub fn cancel(self) -> Writer<W> {
+        // Deleting the libwebm segment only frees it
+        let Self { ffi, writer, .. } = self;
+        drop(ffi);
+        writer
+    }
+}
+
+impl<W: Write + Truncate> Segment<W> {
+    /// Like [`Segment::cancel`], but also cuts the destination back to where it was when the segment was started, so
+    /// that a file or buffer the segment was the first thing written to is left empty, and the writer can be used for
+    /// another segment. Returns the writer as `Err` if truncating the destination fails.
+    pub fn cancel_and_truncate(self) -> Result<Writer<W>, Writer<W>> {
+        let start = self.start;
+        let mut writer = self.cancel();
+        match writer.truncate(start) {
+            Ok(()) => Ok(writer),
+            Err(_) => Err(writer),
+        }
+    }
+}
+
+/// The optional parts of a frame written with [`Segment::add_frame_with_extras`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FrameExtras<'a> {
+    /// A `BlockAdditional` payload, with its `BlockAddID`.
+    pub addition: Option<(u64, &'a [u8])>,
+
+    /// The `BlockDuration`, in nanoseconds. This can't be zero.
+    pub duration_ns: Option<u64>,
+
+    /// The `DiscardPadding`, in nanoseconds. Zero means none is written.
+    pub discard_padding_ns: i64,
+
+    /// The offsets from the frame's timestamp to those of the frames it references, for its `ReferenceBlock`s. Only
+    /// video frames that aren't keyframes can have them, and not with a `DiscardPadding`.
+    pub references_ns: &'a [i64],
+}
+
+impl<W: Write> std::fmt::Debug for Segment<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We can't/shouldn't crawl into our FFI pointers for debug printing, and we don't require `W: Debug`, but we
+        // should still have even a primitive Debug impl to avoid friction with user structs that #[derive(Debug)]
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// Checks the fields of `config` that `libwebm` can't be given, returning the name of the first that is invalid. The
+/// track number is checked against the segment's tracks by the caller.
+fn check_video_config(config: &VideoTrackConfig) -> Result<(), &'static str> {
+    // libwebm takes these as i32s
+    let invalid = |value: u32| value == 0 || i32::try_from(value).is_err();
+    if invalid(config.width) {
+        return Err("width");
+    }
+    if invalid(config.height) {
+        return Err("height");
+    }
+    if i32::try_from(config.codec_private.len()).is_err() {
+        return Err("codec_private");
+    }
+    // Text is passed to libwebm as C strings, which can't hold nul characters
+    if config.name.as_deref().is_some_and(|name| name.contains('\0')) {
+        return Err("name");
+    }
+    if config.language.as_deref().is_some_and(|language| !is_iso_639_2(language)) {
+        return Err("language");
+    }
+    if let Some(color) = config.color {
+        let valid = unsafe {
+            ffi::mux::mux_color_description_is_valid(
+                color.primaries,
+                color.transfer_characteristics,
+                color.matrix_coefficients,
+                color_range_id(color.range),
+                color.chroma_siting_horizontal as u8,
+                color.chroma_siting_vertical as u8,
+            )
+        };
+        if !valid {
+            return Err("color");
+        }
+    }
+    if let Some(bit_depth) = config.bit_depth {
+        if !matches!(bit_depth, 8 | 10 | 12) {
+            return Err("bit_depth");
+        }
+        if !color_format_is_valid(bit_depth, &config.chroma_subsampling) {
+            return Err("chroma_subsampling");
+        }
+    }
+    Ok(())
+}
+
+fn check_audio_config(config: &AudioTrackConfig) -> Result<(), &'static str> {
+    // libwebm takes these as i32s
+    let invalid = |value: u32| value == 0 || i32::try_from(value).is_err();
+    if invalid(config.sample_rate) {
+        return Err("sample_rate");
+    }
+    if invalid(config.channels) {
+        return Err("channels");
+    }
+    if i32::try_from(config.codec_private.len()).is_err() {
+        return Err("codec_private");
+    }
+    // Text is passed to libwebm as C strings, which can't hold nul characters
+    if config.name.as_deref().is_some_and(|name| name.contains('\0')) {
+        return Err("name");
+    }
+    if config.language.as_deref().is_some_and(|language| !is_iso_639_2(language)) {
+        return Err("language");
+    }
+    if config.bit_depth == Some(0) {
+        return Err("bit_depth");
+    }
+    Ok(())
+}
+
+/// Whether `language` has the form of an ISO 639-2 language code, for [`SegmentBuilder::set_track_language`].
+pub(crate) fn is_iso_639_2(language: &str) -> bool {
+    language.len() == 3 && language.bytes().all(|byte| byte.is_ascii_lowercase())
+}
+
+/// Whether `language` has the form of a BCP 47 language tag, for [`SegmentBuilder::set_track_language_bcp47`].
+pub(crate) fn is_bcp_47(language: &str) -> bool {
+    let valid = |subtag: &str| (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric());
+    let mut subtags = language.split('-');
+    subtags.next().is_some_and(|primary| valid(primary) && primary.bytes().all(|b| b.is_ascii_alphabetic()))
+        && subtags.all(valid)
+}
+
+/// Whether [`SegmentBuilder::set_color`] can write `bit_depth` and `subsampling`. The codecs WebM allows only code 8,
+/// 10 and 12-bit color, and Matroska's subsampling factors are the number of chroma samples dropped for each one
+/// kept, so 4:2:0 video has factors of 1 in both directions.
+pub(crate) fn color_format_is_valid(bit_depth: u8, subsampling: &ColorSubsampling) -> bool {
+    matches!(bit_depth, 8 | 10 | 12) && subsampling.chroma_horizontal <= 1 && subsampling.chroma_vertical <= 1
+}
+
+/// The number of samples at 48 kHz in the Opus packet `packet`, from its table of contents as RFC 6716 section 3.1
+/// lays it out, or `None` if it isn't a valid packet.
+fn opus_packet_samples(packet: &[u8]) -> Option<u32> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK-only: 10, 20, 40 or 60 ms
+        0..=11 => [480, 960, 1920, 2880][usize::from(config % 4)],
+        // Hybrid: 10 or 20 ms
+        12..=15 => [480, 960][usize::from(config % 2)],
+        // CELT-only: 2.5, 5, 10 or 20 ms
+        _ => [120, 240, 480, 960][usize::from(config % 4)],
+    };
+    let frames = match toc & 0b11 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => u32::from(*packet.get(1)? & 0b11_1111),
+    };
+    // A packet holds at least one frame, and at most 120 ms of them
+    let samples = frame_samples * frames;
+    (frames > 0 && samples <= 5760).then_some(samples)
+}
+
+/// The value of the `Range` element for `range`.
+fn color_range_id(range: ColorRange) -> u8 {
+    match range {
+        ColorRange::Unspecified => 0,
+        ColorRange::Broadcast => 1,
+        ColorRange::Full => 2,
+    }
+}
+
+fn try_as_i32(x: impl TryInto<i32>) -> Result<i32, Error> {
+    x.try_into().map_err(|_| Error::BadParam)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mux::Writer;
+
+    use super::*;
+    use crate::demux::{ebml, Demuxer, TrackType};
+    use crate::time::{TimeError, Timebase};
+    use std::io::Cursor;
+
+    fn make_segment_builder() -> SegmentBuilder<Cursor<Vec<u8>>> {
+        let output = Vec::new();
+        let writer = Writer::new(Cursor::new(output));
+        SegmentBuilder::new(writer).expect("Segment builder should create OK")
+    }
+
+    /// Builds the segment, writes a keyframe for `video`, and returns the finished file.
+    fn finish_with_keyframe(builder: SegmentBuilder<Cursor<Vec<u8>>>, video: VideoTrack) -> Vec<u8> {
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        writer.into_inner().into_inner()
+    }
+
+    /// The children of each `TrackEntry` in `file`.
+    fn track_entries(file: &[u8]) -> Vec<Vec<ebml::Element>> {
+        let mut source = Cursor::new(file);
+        let top_level = ebml::children(&mut source, 0, file.len() as u64).unwrap();
+        let segment = top_level.iter().find(|element| element.id == 0x1853_8067).unwrap();
+        let children = ebml::children(&mut source, segment.pos, segment.end()).unwrap();
+        let tracks = children.iter().find(|element| element.id == 0x1654_AE6B).unwrap();
+        let entries = ebml::children(&mut source, tracks.pos, tracks.end()).unwrap();
+        entries.iter().map(|entry| ebml::children(&mut source, entry.pos, entry.end()).unwrap()).collect()
+    }
+
+    /// The children of the `Video` element of the first track in `file`.
+    fn video_settings(file: &[u8]) -> Vec<ebml::Element> {
+        let mut source = Cursor::new(file);
+        let mut children = |parent: &ebml::Element| ebml::children(&mut source, parent.pos, parent.end()).unwrap();
+        let root = ebml::Element {
+            id: 0,
+            start: 0,
+            pos: 0,
+            size: file.len() as u64,
+        };
+        let find = |elements: &[ebml::Element], id| *elements.iter().find(|element| element.id == id).unwrap();
+        let segment = find(&children(&root), 0x1853_8067);
+        let tracks = find(&children(&segment), 0x1654_AE6B);
+        let entry = children(&tracks)[0];
+        let video = find(&children(&entry), 0xE0);
+        children(&video)
+    }
+
+    #[test]
+    fn bad_track_number() {
+        let builder = make_segment_builder();
+        let video_track = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123456));
+        assert!(video_track.is_err());
+    }
+
+    #[test]
+    fn overlapping_track_number_same_type() {
+        let builder = make_segment_builder();
+
+        let Ok((builder, _)) = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123))
+        else {
+            panic!("First video track unexpectedly failed")
+        };
+
+        let video_track2 = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123));
+        assert!(video_track2.is_err());
+    }
+
+    #[test]
+    fn overlapping_track_number_different_type() {
+        let builder = make_segment_builder();
+
+        let Ok((builder, _)) = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123))
+        else {
+            panic!("First video track unexpectedly failed")
+        };
+
+        let audio_track = builder.add_audio_track(420, 420, AudioCodecId::Opus, Some(123));
+        assert!(audio_track.is_err());
+    }
+
+    #[test]
+    fn generates_distinct_uids() {
+        let uids: std::collections::HashSet<u64> = (0..4096).map(|_| crate::mux::generate_uid().get()).collect();
+        assert_eq!(uids.len(), 4096);
+        assert!(uids.iter().all(|&uid| uid < 1 << 56));
+    }
+
+    #[test]
+    fn counts_clusters() {
+        let (builder, track) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let mut segment = builder.build();
+        assert_eq!(segment.cluster_count(), 0);
+        assert_eq!(segment.current_cluster_bytes(), None);
+
+        // A cluster for each keyframe, every fourth frame
+        let mut last_size = 0;
+        for i in 0..12u64 {
+            segment.add_frame(track, &[0; 100], i * 100_000_000, i % 4 == 0).unwrap();
+            assert_eq!(segment.cluster_count(), i / 4 + 1);
+
+            let size = segment.current_cluster_bytes().unwrap();
+            if i % 4 == 0 {
+                assert!(size < 150, "{size}");
+            } else {
+                assert!(size > last_size + 100);
+            }
+            last_size = size;
+        }
+        assert!(segment.finalize(None).is_ok());
+    }
+
+    #[test]
+    fn bounds_cluster_latency() {
+        use std::cell::{Cell, RefCell};
+        use std::rc::Rc;
+
+        /// Buffers what is written until it is flushed, and notes the media time each flush happened at.
+        #[derive(Default)]
+        struct ClockedWrite {
+            clock_ns: Rc<Cell<u64>>,
+            written: Rc<RefCell<Vec<u8>>>,
+
+            /// How much had been written by each flush, and when it happened
+            flushes: Rc<RefCell<Vec<(usize, u64)>>>,
+        }
+        impl Write for ClockedWrite {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.written.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushes.borrow_mut().push((self.written.borrow().len(), self.clock_ns.get()));
+                Ok(())
+            }
+        }
+
+        assert!(matches!(make_segment_builder().target_cluster_latency(0), Err(Error::BadParam)));
+
+        const BUDGET_NS: u64 = 200_000_000;
+        let output = ClockedWrite::default();
+        let (clock_ns, written, flushes) = (output.clock_ns.clone(), output.written.clone(), output.flushes.clone());
+        let builder = SegmentBuilder::new(Writer::new_non_seek(output)).unwrap();
+        let (builder, video) = builder.add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.target_cluster_latency(BUDGET_NS).unwrap().build();
+
+        // Six seconds of sparse video, one frame a second with a keyframe every four, and audio every 20ms. Each frame
+        // is unique, so that it can be found in the output.
+        let mut frames = Vec::new();
+        for i in 0..300u64 {
+            let timestamp_ns = i * 20_000_000;
+            clock_ns.set(timestamp_ns);
+            if i % 50 == 0 {
+                let data = [&[1], &i.to_le_bytes()[..], &[0xAA; 40]].concat();
+                segment.add_video_frame(video, &data, timestamp_ns, i % 200 == 0).unwrap();
+                frames.push((data, timestamp_ns));
+            }
+            let data = [&[2], &i.to_le_bytes()[..], &[0x55; 10]].concat();
+            segment.add_audio_frame(audio, &data, timestamp_ns, true).unwrap();
+            frames.push((data, timestamp_ns));
+        }
+        assert!(segment.cluster_count() >= 25, "{}", segment.cluster_count());
+        assert!(segment.finalize(None).is_ok());
+
+        // Every frame but those of the last cluster was flushed within the budget
+        let written = written.borrow();
+        let flushes = flushes.borrow();
+        for (data, timestamp_ns) in frames.iter().filter(|(_, timestamp_ns)| *timestamp_ns < 5_700_000_000) {
+            let end = written.windows(data.len()).position(|window| window == data).unwrap() + data.len();
+            let &(_, flushed_ns) = flushes.iter().find(|&&(flushed, _)| flushed >= end).unwrap();
+            let waited_ns = flushed_ns - timestamp_ns;
+            assert!(waited_ns <= BUDGET_NS, "The frame at {timestamp_ns}ns waited until {flushed_ns}ns");
+        }
+
+        let mut demuxer = Demuxer::new(Cursor::new(written.clone())).unwrap();
+        assert_eq!(demuxer.frames().count(), frames.len());
+    }
+
+    #[test]
+    fn starts_dash_segments_at_keyframes() {
+        use crate::demux::ebml;
+
+        const SEGMENT_NS: u64 = 2_000_000_000;
+        const TOLERANCE_NS: u64 = 100_000_000;
+        assert!(matches!(make_segment_builder().dash_live(0, 0), Err(Error::BadParam)));
+        assert!(matches!(make_segment_builder().dash_live(SEGMENT_NS, SEGMENT_NS / 2), Err(Error::BadParam)));
+        let builder = make_segment_builder().target_cluster_latency(200_000_000).unwrap();
+        assert!(matches!(builder.dash_live(SEGMENT_NS, TOLERANCE_NS), Err(Error::BadParam)));
+        let builder = make_segment_builder().dash_live(SEGMENT_NS, TOLERANCE_NS).unwrap();
+        assert!(matches!(builder.target_cluster_latency(200_000_000), Err(Error::BadParam)));
+
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.dash_live(SEGMENT_NS, TOLERANCE_NS).unwrap().build();
+        assert!(matches!(segment.add_video_frame(video, &[0; 40], 0, false), Err(Error::KeyframeOverdue { .. })));
+
+        // Ten seconds of 30fps video with a keyframe every 60 frames, which is slightly short of two seconds, and audio
+        // every 20ms
+        const FRAME_NS: u64 = 33_333_333;
+        let mut frames: Vec<_> = (0..300).map(|i| (i * FRAME_NS, true)).collect();
+        frames.extend((0..500).map(|i| (i * 20_000_000, false)));
+        frames.sort_by_key(|&(timestamp_ns, is_video)| (timestamp_ns, !is_video));
+        let mut keyframes_ns = Vec::new();
+        for (timestamp_ns, is_video) in frames {
+            if is_video {
+                let keyframe = timestamp_ns % (60 * FRAME_NS) == 0;
+                segment.add_video_frame(video, &[1; 40], timestamp_ns, keyframe).unwrap();
+                if keyframe {
+                    keyframes_ns.push(timestamp_ns);
+                }
+            } else {
+                segment.add_audio_frame(audio, &[2; 10], timestamp_ns, true).unwrap();
+            }
+        }
+
+        // The keyframe due at ten seconds is late: frames can still be written within the tolerance, then only the
+        // keyframe, which starts the next media segment
+        segment.add_video_frame(video, &[1; 40], 300 * FRAME_NS, false).unwrap();
+        segment.add_video_frame(video, &[1; 40], 302 * FRAME_NS, false).unwrap();
+        let late_ns = 304 * FRAME_NS;
+        let result = segment.add_video_frame(video, &[1; 40], late_ns, false);
+        assert!(
+            matches!(result, Err(Error::KeyframeOverdue { boundary_ns, timestamp_ns })
+                if boundary_ns == 10_000_000_000 && timestamp_ns == late_ns),
+            "{result:?}"
+        );
+        segment.add_video_frame(video, &[1; 40], late_ns, true).unwrap();
+        segment.add_video_frame(video, &[1; 40], 305 * FRAME_NS, false).unwrap();
+        keyframes_ns.push(late_ns);
+        assert_eq!(segment.cluster_count(), 6);
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let mut source = Cursor::new(&file[..]);
+        let top_level = ebml::children(&mut source, 0, file.len() as u64).unwrap();
+        let segment = top_level.iter().find(|element| element.id == 0x1853_8067).unwrap();
+        let children = ebml::children(&mut source, segment.pos, segment.end()).unwrap();
+        assert!(!children.iter().any(|element| element.id == 0x1C53_BB6B), "The segment has cues");
+
+        // Every cluster starts with a video keyframe, one for each media segment
+        let mut cluster_starts_ms = Vec::new();
+        for cluster in children.iter().filter(|element| element.id == 0x1F43_B675) {
+            let blocks = ebml::children(&mut source, cluster.pos, cluster.end()).unwrap();
+            let timecode = blocks.iter().find(|element| element.id == 0xE7).unwrap();
+            let timecode_ms = ebml::read_uint(&mut source, timecode).unwrap();
+            let first = blocks.iter().find(|element| element.id == 0xA3).unwrap();
+            let block = ebml::read_bytes(&mut source, first).unwrap();
+            assert_eq!(TrackNum::from(block[0] & 0x7F), TrackNum::from(video));
+            assert_ne!(block[3] & 0x80, 0, "The cluster at {timecode_ms}ms doesn't start with a keyframe");
+            let offset_ms = i16::from_be_bytes([block[1], block[2]]);
+            cluster_starts_ms.push(timecode_ms.checked_add_signed(offset_ms.into()).unwrap());
+        }
+        let keyframes_ms: Vec<_> = keyframes_ns.iter().map(|timestamp_ns| timestamp_ns / 1_000_000).collect();
+        assert_eq!(cluster_starts_ms, keyframes_ms);
+    }
+
+    #[test]
+    fn writes_vfr_durations() {
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let (builder, other) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.vfr_video(video).unwrap().vfr_video(other).unwrap();
+        let mut segment = builder.build();
+        assert!(matches!(segment.finish_vfr(other, None), Ok(())));
+
+        // Screen capture: a burst of frames, then nothing changes for a while. The other track's frames only finish at
+        // finalization, with the last one lasting as long as the one before it.
+        let video_ms = [0, 16, 33, 34, 500, 517, 2_000, 2_016];
+        for (i, &timestamp_ms) in video_ms.iter().enumerate() {
+            let timestamp_ns = timestamp_ms * 1_000_000;
+            segment.add_video_frame(video, &[i as u8; 20], timestamp_ns, i % 4 == 0).unwrap();
+            if i % 2 == 0 {
+                segment.add_video_frame(other, &[i as u8; 10], timestamp_ns, i == 0).unwrap();
+            }
+            segment.add_audio_frame(audio, &[0xA0; 10], timestamp_ns, true).unwrap();
+        }
+        let held_ns = 2_016 * 1_000_000;
+        assert!(matches!(segment.add_video_frame(video, &[0; 20], held_ns, false), Err(Error::BadParam)));
+        assert!(matches!(segment.finish_vfr(other, Some(0)), Err(Error::BadParam)));
+        segment.finish_vfr(video, Some(40_000_000)).unwrap();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        assert!(demuxer.tracks().iter().all(|track| track.default_duration_ns.is_none()));
+
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        let durations = |track: TrackNum| -> Vec<_> {
+            frames
+                .iter()
+                .filter(|frame| frame.track_num == track)
+                .map(|frame| (frame.data[0], frame.timestamp_ns / 1_000_000, frame.duration_ns.unwrap() / 1_000_000))
+                .collect()
+        };
+        let expected: Vec<_> = video_ms
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp_ms)| (i as u8, timestamp_ms, video_ms.get(i + 1).unwrap_or(&2_056) - timestamp_ms))
+            .collect();
+        assert_eq!(durations(video.into()), expected);
+        assert_eq!(durations(other.into()), [(0, 0, 33), (2, 33, 467), (4, 500, 1_500), (6, 2_000, 1_500)]);
+        assert_eq!(frames.iter().filter(|frame| frame.track_num == TrackNum::from(audio)).count(), video_ms.len());
+    }
+
+    #[test]
+    fn writes_reference_blocks() {
+        use crate::demux::ebml;
+
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 20], 0, true).unwrap();
+        segment.add_frame_with_references(video, &[1; 20], 33_000_000, &[-33_000_000]).unwrap();
+
+        // An alternate reference frame, hidden and shown with the same timestamp, from the last two frames. Offsets are
+        // between truncated timecodes, so that they still lead to the referenced blocks
+        segment.add_frame_with_references(video, &[2; 20], 66_900_000, &[-33_900_000, -66_900_000]).unwrap();
+        segment.add_frame_with_references(video, &[3; 20], 66_900_000, &[0]).unwrap();
+        let extras = FrameExtras {
+            addition: Some((1, &[0xAD; 4])),
+            duration_ns: Some(33_000_000),
+            references_ns: &[-100_000_000, -33_100_000],
+            ..FrameExtras::default()
+        };
+        segment.add_frame_with_extras(video, &[4; 20], 100_000_000, false, extras).unwrap();
+
+        for references_ns in [&[][..], &[1], &[-100_000_001]] {
+            let result = segment.add_frame_with_references(video, &[5; 20], 100_000_000, references_ns);
+            assert!(matches!(result, Err(Error::BadParam)), "{references_ns:?}");
+        }
+        let result = segment.add_frame_with_references(audio, &[5; 20], 100_000_000, &[-20_000_000]);
+        assert!(matches!(result, Err(Error::BadParam)));
+        let padded = FrameExtras {
+            discard_padding_ns: 1_000,
+            references_ns: &[-20_000_000],
+            ..FrameExtras::default()
+        };
+        let result = segment.add_frame_with_extras(video, &[5; 20], 100_000_000, false, padded);
+        assert!(matches!(result, Err(Error::BadParam)));
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let mut source = Cursor::new(&file[..]);
+        let top_level = ebml::children(&mut source, 0, file.len() as u64).unwrap();
+        let segment = top_level.iter().find(|element| element.id == 0x1853_8067).unwrap();
+        let children = ebml::children(&mut source, segment.pos, segment.end()).unwrap();
+        let mut groups = Vec::new();
+        for cluster in children.iter().filter(|element| element.id == 0x1F43_B675) {
+            let blocks = ebml::children(&mut source, cluster.pos, cluster.end()).unwrap();
+            for group in blocks.iter().filter(|element| element.id == 0xA0) {
+                let elements = ebml::children(&mut source, group.pos, group.end()).unwrap();
+                let ids: Vec<_> = elements.iter().map(|element| element.id).collect();
+                let references: Vec<_> = elements
+                    .iter()
+                    .filter(|element| element.id == 0xFB)
+                    .map(|element| {
+                        let value = ebml::read_bytes(&mut source, element).unwrap();
+                        let sign = if value[0] & 0x80 == 0 { 0 } else { -1 };
+                        value.iter().fold(sign, |signed, &byte| signed << 8 | i64::from(byte))
+                    })
+                    .collect();
+                groups.push((ids, references));
+            }
+        }
+        let (block, additions, reference, duration) = (0xA1, 0x75A1, 0xFB, 0x9B);
+        let expected = [
+            (vec![block, reference], vec![-33]),
+            (vec![block, reference, reference], vec![-33, -66]),
+            (vec![block, reference], vec![0]),
+            (vec![block, additions, reference, reference, duration], vec![-100, -34]),
+        ];
+        assert_eq!(groups, expected);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        let read: Vec<_> = frames.iter().map(|frame| (frame.data[0], frame.timestamp_ns, frame.is_keyframe)).collect();
+        let expected = [
+            (0, 0, true),
+            (1, 33_000_000, false),
+            (2, 66_000_000, false),
+            (3, 66_000_000, false),
+            (4, 100_000_000, false),
+        ];
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn cancels_without_writing() {
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..10 {
+            segment.add_video_frame(video, &[i; 100], u64::from(i) * 33_000_000, i == 0).unwrap();
+        }
+        let written = segment.writer_mut().dest_mut().get_ref().clone();
+        assert!(!written.is_empty());
+        let writer = segment.cancel();
+        assert_eq!(*writer.into_inner().get_ref(), written);
+
+        // The destination is cut back to where the segment started, which may be after what was written before it
+        let mut dest = Cursor::new(b"header".to_vec());
+        dest.set_position(6);
+        let builder = SegmentBuilder::new(Writer::new(dest)).unwrap();
+        let (builder, video) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[1; 100], 0, true).unwrap();
+        let Ok(writer) = segment.cancel_and_truncate() else {
+            panic!("Truncating the destination failed");
+        };
+        assert_eq!(writer.into_inner().into_inner(), b"header");
+
+        // A writer whose destination is left empty can go on to write another segment
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[1; 100], 0, true).unwrap();
+        let Ok(writer) = segment.cancel_and_truncate() else {
+            panic!("Truncating the destination failed");
+        };
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[2; 100], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, [2; 100]);
+    }
+
+    #[test]
+    fn writes_content_encryption() {
+        use crate::demux::ebml;
+
+        const KEY_ID: &[u8; 16] = b"\x01\x23\x45\x67\x89\xab\xcd\xef\xfe\xdc\xba\x98\x76\x54\x32\x10";
+        let add_video = || make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let settings = EncryptionSettings::default;
+        let (builder, video) = add_video();
+        assert!(matches!(builder.add_content_encryption(video, &KEY_ID[..8], settings()), Err(Error::BadParam)));
+        let (builder, video) = add_video();
+        let unknown = TrackNum::from(video) + 1;
+        assert!(matches!(builder.add_content_encryption(unknown, KEY_ID, settings()), Err(Error::BadParam)));
+        let (builder, video) = add_video();
+        let builder = builder.add_content_encryption(video, KEY_ID, settings()).unwrap();
+        assert!(matches!(builder.add_content_encryption(video, KEY_ID, settings()), Err(Error::BadParam)));
+
+        let (builder, video) = add_video();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.add_content_encryption(video, KEY_ID, settings()).unwrap().build();
+        segment.add_video_frame(video, &[0; 20], 0, true).unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+
+        // The layout mkvmerge and Shaka Packager write: a single ContentEncoding for whole frames (order 0, scope 1,
+        // type 1) with AES (algorithm 5) in CTR mode (1)
+        let mut source = Cursor::new(&file[..]);
+        let mut children = |parent: &ebml::Element| ebml::children(&mut source, parent.pos, parent.end()).unwrap();
+        let root = ebml::Element {
+            id: 0,
+            start: 0,
+            pos: 0,
+            size: file.len() as u64,
+        };
+        let find = |elements: &[ebml::Element], id| *elements.iter().find(|element| element.id == id).unwrap();
+        let segment = find(&children(&root), 0x1853_8067);
+        let tracks = find(&children(&segment), 0x1654_AE6B);
+        let entries = children(&tracks);
+        assert!(!children(&entries[1]).iter().any(|element| element.id == 0x6D80));
+        let encodings = find(&children(&entries[0]), 0x6D80);
+        let encodings = children(&encodings);
+        assert_eq!(encodings.iter().map(|element| element.id).collect::<Vec<_>>(), [0x6240]);
+        let encoding = children(&encodings[0]);
+        assert_eq!(encoding.iter().map(|element| element.id).collect::<Vec<_>>(), [0x5031, 0x5032, 0x5033, 0x5035]);
+        let encryption = children(&encoding[3]);
+        assert_eq!(encryption.iter().map(|element| element.id).collect::<Vec<_>>(), [0x47E1, 0x47E2, 0x47E7]);
+        let aes_settings = children(&encryption[2]);
+        assert_eq!(aes_settings.iter().map(|element| element.id).collect::<Vec<_>>(), [0x47E8]);
+
+        let mut source = Cursor::new(&file[..]);
+        let uints: Vec<_> = [encoding[0], encoding[1], encoding[2], encryption[0], aes_settings[0]]
+            .iter()
+            .map(|element| ebml::read_uint(&mut source, element).unwrap())
+            .collect();
+        assert_eq!(uints, [0, 1, 1, 5, 1]);
+        assert_eq!(ebml::read_bytes(&mut source, &encryption[1]).unwrap(), KEY_ID);
+    }
+
+    #[test]
+    fn rejects_invalid_color_formats() {
+        let sampled = |chroma_horizontal, chroma_vertical| ColorSubsampling {
+            chroma_horizontal,
+            chroma_vertical,
+        };
+        let add_video = || make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None);
+        let bad = [(0, sampled(1, 1)), (9, sampled(1, 1)), (16, sampled(0, 0)), (255, sampled(1, 1))];
+        let bad = bad.into_iter().chain([(8, sampled(2, 1)), (10, sampled(1, 2)), (12, sampled(7, 7))]);
+        for (bit_depth, subsampling) in bad {
+            let (builder, video) = add_video().unwrap();
+            let result = builder.set_color(video, bit_depth, subsampling, ColorRange::Full);
+            assert!(matches!(result, Err(Error::BadParam)), "{bit_depth} bits, {subsampling:?}");
+        }
+
+        for (bit_depth, subsampling) in [(8, sampled(0, 0)), (10, sampled(1, 0)), (12, sampled(1, 1))] {
+            let (builder, video) = add_video().unwrap();
+            let builder = builder.set_color(video, bit_depth, subsampling, ColorRange::Full).unwrap();
+            let file = finish_with_keyframe(builder, video);
+            let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+            let TrackType::Video(info) = &demuxer.track(video.into()).unwrap().track_type else {
+                panic!("Expected a video track");
+            };
+            let colour = info.colour.as_deref().unwrap();
+            assert_eq!((colour.bits_per_channel, colour.chroma_subsampling), (Some(bit_depth), Some(subsampling)));
+        }
+    }
+
+    #[test]
+    fn writes_color_presets() {
+        // The code points of ITU-T H.273 (ISO/IEC 23091-4), which Matroska uses:
+        //   ColourPrimaries:         1 = BT.709, sRGB; 5 = BT.601 625-line (BT.470 B/G); 6 = BT.601 525-line
+        //                            (SMPTE 170M); 9 = BT.2020, BT.2100
+        //   TransferCharacteristics: 1 = BT.709; 6 = BT.601 (SMPTE 170M); 13 = sRGB (IEC 61966-2-1);
+        //                            16 = PQ (SMPTE ST 2084); 18 = HLG (ARIB STD-B67)
+        //   MatrixCoefficients:      0 = identity (RGB); 1 = BT.709; 5 = BT.601 625-line; 6 = BT.601 525-line;
+        //                            9 = BT.2020 non-constant luminance
+        // Matroska ranges are 1 = broadcast and 2 = full, and sitings 1 = collocated and 2 = half. An unspecified
+        // siting is left out.
+        let presets = [
+            (ColorDescription::BT709, [1, 1, 1, 1, 1, 2].map(Some)),
+            (ColorDescription::BT601_625, [5, 6, 5, 1, 1, 2].map(Some)),
+            (ColorDescription::BT601_525, [6, 6, 6, 1, 1, 2].map(Some)),
+            (ColorDescription::BT2020_NCL_PQ, [9, 16, 9, 1, 1, 2].map(Some)),
+            (ColorDescription::BT2020_NCL_HLG, [9, 18, 9, 1, 1, 2].map(Some)),
+            (ColorDescription::SRGB, [Some(1), Some(13), Some(0), Some(2), None, None]),
+        ];
+
+        // libwebm doesn't know the P3 primaries, and would fail to write the track headers
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let p3 = ColorDescription::from_cicp(12, 16, 9, false);
+        assert!(matches!(builder.set_color_preset(video, p3), Err(Error::BadParam)));
+
+        let mut builder = make_segment_builder();
+        let mut tracks = Vec::new();
+        for (preset, _) in presets {
+            let (next, video) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+            builder = next.set_color_preset(video, preset).unwrap();
+            tracks.push(video);
+        }
+        // What set_color sets is kept apart, whichever is called first, except that the last call sets the range
+        builder = builder.set_color(tracks[0], 10, ColorSubsampling::default(), ColorRange::Full).unwrap();
+        builder = builder.set_color_preset(tracks[0], ColorDescription::BT709).unwrap();
+        builder = builder.set_color(tracks[1], 10, ColorSubsampling::default(), ColorRange::Broadcast).unwrap();
+        let mut segment = builder.build();
+        for &video in &tracks {
+            segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        for (&video, (preset, expected)) in tracks.iter().zip(presets) {
+            let colour = demuxer.colour(TrackNum::from(video)).unwrap();
+            let written = [
+                colour.primaries,
+                colour.transfer_characteristics,
+                colour.matrix_coefficients,
+                colour.range,
+                colour.chroma_siting_horz,
+                colour.chroma_siting_vert,
+            ];
+            assert_eq!(written, expected, "{preset:?}");
+        }
+        for video in &tracks[..2] {
+            assert_eq!(demuxer.colour(TrackNum::from(*video)).unwrap().bits_per_channel, Some(10));
+        }
+        assert_eq!(ColorDescription::from_cicp(9, 16, 9, false).chroma_siting_horizontal, ChromaSiting::Unspecified);
+        assert_eq!(ColorDescription::from_cicp(1, 13, 0, true), ColorDescription::SRGB);
+    }
+
+    #[test]
+    fn writes_color_description() {
+        let (builder, _) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let description = builder.set_color_description(
+            VideoTrack(audio.0, audio.1),
+            ColourPrimaries::Bt2020,
+            TransferCharacteristics::Pq,
+            MatrixCoefficients::Bt2020Ncl,
+        );
+        assert!(matches!(description, Err(Error::UnknownTrack { .. })));
+
+        use crate::mux::{ColourPrimaries as P, MatrixCoefficients as M, TransferCharacteristics as T};
+        let cases = [
+            ((P::Bt2020, T::Pq, M::Bt2020Ncl), [Some(9), Some(16), Some(9)]),
+            ((P::Bt709, T::Srgb, M::Identity), [Some(1), Some(13), Some(0)]),
+            ((P::JedecP22, T::Unspecified, M::Unspecified), [Some(22), None, None]),
+            ((P::default(), T::default(), M::default()), [None; 3]),
+        ];
+        let mut builder = make_segment_builder();
+        let mut tracks = Vec::new();
+        for ((primaries, transfer, matrix), _) in cases {
+            let (next, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            builder = next.set_color_description(video, primaries, transfer, matrix).unwrap();
+            tracks.push(video);
+        }
+        // The code points of a preset are replaced, and its range and chroma siting kept
+        let (builder, preset) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let builder = builder.set_color_preset(preset, ColorDescription::BT709).unwrap();
+        let builder = builder.set_color_description(preset, P::Bt2020, T::Hlg, M::Bt2020Ncl).unwrap();
+        let mut segment = builder.build();
+        for &video in tracks.iter().chain([&preset]) {
+            segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let code_points = |video: VideoTrack| {
+            let colour = demuxer.colour(TrackNum::from(video)).unwrap_or_default();
+            [colour.primaries, colour.transfer_characteristics, colour.matrix_coefficients]
+        };
+        for (&video, (description, expected)) in tracks.iter().zip(cases) {
+            assert_eq!(code_points(video), expected, "{description:?}");
+        }
+        assert_eq!(code_points(preset), [Some(9), Some(18), Some(9)]);
+        let colour = demuxer.colour(TrackNum::from(preset)).unwrap();
+        assert_eq!([colour.range, colour.chroma_siting_horz, colour.chroma_siting_vert], [Some(1), Some(1), Some(2)]);
+        let bt709 = ColorDescription::new(P::Bt709, T::Bt709, M::Bt709, false);
+        assert_eq!(bt709, ColorDescription::from_cicp(1, 1, 1, false));
+    }
+
+    #[test]
+    fn video_setters_reject_foreign_tracks() {
+        type Setter = fn(SegmentBuilder<Cursor<Vec<u8>>>, VideoTrack) -> Result<SegmentBuilder<Cursor<Vec<u8>>>, Error>;
+        let setters: [(&str, Setter); 15] = [
+            ("set_color", |builder, track| builder.set_color(track, 10, ColorSubsampling::default(), ColorRange::Full)),
+            ("set_color_preset", |builder, track| builder.set_color_preset(track, ColorDescription::BT709)),
+            ("set_chroma_siting", |builder, track| {
+                builder.set_chroma_siting(track, ChromaSiting::Collocated, ChromaSiting::Half)
+            }),
+            ("set_color_description", |builder, track| {
+                let (p, t, m) = (ColourPrimaries::Bt709, TransferCharacteristics::Bt709, MatrixCoefficients::Bt709);
+                builder.set_color_description(track, p, t, m)
+            }),
+            ("set_display_size", |builder, track| builder.set_display_size(track, 85, 48)),
+            ("set_stereo_mode", |builder, track| builder.set_stereo_mode(track, StereoMode::SideBySideLeftFirst)),
+            ("set_aspect_ratio_type", |builder, track| builder.set_aspect_ratio_type(track, AspectRatioType::Fixed)),
+            ("set_interlacing", |builder, track| {
+                builder.set_interlacing(track, InterlaceMode::Interlaced, FieldOrder::TopFieldFirst)
+            }),
+            ("set_projection", |builder, track| {
+                builder.set_projection(track, &Projection::new(ProjectionType::Rectangular))
+            }),
+            ("set_frame_rate", |builder, track| builder.set_frame_rate(track, 25.0)),
+            ("enable_alpha", |builder, track| builder.enable_alpha(track)),
+            ("set_pixel_crop", |builder, track| builder.set_pixel_crop(track, 2, 2, 0, 0)),
+            ("set_content_light_level", |builder, track| builder.set_content_light_level(track, 1000, 400)),
+            ("set_mastering_metadata", |builder, track| {
+                builder.set_mastering_metadata(track, &MasteringMetadata::default())
+            }),
+            ("set_pixel_aspect_ratio", |builder, track| builder.set_pixel_aspect_ratio(track, 4, 3)),
+        ];
+
+        for (name, setter) in setters {
+            let (_, foreign) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            assert_eq!(TrackNum::from(foreign), TrackNum::from(video));
+            assert!(matches!(setter(builder, foreign), Err(Error::ForeignTrack { track_num: 1 })), "{name}");
+        }
+    }
+
+    #[test]
+    fn writes_chroma_siting() {
+        let subsampling = ColorSubsampling {
+            chroma_horizontal: 1,
+            chroma_vertical: 1,
+        };
+        let cases = [
+            (ChromaSiting::Unspecified, ChromaSiting::Unspecified, [None, None]),
+            (ChromaSiting::Collocated, ChromaSiting::Half, [Some(1), Some(2)]),
+            (ChromaSiting::Half, ChromaSiting::Unspecified, [Some(2), None]),
+        ];
+        for (horizontal, vertical, written) in cases {
+            let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            let builder = builder.set_color(video, 8, subsampling, ColorRange::Broadcast).unwrap();
+            let builder = builder.set_chroma_siting(video, horizontal, vertical).unwrap();
+            let file = finish_with_keyframe(builder, video);
+
+            let colour = *video_settings(&file).iter().find(|element| element.id == 0x55B0).unwrap();
+            let mut source = Cursor::new(&file[..]);
+            let colour = ebml::children(&mut source, colour.pos, colour.end()).unwrap();
+            let mut read = |id| {
+                let element = colour.iter().find(|element| element.id == id)?;
+                Some(ebml::read_uint(&mut source, element).unwrap())
+            };
+            // ChromaSitingHorz and ChromaSitingVert
+            assert_eq!([read(0x55B7), read(0x55B8)], written, "{horizontal:?} {vertical:?}");
+        }
+
+        let (builder, _) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let siting = builder.set_chroma_siting(VideoTrack(audio.0, audio.1), ChromaSiting::Half, ChromaSiting::Half);
+        assert!(matches!(siting, Err(Error::UnknownTrack { .. })));
+    }
+
+    #[test]
+    fn writes_content_light_level() {
+        let (builder, _) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let not_video = VideoTrack(audio.0, audio.1);
+        assert!(matches!(builder.set_content_light_level(not_video, 1000, 400), Err(Error::UnknownTrack { .. })));
+
+        let (builder, alone) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, with_color) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let builder = builder.set_content_light_level(alone, 1000, 400).unwrap();
+        let builder = builder.set_color(with_color, 10, ColorSubsampling::default(), ColorRange::Full).unwrap();
+        let builder = builder.set_content_light_level(with_color, 4000, 0).unwrap();
+        let mut segment = builder.build();
+        for video in [alone, with_color] {
+            segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let colour = demuxer.colour(alone.into()).unwrap();
+        assert_eq!((colour.max_cll, colour.max_fall, colour.bits_per_channel), (Some(1000), Some(400), None));
+        let colour = demuxer.colour(with_color.into()).unwrap();
+        assert_eq!((colour.max_cll, colour.max_fall, colour.bits_per_channel), (Some(4000), Some(0), Some(10)));
+    }
+
+    #[test]
+    fn writes_mastering_metadata() {
+        use crate::mux::Chromaticity;
+
+        let chromaticity = |x, y| Some(Chromaticity { x, y });
+        // BT.2020 primaries and a D65 white point, on a 1000 cd/m² display
+        let hdr10 = MasteringMetadata {
+            primary_r: chromaticity(0.708, 0.292),
+            primary_g: chromaticity(0.17, 0.797),
+            primary_b: chromaticity(0.131, 0.046),
+            white_point: chromaticity(0.3127, 0.329),
+            luminance_max: Some(1000.0),
+            luminance_min: Some(0.0001),
+        };
+
+        let primaries_only = MasteringMetadata {
+            luminance_max: None,
+            luminance_min: None,
+            ..hdr10
+        };
+        let bad = [
+            MasteringMetadata {
+                primary_g: chromaticity(0.17, 1.5),
+                ..hdr10
+            },
+            MasteringMetadata {
+                white_point: chromaticity(-0.1, 0.3),
+                ..hdr10
+            },
+            MasteringMetadata {
+                luminance_min: Some(-1.0),
+                ..hdr10
+            },
+            MasteringMetadata {
+                luminance_max: Some(f32::NAN),
+                ..hdr10
+            },
+            MasteringMetadata {
+                luminance_min: Some(2000.0),
+                ..hdr10
+            },
+            MasteringMetadata {
+                luminance_max: Some(20000.0),
+                ..hdr10
+            },
+            MasteringMetadata {
+                luminance_min: None,
+                ..hdr10
+            },
+        ];
+        let add_video = || make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None);
+        for mm in &bad {
+            let (builder, video) = add_video().unwrap();
+            assert!(matches!(builder.set_mastering_metadata(video, mm), Err(Error::BadParam)), "{mm:?}");
+        }
+
+        let mut builder = make_segment_builder();
+        let mut tracks = Vec::new();
+        for mm in [hdr10, primaries_only] {
+            let (next, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+            builder = next.set_mastering_metadata(video, &mm).unwrap();
+            tracks.push(video);
+        }
+        // The rest of the colour information is kept apart, whichever is set first
+        builder = builder.set_color_preset(tracks[0], ColorDescription::BT2020_NCL_PQ).unwrap();
+        builder = builder.set_color(tracks[0], 10, ColorSubsampling::default(), ColorRange::Broadcast).unwrap();
+        let mut segment = builder.build();
+        for &video in &tracks {
+            segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let colour = demuxer.colour(tracks[0].into()).unwrap();
+        assert_eq!(colour.mastering_metadata, Some(hdr10));
+        assert_eq!((colour.bits_per_channel, colour.transfer_characteristics), (Some(10), Some(16)));
+        let colour = demuxer.colour(tracks[1].into()).unwrap();
+        assert_eq!(colour.mastering_metadata, Some(primaries_only));
+    }
+
+    #[test]
+    fn writes_display_size_from_pixel_aspect_ratio() {
+        use crate::demux::ebml;
+
+        let add_video = |width, height| make_segment_builder().add_video_track(width, height, VideoCodecId::VP9, None);
+        let (builder, video) = add_video(720, 480).unwrap();
+        assert!(matches!(builder.set_pixel_aspect_ratio(video, 0, 11), Err(Error::BadParam)));
+        let (builder, video) = add_video(720, 480).unwrap();
+        assert!(matches!(builder.set_pixel_aspect_ratio(video, 10, 0), Err(Error::BadParam)));
+        let (builder, video) = add_video(1, 1).unwrap();
+        assert!(matches!(builder.set_pixel_aspect_ratio(video, 1, 3), Err(Error::BadParam)));
+
+        // The display sizes of Rec. 601 digitized NTSC and PAL, full width and with the 704-pixel active area, and of
+        // anamorphic HD
+        let cases = [
+            ((720, 480, 10, 11), (654, 480)),
+            ((720, 480, 40, 33), (872, 480)),
+            ((704, 480, 10, 11), (640, 480)),
+            ((720, 576, 12, 11), (786, 576)),
+            ((720, 576, 16, 11), (1048, 576)),
+            ((704, 576, 12, 11), (768, 576)),
+            ((1440, 1080, 4, 3), (1920, 1080)),
+            ((1920, 1080, 1, 1), (1920, 1080)),
+            ((3, 2, 1, 1), (4, 2)),
+        ];
+        for ((width, height, par_num, par_den), display_size) in cases {
+            let (builder, video) = add_video(width, height).unwrap();
+            let mut segment = builder.set_pixel_aspect_ratio(video, par_num, par_den).unwrap().build();
+            segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+            let Ok(writer) = segment.finalize(None) else {
+                panic!("Finalizing the segment failed");
+            };
+            let file = writer.into_inner().into_inner();
+
+            let mut source = Cursor::new(&file[..]);
+            let mut children = |parent: &ebml::Element| ebml::children(&mut source, parent.pos, parent.end()).unwrap();
+            let root = ebml::Element {
+                id: 0,
+                start: 0,
+                pos: 0,
+                size: file.len() as u64,
+            };
+            let find = |elements: &[ebml::Element], id| *elements.iter().find(|element| element.id == id).unwrap();
+            let segment = find(&children(&root), 0x1853_8067);
+            let tracks = find(&children(&segment), 0x1654_AE6B);
+            let entry = children(&tracks)[0];
+            let video = find(&children(&entry), 0xE0);
+            let video = children(&video);
+            let (display_width, display_height) = (find(&video, 0x54B0), find(&video, 0x54BA));
+            let mut source = Cursor::new(&file[..]);
+            let written = (
+                ebml::read_uint(&mut source, &display_width).unwrap(),
+                ebml::read_uint(&mut source, &display_height).unwrap(),
+            );
+            assert_eq!(written, display_size, "{width}x{height} at {par_num}:{par_den}");
+        }
+    }
+
+    #[test]
+    fn writes_display_size() {
+        let add_video = || make_segment_builder().add_video_track(720, 480, VideoCodecId::VP9, None);
+        let (builder, video) = add_video().unwrap();
+        assert!(matches!(builder.set_display_size(video, 0, 480), Err(Error::BadParam)));
+        let (builder, video) = add_video().unwrap();
+        assert!(matches!(builder.set_display_size(video, 853, 0), Err(Error::BadParam)));
+        let (builder, _) = add_video().unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let not_video = VideoTrack(audio.0, audio.1);
+        assert!(matches!(builder.set_display_size(not_video, 853, 480), Err(Error::UnknownTrack { .. })));
+
+        let (builder, video) = add_video().unwrap();
+        let (builder, square) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.set_display_size(video, 853, 480).unwrap().build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_video_frame(square, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let display_size = |track: VideoTrack| match &demuxer.track(track.into()).unwrap().track_type {
+            TrackType::Video(info) => (info.width, info.height, info.display_width, info.display_height),
+            other => panic!("Expected a video track, got {other:?}"),
+        };
+        assert_eq!(display_size(video), (720, 480, 853, 480));
+        // A track without a display size is shown at its coded size
+        assert_eq!(display_size(square), (64, 48, 64, 48));
+    }
+
+    #[test]
+    fn writes_stereo_modes() {
+        let add_video = || make_segment_builder().add_video_track(128, 48, VideoCodecId::VP9, None);
+        for mode in [StereoMode::CheckerboardLeftFirst, StereoMode::AnaglyphCyanRed, StereoMode::LacedRightFirst] {
+            let (builder, video) = add_video().unwrap();
+            assert!(matches!(builder.set_stereo_mode(video, mode), Err(Error::BadParam)), "{mode:?}");
+        }
+
+        // Mono is the default, so libwebm leaves it out
+        for (mode, written) in [(StereoMode::SideBySideLeftFirst, Some(1)), (StereoMode::Mono, None)] {
+            let (builder, video) = add_video().unwrap();
+            let file = finish_with_keyframe(builder.set_stereo_mode(video, mode).unwrap(), video);
+            let stereo_mode = video_settings(&file).into_iter().find(|element| element.id == 0x53B8);
+            let mut source = Cursor::new(&file[..]);
+            assert_eq!(stereo_mode.map(|element| ebml::read_uint(&mut source, &element).unwrap()), written);
+        }
+    }
+
+    #[test]
+    fn writes_aspect_ratio_type() {
+        let add_video = || make_segment_builder().add_video_track(720, 480, VideoCodecId::VP9, None);
+        let (builder, _) = add_video().unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let not_video = VideoTrack(audio.0, audio.1);
+        let aspect_ratio_type = builder.set_aspect_ratio_type(not_video, AspectRatioType::Fixed);
+        assert!(matches!(aspect_ratio_type, Err(Error::UnknownTrack { .. })));
+
+        // FreeResizing is the default, so it's left out
+        let cases = [
+            (AspectRatioType::FreeResizing, None),
+            (AspectRatioType::KeepAspectRatio, Some(1)),
+            (AspectRatioType::Fixed, Some(2)),
+        ];
+        for (ty, written) in cases {
+            let (builder, video) = add_video().unwrap();
+            let builder = builder.set_display_size(video, 853, 480).unwrap();
+            let builder = builder.set_color_preset(video, ColorDescription::BT709).unwrap();
+            let file = finish_with_keyframe(builder.set_aspect_ratio_type(video, ty).unwrap(), video);
+            let settings = video_settings(&file);
+            let mut source = Cursor::new(&file[..]);
+            let mut read = |id| {
+                let element = settings.iter().find(|element| element.id == id)?;
+                Some(ebml::read_uint(&mut source, element).unwrap())
+            };
+            assert_eq!(read(0x54B3), written, "{ty:?}");
+            // The rest of the Video element is still written, and the file still demuxes
+            let sizes = (read(0xB0), read(0xBA), read(0x54B0), read(0x54BA));
+            assert_eq!(sizes, (Some(720), Some(480), Some(853), Some(480)));
+            assert!(settings.iter().any(|element| element.id == 0x55B0));
+            assert!(Demuxer::new(Cursor::new(file)).is_ok());
+        }
     }
-}
 
-fn try_as_i32(x: impl TryInto<i32>) -> Result<i32, Error> {
-    x.try_into().map_err(|_| Error::BadParam)
-}
+    #[test]
+    fn writes_interlacing() {
+        let add_video = || make_segment_builder().add_video_track(720, 576, VideoCodecId::VP9, None);
+        for order in [FieldOrder::TopFieldFirst, FieldOrder::Undetermined] {
+            let (builder, video) = add_video().unwrap();
+            let result = builder.set_interlacing(video, InterlaceMode::Interlaced, order);
+            assert!(matches!(result, Err(Error::NotInWebm { element: "FlagInterlaced" })), "{order:?}");
+        }
+        let bad = [
+            (InterlaceMode::Progressive, FieldOrder::Undetermined),
+            (InterlaceMode::Undetermined, FieldOrder::BottomFieldFirst),
+        ];
+        for (mode, order) in bad {
+            let (builder, video) = add_video().unwrap();
+            assert!(matches!(builder.set_interlacing(video, mode, order), Err(Error::BadParam)), "{mode:?} {order:?}");
+        }
 
-#[cfg(test)]
-mod tests {
-    use crate::mux::Writer;
+        // Undetermined is the default of both, so setting it back leaves them out. The aspect ratio type is written
+        // alongside them
+        let cases = [
+            ((InterlaceMode::Progressive, FieldOrder::Progressive), (Some(2), Some(0))),
+            ((InterlaceMode::Undetermined, FieldOrder::Undetermined), (None, None)),
+        ];
+        for ((mode, order), written) in cases {
+            let (builder, video) = add_video().unwrap();
+            let builder = builder.set_interlacing(video, InterlaceMode::Progressive, FieldOrder::Progressive).unwrap();
+            let builder = builder.set_aspect_ratio_type(video, AspectRatioType::Fixed).unwrap();
+            let file = finish_with_keyframe(builder.set_interlacing(video, mode, order).unwrap(), video);
+            let settings = video_settings(&file);
+            let mut source = Cursor::new(&file[..]);
+            let mut read = |id| {
+                let element = settings.iter().find(|element| element.id == id)?;
+                Some(ebml::read_uint(&mut source, element).unwrap())
+            };
+            assert_eq!((read(0x9A), read(0x9D)), written, "{mode:?}");
+            assert_eq!(read(0x54B3), Some(2));
+        }
+    }
 
-    use super::*;
-    use std::io::Cursor;
+    #[test]
+    fn writes_projections() {
+        let add_video = || make_segment_builder().add_video_track(256, 128, VideoCodecId::VP9, None);
+        let posed = |pose_yaw, pose_pitch, pose_roll| Projection {
+            pose_yaw,
+            pose_pitch,
+            pose_roll,
+            ..Projection::new(ProjectionType::Equirectangular)
+        };
+        let bad = [
+            posed(180.5, 0.0, 0.0),
+            posed(0.0, -90.5, 0.0),
+            posed(0.0, 0.0, 181.0),
+            posed(f32::NAN, 0.0, 0.0),
+            Projection::new(ProjectionType::Other(4)),
+        ];
+        for proj in &bad {
+            let (builder, video) = add_video().unwrap();
+            assert!(matches!(builder.set_projection(video, proj), Err(Error::BadParam)), "{proj:?}");
+        }
+        let (builder, _) = add_video().unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let not_video = VideoTrack(audio.0, audio.1);
+        let mesh = Projection::new(ProjectionType::Mesh);
+        assert!(matches!(builder.set_projection(not_video, &mesh), Err(Error::UnknownTrack { .. })));
 
-    fn make_segment_builder() -> SegmentBuilder<Cursor<Vec<u8>>> {
-        let output = Vec::new();
-        let writer = Writer::new(Cursor::new(output));
-        SegmentBuilder::new(writer).expect("Segment builder should create OK")
+        // An equirectangular projection with its `equi` box, turned to the edges of the pose ranges
+        let equirectangular = Projection {
+            private_data: vec![0; 20],
+            ..posed(-180.0, 90.0, 12.5)
+        };
+        for proj in [equirectangular, Projection::new(ProjectionType::Rectangular)] {
+            let (builder, video) = add_video().unwrap();
+            let file = finish_with_keyframe(builder.set_projection(video, &proj).unwrap(), video);
+
+            let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+            let TrackType::Video(info) = &demuxer.track(video.into()).unwrap().track_type else {
+                panic!("Expected a video track");
+            };
+            let written = info.projection.as_ref().unwrap();
+            assert_eq!(written.projection_type, proj.projection_type);
+            assert_eq!(written.private_data, proj.private_data);
+            let pose = (written.pose_yaw, written.pose_pitch, written.pose_roll);
+            assert_eq!(pose, (Some(proj.pose_yaw), Some(proj.pose_pitch), Some(proj.pose_roll)));
+        }
     }
 
     #[test]
-    fn bad_track_number() {
+    fn writes_frame_rate() {
+        let add_video = || make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None);
+        for fps in [0.0, -25.0, f64::NAN, f64::INFINITY, 1e39, 1e-46] {
+            let (builder, video) = add_video().unwrap();
+            assert!(matches!(builder.set_frame_rate(video, fps), Err(Error::BadParam)), "{fps}");
+        }
+        let (builder, _) = add_video().unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(builder.set_frame_rate(VideoTrack(audio.0, audio.1), 25.0), Err(Error::UnknownTrack { .. })));
+
+        let (builder, video) = add_video().unwrap();
+        let file = finish_with_keyframe(builder.set_frame_rate(video, 30000.0 / 1001.0).unwrap(), video);
+        let frame_rate = *video_settings(&file).iter().find(|element| element.id == 0x2383E3).unwrap();
+        assert_eq!(frame_rate.size, 4);
+        let written = ebml::read_float(&mut Cursor::new(&file[..]), &frame_rate).unwrap();
+        assert_eq!(written, f64::from((30000.0 / 1001.0) as f32));
+    }
+
+    #[test]
+    fn writes_alpha_mode() {
+        let (builder, _) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(builder.enable_alpha(VideoTrack(audio.0, audio.1)), Err(Error::UnknownTrack { .. })));
+
+        let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.enable_alpha(video).unwrap().build();
+        let alpha = [0xA1, 0xA2];
+        let extras = FrameExtras {
+            addition: Some((1, &alpha)),
+            ..FrameExtras::default()
+        };
+        segment.add_frame_with_extras(video, &[0; 10], 0, true, extras).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+
+        let alpha_mode = *video_settings(&file).iter().find(|element| element.id == 0x53C0).unwrap();
+        assert_eq!(ebml::read_uint(&mut Cursor::new(&file[..]), &alpha_mode).unwrap(), 1);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.track(video.into()).unwrap().max_block_addition_id, 1);
+        let frames: Vec<_> = demuxer.frames().map(Result::unwrap).collect();
+        assert_eq!(frames[0].additions, [(1, alpha.to_vec())]);
+    }
+
+    #[test]
+    fn writes_pixel_crop() {
+        let add_video = || make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None);
+        let (builder, _) = add_video().unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let not_video = VideoTrack(audio.0, audio.1);
+        assert!(matches!(builder.set_pixel_crop(not_video, 0, 0, 0, 0), Err(Error::UnknownTrack { .. })));
+        let (builder, video) = add_video().unwrap();
+        assert!(matches!(builder.set_pixel_crop(video, 24, 24, 0, 0), Err(Error::BadParam)));
+        let (builder, video) = add_video().unwrap();
+        assert!(matches!(builder.set_pixel_crop(video, 0, 0, 0, 70), Err(Error::BadParam)));
+        let (builder, video) = add_video().unwrap();
+        assert!(matches!(builder.set_pixel_crop(video, 0, 0, u32::MAX, u32::MAX), Err(Error::BadParam)));
+
+        // The display size takes in the crop set before the pixel aspect ratio
+        let (builder, video) = add_video().unwrap();
+        let builder = builder.set_pixel_crop(video, 2, 6, 0, 4).unwrap();
+        let file = finish_with_keyframe(builder.set_pixel_aspect_ratio(video, 1, 1).unwrap(), video);
+        let settings = video_settings(&file);
+        let mut source = Cursor::new(&file[..]);
+        let mut read = |id| {
+            let element = settings.iter().find(|element| element.id == id)?;
+            Some(ebml::read_uint(&mut source, element).unwrap())
+        };
+        // PixelCropTop, PixelCropBottom, PixelCropLeft and PixelCropRight, the last two left out at zero
+        assert_eq!([read(0x54BB), read(0x54AA), read(0x54CC), read(0x54DD)], [Some(2), Some(6), None, Some(4)]);
+        assert_eq!((read(0x54B0), read(0x54BA)), (Some(60), Some(40)));
+    }
+
+    #[test]
+    fn moves_between_threads() {
+        let (builder, track) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(track, &[0; 10], 0, true).unwrap();
+
+        // Moving a segment to another thread is fine, and isn't mistaken for sharing it
+        let mut segment = std::thread::spawn(move || {
+            segment.add_frame(track, &[0; 10], 1_000_000, false).unwrap();
+            segment
+        })
+        .join()
+        .unwrap();
+        segment.add_frame(track, &[0; 10], 2_000_000, false).unwrap();
+        assert!(segment.finalize(None).is_ok());
+    }
+
+    #[test]
+    fn rejects_foreign_tracks() {
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        // The same tracks the other way around, so that the numbers of the handles are swapped
+        let (other, other_audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (other, other_video) = other.add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        assert_eq!(TrackNum::from(other_audio), TrackNum::from(video));
+        drop(other);
+
+        // The generic method writes the audio frame to the video track
+        segment.add_frame(other_audio, &[0; 10], 0, true).unwrap();
+
+        let result = segment.add_audio_frame(other_audio, &[0; 10], 0, true);
+        assert!(matches!(result, Err(Error::ForeignTrack { .. })), "{result:?}");
+        let result = segment.add_video_frame(other_video, &[0; 10], 0, true);
+        assert!(matches!(result, Err(Error::ForeignTrack { .. })), "{result:?}");
+
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        assert!(segment.finalize(None).is_ok());
+    }
+
+    /// The `FlagDefault` of each track of `file` by track number, taking a missing flag to be set
+    fn default_flags(file: &[u8]) -> Vec<(TrackNum, bool)> {
+        use crate::demux::ebml;
+
+        let mut source = Cursor::new(file);
+        let top_level = ebml::children(&mut source, 0, file.len() as u64).unwrap();
+        let segment = top_level.iter().find(|element| element.id == 0x1853_8067).unwrap();
+        let children = ebml::children(&mut source, segment.pos, segment.end()).unwrap();
+        let tracks = children.iter().find(|element| element.id == 0x1654_AE6B).unwrap();
+        let entries = ebml::children(&mut source, tracks.pos, tracks.end()).unwrap();
+        entries
+            .iter()
+            .map(|entry| {
+                let mut track_num = 0;
+                let mut default = true;
+                for child in ebml::children(&mut source, entry.pos, entry.end()).unwrap() {
+                    match child.id {
+                        0xD7 => track_num = ebml::read_uint(&mut source, &child).unwrap(),
+                        0x88 => default = ebml::read_uint(&mut source, &child).unwrap() == 1,
+                        _ => {},
+                    }
+                }
+                (track_num, default)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picks_one_default_track() {
+        let add_tracks = || {
+            let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+            let (builder, first) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+            let (builder, second) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+            let (builder, third) = builder.add_audio_track(48_000, 1, AudioCodecId::Opus, None).unwrap();
+            (builder, video, [first, second, third])
+        };
+
+        // Every track is a default track to begin with
+        let (builder, _, audio) = add_tracks();
+        let result = builder.build_strict();
+        let Err(Error::DefaultTracks { track_nums }) = result else {
+            panic!("{result:?}")
+        };
+        assert_eq!(track_nums, audio.map(TrackNum::from));
+
+        let (builder, _, _) = add_tracks();
+        let (_, other) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let result = builder.set_default_audio_track(other);
+        assert!(matches!(result, Err(Error::ForeignTrack { .. })), "{result:?}");
+
+        // Picking another track clears the flag of the one picked before
+        let (builder, video, audio) = add_tracks();
+        let builder = builder.set_default_audio_track(audio[0]).unwrap().set_default_audio_track(audio[1]).unwrap();
+        let mut segment = builder.set_default_video_track(video).unwrap().build_strict().unwrap();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        for track in audio {
+            segment.add_audio_frame(track, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        assert_eq!(default_flags(&file), [(1, true), (2, false), (3, true), (4, false)]);
+
+        let report = crate::validate::validate(Cursor::new(&file), crate::validate::ValidationOptions::default());
+        assert!(report.is_valid(), "{:?}", report.findings);
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.tracks().len(), 4);
+        assert_eq!(demuxer.frames().count(), 4);
+    }
+
+    #[test]
+    fn adds_audio_tracks_in_bulk() {
+        const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+        let languages = ["eng", "fre", "ger", "spa", "ita", "jpn"];
+        let mut configs: Vec<AudioTrackConfig> = languages
+            .iter()
+            .map(|language| {
+                let mut config = AudioTrackConfig::new(AudioCodecId::Opus, 48_000, 2);
+                config.codec_private = OPUS_HEAD.to_vec();
+                config.name = Some(format!("Dub ({language})"));
+                config.language = Some((*language).to_owned());
+                config
+            })
+            .collect();
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+
+        // Nothing is added when a config is invalid, so the call can be retried
+        configs[3].language = Some("sp\0a".into());
+        configs[4].track_num = Some(1);
+        let Err((builder, Error::Spec { path, .. }, 3)) = builder.add_audio_tracks(&configs) else {
+            panic!("The invalid language should have been reported")
+        };
+        assert_eq!(path, "language");
+        configs[3].language = Some("spa".into());
+        let Err((builder, Error::Spec { path, .. }, 4)) = builder.add_audio_tracks(&configs) else {
+            panic!("The taken track number should have been reported")
+        };
+        assert_eq!(path, "track_num");
+        configs[4].track_num = Some(9);
+
+        let (builder, audio) = builder.add_audio_tracks(&configs).unwrap();
+        assert_eq!(audio.iter().map(|&track| TrackNum::from(track)).collect::<Vec<_>>(), [2, 3, 4, 5, 9, 7]);
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        for &track in &audio {
+            segment.add_audio_frame(track, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        let tracks = demuxer.tracks();
+        assert_eq!(tracks.len(), 7);
+        for ((info, track), language) in tracks[1..].iter().zip(audio).zip(languages) {
+            assert_eq!(info.track_num, TrackNum::from(track));
+            assert_eq!(info.language.as_deref(), Some(language));
+            assert_eq!(info.name, Some(format!("Dub ({language})")));
+            assert_eq!(info.codec_private, OPUS_HEAD);
+        }
+    }
+
+    #[test]
+    fn retries_audio_tracks_after_libwebm_fails() {
+        let (builder, video) = make_segment_builder().add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        // Hide track 2 from the builder, so that the configs are checked fine but libwebm refuses the last of them
+        let (mut builder, _) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, Some(2)).unwrap();
+        builder.tracks.tracks.pop();
+        let mut configs: Vec<AudioTrackConfig> = [3, 4, 2]
+            .into_iter()
+            .map(|track_num| AudioTrackConfig {
+                track_num: Some(track_num),
+                ..AudioTrackConfig::new(AudioCodecId::Opus, 48_000, 2)
+            })
+            .collect();
+        configs[1].default = false;
+
+        let Err((builder, _, 2)) = builder.add_audio_tracks(&configs) else {
+            panic!("libwebm should have refused the taken track number")
+        };
+        assert_eq!(builder.tracks.tracks, [(1, TrackKind::Video)]);
+        assert!(builder.tracks.not_default.is_empty());
+        assert!(builder.opus.iter().all(|delays| delays.track_num == 2));
+
+        configs[2].track_num = Some(5);
+        let (builder, audio) = builder.add_audio_tracks(&configs).unwrap();
+        assert_eq!(audio.iter().map(|&track| TrackNum::from(track)).collect::<Vec<_>>(), [3, 4, 5]);
+        let file = finish_with_keyframe(builder, video);
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let track_nums: Vec<TrackNum> = demuxer.tracks().iter().map(|info| info.track_num).collect();
+        assert_eq!(track_nums, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn adds_configured_audio_track() {
+        const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+        let (builder, main) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let config = AudioTrackConfig {
+            track_num: Some(4),
+            codec_private: OPUS_HEAD.to_vec(),
+            name: Some("Commentary".into()),
+            language: Some("eng".into()),
+            seek_pre_roll_ns: 80_000_000,
+            bit_depth: Some(16),
+            default: false,
+            ..AudioTrackConfig::new(AudioCodecId::Opus, 48_000, 2)
+        };
+
+        // Invalid fields are reported before the track is added
+        for (field, invalid) in [
+            ("channels", AudioTrackConfig { channels: 0, ..config.clone() }),
+            ("language", AudioTrackConfig { language: Some("english".into()), ..config.clone() }),
+            ("bit_depth", AudioTrackConfig { bit_depth: Some(0), ..config.clone() }),
+            ("track_num", AudioTrackConfig { track_num: Some(0), ..config.clone() }),
+        ] {
+            let Err((_, Error::Spec { path, .. })) = make_segment_builder().add_audio_track_with(&invalid) else {
+                panic!("The invalid {field} should have been reported")
+            };
+            assert_eq!(path, field);
+        }
+        let taken = AudioTrackConfig {
+            track_num: Some(main.into()),
+            ..config.clone()
+        };
+        let Err((builder, Error::Spec { path, .. })) = builder.add_audio_track_with(&taken) else {
+            panic!("The taken track number should have been reported")
+        };
+        assert_eq!(path, "track_num");
+
+        // The CodecDelay comes from the OpusHead, and the cleared FlagDefault leaves a single default track
+        let (builder, commentary) = builder.add_audio_track_with(&config).unwrap();
+        assert_eq!(TrackNum::from(commentary), 4);
+        let mut segment = builder.build_strict().unwrap();
+        segment.add_audio_frame(main, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(commentary, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let file = writer.into_inner().into_inner();
+        assert_eq!(default_flags(&file), [(1, true), (4, false)]);
+        let demuxer = Demuxer::new(Cursor::new(&file)).unwrap();
+        let info = demuxer.track(commentary.into()).unwrap();
+        assert_eq!((info.name.as_deref(), info.language.as_deref()), (Some("Commentary"), Some("eng")));
+        assert_eq!(info.codec_private, OPUS_HEAD);
+        assert_eq!((info.codec_delay_ns, info.seek_pre_roll_ns), (6_500_000, 80_000_000));
+        let TrackType::Audio(audio_info) = &info.track_type else {
+            panic!("Expected an audio track");
+        };
+        assert_eq!((audio_info.sample_rate, audio_info.channels, audio_info.bit_depth), (48_000.0, 2, Some(16)));
+    }
+
+    #[test]
+    fn adds_configured_video_track() {
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let config = VideoTrackConfig {
+            track_num: Some(3),
+            color: Some(ColorDescription::BT709),
+            bit_depth: Some(10),
+            chroma_subsampling: ColorSubsampling {
+                chroma_horizontal: 1,
+                chroma_vertical: 1,
+            },
+            color_range: ColorRange::Broadcast,
+            name: Some("Camera 2".into()),
+            language: Some("ger".into()),
+            default: false,
+            ..VideoTrackConfig::new(VideoCodecId::VP9, 1280, 720)
+        };
+
+        // Invalid fields are reported before the track is added
+        for (field, invalid) in [
+            ("width", VideoTrackConfig { width: 0, ..config.clone() }),
+            ("name", VideoTrackConfig { name: Some("Cam\0".into()), ..config.clone() }),
+            ("bit_depth", VideoTrackConfig { bit_depth: Some(16), ..config.clone() }),
+            ("track_num", VideoTrackConfig { track_num: Some(127), ..config.clone() }),
+        ] {
+            let Err((_, Error::Spec { path, .. })) = make_segment_builder().add_video_track_with(&invalid) else {
+                panic!("The invalid {field} should have been reported")
+            };
+            assert_eq!(path, field);
+        }
+        let taken = VideoTrackConfig {
+            track_num: Some(audio.into()),
+            ..config.clone()
+        };
+        let Err((builder, Error::Spec { path, .. })) = builder.add_video_track_with(&taken) else {
+            panic!("The taken track number should have been reported")
+        };
+        assert_eq!(path, "track_num");
+
+        // A color description that libwebm can't write is refused before the track is added, so its number is free
+        // for the retry
+        let refused = VideoTrackConfig {
+            color: Some(ColorDescription::from_cicp(200, 1, 1, false)),
+            ..config.clone()
+        };
+        let Err((builder, Error::Spec { path, .. })) = builder.add_video_track_with(&refused) else {
+            panic!("The color description should have been refused")
+        };
+        assert_eq!(path, "color");
+        assert_eq!(builder.tracks.tracks.len(), 1);
+        let (builder, video) = builder.add_video_track_with(&config).unwrap();
+        assert_eq!(TrackNum::from(video), 3);
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let file = writer.into_inner().into_inner();
+        assert_eq!(default_flags(&file), [(1, true), (3, false)]);
+        let demuxer = Demuxer::new(Cursor::new(&file)).unwrap();
+        assert_eq!(demuxer.tracks().len(), 2);
+        let info = demuxer.track(video.into()).unwrap();
+        assert_eq!((info.name.as_deref(), info.language.as_deref()), (Some("Camera 2"), Some("ger")));
+        let TrackType::Video(video_info) = &info.track_type else {
+            panic!("Expected a video track");
+        };
+        assert_eq!((video_info.width, video_info.height), (1280, 720));
+        let colour = video_info.colour.as_deref().unwrap();
+        assert_eq!((colour.primaries, colour.transfer_characteristics, colour.range), (Some(1), Some(1), Some(1)));
+        assert_eq!(colour.bits_per_channel, Some(10));
+        assert_eq!(colour.chroma_subsampling.map(|sampling| sampling.chroma_vertical), Some(1));
+    }
+
+    #[test]
+    fn keeps_color_range_of_configured_preset() {
+        // An unspecified color_range leaves the range of the preset; one that is given replaces it
+        let cases = [
+            (ColorDescription::BT709, ColorRange::Unspecified, Some(1)),
+            (ColorDescription::SRGB, ColorRange::Unspecified, Some(2)),
+            (ColorDescription::BT709, ColorRange::Full, Some(2)),
+        ];
+        for (preset, color_range, range) in cases {
+            let config = VideoTrackConfig {
+                color: Some(preset),
+                bit_depth: Some(10),
+                color_range,
+                ..VideoTrackConfig::new(VideoCodecId::VP9, 64, 48)
+            };
+            let (builder, video) = make_segment_builder().add_video_track_with(&config).unwrap();
+            let file = finish_with_keyframe(builder, video);
+            let demuxer = Demuxer::new(Cursor::new(&file)).unwrap();
+            let colour = demuxer.colour(video.into()).unwrap();
+            assert_eq!((colour.range, colour.bits_per_channel), (range, Some(10)), "{preset:?} {color_range:?}");
+        }
+    }
+
+    #[test]
+    fn writes_default_duration() {
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(builder.set_default_duration(audio, 0), Err(Error::BadParam)));
+
+        // Both kinds of track take one, and a track without one has none
         let builder = make_segment_builder();
-        let video_track = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123456));
-        assert!(video_track.is_err());
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, plain) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_default_duration(video, 33_333_333).unwrap();
+        let mut segment = builder.set_default_duration(audio, 20_000_000).unwrap().build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(plain, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let durations: Vec<_> = demuxer.tracks().iter().map(|track| track.default_duration_ns).collect();
+        assert_eq!(durations, [Some(33_333_333), Some(20_000_000), None]);
     }
 
     #[test]
-    fn overlapping_track_number_same_type() {
+    fn writes_track_uids() {
         let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let generated = builder.track_uid(audio).unwrap();
+        assert_ne!(generated, 0);
+        assert!(matches!(builder.track_uid(9_u64), Err(Error::BadParam)));
+        let builder = builder.set_track_uid(video, 0x1234_5678_9ABC).unwrap();
+        assert_eq!(builder.track_uid(video).unwrap(), 0x1234_5678_9ABC);
 
-        let Ok((builder, _)) = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123))
+        // A UID must be nonzero, and can't be another track's
+        let (other, first) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(other.set_track_uid(first, 0), Err(Error::BadParam)));
+        let (other, first) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (other, second) = other.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let first_uid = other.track_uid(first).unwrap();
+        assert!(matches!(other.set_track_uid(second, first_uid), Err(Error::BadParam)));
+
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let uids: Vec<_> = demuxer.tracks().iter().map(|track| track.uid).collect();
+        assert_eq!(uids, [0x1234_5678_9ABC, generated]);
+    }
+
+    #[test]
+    fn writes_track_names() {
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(builder.set_track_name(audio, "Main\0mix"), Err(Error::BadParam)));
+
+        let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, main) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, commentary) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_track_name(video, "Caméra 1 — 主").unwrap();
+        let builder = builder.set_track_name(main, "Main mix").unwrap();
+        let mut segment = builder.set_track_name(commentary, "Commentary").unwrap().build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(main, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(commentary, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let names: Vec<_> = demuxer.tracks().iter().map(|track| track.name.as_deref()).collect();
+        assert_eq!(names, [Some("Caméra 1 — 主"), Some("Main mix"), Some("Commentary")]);
+    }
+
+    #[test]
+    fn rejects_attachments() {
+        let cover = [0xFF, 0xD8, 0xFF, 0xD9];
+        let invalid = [("", "image/jpeg", None), ("cover.jpg", "", None), ("cover.jpg", "image/jpeg", Some(0))];
+        for (name, mime, uid) in invalid {
+            let attachment = make_segment_builder().add_attachment(name, mime, &cover, uid);
+            assert!(matches!(attachment, Err(Error::BadParam)), "{name:?} {mime:?} {uid:?}");
+        }
+
+        // Every segment is WebM, which has no room for attachments
+        let Err(err) = make_segment_builder().add_attachment("cover.jpg", "image/jpeg", &cover, Some(7)) else {
+            panic!("Attaching a file to a WebM segment succeeded");
+        };
+        assert!(matches!(err, Error::NotInWebm { element: "Attachments" }));
+        assert_eq!(err.to_string(), "WebM files cannot contain Attachments");
+    }
+
+    #[test]
+    fn writes_codec_names() {
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        assert!(matches!(builder.set_codec_name(audio, "libopus\0"), Err(Error::BadParam)));
+        assert!(matches!(make_segment_builder().set_codec_name(1_u64, "libopus"), Err(Error::BadParam)));
+
+        let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, unnamed) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_codec_name(video, "VP9 (libvpx)").unwrap();
+        let builder = builder.set_codec_name(audio, "Opus").unwrap();
+        let builder = builder.set_codec_name(unnamed, "Opus").unwrap();
+        let builder = builder.set_codec_name(unnamed, "").unwrap();
+        let file = finish_with_keyframe(builder, video);
+
+        let mut source = Cursor::new(file.as_slice());
+        let names: Vec<Option<String>> = track_entries(&file)
+            .iter()
+            .map(|children| {
+                let name = children.iter().find(|child| child.id == 0x25_8688)?;
+                Some(ebml::read_string(&mut source, name).unwrap())
+            })
+            .collect();
+        assert_eq!(names, [Some("VP9 (libvpx)".to_owned()), Some("Opus".to_owned()), None]);
+    }
+
+    #[test]
+    fn writes_output_sample_rate() {
+        for rate in [0.0, -48_000.0, f64::NAN, f64::INFINITY] {
+            let (builder, audio) = make_segment_builder().add_audio_track(44_100, 2, AudioCodecId::Opus, None).unwrap();
+            assert!(matches!(builder.set_output_sample_rate(audio, rate), Err(Error::BadParam)), "{rate}");
+        }
+        let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let result = builder.set_output_sample_rate(AudioTrack(video.0, video.1), 48_000.0);
+        assert!(matches!(result, Err(Error::UnknownTrack { .. })));
+        let (_, foreign) = make_segment_builder().add_audio_track(44_100, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, _) = make_segment_builder().add_audio_track(44_100, 2, AudioCodecId::Opus, None).unwrap();
+        let result = builder.set_output_sample_rate(foreign, 48_000.0);
+        assert!(matches!(result, Err(Error::ForeignTrack { track_num: 1 })));
+
+        let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, resampled) = builder.add_audio_track(44_100, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, _) = builder.add_audio_track(48_000, 1, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_output_sample_rate(resampled, 48_000.0).unwrap();
+        let file = finish_with_keyframe(builder, video);
+
+        let mut source = Cursor::new(file.as_slice());
+        let entries = track_entries(&file);
+        let audio_settings: Vec<Vec<(u32, f64)>> = entries[1..]
+            .iter()
+            .map(|children| {
+                let audio = children.iter().find(|child| child.id == 0xE1).unwrap();
+                let settings = ebml::children(&mut source, audio.pos, audio.end()).unwrap();
+                let rates = settings.iter().filter(|setting| [0xB5, 0x78B5].contains(&setting.id));
+                rates.map(|rate| (rate.id, ebml::read_float(&mut source, rate).unwrap())).collect()
+            })
+            .collect();
+        assert_eq!(audio_settings, [vec![(0xB5, 44_100.0), (0x78B5, 48_000.0)], vec![(0xB5, 48_000.0)]]);
+
+        // The demuxer reads the rest of the Audio element as before
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let channels: Vec<_> = demuxer
+            .tracks()
+            .iter()
+            .filter_map(|track| match &track.track_type {
+                TrackType::Audio(audio) => Some((audio.sample_rate, audio.channels)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(channels, [(44_100.0, 2), (48_000.0, 1)]);
+    }
+
+    #[test]
+    fn writes_track_languages() {
+        for language in ["", "en", "English", "ENG", "e1g", "en\0"] {
+            let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+            assert!(matches!(builder.set_track_language(audio, language), Err(Error::BadParam)), "{language:?}");
+        }
+        for language in ["", "en-", "-en", "1en", "en_GB", "en-toolongsubtag", "en-GB\0"] {
+            let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+            let result = builder.set_track_language_bcp47(audio, language);
+            assert!(matches!(result, Err(Error::BadParam)), "{language:?}");
+        }
+
+        let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, english) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, chinese) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_track_language(video, "und").unwrap();
+        let builder = builder.set_track_language(english, "eng").unwrap();
+        let builder = builder.set_track_language_bcp47(english, "en-GB").unwrap();
+        let builder = builder.set_track_language_bcp47(chinese, "zh-Hant-TW").unwrap();
+        let mut segment = builder.set_default_audio_track(english).unwrap().build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(english, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(chinese, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        // The LanguageBCP47 goes alongside a cleared FlagDefault
+        let file = writer.into_inner().into_inner();
+        assert_eq!(default_flags(&file), [(1, true), (2, true), (3, false)]);
+        let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let languages: Vec<_> =
+            demuxer.tracks().iter().map(|track| (track.language.as_deref(), track.language_bcp47.as_deref())).collect();
+        assert_eq!(languages, [(Some("und"), None), (Some("eng"), Some("en-GB")), (None, Some("zh-Hant-TW"))]);
+    }
+
+    #[test]
+    fn writes_track_flags() {
+        let builder = make_segment_builder();
+        let (builder, main) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, commentary) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let flags = TrackFlags {
+            default: Some(false),
+            ..TrackFlags::default()
+        };
+        let builder = builder.set_track_flags(commentary, flags).unwrap();
+        let mut segment = builder.build_strict().unwrap();
+        segment.add_audio_frame(main, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(commentary, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        assert_eq!(default_flags(&writer.into_inner().into_inner()), [(1, true), (2, false)]);
+
+        // Only flags that differ from what a missing one means are written, and None leaves a flag alone
+        let builder = make_segment_builder();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let flags = TrackFlags {
+            default: None,
+            enabled: Some(false),
+            forced: Some(true),
+        };
+        let builder = builder.set_track_flags(audio, flags).unwrap();
+        let builder = builder.set_track_flags(video, flags).unwrap();
+        let flags = TrackFlags {
+            default: Some(true),
+            enabled: None,
+            forced: Some(false),
+        };
+        let builder = builder.set_track_flags(video, flags).unwrap();
+        let file = finish_with_keyframe(builder, video);
+        assert!(matches!(make_segment_builder().set_track_flags(1_u64, flags), Err(Error::BadParam)));
+
+        let mut source = Cursor::new(file.as_slice());
+        let flags: Vec<Vec<(u32, u64)>> = track_entries(&file)
+            .iter()
+            .map(|children| {
+                children
+                    .iter()
+                    .filter(|child| [0x88, 0xB9, 0x55AA].contains(&child.id))
+                    .map(|child| (child.id, ebml::read_uint(&mut source, child).unwrap()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(flags, [vec![(0xB9, 0), (0x55AA, 1)], vec![(0xB9, 0)]]);
+    }
+
+    #[test]
+    fn writes_codec_delay_and_seek_pre_roll() {
+        let builder = make_segment_builder();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, opus) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, vorbis) = builder.add_audio_track(44_100, 2, AudioCodecId::Vorbis, None).unwrap();
+        let builder = builder.set_opus_pre_skip(opus, 312).unwrap().set_seek_pre_roll(opus, 80_000_000).unwrap();
+        let builder = builder.set_codec_delay(vorbis, 1_000_000).unwrap().set_seek_pre_roll(vorbis, 0).unwrap();
+        assert!(matches!(make_segment_builder().set_seek_pre_roll(1_u64, 0), Err(Error::BadParam)));
+        let file = finish_with_keyframe(builder, video);
+
+        // 312 samples at 48 kHz are 6.5 ms. A SeekPreRoll of 0 is what Matroska takes a missing one to be
+        let demuxer = Demuxer::new(Cursor::new(file.as_slice())).unwrap();
+        let delays: Vec<_> =
+            demuxer.tracks().iter().map(|track| (track.codec_delay_ns, track.seek_pre_roll_ns)).collect();
+        assert_eq!(delays, [(0, 0), (6_500_000, 80_000_000), (1_000_000, 0)]);
+        let mut source = Cursor::new(file.as_slice());
+        let opus = &track_entries(&file)[1];
+        let find = |id| *opus.iter().find(|child| child.id == id).unwrap();
+        let (codec_delay, seek_pre_roll) = (find(0x56AA), find(0x56BB));
+        assert_eq!(ebml::read_uint(&mut source, &codec_delay).unwrap(), 6_500_000);
+        assert_eq!(ebml::read_uint(&mut source, &seek_pre_roll).unwrap(), 80_000_000);
+    }
+
+    #[test]
+    fn reads_opus_packet_samples() {
+        // SILK 60 ms, hybrid 10 ms and CELT 2.5 ms frames, one each; two 20 ms CELT frames; three 20 ms CELT frames
+        // with the count in the second byte
+        assert_eq!(opus_packet_samples(&[3 << 3]), Some(2880));
+        assert_eq!(opus_packet_samples(&[12 << 3]), Some(480));
+        assert_eq!(opus_packet_samples(&[16 << 3]), Some(120));
+        assert_eq!(opus_packet_samples(&[31 << 3 | 1, 0]), Some(1920));
+        assert_eq!(opus_packet_samples(&[31 << 3 | 3, 3]), Some(2880));
+        // No TOC, no frame count, no frames, and seven 20 ms frames, which is more than 120 ms
+        assert_eq!(opus_packet_samples(&[]), None);
+        assert_eq!(opus_packet_samples(&[31 << 3 | 3]), None);
+        assert_eq!(opus_packet_samples(&[31 << 3 | 3, 0]), None);
+        assert_eq!(opus_packet_samples(&[31 << 3 | 3, 7]), None);
+    }
+
+    #[test]
+    fn writes_final_audio_frames() {
+        // One 20 ms CELT frame, which is 960 samples at 48 kHz
+        const PACKET: &[u8] = &[31 << 3, 0xAA, 0xBB];
+
+        let builder = make_segment_builder();
+        let (builder, trimmed) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, whole) = builder.add_audio_track(44_100, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, vorbis) = builder.add_audio_track(48_000, 2, AudioCodecId::Vorbis, None).unwrap();
+        let mut segment = builder.build();
+        assert!(matches!(segment.add_final_audio_frame(vorbis, PACKET, 0, 480, 48_000), Err(Error::BadParam)));
+        assert!(matches!(segment.add_final_audio_frame(trimmed, &[], 0, 480, 48_000), Err(Error::BadParam)));
+        assert!(matches!(segment.add_final_audio_frame(trimmed, PACKET, 0, 961, 48_000), Err(Error::BadParam)));
+        assert!(matches!(segment.add_final_audio_frame(whole, PACKET, 0, 883, 44_100), Err(Error::BadParam)));
+        assert!(matches!(segment.add_final_audio_frame(whole, PACKET, 0, 1, 0), Err(Error::BadParam)));
+
+        // Half of the trimmed track's last packet is padding, and all 882 samples at 44.1 kHz of the other's are valid
+        segment.add_audio_frame(trimmed, PACKET, 0, true).unwrap();
+        segment.add_audio_frame(whole, PACKET, 0, true).unwrap();
+        segment.add_final_audio_frame(trimmed, PACKET, 20_000_000, 480, 48_000).unwrap();
+        segment.add_final_audio_frame(whole, PACKET, 20_000_000, 882, 44_100).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames = demuxer.frames().collect::<Result<Vec<_>, _>>().unwrap();
+        let padding: Vec<_> =
+            frames.iter().map(|frame| (frame.track_num, frame.timestamp_ns, frame.discard_padding_ns)).collect();
+        assert_eq!(padding, [(1, 0, None), (2, 0, None), (1, 20_000_000, Some(10_000_000)), (2, 20_000_000, None)]);
+    }
+
+    #[test]
+    fn final_audio_frames_wait_for_held_vfr_frames() {
+        const PACKET: &[u8] = &[31 << 3, 0xAA, 0xBB];
+
+        let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.vfr_video(video).unwrap().build();
+
+        // The video keyframe is held until the next video frame, and the audio waits behind it
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(audio, PACKET, 0, true).unwrap();
+        segment.add_final_audio_frame(audio, PACKET, 20_000_000, 480, 48_000).unwrap();
+        segment.add_video_frame(video, &[1; 10], 40_000_000, false).unwrap();
+        segment.finish_vfr(video, Some(20_000_000)).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames = demuxer.frames().collect::<Result<Vec<_>, _>>().unwrap();
+        let written: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.track_num, frame.timestamp_ns / 1_000_000, frame.discard_padding_ns))
+            .collect();
+        assert_eq!(written, [(1, 0, None), (2, 0, None), (2, 20, Some(10_000_000)), (1, 40, None)]);
+        assert_eq!(frames[0].duration_ns, Some(40_000_000));
+    }
+
+    #[test]
+    fn derives_opus_codec_delay() {
+        use crate::validate::{validate, Rule, ValidationOptions};
+
+        const PRE_SKIP_312: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+        const PRE_SKIP_0: &[u8] = b"OpusHead\x01\x02\x00\x00\x80\xbb\x00\x00\x00\x00\x00";
+
+        // 312 samples at 48 kHz are 6.5 ms, and no pre-skip needs no CodecDelay. Setting a CodecDelay explicitly
+        // overrides the pre-skip, whether before or after the OpusHead
+        let builder = make_segment_builder();
+        let (builder, derived) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, none) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, before) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, after) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, vorbis) = builder.add_audio_track(48_000, 2, AudioCodecId::Vorbis, None).unwrap();
+        let mut config = AudioTrackConfig::new(AudioCodecId::Opus, 48_000, 2);
+        config.codec_private = PRE_SKIP_312.to_vec();
+        let (builder, configured) = builder.add_audio_tracks(&[config]).unwrap();
+        let builder = builder.set_codec_private(derived, PRE_SKIP_312).unwrap();
+        let builder = builder.set_codec_private(none, PRE_SKIP_0).unwrap();
+        let builder = builder.set_codec_delay(before, 1_000_000).unwrap().set_codec_private(before, PRE_SKIP_312);
+        let builder = builder.unwrap().set_codec_private(after, PRE_SKIP_312).unwrap().set_codec_delay(after, 0);
+        let builder = builder.unwrap().set_codec_private(vorbis, PRE_SKIP_312).unwrap();
+        let mut segment = builder.build();
+        for track in [derived, none, before, after, vorbis, configured[0]] {
+            segment.add_audio_frame(track, &[0; 10], 0, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let demuxer = Demuxer::new(Cursor::new(&file)).unwrap();
+        let delays: Vec<_> = demuxer.tracks().iter().map(|info| (info.codec_delay_ns, info.seek_pre_roll_ns)).collect();
+        assert_eq!(delays, [(6_500_000, 0), (0, 0), (1_000_000, 0), (0, 0), (0, 0), (6_500_000, 0)]);
+
+        // The validator warns about the overrides that don't match
+        let report = validate(Cursor::new(&file), ValidationOptions::default());
+        let mismatched = report.warnings().filter(|finding| finding.rule == Rule::CodecDelay).count();
+        assert_eq!(mismatched, 2, "{:?}", report.findings);
+
+        // Strict building rejects them, and sets the SeekPreRoll that Opus needs
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_codec_private(audio, PRE_SKIP_312).unwrap().set_codec_delay(audio, 1_000_000);
+        let result = builder.unwrap().build_strict();
+        let Err(Error::CodecDelayMismatch {
+            track_num,
+            codec_delay_ns,
+            pre_skip_ns,
+        }) = result
         else {
-            panic!("First video track unexpectedly failed")
+            panic!("The mismatched CodecDelay should have been rejected");
         };
+        assert_eq!((track_num, codec_delay_ns, pre_skip_ns), (TrackNum::from(audio), 1_000_000, 6_500_000));
 
-        let video_track2 = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123));
-        assert!(video_track2.is_err());
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, custom) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_codec_private(audio, PRE_SKIP_312).unwrap().set_codec_delay(audio, 6_500_000);
+        let builder = builder.unwrap().set_seek_pre_roll(custom, 10_000_000).unwrap();
+        let mut segment = builder.set_default_audio_track(audio).unwrap().build_strict().unwrap();
+        segment.add_audio_frame(audio, &[0; 10], 0, true).unwrap();
+        segment.add_audio_frame(custom, &[0; 10], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        let delays: Vec<_> = demuxer.tracks().iter().map(|info| (info.codec_delay_ns, info.seek_pre_roll_ns)).collect();
+        assert_eq!(delays, [(6_500_000, 80_000_000), (0, 10_000_000)]);
     }
 
     #[test]
-    fn overlapping_track_number_different_type() {
+    fn compares_codec_private_with_tracks() {
+        // Six channels from 44.1 kHz input, then the same with the input sample rate unspecified
+        const OPUS_6CH: &[u8] =
+            b"OpusHead\x01\x06\x38\x01\x44\xAC\x00\x00\x00\x00\x01\x04\x02\x00\x04\x01\x02\x03\x04\x05";
+        const OPUS_6CH_ANY_RATE: &[u8] =
+            b"OpusHead\x01\x06\x38\x01\x00\x00\x00\x00\x00\x00\x01\x04\x02\x00\x04\x01\x02\x03\x04\x05";
+        const VORBIS_6CH: &[u8] = b"\x02\x1E\x00\x01vorbis\x00\x00\x00\x00\x06\x44\xAC\x00\x00\
+            \x00\x00\x00\x00\x00\xF4\x01\x00\x00\x00\x00\x00\xB8\x01";
+        const AV1_1080P: &[u8] = b"\x81\x08\x0D\x00\x0A\x0E\x00\x00\x00\x42\xAB\xBF\xC3\x73\xFF\xE6\x40\x40\x40\x49";
+
+        let mismatch = |track: TrackNum, field, track_value, private_value| MuxWarning::CodecPrivateMismatch {
+            track_num: track,
+            field,
+            track_value,
+            private_value,
+        };
+
         let builder = make_segment_builder();
+        let (builder, opus) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, any_rate) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, matching) = builder.add_audio_track(44_100, 6, AudioCodecId::Opus, None).unwrap();
+        let (builder, vorbis) = builder.add_audio_track(48_000, 6, AudioCodecId::Vorbis, None).unwrap();
+        let (builder, larger) = builder.add_video_track(2560, 1080, VideoCodecId::AV1, None).unwrap();
+        let (builder, smaller) = builder.add_video_track(1280, 720, VideoCodecId::AV1, None).unwrap();
+        let (builder, vp9) = builder.add_video_track(4096, 2160, VideoCodecId::VP9, None).unwrap();
+        let builder = builder.set_codec_private(opus, OPUS_6CH).unwrap();
+        let builder = builder.set_codec_private(any_rate, OPUS_6CH_ANY_RATE).unwrap();
+        let builder = builder.set_codec_private(matching, OPUS_6CH).unwrap();
+        let builder = builder.set_codec_private(vorbis, VORBIS_6CH).unwrap();
+        let builder = builder.set_codec_private(larger, AV1_1080P).unwrap();
+        let builder = builder.set_codec_private(smaller, AV1_1080P).unwrap();
+        let builder = builder.set_codec_private(vp9, AV1_1080P).unwrap();
+        let mut config = AudioTrackConfig::new(AudioCodecId::Vorbis, 44_100, 2);
+        config.codec_private = VORBIS_6CH.to_vec();
+        let (builder, configured) = builder.add_audio_tracks(&[config]).unwrap();
+        let builder = builder.set_default_audio_track(opus).unwrap().set_default_video_track(vp9).unwrap();
+        let (opus, any_rate, vorbis) = (TrackNum::from(opus), TrackNum::from(any_rate), TrackNum::from(vorbis));
+        let (larger, configured) = (TrackNum::from(larger), TrackNum::from(configured[0]));
+        let expected = [
+            mismatch(opus, "channels", 2, 6),
+            mismatch(opus, "sample_rate", 48_000, 44_100),
+            mismatch(any_rate, "channels", 2, 6),
+            mismatch(vorbis, "sample_rate", 48_000, 44_100),
+            mismatch(larger, "width", 2560, 1920),
+            mismatch(configured, "channels", 2, 6),
+        ];
+        assert_eq!(builder.warnings(), expected);
 
-        let Ok((builder, _)) = builder.add_video_track(420, 420, VideoCodecId::VP8, Some(123))
+        // A later CodecPrivate replaces the warnings of the one before
+        let builder = builder.set_codec_private(opus, OPUS_6CH_ANY_RATE).unwrap();
+        assert_eq!(builder.warnings().iter().filter(|warning| **warning == expected[1]).count(), 0);
+        let builder = builder.set_codec_private(larger, b"\x81\x08\x0C\x00").unwrap();
+        assert_eq!(builder.warnings().len(), 4);
+
+        // Strict building fails on the first, while building carries them on to the segment
+        let Err(Error::CodecPrivateMismatch {
+            track_num,
+            field,
+            track_value,
+            private_value,
+        }) = builder.build_strict()
         else {
-            panic!("First video track unexpectedly failed")
+            panic!("The mismatched CodecPrivate should have been rejected");
         };
+        assert_eq!((track_num, field, track_value, private_value), (any_rate, "channels", 2, 6));
 
-        let audio_track = builder.add_audio_track(420, 420, AudioCodecId::Opus, Some(123));
-        assert!(audio_track.is_err());
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let segment = builder.set_codec_private(audio, OPUS_6CH).unwrap().build();
+        assert_eq!(segment.warnings().len(), 2);
+    }
+
+    #[test]
+    fn adds_frames_in_any_timebase() {
+        let (builder, audio) = make_segment_builder().add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..5 {
+            segment.add_frame_at(audio, &[0; 10], (i * 960, Timebase::OPUS), true).unwrap();
+        }
+        segment.add_frame_at(audio, &[0; 10], Timestamp::from_ns(100_000_000), true).unwrap();
+
+        let result = segment.add_frame_at(audio, &[0; 10], (1, Timebase::new(1, 0)), true);
+        assert!(matches!(result, Err(Error::Time(TimeError::ZeroTimebase(_)))), "{result:?}");
+        let result = segment.add_frame_at(audio, &[0; 10], (u64::MAX, Timebase::MILLISECONDS), true);
+        assert!(matches!(result, Err(Error::Time(TimeError::Overflow))), "{result:?}");
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let timestamps: Vec<u64> = demuxer.frames().map(|frame| frame.unwrap().timestamp_ns).collect();
+        assert_eq!(timestamps, [0, 20_000_000, 40_000_000, 60_000_000, 80_000_000, 100_000_000]);
+    }
+
+    #[test]
+    fn leaves_pauses_out() {
+        let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        *segment.timestamp_mapper_mut() = TimestampMapper::starting_at(5_000_000_000);
+
+        // Frames every 40ms of wall-clock time, paused twice for a while in between
+        let mut wall_ns = 5_000_000_000;
+        for (frames, pause_ns) in [(10, 3_000_000_000), (10, 777_000_000), (10, 0)] {
+            for i in 0..frames {
+                segment.add_frame_wall(video, &[0; 10], wall_ns, i == 0).unwrap();
+                wall_ns += 40_000_000;
+            }
+            if pause_ns != 0 {
+                segment.timestamp_mapper_mut().pause(wall_ns).unwrap();
+                let result = segment.add_frame_wall(video, &[0; 10], wall_ns + 1, false);
+                assert!(matches!(result, Err(Error::Time(TimeError::Paused))), "{result:?}");
+                wall_ns += pause_ns;
+                segment.timestamp_mapper_mut().resume(wall_ns).unwrap();
+            }
+        }
+        let result = segment.add_frame_wall(video, &[0; 10], 5_000_000_000, false);
+        assert!(matches!(result, Err(Error::Time(TimeError::Backwards { .. }))), "{result:?}");
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        let timestamps: Vec<u64> = demuxer.frames().map(|frame| frame.unwrap().timestamp_ns).collect();
+        let expected: Vec<u64> = (0..30).map(|i| i * 40_000_000).collect();
+        assert_eq!(timestamps, expected);
+    }
+
+    #[test]
+    fn anchors_wall_clock() {
+        let start = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let builder = make_segment_builder().anchor_wall_clock(start).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..50 {
+            segment.add_video_frame(video, &[0; 10], i * 40_000_000, i % 25 == 0).unwrap();
+        }
+        let last_utc = segment.media_to_utc(49 * 40_000_000).unwrap();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let file = writer.into_inner().into_inner();
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.segment_info().date_utc, Some(start));
+        assert_eq!(demuxer.wall_clock_anchor().unwrap(), Some(start));
+        let duration_ns = demuxer.segment_info().duration_ns.unwrap();
+        assert_eq!(last_utc, start + Duration::from_nanos(duration_ns));
+
+        // Unanchored files have neither
+        let (builder, video) = make_segment_builder().add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_video_frame(video, &[0; 10], 0, true).unwrap();
+        assert_eq!(segment.media_to_utc(0), None);
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let mut demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert_eq!(demuxer.wall_clock_anchor().unwrap(), None);
+
+        // Nor can be anchored too far from 2001
+        let far = SystemTime::UNIX_EPOCH + Duration::from_secs(400 * 365 * 86_400);
+        let result = make_segment_builder().anchor_wall_clock(far);
+        assert!(matches!(result, Err(Error::Time(TimeError::Overflow))), "{result:?}");
+    }
+
+    #[test]
+    fn describes_libwebm_errors() {
+        struct FailingWrite;
+        impl Write for FailingWrite {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let builder = SegmentBuilder::new(Writer::new_non_seek(FailingWrite)).unwrap();
+        let (builder, track) = builder.add_video_track(64, 64, VideoCodecId::VP8, None).unwrap();
+        let mut segment = builder.build();
+
+        // The headers are written with the first frame
+        let Err(Error::Libwebm { message }) = segment.add_frame(track, &[0; 10], 0, true) else {
+            panic!("Writing to a failing writer unexpectedly didn't fail with a description");
+        };
+        assert!(message.starts_with("AddFrame: "), "{message}");
+        assert!(message.ends_with("the write callback failed"), "{message}");
     }
 }
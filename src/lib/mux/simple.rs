@@ -0,0 +1,255 @@
+use std::io::Write;
+
+use super::{
+    AudioCodecId, AudioTrack, Error, Segment, SegmentBuilder, SimpleWriterOptions, TrackNum, VideoCodecId,
+    VideoTrack, Writer,
+};
+
+/// The segment of a single-track writer, with what it needs to compute the duration.
+struct SingleTrack<W: Write> {
+    segment: Segment<W>,
+    track: TrackNum,
+
+    /// The timestamp of the last frame written
+    last_ns: Option<u64>,
+
+    /// The end of the last frame written, taking it to last as long as the gap since the one before it
+    end_ns: u64,
+
+    /// Whether the last frame is held back until the next one or the end, so that it can be given a `DiscardPadding`
+    hold_last: bool,
+
+    /// The last frame's payload, while it is held back
+    held: Option<Vec<u8>>,
+}
+
+impl<W: Write> SingleTrack<W> {
+    fn new(
+        builder: SegmentBuilder<W>,
+        track: TrackNum,
+        options: &SimpleWriterOptions,
+        hold_last: bool,
+    ) -> Result<Self, Error> {
+        let mut builder = builder.set_cues_track(track)?;
+        if !options.codec_private.is_empty() {
+            builder = builder.set_codec_private(track, &options.codec_private)?;
+        }
+        if let Some(app) = &options.writing_app {
+            builder = builder.set_writing_app(app)?;
+        }
+
+        Ok(Self {
+            segment: builder.build(),
+            track,
+            last_ns: None,
+            end_ns: 0,
+            hold_last,
+            held: None,
+        })
+    }
+
+    fn write_frame(&mut self, data: &[u8], timestamp_ns: u64, keyframe: bool) -> Result<(), Error> {
+        if self.last_ns.is_some_and(|last| timestamp_ns < last) {
+            return Err(Error::BadParam);
+        }
+
+        if self.hold_last {
+            // Only audio is held back, and every audio frame is a keyframe
+            if let (Some(held), Some(last_ns)) = (self.held.take(), self.last_ns) {
+                self.segment.add_frame(self.track, &held, last_ns, true)?;
+            }
+            self.held = Some(data.to_vec());
+        } else {
+            self.segment.add_frame(self.track, data, timestamp_ns, keyframe)?;
+        }
+        let gap = self.last_ns.map_or(0, |last| timestamp_ns - last);
+        self.end_ns = self.end_ns.max(timestamp_ns.saturating_add(gap));
+        self.last_ns = Some(timestamp_ns);
+        Ok(())
+    }
+
+    /// Writes the frame held back, if any, with `discard_padding_ns` of it to be dropped, and finalizes the segment
+    /// with its duration shortened by as much.
+    fn finish(mut self, discard_padding_ns: u64) -> Result<Writer<W>, Error> {
+        if let (Some(held), Some(last_ns)) = (self.held.take(), self.last_ns) {
+            if discard_padding_ns == 0 {
+                self.segment.add_frame(self.track, &held, last_ns, true)?;
+            } else {
+                let padding = i64::try_from(discard_padding_ns).map_err(|_| Error::BadParam)?;
+                self.segment.add_frame_with_discard_padding(self.track, &held, last_ns, true, padding)?;
+            }
+        }
+
+        let end_ns = self.end_ns.saturating_sub(discard_padding_ns).max(self.last_ns.unwrap_or(0));
+        self.segment.finalize_with_duration_ns(end_ns).map_err(|_| Error::Unknown)
+    }
+}
+
+/// Writes a single video track, for recorders that don't need the flexibility of [`SegmentBuilder`] and [`Segment`].
+///
+/// Cue points are written for the track, so that players can seek, and the file's `Duration` is computed from the
+/// frames when it is finished: each frame is taken to last as long as the gap since the frame before it.
+pub struct SimpleVideoWriter<W: Write> {
+    inner: SingleTrack<W>,
+    track: VideoTrack,
+}
+
+impl<W: Write> SimpleVideoWriter<W> {
+    /// Starts a segment in `writer` with one video track of the given size and codec.
+    pub fn new(
+        writer: Writer<W>,
+        width: u32,
+        height: u32,
+        codec: VideoCodecId,
+        options: &SimpleWriterOptions,
+    ) -> Result<Self, Error> {
+        let (builder, track) = SegmentBuilder::new(writer)?.add_video_track(width, height, codec, None)?;
+        Ok(Self {
+            inner: SingleTrack::new(builder, track.into(), options, false)?,
+            track,
+        })
+    }
+
+    /// The track the frames are written to.
+    #[must_use]
+    pub fn track(&self) -> VideoTrack {
+        self.track
+    }
+
+    /// Writes a frame, with its timestamp in nanoseconds. Timestamps must not decrease, or this fails with
+    /// [`Error::BadParam`].
+    pub fn write_frame(&mut self, data: &[u8], timestamp_ns: u64, keyframe: bool) -> Result<(), Error> {
+        self.inner.write_frame(data, timestamp_ns, keyframe)
+    }
+
+    /// Finalizes the segment with its computed duration, and returns the writer. This fails if no frames were written.
+    pub fn finish(self) -> Result<Writer<W>, Error> {
+        self.inner.finish(0)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for SimpleVideoWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// Writes a single audio track, for recorders that don't need the flexibility of [`SegmentBuilder`] and [`Segment`].
+///
+/// Cue points are written for the track, so that players can seek, and the file's `Duration` is computed from the
+/// frames when it is finished: each frame is taken to last as long as the gap since the frame before it. Opus
+/// requires its identification header as [`SimpleWriterOptions::codec_private`]; [`presets::opus_audio`] sets up the
+/// rest of what Opus needs from the header as well.
+///
+/// Each frame is held back until the next one is written, so that the last can be given a `DiscardPadding` by
+/// [`SimpleAudioWriter::finish_with_padding`].
+///
+/// [`presets::opus_audio`]: super::presets::opus_audio
+pub struct SimpleAudioWriter<W: Write> {
+    inner: SingleTrack<W>,
+    track: AudioTrack,
+}
+
+impl<W: Write> SimpleAudioWriter<W> {
+    /// Starts a segment in `writer` with one audio track of the given sample rate, channel count and codec.
+    pub fn new(
+        writer: Writer<W>,
+        sample_rate: u32,
+        channels: u32,
+        codec: AudioCodecId,
+        options: &SimpleWriterOptions,
+    ) -> Result<Self, Error> {
+        let (builder, track) = SegmentBuilder::new(writer)?.add_audio_track(sample_rate, channels, codec, None)?;
+        Self::from_builder(builder, track, options)
+    }
+
+    /// Starts the segment of `builder`, which has `track` as its only track and may have been set up further.
+    pub(super) fn from_builder(
+        builder: SegmentBuilder<W>,
+        track: AudioTrack,
+        options: &SimpleWriterOptions,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            inner: SingleTrack::new(builder, track.into(), options, true)?,
+            track,
+        })
+    }
+
+    /// The track the frames are written to.
+    #[must_use]
+    pub fn track(&self) -> AudioTrack {
+        self.track
+    }
+
+    /// Writes a frame, with its timestamp in nanoseconds. Timestamps must not decrease, or this fails with
+    /// [`Error::BadParam`]. Every audio frame is a keyframe.
+    pub fn write_frame(&mut self, data: &[u8], timestamp_ns: u64) -> Result<(), Error> {
+        self.inner.write_frame(data, timestamp_ns, true)
+    }
+
+    /// Finalizes the segment with its computed duration, and returns the writer. This fails if no frames were written.
+    pub fn finish(self) -> Result<Writer<W>, Error> {
+        self.inner.finish(0)
+    }
+
+    /// Like [`SimpleAudioWriter::finish`], but marks the last `final_padding_ns` nanoseconds of the last frame as
+    /// padding with a `DiscardPadding`, for encoders that pad out their final frame, and leaves them out of the
+    /// duration. Fails with [`Error::BadParam`] if `final_padding_ns` doesn't fit in an `i64`.
+    pub fn finish_with_padding(self, final_padding_ns: u64) -> Result<Writer<W>, Error> {
+        self.inner.finish(final_padding_ns)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for SimpleAudioWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::validate::{validate, ValidationOptions};
+    use std::io::Cursor;
+
+    fn check(file: Vec<u8>, frames: usize, duration_ns: u64) {
+        let report = validate(Cursor::new(&file), ValidationOptions::default());
+        assert!(report.is_valid(), "{:?}", report.findings);
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.segment_info().duration_ns, Some(duration_ns));
+        assert_eq!(demuxer.tracks().len(), 1);
+        assert_eq!(demuxer.frames().count(), frames);
+        assert!(!demuxer.cue_index().unwrap().is_empty());
+    }
+
+    #[test]
+    fn writes_video() {
+        let options = SimpleWriterOptions {
+            writing_app: Some("recorder".into()),
+            ..SimpleWriterOptions::default()
+        };
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let mut video = SimpleVideoWriter::new(writer, 64, 48, VideoCodecId::VP9, &options).unwrap();
+        for i in 0..30 {
+            video.write_frame(&[i; 50], u64::from(i) * 40_000_000, i % 10 == 0).unwrap();
+        }
+        assert!(matches!(video.write_frame(&[0; 50], 0, true), Err(Error::BadParam)));
+        check(video.finish().unwrap().into_inner().into_inner(), 30, 1_200_000_000);
+    }
+
+    #[test]
+    fn writes_audio() {
+        let options = SimpleWriterOptions {
+            codec_private: b"OpusHead\x01\x01\x38\x01\x80\xbb\x00\x00\x00\x00\x00".to_vec(),
+            ..SimpleWriterOptions::default()
+        };
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let mut audio = SimpleAudioWriter::new(writer, 48_000, 1, AudioCodecId::Opus, &options).unwrap();
+        for i in 0..100 {
+            audio.write_frame(&[i; 20], u64::from(i) * 20_000_000).unwrap();
+        }
+        check(audio.finish().unwrap().into_inner().into_inner(), 100, 2_000_000_000);
+    }
+}
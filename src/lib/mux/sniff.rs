@@ -0,0 +1,230 @@
+use super::VideoCodecId;
+
+/// The `frame_sync_code` of VP9 keyframes, and the start code of VP8 ones.
+const VP9_SYNC_CODE: u32 = 0x49_8342;
+const VP8_START_CODE: [u8; 3] = [0x9D, 0x01, 0x2A];
+
+pub(super) const AV1_OBU_SEQUENCE_HEADER: u8 = 1;
+const AV1_OBU_FRAME_HEADER: u8 = 3;
+const AV1_OBU_FRAME: u8 = 6;
+const AV1_KEY_FRAME: u8 = 0;
+
+/// Whether `data`, a frame of the given codec as it would be written to a block, is a keyframe, read from the start of
+/// its bitstream:
+/// - for VP8, the `key_frame` bit of the frame tag (RFC 6386, section 9.1), with the start code that follows it in
+///   keyframes;
+/// - for VP9, the `frame_type` of the uncompressed header, with the `frame_sync_code` that follows it in keyframes,
+///   of the first frame of a superframe;
+/// - for AV1, the `frame_type` of the first frame header in the temporal unit, or whether the sequence header says
+///   there is only one, still picture.
+///
+/// Returns `None` if `data` is too short to tell, or isn't a frame of the codec as far as has been read. A VP9 or AV1
+/// frame that shows an earlier one again is `None` for AV1, whose keyframes can be shown that way, and `Some(false)`
+/// for VP9, where they can't be.
+#[must_use]
+pub fn is_keyframe(codec: VideoCodecId, data: &[u8]) -> Option<bool> {
+    match codec {
+        VideoCodecId::VP8 => vp8_is_keyframe(data),
+        VideoCodecId::VP9 => vp9_is_keyframe(data),
+        VideoCodecId::AV1 => av1_is_keyframe(data),
+    }
+}
+
+fn vp8_is_keyframe(data: &[u8]) -> Option<bool> {
+    // The key_frame bit is the lowest of the frame tag, and clear for keyframes
+    if data.first()? & 1 != 0 {
+        return data.get(..3).map(|_| false);
+    }
+    Some(data.get(3..6)? == VP8_START_CODE).filter(|&start_code| start_code)
+}
+
+fn vp9_is_keyframe(data: &[u8]) -> Option<bool> {
+    // frame_marker, profile_low_bit and profile_high_bit, then a reserved_zero bit in profile 3
+    if bits(data, 0, 2)? != 0b10 {
+        return None;
+    }
+    let profile = bits(data, 2, 1)? | bits(data, 3, 1)? << 1;
+    let mut bit = if profile == 3 { 5 } else { 4 };
+
+    // show_existing_frame, then frame_type, which is 0 for keyframes
+    if bits(data, bit, 1)? == 1 {
+        return Some(false);
+    }
+    if bits(data, bit + 1, 1)? == 1 {
+        return Some(false);
+    }
+    // show_frame and error_resilient_mode come before the sync code
+    bit += 4;
+    Some(bits(data, bit, 24)? == VP9_SYNC_CODE).filter(|&sync| sync)
+}
+
+fn av1_is_keyframe(data: &[u8]) -> Option<bool> {
+    let mut reduced_still_picture_header = false;
+    let mut rest = data;
+    while !rest.is_empty() {
+        // obu_forbidden_bit, obu_type, obu_extension_flag, obu_has_size_field and obu_reserved_1bit, then the
+        // extension header and the size
+        let header = rest[0];
+        if header & 0x80 != 0 {
+            return None;
+        }
+        let obu_type = header >> 3 & 0x0F;
+        let mut pos = 1 + usize::from(header & 0x04 != 0);
+        let size = if header & 0x02 != 0 {
+            let (size, len) = leb128(rest.get(pos..)?)?;
+            pos += len;
+            usize::try_from(size).ok()?
+        } else {
+            rest.len().checked_sub(pos)?
+        };
+        let payload = rest.get(pos..)?;
+
+        match obu_type {
+            AV1_OBU_SEQUENCE_HEADER => {
+                // seq_profile and still_picture come first
+                reduced_still_picture_header = payload.first()? & 0x08 != 0;
+            },
+            AV1_OBU_FRAME_HEADER | AV1_OBU_FRAME => {
+                if reduced_still_picture_header {
+                    return Some(true);
+                }
+                // show_existing_frame, then frame_type
+                let first = *payload.first()?;
+                if first & 0x80 != 0 {
+                    return None;
+                }
+                return Some(first >> 5 & 0b11 == AV1_KEY_FRAME);
+            },
+            _ => {},
+        }
+        rest = rest.get(pos.checked_add(size)?..)?;
+    }
+    None
+}
+
+/// The `count` bits of `data` starting `start` bits in, most significant first, or `None` if it is too short.
+pub(super) fn bits(data: &[u8], start: usize, count: usize) -> Option<u32> {
+    (start..start + count).try_fold(0, |value, bit| {
+        let byte = data.get(bit / 8)?;
+        Some(value << 1 | u32::from(byte >> (7 - bit % 8) & 1))
+    })
+}
+
+/// Reads an AV1 `leb128()` value, returning it and its length.
+pub(super) fn leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= u64::from(byte & 0x7F) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{Error, KeyframeFlagPolicy, SegmentBuilder, TrackNum, Writer};
+    use std::io::Cursor;
+
+    // The start of frames of each codec, with their headers as the specifications lay them out for a 16x16 picture
+    // in the most common profile. Nothing past the header fields that are sniffed needs to decode.
+
+    /// Frame tag with key_frame = 0, show_frame and a first partition of 17 bytes, start code, 16x16
+    const VP8_KEYFRAME: &[u8] = b"\x30\x02\x00\x9d\x01\x2a\x10\x00\x10\x00\x00\x47\x08\x85\x85\x88";
+    /// Frame tag with key_frame = 1, show_frame and a first partition of 8 bytes
+    const VP8_INTERFRAME: &[u8] = b"\x11\x01\x00\x00\x07\x08\x85\x85\x88\x99\x84\x88";
+
+    /// Profile 0, frame_type = 0 and show_frame, sync code, 8-bit 4:2:0 BT.601, 16x16
+    const VP9_KEYFRAME: &[u8] = b"\x82\x49\x83\x42\x00\x00\xf0\x00\xf6\x00\x38\x24\x1c\x18";
+    /// Profile 0, frame_type = 1 and show_frame
+    const VP9_INTERFRAME: &[u8] = b"\x86\x00\x40\x92\x88\x2c\x49\xe0\x00\x00";
+    /// Profile 3, whose reserved bit moves the sync code a bit along
+    const VP9_PROFILE_3_KEYFRAME: &[u8] = b"\xb1\x24\xc1\xa1\x00\x00\x0f\x00\x0f\x60";
+    /// Profile 0, showing frame 2 again
+    const VP9_SHOW_EXISTING: &[u8] = b"\x8a";
+
+    /// Temporal delimiter, sequence header (profile 0), and a frame with frame_type = KEY_FRAME and show_frame
+    const AV1_KEYFRAME: &[u8] =
+        b"\x12\x00\x0a\x0b\x00\x00\x00\x02\xaf\xff\x9b\x5f\x30\xc0\x10\x32\x06\x10\x00\x80\x10\x01\x00";
+    /// Temporal delimiter, and a frame with frame_type = INTER_FRAME and show_frame
+    const AV1_INTERFRAME: &[u8] = b"\x12\x00\x32\x05\x30\x03\xc0\x20\x00";
+    /// Temporal delimiter, and a frame header with an extension header and frame_type = INTRA_ONLY_FRAME
+    const AV1_INTRA_ONLY: &[u8] = b"\x12\x00\x1e\x00\x03\x50\x01\x00";
+    /// Sequence header with reduced_still_picture_header, and a frame
+    const AV1_STILL_PICTURE: &[u8] = b"\x0a\x06\x18\x15\x7f\xfc\x00\x08\x32\x03\x10\x80\x00";
+    /// Temporal delimiter, and a frame header that shows frame 1 again
+    const AV1_SHOW_EXISTING: &[u8] = b"\x12\x00\x1a\x01\x90";
+
+    #[test]
+    fn sniffs_keyframes() {
+        let samples = [
+            (VideoCodecId::VP8, VP8_KEYFRAME, Some(true)),
+            (VideoCodecId::VP8, VP8_INTERFRAME, Some(false)),
+            (VideoCodecId::VP9, VP9_KEYFRAME, Some(true)),
+            (VideoCodecId::VP9, VP9_INTERFRAME, Some(false)),
+            (VideoCodecId::VP9, VP9_PROFILE_3_KEYFRAME, Some(true)),
+            (VideoCodecId::VP9, VP9_SHOW_EXISTING, Some(false)),
+            (VideoCodecId::AV1, AV1_KEYFRAME, Some(true)),
+            (VideoCodecId::AV1, AV1_INTERFRAME, Some(false)),
+            (VideoCodecId::AV1, AV1_INTRA_ONLY, Some(false)),
+            (VideoCodecId::AV1, AV1_STILL_PICTURE, Some(true)),
+            (VideoCodecId::AV1, AV1_SHOW_EXISTING, None),
+        ];
+        for (index, (codec, sample, expected)) in samples.into_iter().enumerate() {
+            assert_eq!(is_keyframe(codec, sample), expected, "sample {index}");
+
+            // Cut short, a sample gives the same answer or none
+            assert_eq!(is_keyframe(codec, &[]), None);
+            for len in 1..sample.len() {
+                let sniffed = is_keyframe(codec, &sample[..len]);
+                assert!(sniffed.is_none() || sniffed == expected, "sample {index} cut to {len}");
+            }
+        }
+
+        // Another codec's frames, or a corrupt start code, don't pass
+        assert_eq!(is_keyframe(VideoCodecId::VP9, VP8_KEYFRAME), None);
+        assert_eq!(is_keyframe(VideoCodecId::VP8, b"\x30\x02\x00\x9d\x01\x2b\x10\x00\x10\x00"), None);
+        assert_eq!(is_keyframe(VideoCodecId::VP9, b"\x82\x49\x83\x43\x00"), None);
+        assert_eq!(is_keyframe(VideoCodecId::AV1, b"\x92\x00\x32\x05\x30\x03\xc0\x20\x00"), None);
+        assert_eq!(is_keyframe(VideoCodecId::AV1, b"\x12\x00\x32\x80\x80\x80\x80\x80\x80\x80\x80\x01"), None);
+    }
+
+    #[test]
+    fn verifies_keyframe_flags() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, vp9) = builder.add_video_track(16, 16, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.verify_keyframe_flags(true).build();
+        segment.add_video_frame(vp9, VP9_KEYFRAME, 0, true).unwrap();
+        let result = segment.add_video_frame(vp9, VP9_INTERFRAME, 40_000_000, true);
+        let Err(Error::KeyframeFlag {
+            track_num,
+            timestamp_ns: 40_000_000,
+            keyframe: false,
+        }) = result
+        else {
+            panic!("The wrong keyframe flag should have been rejected: {result:?}");
+        };
+        assert_eq!(track_num, TrackNum::from(vp9));
+        // Frames that can't be sniffed are written as flagged
+        segment.add_video_frame(vp9, VP9_INTERFRAME, 40_000_000, false).unwrap();
+        segment.add_video_frame(vp9, &VP9_KEYFRAME[..3], 80_000_000, false).unwrap();
+        assert!(segment.finalize(None).is_ok());
+
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, vp8) = builder.add_video_track(16, 16, VideoCodecId::VP8, None).unwrap();
+        let builder = builder.verify_keyframe_flags(true).keyframe_flag_policy(KeyframeFlagPolicy::Correct);
+        let mut segment = builder.build();
+        segment.add_video_frame(vp8, VP8_KEYFRAME, 0, false).unwrap();
+        segment.add_video_frame(vp8, VP8_INTERFRAME, 40_000_000, true).unwrap();
+        segment.add_video_frame(vp8, VP8_KEYFRAME, 80_000_000, false).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let keyframes: Vec<_> = demuxer.frames().map(|frame| frame.unwrap().is_keyframe).collect();
+        assert_eq!(keyframes, [true, false, true]);
+    }
+}
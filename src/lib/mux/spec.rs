@@ -0,0 +1,487 @@
+use std::io::Write;
+
+use super::{Error, MediaSpec, MuxSpec, SegmentBuilder, TrackHandle, TrackHandles, TrackNum, TrackSpec};
+
+/// Adds `track` with all of its settings. On failure, also returns the name of the field that couldn't be applied.
+pub(crate) fn add_track<W: Write>(
+    builder: SegmentBuilder<W>,
+    track: &TrackSpec,
+) -> Result<(SegmentBuilder<W>, TrackHandle), (&'static str, Error)> {
+    let field = |field: &'static str| move |error: Error| (field, error);
+    if track.number == Some(0) {
+        return Err(field("number")(Error::BadParam));
+    }
+    let (width_or_rate, height_or_channels) = match track.media {
+        MediaSpec::Video { width, height, .. } => (width, height),
+        MediaSpec::Audio {
+            sample_rate, channels, ..
+        } => (sample_rate, channels),
+    };
+    if [width_or_rate, height_or_channels].iter().any(|&value| value == 0 || i32::try_from(value).is_err()) {
+        return Err(field("media")(Error::BadParam));
+    }
+    if track.color.is_some() && matches!(track.media, MediaSpec::Audio { .. }) {
+        return Err(field("color")(Error::BadParam));
+    }
+    for (name, text) in [("title", &track.title), ("language", &track.language)] {
+        check_text(text.as_deref().unwrap_or_default()).map_err(field(name))?;
+    }
+
+    // With the media checked, the track can only fail to be added for want of the requested number
+    let added = if track.number.is_some() { "number" } else { "media" };
+    let (mut builder, handle) = match track.media {
+        MediaSpec::Video { width, height, codec } => {
+            let (builder, video) = builder.add_video_track(width, height, codec, track.number).map_err(field(added))?;
+            (builder, TrackHandle::Video(video))
+        },
+        MediaSpec::Audio {
+            sample_rate,
+            channels,
+            codec,
+        } => {
+            let (builder, audio) =
+                builder.add_audio_track(sample_rate, channels, codec, track.number).map_err(field(added))?;
+            (builder, TrackHandle::Audio(audio))
+        },
+    };
+
+    let track_num = TrackNum::from(handle);
+    if !track.codec_private.is_empty() {
+        builder = builder.set_codec_private(track_num, &track.codec_private).map_err(field("codec_private"))?;
+    }
+    if let Some(title) = &track.title {
+        builder = builder.set_track_name(track_num, title).map_err(field("title"))?;
+    }
+    if let Some(language) = &track.language {
+        builder = builder.set_track_language(track_num, language).map_err(field("language"))?;
+    }
+    if track.codec_delay_ns != 0 {
+        builder = builder.set_codec_delay(track_num, track.codec_delay_ns).map_err(field("codec_delay_ns"))?;
+    }
+    if track.seek_pre_roll_ns != 0 {
+        builder = builder.set_seek_pre_roll(track_num, track.seek_pre_roll_ns).map_err(field("seek_pre_roll_ns"))?;
+    }
+    if let (Some(color), TrackHandle::Video(video)) = (track.color, handle) {
+        builder = builder.set_color(video, color.bit_depth, color.subsampling, color.range).map_err(field("color"))?;
+    }
+    Ok((builder, handle))
+}
+
+/// Text is passed to libwebm as C strings, which can't hold nul characters.
+fn check_text(text: &str) -> Result<(), Error> {
+    if text.contains('\0') {
+        return Err(Error::BadParam);
+    }
+    Ok(())
+}
+
+impl<W: Write> SegmentBuilder<W> {
+    /// Adds a track of the kind `spec` describes, with all of its settings, though its [`TrackSpec::name`] is ignored.
+    ///
+    /// Anything the spec gets wrong fails with [`Error::Spec`], whose path names the offending field, e.g. `color` for
+    /// color information on an audio track.
+    pub fn add_track(self, spec: &TrackSpec) -> Result<(Self, TrackHandle), Error> {
+        add_track(self, spec).map_err(|(field, error)| Error::Spec {
+            path: field.into(),
+            error: Box::new(error),
+        })
+    }
+
+    /// Sets up the segment as `spec` describes: its segment information, tracks, chapters and tags. Returns the
+    /// handles of the tracks, by their [`TrackSpec::name`]s.
+    ///
+    /// Anything the spec gets wrong fails with [`Error::Spec`], whose path names the offending field, e.g.
+    /// `tracks[1].color` for color information on an audio track, or `cues_track` for the name of a track the spec
+    /// doesn't have.
+    pub fn apply_spec(self, spec: &MuxSpec) -> Result<(Self, TrackHandles), Error> {
+        let at = |path: String| {
+            move |error| Error::Spec {
+                path,
+                error: Box::new(error),
+            }
+        };
+
+        let mut builder = self;
+        if let Some(app) = &spec.writing_app {
+            builder = builder.set_writing_app(app).map_err(at("writing_app".into()))?;
+        }
+        if let Some(timecode_scale) = spec.timecode_scale {
+            builder = builder.set_timecode_scale(timecode_scale).map_err(at("timecode_scale".into()))?;
+        }
+        if spec.live {
+            builder = builder.set_live(true).map_err(at("live".into()))?;
+        }
+
+        let mut handles = TrackHandles::default();
+        for (index, track) in spec.tracks.iter().enumerate() {
+            if track.name.is_empty() || handles.find(&track.name).is_some() {
+                return Err(at(format!("tracks[{index}].name"))(Error::BadParam));
+            }
+            let (next, handle) =
+                add_track(builder, track).map_err(|(field, error)| at(format!("tracks[{index}].{field}"))(error))?;
+            builder = next;
+            handles.tracks.push((track.name.clone(), handle));
+        }
+        if let Some(name) = &spec.cues_track {
+            let track = handles.track_num(name).ok_or(Error::BadParam).map_err(at("cues_track".into()))?;
+            builder = builder.set_cues_track(track).map_err(at("cues_track".into()))?;
+        }
+
+        for (index, chapter) in spec.chapters.iter().enumerate() {
+            let id = chapter.id.clone().unwrap_or_else(|| (index + 1).to_string());
+            for (field, text) in [("id", id.as_str()), ("title", &chapter.title)] {
+                check_text(text).map_err(at(format!("chapters[{index}].{field}")))?;
+            }
+            check_text(chapter.language.as_deref().unwrap_or_default())
+                .map_err(at(format!("chapters[{index}].language")))?;
+            if chapter.end_ns < chapter.start_ns {
+                return Err(at(format!("chapters[{index}].end_ns"))(Error::BadParam));
+            }
+            builder = builder
+                .add_chapter(&id, chapter.start_ns, chapter.end_ns, &chapter.title, chapter.language.as_deref())
+                .map_err(at(format!("chapters[{index}]")))?;
+        }
+
+        // libwebm's SeekHead has room for five entries, and files spend four on Info, Tracks, Cues and the first Cluster
+        if !spec.live && !spec.chapters.is_empty() && !spec.tags.is_empty() {
+            return Err(at("tags".into())(Error::BadParam));
+        }
+        for (index, tag) in spec.tags.iter().enumerate() {
+            if tag.simple_tags.is_empty() {
+                return Err(at(format!("tags[{index}].simple_tags"))(Error::BadParam));
+            }
+            for (simple_index, (name, value)) in tag.simple_tags.iter().enumerate() {
+                let checked = if name.is_empty() { Err(Error::BadParam) } else { check_text(name) };
+                checked
+                    .and_then(|()| check_text(value))
+                    .map_err(at(format!("tags[{index}].simple_tags[{simple_index}]")))?;
+            }
+            builder = builder.add_tag(&tag.simple_tags).map_err(at(format!("tags[{index}]")))?;
+        }
+
+        Ok((builder, handles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::{Demuxer, TagValue};
+    use crate::mux::{
+        AudioCodecId, ChapterSpec, ColorRange, ColorSpec, ColorSubsampling, TagSpec, Track, VideoCodecId, Writer,
+    };
+    use std::io::Cursor;
+
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    fn builder() -> SegmentBuilder<Cursor<Vec<u8>>> {
+        SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap()
+    }
+
+    /// A spec that sets every field.
+    fn maximal_spec() -> MuxSpec {
+        let mut video = TrackSpec::video(64, 48, VideoCodecId::VP9).named("main");
+        video.number = Some(5);
+        video.title = Some("Main".into());
+        video.language = Some("und".into());
+        video.color = Some(ColorSpec {
+            bit_depth: 10,
+            subsampling: ColorSubsampling {
+                chroma_horizontal: 1,
+                chroma_vertical: 1,
+            },
+            range: ColorRange::Full,
+        });
+
+        let mut audio = TrackSpec::audio(48_000, 2, AudioCodecId::Opus).named("commentary");
+        audio.codec_private = OPUS_HEAD.to_vec();
+        audio.title = Some("Commentary".into());
+        audio.language = Some("fre".into());
+        audio.codec_delay_ns = 6_500_000;
+        audio.seek_pre_roll_ns = 80_000_000;
+
+        MuxSpec {
+            writing_app: Some("transcoder".into()),
+            timecode_scale: Some(100_000),
+            live: false,
+            tracks: vec![video, audio],
+            cues_track: Some("commentary".into()),
+            chapters: vec![
+                ChapterSpec {
+                    id: Some("intro".into()),
+                    language: Some("fre".into()),
+                    ..ChapterSpec::new(0, 100_000_000, "Intro")
+                },
+                ChapterSpec::new(100_000_000, 200_000_000, "Outro"),
+            ],
+            // Only live files can have tags as well as chapters
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn applies_every_field() {
+        let (builder, handles) = builder().apply_spec(&maximal_spec()).unwrap();
+        assert_eq!(handles.names().collect::<Vec<_>>(), ["main", "commentary"]);
+        assert_eq!(handles.track_num("main"), Some(5));
+        assert!(handles.audio("main").is_none());
+
+        let mut segment = builder.build();
+        let (video, audio) = (handles.video("main").unwrap(), handles.audio("commentary").unwrap());
+        for i in 0..10u8 {
+            if i % 2 == 0 {
+                segment.add_video_frame(video, &[i; 20], u64::from(i) * 20_000_000, i == 0).unwrap();
+            }
+            segment.add_audio_frame(audio, &[i; 10], u64::from(i) * 20_000_000, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        let info = demuxer.segment_info();
+        assert_eq!(info.writing_app.as_deref(), Some("transcoder"));
+        assert_eq!(info.timecode_scale, 100_000);
+
+        let main = demuxer.track(5).unwrap();
+        assert_eq!((main.name.as_deref(), main.language.as_deref()), (Some("Main"), Some("und")));
+        let colour = demuxer.colour(5).unwrap();
+        assert_eq!(colour.bits_per_channel, Some(10));
+        assert_eq!(colour.chroma_subsampling, maximal_spec().tracks[0].color.map(|color| color.subsampling));
+        assert_eq!(colour.range, Some(2));
+
+        let audio_num = handles.track_num("commentary").unwrap();
+        let commentary = demuxer.track(audio_num).unwrap();
+        assert_eq!(commentary.codec_private, OPUS_HEAD);
+        assert_eq!((commentary.name.as_deref(), commentary.language.as_deref()), (Some("Commentary"), Some("fre")));
+        assert_eq!((commentary.codec_delay_ns, commentary.seek_pre_roll_ns), (6_500_000, 80_000_000));
+
+        let cues = demuxer.cue_index().unwrap();
+        assert!(!cues.is_empty() && cues.iter().all(|cue| cue.track_num == audio_num), "{cues:?}");
+
+        let chapters = demuxer.chapters().unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].string_uid.as_deref(), Some("intro"));
+        assert_eq!((chapters[0].start_ns, chapters[0].end_ns), (0, Some(100_000_000)));
+        assert_eq!(chapters[0].displays[0].string, "Intro");
+        assert_eq!(chapters[0].displays[0].languages, ["fre"]);
+        assert_eq!(chapters[1].string_uid.as_deref(), Some("2"));
+        assert_eq!(chapters[1].displays[0].string, "Outro");
+
+        assert_eq!(demuxer.frames().count(), 15);
+    }
+
+    #[test]
+    fn adds_tracks_of_any_kind() {
+        let result = builder().add_track(&TrackSpec::audio(0, 2, AudioCodecId::Opus));
+        assert!(matches!(&result, Err(Error::Spec { path, .. }) if path == "media"), "{result:?}");
+
+        // Tracks as a job might list them, known only at runtime
+        let config = [("video", 64), ("audio", 48_000), ("audio", 44_100), ("video", 32)];
+        let mut builder = builder();
+        let mut tracks: Vec<TrackHandle> = Vec::new();
+        for (kind, size) in config {
+            let spec = match kind {
+                "video" => TrackSpec::video(size, size, VideoCodecId::VP8),
+                _ => TrackSpec::audio(size, 2, AudioCodecId::Vorbis),
+            };
+            let (next, track) = builder.add_track(&spec).unwrap();
+            builder = next;
+            tracks.push(track);
+        }
+        assert_eq!(tracks.iter().map(Track::is_video).collect::<Vec<_>>(), [true, false, false, true]);
+        assert_eq!(tracks.iter().map(Track::track_number).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(tracks[0].as_video().map(TrackHandle::from), Some(tracks[0]));
+        assert!(tracks[1].as_video().is_none() && tracks[1].as_audio().is_some());
+
+        let mut segment = builder.build();
+        for i in 0..5u8 {
+            for &track in &tracks {
+                segment.add_frame(track, &[i; 10], u64::from(i) * 20_000_000, true).unwrap();
+            }
+        }
+        let video = tracks[3].as_video().unwrap();
+        segment.add_video_frame(video, &[5; 10], 100_000_000, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert_eq!(demuxer.tracks().len(), 4);
+        let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap().track_num).collect();
+        assert_eq!(frames.iter().filter(|&&track| track == 4).count(), 6);
+        assert_eq!(frames.len(), 21);
+    }
+
+    #[test]
+    fn writes_live_segments() {
+        let spec = MuxSpec {
+            live: true,
+            tracks: vec![TrackSpec::audio(48_000, 1, AudioCodecId::Opus).named("audio")],
+            chapters: vec![ChapterSpec::new(0, 100_000_000, "All")],
+            tags: vec![TagSpec::new([("TITLE", "Job 42"), ("ENCODER", "farm")])],
+            ..MuxSpec::default()
+        };
+        let (builder, handles) = builder().apply_spec(&spec).unwrap();
+        let mut segment = builder.build();
+        for i in 0..5 {
+            segment.add_audio_frame(handles.audio("audio").unwrap(), &[0; 10], i * 20_000_000, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing the segment failed");
+        };
+
+        let mut demuxer = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert_eq!(demuxer.segment_info().duration_ns, None);
+        assert!(demuxer.cue_index().unwrap().is_empty());
+        assert_eq!(demuxer.chapters().unwrap().len(), 1);
+
+        let tags = demuxer.tags().unwrap();
+        assert_eq!(tags.len(), 1);
+        let simple_tags: Vec<_> = tags[0].simple_tags.iter().map(|tag| (tag.name.as_str(), &tag.value)).collect();
+        assert_eq!(
+            simple_tags,
+            [
+                ("TITLE", &TagValue::String("Job 42".into())),
+                ("ENCODER", &TagValue::String("farm".into()))
+            ]
+        );
+        assert_eq!(demuxer.frames().count(), 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_with_serde() {
+        use crate::demux::DemuxOptions;
+        use crate::mux::{MuxFileOptions, RotationPolicy, SimpleWriterOptions};
+        use crate::validate::{SyncOptions, ValidationOptions};
+
+        let mut spec = maximal_spec();
+        spec.tags.push(TagSpec::new([("TITLE", "Job 42")]));
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(serde_json::from_str::<MuxSpec>(&json).unwrap(), spec);
+        for name in [r#""codec":"vp9""#, r#""codec":"opus""#, r#""range":"full""#, r#""type":"video""#] {
+            assert!(json.contains(name), "{name} isn't in {json}");
+        }
+
+        let options = SimpleWriterOptions {
+            writing_app: Some("recorder".into()),
+            ..SimpleWriterOptions::default()
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(serde_json::from_str::<SimpleWriterOptions>(&json).unwrap(), options);
+        let options = MuxFileOptions { sort_frames: true };
+        assert_eq!(serde_json::from_str::<MuxFileOptions>(&serde_json::to_string(&options).unwrap()).unwrap(), options);
+        let options = DemuxOptions {
+            lenient: true,
+            ..DemuxOptions::default()
+        };
+        assert_eq!(serde_json::from_str::<DemuxOptions>(&serde_json::to_string(&options).unwrap()).unwrap(), options);
+        let options = ValidationOptions {
+            allow_matroska: true,
+            ..ValidationOptions::default()
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(serde_json::from_str::<ValidationOptions>(&json).unwrap(), options);
+        let options = SyncOptions {
+            max_trend_ppm: 12.5,
+            ..SyncOptions::default()
+        };
+        assert_eq!(serde_json::from_str::<SyncOptions>(&serde_json::to_string(&options).unwrap()).unwrap(), options);
+        let policy = RotationPolicy {
+            max_duration_ns: Some(60_000_000_000),
+            ..RotationPolicy::default()
+        };
+        let json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(serde_json::from_str::<RotationPolicy>(&json).unwrap(), policy);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn reads_handwritten_specs() {
+        let spec: MuxSpec = serde_json::from_str(
+            r#"{
+                "tracks": [
+                    {"name": "main", "media": {"type": "video", "width": 64, "height": 48, "codec": "av1"}},
+                    {
+                        "name": "audio",
+                        "media": {"type": "audio", "sample_rate": 48000, "channels": 2, "codec": "vorbis"},
+                        "language": "fre"
+                    }
+                ],
+                "chapters": [{"start_ns": 0, "end_ns": 100000000, "title": "All"}],
+                "cues_track": "main"
+            }"#,
+        )
+        .unwrap();
+        let mut audio = TrackSpec::audio(48_000, 2, AudioCodecId::Vorbis).named("audio");
+        audio.language = Some("fre".into());
+        let expected = MuxSpec {
+            tracks: vec![TrackSpec::video(64, 48, VideoCodecId::AV1).named("main"), audio],
+            cues_track: Some("main".into()),
+            chapters: vec![ChapterSpec::new(0, 100_000_000, "All")],
+            ..MuxSpec::default()
+        };
+        assert_eq!(spec, expected);
+
+        // Misspelled fields are errors rather than silently ignored
+        let error = serde_json::from_str::<MuxSpec>(r#"{"timecodescale": 1000}"#).unwrap_err();
+        assert!(error.to_string().contains("unknown field `timecodescale`"), "{error}");
+        let error = serde_json::from_str::<ColorSpec>(r#"{"bit_depth": 10, "range": "studio"}"#).unwrap_err();
+        assert!(error.to_string().contains("unknown variant `studio`"), "{error}");
+    }
+
+    /// The `serde` feature is opt-in, so nothing else may pull serde into the default build.
+    #[cfg(not(target_family = "wasm"))]
+    #[test]
+    fn serde_is_optional() {
+        let output = std::process::Command::new(env!("CARGO"))
+            .args(["tree", "--edges", "normal,build", "--prefix", "none", "--package", env!("CARGO_PKG_NAME")])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let tree = String::from_utf8(output.stdout).unwrap();
+        assert!(!tree.lines().any(|line| line.starts_with("serde")), "{tree}");
+    }
+
+    #[test]
+    fn reports_field_paths() {
+        let path = |change: fn(&mut MuxSpec)| {
+            let mut spec = maximal_spec();
+            change(&mut spec);
+            match builder().apply_spec(&spec) {
+                Err(Error::Spec { path, .. }) => path,
+                result => panic!("The spec unexpectedly didn't fail: {result:?}"),
+            }
+        };
+
+        assert_eq!(path(|spec| spec.timecode_scale = Some(0)), "timecode_scale");
+        assert_eq!(path(|spec| spec.writing_app = Some("nul\0".into())), "writing_app");
+        assert_eq!(path(|spec| spec.tracks[1].name = "main".into()), "tracks[1].name");
+        assert_eq!(path(|spec| spec.tracks[0].name.clear()), "tracks[0].name");
+        assert_eq!(path(|spec| spec.tracks[1].color = spec.tracks[0].color), "tracks[1].color");
+        assert_eq!(path(|spec| spec.tracks[1].number = Some(5)), "tracks[1].number");
+        let no_width = |spec: &mut MuxSpec| spec.tracks[0].media = TrackSpec::video(0, 48, VideoCodecId::VP9).media;
+        assert_eq!(path(no_width), "tracks[0].media");
+        assert_eq!(path(|spec| spec.tracks[1].language = Some("fr\0".into())), "tracks[1].language");
+        assert_eq!(path(|spec| spec.cues_track = Some("subtitles".into())), "cues_track");
+        assert_eq!(path(|spec| spec.chapters[1].end_ns = 0), "chapters[1].end_ns");
+        assert_eq!(path(|spec| spec.chapters[0].title.push('\0')), "chapters[0].title");
+        assert_eq!(path(|spec| spec.tags.push(TagSpec::new([("TITLE", "Job 42")]))), "tags");
+        fn live_tags(spec: &mut MuxSpec, tag: TagSpec) {
+            spec.live = true;
+            spec.tags.push(tag);
+        }
+        assert_eq!(path(|spec| live_tags(spec, TagSpec::default())), "tags[0].simple_tags");
+        let unnamed = |spec: &mut MuxSpec| live_tags(spec, TagSpec::new([("TITLE", "Job 42"), ("", "farm")]));
+        assert_eq!(path(unnamed), "tags[0].simple_tags[1]");
+
+        // The tag of an anchored segment counts too
+        let anchored = builder().anchor_wall_clock(std::time::SystemTime::now()).unwrap();
+        let result = anchored.apply_spec(&maximal_spec());
+        assert!(matches!(&result, Err(Error::Spec { path, .. }) if path == "chapters[0]"), "{result:?}");
+        let (builder, _) = builder().apply_spec(&maximal_spec()).unwrap();
+        assert!(matches!(builder.anchor_wall_clock(std::time::SystemTime::now()), Err(Error::BadParam)));
+    }
+}
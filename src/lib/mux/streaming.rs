@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::io::Cursor;
+
+use crate::demux::ebml;
+
+use super::{AudioTrack, Error, Segment, SegmentBuilder, TrackNum, VideoTrack};
+
+const SEGMENT_ID: u32 = 0x1853_8067;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+
+impl SegmentBuilder<Vec<u8>> {
+    /// Builds a [`StreamingSegment`], which splits its output into an initialization segment and media segments for
+    /// Media Source Extensions. The segment is written for live streaming, without cues.
+    ///
+    /// The builder must have been made with an empty `Vec`, e.g. `Writer::new_non_seek(Vec::new())`, or this fails
+    /// with [`Error::BadParam`].
+    pub fn build_streaming(self) -> Result<StreamingSegment, Error> {
+        let mut segment = self.set_live(true)?.build();
+        if !segment.writer_mut().dest_mut().is_empty() {
+            return Err(Error::BadParam);
+        }
+
+        Ok(StreamingSegment {
+            segment,
+            splitter: Splitter::default(),
+        })
+    }
+}
+
+/// A segment whose output is split as the WebM Byte Stream Format of Media Source Extensions has it: an
+/// initialization segment, from the EBML header through the `Tracks`, and then media segments that are each one
+/// cluster, to be passed to `SourceBuffer.appendBuffer` in turn.
+///
+/// `libwebm` writes the headers along with the first frame, so [`StreamingSegment::init_segment`] is empty until
+/// then. A cluster is only known to be complete once the next one starts, or the segment is finished.
+pub struct StreamingSegment {
+    segment: Segment<Vec<u8>>,
+    splitter: Splitter,
+}
+
+impl StreamingSegment {
+    /// Like [`Segment::add_frame`].
+    pub fn add_frame(
+        &mut self,
+        track: impl Into<TrackNum>,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.segment.add_frame(track, data, timestamp_ns, keyframe)?;
+        self.splitter.split(self.segment.writer_mut().dest_mut());
+        Ok(())
+    }
+
+    /// Like [`Segment::add_video_frame`].
+    pub fn add_video_frame(
+        &mut self,
+        track: VideoTrack,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.segment.add_video_frame(track, data, timestamp_ns, keyframe)?;
+        self.splitter.split(self.segment.writer_mut().dest_mut());
+        Ok(())
+    }
+
+    /// Like [`Segment::add_audio_frame`].
+    pub fn add_audio_frame(
+        &mut self,
+        track: AudioTrack,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<(), Error> {
+        self.segment.add_audio_frame(track, data, timestamp_ns, keyframe)?;
+        self.splitter.split(self.segment.writer_mut().dest_mut());
+        Ok(())
+    }
+
+    /// The initialization segment: everything before the first cluster. Empty until the first frame is written.
+    #[must_use]
+    pub fn init_segment(&self) -> &[u8] {
+        &self.splitter.init
+    }
+
+    /// Takes the next complete media segment, which starts with a `Cluster` ID, or returns `None` if no more are
+    /// complete yet.
+    pub fn next_media_segment(&mut self) -> Option<Vec<u8>> {
+        self.splitter.ready.pop_front()
+    }
+
+    /// Finalizes the segment, and returns the media segments that haven't been taken yet, the last of which is the
+    /// final cluster. Like [`Segment::finalize`], this fails if no frames have been written.
+    pub fn finish(self) -> Result<Vec<Vec<u8>>, Error> {
+        let Self { segment, mut splitter } = self;
+        let mut output = segment.finalize(None).map_err(|_| Error::Unknown)?.into_inner();
+        splitter.split(&mut output);
+        if let Level::Cluster { start } = splitter.level {
+            let end = splitter.output_start + output.len() as u64;
+            splitter.take(&mut output, start, end);
+        }
+        Ok(splitter.ready.into())
+    }
+}
+
+impl std::fmt::Debug for StreamingSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
+}
+
+/// Where [`Splitter`] is in the structure of the output.
+#[derive(Debug, Clone, Copy, Default)]
+enum Level {
+    /// Before the `Segment`'s payload
+    #[default]
+    Top,
+
+    /// Among the children of the `Segment`
+    Segment,
+
+    /// Among the children of a cluster of unknown size, which started at `start`
+    Cluster { start: u64 },
+}
+
+/// Finds the clusters in the output as it is written, by walking its elements. Positions are from the start of the
+/// output, though what has been split off is no longer in the buffer.
+#[derive(Debug, Default)]
+struct Splitter {
+    init: Vec<u8>,
+    ready: VecDeque<Vec<u8>>,
+    level: Level,
+
+    /// The position of the next element to look at
+    pos: u64,
+
+    /// The position of the first byte in the buffer
+    output_start: u64,
+}
+
+impl Splitter {
+    /// Splits off what the buffer holds of the initialization segment and complete clusters. Elements that have only
+    /// been partly written are left to the next call.
+    fn split(&mut self, buffer: &mut Vec<u8>) {
+        loop {
+            let end = self.output_start + buffer.len() as u64;
+            let header = ebml::read_declared_header(&mut Cursor::new(&buffer[..]), self.pos - self.output_start);
+            let Ok((id, payload, size)) = header else {
+                return;
+            };
+            let payload = self.output_start + payload;
+            let element_end = size.and_then(|size| payload.checked_add(size));
+
+            match self.level {
+                Level::Top if id == SEGMENT_ID => {
+                    self.level = Level::Segment;
+                    self.pos = payload;
+                },
+                Level::Segment if id == CLUSTER_ID => {
+                    if self.init.is_empty() {
+                        self.init = buffer.drain(..(self.pos - self.output_start) as usize).collect();
+                        self.output_start = self.pos;
+                    }
+                    match element_end {
+                        Some(element_end) if element_end <= end => {
+                            self.take(buffer, self.pos, element_end);
+                            self.pos = element_end;
+                        },
+                        Some(_) => return,
+                        None => {
+                            self.level = Level::Cluster { start: self.pos };
+                            self.pos = payload;
+                        },
+                    }
+                },
+                // Only the top-level elements have four-byte IDs, so one ends a cluster of unknown size
+                Level::Cluster { start } if id > 0xFF_FFFF => {
+                    self.take(buffer, start, self.pos);
+                    self.level = Level::Segment;
+                },
+                _ => match element_end {
+                    Some(element_end) if element_end <= end => self.pos = element_end,
+                    _ => return,
+                },
+            }
+        }
+    }
+
+    /// Splits off the output from `start` to `end` as a media segment, dropping anything before it.
+    fn take(&mut self, buffer: &mut Vec<u8>, start: u64, end: u64) {
+        let skip = (start - self.output_start) as usize;
+        let media_segment = buffer.drain(..(end - self.output_start) as usize).skip(skip).collect();
+        self.ready.push_back(media_segment);
+        self.output_start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::mux::{AudioCodecId, VideoCodecId, Writer};
+
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    #[test]
+    fn splits_at_clusters() {
+        let builder = SegmentBuilder::new(Writer::new_non_seek(Vec::new())).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_codec_private(audio, OPUS_HEAD).unwrap();
+        let mut segment = builder.build_streaming().unwrap();
+        assert!(segment.init_segment().is_empty());
+
+        // Five seconds of video with a keyframe every second, and audio
+        let mut media_segments = Vec::new();
+        for i in 0..250u64 {
+            if i % 2 == 0 {
+                segment.add_video_frame(video, &[i as u8; 100], i * 20_000_000, i % 50 == 0).unwrap();
+            }
+            segment.add_audio_frame(audio, &[i as u8; 20], i * 20_000_000, true).unwrap();
+            media_segments.extend(std::iter::from_fn(|| segment.next_media_segment()));
+            if i == 0 {
+                assert!(!segment.init_segment().is_empty());
+            }
+        }
+        assert!(media_segments.len() >= 3, "{}", media_segments.len());
+        let init = segment.init_segment().to_vec();
+        media_segments.extend(segment.finish().unwrap());
+        assert_eq!(media_segments.len(), 5);
+
+        assert_eq!(init[..4], [0x1A, 0x45, 0xDF, 0xA3]);
+        let demuxer = Demuxer::new(Cursor::new(init.clone())).unwrap();
+        assert_eq!(demuxer.tracks().len(), 2);
+        for media_segment in &media_segments {
+            assert_eq!(media_segment[..4], CLUSTER_ID.to_be_bytes());
+        }
+
+        // Appended in turn, the pieces are the whole stream
+        let stream = [&init[..], &media_segments.concat()].concat();
+        let mut demuxer = Demuxer::new(Cursor::new(stream)).unwrap();
+        assert_eq!(demuxer.frames().count(), 375);
+
+        // Each media segment stands on its own after the initialization segment, starting with a video keyframe. The
+        // audio held back for it comes first.
+        for (index, media_segment) in media_segments.iter().enumerate() {
+            let mut demuxer = Demuxer::new(Cursor::new([&init[..], media_segment].concat())).unwrap();
+            let frames: Vec<_> = demuxer.frames().map(|frame| frame.unwrap()).collect();
+            let first_video = frames.iter().find(|frame| frame.track_num == TrackNum::from(video)).unwrap();
+            assert!(first_video.is_keyframe);
+            assert_eq!(first_video.timestamp_ns, index as u64 * 1_000_000_000);
+        }
+    }
+
+    #[test]
+    fn needs_an_empty_buffer() {
+        let builder = SegmentBuilder::new(Writer::new_non_seek(vec![0])).unwrap();
+        let (builder, _) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        assert!(matches!(builder.build_streaming(), Err(Error::BadParam)));
+    }
+}
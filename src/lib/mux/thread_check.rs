@@ -0,0 +1,102 @@
+//! A diagnostic for unsound use of a [`Writer`](super::Writer) and the [`Segment`](super::Segment) writing to it from
+//! two threads at once.
+//!
+//! Both are `Send` but not `Sync`, so safe code can only move them between threads. Unsafe code can still share them,
+//! and since `libwebm` isn't thread-safe, the result is corrupt output or worse, far from the cause. With debug
+//! assertions or the `debug-thread-checks` feature, each FFI-calling method marks the writer as in use by its thread
+//! for the duration of the call, and panics if another thread is already using it. Moving between threads is never
+//! flagged, as only overlapping calls are. Otherwise, this compiles to nothing.
+
+#[cfg(any(debug_assertions, feature = "debug-thread-checks"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Default)]
+pub(crate) struct ThreadCheck {
+    /// The token of the thread in a method call, or 0 if there is none.
+    #[cfg(any(debug_assertions, feature = "debug-thread-checks"))]
+    user: AtomicU64,
+}
+
+/// Marks the end of a method call, on drop.
+pub(crate) struct ThreadGuard<'a> {
+    #[cfg(any(debug_assertions, feature = "debug-thread-checks"))]
+    check: Option<&'a ThreadCheck>,
+    #[cfg(not(any(debug_assertions, feature = "debug-thread-checks")))]
+    check: std::marker::PhantomData<&'a ThreadCheck>,
+}
+
+#[cfg(any(debug_assertions, feature = "debug-thread-checks"))]
+impl ThreadCheck {
+    /// Marks this as in use by the current thread until the guard is dropped. Panics if another thread is using it.
+    pub(crate) fn enter(&self, what: &'static str) -> ThreadGuard<'_> {
+        static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+        thread_local! {
+            static TOKEN: u64 = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let token = TOKEN.with(|&token| token);
+        match self.user.compare_exchange(0, token, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => ThreadGuard { check: Some(self) },
+            // A nested call, e.g. from a callback
+            Err(user) if user == token => ThreadGuard { check: None },
+            Err(_) => panic!(
+                "webm: a {what} was used from two threads at once. It is Send but not Sync, so it must not be shared \
+                 between threads without synchronization"
+            ),
+        }
+    }
+}
+
+#[cfg(not(any(debug_assertions, feature = "debug-thread-checks")))]
+impl ThreadCheck {
+    #[inline(always)]
+    pub(crate) fn enter(&self, _what: &'static str) -> ThreadGuard<'_> {
+        ThreadGuard {
+            check: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "debug-thread-checks"))]
+impl Drop for ThreadGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(check) = self.check {
+            check.user.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(all(test, any(debug_assertions, feature = "debug-thread-checks")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn panics_on_overlapping_use() {
+        let check = ThreadCheck::default();
+        let (entered, wait_entered) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+
+        thread::scope(|scope| {
+            let check = &check;
+            let holder = scope.spawn(move || {
+                let _guard = check.enter("Segment");
+                entered.send(()).unwrap();
+                wait_release.recv().unwrap();
+            });
+            wait_entered.recv().unwrap();
+
+            let intruder = scope.spawn(move || drop(check.enter("Segment")));
+            assert!(intruder.join().is_err());
+            release.send(()).unwrap();
+            holder.join().unwrap();
+        });
+
+        // Nested and sequential calls from any thread are fine
+        let guard = check.enter("Segment");
+        drop(check.enter("Segment"));
+        drop(guard);
+        thread::scope(|scope| scope.spawn(|| drop(check.enter("Segment"))).join().unwrap());
+    }
+}
@@ -1,9 +1,11 @@
 use std::ffi::c_void;
-use std::io::{Seek, Write};
+use std::fs::File;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
 use std::marker::PhantomPinned;
 use std::pin::Pin;
 use std::ptr::NonNull;
 
+use super::thread_check::{ThreadCheck, ThreadGuard};
 use crate::ffi;
 use crate::ffi::mux::{WriterGetPosFn, WriterSetPosFn};
 
@@ -47,6 +49,9 @@ impl Drop for OwnedWriterPtr {
 /// It is recommended, but not required, that `T` also implement [`Seek`]. This allows the resulting WebM
 /// file to have things like seeking headers and a stream duration known upfront.
 ///
+/// Positions are 64-bit throughout, but an in-memory `Cursor<Vec<u8>>` can't grow past `usize::MAX` bytes, so on
+/// 32-bit targets like wasm32 muxing into one fails once the output would pass 4 GiB.
+///
 /// Once this [`Writer`] is created, you can use it to create one or more [`Segment`](crate::mux::Segment)s.
 pub struct Writer<T>
 where
@@ -54,6 +59,10 @@ where
 {
     writer_data: Pin<Box<MuxWriterData<T>>>,
     mkv_writer: OwnedWriterPtr,
+    thread_check: ThreadCheck,
+
+    /// The callback `libwebm` asks for the position with
+    get_pos: WriterGetPosFn,
 }
 
 struct MuxWriterData<T> {
@@ -91,10 +100,42 @@ where
         unsafe { Pin::into_inner_unchecked(writer_data).dest }
     }
 
+    /// The write destination, e.g. to take what has been written so far out of a `Vec`. The position `libwebm` sees
+    /// must be left as it is.
+    pub(crate) fn dest_mut(&mut self) -> &mut T {
+        // SAFETY: Only the `MuxWriterData` as a whole needs to stay put, for `libwebm` to point at; nothing points into
+        // the destination itself
+        unsafe { &mut self.writer_data.as_mut().get_unchecked_mut().dest }
+    }
+
+    /// The position `libwebm` sees, at which the next write goes.
+    pub(crate) fn position(&mut self) -> u64 {
+        // SAFETY: As for `dest_mut`
+        let data = unsafe { self.writer_data.as_mut().get_unchecked_mut() };
+        (self.get_pos)(std::ptr::from_mut(data).cast())
+    }
+
+    /// Cuts the destination back to its first `len` bytes, and moves the position there.
+    pub(crate) fn truncate(&mut self, len: u64) -> io::Result<()>
+    where
+        T: Truncate,
+    {
+        // SAFETY: As for `dest_mut`
+        let data = unsafe { self.writer_data.as_mut().get_unchecked_mut() };
+        data.dest.truncate(len)?;
+        data.bytes_written = len;
+        Ok(())
+    }
+
     pub(crate) fn mkv_writer(&self) -> ffi::mux::WriterMutPtr {
         self.mkv_writer.as_ptr()
     }
 
+    /// Marks this writer, and `what` is writing to it, as in use by the current thread, until the guard is dropped.
+    pub(crate) fn enter(&self, what: &'static str) -> ThreadGuard<'_> {
+        self.thread_check.enter(what)
+    }
+
     fn make_writer(
         dest: T,
         get_pos_fn: WriterGetPosFn,
@@ -143,6 +184,8 @@ where
         Writer {
             writer_data,
             mkv_writer: unsafe { OwnedWriterPtr::new(NonNull::new(mkv_writer).unwrap()) },
+            thread_check: ThreadCheck::default(),
+            get_pos: get_pos_fn,
         }
     }
 }
@@ -156,8 +199,6 @@ where
     ///
     /// You can use `io::Cursor::new(Vec::new())` for in-memory writing, or `BufReader::new(File)`.
     pub fn new(dest: T) -> Writer<T> {
-        use std::io::SeekFrom;
-
         extern "C" fn get_pos_fn<T>(data: *mut c_void) -> u64
         where
             T: Write + Seek,
@@ -177,6 +218,30 @@ where
     }
 }
 
+/// A write destination that can be cut back to a length, so that
+/// [`Segment::cancel_and_truncate`](super::Segment::cancel_and_truncate) can leave it as it was before the segment.
+pub trait Truncate {
+    /// Cuts the destination back to its first `len` bytes, and moves its position to the new end.
+    fn truncate(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl Truncate for File {
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)?;
+        self.seek(SeekFrom::Start(len))?;
+        Ok(())
+    }
+}
+
+impl Truncate for Cursor<Vec<u8>> {
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        let len_usize = usize::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.get_mut().truncate(len_usize);
+        self.set_position(len);
+        Ok(())
+    }
+}
+
 #[test]
 fn sendable() {
     fn is_send<T: Send>(_: &T) {}
@@ -189,3 +254,44 @@ fn sendable() {
     is_send(&w);
     assert_eq!([3,4,5], *w.into_inner());
 }
+
+#[test]
+fn muxes_to_memory() {
+    use crate::mux::{SegmentBuilder, VideoCodecId};
+
+    // Also run under wasm, where `usize` is 32 bits and everything is muxed in memory
+    fn mux<W: Write>(writer: Writer<W>) -> W {
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, track) = builder.add_video_track(64, 64, VideoCodecId::VP9, None).unwrap();
+        let mut segment = builder.build();
+        for i in 0..10 {
+            segment.add_frame(track, &[i; 100], u64::from(i) * 33_000_000, i == 0).unwrap();
+        }
+        segment.finalize(None).ok().unwrap().into_inner()
+    }
+
+    let seekable = mux(Writer::new(std::io::Cursor::new(Vec::new()))).into_inner();
+    let streamed = mux(Writer::new_non_seek(Vec::new()));
+    for output in [seekable, streamed] {
+        assert_eq!(output[..4], [0x1A, 0x45, 0xDF, 0xA3]);
+        assert!(output.len() > 1000);
+    }
+}
+
+#[test]
+fn truncates_destinations() {
+    let mut cursor = Cursor::new(b"abcdef".to_vec());
+    Truncate::truncate(&mut cursor, 2).unwrap();
+    cursor.write_all(b"xy").unwrap();
+    assert_eq!(cursor.into_inner(), b"abxy");
+
+    let path = std::env::temp_dir().join(format!("webm-{}-truncate.webm", std::process::id()));
+    let mut file = File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    file.write_all(b"abcdef").unwrap();
+    Truncate::truncate(&mut file, 3).unwrap();
+    file.write_all(b"x").unwrap();
+    drop(file);
+    let written = std::fs::read(&path);
+    _ = std::fs::remove_file(&path);
+    assert_eq!(written.unwrap(), b"abcx");
+}
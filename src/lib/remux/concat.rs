@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::demux::{DemuxTrackInfo, Demuxer, MkvRead};
+use crate::mux::{Segment, SegmentBuilder, TrackNum, Writer};
+
+use super::{clone_tracks, finalize, CodecPrivateMismatch, ConcatError, ConcatOptions, TrackMap, TrackMatching};
+
+/// Joins several WebM files with compatible tracks into one, writing it to `writer`.
+///
+/// The tracks of the output are cloned from the first input with [`clone_tracks`], and the tracks of every input are
+/// matched with them as set in `options`. Tracks of the first input that [`clone_tracks`] skips, and tracks of later
+/// inputs that don't match any of the first input's, are dropped.
+///
+/// Each input's timestamps are offset by the total duration of the inputs before it. An input's duration is taken
+/// from its `Duration` element, or from the end of its last frame if that is later (or there is no `Duration`). Frames
+/// without a known duration are assumed to last as long as the gap since the previous frame of their track.
+pub fn concatenate<R: MkvRead, W: Write>(
+    inputs: impl IntoIterator<Item = R>,
+    writer: Writer<W>,
+    options: ConcatOptions,
+) -> Result<Writer<W>, ConcatError> {
+    let mut inputs = inputs.into_iter().enumerate();
+    let (_, first) = inputs.next().ok_or(ConcatError::NoInputs)?;
+    let mut first = Demuxer::new(first).map_err(|error| ConcatError::Demux { input: 0, error })?;
+
+    let builder = SegmentBuilder::new(writer).map_err(ConcatError::Mux)?;
+    let (builder, map) = clone_tracks(&first, builder).map_err(ConcatError::Remux)?;
+    let reference = first.tracks().to_vec();
+
+    let mut joiner = Joiner {
+        segment: builder.build(),
+        offset: 0,
+        last_timestamp: 0,
+    };
+    let tracks = match_tracks(0, &reference, &map, first.tracks(), options)?;
+    joiner.append(0, &mut first, &tracks, options)?;
+
+    for (input, source) in inputs {
+        let mut demuxer = Demuxer::new(source).map_err(|error| ConcatError::Demux { input, error })?;
+        let tracks = match_tracks(input, &reference, &map, demuxer.tracks(), options)?;
+        joiner.append(input, &mut demuxer, &tracks, options)?;
+    }
+
+    finalize(joiner.segment, joiner.offset).map_err(ConcatError::Mux)
+}
+
+/// Maps the track numbers of an input to the output tracks they are written to.
+fn match_tracks(
+    input: usize,
+    reference: &[DemuxTrackInfo],
+    map: &TrackMap,
+    tracks: &[DemuxTrackInfo],
+    options: ConcatOptions,
+) -> Result<HashMap<TrackNum, TrackNum>, ConcatError> {
+    let mut matched = HashMap::new();
+    let mut used = vec![false; tracks.len()];
+
+    for (index, reference_track) in reference.iter().enumerate() {
+        let Some(output) = map.get(reference_track.track_num) else {
+            continue;
+        };
+
+        let found = match options.track_matching {
+            TrackMatching::ByIndex => Some(index).filter(|&index| {
+                tracks.get(index).is_some_and(|track| track.codec_id == reference_track.codec_id)
+            }),
+            TrackMatching::ByCodec => {
+                (0..tracks.len()).find(|&index| !used[index] && tracks[index].codec_id == reference_track.codec_id)
+            },
+        };
+        let Some(found) = found else {
+            return Err(ConcatError::MissingTrack {
+                input,
+                track_num: reference_track.track_num,
+            });
+        };
+        used[found] = true;
+
+        let track = &tracks[found];
+        if track.codec_private != reference_track.codec_private
+            && options.codec_private_mismatch == CodecPrivateMismatch::Fail
+        {
+            return Err(ConcatError::CodecPrivateMismatch {
+                input,
+                track_num: track.track_num,
+            });
+        }
+        matched.insert(track.track_num, TrackNum::from(output));
+    }
+
+    Ok(matched)
+}
+
+struct Joiner<W: Write> {
+    segment: Segment<W>,
+
+    /// The timestamp that the current input starts at in the output.
+    offset: u64,
+
+    /// The latest timestamp written so far, which the next input must not start before.
+    last_timestamp: u64,
+}
+
+impl<W: Write> Joiner<W> {
+    /// Writes the frames of `demuxer`'s matched tracks, then moves the offset past its end.
+    fn append<R: MkvRead>(
+        &mut self,
+        input: usize,
+        demuxer: &mut Demuxer<R>,
+        tracks: &HashMap<TrackNum, TrackNum>,
+        options: ConcatOptions,
+    ) -> Result<(), ConcatError> {
+        let default_durations: HashMap<TrackNum, Option<u64>> =
+            demuxer.tracks().iter().map(|track| (track.track_num, track.default_duration_ns)).collect();
+        let mut last_frames: HashMap<TrackNum, u64> = HashMap::new();
+        let mut end = demuxer.segment_info().duration_ns.unwrap_or(0);
+
+        for frame in demuxer.frames() {
+            let frame = frame.map_err(|error| ConcatError::Demux { input, error })?;
+            let Some(&output) = tracks.get(&frame.track_num) else {
+                continue;
+            };
+
+            let previous = last_frames.insert(frame.track_num, frame.timestamp_ns);
+            if previous.is_none() && input > 0 && options.require_keyframe_joins && !frame.is_keyframe {
+                return Err(ConcatError::NoKeyframeAtJoin {
+                    input,
+                    track_num: frame.track_num,
+                });
+            }
+
+            let duration = frame
+                .duration_ns
+                .or(default_durations[&frame.track_num])
+                .or_else(|| previous.map(|previous| frame.timestamp_ns.saturating_sub(previous)))
+                .unwrap_or(0);
+            end = end.max(frame.timestamp_ns.saturating_add(duration));
+
+            let timestamp = self.offset.saturating_add(frame.timestamp_ns);
+            self.segment
+                .add_frame(output, &frame.data, timestamp, frame.is_keyframe)
+                .map_err(ConcatError::Mux)?;
+            self.last_timestamp = self.last_timestamp.max(timestamp);
+        }
+
+        self.offset = self.offset.saturating_add(end).max(self.last_timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::DemuxFrame;
+    use crate::mux::{AudioCodecId, VideoCodecId};
+    use crate::remux::ClonedTrack;
+    use std::io::Cursor;
+
+    /// Muxes `frame_count` frames at 10ms intervals to a VP9 and an Opus track, plus an extra Vorbis track if asked.
+    fn mux_input<W: Write>(writer: Writer<W>, frame_count: u64, extra_track: bool, video_keyframe: bool) -> W {
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let (builder, extra) = if extra_track {
+            let (builder, extra) = builder.add_audio_track(44100, 1, AudioCodecId::Vorbis, None).unwrap();
+            (builder, Some(extra))
+        } else {
+            (builder, None)
+        };
+        let mut segment = builder.build();
+
+        for i in 0..frame_count {
+            let timestamp = i * 10_000_000;
+            segment.add_frame(video, &[1; 16], timestamp, video_keyframe || i > 0).unwrap();
+            segment.add_frame(audio, &[2; 8], timestamp, true).unwrap();
+            if let Some(extra) = extra {
+                segment.add_frame(extra, &[3; 8], timestamp, true).unwrap();
+            }
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner()
+    }
+
+    fn seekable_input(frame_count: u64, extra_track: bool) -> Vec<u8> {
+        mux_input(Writer::new(Cursor::new(Vec::new())), frame_count, extra_track, true).into_inner()
+    }
+
+    fn read_output(writer: Writer<Cursor<Vec<u8>>>) -> (Option<u64>, Vec<DemuxFrame>) {
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        (demuxer.segment_info().duration_ns, frames)
+    }
+
+    #[test]
+    fn concatenates_files() {
+        // The second input has no `Duration`, since its writer can't seek
+        let inputs = vec![
+            Cursor::new(seekable_input(10, false)),
+            Cursor::new(mux_input(Writer::new_non_seek(Vec::new()), 5, false, true)),
+            Cursor::new(seekable_input(10, false)),
+        ];
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let writer = concatenate(inputs, writer, ConcatOptions::default()).unwrap();
+
+        let (duration_ns, frames) = read_output(writer);
+        assert_eq!(frames.len(), 2 * 25);
+        assert_eq!(duration_ns, Some(250_000_000));
+        assert!(frames.windows(2).all(|pair| pair[0].timestamp_ns <= pair[1].timestamp_ns));
+
+        let video_timestamps: Vec<u64> =
+            frames.iter().filter(|frame| frame.data[0] == 1).map(|frame| frame.timestamp_ns / 1_000_000).collect();
+        let expected: Vec<u64> = (0..10).chain(10..15).chain(15..25).map(|i| i * 10).collect();
+        assert_eq!(video_timestamps, expected);
+    }
+
+    #[test]
+    fn matches_tracks_by_codec() {
+        let inputs = || {
+            vec![
+                Cursor::new(seekable_input(3, false)),
+                Cursor::new(seekable_input(3, true)),
+                Cursor::new(seekable_input(3, false)),
+            ]
+        };
+
+        // The extra track is after the matching ones, so matching by index works too
+        for track_matching in [TrackMatching::ByIndex, TrackMatching::ByCodec] {
+            let options = ConcatOptions {
+                track_matching,
+                ..ConcatOptions::default()
+            };
+            let writer = concatenate(inputs(), Writer::new(Cursor::new(Vec::new())), options).unwrap();
+            let (_, frames) = read_output(writer);
+            assert_eq!(frames.len(), 2 * 9);
+            assert!(frames.iter().all(|frame| frame.data[0] != 3));
+        }
+
+        // But not if the first input has the extra track
+        let mut inputs = inputs();
+        inputs.swap(0, 1);
+        let result = concatenate(inputs, Writer::new(Cursor::new(Vec::new())), ConcatOptions::default());
+        assert!(matches!(result, Err(ConcatError::MissingTrack { input: 1, .. })));
+    }
+
+    #[test]
+    fn checks_keyframe_joins() {
+        let inputs = || {
+            vec![
+                Cursor::new(seekable_input(3, false)),
+                Cursor::new(mux_input(Writer::new(Cursor::new(Vec::new())), 3, false, false).into_inner()),
+            ]
+        };
+
+        assert!(concatenate(inputs(), Writer::new(Cursor::new(Vec::new())), ConcatOptions::default()).is_ok());
+
+        let options = ConcatOptions {
+            require_keyframe_joins: true,
+            ..ConcatOptions::default()
+        };
+        let result = concatenate(inputs(), Writer::new(Cursor::new(Vec::new())), options);
+        assert!(matches!(result, Err(ConcatError::NoKeyframeAtJoin { input: 1, .. })));
+    }
+
+    #[test]
+    fn checks_codec_private() {
+        let first = Demuxer::new(Cursor::new(seekable_input(1, false))).unwrap();
+        let reference = first.tracks().to_vec();
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let (_, map) = clone_tracks(&first, SegmentBuilder::new(writer).unwrap()).unwrap();
+
+        let mut tracks = reference.clone();
+        tracks[1].codec_private = b"OpusHead".to_vec();
+        let result = match_tracks(1, &reference, &map, &tracks, ConcatOptions::default());
+        assert!(matches!(result, Err(ConcatError::CodecPrivateMismatch { input: 1, track_num: 2 })));
+
+        let options = ConcatOptions {
+            codec_private_mismatch: CodecPrivateMismatch::KeepFirst,
+            ..ConcatOptions::default()
+        };
+        let matched = match_tracks(1, &reference, &map, &tracks, options).unwrap();
+        assert!(matches!(map.get(1), Some(ClonedTrack::Video(_))));
+        assert_eq!(matched.len(), 2);
+    }
+
+    #[test]
+    fn rejects_no_inputs() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let result = concatenate(Vec::<Cursor<Vec<u8>>>::new(), writer, ConcatOptions::default());
+        assert!(matches!(result, Err(ConcatError::NoInputs)));
+    }
+}
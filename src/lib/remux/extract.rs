@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::demux::{Demuxer, MkvRead, TrackType};
+use crate::mux::{Error, SegmentBuilder, TrackNum, Writer};
+
+use super::tracks::{clone_track, mux_codec};
+use super::{clone_tracks, finalize, ClonedTrack, ExtractError, ExtractOptions};
+
+/// Copies the frames from `start_ns` up to `end_ns` of `input` into a new file written to `writer`, without
+/// re-encoding.
+///
+/// Tracks are cloned with [`clone_tracks`], and those it skips are dropped. Since decoding can only start at a
+/// keyframe, the copied range actually starts at the keyframe of the first video track at or before `start_ns`, as
+/// found with [`Demuxer::seek`]. Every video track starts with a keyframe, and audio tracks start somewhat earlier
+/// still, as set by [`ExtractOptions::audio_pre_roll_ns`]. Timestamps are rebased so that the first frame copied is
+/// at zero, and the `Duration` is set to the end of the range.
+///
+/// `CodecDelay`, `SeekPreRoll` and `DiscardPadding` are preserved, and audio frames running past `end_ns` can be
+/// trimmed with a synthesized `DiscardPadding` (see [`ExtractOptions::trim_audio_end`]).
+pub fn extract_range<R: MkvRead, W: Write>(
+    input: R,
+    writer: Writer<W>,
+    start_ns: u64,
+    end_ns: u64,
+    options: ExtractOptions,
+) -> Result<Writer<W>, ExtractError> {
+    if end_ns <= start_ns {
+        return Err(ExtractError::BadRange);
+    }
+
+    let mut demuxer = Demuxer::new(input).map_err(ExtractError::Demux)?;
+    let builder = SegmentBuilder::new(writer).map_err(ExtractError::Mux)?;
+    let (builder, map) = clone_tracks(&demuxer, builder).map_err(ExtractError::Remux)?;
+    let mut segment = builder.build();
+
+    // How early each copied track starts. Video tracks are only ever started at a keyframe.
+    let mut pre_rolls = HashMap::new();
+    let mut default_durations = HashMap::new();
+    let mut seek_track = None;
+    for track in demuxer.tracks() {
+        let Some(cloned) = map.get(track.track_num) else {
+            continue;
+        };
+        let pre_roll = match cloned {
+            ClonedTrack::Video(_) => 0,
+            ClonedTrack::Audio(_) => options.audio_pre_roll_ns.unwrap_or(track.seek_pre_roll_ns),
+        };
+        pre_rolls.insert(track.track_num, pre_roll);
+        default_durations.insert(track.track_num, track.default_duration_ns);
+
+        let is_video = matches!(track.track_type, TrackType::Video(_));
+        if seek_track.is_none() || (is_video && !matches!(seek_track, Some((_, true)))) {
+            seek_track = Some((track.track_num, is_video));
+        }
+    }
+    let Some((seek_track, seek_track_is_video)) = seek_track else {
+        return Err(ExtractError::NoFrames);
+    };
+
+    let start = if seek_track_is_video {
+        demuxer.seek(start_ns, seek_track).map_err(ExtractError::Demux)?.timestamp_ns
+    } else {
+        start_ns
+    };
+    let max_pre_roll = pre_rolls.values().copied().max().unwrap_or(0);
+    demuxer.seek(start.saturating_sub(max_pre_roll), seek_track).map_err(ExtractError::Demux)?;
+
+    let mut base = None;
+    let mut started_video_tracks = Vec::new();
+    let mut reached_end = false;
+    let mut last_end = 0;
+    for frame in demuxer.frames() {
+        let frame = frame.map_err(ExtractError::Demux)?;
+        let (Some(output), Some(&pre_roll)) = (map.get(frame.track_num), pre_rolls.get(&frame.track_num)) else {
+            continue;
+        };
+
+        // Frames are stored in timestamp order, so nothing of interest follows
+        if frame.timestamp_ns >= end_ns {
+            reached_end = true;
+            break;
+        }
+        if frame.timestamp_ns < start.saturating_sub(pre_roll) {
+            continue;
+        }
+        if let ClonedTrack::Video(_) = output {
+            if !started_video_tracks.contains(&frame.track_num) {
+                if !frame.is_keyframe {
+                    continue;
+                }
+                started_video_tracks.push(frame.track_num);
+            }
+        }
+
+        let base = *base.get_or_insert(frame.timestamp_ns);
+        let timestamp = frame.timestamp_ns.saturating_sub(base);
+
+        let duration = frame.duration_ns.or(default_durations[&frame.track_num]);
+        last_end = last_end.max(frame.timestamp_ns.saturating_add(duration.unwrap_or(0)));
+
+        let mut discard_padding_ns = frame.discard_padding_ns;
+        if let (ClonedTrack::Audio(_), true) = (output, options.trim_audio_end) {
+            let overhang = duration.map_or(0, |duration| (frame.timestamp_ns + duration).saturating_sub(end_ns));
+            if let Ok(overhang) = i64::try_from(overhang) {
+                if overhang > 0 {
+                    discard_padding_ns = Some(discard_padding_ns.unwrap_or(0).max(overhang));
+                }
+            }
+        }
+
+        let result = match discard_padding_ns {
+            Some(discard_padding_ns) if discard_padding_ns != 0 => segment.add_frame_with_discard_padding(
+                output,
+                &frame.data,
+                timestamp,
+                frame.is_keyframe,
+                discard_padding_ns,
+            ),
+            _ => segment.add_frame(output, &frame.data, timestamp, frame.is_keyframe),
+        };
+        result.map_err(ExtractError::Mux)?;
+    }
+
+    let Some(base) = base else {
+        return Err(ExtractError::NoFrames);
+    };
+
+    // If the input ends early, so does the output
+    let end = if reached_end {
+        end_ns
+    } else {
+        demuxer.segment_info().duration_ns.unwrap_or(0).max(last_end).min(end_ns)
+    };
+    finalize(segment, end.saturating_sub(base)).map_err(ExtractError::Mux)
+}
+
+/// Copies the track numbered `source_track_num` of `input`, and only that track, into a new file written to `writer`.
+///
+/// The track is cloned as by [`clone_tracks`], and its frames are copied with their original timestamps. Cue points
+/// are written for it whether it is a video or an audio track, and the `Duration` of `input` is kept if it has one.
+pub fn extract_track<R: MkvRead, W: Write>(
+    input: R,
+    writer: Writer<W>,
+    source_track_num: TrackNum,
+) -> Result<Writer<W>, ExtractError> {
+    let mut demuxer = Demuxer::new(input).map_err(ExtractError::Demux)?;
+    let track = demuxer.track(source_track_num).ok_or(ExtractError::MissingTrack {
+        track_num: source_track_num,
+    })?;
+    let codec = mux_codec(track).map_err(ExtractError::Remux)?;
+
+    let builder = SegmentBuilder::new(writer).map_err(ExtractError::Mux)?;
+    let (builder, output) = clone_track(builder, track, codec).map_err(ExtractError::Remux)?;
+    let mut segment = builder.set_cues_track(output).map_err(ExtractError::Mux)?.build();
+
+    for frame in demuxer.frames() {
+        let frame = frame.map_err(ExtractError::Demux)?;
+        if frame.track_num != source_track_num {
+            continue;
+        }
+
+        let result = match frame.discard_padding_ns {
+            Some(discard_padding_ns) if discard_padding_ns != 0 => segment.add_frame_with_discard_padding(
+                output,
+                &frame.data,
+                frame.timestamp_ns,
+                frame.is_keyframe,
+                discard_padding_ns,
+            ),
+            _ => segment.add_frame(output, &frame.data, frame.timestamp_ns, frame.is_keyframe),
+        };
+        result.map_err(ExtractError::Mux)?;
+    }
+
+    match demuxer.segment_info().duration_ns {
+        Some(duration_ns) => finalize(segment, duration_ns).map_err(ExtractError::Mux),
+        None => segment.finalize(None).map_err(|_| ExtractError::Mux(Error::Unknown)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::DemuxFrame;
+    use crate::mux::{AudioCodecId, VideoCodecId};
+    use std::io::Cursor;
+
+    /// One second of video at 50 fps with a keyframe every 100ms, and audio frames halfway between the video frames.
+    fn mux_input() -> Vec<u8> {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for i in 0..50u64 {
+            segment.add_frame(video, &[1; 16], i * 20_000_000, i % 5 == 0).unwrap();
+            segment.add_frame(audio, &[2; 8], i * 20_000_000 + 10_000_000, true).unwrap();
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner().into_inner()
+    }
+
+    fn read_output(writer: Writer<Cursor<Vec<u8>>>) -> (Option<u64>, Vec<DemuxFrame>) {
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        let frames = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        (demuxer.segment_info().duration_ns, frames)
+    }
+
+    #[test]
+    fn extracts_range() {
+        let options = ExtractOptions {
+            audio_pre_roll_ns: Some(40_000_000),
+            ..ExtractOptions::default()
+        };
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let writer = extract_range(Cursor::new(mux_input()), writer, 250_000_000, 600_000_000, options).unwrap();
+        let (duration_ns, frames) = read_output(writer);
+
+        // Video starts at the keyframe at 200ms, and audio 40ms earlier at 170ms, which becomes zero
+        assert_eq!(duration_ns, Some(430_000_000));
+        assert_eq!(frames[0].timestamp_ns, 0);
+        assert_eq!(frames[0].data[0], 2);
+
+        let video: Vec<&DemuxFrame> = frames.iter().filter(|frame| frame.data[0] == 1).collect();
+        assert!(video[0].is_keyframe);
+        assert_eq!(video[0].timestamp_ns, 30_000_000);
+        assert_eq!(video.len(), 20);
+        assert!(frames.iter().all(|frame| frame.timestamp_ns < 430_000_000));
+    }
+
+    #[test]
+    fn extracts_from_keyframe() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let writer =
+            extract_range(Cursor::new(mux_input()), writer, 300_000_000, 2_000_000_000, Default::default()).unwrap();
+        let (duration_ns, frames) = read_output(writer);
+
+        // Without any pre-roll, everything starts at the keyframe, and the range is cut short by the input's end
+        assert_eq!(frames[0].timestamp_ns, 0);
+        assert!(frames[0].is_keyframe);
+        assert_eq!(frames[0].data[0], 1);
+        assert_eq!(duration_ns, Some(690_000_000));
+    }
+
+    #[test]
+    fn extracts_audio_track() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let writer = extract_track(Cursor::new(mux_input()), writer, 2).unwrap();
+
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        assert_eq!(demuxer.tracks().len(), 1);
+        assert_eq!(demuxer.tracks()[0].codec_id, "A_OPUS");
+        assert!(matches!(demuxer.tracks()[0].track_type, TrackType::Audio(_)));
+
+        let frames: Vec<DemuxFrame> = demuxer.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames.len(), 50);
+        assert!(frames.iter().all(|frame| frame.data[0] == 2));
+        assert_eq!(frames[1].timestamp_ns, 30_000_000);
+
+        // Audio-only files are still seekable through their cues
+        let track = demuxer.tracks()[0].track_num;
+        assert!(!demuxer.seek(500_000_000, track).unwrap().linear_scan);
+    }
+
+    #[test]
+    fn rejects_missing_track() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let result = extract_track(Cursor::new(mux_input()), writer, 7);
+        assert!(matches!(result, Err(ExtractError::MissingTrack { track_num: 7 })));
+    }
+
+    #[test]
+    fn rejects_bad_range() {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let result = extract_range(Cursor::new(mux_input()), writer, 10, 10, ExtractOptions::default());
+        assert!(matches!(result, Err(ExtractError::BadRange)));
+    }
+}
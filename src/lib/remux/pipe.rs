@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use crate::demux::{DemuxFrame, Demuxer, MkvRead};
+use crate::mux::{FrameExtras, Segment};
+
+use super::{FrameAction, PipeError, TrackMap};
+
+/// Copies the remaining frames of `demuxer` into `segment`, passing each through `filter` first.
+///
+/// `track_map` says which track of `segment` each source track is written to, as returned by [`clone_tracks`]; frames
+/// of tracks it doesn't map are dropped without being passed to `filter`. Each frame keeps its `BlockDuration` and
+/// `DiscardPadding`, and its first `BlockAdditional` (the muxer only writes one per frame, which covers alpha
+/// channels). Timestamps are written as given, so a filter that rewrites them must keep them increasing.
+///
+/// [`clone_tracks`]: super::clone_tracks
+pub fn pipe_frames<R: MkvRead, W: Write>(
+    demuxer: &mut Demuxer<R>,
+    segment: &mut Segment<W>,
+    track_map: &TrackMap,
+    mut filter: impl FnMut(&DemuxFrame) -> FrameAction,
+) -> Result<(), PipeError> {
+    for frame in demuxer.frames() {
+        let frame = frame.map_err(PipeError::Demux)?;
+        let Some(output) = track_map.get(frame.track_num) else {
+            continue;
+        };
+
+        let (timestamp_ns, keyframe) = match filter(&frame) {
+            FrameAction::Keep => (frame.timestamp_ns, frame.is_keyframe),
+            FrameAction::Drop => continue,
+            FrameAction::Rewrite { timestamp_ns, keyframe } => (timestamp_ns, keyframe),
+        };
+
+        let extras = FrameExtras {
+            addition: frame.additions.first().map(|(id, data)| (*id, data.as_slice())),
+            duration_ns: frame.duration_ns.filter(|&duration| duration > 0),
+            discard_padding_ns: frame.discard_padding_ns.unwrap_or(0),
+            references_ns: &[],
+        };
+        let result = match extras {
+            FrameExtras {
+                addition: None,
+                duration_ns: None,
+                discard_padding_ns: 0,
+                references_ns: [],
+            } => segment.add_frame(output, &frame.data, timestamp_ns, keyframe),
+            _ => segment.add_frame_with_extras(output, &frame.data, timestamp_ns, keyframe, extras),
+        };
+        result.map_err(PipeError::Mux)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId, Writer};
+    use crate::remux::clone_tracks;
+    use std::io::Cursor;
+
+    /// Half a second of video at 50 fps with a keyframe every 100ms, and audio frames halfway between the video
+    /// frames. The last audio frame has a `DiscardPadding`.
+    fn mux_input() -> Vec<u8> {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for i in 0..25u64 {
+            segment.add_frame(video, &[i as u8; 16], i * 20_000_000, i % 5 == 0).unwrap();
+            let timestamp = i * 20_000_000 + 10_000_000;
+            if i == 24 {
+                segment.add_frame_with_discard_padding(audio, &[2; 8], timestamp, true, 5_000_000).unwrap();
+            } else {
+                segment.add_frame(audio, &[2; 8], timestamp, true).unwrap();
+            }
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner().into_inner()
+    }
+
+    fn pipe(filter: impl FnMut(&DemuxFrame) -> FrameAction) -> (Vec<DemuxFrame>, Vec<DemuxFrame>) {
+        let input = mux_input();
+        let mut demuxer = Demuxer::new(Cursor::new(input.clone())).unwrap();
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, map) = clone_tracks(&demuxer, builder).unwrap();
+        let mut segment = builder.build();
+        pipe_frames(&mut demuxer, &mut segment, &map, filter).unwrap();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let read_frames = |data| {
+            let mut demuxer = Demuxer::new(Cursor::new(data)).unwrap();
+            demuxer.frames().collect::<Result<Vec<_>, _>>().unwrap()
+        };
+        (read_frames(input), read_frames(writer.into_inner().into_inner()))
+    }
+
+    #[test]
+    fn pipes_identically() {
+        let (input, output) = pipe(|_| FrameAction::Keep);
+        assert_eq!(output, input);
+        assert_eq!(output.last().unwrap().discard_padding_ns, Some(5_000_000));
+    }
+
+    #[test]
+    fn drops_and_rewrites_frames() {
+        let mut video_frames = 0;
+        let (input, output) = pipe(|frame| {
+            if frame.data.len() != 16 {
+                return FrameAction::Keep;
+            }
+            video_frames += 1;
+            if video_frames % 2 == 0 {
+                FrameAction::Drop
+            } else {
+                FrameAction::Rewrite {
+                    timestamp_ns: frame.timestamp_ns,
+                    keyframe: true,
+                }
+            }
+        });
+
+        let video: Vec<&DemuxFrame> = output.iter().filter(|frame| frame.data.len() == 16).collect();
+        assert_eq!(video.len(), 13);
+        assert!(video.iter().all(|frame| frame.is_keyframe && frame.data[0] % 2 == 0));
+        assert_eq!(output.len(), input.len() - 12);
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::Write;
+
+use crate::demux::{ColourInfo, DemuxTrackInfo, Demuxer, TrackType};
+use crate::mux::{
+    color_format_is_valid, is_bcp_47, is_iso_639_2, AudioCodecId, ChromaSiting, ColorRange, SegmentBuilder,
+    VideoCodecId,
+};
+
+use super::{ClonedTrack, RemuxError, TrackMap};
+
+/// The codec of a source track, as the muxer knows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Codec {
+    Video(VideoCodecId),
+    Audio(AudioCodecId),
+}
+
+pub(crate) fn mux_codec(track: &DemuxTrackInfo) -> Result<Codec, RemuxError> {
+    let codec = match track.codec_id.as_str() {
+        "V_VP8" => Codec::Video(VideoCodecId::VP8),
+        "V_VP9" => Codec::Video(VideoCodecId::VP9),
+        "V_AV1" => Codec::Video(VideoCodecId::AV1),
+        "A_OPUS" => Codec::Audio(AudioCodecId::Opus),
+        "A_VORBIS" => Codec::Audio(AudioCodecId::Vorbis),
+        _ => {
+            return Err(RemuxError::UnsupportedCodec {
+                track_num: track.track_num,
+                codec_id: track.codec_id.clone(),
+            })
+        },
+    };
+
+    match (codec, &track.track_type) {
+        (Codec::Video(_), TrackType::Video(_)) | (Codec::Audio(_), TrackType::Audio(_)) => Ok(codec),
+        _ => Err(RemuxError::UnsupportedTrackType {
+            track_num: track.track_num,
+        }),
+    }
+}
+
+/// The arguments to [`SegmentBuilder::set_color`], if `colour` can be written by the muxer without losing anything
+/// it can express.
+fn mux_color(colour: &ColourInfo) -> Option<(u8, crate::mux::ColorSubsampling, ColorRange)> {
+    let range = match colour.range {
+        None | Some(0) => ColorRange::Unspecified,
+        Some(1) => ColorRange::Broadcast,
+        Some(2) => ColorRange::Full,
+        Some(_) => return None,
+    };
+    let (bit_depth, subsampling) = (colour.bits_per_channel?, colour.chroma_subsampling?);
+    color_format_is_valid(bit_depth, &subsampling).then_some((bit_depth, subsampling, range))
+}
+
+/// The arguments to [`SegmentBuilder::set_chroma_siting`], if `colour` has a chroma siting the muxer can write.
+fn mux_chroma_siting(colour: &ColourInfo) -> Option<(ChromaSiting, ChromaSiting)> {
+    let siting = |value| match value {
+        None | Some(0) => Some(ChromaSiting::Unspecified),
+        Some(1) => Some(ChromaSiting::Collocated),
+        Some(2) => Some(ChromaSiting::Half),
+        Some(_) => None,
+    };
+    let sitings = (siting(colour.chroma_siting_horz)?, siting(colour.chroma_siting_vert)?);
+    (sitings != (ChromaSiting::Unspecified, ChromaSiting::Unspecified)).then_some(sitings)
+}
+
+/// Adds a copy of each of `demuxer`'s tracks to `builder`, for remuxing its frames into a new file.
+///
+/// The codec, dimensions or sample rate and channel count, `CodecPrivate` data, `CodecDelay`, `SeekPreRoll` and basic
+/// color information (bit depth, chroma subsampling and siting, and range) are copied, as are the `Name` and the
+/// languages where they are well-formed. Track numbers are chosen by the muxer, so frames must be added to the track
+/// that the returned [`TrackMap`] gives for their source track.
+///
+/// Tracks whose codec the muxer cannot write, and tracks that are neither video nor audio, are skipped and reported
+/// in [`TrackMap::skipped`]. An error is only returned if creating a track fails, in which case `builder` is lost.
+pub fn clone_tracks<R, W: Write>(
+    demuxer: &Demuxer<R>,
+    mut builder: SegmentBuilder<W>,
+) -> Result<(SegmentBuilder<W>, TrackMap), RemuxError> {
+    let mut map = TrackMap::default();
+
+    for track in demuxer.tracks() {
+        let codec = match mux_codec(track) {
+            Ok(codec) => codec,
+            Err(err) => {
+                map.skipped.push(err);
+                continue;
+            },
+        };
+
+        let cloned;
+        (builder, cloned) = clone_track(builder, track, codec)?;
+        map.tracks.insert(track.track_num, cloned);
+    }
+
+    Ok((builder, map))
+}
+
+/// Adds a copy of `track`, whose codec `mux_codec` gave as `codec`, to `builder`.
+pub(super) fn clone_track<W: Write>(
+    builder: SegmentBuilder<W>,
+    track: &DemuxTrackInfo,
+    codec: Codec,
+) -> Result<(SegmentBuilder<W>, ClonedTrack), RemuxError> {
+    let (mut builder, cloned) = match (codec, &track.track_type) {
+        (Codec::Video(codec), TrackType::Video(video)) => {
+            let (mut builder, new_track) =
+                builder.add_video_track(video.width, video.height, codec, None).map_err(RemuxError::Mux)?;
+            if let Some((bit_depth, subsampling, range)) = video.colour.as_deref().and_then(mux_color) {
+                builder = builder.set_color(new_track, bit_depth, subsampling, range).map_err(RemuxError::Mux)?;
+            }
+            if let Some((horizontal, vertical)) = video.colour.as_deref().and_then(mux_chroma_siting) {
+                builder = builder.set_chroma_siting(new_track, horizontal, vertical).map_err(RemuxError::Mux)?;
+            }
+            (builder, ClonedTrack::Video(new_track))
+        },
+        (Codec::Audio(codec), TrackType::Audio(audio)) => {
+            // The muxer only takes whole sample rates
+            let sample_rate = audio.sample_rate.round() as u32;
+            let (builder, new_track) =
+                builder.add_audio_track(sample_rate, audio.channels, codec, None).map_err(RemuxError::Mux)?;
+            (builder, ClonedTrack::Audio(new_track))
+        },
+        _ => unreachable!("mux_codec checks the track type"),
+    };
+
+    if !track.codec_private.is_empty() {
+        builder = builder.set_codec_private(cloned, &track.codec_private).map_err(RemuxError::Mux)?;
+    }
+    if track.codec_delay_ns != 0 {
+        builder = builder.set_codec_delay(cloned, track.codec_delay_ns).map_err(RemuxError::Mux)?;
+    }
+    if track.seek_pre_roll_ns != 0 {
+        builder = builder.set_seek_pre_roll(cloned, track.seek_pre_roll_ns).map_err(RemuxError::Mux)?;
+    }
+    if let Some(name) = &track.name {
+        builder = builder.set_track_name(cloned, name).map_err(RemuxError::Mux)?;
+    }
+    // A language that isn't well-formed is left behind rather than failing the copy
+    if let Some(language) = track.language.as_deref().filter(|language| is_iso_639_2(language)) {
+        builder = builder.set_track_language(cloned, language).map_err(RemuxError::Mux)?;
+    }
+    if let Some(language) = track.language_bcp47.as_deref().filter(|language| is_bcp_47(language)) {
+        builder = builder.set_track_language_bcp47(cloned, language).map_err(RemuxError::Mux)?;
+    }
+    Ok((builder, cloned))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::DemuxFrame;
+    use crate::mux::{ColorSubsampling, TrackNum, Writer};
+    use std::io::Cursor;
+
+    fn mux_source_file() -> Vec<u8> {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, Some(3)).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, Some(5)).unwrap();
+        let subsampling = ColorSubsampling {
+            chroma_horizontal: 1,
+            chroma_vertical: 1,
+        };
+        let builder = builder
+            .set_color(video, 10, subsampling, ColorRange::Full)
+            .unwrap()
+            .set_chroma_siting(video, ChromaSiting::Collocated, ChromaSiting::Half)
+            .unwrap()
+            .set_codec_private(audio, b"OpusHead")
+            .unwrap()
+            .set_track_name(audio, "Director's commentary")
+            .unwrap()
+            .set_track_language(audio, "eng")
+            .unwrap()
+            .set_track_language_bcp47(audio, "en-US")
+            .unwrap();
+        let mut segment = builder.build();
+
+        for i in 0..20u64 {
+            let track: TrackNum = if i % 2 == 0 { video.into() } else { audio.into() };
+            segment.add_frame(track, &[i as u8; 16], i * 10_000_000, i % 2 == 1 || i == 0).unwrap();
+        }
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner().into_inner()
+    }
+
+    #[test]
+    fn remuxes_tracks() {
+        let mut source = Demuxer::new(Cursor::new(mux_source_file())).unwrap();
+
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let (builder, map) = clone_tracks(&source, SegmentBuilder::new(writer).unwrap()).unwrap();
+        assert!(map.skipped().is_empty());
+        assert_eq!(map.iter().count(), 2);
+
+        let mut segment = builder.build();
+        let source_frames: Vec<DemuxFrame> = source.frames().collect::<Result<_, _>>().unwrap();
+        for frame in &source_frames {
+            let track = map.get(frame.track_num).unwrap();
+            segment.add_frame(track, &frame.data, frame.timestamp_ns, frame.is_keyframe).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let mut remuxed = Demuxer::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert_eq!(remuxed.tracks().len(), source.tracks().len());
+        for original in source.tracks() {
+            let new_num = TrackNum::from(map.get(original.track_num).unwrap());
+            let copy = remuxed.track(new_num).unwrap();
+
+            // UIDs are random, and track numbers are chosen anew
+            assert_eq!(copy.track_type, original.track_type);
+            assert_eq!(copy.codec_id, original.codec_id);
+            assert_eq!(copy.codec_private, original.codec_private);
+            assert_eq!(copy.default_duration_ns, original.default_duration_ns);
+            assert_eq!(copy.codec_delay_ns, original.codec_delay_ns);
+            assert_eq!(copy.seek_pre_roll_ns, original.seek_pre_roll_ns);
+            assert_eq!(copy.name, original.name);
+            assert_eq!(copy.language, original.language);
+            assert_eq!(copy.language_bcp47, original.language_bcp47);
+        }
+
+        let remuxed_frames: Vec<DemuxFrame> = remuxed.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(remuxed_frames.len(), source_frames.len());
+        for (copy, original) in remuxed_frames.iter().zip(&source_frames) {
+            assert_eq!(Some(copy.track_num), map.get(original.track_num).map(TrackNum::from));
+            assert_eq!(copy.timestamp_ns, original.timestamp_ns);
+            assert_eq!(copy.is_keyframe, original.is_keyframe);
+            assert_eq!(copy.data, original.data);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_codecs() {
+        let source = Demuxer::new(Cursor::new(mux_source_file())).unwrap();
+        let mut track = source.tracks()[0].clone();
+        assert_eq!(mux_codec(&track).unwrap(), Codec::Audio(AudioCodecId::Opus));
+
+        track.codec_id = "A_AAC".to_string();
+        assert!(matches!(
+            mux_codec(&track),
+            Err(RemuxError::UnsupportedCodec { track_num: 3, codec_id }) if codec_id == "A_AAC"
+        ));
+
+        track.codec_id = "V_VP9".to_string();
+        assert!(matches!(mux_codec(&track), Err(RemuxError::UnsupportedTrackType { track_num: 3 })));
+    }
+}
@@ -0,0 +1,728 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::demux::ebml::{self, Element};
+use crate::demux::{DemuxError, DemuxOptions, DemuxTrackInfo, Demuxer, DurationStrategy, MkvRead, TrackType};
+use crate::mux::TrackNum;
+
+use super::{RepairError, RepairReport};
+
+const EBML_ID: u32 = 0x1A45_DFA3;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const SEEK_HEAD_ID: u32 = 0x114D_9B74;
+const SEEK_ID: u32 = 0x4DBB;
+const SEEK_ID_ID: u32 = 0x53AB;
+const SEEK_POSITION_ID: u32 = 0x53AC;
+const INFO_ID: u32 = 0x1549_A966;
+const DURATION_ID: u32 = 0x4489;
+const TRACKS_ID: u32 = 0x1654_AE6B;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const SIMPLE_BLOCK_ID: u32 = 0xA3;
+const BLOCK_GROUP_ID: u32 = 0xA0;
+const CUES_ID: u32 = 0x1C53_BB6B;
+const CUE_POINT_ID: u32 = 0xBB;
+const CUE_TIME_ID: u32 = 0xB3;
+const CUE_TRACK_POSITIONS_ID: u32 = 0xB7;
+const CUE_TRACK_ID: u32 = 0xF7;
+const CUE_CLUSTER_POSITION_ID: u32 = 0xF1;
+const VOID_ID: u32 = 0xEC;
+const CRC32_ID: u32 = 0xBF;
+
+/// Bytes to write or overlay, each with its position
+type Patches = Vec<(u64, Vec<u8>)>;
+
+/// Finalizes a WebM file that was left unfinalized, e.g. by a crash or power loss while recording, in place.
+///
+/// The clusters are scanned with a lenient [`Demuxer`], and everything after the last complete block is dropped: a
+/// partly written block, a cluster without any complete blocks, and anything else after the last cluster (such as the
+/// `Cues` of an interrupted finalization). Then the sizes of the `Segment` and of clusters written without one are
+/// filled in, `Cues` are appended with a cue point for the first keyframe in each cluster, and the `SeekHead` and
+/// `Duration` that `libwebm` leaves room for are written. Cue points are for the first video track, or the first track
+/// if there is no video.
+///
+/// A file can't be shortened through [`Write`], so the dropped bytes are covered by a `Void` element instead.
+/// Truncating the file to [`RepairReport::file_len`] afterwards removes them.
+///
+/// Nothing is written unless the whole repair fits, which takes the space that `libwebm` reserves in files written to a
+/// seekable [`Writer`](crate::mux::Writer). Other files, such as those written for live streaming, fail with
+/// [`RepairError::NoRoom`], and can be repaired into a copy with [`repair_to`] instead.
+pub fn finalize_in_place<F: Read + Write + Seek>(file: &mut F) -> Result<RepairReport, RepairError> {
+    let plan = Plan::unfinalized(file)?;
+    let (writes, file_len) = plan.in_place_writes(file)?;
+    for (pos, data) in &writes {
+        write_at(file, *pos, data)?;
+    }
+    file.flush().map_err(RepairError::Io)?;
+    Ok(plan.report(file_len))
+}
+
+/// What is kept of an unfinalized file, for [`Segment::resume`](crate::mux::Segment::resume) to add clusters to.
+#[derive(Debug)]
+pub(crate) struct Resumable {
+    /// The end of the last complete cluster, where new ones are to be written
+    pub end: u64,
+
+    /// The end of the last frame
+    pub end_ns: u64,
+
+    pub timecode_scale: u64,
+    pub tracks: Vec<DemuxTrackInfo>,
+    pub truncated_bytes: u64,
+}
+
+/// Scans an unfinalized file for what can be kept of it. Fails as [`finalize_in_place`] would if the file couldn't be
+/// finalized in place, without writing anything.
+pub(crate) fn resumable<R: MkvRead>(source: &mut R) -> Result<Resumable, RepairError> {
+    let plan = Plan::unfinalized(source)?;
+    plan.in_place_writes(source)?;
+    Ok(Resumable {
+        end: plan.layout.end,
+        end_ns: plan.duration_ns,
+        timecode_scale: plan.timecode_scale,
+        tracks: plan.tracks,
+        truncated_bytes: plan.layout.truncated_bytes,
+    })
+}
+
+/// Writes a repaired copy of `source`, a WebM file that was left unfinalized, to `dest`.
+///
+/// The frames kept and the elements added are the same as with [`finalize_in_place`], but as this rewrites the
+/// `Segment`'s headers, it also works for files without room for them, such as those written for live streaming. The
+/// `SeekHead`, `Duration` and `Cues` replace any the file had, so this can also rebuild them for a finalized file.
+pub fn repair_to<R: MkvRead, W: Write>(mut source: R, mut dest: W) -> Result<RepairReport, RepairError> {
+    let layout = Layout::scan(&mut source)?;
+    let plan = Plan::new(&mut source, layout)?;
+    let layout = &plan.layout;
+
+    // The `Info` is rebuilt with a `Duration`, and the other headers are copied as they are
+    let info = layout.header(INFO_ID).ok_or(RepairError::InvalidFile)?;
+    let mut info_payload = Vec::new();
+    for child in ebml::children(&mut source, info.pos, info.end()).map_err(RepairError::Demux)? {
+        if !matches!(child.id, DURATION_ID | VOID_ID | CRC32_ID) {
+            info_payload.extend(read_range(&mut source, child.start, child.end())?);
+        }
+    }
+    info_payload.extend(element(DURATION_ID, &plan.duration().to_be_bytes()));
+    let info = element(INFO_ID, &info_payload);
+    let headers = layout.headers.iter().filter(|header| header.id == INFO_ID || is_kept(header.id));
+    let headers: Vec<&Element> = headers.collect();
+    let header_len = |header: &Element| {
+        if header.id == INFO_ID {
+            info.len() as u64
+        } else {
+            header.end() - header.start
+        }
+    };
+
+    // Positions in the `SeekHead` are all written with 8 bytes, so that its size doesn't depend on them
+    let mut entries = layout.seek_entries();
+    if !plan.cue_points.is_empty() {
+        entries.push((CUES_ID, 0));
+    }
+    let mut pos = element(SEEK_HEAD_ID, &seek_head_payload(&entries, true)).len() as u64;
+    for (entry, header) in entries.iter_mut().zip(&headers) {
+        entry.1 = pos;
+        pos += header_len(header);
+    }
+    let mut positions = Vec::new();
+    for cluster in &layout.clusters {
+        positions.push(pos);
+        pos += 12 + cluster.end - cluster.payload;
+    }
+    let cues = plan.cues_payload(|cluster| positions[cluster]);
+    let cues = if cues.is_empty() { cues } else { element(CUES_ID, &cues) };
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.0 == CUES_ID) {
+        entry.1 = pos;
+    }
+    let seek_head = element(SEEK_HEAD_ID, &seek_head_payload(&entries, true));
+    let segment_size = pos + cues.len() as u64;
+
+    let write = |dest: &mut W, data: &[u8]| dest.write_all(data).map_err(RepairError::Io);
+    copy_range(&mut source, &mut dest, 0, layout.segment_start)?;
+    write(&mut dest, &[ebml_id(SEGMENT_ID), size_field(segment_size, 8)].concat())?;
+    write(&mut dest, &seek_head)?;
+    for header in &headers {
+        if header.id == INFO_ID {
+            write(&mut dest, &info)?;
+        } else {
+            copy_range(&mut source, &mut dest, header.start, header.end())?;
+        }
+    }
+    for cluster in &layout.clusters {
+        let mut header = ebml_id(CLUSTER_ID);
+        header.extend(size_field(cluster.end - cluster.payload, 8));
+        write(&mut dest, &header)?;
+        copy_range(&mut source, &mut dest, cluster.payload, cluster.end)?;
+    }
+    write(&mut dest, &cues)?;
+    dest.flush().map_err(RepairError::Io)?;
+
+    Ok(plan.report(layout.segment_start + 12 + segment_size))
+}
+
+/// Whether a top-level element before the clusters is copied by [`repair_to`]. The rest are rebuilt or dropped.
+fn is_kept(id: u32) -> bool {
+    !matches!(id, SEEK_HEAD_ID | INFO_ID | CUES_ID | VOID_ID | CRC32_ID)
+}
+
+/// A cluster found by [`Layout::scan`].
+#[derive(Debug, Clone, Copy)]
+struct Cluster {
+    start: u64,
+    payload: u64,
+
+    /// The end of its last complete child
+    end: u64,
+
+    /// Whether its declared size ends there, so that it needn't be patched
+    sized: bool,
+}
+
+/// How a file is laid out, as far as it is complete.
+#[derive(Debug)]
+struct Layout {
+    len: u64,
+    segment_start: u64,
+    segment_payload: u64,
+    segment_size: Option<u64>,
+
+    /// The top-level elements before the first cluster
+    headers: Vec<Element>,
+
+    clusters: Vec<Cluster>,
+
+    /// The end of the last cluster, after which the file is dropped
+    end: u64,
+
+    /// How much of the segment is cut off or damaged
+    truncated_bytes: u64,
+}
+
+impl Layout {
+    /// Walks the top-level elements of the file's `Segment` up to the first that is incomplete. Clusters of unknown
+    /// size are walked up to the next top-level element.
+    fn scan<R: MkvRead>(source: &mut R) -> Result<Self, RepairError> {
+        let len = source.len().map_err(RepairError::Io)?;
+        let (id, payload, size) = read_header(source, 0)?.ok_or(RepairError::InvalidFile)?;
+        let segment_start = size.filter(|_| id == EBML_ID).and_then(|size| payload.checked_add(size));
+        let segment_start = segment_start.ok_or(RepairError::InvalidFile)?;
+        let segment = read_header(source, segment_start)?.filter(|(id, ..)| *id == SEGMENT_ID);
+        let (_, segment_payload, segment_size) = segment.ok_or(RepairError::InvalidFile)?;
+        let limit = segment_size.and_then(|size| segment_payload.checked_add(size)).map_or(len, |end| end.min(len));
+
+        let mut layout = Self {
+            len,
+            segment_start,
+            segment_payload,
+            segment_size,
+            headers: Vec::new(),
+            clusters: Vec::new(),
+            end: segment_payload,
+            truncated_bytes: 0,
+        };
+        // Elements after the clusters are passed over, but only to tell whether the file was cut off
+        let mut pos = segment_payload;
+        while pos < limit {
+            let Some((id, payload, size)) = read_header(source, pos)? else {
+                break;
+            };
+            let declared_end = size.and_then(|size| payload.checked_add(size));
+
+            if id == CLUSTER_ID {
+                if pos != layout.end {
+                    break;
+                }
+                let cluster_limit = declared_end.map_or(limit, |end| end.min(limit));
+                let (end, has_blocks) = scan_cluster(source, payload, cluster_limit)?;
+                let sized = declared_end == Some(end);
+                if !sized && !has_blocks {
+                    break;
+                }
+                layout.clusters.push(Cluster {
+                    start: pos,
+                    payload,
+                    end,
+                    sized,
+                });
+                layout.end = end;
+                pos = end;
+                // What follows a cluster that was cut short can't be trusted
+                if !sized && size.is_some() {
+                    break;
+                }
+            } else {
+                let Some(end) = declared_end.filter(|&end| end <= limit) else {
+                    break;
+                };
+                if layout.clusters.is_empty() {
+                    layout.headers.push(Element {
+                        id,
+                        start: pos,
+                        pos: payload,
+                        size: end - payload,
+                    });
+                    layout.end = end;
+                }
+                pos = end;
+            }
+        }
+        layout.truncated_bytes = limit - pos;
+
+        if layout.header(INFO_ID).is_none() || layout.header(TRACKS_ID).is_none() {
+            return Err(RepairError::InvalidFile);
+        }
+        Ok(layout)
+    }
+
+    fn header(&self, id: u32) -> Option<Element> {
+        self.headers.iter().find(|header| header.id == id).copied()
+    }
+
+    /// The size of the `Void` (or old `SeekHead` and `Void`) at the start of the segment, which is room for a new
+    /// `SeekHead`.
+    fn reserved(&self) -> u64 {
+        let reserved = self.headers.iter().take_while(|header| matches!(header.id, SEEK_HEAD_ID | VOID_ID)).last();
+        reserved.map_or(0, |header| header.end() - self.segment_payload)
+    }
+
+    /// The `SeekHead` entries for the headers, with their positions in the segment.
+    fn seek_entries(&self) -> Vec<(u32, u64)> {
+        let headers = self.headers.iter().filter(|header| header.id == INFO_ID || is_kept(header.id));
+        headers.map(|header| (header.id, header.start - self.segment_payload)).collect()
+    }
+}
+
+/// Walks the children of the cluster whose payload starts at `payload`, up to `limit`, the first incomplete child or
+/// the next top-level element. Returns the end of the last complete child, and whether there were any blocks.
+fn scan_cluster<R: MkvRead>(source: &mut R, payload: u64, limit: u64) -> Result<(u64, bool), RepairError> {
+    let mut end = payload;
+    let mut has_blocks = false;
+    while end < limit {
+        let Some((id, child_payload, size)) = read_header(source, end)? else {
+            break;
+        };
+        // Only the top-level elements have four-byte IDs
+        if id > 0xFF_FFFF {
+            break;
+        }
+        let child_end = size.and_then(|size| child_payload.checked_add(size)).filter(|&child_end| child_end <= limit);
+        let Some(child_end) = child_end else {
+            break;
+        };
+        has_blocks |= matches!(id, SIMPLE_BLOCK_ID | BLOCK_GROUP_ID);
+        end = child_end;
+    }
+    Ok((end, has_blocks))
+}
+
+/// Reads the element header at `pos`, or returns `None` if it is cut off or garbled.
+fn read_header<R: MkvRead>(source: &mut R, pos: u64) -> Result<Option<(u32, u64, Option<u64>)>, RepairError> {
+    match ebml::read_declared_header(source, pos) {
+        Ok(header) => Ok(Some(header)),
+        Err(DemuxError::Io(err)) if err.kind() != io::ErrorKind::UnexpectedEof => Err(RepairError::Io(err)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A cue point to be written, for the first keyframe of a cluster.
+#[derive(Debug)]
+struct CuePoint {
+    /// In units of the timecode scale
+    time: u64,
+    track_num: TrackNum,
+
+    /// The index of the cluster in [`Layout::clusters`]
+    cluster: usize,
+}
+
+/// What the repaired file is made of.
+#[derive(Debug)]
+struct Plan {
+    layout: Layout,
+    timecode_scale: u64,
+    duration_ns: u64,
+    cue_points: Vec<CuePoint>,
+    tracks: Vec<DemuxTrackInfo>,
+}
+
+impl Plan {
+    /// Reads the frames of the clusters in `layout` for the duration and cue points.
+    fn new<R: MkvRead>(source: &mut R, layout: Layout) -> Result<Self, RepairError> {
+        if layout.clusters.is_empty() {
+            return Err(RepairError::NoFrames);
+        }
+
+        let options = DemuxOptions {
+            lenient: true,
+            ..DemuxOptions::default()
+        };
+        // The demuxer sees the file as it will be: unknown sizes can only be told from the start of what follows
+        let unsized_clusters = layout.clusters.iter().filter(|cluster| !cluster.sized);
+        let mut patches: Vec<_> =
+            unsized_clusters.filter_map(|cluster| size_patch(cluster.start, cluster.payload, cluster.end)).collect();
+        patches.extend(size_patch(layout.segment_start, layout.segment_payload, layout.end));
+        let source = Repaired {
+            source,
+            len: layout.end,
+            patches,
+        };
+        let mut demuxer = Demuxer::with_options(source, options).map_err(RepairError::Demux)?;
+        let timecode_scale = demuxer.segment_info().timecode_scale.max(1);
+        let tracks = demuxer.tracks();
+        let video = tracks.iter().find(|track| matches!(track.track_type, TrackType::Video(_)));
+        let track = video.or(tracks.first()).ok_or(RepairError::InvalidFile)?.track_num;
+
+        let mut cue_points: Vec<CuePoint> = Vec::new();
+        for keyframe in demuxer.keyframe_index(track).map_err(RepairError::Demux)? {
+            let Ok(cluster) = layout.clusters.binary_search_by_key(&keyframe.cluster_offset, |cluster| cluster.start)
+            else {
+                continue;
+            };
+            if cue_points.last().is_none_or(|cue_point| cue_point.cluster != cluster) {
+                cue_points.push(CuePoint {
+                    time: keyframe.timestamp_ns / timecode_scale,
+                    track_num: keyframe.track_num,
+                    cluster,
+                });
+            }
+        }
+        let duration_ns = demuxer.compute_duration(DurationStrategy::FullScan).map_err(RepairError::Demux)?;
+
+        Ok(Self {
+            layout,
+            timecode_scale,
+            duration_ns,
+            cue_points,
+            tracks: demuxer.tracks().to_vec(),
+        })
+    }
+
+    /// Scans an unfinalized file, failing if its `Segment` already has a size.
+    fn unfinalized<R: MkvRead>(source: &mut R) -> Result<Self, RepairError> {
+        let layout = Layout::scan(source)?;
+        if layout.segment_size.is_some() {
+            return Err(RepairError::AlreadyFinalized);
+        }
+        Self::new(source, layout)
+    }
+
+    /// What [`finalize_in_place`] writes, as positions and bytes in the order they are written, and the end of the
+    /// repaired segment. Fails with [`RepairError::NoRoom`] if something doesn't fit.
+    fn in_place_writes<R: MkvRead>(&self, source: &mut R) -> Result<(Patches, u64), RepairError> {
+        let layout = &self.layout;
+        let info = layout.header(INFO_ID).ok_or(RepairError::InvalidFile)?;
+        let children = ebml::children(source, info.pos, info.end()).map_err(RepairError::Demux)?;
+        let duration = children.into_iter().find(|child| child.id == DURATION_ID && matches!(child.size, 4 | 8));
+        let duration = duration.ok_or(RepairError::NoRoom { element: "Duration" })?;
+        let duration_value = if duration.size == 4 {
+            (self.duration() as f32).to_be_bytes().to_vec()
+        } else {
+            self.duration().to_be_bytes().to_vec()
+        };
+
+        let cues_payload = self.cues_payload(|cluster| layout.clusters[cluster].start - layout.segment_payload);
+        let mut cues = Vec::new();
+        if !cues_payload.is_empty() {
+            cues = element(CUES_ID, &cues_payload);
+            // A single byte can't be covered by a `Void`, so the `Cues` take it up instead
+            if layout.len == layout.end + cues.len() as u64 + 1 {
+                let size_len = size_len(cues_payload.len() as u64) + 1;
+                cues = element_sized(CUES_ID, &cues_payload, size_len);
+            }
+        }
+        let segment_end = layout.end + cues.len() as u64;
+        let segment_size = size_patch(layout.segment_start, layout.segment_payload, segment_end);
+        let segment_size = segment_size.ok_or(RepairError::NoRoom { element: "Segment" })?;
+
+        let mut entries = layout.seek_entries();
+        if !cues.is_empty() {
+            entries.push((CUES_ID, layout.end - layout.segment_payload));
+        }
+        let seek_head = fill(SEEK_HEAD_ID, &seek_head_payload(&entries, false), layout.reserved())
+            .ok_or(RepairError::NoRoom { element: "SeekHead" })?;
+
+        let mut writes = vec![(layout.end, cues)];
+        if layout.len >= segment_end + 2 {
+            writes.push((segment_end, void(layout.len - segment_end)));
+        }
+        for cluster in layout.clusters.iter().filter(|cluster| !cluster.sized) {
+            let size = size_patch(cluster.start, cluster.payload, cluster.end);
+            writes.push(size.ok_or(RepairError::NoRoom { element: "Cluster" })?);
+        }
+        writes.push((layout.segment_payload, seek_head));
+        writes.push((duration.pos, duration_value));
+        // The `Segment` size goes last, so that the file only looks finalized once it is
+        writes.push(segment_size);
+        Ok((writes, segment_end))
+    }
+
+    /// The `Duration`, in units of the timecode scale.
+    fn duration(&self) -> f64 {
+        self.duration_ns as f64 / self.timecode_scale as f64
+    }
+
+    /// The payload of the `Cues`, with clusters at the positions given by `position`, or nothing if there are no cue
+    /// points.
+    fn cues_payload(&self, position: impl Fn(usize) -> u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for cue_point in &self.cue_points {
+            let track_positions = [
+                uint_element(CUE_TRACK_ID, cue_point.track_num),
+                uint_element(CUE_CLUSTER_POSITION_ID, position(cue_point.cluster)),
+            ]
+            .concat();
+            let cue_point = [
+                uint_element(CUE_TIME_ID, cue_point.time),
+                element(CUE_TRACK_POSITIONS_ID, &track_positions),
+            ];
+            payload.extend(element(CUE_POINT_ID, &cue_point.concat()));
+        }
+        payload
+    }
+
+    fn report(&self, file_len: u64) -> RepairReport {
+        RepairReport {
+            duration_ns: self.duration_ns,
+            clusters: self.layout.clusters.len() as u64,
+            cue_points: self.cue_points.len() as u64,
+            truncated_bytes: self.layout.truncated_bytes,
+            file_len,
+        }
+    }
+}
+
+/// A source as it reads once repaired, up to `len`: what comes after the last complete cluster is cut off, and the
+/// size fields in `patches` (positions and bytes) are filled in.
+struct Repaired<'a, R> {
+    source: &'a mut R,
+    len: u64,
+    patches: Patches,
+}
+
+impl<R: MkvRead> MkvRead for Repaired<'_, R> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let available = usize::try_from(self.len.saturating_sub(offset)).unwrap_or(usize::MAX);
+        let len = buf.len().min(available);
+        let len = self.source.read_at(offset, &mut buf[..len])?;
+        for (pos, patch) in &self.patches {
+            for (index, &byte) in patch.iter().enumerate() {
+                if let Some(at) = (pos + index as u64).checked_sub(offset).filter(|&at| at < len as u64) {
+                    buf[at as usize] = byte;
+                }
+            }
+        }
+        Ok(len)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
+
+/// The size field to write for the element at `start` so that it ends at `end`, as its position and bytes. Returns
+/// `None` if the size doesn't fit the field as long as it is. Only four-byte IDs are expected.
+fn size_patch(start: u64, payload: u64, end: u64) -> Option<(u64, Vec<u8>)> {
+    let size_len = (payload - start - 4) as usize;
+    fits(end - payload, size_len).then(|| (start + 4, size_field(end - payload, size_len)))
+}
+
+/// The payload of a `SeekHead` with the given IDs and positions. With `fixed_width`, every position takes 8 bytes.
+fn seek_head_payload(entries: &[(u32, u64)], fixed_width: bool) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for &(id, position) in entries {
+        let position = if fixed_width {
+            element(SEEK_POSITION_ID, &position.to_be_bytes())
+        } else {
+            uint_element(SEEK_POSITION_ID, position)
+        };
+        let seek = [element(SEEK_ID_ID, &ebml_id(id)), position].concat();
+        payload.extend(element(SEEK_ID, &seek));
+    }
+    payload
+}
+
+/// The bytes of an element ID, which includes its length marker, so that it has no leading zero bytes.
+fn ebml_id(id: u32) -> Vec<u8> {
+    id.to_be_bytes()[(id.leading_zeros() / 8) as usize..].to_vec()
+}
+
+/// Whether `size` can be written in a size field of `len` bytes. All ones is reserved for an unknown size.
+fn fits(size: u64, len: usize) -> bool {
+    (1..=8).contains(&len) && size < (1 << (7 * len)) - 1
+}
+
+/// The length of the shortest size field for `size`.
+fn size_len(size: u64) -> usize {
+    (1..8).find(|&len| fits(size, len)).unwrap_or(8)
+}
+
+/// A size field of `len` bytes, which `size` must fit.
+fn size_field(size: u64, len: usize) -> Vec<u8> {
+    (size | 1 << (7 * len)).to_be_bytes()[8 - len..].to_vec()
+}
+
+fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    element_sized(id, payload, size_len(payload.len() as u64))
+}
+
+/// An element whose size field is `size_len` bytes long.
+fn element_sized(id: u32, payload: &[u8], size_len: usize) -> Vec<u8> {
+    [ebml_id(id), size_field(payload.len() as u64, size_len), payload.to_vec()].concat()
+}
+
+fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    element(id, &bytes[(value.leading_zeros() / 8).min(7) as usize..])
+}
+
+/// The header of a `Void` element that is `len` bytes long in all, which must be at least 2. Its payload is whatever
+/// is already there.
+fn void(len: u64) -> Vec<u8> {
+    let size_len = (1..=8).find(|&size_len| fits(len.saturating_sub(1 + size_len as u64), size_len)).unwrap_or(8);
+    [ebml_id(VOID_ID), size_field(len - 1 - size_len as u64, size_len)].concat()
+}
+
+/// An element that takes up exactly `room` bytes, followed by a `Void` for what it doesn't need. A single byte left
+/// over goes to a longer size field instead. Returns `None` if the element doesn't fit.
+fn fill(id: u32, payload: &[u8], room: u64) -> Option<Vec<u8>> {
+    let mut data = element(id, payload);
+    match room.checked_sub(data.len() as u64)? {
+        0 => {},
+        1 => {
+            let size_len = size_len(payload.len() as u64) + 1;
+            if size_len > 8 {
+                return None;
+            }
+            data = element_sized(id, payload, size_len);
+        },
+        left => data.extend(void(left)),
+    }
+    Some(data)
+}
+
+fn write_at<W: Write + Seek>(file: &mut W, pos: u64, data: &[u8]) -> Result<(), RepairError> {
+    file.seek(SeekFrom::Start(pos)).map_err(RepairError::Io)?;
+    file.write_all(data).map_err(RepairError::Io)
+}
+
+fn read_range<R: MkvRead>(source: &mut R, start: u64, end: u64) -> Result<Vec<u8>, RepairError> {
+    let mut data = vec![0; usize::try_from(end - start).map_err(|_| RepairError::InvalidFile)?];
+    ebml::read_exact_at(source, start, &mut data).map_err(RepairError::Demux)?;
+    Ok(data)
+}
+
+fn copy_range<R: MkvRead, W: Write>(source: &mut R, dest: &mut W, start: u64, end: u64) -> Result<(), RepairError> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut pos = start;
+    while pos < end {
+        let len = buffer.len().min(usize::try_from(end - pos).unwrap_or(usize::MAX));
+        ebml::read_exact_at(source, pos, &mut buffer[..len]).map_err(RepairError::Demux)?;
+        dest.write_all(&buffer[..len]).map_err(RepairError::Io)?;
+        pos += len as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId, Writer};
+    use crate::validate::{validate, ValidationOptions};
+    use std::io::Cursor;
+
+    const OPUS_HEAD: &[u8] = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+
+    /// Three seconds of video at 25fps with a keyframe every second, and audio, as written before the segment is
+    /// finalized.
+    fn unfinalized(writer: Writer<Cursor<Vec<u8>>>) -> Vec<u8> {
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.set_codec_private(audio, OPUS_HEAD).unwrap().build();
+        for i in 0..150u64 {
+            if i % 2 == 0 {
+                segment.add_video_frame(video, &[i as u8; 500], i * 20_000_000, i % 50 == 0).unwrap();
+            }
+            segment.add_audio_frame(audio, &[i as u8; 50], i * 20_000_000, true).unwrap();
+        }
+        segment.writer_mut().dest_mut().get_ref().clone()
+    }
+
+    fn find(data: &[u8], id: u32) -> usize {
+        data.windows(4).position(|window| window == id.to_be_bytes()).unwrap()
+    }
+
+    /// Checks that a repaired file is valid, has the right duration and cues, and can be seeked in.
+    fn check_repaired(data: &[u8], report: &RepairReport) {
+        let validation = validate(Cursor::new(data), ValidationOptions::default());
+        assert!(validation.is_valid(), "{validation:?}");
+
+        let mut demuxer = Demuxer::new(Cursor::new(data)).unwrap();
+        let last_ns = demuxer.frames().map(|frame| frame.unwrap().timestamp_ns).max().unwrap();
+        assert_eq!(report.duration_ns, last_ns);
+        assert_eq!(demuxer.segment_info().duration_ns, Some(last_ns));
+        assert_eq!(demuxer.cue_index().unwrap().len() as u64, report.cue_points);
+
+        let target_ns = last_ns * 2 / 3;
+        let point = demuxer.seek(target_ns, 1u64).unwrap();
+        assert!(!point.linear_scan);
+        assert_eq!(point.timestamp_ns, target_ns / 1_000_000_000 * 1_000_000_000);
+        let frame = demuxer.frames().next().unwrap().unwrap();
+        assert!(frame.is_keyframe && frame.timestamp_ns == point.timestamp_ns, "{frame:?}");
+    }
+
+    #[test]
+    fn finalizes_cut_off_files_in_place() {
+        let data = unfinalized(Writer::new(Cursor::new(Vec::new())));
+        let cluster = find(&data, CLUSTER_ID);
+        let len = data.len();
+        for cut in [len, len - 1, len - 300, len * 2 / 3, len / 2 + 7, cluster + 700] {
+            let mut file = Cursor::new(data[..cut].to_vec());
+            let report = finalize_in_place(&mut file).unwrap();
+            assert!(report.cue_points > 0 && report.clusters >= report.cue_points, "{report:?}");
+            let mut repaired = file.into_inner();
+            check_repaired(&repaired, &report);
+
+            // The dropped bytes are covered by a `Void` until they are truncated away
+            assert!(repaired.len() as u64 >= report.file_len);
+            repaired.truncate(report.file_len as usize);
+            check_repaired(&repaired, &report);
+            assert!(matches!(finalize_in_place(&mut Cursor::new(repaired)), Err(RepairError::AlreadyFinalized)));
+        }
+
+        // Only the frames still held by the muxer are lost
+        let report = finalize_in_place(&mut Cursor::new(data.clone())).unwrap();
+        assert_eq!(report.truncated_bytes, 0);
+        assert!(report.duration_ns >= 2_500_000_000, "{report:?}");
+    }
+
+    #[test]
+    fn repairs_into_a_copy() {
+        let data = unfinalized(Writer::new_non_seek(Cursor::new(Vec::new())));
+        let cut = data.len() * 3 / 4;
+        let mut file = Cursor::new(data[..cut].to_vec());
+        assert!(matches!(finalize_in_place(&mut file), Err(RepairError::NoRoom { .. })));
+        assert_eq!(file.get_ref()[..], data[..cut]);
+
+        let mut repaired = Vec::new();
+        let report = repair_to(&mut file, &mut repaired).unwrap();
+        assert_eq!(report.file_len, repaired.len() as u64);
+        assert!(report.truncated_bytes > 0);
+        check_repaired(&repaired, &report);
+
+        // The headers of a finalized file are rebuilt the same way
+        let mut again = Vec::new();
+        let report = repair_to(Cursor::new(&repaired), &mut again).unwrap();
+        assert_eq!(report.truncated_bytes, 0);
+        check_repaired(&again, &report);
+    }
+
+    #[test]
+    fn rejects_unusable_files() {
+        let data = unfinalized(Writer::new(Cursor::new(Vec::new())));
+        let cut = |len: usize| Cursor::new(data[..len].to_vec());
+        assert!(matches!(finalize_in_place(&mut cut(find(&data, TRACKS_ID) + 10)), Err(RepairError::InvalidFile)));
+        assert!(matches!(finalize_in_place(&mut cut(find(&data, CLUSTER_ID) + 20)), Err(RepairError::NoFrames)));
+        let mut text = Cursor::new(b"Not a WebM file".to_vec());
+        assert!(matches!(finalize_in_place(&mut text), Err(RepairError::InvalidFile)));
+    }
+}
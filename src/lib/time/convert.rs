@@ -0,0 +1,172 @@
+use std::time::{Duration, SystemTime};
+
+use super::{Rounding, TimeError, Timebase};
+
+const NS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Converts `ticks` of `timebase` to nanoseconds, rounded to the nearest one.
+///
+/// The conversion is exact up to the final rounding, for any result that fits in a `u64` (about 584 years); larger
+/// ones fail with [`TimeError::Overflow`].
+pub fn ticks_to_ns(ticks: u64, timebase: Timebase) -> Result<u64, TimeError> {
+    ticks_to_ns_rounded(ticks, timebase, Rounding::Nearest)
+}
+
+/// Like [`ticks_to_ns`], but rounded as given.
+pub fn ticks_to_ns_rounded(ticks: u64, timebase: Timebase, rounding: Rounding) -> Result<u64, TimeError> {
+    check(timebase)?;
+    // At most 2^64 * 2^32 * 2^30, so this can't overflow
+    let ns = u128::from(ticks) * u128::from(timebase.num) * NS_PER_SECOND;
+    narrow(divide(ns, u128::from(timebase.den), rounding))
+}
+
+/// Converts nanoseconds to ticks of `timebase`, rounded to the nearest tick.
+///
+/// The conversion is exact up to the final rounding, for any result that fits in a `u64`; larger ones fail with
+/// [`TimeError::Overflow`]. Converting ticks to nanoseconds and back gives the same ticks for any timebase with ticks
+/// of at least a nanosecond, and is off by at most a nanosecond's worth of ticks for finer ones.
+pub fn ns_to_ticks(ns: u64, timebase: Timebase) -> Result<u64, TimeError> {
+    ns_to_ticks_rounded(ns, timebase, Rounding::Nearest)
+}
+
+/// Like [`ns_to_ticks`], but rounded as given.
+pub fn ns_to_ticks_rounded(ns: u64, timebase: Timebase, rounding: Rounding) -> Result<u64, TimeError> {
+    check(timebase)?;
+    let ticks = u128::from(ns) * u128::from(timebase.den);
+    narrow(divide(ticks, u128::from(timebase.num) * NS_PER_SECOND, rounding))
+}
+
+/// `time` in nanoseconds from the Unix epoch, negative if it is before it.
+pub(crate) fn unix_ns(time: SystemTime) -> i128 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => after.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    }
+}
+
+/// The time `ns` nanoseconds from the Unix epoch, or `None` if it can't be represented on this platform.
+pub(crate) fn from_unix_ns(ns: i128) -> Option<SystemTime> {
+    let magnitude = ns.unsigned_abs();
+    let secs = u64::try_from(magnitude / NS_PER_SECOND).ok()?;
+    let offset = Duration::new(secs, (magnitude % NS_PER_SECOND) as u32);
+    if ns >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+fn check(timebase: Timebase) -> Result<(), TimeError> {
+    if timebase.num == 0 || timebase.den == 0 {
+        return Err(TimeError::ZeroTimebase(timebase));
+    }
+    Ok(())
+}
+
+fn divide(dividend: u128, divisor: u128, rounding: Rounding) -> u128 {
+    let (quotient, remainder) = (dividend / divisor, dividend % divisor);
+    let round_up = match rounding {
+        Rounding::Down => false,
+        // The divisor is below 2^62, so this can't overflow
+        Rounding::Nearest => remainder * 2 >= divisor,
+        Rounding::Up => remainder != 0,
+    };
+    quotient + u128::from(round_up)
+}
+
+fn narrow(value: u128) -> Result<u64, TimeError> {
+    value.try_into().map_err(|_| TimeError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A xorshift generator, so that the property tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn timebase(&mut self) -> Timebase {
+            let bits = |rng: &mut Self| (rng.next() >> (rng.next() % 32 + 32)) as u32 | 1;
+            Timebase::new(bits(self), bits(self))
+        }
+    }
+
+    #[test]
+    fn converts_common_timebases() {
+        assert_eq!(ticks_to_ns(960, Timebase::OPUS), Ok(20_000_000));
+        assert_eq!(ticks_to_ns(3003, Timebase::RTP_VIDEO), Ok(33_366_667));
+        assert_eq!(ticks_to_ns(1, Timebase::NTSC), Ok(33_366_667));
+        assert_eq!(ticks_to_ns(30_000, Timebase::NTSC), Ok(1_001_000_000_000));
+        assert_eq!(ns_to_ticks(1_001_000_000_000, Timebase::NTSC), Ok(30_000));
+        assert_eq!(ns_to_ticks(20_000_000, Timebase::OPUS), Ok(960));
+        assert_eq!(ticks_to_ns(7, Timebase::NANOSECONDS), Ok(7));
+    }
+
+    #[test]
+    fn rounds_as_given() {
+        // A frame at 30000/1001 fps is 33366666.67ns
+        let round = |rounding| ticks_to_ns_rounded(1, Timebase::NTSC, rounding);
+        assert_eq!(round(Rounding::Down), Ok(33_366_666));
+        assert_eq!(round(Rounding::Nearest), Ok(33_366_667));
+        assert_eq!(round(Rounding::Up), Ok(33_366_667));
+
+        // Exactly half a tick rounds up to the nearest
+        let round = |rounding| ns_to_ticks_rounded(500_000, Timebase::MILLISECONDS, rounding);
+        assert_eq!(round(Rounding::Down), Ok(0));
+        assert_eq!(round(Rounding::Nearest), Ok(1));
+        assert_eq!(round(Rounding::Up), Ok(1));
+        assert_eq!(ns_to_ticks_rounded(2_000_000, Timebase::MILLISECONDS, Rounding::Up), Ok(2));
+    }
+
+    #[test]
+    fn rejects_bad_timebases_and_overflow() {
+        let zero = Timebase::new(0, 1000);
+        assert_eq!(ticks_to_ns(1, zero), Err(TimeError::ZeroTimebase(zero)));
+        assert_eq!(ns_to_ticks(1, Timebase::new(1, 0)), Err(TimeError::ZeroTimebase(Timebase::new(1, 0))));
+
+        // Everything up to `u64::MAX` nanoseconds converts, and nothing past it
+        assert_eq!(ticks_to_ns(u64::MAX, Timebase::NANOSECONDS), Ok(u64::MAX));
+        let max_ms = u64::MAX / 1_000_000;
+        assert_eq!(ticks_to_ns(max_ms, Timebase::MILLISECONDS), Ok(max_ms * 1_000_000));
+        assert_eq!(ticks_to_ns(max_ms + 1, Timebase::MILLISECONDS), Err(TimeError::Overflow));
+        assert_eq!(ticks_to_ns(u64::MAX, Timebase::new(u32::MAX, 1)), Err(TimeError::Overflow));
+        assert_eq!(ns_to_ticks(u64::MAX, Timebase::new(1, u32::MAX)), Err(TimeError::Overflow));
+        assert_eq!(ns_to_ticks(u64::MAX, Timebase::MILLISECONDS), Ok(u64::MAX / 1_000_000 + 1));
+    }
+
+    #[test]
+    fn round_trips_within_a_tick() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..100_000 {
+            let timebase = rng.timebase();
+            let ticks = rng.next() >> (rng.next() % 64);
+            let (num, den) = (u128::from(timebase.num), u128::from(timebase.den));
+            let Ok(ns) = ticks_to_ns(ticks, timebase) else {
+                // Only results of about `u64::MAX` nanoseconds or more overflow
+                assert!(u128::from(ticks) * num * NS_PER_SECOND / den >= u128::from(u64::MAX));
+                continue;
+            };
+
+            let back = ns_to_ticks(ns, timebase).unwrap();
+            if num * NS_PER_SECOND >= den {
+                assert_eq!(back, ticks, "{ticks} ticks of {timebase:?} are {ns}ns");
+            } else {
+                let error = u128::from(back.abs_diff(ticks)) * num * NS_PER_SECOND;
+                assert!(error <= den, "{ticks} ticks of {timebase:?} are {ns}ns, and back {back}");
+            }
+
+            // Rounding down and up bracket the exact value
+            let down = ticks_to_ns_rounded(ticks, timebase, Rounding::Down).unwrap();
+            let up = ticks_to_ns_rounded(ticks, timebase, Rounding::Up);
+            assert!(down <= ns && up.map_or(true, |up| ns <= up && up - down <= 1));
+        }
+    }
+}
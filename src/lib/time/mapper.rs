@@ -0,0 +1,168 @@
+use super::TimeError;
+
+/// Maps wall-clock time to media time for recordings that can be paused: while paused, the wall clock keeps running
+/// but media time doesn't, so everything after a resume is moved back by the time spent paused.
+///
+/// Wall-clock times are in nanoseconds from any fixed point, e.g. a monotonic clock's. Media time starts at zero at
+/// the origin, which is the first wall-clock time the mapper is given unless set with
+/// [`TimestampMapper::starting_at`].
+///
+/// Times are only mapped forward from the last resume (or the origin), since anything earlier has already been
+/// shifted by a different amount. While paused, times up to the pause can still be mapped, for frames that were
+/// captured before it but arrive after.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimestampMapper {
+    /// The wall-clock time of media time zero, once known
+    origin_ns: Option<u64>,
+
+    /// The wall-clock time of the last resume, or the origin if there hasn't been one
+    resumed_ns: u64,
+
+    /// The wall-clock time of the current pause, if paused
+    paused_at_ns: Option<u64>,
+
+    /// The time spent in pauses that have ended
+    paused_ns: u64,
+}
+
+impl TimestampMapper {
+    /// A mapper whose origin is the first wall-clock time it is given.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A mapper whose media time zero is at `origin_ns` on the wall clock.
+    #[must_use]
+    pub fn starting_at(origin_ns: u64) -> Self {
+        Self {
+            origin_ns: Some(origin_ns),
+            resumed_ns: origin_ns,
+            ..Self::default()
+        }
+    }
+
+    /// Pauses media time at `at_wall_ns`. Fails with [`TimeError::Paused`] if already paused, or with
+    /// [`TimeError::Backwards`] if `at_wall_ns` is before the last resume.
+    pub fn pause(&mut self, at_wall_ns: u64) -> Result<(), TimeError> {
+        if self.paused_at_ns.is_some() {
+            return Err(TimeError::Paused);
+        }
+        self.check(at_wall_ns)?;
+        self.paused_at_ns = Some(at_wall_ns);
+        Ok(())
+    }
+
+    /// Resumes media time at `at_wall_ns`, where it continues from where it was paused. Fails with
+    /// [`TimeError::NotPaused`] if not paused, or with [`TimeError::Backwards`] if `at_wall_ns` is before the pause.
+    pub fn resume(&mut self, at_wall_ns: u64) -> Result<(), TimeError> {
+        let paused_at_ns = self.paused_at_ns.ok_or(TimeError::NotPaused)?;
+        if at_wall_ns < paused_at_ns {
+            return Err(TimeError::Backwards {
+                wall_ns: at_wall_ns,
+                earliest_ns: paused_at_ns,
+            });
+        }
+        self.paused_ns += at_wall_ns - paused_at_ns;
+        self.paused_at_ns = None;
+        self.resumed_ns = at_wall_ns;
+        Ok(())
+    }
+
+    /// The media time of `wall_ns`. Fails with [`TimeError::Backwards`] if it is before the last resume, or with
+    /// [`TimeError::Paused`] if it is after the current pause.
+    pub fn map(&mut self, wall_ns: u64) -> Result<u64, TimeError> {
+        let origin_ns = self.check(wall_ns)?;
+        if self.paused_at_ns.is_some_and(|paused_at_ns| wall_ns > paused_at_ns) {
+            return Err(TimeError::Paused);
+        }
+        Ok(wall_ns - origin_ns - self.paused_ns)
+    }
+
+    /// Whether media time is paused.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused_at_ns.is_some()
+    }
+
+    /// The total time spent in pauses that have ended, in nanoseconds.
+    #[must_use]
+    pub fn paused_ns(&self) -> u64 {
+        self.paused_ns
+    }
+
+    /// Sets the origin to `wall_ns` if there isn't one yet, and checks that `wall_ns` isn't before the last resume.
+    /// Returns the origin.
+    fn check(&mut self, wall_ns: u64) -> Result<u64, TimeError> {
+        let Some(origin_ns) = self.origin_ns else {
+            self.origin_ns = Some(wall_ns);
+            self.resumed_ns = wall_ns;
+            return Ok(wall_ns);
+        };
+        if wall_ns < self.resumed_ns {
+            return Err(TimeError::Backwards {
+                wall_ns,
+                earliest_ns: self.resumed_ns,
+            });
+        }
+        Ok(origin_ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_pauses() {
+        let mut mapper = TimestampMapper::starting_at(1_000);
+        assert_eq!(mapper.map(1_000), Ok(0));
+        assert_eq!(mapper.map(1_500), Ok(500));
+
+        // A frame captured before the pause can still be mapped while paused, but not one after it
+        mapper.pause(2_000).unwrap();
+        assert!(mapper.is_paused());
+        assert_eq!(mapper.map(1_900), Ok(900));
+        assert_eq!(mapper.map(2_000), Ok(1_000));
+        assert_eq!(mapper.map(2_001), Err(TimeError::Paused));
+        assert_eq!(mapper.pause(2_500), Err(TimeError::Paused));
+
+        // Media time continues from the pause
+        mapper.resume(5_000).unwrap();
+        assert_eq!(mapper.paused_ns(), 3_000);
+        assert_eq!(mapper.map(5_000), Ok(1_000));
+        assert_eq!(mapper.map(6_000), Ok(2_000));
+        assert_eq!(mapper.map(4_999), Err(TimeError::Backwards { wall_ns: 4_999, earliest_ns: 5_000 }));
+
+        mapper.pause(7_000).unwrap();
+        mapper.resume(7_000).unwrap();
+        mapper.pause(7_500).unwrap();
+        mapper.resume(10_000).unwrap();
+        assert_eq!(mapper.paused_ns(), 5_500);
+        assert_eq!(mapper.map(10_500), Ok(4_000));
+        assert!(!mapper.is_paused());
+    }
+
+    #[test]
+    fn starts_at_the_first_time_given() {
+        let mut mapper = TimestampMapper::new();
+        assert_eq!(mapper.map(123_456), Ok(0));
+        assert_eq!(mapper.map(123_000), Err(TimeError::Backwards { wall_ns: 123_000, earliest_ns: 123_456 }));
+
+        // A pause before any frame sets the origin too
+        let mut mapper = TimestampMapper::new();
+        mapper.pause(50).unwrap();
+        mapper.resume(80).unwrap();
+        assert_eq!(mapper.map(100), Ok(20));
+    }
+
+    #[test]
+    fn rejects_out_of_order_calls() {
+        let mut mapper = TimestampMapper::starting_at(100);
+        assert_eq!(mapper.resume(200), Err(TimeError::NotPaused));
+        assert_eq!(mapper.pause(99), Err(TimeError::Backwards { wall_ns: 99, earliest_ns: 100 }));
+        mapper.pause(300).unwrap();
+        assert_eq!(mapper.resume(299), Err(TimeError::Backwards { wall_ns: 299, earliest_ns: 300 }));
+        assert!(mapper.is_paused());
+    }
+}
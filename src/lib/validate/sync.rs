@@ -0,0 +1,282 @@
+use crate::mux::TrackNum;
+
+use super::{PairSync, SyncOptions, SyncReport, TrackSync};
+
+/// Follows the drift of each track as frames are written or read, for a [`SyncReport`].
+#[derive(Debug, Default)]
+pub(crate) struct SyncTracker {
+    /// By track number
+    tracks: Vec<TrackState>,
+
+    /// By track numbers, the lower first
+    pairs: Vec<PairState>,
+}
+
+#[derive(Debug)]
+struct TrackState {
+    track_num: TrackNum,
+    frames: u64,
+    start_ns: u64,
+    end_ns: u64,
+    media_ns: u64,
+
+    /// The nominal duration of the last frame, if it had one of its own
+    last_duration_ns: Option<u64>,
+
+    /// The gap between the first two frames, for frames without a nominal duration
+    first_gap_ns: Option<u64>,
+
+    drift_ns: i64,
+    max_drift_ns: i64,
+    trend: Trend,
+}
+
+#[derive(Debug)]
+struct PairState {
+    tracks: (TrackNum, TrackNum),
+    skew_ns: i64,
+    max_skew_ns: i64,
+}
+
+/// A least-squares fit of drift against elapsed time, kept up to date one frame at a time.
+#[derive(Debug, Default)]
+struct Trend {
+    count: f64,
+    mean_x: f64,
+    mean_y: f64,
+    co_moment: f64,
+    x_moment: f64,
+}
+
+impl Trend {
+    fn add(&mut self, x: f64, y: f64) {
+        self.count += 1.0;
+        let dx = x - self.mean_x;
+        self.mean_x += dx / self.count;
+        self.mean_y += (y - self.mean_y) / self.count;
+        self.co_moment += dx * (y - self.mean_y);
+        self.x_moment += dx * (x - self.mean_x);
+    }
+
+    /// The slope of the fit, in parts per million, or 0 until there are two distinct times.
+    fn ppm(&self) -> f64 {
+        if self.x_moment > 0.0 {
+            self.co_moment / self.x_moment * 1e6
+        } else {
+            0.0
+        }
+    }
+}
+
+impl SyncTracker {
+    /// Follows a frame of `track_num` at `timestamp_ns`. Its nominal duration is `duration_ns`, or failing that,
+    /// `default_duration_ns`.
+    pub fn add_frame(
+        &mut self,
+        track_num: TrackNum,
+        timestamp_ns: u64,
+        duration_ns: Option<u64>,
+        default_duration_ns: Option<u64>,
+    ) {
+        let index = match self.tracks.binary_search_by_key(&track_num, |track| track.track_num) {
+            Ok(index) => index,
+            Err(index) => {
+                self.tracks.insert(index, TrackState::new(track_num, timestamp_ns));
+                index
+            },
+        };
+        self.tracks[index].add_frame(timestamp_ns, duration_ns.or(default_duration_ns));
+
+        let drift_ns = self.tracks[index].drift_ns;
+        for other in &self.tracks {
+            if other.track_num == track_num {
+                continue;
+            }
+            let (tracks, skew_ns) = if track_num < other.track_num {
+                ((track_num, other.track_num), drift_ns.saturating_sub(other.drift_ns))
+            } else {
+                ((other.track_num, track_num), other.drift_ns.saturating_sub(drift_ns))
+            };
+            let pair = match self.pairs.binary_search_by_key(&tracks, |pair| pair.tracks) {
+                Ok(index) => &mut self.pairs[index],
+                Err(index) => {
+                    self.pairs.insert(
+                        index,
+                        PairState {
+                            tracks,
+                            skew_ns,
+                            max_skew_ns: skew_ns,
+                        },
+                    );
+                    &mut self.pairs[index]
+                },
+            };
+            pair.skew_ns = skew_ns;
+            if skew_ns.unsigned_abs() > pair.max_skew_ns.unsigned_abs() {
+                pair.max_skew_ns = skew_ns;
+            }
+        }
+    }
+
+    pub fn report(&self, options: &SyncOptions) -> SyncReport {
+        let tracks: Vec<TrackSync> = self
+            .tracks
+            .iter()
+            .map(|track| TrackSync {
+                track_num: track.track_num,
+                frames: track.frames,
+                start_ns: track.start_ns,
+                end_ns: track.end_ns,
+                media_ns: track.media_ns,
+                drift_ns: track.drift_ns,
+                max_drift_ns: track.max_drift_ns,
+                trend_ppm: track.trend.ppm(),
+            })
+            .collect();
+        let trend = |track_num| tracks.iter().find(|track| track.track_num == track_num).map_or(0.0, |t| t.trend_ppm);
+
+        let pairs = self
+            .pairs
+            .iter()
+            .map(|pair| {
+                let trend_ppm = trend(pair.tracks.0) - trend(pair.tracks.1);
+                PairSync {
+                    tracks: pair.tracks,
+                    skew_ns: pair.skew_ns,
+                    max_skew_ns: pair.max_skew_ns,
+                    trend_ppm,
+                    out_of_sync: pair.max_skew_ns.unsigned_abs() > options.max_skew_ns
+                        || trend_ppm.abs() > options.max_trend_ppm,
+                }
+            })
+            .collect();
+        SyncReport { tracks, pairs }
+    }
+}
+
+impl TrackState {
+    fn new(track_num: TrackNum, start_ns: u64) -> Self {
+        Self {
+            track_num,
+            frames: 0,
+            start_ns,
+            end_ns: start_ns,
+            media_ns: 0,
+            last_duration_ns: None,
+            first_gap_ns: None,
+            drift_ns: 0,
+            max_drift_ns: 0,
+            trend: Trend::default(),
+        }
+    }
+
+    fn add_frame(&mut self, timestamp_ns: u64, duration_ns: Option<u64>) {
+        if self.frames > 0 {
+            let first_gap_ns = *self.first_gap_ns.get_or_insert(timestamp_ns.saturating_sub(self.end_ns));
+            self.media_ns = self.media_ns.saturating_add(self.last_duration_ns.unwrap_or(first_gap_ns));
+        }
+        self.frames += 1;
+        self.end_ns = timestamp_ns;
+        self.last_duration_ns = duration_ns;
+
+        let elapsed_ns = timestamp_ns.saturating_sub(self.start_ns);
+        let drift_ns = i128::from(elapsed_ns) - i128::from(self.media_ns);
+        self.drift_ns = drift_ns.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64;
+        if self.drift_ns.unsigned_abs() > self.max_drift_ns.unsigned_abs() {
+            self.max_drift_ns = self.drift_ns;
+        }
+        self.trend.add(elapsed_ns as f64, self.drift_ns as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames of `duration_ns` each, whose timestamps run `ppm` parts per million fast.
+    fn add_frames(tracker: &mut SyncTracker, track_num: TrackNum, duration_ns: u64, count: u64, ppm: u64) {
+        for i in 0..count {
+            let timestamp_ns = i * (duration_ns + duration_ns * ppm / 1_000_000);
+            tracker.add_frame(track_num, timestamp_ns, Some(duration_ns), None);
+        }
+    }
+
+    #[test]
+    fn detects_drift() {
+        // Ten minutes of video at 25fps and 20ms audio frames, whose timestamps run 500ppm fast
+        let mut tracker = SyncTracker::default();
+        add_frames(&mut tracker, 1, 40_000_000, 15_000, 0);
+        add_frames(&mut tracker, 2, 20_000_000, 30_000, 500);
+        let report = tracker.report(&SyncOptions::default());
+
+        let [video, audio] = report.tracks[..] else {
+            panic!("{report:?}");
+        };
+        assert_eq!((video.drift_ns, video.trend_ppm), (0, 0.0));
+        assert_eq!((audio.frames, audio.media_ns), (30_000, 29_999 * 20_000_000));
+        // 600s at 500ppm is 300ms
+        assert_eq!(audio.drift_ns, 29_999 * 10_000);
+        // The trend is of the elapsed time, which is 500ppm longer than the media time
+        assert!((audio.trend_ppm - 499.75).abs() < 0.01, "{audio:?}");
+
+        let [pair] = report.pairs[..] else {
+            panic!("{report:?}");
+        };
+        assert_eq!(pair.tracks, (1, 2));
+        assert!((pair.trend_ppm + 499.75).abs() < 0.01, "{pair:?}");
+        // All the video was added first, so the skew is only ever the audio's drift
+        assert_eq!((pair.skew_ns, pair.max_skew_ns), (-299_990_000, -299_990_000));
+        assert!(pair.out_of_sync && !report.is_in_sync());
+        assert_eq!(report.to_string(), "Tracks 1 and 2: skew -300.0ms (at most -300.0ms), trend -500ppm, out of sync");
+    }
+
+    #[test]
+    fn tolerates_drift_within_the_options() {
+        let mut tracker = SyncTracker::default();
+        add_frames(&mut tracker, 1, 40_000_000, 1500, 0);
+        add_frames(&mut tracker, 2, 20_000_000, 3000, 50);
+        let report = tracker.report(&SyncOptions::default());
+        assert!(report.is_in_sync(), "{report}");
+
+        let strict = SyncOptions {
+            max_trend_ppm: 10.0,
+            ..SyncOptions::default()
+        };
+        assert!(!tracker.report(&strict).is_in_sync());
+    }
+
+    #[test]
+    fn falls_back_to_the_first_gap() {
+        // Without durations, frames last as long as the first gap, so only a change of pace is drift
+        let mut tracker = SyncTracker::default();
+        for i in 0..100 {
+            let timestamp_ns = if i < 50 { i * 20_000_000 } else { i * 21_000_000 - 50_000_000 };
+            tracker.add_frame(1, timestamp_ns, None, None);
+        }
+        let track = tracker.report(&SyncOptions::default()).tracks[0];
+        assert_eq!(track.drift_ns, 49 * 1_000_000);
+        assert!(track.trend_ppm > 0.0, "{track:?}");
+
+        // Default durations are used for frames without their own
+        let mut tracker = SyncTracker::default();
+        for i in 0..100 {
+            tracker.add_frame(1, i * 20_000_000, (i % 2 == 0).then_some(20_000_000), Some(19_000_000));
+        }
+        assert_eq!(tracker.report(&SyncOptions::default()).tracks[0].drift_ns, 49 * 1_000_000);
+    }
+
+    #[test]
+    fn reports_the_largest_skew() {
+        let mut tracker = SyncTracker::default();
+        tracker.add_frame(2, 0, Some(20_000_000), None);
+        tracker.add_frame(1, 0, Some(40_000_000), None);
+        // A late audio frame, which the next catches up on
+        tracker.add_frame(2, 80_000_000, Some(20_000_000), None);
+        tracker.add_frame(2, 40_000_000, Some(20_000_000), None);
+        let report = tracker.report(&SyncOptions::default());
+        assert_eq!(report.tracks.iter().map(|track| track.track_num).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(report.tracks[1].max_drift_ns, 60_000_000);
+        assert_eq!((report.pairs[0].skew_ns, report.pairs[0].max_skew_ns), (0, -60_000_000));
+        assert!(!report.is_in_sync());
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::demux::ebml::{self, Element};
+use crate::demux::{DemuxError, MkvRead};
+use crate::mux::OpusHead;
+
+use super::{Finding, Rule, Severity, ValidationOptions, ValidationReport};
+
+const EBML_ID: u32 = 0x1A45_DFA3;
+const DOC_TYPE_ID: u32 = 0x4282;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const INFO_ID: u32 = 0x1549_A966;
+const TIMECODE_SCALE_ID: u32 = 0x2A_D7B1;
+const DURATION_ID: u32 = 0x4489;
+const TRACKS_ID: u32 = 0x1654_AE6B;
+const TRACK_ENTRY_ID: u32 = 0xAE;
+const TRACK_NUMBER_ID: u32 = 0xD7;
+const TRACK_TYPE_ID: u32 = 0x83;
+const CODEC_ID_ID: u32 = 0x86;
+const CODEC_PRIVATE_ID: u32 = 0x63A2;
+const CODEC_DELAY_ID: u32 = 0x56AA;
+const DEFAULT_DURATION_ID: u32 = 0x23_E383;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const TIMECODE_ID: u32 = 0xE7;
+const SIMPLE_BLOCK_ID: u32 = 0xA3;
+const BLOCK_GROUP_ID: u32 = 0xA0;
+const BLOCK_ID: u32 = 0xA1;
+const BLOCK_DURATION_ID: u32 = 0x9B;
+const REFERENCE_BLOCK_ID: u32 = 0xFB;
+const CUES_ID: u32 = 0x1C53_BB6B;
+const CUE_POINT_ID: u32 = 0xBB;
+const CUE_TIME_ID: u32 = 0xB3;
+const CUE_TRACK_POSITIONS_ID: u32 = 0xB7;
+const CUE_CLUSTER_POSITION_ID: u32 = 0xF1;
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+/// The codecs that the WebM specification allows.
+const WEBM_CODECS: &[&str] = &["V_VP8", "V_VP9", "V_AV1", "A_OPUS", "A_VORBIS"];
+
+/// Checks a WebM file read from `source` against a set of rules, reporting every problem found rather than stopping
+/// at the first.
+///
+/// The checks are:
+/// - The DocType is `webm` (or `matroska`, if allowed in `options`), and every track uses a codec that WebM allows.
+/// - Every Opus track's `CodecDelay` is the pre-skip of its `OpusHead`.
+/// - Cluster timecodes never decrease, and neither do frame timestamps within a track.
+/// - Every cue point points at the start of a cluster, and the file has cues at all.
+/// - Each cluster starts with a keyframe on every video track with frames in it.
+/// - There are no gaps or overlaps between consecutive frames of a track beyond the limits set in `options`.
+/// - The `Duration` is present, and matches the end of the last frame within the tolerance set in `options`.
+///
+/// The file is read directly as EBML rather than through a [`Demuxer`](crate::demux::Demuxer), so that files the
+/// demuxer would reject can still be checked as far as possible. If the file cannot be parsed past some point, that is
+/// reported as a [`Rule::Structure`] error, along with everything found before it.
+pub fn validate<R: MkvRead>(mut source: R, options: ValidationOptions) -> ValidationReport {
+    let mut validator = Validator {
+        source: &mut source,
+        options,
+        report: ValidationReport::default(),
+        doc_type: String::new(),
+        timecode_scale: DEFAULT_TIMECODE_SCALE,
+        tracks: HashMap::new(),
+        last_cluster_timecode: None,
+        cluster_positions: HashSet::new(),
+        content_end: None,
+    };
+
+    if let Err(err) = validator.check_file() {
+        let message = format!("Failed to parse the file: {err}");
+        validator.report.add(Severity::Error, Rule::Structure, None, message);
+    }
+    validator.report
+}
+
+impl ValidationReport {
+    fn add(&mut self, severity: Severity, rule: Rule, offset: Option<u64>, message: String) {
+        self.findings.push(Finding {
+            severity,
+            rule,
+            offset,
+            message,
+        });
+    }
+}
+
+/// What is known about a track while checking its frames.
+struct TrackState {
+    is_video: bool,
+    default_duration_ns: Option<u64>,
+
+    /// The timestamp and end of the previous frame.
+    last_frame: Option<(u64, u64)>,
+}
+
+/// The header of a `Block` or `SimpleBlock`.
+struct BlockHeader {
+    track_num: u64,
+    relative_timecode: i16,
+    flags: u8,
+}
+
+struct Validator<'a, R> {
+    source: &'a mut R,
+    options: ValidationOptions,
+    report: ValidationReport,
+
+    doc_type: String,
+    timecode_scale: u64,
+    tracks: HashMap<u64, TrackState>,
+
+    last_cluster_timecode: Option<u64>,
+    /// The positions of all clusters, relative to the start of the segment's payload (as cue points give them).
+    cluster_positions: HashSet<u64>,
+    /// The end of the last frame, if there were any.
+    content_end: Option<u64>,
+}
+
+impl<R: MkvRead> Validator<'_, R> {
+    fn check_file(&mut self) -> Result<(), DemuxError> {
+        let len = self.source.len().map_err(DemuxError::Io)?;
+        let top_level = ebml::children(self.source, 0, len)?;
+
+        let Some(header) = top_level.first().filter(|element| element.id == EBML_ID) else {
+            let message = "The file does not start with an EBML header".to_string();
+            self.report.add(Severity::Error, Rule::Structure, Some(0), message);
+            return Ok(());
+        };
+        self.check_header(header)?;
+
+        let Some(segment) = top_level.iter().find(|element| element.id == SEGMENT_ID) else {
+            let message = "The file has no Segment".to_string();
+            self.report.add(Severity::Error, Rule::Structure, None, message);
+            return Ok(());
+        };
+        self.check_segment(segment)
+    }
+
+    fn check_header(&mut self, header: &Element) -> Result<(), DemuxError> {
+        // The DocType defaults to matroska if absent
+        self.doc_type = "matroska".to_string();
+        for child in ebml::children(self.source, header.pos, header.end())? {
+            if child.id == DOC_TYPE_ID {
+                self.doc_type = ebml::read_string(self.source, &child)?;
+            }
+        }
+
+        let allowed = self.doc_type == "webm" || (self.options.allow_matroska && self.doc_type == "matroska");
+        if !allowed {
+            let message = format!("The DocType is {:?} rather than \"webm\"", self.doc_type);
+            self.report.add(Severity::Error, Rule::DocType, Some(header.start), message);
+        }
+        Ok(())
+    }
+
+    fn check_segment(&mut self, segment: &Element) -> Result<(), DemuxError> {
+        let children = ebml::children(self.source, segment.pos, segment.end())?;
+
+        // Clusters can only be checked once the timecode scale and tracks are known, and cues once every cluster's
+        // position is, whatever order the elements are in.
+        let mut duration = None;
+        for child in &children {
+            match child.id {
+                INFO_ID => duration = self.check_info(child)?,
+                TRACKS_ID => self.check_tracks(child)?,
+                _ => {},
+            }
+        }
+
+        let mut clusters: Vec<Element> = children.iter().filter(|child| child.id == CLUSTER_ID).copied().collect();
+        clusters.reverse();
+        while let Some(cluster) = clusters.pop() {
+            self.cluster_positions.insert(cluster.start - segment.pos);
+            self.check_cluster(&cluster, &mut clusters)?;
+        }
+
+        let cues: Vec<&Element> = children.iter().filter(|child| child.id == CUES_ID).collect();
+        for cues in &cues {
+            self.check_cues(cues)?;
+        }
+        if cues.is_empty() && !self.cluster_positions.is_empty() {
+            let message = "The file has no Cues, so seeking in it will be slow".to_string();
+            self.report.add(Severity::Warning, Rule::Cues, None, message);
+        }
+
+        self.check_duration(duration);
+        Ok(())
+    }
+
+    /// Reads the timecode scale, and returns the `Duration` in nanoseconds.
+    fn check_info(&mut self, info: &Element) -> Result<Option<f64>, DemuxError> {
+        let mut duration = None;
+        for child in ebml::children(self.source, info.pos, info.end())? {
+            match child.id {
+                TIMECODE_SCALE_ID => self.timecode_scale = ebml::read_uint(self.source, &child)?,
+                DURATION_ID => duration = Some(ebml::read_float(self.source, &child)?),
+                _ => {},
+            }
+        }
+        Ok(duration.map(|duration| duration * self.timecode_scale as f64))
+    }
+
+    fn check_tracks(&mut self, tracks: &Element) -> Result<(), DemuxError> {
+        for entry in ebml::children(self.source, tracks.pos, tracks.end())? {
+            if entry.id != TRACK_ENTRY_ID {
+                continue;
+            }
+
+            let mut track_num = 0;
+            let mut track_type = 0;
+            let mut codec_id = String::new();
+            let mut default_duration_ns = None;
+            let mut codec_private = Vec::new();
+            let mut codec_delay_ns = 0;
+            for child in ebml::children(self.source, entry.pos, entry.end())? {
+                match child.id {
+                    TRACK_NUMBER_ID => track_num = ebml::read_uint(self.source, &child)?,
+                    TRACK_TYPE_ID => track_type = ebml::read_uint(self.source, &child)?,
+                    CODEC_ID_ID => codec_id = ebml::read_string(self.source, &child)?,
+                    DEFAULT_DURATION_ID => default_duration_ns = Some(ebml::read_uint(self.source, &child)?),
+                    CODEC_PRIVATE_ID => codec_private = ebml::read_bytes(self.source, &child)?,
+                    CODEC_DELAY_ID => codec_delay_ns = ebml::read_uint(self.source, &child)?,
+                    _ => {},
+                }
+            }
+
+            if self.doc_type != "matroska" && !WEBM_CODECS.contains(&codec_id.as_str()) {
+                let message = format!("Track {track_num} uses the codec {codec_id:?}, which WebM does not allow");
+                self.report.add(Severity::Error, Rule::Codec, Some(entry.start), message);
+            }
+            let head = OpusHead::parse(&codec_private).filter(|_| codec_id == "A_OPUS");
+            if let Some(pre_skip_ns) = head.map(OpusHead::codec_delay_ns).filter(|&ns| ns != codec_delay_ns) {
+                let message = format!(
+                    "Track {track_num} has a CodecDelay of {codec_delay_ns}ns, but its OpusHead has a pre-skip of \
+                     {pre_skip_ns}ns"
+                );
+                self.report.add(Severity::Warning, Rule::CodecDelay, Some(entry.start), message);
+            }
+            self.tracks.insert(
+                track_num,
+                TrackState {
+                    is_video: track_type == TRACK_TYPE_VIDEO,
+                    default_duration_ns,
+                    last_frame: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the frames of `cluster`. Clusters of unknown size extend to the end of the segment, and so appear to
+    /// contain the clusters after them; these are pushed to `following` instead.
+    fn check_cluster(&mut self, cluster: &Element, following: &mut Vec<Element>) -> Result<(), DemuxError> {
+        let children = ebml::children(self.source, cluster.pos, cluster.end())?;
+        let Some(timecode) = children.iter().find(|child| child.id == TIMECODE_ID) else {
+            let message = "The cluster has no Timecode".to_string();
+            self.report.add(Severity::Error, Rule::Structure, Some(cluster.start), message);
+            return Ok(());
+        };
+        let timecode = ebml::read_uint(self.source, timecode)?;
+
+        if let Some(last) = self.last_cluster_timecode.filter(|&last| timecode < last) {
+            let message = format!("The cluster's timecode of {timecode} is before the previous cluster's, {last}");
+            self.report.add(Severity::Error, Rule::ClusterTimecodes, Some(cluster.start), message);
+        }
+        self.last_cluster_timecode = Some(timecode);
+
+        let mut started_tracks = HashSet::new();
+        let mut nested_clusters = Vec::new();
+        for child in children {
+            match child.id {
+                SIMPLE_BLOCK_ID => {
+                    let header = self.read_block_header(&child)?;
+                    let keyframe = header.flags & 0x80 != 0;
+                    self.check_frame(&child, timecode, &header, keyframe, None, &mut started_tracks);
+                },
+                BLOCK_GROUP_ID => {
+                    let mut block = None;
+                    let mut duration = None;
+                    let mut keyframe = true;
+                    for child in ebml::children(self.source, child.pos, child.end())? {
+                        match child.id {
+                            BLOCK_ID => block = Some(child),
+                            BLOCK_DURATION_ID => duration = Some(ebml::read_uint(self.source, &child)?),
+                            REFERENCE_BLOCK_ID => keyframe = false,
+                            _ => {},
+                        }
+                    }
+                    if let Some(block) = block {
+                        let header = self.read_block_header(&block)?;
+                        self.check_frame(&block, timecode, &header, keyframe, duration, &mut started_tracks);
+                    }
+                },
+                CLUSTER_ID => nested_clusters.push(child),
+                _ => {},
+            }
+        }
+
+        following.extend(nested_clusters.into_iter().rev());
+        Ok(())
+    }
+
+    fn read_block_header(&mut self, block: &Element) -> Result<BlockHeader, DemuxError> {
+        let mut data = [0; 11];
+        let data = &mut data[..block.size.min(11) as usize];
+        ebml::read_exact_at(self.source, block.pos, data)?;
+
+        let track_len = data.first().map_or(9, |byte| byte.leading_zeros() as usize + 1);
+        if track_len > 8 || data.len() < track_len + 3 {
+            return Err(DemuxError::InvalidFile);
+        }
+        let track_num = data[1..track_len].iter().fold(u64::from(data[0] & (0xFF >> track_len)), |value, &byte| {
+            (value << 8) | u64::from(byte)
+        });
+
+        Ok(BlockHeader {
+            track_num,
+            relative_timecode: i16::from_be_bytes([data[track_len], data[track_len + 1]]),
+            flags: data[track_len + 2],
+        })
+    }
+
+    fn check_frame(
+        &mut self,
+        block: &Element,
+        cluster_timecode: u64,
+        header: &BlockHeader,
+        keyframe: bool,
+        duration: Option<u64>,
+        started_tracks: &mut HashSet<u64>,
+    ) {
+        let track_num = header.track_num;
+        let Some(track) = self.tracks.get_mut(&track_num) else {
+            let message = format!("The block is for track {track_num}, which is not in Tracks");
+            self.report.add(Severity::Error, Rule::Structure, Some(block.start), message);
+            return;
+        };
+
+        let timecode = cluster_timecode.saturating_add_signed(i64::from(header.relative_timecode));
+        let timestamp = timecode.saturating_mul(self.timecode_scale);
+        let duration = duration.map(|duration| duration.saturating_mul(self.timecode_scale));
+        let end = timestamp.saturating_add(duration.or(track.default_duration_ns).unwrap_or(0));
+
+        if track.is_video && started_tracks.insert(track_num) && !keyframe {
+            let message = format!("The cluster does not start with a keyframe on video track {track_num}");
+            self.report.add(Severity::Warning, Rule::ClusterKeyframes, Some(block.start), message);
+        }
+
+        if let Some((last_timestamp, last_end)) = track.last_frame {
+            if timestamp < last_timestamp {
+                let message = format!(
+                    "The frame at {timestamp}ns on track {track_num} is before the previous one, at {last_timestamp}ns"
+                );
+                self.report.add(Severity::Error, Rule::Timestamps, Some(block.start), message);
+            } else if timestamp > last_end.saturating_add(self.options.max_gap_ns) {
+                let message = format!("Track {track_num} has a gap of {}ns before this frame", timestamp - last_end);
+                self.report.add(Severity::Warning, Rule::Continuity, Some(block.start), message);
+            } else if last_end > timestamp.saturating_add(self.options.max_overlap_ns) {
+                let message =
+                    format!("This frame overlaps the previous one on track {track_num} by {}ns", last_end - timestamp);
+                self.report.add(Severity::Warning, Rule::Continuity, Some(block.start), message);
+            }
+        }
+        track.last_frame = Some((timestamp, end));
+        self.content_end = Some(self.content_end.map_or(end, |content_end| content_end.max(end)));
+    }
+
+    fn check_cues(&mut self, cues: &Element) -> Result<(), DemuxError> {
+        for point in ebml::children(self.source, cues.pos, cues.end())? {
+            if point.id != CUE_POINT_ID {
+                continue;
+            }
+
+            let mut time = None;
+            let mut positions = Vec::new();
+            for child in ebml::children(self.source, point.pos, point.end())? {
+                match child.id {
+                    CUE_TIME_ID => time = Some(ebml::read_uint(self.source, &child)?),
+                    CUE_TRACK_POSITIONS_ID => {
+                        for child in ebml::children(self.source, child.pos, child.end())? {
+                            if child.id == CUE_CLUSTER_POSITION_ID {
+                                positions.push(ebml::read_uint(self.source, &child)?);
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+
+            for position in positions {
+                if !self.cluster_positions.contains(&position) {
+                    let time = time.map_or_else(|| "?".to_string(), |time| time.to_string());
+                    let message = format!("The cue point at {time} points at {position}, where there is no cluster");
+                    self.report.add(Severity::Error, Rule::Cues, Some(point.start), message);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_duration(&mut self, duration: Option<f64>) {
+        let Some(content_end) = self.content_end else {
+            return;
+        };
+
+        let Some(duration) = duration else {
+            let message = "The file has no Duration".to_string();
+            self.report.add(Severity::Warning, Rule::Duration, None, message);
+            return;
+        };
+        if (duration - content_end as f64).abs() > self.options.duration_tolerance_ns as f64 {
+            let message = format!("The Duration is {duration}ns, but the last frame ends at {content_end}ns");
+            self.report.add(Severity::Warning, Rule::Duration, None, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId, Writer};
+    use std::io::Cursor;
+
+    /// Muxes (is video, timestamp in ms, is keyframe) frames, which must be in timestamp order.
+    fn mux_test_file(frames: &[(bool, u64, bool)], duration: Option<u64>) -> Vec<u8> {
+        let writer = Writer::new(Cursor::new(Vec::new()));
+        let builder = SegmentBuilder::new(writer).unwrap();
+        let (builder, video) = builder.add_video_track(64, 48, VideoCodecId::VP9, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48000, 2, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for &(is_video, timestamp_ms, keyframe) in frames {
+            let timestamp = timestamp_ms * 1_000_000;
+            if is_video {
+                segment.add_frame(video, &[1; 16], timestamp, keyframe).unwrap();
+            } else {
+                segment.add_frame(audio, &[2; 8], timestamp, keyframe).unwrap();
+            }
+        }
+
+        let Ok(writer) = segment.finalize(duration) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer.into_inner().into_inner()
+    }
+
+    /// Video and audio frames every `step_ms`, with every video frame a keyframe.
+    fn regular_frames(count: u64, step_ms: u64) -> Vec<(bool, u64, bool)> {
+        (0..count).flat_map(|i| [(true, i * step_ms, true), (false, i * step_ms, true)]).collect()
+    }
+
+    /// Finds the elements at the end of `path`, starting from the top level.
+    fn find_elements(data: &[u8], path: &[u32]) -> Vec<Element> {
+        let mut source = Cursor::new(data);
+        let mut elements = ebml::children(&mut source, 0, data.len() as u64).unwrap();
+        for (depth, &id) in path.iter().enumerate() {
+            elements.retain(|element| element.id == id);
+            if depth + 1 < path.len() {
+                elements = elements
+                    .iter()
+                    .flat_map(|element| ebml::children(&mut source, element.pos, element.end()).unwrap())
+                    .collect();
+            }
+        }
+        elements
+    }
+
+    fn rules(findings: impl IntoIterator<Item = Finding>) -> Vec<Rule> {
+        findings.into_iter().map(|finding| finding.rule).collect()
+    }
+
+    #[test]
+    fn accepts_muxer_output() {
+        let file = mux_test_file(&regular_frames(20, 10), None);
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        assert_eq!(report.findings, []);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn reports_doc_type_and_codec() {
+        let mut file = mux_test_file(&regular_frames(5, 10), None);
+        let doc_type = find_elements(&file, &[EBML_ID, DOC_TYPE_ID])[0];
+        file[doc_type.pos as usize..doc_type.end() as usize].copy_from_slice(b"wxbm");
+        let codec = find_elements(&file, &[SEGMENT_ID, TRACKS_ID, TRACK_ENTRY_ID, CODEC_ID_ID])[0];
+        file[codec.pos as usize..codec.pos as usize + 5].copy_from_slice(b"V_MJ2");
+
+        let report = validate(Cursor::new(file.clone()), ValidationOptions::default());
+        assert!(!report.is_valid());
+        assert_eq!(rules(report.errors().cloned()), [Rule::DocType, Rule::Codec]);
+        assert_eq!(report.findings[0].offset, Some(0));
+    }
+
+    #[test]
+    fn reports_cluster_timecode_regression() {
+        // A video keyframe every second for over a minute gives several clusters
+        let mut file = mux_test_file(&regular_frames(70, 1000), None);
+        let clusters = find_elements(&file, &[SEGMENT_ID, CLUSTER_ID]);
+        assert!(clusters.len() >= 4);
+        // libwebm holds audio back until the next video frame, so the first two clusters both start at 0
+        let timecodes = find_elements(&file, &[SEGMENT_ID, CLUSTER_ID, TIMECODE_ID]);
+        file[timecodes[3].pos as usize..timecodes[3].end() as usize].fill(0);
+
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        let regression = report.errors().find(|finding| finding.rule == Rule::ClusterTimecodes).unwrap();
+        assert_eq!(regression.offset, Some(clusters[3].start));
+        assert!(report.errors().any(|finding| finding.rule == Rule::Timestamps));
+    }
+
+    #[test]
+    fn reports_dangling_cue() {
+        let mut file = mux_test_file(&regular_frames(20, 10), None);
+        let path = [SEGMENT_ID, CUES_ID, CUE_POINT_ID, CUE_TRACK_POSITIONS_ID, CUE_CLUSTER_POSITION_ID];
+        let position = find_elements(&file, &path)[0];
+        file[position.end() as usize - 1] ^= 1;
+
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        assert_eq!(rules(report.findings), [Rule::Cues]);
+    }
+
+    #[test]
+    fn reports_keyframes_and_gaps() {
+        let frames = [
+            (true, 0, false),
+            (false, 0, true),
+            (true, 10, false),
+            (false, 10, true),
+            (true, 20, true),
+            (false, 20, true),
+            (false, 2500, true),
+        ];
+        let file = mux_test_file(&frames, None);
+
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        assert!(report.is_valid());
+        assert_eq!(rules(report.findings), [Rule::ClusterKeyframes, Rule::Continuity]);
+
+        let options = ValidationOptions {
+            max_gap_ns: 5_000_000_000,
+            ..ValidationOptions::default()
+        };
+        let report = validate(Cursor::new(mux_test_file(&frames, None)), options);
+        assert_eq!(rules(report.findings), [Rule::ClusterKeyframes]);
+    }
+
+    #[test]
+    fn reports_wrong_duration() {
+        let file = mux_test_file(&regular_frames(20, 10), Some(60_000));
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        assert!(report.is_valid());
+        assert_eq!(rules(report.findings), [Rule::Duration]);
+    }
+
+    #[test]
+    fn reports_truncated_file() {
+        let mut file = mux_test_file(&regular_frames(20, 10), None);
+        file.truncate(file.len() / 2);
+        let report = validate(Cursor::new(file), ValidationOptions::default());
+        assert_eq!(rules(report.findings), [Rule::Structure]);
+    }
+}
@@ -0,0 +1,101 @@
+// Describes the signatures of the exported functions and the layouts of the structs they fill, so that the tests in
+// lib.rs can check the hand-written Rust declarations against what the compiler actually built.
+//
+// A signature is described as `(<params>)<return>`. Each scalar is a kind followed by its size in bytes: `b` for
+// bool, `i`/`u` for integers (enums by their underlying type), `f` for floats, and `p` for data pointers. Function
+// pointers are `*` followed by their own signature, and a void return is `v`. E.g. `(p8,u8,*(p8)u8)i4`.
+
+#ifndef WEBM_SYS_ABI_H
+#define WEBM_SYS_ABI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include <string>
+#include <type_traits>
+#include <vector>
+
+namespace abi {
+  template<typename T, bool = std::is_enum<T>::value>
+  struct Underlying { using type = T; };
+  template<typename T>
+  struct Underlying<T, true> { using type = typename std::underlying_type<T>::type; };
+
+  template<typename T>
+  struct Type {
+    using Scalar = typename Underlying<T>::type;
+    static_assert(std::is_arithmetic<Scalar>::value || std::is_pointer<Scalar>::value,
+                  "only scalars and pointers may cross the FFI boundary");
+    static_assert(sizeof(Scalar) < 10, "sizes are described with one digit");
+
+    static void describe(std::string& out) {
+      out += std::is_same<Scalar, bool>::value ? 'b'
+        : std::is_pointer<Scalar>::value ? 'p'
+        : std::is_floating_point<Scalar>::value ? 'f'
+        : std::is_signed<Scalar>::value ? 'i'
+        : 'u';
+      out += static_cast<char>('0' + sizeof(Scalar));
+    }
+  };
+  template<>
+  struct Type<void> {
+    static void describe(std::string& out) { out += 'v'; }
+  };
+
+  template<typename F>
+  struct Signature;
+  template<typename R, typename... Args>
+  struct Signature<R (*)(Args...)> {
+    static void describe(std::string& out) {
+      out += '(';
+      const int expand[] = {0, (Type<Args>::describe(out), out += ',', 0)...};
+      (void)expand;
+      if(sizeof...(Args) != 0) { out.pop_back(); }
+      out += ')';
+      Type<R>::describe(out);
+    }
+  };
+  template<typename R, typename... Args>
+  struct Type<R (*)(Args...)> {
+    static void describe(std::string& out) {
+      out += '*';
+      Signature<R (*)(Args...)>::describe(out);
+    }
+  };
+
+  struct Binding {
+    const char* name;
+    void (*describe)(std::string&);
+  };
+
+  struct Field {
+    const char* type;
+    const char* name;
+    size_t offset;
+    size_t size;
+    size_t type_size;
+  };
+
+  // Describes the `index`th of `bindings`, or returns null past the end. The descriptions are kept in `signatures`.
+  template<size_t N>
+  const char* describe_binding(const Binding (&bindings)[N], std::vector<std::string>& signatures, size_t index,
+                               const char** name_out) {
+    if(index >= N) { return nullptr; }
+
+    if(signatures.empty()) {
+      for(const Binding& binding: bindings) {
+        signatures.emplace_back();
+        binding.describe(signatures.back());
+      }
+    }
+    if(name_out != nullptr) { *name_out = bindings[index].name; }
+    return signatures[index].c_str();
+  }
+}
+
+#define WEBM_ABI_BINDING(name) { #name, &abi::Signature<decltype(&name)>::describe }
+// `type` is named as on the Rust side
+#define WEBM_ABI_FIELD(type, cpp_type, field) \
+  { #type, #field, offsetof(cpp_type, field), sizeof(cpp_type::field), sizeof(cpp_type) }
+
+#endif
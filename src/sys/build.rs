@@ -1,22 +1,198 @@
+use std::env;
+use std::path::PathBuf;
+
+/// The headers the adapters include, relative to the libwebm source root.
+const HEADERS: &[&str] = &[
+    "common/webmids.h",
+    "mkvmuxer/mkvmuxer.h",
+    "mkvmuxer/mkvmuxertypes.h",
+    "mkvmuxer/mkvmuxerutil.h",
+    "mkvmuxer/mkvwriter.h",
+    "mkvparser/mkvparser.h",
+    "mkvparser/mkvreader.h",
+];
+
 fn main() {
     println!("cargo:rerun-if-changed=ffi.cpp");
-    let files = &[
-        "libwebm/mkvmuxer/mkvmuxer.cc",
-        "libwebm/mkvmuxer/mkvwriter.cc",
-        "libwebm/mkvmuxer/mkvmuxerutil.cc",
-        "libwebm/mkvparser/mkvparser.cc",
-        "libwebm/mkvparser/mkvreader.cc",
-        "ffi.cpp",
-    ];
+    println!("cargo:rerun-if-changed=ffi_parser.cpp");
+    println!("cargo:rerun-if-changed=abi.h");
+    println!("cargo:rerun-if-env-changed=WEBM_SYS_USE_SYSTEM_LIBWEBM");
+    println!("cargo:rerun-if-env-changed=WEBM_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=WEBM_INCLUDE_DIR");
+    println!("cargo:rerun-if-env-changed=ANDROID_NDK_HOME");
+    println!("cargo:rerun-if-env-changed=ANDROID_NDK_ROOT");
+    println!("cargo:rerun-if-env-changed=NDK_HOME");
+    println!("cargo:rerun-if-env-changed=ANDROID_API_LEVEL");
+
+    let target = env::var("TARGET").unwrap();
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let mobile = target_os == "android" || target_os == "ios";
+
     let mut c = cc::Build::new();
     c.cpp(true);
     c.warnings(false);
-    c.flag("-fno-rtti");
-    c.flag("-std=gnu++11");
-    c.flag("-fno-exceptions");
-    c.include("libwebm");
-    for &f in files.iter() {
-        c.file(f);
-    }
-    c.compile("libwebmadapter.a");
+    if target_os == "android" {
+        use_ndk_clang(&mut c, &target);
+    }
+    if c.get_compiler().is_like_msvc() {
+        c.flag("/GR-");
+        c.flag("/std:c++14");
+        c.flag("/EHs-c-");
+        c.define("_HAS_EXCEPTIONS", "0");
+        c.define("_CRT_SECURE_NO_WARNINGS", None);
+        c.define("NOMINMAX", None);
+    } else {
+        c.flag("-fno-rtti");
+        c.flag("-fno-exceptions");
+        if mobile {
+            // The NDK and Xcode only ship libc++, whose headers clash with GNU mode in some NDK releases. The sysroot,
+            // and on iOS the minimum version (from IPHONEOS_DEPLOYMENT_TARGET), are set by cc-rs. No bitcode is
+            // embedded: Xcode 14 and later reject it, and Rust's own objects don't carry it either.
+            c.flag("-std=c++14");
+            c.flag("-fvisibility=hidden");
+        } else {
+            c.flag("-std=gnu++11");
+        }
+    }
+    if env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "wasm32") {
+        // Nothing here is shared between threads, and without this every function-local static would need the C++
+        // runtime's thread-aware guards, which pulls in pthreads under Emscripten.
+        c.flag("-fno-threadsafe-statics");
+    }
+    c.file("ffi.cpp");
+    c.file("ffi_parser.cpp");
+
+    if !use_system_libwebm() {
+        let files = &[
+            "libwebm/mkvmuxer/mkvmuxer.cc",
+            "libwebm/mkvmuxer/mkvwriter.cc",
+            "libwebm/mkvmuxer/mkvmuxerutil.cc",
+            "libwebm/mkvparser/mkvparser.cc",
+            "libwebm/mkvparser/mkvreader.cc",
+        ];
+        c.include("libwebm");
+        for &f in files.iter() {
+            c.file(f);
+        }
+        c.compile("libwebmadapter.a");
+        return;
+    }
+
+    let system = probe_system_libwebm();
+    let include = system
+        .include_paths
+        .iter()
+        .flat_map(|path| [path.clone(), path.join("webm")])
+        .find(|path| path.join(HEADERS[1]).is_file())
+        .unwrap_or_else(|| {
+            panic!(
+                "system libwebm: no mkvmuxer/mkvmuxer.h in {:?} (set WEBM_INCLUDE_DIR to the directory holding \
+                 libwebm's mkvmuxer/, mkvparser/ and common/ headers)",
+                system.include_paths
+            )
+        });
+    let missing: Vec<_> = HEADERS.iter().filter(|header| !include.join(header).is_file()).collect();
+    if !missing.is_empty() {
+        panic!("system libwebm in {} lacks headers {:?}; it is too old or incomplete", include.display(), missing);
+    }
+
+    c.include(&include);
+    if let Err(err) = c.try_compile("libwebmadapter.a") {
+        panic!(
+            "system libwebm in {} is incompatible with webm-sys, which needs a libwebm at least as new as its \
+             vendored copy: {err}",
+            include.display()
+        );
+    }
+
+    // The adapter refers to libwebm, so libwebm goes after it on the link line
+    for path in &system.link_paths {
+        println!("cargo:rustc-link-search=native={}", path.display());
+    }
+    for lib in &system.libs {
+        println!("cargo:rustc-link-lib={lib}");
+    }
+}
+
+/// Points cc-rs at the NDK's clang for an Android target, from `ANDROID_NDK_HOME` (or `ANDROID_NDK_ROOT`/`NDK_HOME`),
+/// unless a C++ compiler was given explicitly.
+///
+/// This expects the layout of NDK r19 and later (tested up to r27), with a single clang taking the target and API
+/// level as `--target=<triple><api>`, and `llvm-ar` instead of binutils (which r23 removed). The API level comes from
+/// `ANDROID_API_LEVEL`, defaulting to 21, the oldest that current NDKs support.
+fn use_ndk_clang(c: &mut cc::Build, target: &str) {
+    let explicit = [format!("CXX_{target}"), format!("CXX_{}", target.replace('-', "_"))];
+    if explicit.iter().map(String::as_str).chain(["TARGET_CXX", "CXX"]).any(|var| env::var_os(var).is_some()) {
+        return;
+    }
+    let Some(ndk) = ["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT", "NDK_HOME"].into_iter().find_map(env::var_os) else {
+        return;
+    };
+
+    let host = env::var("HOST").unwrap();
+    let (host_tag, exe) = if host.contains("windows") {
+        ("windows-x86_64", ".exe")
+    } else if host.contains("apple") {
+        // Also used on Apple silicon, as the binaries are universal
+        ("darwin-x86_64", "")
+    } else {
+        ("linux-x86_64", "")
+    };
+    let bin = PathBuf::from(ndk).join("toolchains/llvm/prebuilt").join(host_tag).join("bin");
+    let clang = bin.join(format!("clang++{exe}"));
+    if !clang.is_file() {
+        panic!("{} doesn't exist; ANDROID_NDK_HOME must point to NDK r19 or later", clang.display());
+    }
+
+    let clang_target = match target {
+        "armv7-linux-androideabi" | "thumbv7neon-linux-androideabi" => "armv7a-linux-androideabi",
+        target => target,
+    };
+    let api = env::var("ANDROID_API_LEVEL").unwrap_or_else(|_| "21".to_owned());
+    c.compiler(clang);
+    c.archiver(bin.join(format!("llvm-ar{exe}")));
+    c.flag(format!("--target={clang_target}{api}"));
+}
+
+struct SystemLibwebm {
+    include_paths: Vec<PathBuf>,
+    link_paths: Vec<PathBuf>,
+    libs: Vec<String>,
+}
+
+fn use_system_libwebm() -> bool {
+    env::var_os("CARGO_FEATURE_SYSTEM_LIBWEBM").is_some()
+        || env::var("WEBM_SYS_USE_SYSTEM_LIBWEBM").is_ok_and(|value| value != "0" && !value.is_empty())
+}
+
+/// Finds libwebm from `WEBM_LIB_DIR`/`WEBM_INCLUDE_DIR` if either is set, or else from pkg-config.
+fn probe_system_libwebm() -> SystemLibwebm {
+    let lib_dir = env::var_os("WEBM_LIB_DIR").map(PathBuf::from);
+    let include_dir = env::var_os("WEBM_INCLUDE_DIR").map(PathBuf::from);
+    if lib_dir.is_some() || include_dir.is_some() {
+        let include_dir = include_dir.or_else(|| Some(lib_dir.as_deref()?.parent()?.join("include")));
+        return SystemLibwebm {
+            include_paths: include_dir.into_iter().collect(),
+            link_paths: lib_dir.into_iter().collect(),
+            libs: vec!["webm".into()],
+        };
+    }
+
+    let mut errors = Vec::new();
+    for name in ["libwebm", "webm"] {
+        match pkg_config::Config::new().cargo_metadata(false).probe(name) {
+            Ok(lib) => {
+                return SystemLibwebm {
+                    include_paths: lib.include_paths,
+                    link_paths: lib.link_paths,
+                    libs: lib.libs,
+                }
+            },
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    panic!(
+        "system libwebm: not found with pkg-config, and neither WEBM_LIB_DIR nor WEBM_INCLUDE_DIR is set:\n{}",
+        errors.join("\n")
+    );
 }
@@ -1,14 +1,346 @@
 
-#include "libwebm/mkvmuxer/mkvmuxer.h"
-#include "libwebm/mkvmuxer/mkvmuxertypes.h"
-#include "libwebm/mkvmuxer/mkvmuxerutil.h"
-#include "libwebm/mkvmuxer/mkvwriter.h"
-#include "libwebm/mkvparser/mkvparser.h"
-#include "libwebm/mkvparser/mkvreader.h"
-#include "libwebm/common/webmids.h"
+#include "mkvmuxer/mkvmuxer.h"
+#include "mkvmuxer/mkvmuxertypes.h"
+#include "mkvmuxer/mkvmuxerutil.h"
+#include "mkvmuxer/mkvwriter.h"
+#include "mkvparser/mkvparser.h"
+#include "mkvparser/mkvreader.h"
+#include "common/webmids.h"
+
+#include "abi.h"
 
 #include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
 #include <assert.h>
+#include <cmath>
+#include <new>
+#include <string>
+#include <utility>
+
+// Not in libwebm's list of IDs
+static const uint64_t kMkvFieldOrder = 0x9D;
+static const uint64_t kMkvLanguageBCP47 = 0x22B59D;
+
+// mkvmuxer never writes a track's FlagDefault, FlagEnabled or FlagForced, which Matroska then takes to be 1, 1 and 0,
+// nor its LanguageBCP47 or CodecName. The adapter adds tracks of these classes instead of mkvmuxer's own, which also
+// write each flag once it differs from what a missing one means, and the strings once they have been set.
+template <typename Base> struct FlaggedTrack: public Base {
+  bool flag_default = true;
+  bool flag_enabled = true;
+  bool flag_forced = false;
+  std::string language_bcp47;
+  std::string codec_name;
+
+  explicit FlaggedTrack(unsigned int* seed): Base(seed) {}
+
+  uint64_t PayloadSize() const override { return Base::PayloadSize() + this->AddedSize(); }
+  bool Write(mkvmuxer::IMkvWriter* writer) const override {
+    return Base::Write(writer) && this->WriteAdded(writer);
+  }
+
+protected:
+  // The size of the elements written after the ones mkvmuxer writes, and writing them.
+  uint64_t AddedSize() const {
+    uint64_t size = 0;
+    if(!this->flag_default) {
+      size += mkvmuxer::EbmlElementSize(libwebm::kMkvFlagDefault, static_cast<mkvmuxer::uint64>(0));
+    }
+    if(!this->flag_enabled) {
+      size += mkvmuxer::EbmlElementSize(libwebm::kMkvFlagEnabled, static_cast<mkvmuxer::uint64>(0));
+    }
+    if(this->flag_forced) {
+      size += mkvmuxer::EbmlElementSize(libwebm::kMkvFlagForced, static_cast<mkvmuxer::uint64>(1));
+    }
+    if(!this->language_bcp47.empty()) {
+      size += mkvmuxer::EbmlElementSize(kMkvLanguageBCP47, this->language_bcp47.c_str());
+    }
+    if(!this->codec_name.empty()) {
+      size += mkvmuxer::EbmlElementSize(libwebm::kMkvCodecName, this->codec_name.c_str());
+    }
+    return size;
+  }
+  bool WriteAdded(mkvmuxer::IMkvWriter* writer) const {
+    if(!this->flag_default &&
+       !mkvmuxer::WriteEbmlElement(writer, libwebm::kMkvFlagDefault, static_cast<mkvmuxer::uint64>(0))) {
+      return false;
+    }
+    if(!this->flag_enabled &&
+       !mkvmuxer::WriteEbmlElement(writer, libwebm::kMkvFlagEnabled, static_cast<mkvmuxer::uint64>(0))) {
+      return false;
+    }
+    if(this->flag_forced &&
+       !mkvmuxer::WriteEbmlElement(writer, libwebm::kMkvFlagForced, static_cast<mkvmuxer::uint64>(1))) {
+      return false;
+    }
+    if(!this->language_bcp47.empty() &&
+       !mkvmuxer::WriteEbmlElement(writer, kMkvLanguageBCP47, this->language_bcp47.c_str())) {
+      return false;
+    }
+    return this->codec_name.empty() ||
+           mkvmuxer::WriteEbmlElement(writer, libwebm::kMkvCodecName, this->codec_name.c_str());
+  }
+};
+
+// Adding a track the way mkvmuxer::Segment does needs members it keeps private. An explicit instantiation may name
+// them, and the friend function it defines hands out a pointer to the member named.
+template <typename Tag, typename Tag::Type member> struct Expose {
+  friend typename Tag::Type exposed(Tag) { return member; }
+};
+template <typename Class, typename T> using MemberOf = T Class::*;
+
+// Declares `Tag`, for which `object->*exposed(Tag())` names the member `member` of `Class`, of type `...`.
+#define EXPOSE(Tag, Class, member, ...)          \
+  struct Tag {                                   \
+    typedef MemberOf<Class, __VA_ARGS__> Type;   \
+    friend Type exposed(Tag);                    \
+  };                                             \
+  template struct Expose<Tag, &Class::member>
+
+EXPOSE(SegmentTracks, mkvmuxer::Segment, tracks_, mkvmuxer::Tracks);
+EXPOSE(SegmentSeed, mkvmuxer::Segment, seed_, unsigned int);
+EXPOSE(SegmentHasVideo, mkvmuxer::Segment, has_video_, bool);
+EXPOSE(TracksTrackEntries, mkvmuxer::Tracks, track_entries_, mkvmuxer::Track**);
+EXPOSE(TracksTrackEntriesSize, mkvmuxer::Tracks, track_entries_size_, uint32_t);
+
+// Writing a frame the way Segment::AddGenericFrame and Cluster::AddFrame do, for mux_segment_add_referenced_frame
+typedef uint64_t PerTrack[mkvmuxer::kMaxTrackNumber];
+EXPOSE(SegmentCheckHeaderInfo, mkvmuxer::Segment, CheckHeaderInfo, bool());
+EXPOSE(SegmentDoNewClusterProcessing, mkvmuxer::Segment, DoNewClusterProcessing, bool(uint64_t, uint64_t, bool));
+EXPOSE(SegmentClusterList, mkvmuxer::Segment, cluster_list_, mkvmuxer::Cluster**);
+EXPOSE(SegmentClusterListSize, mkvmuxer::Segment, cluster_list_size_, int32_t);
+EXPOSE(SegmentForceNewCluster, mkvmuxer::Segment, force_new_cluster_, bool);
+EXPOSE(SegmentNewCuepoint, mkvmuxer::Segment, new_cuepoint_, bool);
+EXPOSE(SegmentLastBlockDuration, mkvmuxer::Segment, last_block_duration_, uint64_t);
+EXPOSE(SegmentLastTimestamp, mkvmuxer::Segment, last_timestamp_, uint64_t);
+EXPOSE(SegmentLastTrackTimestamp, mkvmuxer::Segment, last_track_timestamp_, PerTrack);
+EXPOSE(SegmentTrackFramesWritten, mkvmuxer::Segment, track_frames_written_, PerTrack);
+EXPOSE(ClusterPreWriteBlock, mkvmuxer::Cluster, PreWriteBlock, bool());
+EXPOSE(ClusterPostWriteBlock, mkvmuxer::Cluster, PostWriteBlock, void(uint64_t));
+EXPOSE(ClusterWriter, mkvmuxer::Cluster, writer_, mkvmuxer::IMkvWriter*);
+
+// Adds a track of class `Track` to `segment` as the number given, or the lowest free one if it is 0, as
+// Segment::AddVideoTrack and Segment::AddAudioTrack do. Returns null on failure.
+template <typename Track> static Track* add_track(mkvmuxer::Segment* segment, int32_t number) {
+  Track* track = new (std::nothrow) Track(&(segment->*exposed(SegmentSeed())));
+  if(track == nullptr) { return nullptr; }
+  if(!(segment->*exposed(SegmentTracks())).AddTrack(track, number)) {
+    delete track;
+    return nullptr;
+  }
+  return track;
+}
+
+EXPOSE(VideoTrackVideoPayloadSize, mkvmuxer::VideoTrack, VideoPayloadSize, uint64_t() const);
+
+// mkvmuxer has no FlagInterlaced, FieldOrder or AspectRatioType. They belong inside the Video element, whose size
+// VideoTrack::Write works out and checks itself, so a track with any of them set writes its Video element here
+// instead: what mkvmuxer writes, then these.
+static const uint64_t kUndeterminedFieldOrder = 2;
+
+struct FfiVideoTrack: public FlaggedTrack<mkvmuxer::VideoTrack> {
+  // Each is left out while it is the Matroska default
+  uint64_t flag_interlaced = 0;
+  uint64_t field_order = kUndeterminedFieldOrder;
+  uint64_t aspect_ratio_type = 0;
+
+  explicit FfiVideoTrack(unsigned int* seed): FlaggedTrack(seed) {}
+
+  uint64_t PayloadSize() const override {
+    Extra extras[3];
+    const size_t count = this->Extras(extras);
+    if(count == 0) { return FlaggedTrack::PayloadSize(); }
+    const uint64_t size = this->VideoSize(extras, count);
+    return mkvmuxer::Track::PayloadSize() + mkvmuxer::EbmlMasterElementSize(libwebm::kMkvVideo, size) + size +
+           this->AddedSize();
+  }
+  bool Write(mkvmuxer::IMkvWriter* writer) const override;
+
+private:
+  typedef std::pair<uint64_t, mkvmuxer::uint64> Extra;
+
+  // Fills `extras` with the IDs and values of the elements to add to the Video element, returning how many there are
+  size_t Extras(Extra (&extras)[3]) const {
+    size_t count = 0;
+    if(this->flag_interlaced != 0) { extras[count++] = { libwebm::kMkvFlagInterlaced, this->flag_interlaced }; }
+    if(this->field_order != kUndeterminedFieldOrder) { extras[count++] = { kMkvFieldOrder, this->field_order }; }
+    if(this->aspect_ratio_type != 0) { extras[count++] = { libwebm::kMkvAspectRatioType, this->aspect_ratio_type }; }
+    return count;
+  }
+  uint64_t VideoSize(const Extra* extras, size_t count) const {
+    uint64_t size = (this->*exposed(VideoTrackVideoPayloadSize()))();
+    for(size_t i = 0; i < count; i++) { size += mkvmuxer::EbmlElementSize(extras[i].first, extras[i].second); }
+    return size;
+  }
+};
+
+bool FfiVideoTrack::Write(mkvmuxer::IMkvWriter* writer) const {
+  using mkvmuxer::WriteEbmlElement;
+  typedef mkvmuxer::uint64 uint64;
+
+  Extra extras[3];
+  const size_t count = this->Extras(extras);
+  if(count == 0) { return FlaggedTrack::Write(writer); }
+  if(!mkvmuxer::Track::Write(writer)) { return false; }
+
+  const uint64_t size = this->VideoSize(extras, count);
+  if(!mkvmuxer::WriteEbmlMasterElement(writer, libwebm::kMkvVideo, size)) { return false; }
+  const int64_t payload_position = writer->Position();
+  if(payload_position < 0) { return false; }
+
+  // stereo_mode(), alpha_mode(), colour() and projection() aren't const
+  FfiVideoTrack* self = const_cast<FfiVideoTrack*>(this);
+  const uint64_t pixel_width = this->pixel_width() > 0 ? this->pixel_width() : this->width();
+  const uint64_t pixel_height = this->pixel_height() > 0 ? this->pixel_height() : this->height();
+  if(!WriteEbmlElement(writer, libwebm::kMkvPixelWidth, static_cast<uint64>(pixel_width))) { return false; }
+  if(!WriteEbmlElement(writer, libwebm::kMkvPixelHeight, static_cast<uint64>(pixel_height))) { return false; }
+  const std::pair<libwebm::MkvId, uint64_t> optional[] = {
+    { libwebm::kMkvDisplayWidth, this->display_width() },
+    { libwebm::kMkvDisplayHeight, this->display_height() },
+    { libwebm::kMkvPixelCropLeft, this->crop_left() },
+    { libwebm::kMkvPixelCropRight, this->crop_right() },
+    { libwebm::kMkvPixelCropTop, this->crop_top() },
+    { libwebm::kMkvPixelCropBottom, this->crop_bottom() },
+    { libwebm::kMkvStereoMode, self->stereo_mode() },
+    { libwebm::kMkvAlphaMode, self->alpha_mode() },
+  };
+  for(const auto& element: optional) {
+    if(element.second > 0 && !WriteEbmlElement(writer, element.first, static_cast<uint64>(element.second))) {
+      return false;
+    }
+  }
+  if(this->colour_space() && !WriteEbmlElement(writer, libwebm::kMkvColourSpace, this->colour_space())) {
+    return false;
+  }
+  if(this->frame_rate() > 0.0 &&
+     !WriteEbmlElement(writer, libwebm::kMkvFrameRate, static_cast<float>(this->frame_rate()))) {
+    return false;
+  }
+  if(self->colour() && !self->colour()->Write(writer)) { return false; }
+  if(self->projection() && !self->projection()->Write(writer)) { return false; }
+  for(size_t i = 0; i < count; i++) {
+    if(!WriteEbmlElement(writer, extras[i].first, extras[i].second)) { return false; }
+  }
+
+  const int64_t stop_position = writer->Position();
+  if(stop_position < 0 || stop_position - payload_position != static_cast<int64_t>(size)) { return false; }
+  return this->WriteAdded(writer);
+}
+
+// Nor does it have an OutputSamplingFrequency, so a track with one writes its Audio element here, in the order Matroska
+// lists its children.
+struct FfiAudioTrack: public FlaggedTrack<mkvmuxer::AudioTrack> {
+  // Left out while 0, as Matroska takes a missing one to be the SamplingFrequency
+  double output_sample_rate = 0.0;
+
+  explicit FfiAudioTrack(unsigned int* seed): FlaggedTrack(seed) {}
+
+  uint64_t PayloadSize() const override {
+    if(this->output_sample_rate == 0.0) { return FlaggedTrack::PayloadSize(); }
+    const uint64_t size = this->AudioSize();
+    return mkvmuxer::Track::PayloadSize() + mkvmuxer::EbmlMasterElementSize(libwebm::kMkvAudio, size) + size +
+           this->AddedSize();
+  }
+  bool Write(mkvmuxer::IMkvWriter* writer) const override {
+    using mkvmuxer::WriteEbmlElement;
+    typedef mkvmuxer::uint64 uint64;
+
+    if(this->output_sample_rate == 0.0) { return FlaggedTrack::Write(writer); }
+    if(!mkvmuxer::Track::Write(writer)) { return false; }
+
+    const uint64_t size = this->AudioSize();
+    if(!mkvmuxer::WriteEbmlMasterElement(writer, libwebm::kMkvAudio, size)) { return false; }
+    const int64_t payload_position = writer->Position();
+    if(payload_position < 0) { return false; }
+    if(!WriteEbmlElement(writer, libwebm::kMkvSamplingFrequency, static_cast<float>(this->sample_rate())) ||
+       !WriteEbmlElement(writer, libwebm::kMkvOutputSamplingFrequency, static_cast<float>(this->output_sample_rate)) ||
+       !WriteEbmlElement(writer, libwebm::kMkvChannels, static_cast<uint64>(this->channels()))) {
+      return false;
+    }
+    if(this->bit_depth() > 0 &&
+       !WriteEbmlElement(writer, libwebm::kMkvBitDepth, static_cast<uint64>(this->bit_depth()))) {
+      return false;
+    }
+
+    const int64_t stop_position = writer->Position();
+    if(stop_position < 0 || stop_position - payload_position != static_cast<int64_t>(size)) { return false; }
+    return this->WriteAdded(writer);
+  }
+
+private:
+  uint64_t AudioSize() const {
+    using mkvmuxer::EbmlElementSize;
+    uint64_t size = EbmlElementSize(libwebm::kMkvSamplingFrequency, static_cast<float>(this->sample_rate()));
+    size += EbmlElementSize(libwebm::kMkvOutputSamplingFrequency, static_cast<float>(this->output_sample_rate));
+    size += EbmlElementSize(libwebm::kMkvChannels, static_cast<mkvmuxer::uint64>(this->channels()));
+    if(this->bit_depth() > 0) {
+      size += EbmlElementSize(libwebm::kMkvBitDepth, static_cast<mkvmuxer::uint64>(this->bit_depth()));
+    }
+    return size;
+  }
+};
+
+// Writes `frame` as mkvmuxer's WriteBlock writes a BlockGroup, but with a ReferenceBlock for each of the `count`
+// offsets in `references`, in timecode units. mkvmuxer writes at most one, and as the reference's absolute timecode.
+// Returns the size of the BlockGroup, or 0 if writing failed.
+static uint64_t write_referenced_block(mkvmuxer::IMkvWriter* writer, const mkvmuxer::Frame& frame,
+                                       int64_t relative_timecode, uint64_t timecode_scale,
+                                       const int64_t* references, size_t count) {
+  using mkvmuxer::EbmlElementSize;
+  using mkvmuxer::EbmlMasterElementSize;
+  using mkvmuxer::WriteEbmlElement;
+  using mkvmuxer::WriteEbmlMasterElement;
+
+  uint64_t block_more_size = 0;
+  uint64_t block_additions_size = 0;
+  if(frame.additional() != nullptr) {
+    block_more_size = EbmlElementSize(libwebm::kMkvBlockAddID, static_cast<mkvmuxer::uint64>(frame.add_id())) +
+                      EbmlElementSize(libwebm::kMkvBlockAdditional, frame.additional(), frame.additional_length());
+    block_additions_size = EbmlMasterElementSize(libwebm::kMkvBlockMore, block_more_size) + block_more_size;
+  }
+  uint64_t references_size = 0;
+  for(size_t i = 0; i < count; i++) {
+    references_size += EbmlElementSize(libwebm::kMkvReferenceBlock, static_cast<mkvmuxer::int64>(references[i]));
+  }
+  const uint64_t duration = frame.duration() / timecode_scale;
+  const uint64_t duration_size =
+      duration > 0 ? EbmlElementSize(libwebm::kMkvBlockDuration, static_cast<mkvmuxer::uint64>(duration)) : 0;
+
+  // Track number, timecode and flags, which are always 0 in a Block
+  const uint64_t block_payload_size = 4 + frame.length();
+  const uint64_t block_size = EbmlMasterElementSize(libwebm::kMkvBlock, block_payload_size) + block_payload_size;
+  const uint64_t payload_size = block_size +
+                                (block_additions_size > 0
+                                     ? EbmlMasterElementSize(libwebm::kMkvBlockAdditions, block_additions_size) +
+                                           block_additions_size
+                                     : 0) +
+                                references_size + duration_size;
+
+  if(!WriteEbmlMasterElement(writer, libwebm::kMkvBlockGroup, payload_size) ||
+     !WriteEbmlMasterElement(writer, libwebm::kMkvBlock, block_payload_size) ||
+     mkvmuxer::WriteUInt(writer, frame.track_number()) != 0 ||
+     mkvmuxer::SerializeInt(writer, relative_timecode, 2) != 0 || mkvmuxer::SerializeInt(writer, 0, 1) != 0 ||
+     writer->Write(frame.frame(), static_cast<uint32_t>(frame.length())) != 0) {
+    return 0;
+  }
+  if(block_additions_size > 0 &&
+     (!WriteEbmlMasterElement(writer, libwebm::kMkvBlockAdditions, block_additions_size) ||
+      !WriteEbmlMasterElement(writer, libwebm::kMkvBlockMore, block_more_size) ||
+      !WriteEbmlElement(writer, libwebm::kMkvBlockAddID, static_cast<mkvmuxer::uint64>(frame.add_id())) ||
+      !WriteEbmlElement(writer, libwebm::kMkvBlockAdditional, frame.additional(), frame.additional_length()))) {
+    return 0;
+  }
+  for(size_t i = 0; i < count; i++) {
+    if(!WriteEbmlElement(writer, libwebm::kMkvReferenceBlock, static_cast<mkvmuxer::int64>(references[i]))) {
+      return 0;
+    }
+  }
+  if(duration > 0 && !WriteEbmlElement(writer, libwebm::kMkvBlockDuration, static_cast<mkvmuxer::uint64>(duration))) {
+    return 0;
+  }
+  return EbmlMasterElementSize(libwebm::kMkvBlockGroup, payload_size) + payload_size;
+}
 
 extern "C" {
   enum class ResultCode: int32_t {
@@ -20,10 +352,14 @@ extern "C" {
   using TrackNum = uint64_t;
   typedef mkvmuxer::IMkvWriter* MkvWriterPtr;
 
+  static_assert(sizeof(ResultCode) == 4, "ResultCode is an i32 in Rust");
+  static_assert(sizeof(bool) == 1, "Rust's bool is one byte");
+  static_assert(sizeof(size_t) == sizeof(void*), "size_t is a usize in Rust");
+
   struct FfiMkvWriter: public mkvmuxer::IMkvWriter {
   public:
     typedef bool (*WriteFun)(void*, const void*, size_t);
-    typedef int64_t (*GetPositionFun)(void*);
+    typedef uint64_t (*GetPositionFun)(void*);
     typedef bool (*SetPositionFun)(void*, uint64_t);
     typedef void (*ElementStartNotifyFun)(void*, uint64_t, int64_t);
 
@@ -34,13 +370,22 @@ extern "C" {
 
     mutable void* user_data = nullptr;
 
+    // The clusters started so far, and the position of the last one's ID
+    uint64_t clusters = 0;
+    int64_t cluster_start = -1;
+
+    // Why the last failed write or seek failed, until a segment call reports it
+    const char* last_error = nullptr;
+
     FfiMkvWriter() = default;
     virtual ~FfiMkvWriter() = default;
 
     mkvmuxer::int32 Write(const void* buf, uint32_t len) override final {
       assert(this->write_ != nullptr);
 
-      return this->write_(this->user_data, buf, static_cast<size_t>(len)) ? 0 : 1;
+      if(this->write_(this->user_data, buf, static_cast<size_t>(len))) { return 0; }
+      this->last_error = "the write callback failed";
+      return 1;
     }
     mkvmuxer::int64 Position() const override final {
       assert(this->get_position_ != nullptr);
@@ -48,11 +393,15 @@ extern "C" {
       return this->get_position_(this->user_data);
     }
     mkvmuxer::int32 Position(mkvmuxer::int64 pos) override final {
-      if(this->set_position_ == nullptr) { return 1; }
+      if(this->set_position_ == nullptr) {
+        this->last_error = "the writer can't seek";
+        return 1;
+      }
 
       if(this->set_position_(this->user_data, pos)) {
         return 0;
       } else {
+        this->last_error = "the seek callback failed";
         return 1;
       }
     }
@@ -60,6 +409,10 @@ extern "C" {
       return this->set_position_ != nullptr;
     }
     void ElementStartNotify(mkvmuxer::uint64 element_id, mkvmuxer::int64 position) override final {
+      if(element_id == libwebm::kMkvCluster) {
+        this->clusters += 1;
+        this->cluster_start = position;
+      }
       if(this->element_start_notify_ == nullptr) { return; }
 
       this->element_start_notify_(this->user_data, element_id, position);
@@ -91,27 +444,201 @@ extern "C" {
     delete static_cast<FfiMkvWriter*>(writer);
   }
 
+  // The version of mkvmuxer, which it also writes as the MuxingApp. Null pointers are skipped.
+  void mux_libwebm_version(int32_t* major, int32_t* minor, int32_t* build, int32_t* revision) {
+    int32_t version[4] = {0, 0, 0, 0};
+    mkvmuxer::GetVersion(&version[0], &version[1], &version[2], &version[3]);
+
+    if(major != nullptr) { *major = version[0]; }
+    if(minor != nullptr) { *minor = version[1]; }
+    if(build != nullptr) { *build = version[2]; }
+    if(revision != nullptr) { *revision = version[3]; }
+  }
+
+  uint64_t mux_make_uid(unsigned int* seed) {
+    if(seed == nullptr) { return 0; }
+#if defined(_MSC_VER) || defined(__MINGW32__)
+    // MakeUID draws from rand() instead of advancing the seed on Windows, so seed rand() and advance the seed here
+    srand(*seed);
+    *seed = *seed * 1103515245u + 12345u;
+#endif
+    return mkvmuxer::MakeUID(seed);
+  }
+
+  // mkvmuxer keeps its clusters to itself, so a segment remembers its writer, which counts the clusters started
+  // through it. Every writer is an FfiMkvWriter, being made by mux_new_writer.
+  struct FfiSegment: public mkvmuxer::Segment {
+    FfiMkvWriter* writer = nullptr;
+
+    // The last tag added, which mux_segment_add_simple_tag adds to
+    mkvmuxer::Tag* tag = nullptr;
+
+    // Why the last failed call failed, for mux_segment_last_error
+    char last_error[256] = {};
+  };
+
   typedef mkvmuxer::Segment* MuxSegmentPtr;
+
+  // Records why a call on `segment` failed and returns `code`. A failure of the writer during the call is usually the
+  // cause, so it is included.
+  static ResultCode fail(MuxSegmentPtr segment, ResultCode code, const char* message) {
+    FfiSegment* ffi_segment = static_cast<FfiSegment*>(segment);
+    FfiMkvWriter* writer = ffi_segment->writer;
+    if(writer != nullptr && writer->last_error != nullptr) {
+      snprintf(ffi_segment->last_error, sizeof(ffi_segment->last_error), "%s: %s", message, writer->last_error);
+      writer->last_error = nullptr;
+    } else {
+      snprintf(ffi_segment->last_error, sizeof(ffi_segment->last_error), "%s", message);
+    }
+    return code;
+  }
+
+  // Copies why the last failed call on `segment` failed into `buffer`, truncated to fit and nul-terminated, and
+  // returns the full length of the message. This is 0 if no call has failed.
+  size_t mux_segment_last_error(MuxSegmentPtr segment, char* buffer, size_t len) {
+    if(segment == nullptr) { return 0; }
+    const char* message = static_cast<FfiSegment*>(segment)->last_error;
+    const size_t message_len = strlen(message);
+    if(buffer != nullptr && len != 0) {
+      const size_t copied = message_len < len - 1 ? message_len : len - 1;
+      memcpy(buffer, message, copied);
+      buffer[copied] = '\0';
+    }
+    return message_len;
+  }
+
   MuxSegmentPtr mux_new_segment() {
-    return new mkvmuxer::Segment();
+    return new FfiSegment();
   }
   ResultCode mux_initialize_segment(MuxSegmentPtr segment, MkvWriterPtr writer) {
+    if(segment == nullptr || writer == nullptr) { return ResultCode::BadParam; }
+    static_cast<FfiSegment*>(segment)->writer = static_cast<FfiMkvWriter*>(writer);
     bool success = segment->Init(writer);
-    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+    if(!success) { return fail(segment, ResultCode::UnknownLibwebmError, "Init: Segment::Init returned false"); }
+    return ResultCode::Ok;
   }
-  void mux_set_writing_app(MuxSegmentPtr segment, const char *name) {
+  uint64_t mux_segment_cluster_count(MuxSegmentPtr segment) {
+    if(segment == nullptr) { return 0; }
+    const FfiMkvWriter* writer = static_cast<FfiSegment*>(segment)->writer;
+    return writer == nullptr ? 0 : writer->clusters;
+  }
+  // The bytes written to the last cluster so far, from the start of its ID. Fails if no cluster has been started.
+  ResultCode mux_segment_current_cluster_size(MuxSegmentPtr segment, uint64_t* size_out) {
+    if(segment == nullptr || size_out == nullptr) { return ResultCode::BadParam; }
+    const FfiMkvWriter* writer = static_cast<FfiSegment*>(segment)->writer;
+    if(writer == nullptr || writer->cluster_start < 0) {
+      return fail(segment, ResultCode::BadParam, "CurrentClusterSize: no cluster has been started");
+    }
+
+    const int64_t position = writer->Position();
+    if(position < writer->cluster_start) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "CurrentClusterSize: the writer is before the cluster");
+    }
+    *size_out = static_cast<uint64_t>(position - writer->cluster_start);
+    return ResultCode::Ok;
+  }
+  ResultCode mux_set_writing_app(MuxSegmentPtr segment, const char *name) {
+    if(segment == nullptr || name == nullptr) { return ResultCode::BadParam; }
     auto info = segment->GetSegmentInfo();
     info->set_writing_app(name);
+    return ResultCode::Ok;
+  }
+  // Sets the segment's DateUTC, in nanoseconds from 2001-01-01T00:00:00 UTC.
+  ResultCode mux_set_date_utc(MuxSegmentPtr segment, int64_t date_utc) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    segment->GetSegmentInfo()->set_date_utc(date_utc);
+    return ResultCode::Ok;
+  }
+  ResultCode mux_set_timecode_scale(MuxSegmentPtr segment, uint64_t timecode_scale) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(timecode_scale == 0) { return fail(segment, ResultCode::BadParam, "SetTimecodeScale: the scale is 0"); }
+    segment->GetSegmentInfo()->set_timecode_scale(timecode_scale);
+    return ResultCode::Ok;
+  }
+  // A live segment is written for streaming: without cues, and without going back to fill in its size or duration.
+  ResultCode mux_set_live(MuxSegmentPtr segment, bool live) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    segment->set_mode(live ? mkvmuxer::Segment::kLive : mkvmuxer::Segment::kFile);
+    return ResultCode::Ok;
+  }
+  // Starts a new cluster once the current one spans `max_ns`. 0 leaves the duration unbounded.
+  ResultCode mux_segment_set_max_cluster_duration(MuxSegmentPtr segment, uint64_t max_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    segment->set_max_cluster_duration(max_ns);
+    return ResultCode::Ok;
+  }
+  // Starts a new cluster for the next frame, which also writes out any audio held back for a video frame.
+  ResultCode mux_segment_force_new_cluster(MuxSegmentPtr segment) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    segment->ForceNewClusterOnNextFrame();
+    return ResultCode::Ok;
+  }
+  // Sets whether audio frames are held back until the next video frame, so that the audio up to a video keyframe
+  // goes in the keyframe's cluster. Adding a video track turns this on; without it, frames are written in order.
+  ResultCode mux_segment_set_hold_audio(MuxSegmentPtr segment, bool hold) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    segment->*exposed(SegmentHasVideo()) = hold;
+    return ResultCode::Ok;
+  }
+  // Adds a chapter with one title. `language` may be null, for the default of eng.
+  ResultCode mux_segment_add_chapter(MuxSegmentPtr segment, const char* id, uint64_t start_ns, uint64_t end_ns,
+                                     const char* title, const char* language) {
+    if(segment == nullptr || id == nullptr || title == nullptr) { return ResultCode::BadParam; }
+    if(end_ns < start_ns) {
+      return fail(segment, ResultCode::BadParam, "AddChapter: the chapter ends before it starts");
+    }
+    // Chapter times are in nanoseconds whatever the TimecodeScale, but Chapter::set_time divides by it, so scale them
+    // up to match. The scale must therefore be set before any chapters are added.
+    const uint64_t timecode_scale = segment->GetSegmentInfo()->timecode_scale();
+    if(end_ns > UINT64_MAX / timecode_scale) {
+      return fail(segment, ResultCode::BadParam, "AddChapter: the chapter ends too late for the TimecodeScale");
+    }
+
+    mkvmuxer::Chapter* chapter = segment->AddChapter();
+    if(chapter == nullptr) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddChapter: Segment::AddChapter returned null");
+    }
+    if(!chapter->set_id(id)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddChapter: Chapter::set_id returned false");
+    }
+    chapter->set_time(*segment, start_ns * timecode_scale, end_ns * timecode_scale);
+    if(!chapter->add_string(title, language, nullptr)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddChapter: Chapter::add_string returned false");
+    }
+    return ResultCode::Ok;
+  }
+  // Adds an empty tag, to which mux_segment_add_simple_tag adds.
+  ResultCode mux_segment_add_tag(MuxSegmentPtr segment) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Tag* tag = segment->AddTag();
+    if(tag == nullptr) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddTag: Segment::AddTag returned null");
+    }
+    static_cast<FfiSegment*>(segment)->tag = tag;
+    return ResultCode::Ok;
+  }
+  ResultCode mux_segment_add_simple_tag(MuxSegmentPtr segment, const char* name, const char* value) {
+    if(segment == nullptr || name == nullptr || value == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Tag* tag = static_cast<FfiSegment*>(segment)->tag;
+    if(tag == nullptr) { return fail(segment, ResultCode::BadParam, "AddSimpleTag: no tag has been added"); }
+    if(!tag->add_simple_tag(name, value)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddSimpleTag: Tag::add_simple_tag returned false");
+    }
+    return ResultCode::Ok;
   }
   ResultCode mux_finalize_segment(MuxSegmentPtr segment, uint64_t timeCodeDuration) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
     if (timeCodeDuration) {
       segment->set_duration(timeCodeDuration);
     }
     bool success = segment->Finalize();
-    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+    if(!success) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "Finalize: Segment::Finalize returned false");
+    }
+    return ResultCode::Ok;
   }
   void mux_delete_segment(MuxSegmentPtr segment) {
-    delete segment;
+    delete static_cast<FfiSegment*>(segment);
   }
 
   typedef mkvmuxer::Track* MuxTrackPtr;
@@ -127,10 +654,230 @@ extern "C" {
   const uint32_t VP9_CODEC_ID = 1;
   const uint32_t AV1_CODEC_ID = 2;
 
+  // encryption
+  const uint32_t AES_CTR_CIPHER_MODE = mkvmuxer::ContentEncAESSettings::kCTR;
+
   ResultCode mux_segment_set_codec_private(MuxSegmentPtr segment, TrackNum track_num, const uint8_t *data, int len) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(len < 0 || (data == nullptr && len != 0)) {
+      return fail(segment, ResultCode::BadParam, "SetCodecPrivate: invalid data");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if (!track) { return fail(segment, ResultCode::BadParam, "SetCodecPrivate: no track with this number"); }
+    if (!track->SetCodecPrivate(data, len)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "SetCodecPrivate: Track::SetCodecPrivate returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_codec_delay(MuxSegmentPtr segment, TrackNum track_num, uint64_t codec_delay_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
     MuxTrackPtr track = segment->GetTrackByNumber(track_num);
-    if (!track) { return ResultCode::BadParam; }
-    if (!track->SetCodecPrivate(data, len)) { return ResultCode::UnknownLibwebmError; }
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetCodecDelay: no track with this number"); }
+    track->set_codec_delay(codec_delay_ns);
+    return ResultCode::Ok;
+  }
+
+  // Fails if another track of the segment already has `uid`, since a TrackUID must identify one track
+  ResultCode mux_segment_set_track_uid(MuxSegmentPtr segment, TrackNum track_num, uint64_t uid) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetUID: no track with this number"); }
+    if(uid == 0) { return fail(segment, ResultCode::BadParam, "SetUID: a TrackUID can't be 0"); }
+    const mkvmuxer::Tracks& tracks = segment->*exposed(SegmentTracks());
+    for(uint32_t i = 0; i < tracks.track_entries_size(); i++) {
+      const mkvmuxer::Track* other = tracks.GetTrackByIndex(i);
+      if(other != track && other->uid() == uid) {
+        return fail(segment, ResultCode::BadParam, "SetUID: another track has this TrackUID");
+      }
+    }
+    track->set_uid(uid);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_get_track_uid(MuxSegmentPtr segment, TrackNum track_num, uint64_t* uid_out) {
+    if(segment == nullptr || uid_out == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "GetUID: no track with this number"); }
+    *uid_out = track->uid();
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_track_name(MuxSegmentPtr segment, TrackNum track_num, const char* name) {
+    if(segment == nullptr || name == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetName: no track with this number"); }
+    track->set_name(name);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_track_language(MuxSegmentPtr segment, TrackNum track_num, const char* language) {
+    if(segment == nullptr || language == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetLanguage: no track with this number"); }
+    track->set_language(language);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_track_language_bcp47(MuxSegmentPtr segment, TrackNum track_num, const char* language) {
+    if(segment == nullptr || language == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) {
+      return fail(segment, ResultCode::BadParam, "SetLanguageBCP47: no track with this number");
+    }
+    if(track->type() == mkvmuxer::Tracks::kVideo) {
+      static_cast<FfiVideoTrack*>(track)->language_bcp47 = language;
+    } else {
+      static_cast<FfiAudioTrack*>(track)->language_bcp47 = language;
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_output_sample_rate(MuxSegmentPtr segment, TrackNum track_num, double output_sample_rate) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kAudio) {
+      return fail(segment, ResultCode::BadParam, "SetOutputSampleRate: no audio track with this number");
+    }
+    if(!std::isfinite(output_sample_rate) || output_sample_rate <= 0.0) {
+      return fail(segment, ResultCode::BadParam, "SetOutputSampleRate: the rate must be positive and finite");
+    }
+    static_cast<FfiAudioTrack*>(track)->output_sample_rate = output_sample_rate;
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_audio_bit_depth(MuxSegmentPtr segment, TrackNum track_num, uint64_t bit_depth) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kAudio) {
+      return fail(segment, ResultCode::BadParam, "SetBitDepth: no audio track with this number");
+    }
+    if(bit_depth == 0) { return fail(segment, ResultCode::BadParam, "SetBitDepth: the bit depth must not be 0"); }
+    static_cast<mkvmuxer::AudioTrack*>(track)->set_bit_depth(bit_depth);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_codec_name(MuxSegmentPtr segment, TrackNum track_num, const char* name) {
+    if(segment == nullptr || name == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetCodecName: no track with this number"); }
+    if(track->type() == mkvmuxer::Tracks::kVideo) {
+      static_cast<FfiVideoTrack*>(track)->codec_name = name;
+    } else {
+      static_cast<FfiAudioTrack*>(track)->codec_name = name;
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_seek_pre_roll(MuxSegmentPtr segment, TrackNum track_num, uint64_t seek_pre_roll_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetSeekPreRoll: no track with this number"); }
+    track->set_seek_pre_roll(seek_pre_roll_ns);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_default_duration(MuxSegmentPtr segment, TrackNum track_num, uint64_t default_duration_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) {
+      return fail(segment, ResultCode::BadParam, "SetDefaultDuration: no track with this number");
+    }
+    // mkvmuxer leaves a DefaultDuration of 0 out
+    if(default_duration_ns == 0) { return fail(segment, ResultCode::BadParam, "SetDefaultDuration: duration is 0"); }
+    track->set_default_duration(default_duration_ns);
+    return ResultCode::Ok;
+  }
+
+  // Marks the track's frames as encrypted with AES, in whole frames, under the given key ID. mkvmuxer only writes
+  // the CTR cipher mode.
+  ResultCode mux_segment_add_content_encryption(MuxSegmentPtr segment, TrackNum track_num, const uint8_t* key_id,
+                                                size_t key_id_len, uint32_t cipher_mode) {
+    if(segment == nullptr || key_id == nullptr || key_id_len == 0) { return ResultCode::BadParam; }
+    if(cipher_mode != AES_CTR_CIPHER_MODE) {
+      return fail(segment, ResultCode::BadParam, "AddContentEncoding: unsupported cipher mode");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) {
+      return fail(segment, ResultCode::BadParam, "AddContentEncoding: no track with this number");
+    }
+    if(track->content_encoding_entries_size() != 0) {
+      return fail(segment, ResultCode::BadParam, "AddContentEncoding: the track is already encrypted");
+    }
+    if(!track->AddContentEncoding()) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "AddContentEncoding: Track::AddContentEncoding returned false");
+    }
+    mkvmuxer::ContentEncoding* encoding = track->GetContentEncodingByIndex(0);
+    if(encoding == nullptr || !encoding->SetEncryptionID(key_id, key_id_len)) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "AddContentEncoding: ContentEncoding::SetEncryptionID returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  // Every track is added by mux_segment_add_video_track or mux_segment_add_audio_track, so is of one of our classes
+  ResultCode mux_segment_set_track_default(MuxSegmentPtr segment, TrackNum track_num, bool flag_default) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetFlagDefault: no track with this number"); }
+    if(track->type() == mkvmuxer::Tracks::kVideo) {
+      static_cast<FfiVideoTrack*>(track)->flag_default = flag_default;
+    } else {
+      static_cast<FfiAudioTrack*>(track)->flag_default = flag_default;
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_track_enabled(MuxSegmentPtr segment, TrackNum track_num, bool flag_enabled) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetFlagEnabled: no track with this number"); }
+    if(track->type() == mkvmuxer::Tracks::kVideo) {
+      static_cast<FfiVideoTrack*>(track)->flag_enabled = flag_enabled;
+    } else {
+      static_cast<FfiAudioTrack*>(track)->flag_enabled = flag_enabled;
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_track_forced(MuxSegmentPtr segment, TrackNum track_num, bool flag_forced) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return fail(segment, ResultCode::BadParam, "SetFlagForced: no track with this number"); }
+    if(track->type() == mkvmuxer::Tracks::kVideo) {
+      static_cast<FfiVideoTrack*>(track)->flag_forced = flag_forced;
+    } else {
+      static_cast<FfiAudioTrack*>(track)->flag_forced = flag_forced;
+    }
+    return ResultCode::Ok;
+  }
+
+  // Removes the track added last, which must be `track_num`, undoing mux_segment_add_video_track or
+  // mux_segment_add_audio_track. Tracks::AddTrack only ever appends, so this leaves the tracks as they were before.
+  ResultCode mux_segment_remove_track(MuxSegmentPtr segment, TrackNum track_num) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Tracks& tracks = segment->*exposed(SegmentTracks());
+    uint32_t& count = tracks.*exposed(TracksTrackEntriesSize());
+    if(count == 0 || tracks.GetTrackByIndex(count - 1)->number() != track_num) {
+      return fail(segment, ResultCode::BadParam, "RemoveTrack: this isn't the track added last");
+    }
+
+    count -= 1;
+    delete (tracks.*exposed(TracksTrackEntries()))[count];
+    bool has_video = false;
+    for(uint32_t i = 0; i < count; i++) {
+      has_video = has_video || tracks.GetTrackByIndex(i)->type() == mkvmuxer::Tracks::kVideo;
+    }
+    segment->*exposed(SegmentHasVideo()) = has_video;
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_set_cues_track(MuxSegmentPtr segment, TrackNum track_num) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(!segment->CuesTrack(track_num)) {
+      return fail(segment, ResultCode::BadParam, "CuesTrack: no track with this number");
+    }
     return ResultCode::Ok;
   }
 
@@ -144,16 +891,20 @@ extern "C" {
     case VP8_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kVp8CodecId; break;
     case VP9_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kVp9CodecId; break;
     case AV1_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kAv1CodecId; break;
-    default: return ResultCode::BadParam;
+    default: return fail(segment, ResultCode::BadParam, "AddVideoTrack: unknown codec");
     }
 
-    TrackNum track_num = segment->AddVideoTrack(width, height, number);
-    if(track_num == 0) { return ResultCode::UnknownLibwebmError; }
-
-    auto video = static_cast<MuxVideoTrackPtr>(segment->GetTrackByNumber(track_num));
+    FfiVideoTrack* video = add_track<FfiVideoTrack>(segment, number);
+    if(video == nullptr) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddVideoTrack: Tracks::AddTrack returned false");
+    }
+    video->set_type(mkvmuxer::Tracks::kVideo);
     video->set_codec_id(codec_id_str);
+    video->set_width(width);
+    video->set_height(height);
+    segment->*exposed(SegmentHasVideo()) = true;
 
-    *track_num_out = track_num;
+    *track_num_out = video->number();
     return ResultCode::Ok;
   }
   ResultCode mux_segment_add_audio_track(MuxSegmentPtr segment, const int32_t sample_rate,
@@ -165,43 +916,654 @@ extern "C" {
     switch(codec_id) {
     case OPUS_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kOpusCodecId; break;
     case VORBIS_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kVorbisCodecId; break;
-    default: return ResultCode::BadParam;
+    default: return fail(segment, ResultCode::BadParam, "AddAudioTrack: unknown codec");
     }
 
-    const auto track_num = segment->AddAudioTrack(sample_rate, channels, number);
-    if(track_num == 0) { return ResultCode::UnknownLibwebmError; }
-
-    auto audio = static_cast<MuxAudioTrackPtr>(segment->GetTrackByNumber(track_num));
+    FfiAudioTrack* audio = add_track<FfiAudioTrack>(segment, number);
+    if(audio == nullptr) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddAudioTrack: Tracks::AddTrack returned false");
+    }
+    audio->set_type(mkvmuxer::Tracks::kAudio);
     audio->set_codec_id(codec_id_str);
+    audio->set_sample_rate(sample_rate);
+    audio->set_channels(channels);
 
-    *track_num_out = track_num;
+    *track_num_out = audio->number();
+    return ResultCode::Ok;
+  }
+
+  // Finds the Colour of the video track `video_track_num`, adding an empty one if it has none yet, so that the colour
+  // setters each leave what the others set alone. `caller` prefixes the message of a failure.
+  static ResultCode track_colour(MuxSegmentPtr segment, TrackNum video_track_num, const char* caller,
+                                 mkvmuxer::Colour** colour_out) {
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      char message[64];
+      snprintf(message, sizeof(message), "%s: no video track with this number", caller);
+      return fail(segment, ResultCode::BadParam, message);
+    }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+    if(video->colour() == nullptr && !video->SetColour(mkvmuxer::Colour())) {
+      char message[64];
+      snprintf(message, sizeof(message), "%s: VideoTrack::SetColour returned false", caller);
+      return fail(segment, ResultCode::UnknownLibwebmError, message);
+    }
+    *colour_out = video->colour();
     return ResultCode::Ok;
   }
 
   ResultCode mux_set_color(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t bits, uint8_t sampling_horiz, uint8_t sampling_vert, uint8_t color_range) {
-    mkvmuxer::Colour color;
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetColour", &color);
+    if(result != ResultCode::Ok) { return result; }
+
+    color->set_bits_per_channel(bits);
+    color->set_chroma_subsampling_horz(sampling_horiz);
+    color->set_chroma_subsampling_vert(sampling_vert);
+    color->set_range(color_range);
+    return ResultCode::Ok;
+  }
+
+  // Sets the chroma siting of a video track's Colour in each direction, leaving it out where it is 0 (unspecified).
+  ResultCode mux_set_chroma_siting(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t siting_horz,
+                                   uint8_t siting_vert) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(siting_horz > mkvmuxer::Colour::kHalfCsh || siting_vert > mkvmuxer::Colour::kHalfCsv) {
+      return fail(segment, ResultCode::BadParam, "SetChromaSiting: unknown chroma siting");
+    }
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetChromaSiting", &color);
+    if(result != ResultCode::Ok) { return result; }
+
+    const uint64_t not_present = mkvmuxer::Colour::kValueNotPresent;
+    color->set_chroma_siting_horz(siting_horz == mkvmuxer::Colour::kUnspecifiedCsh ? not_present : siting_horz);
+    color->set_chroma_siting_vert(siting_vert == mkvmuxer::Colour::kUnspecifiedCsv ? not_present : siting_vert);
+    return ResultCode::Ok;
+  }
+
+  // Fills `description` with the CICP code points, range and chroma siting of a color description, leaving out a
+  // chroma siting that is 0 (unspecified), and returns whether libwebm can write them.
+  static bool color_description(uint8_t primaries, uint8_t transfer_characteristics, uint8_t matrix_coefficients,
+                                uint8_t color_range, uint8_t siting_horz, uint8_t siting_vert,
+                                mkvmuxer::Colour& description) {
+    const uint64_t not_present = mkvmuxer::Colour::kValueNotPresent;
+    description.set_primaries(primaries);
+    description.set_transfer_characteristics(transfer_characteristics);
+    description.set_matrix_coefficients(matrix_coefficients);
+    description.set_range(color_range);
+    description.set_chroma_siting_horz(siting_horz == mkvmuxer::Colour::kUnspecifiedCsh ? not_present : siting_horz);
+    description.set_chroma_siting_vert(siting_vert == mkvmuxer::Colour::kUnspecifiedCsv ? not_present : siting_vert);
+    return description.Valid();
+  }
+
+  // Whether libwebm can write a color description, so that it can be checked before the track it is for is added.
+  bool mux_color_description_is_valid(uint8_t primaries, uint8_t transfer_characteristics,
+                                      uint8_t matrix_coefficients, uint8_t color_range, uint8_t siting_horz,
+                                      uint8_t siting_vert) {
+    mkvmuxer::Colour description;
+    return color_description(primaries, transfer_characteristics, matrix_coefficients, color_range, siting_horz,
+                             siting_vert, description);
+  }
+
+  // Sets the CICP code points, range and chroma siting of a video track's Colour, leaving out a chroma siting that is
+  // 0 (unspecified). Fails without changing anything if libwebm doesn't know one of the values, since it would then
+  // fail to write the track headers.
+  ResultCode mux_set_color_description(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t primaries,
+                                       uint8_t transfer_characteristics, uint8_t matrix_coefficients,
+                                       uint8_t color_range, uint8_t siting_horz, uint8_t siting_vert) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Colour description;
+    if(!color_description(primaries, transfer_characteristics, matrix_coefficients, color_range, siting_horz,
+                          siting_vert, description)) {
+      return fail(segment, ResultCode::BadParam, "SetColorDescription: a value is not one libwebm can write");
+    }
+
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetColorDescription", &color);
+    if(result != ResultCode::Ok) { return result; }
+
+    color->set_primaries(description.primaries());
+    color->set_transfer_characteristics(description.transfer_characteristics());
+    color->set_matrix_coefficients(description.matrix_coefficients());
+    color->set_range(description.range());
+    color->set_chroma_siting_horz(description.chroma_siting_horz());
+    color->set_chroma_siting_vert(description.chroma_siting_vert());
+    return ResultCode::Ok;
+  }
+
+  // Sets the CICP code points of a video track's Colour, leaving out each one that is 2 (unspecified) and keeping its
+  // range and chroma siting. Fails without changing anything if libwebm doesn't know one of the values.
+  ResultCode mux_set_color_code_points(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t primaries,
+                                       uint8_t transfer_characteristics, uint8_t matrix_coefficients) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    const uint64_t not_present = mkvmuxer::Colour::kValueNotPresent;
+    mkvmuxer::Colour description;
+    description.set_primaries(primaries == mkvmuxer::Colour::kUnspecifiedP ? not_present : primaries);
+    description.set_transfer_characteristics(
+        transfer_characteristics == mkvmuxer::Colour::kUnspecifiedTc ? not_present : transfer_characteristics);
+    description.set_matrix_coefficients(
+        matrix_coefficients == mkvmuxer::Colour::kUnspecifiedMc ? not_present : matrix_coefficients);
+    if(!description.Valid()) {
+      return fail(segment, ResultCode::BadParam, "SetColorCodePoints: a value is not one libwebm can write");
+    }
+
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetColorCodePoints", &color);
+    if(result != ResultCode::Ok) { return result; }
+
+    color->set_primaries(description.primaries());
+    color->set_transfer_characteristics(description.transfer_characteristics());
+    color->set_matrix_coefficients(description.matrix_coefficients());
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_display_size(MuxSegmentPtr segment, TrackNum video_track_num, uint32_t display_width,
+                                  uint32_t display_height) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(display_width == 0 || display_height == 0) {
+      return fail(segment, ResultCode::BadParam, "SetDisplaySize: the display size is zero");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetDisplaySize: no video track with this number");
+    }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+    video->set_display_width(display_width);
+    video->set_display_height(display_height);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_stereo_mode(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t stereo_mode) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetStereoMode: no video track with this number");
+    }
+    if(!static_cast<MuxVideoTrackPtr>(track)->SetStereoMode(stereo_mode)) {
+      return fail(segment, ResultCode::BadParam, "SetStereoMode: WebM doesn't allow this stereo mode");
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_aspect_ratio_type(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t aspect_ratio_type) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetAspectRatioType: no video track with this number");
+    }
+    if(aspect_ratio_type > 2) {
+      return fail(segment, ResultCode::BadParam, "SetAspectRatioType: unknown aspect ratio type");
+    }
+    static_cast<FfiVideoTrack*>(track)->aspect_ratio_type = aspect_ratio_type;
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_interlacing(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t flag_interlaced,
+                                 uint8_t field_order) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetInterlacing: no video track with this number");
+    }
+    if(flag_interlaced > 2) { return fail(segment, ResultCode::BadParam, "SetInterlacing: unknown interlacing"); }
+    switch(field_order) {
+    case 0: case 1: case 2: case 6: case 9: case 14: break;
+    default: return fail(segment, ResultCode::BadParam, "SetInterlacing: unknown field order");
+    }
+    FfiVideoTrack* video = static_cast<FfiVideoTrack*>(track);
+    video->flag_interlaced = flag_interlaced;
+    video->field_order = field_order;
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_projection(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t projection_type,
+                                const uint8_t* private_data, size_t private_data_length, float pose_yaw,
+                                float pose_pitch, float pose_roll) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(projection_type > mkvmuxer::Projection::kMesh) {
+      return fail(segment, ResultCode::BadParam, "SetProjection: unknown projection type");
+    }
+    if((private_data == nullptr) != (private_data_length == 0)) {
+      return fail(segment, ResultCode::BadParam, "SetProjection: the private data is null or empty, but not both");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetProjection: no video track with this number");
+    }
+
+    mkvmuxer::Projection projection;
+    projection.set_type(static_cast<mkvmuxer::Projection::ProjectionType>(projection_type));
+    projection.set_pose_yaw(pose_yaw);
+    projection.set_pose_pitch(pose_pitch);
+    projection.set_pose_roll(pose_roll);
+    if(private_data != nullptr && !projection.SetProjectionPrivate(private_data, private_data_length)) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "SetProjection: Projection::SetProjectionPrivate returned false");
+    }
+    if(!static_cast<MuxVideoTrackPtr>(track)->SetProjection(projection)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "SetProjection: VideoTrack::SetProjection returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  // Sets the FrameRate of a video track, which libwebm writes as a 32-bit float. Fails unless that float is finite and
+  // positive, since libwebm leaves the element out for anything else but zero.
+  ResultCode mux_set_frame_rate(MuxSegmentPtr segment, TrackNum video_track_num, double frame_rate) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    const float written = static_cast<float>(frame_rate);
+    if(!std::isfinite(written) || !(written > 0.0f)) {
+      return fail(segment, ResultCode::BadParam, "SetFrameRate: the frame rate isn't finite and positive");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetFrameRate: no video track with this number");
+    }
+    static_cast<MuxVideoTrackPtr>(track)->set_frame_rate(frame_rate);
+    return ResultCode::Ok;
+  }
 
+  // Marks a video track as having an alpha channel, whose frames carry the alpha plane as BlockAdditional 1: sets
+  // AlphaMode and raises MaxBlockAdditionID to at least 1, which players need to look for the BlockAdditional.
+  ResultCode mux_enable_alpha(MuxSegmentPtr segment, TrackNum video_track_num) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
     MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
-    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) { return ResultCode::BadParam; }
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "EnableAlpha: no video track with this number");
+    }
     auto video = static_cast<MuxVideoTrackPtr>(track);
+    if(!video->SetAlphaMode(mkvmuxer::VideoTrack::kAlpha)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "EnableAlpha: VideoTrack::SetAlphaMode returned false");
+    }
+    video->set_max_block_additional_id(std::max<uint64_t>(video->max_block_additional_id(), 1));
+    return ResultCode::Ok;
+  }
 
-    color.set_bits_per_channel(bits);
-    color.set_chroma_subsampling_horz(sampling_horiz);
-    color.set_chroma_subsampling_vert(sampling_vert);
+  // Sets the PixelCrop of a video track, which players cut off its frames before showing them. Fails if the crop would
+  // leave no pixels in either direction.
+  ResultCode mux_set_pixel_crop(MuxSegmentPtr segment, TrackNum video_track_num, uint32_t top, uint32_t bottom,
+                                uint32_t left, uint32_t right) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetPixelCrop: no video track with this number");
+    }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+    if(uint64_t(top) + bottom >= video->height() || uint64_t(left) + right >= video->width()) {
+      return fail(segment, ResultCode::BadParam, "SetPixelCrop: the crop leaves no pixels");
+    }
+    video->set_crop_top(top);
+    video->set_crop_bottom(bottom);
+    video->set_crop_left(left);
+    video->set_crop_right(right);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_content_light_level(MuxSegmentPtr segment, TrackNum video_track_num, uint16_t max_cll,
+                                         uint16_t max_fall) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetContentLightLevel", &color);
+    if(result != ResultCode::Ok) { return result; }
 
-    color.set_range(color_range);
-    bool success = video->SetColour(color);
+    color->set_max_cll(max_cll);
+    color->set_max_fall(max_fall);
+    return ResultCode::Ok;
+  }
 
-    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+  // Sets the MasteringMetadata of a video track's Colour, keeping its other colour information. `chromaticities` holds
+  // the (x, y) pairs of the red, green and blue primaries and the white point; a pair or a luminance is left out if it
+  // is negative. MasteringMetadata::Valid compares a maximum luminance with a missing minimum's placeholder, so a
+  // maximum can't be written without a minimum.
+  ResultCode mux_set_mastering_metadata(MuxSegmentPtr segment, TrackNum video_track_num, const float* chromaticities,
+                                        float luminance_max, float luminance_min) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(chromaticities == nullptr) {
+      return fail(segment, ResultCode::BadParam, "SetMasteringMetadata: the chromaticities are null");
+    }
+    mkvmuxer::PrimaryChromaticity primaries[4];
+    const mkvmuxer::PrimaryChromaticity* present[4] = {};
+    for(int i = 0; i < 4; i++) {
+      const float x = chromaticities[2 * i];
+      const float y = chromaticities[2 * i + 1];
+      if(x < 0.0f || y < 0.0f) { continue; }
+      primaries[i] = mkvmuxer::PrimaryChromaticity(x, y);
+      present[i] = &primaries[i];
+    }
+    mkvmuxer::MasteringMetadata metadata;
+    if(!metadata.SetChromaticity(present[0], present[1], present[2], present[3])) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "SetMasteringMetadata: MasteringMetadata::SetChromaticity returned false");
+    }
+    if(luminance_max >= 0.0f) { metadata.set_luminance_max(luminance_max); }
+    if(luminance_min >= 0.0f) { metadata.set_luminance_min(luminance_min); }
+    if(!metadata.Valid()) {
+      return fail(segment, ResultCode::BadParam, "SetMasteringMetadata: a value is not one libwebm can write");
+    }
+
+    mkvmuxer::Colour* color = nullptr;
+    const ResultCode result = track_colour(segment, video_track_num, "SetMasteringMetadata", &color);
+    if(result != ResultCode::Ok) { return result; }
+    if(!color->SetMasteringMetadata(metadata)) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "SetMasteringMetadata: Colour::SetMasteringMetadata returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  // Sets the DisplayWidth and DisplayHeight of a video track from the aspect ratio of its pixels, scaling the width of
+  // its frames less any crop and keeping their height. The width is rounded to the nearest even number, halves up.
+  ResultCode mux_set_pixel_aspect_ratio(MuxSegmentPtr segment, TrackNum video_track_num, uint32_t par_num,
+                                        uint32_t par_den) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(par_num == 0 || par_den == 0) {
+      return fail(segment, ResultCode::BadParam, "SetPixelAspectRatio: the pixel aspect ratio is zero");
+    }
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "SetPixelAspectRatio: no video track with this number");
+    }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+
+    const uint64_t crop_width = video->crop_left() + video->crop_right();
+    const uint64_t crop_height = video->crop_top() + video->crop_bottom();
+    if(crop_width >= video->width() || crop_height >= video->height()) {
+      return fail(segment, ResultCode::BadParam, "SetPixelAspectRatio: the crop leaves no pixels");
+    }
+    const uint64_t width = video->width() - crop_width;
+    // Twice the nearest whole number to half the display width, which can't overflow since the frame width and the
+    // ratio both fit in 32 bits
+    const uint64_t display_width = 2 * ((width * par_num + par_den) / (2 * uint64_t(par_den)));
+    if(display_width == 0) {
+      return fail(segment, ResultCode::BadParam, "SetPixelAspectRatio: the display width rounds to zero");
+    }
+    video->set_display_width(display_width);
+    video->set_display_height(video->height() - crop_height);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_add_frame_with_discard_padding(MuxSegmentPtr segment, TrackNum track_num,
+                                                        const uint8_t* frame, const size_t length,
+                                                        const uint64_t timestamp_ns, const bool keyframe,
+                                                        const int64_t discard_padding_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(frame == nullptr || length == 0) {
+      return fail(segment, ResultCode::BadParam, "AddFrameWithDiscardPadding: the frame is empty");
+    }
+    if(segment->GetTrackByNumber(track_num) == nullptr) {
+      return fail(segment, ResultCode::BadParam, "AddFrameWithDiscardPadding: no track with this number");
+    }
+
+    bool success = segment->AddFrameWithDiscardPadding(frame, length, discard_padding_ns, track_num, timestamp_ns,
+                                                       keyframe);
+    if(!success) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "AddFrameWithDiscardPadding: Segment::AddFrameWithDiscardPadding returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_segment_add_generic_frame(MuxSegmentPtr segment, TrackNum track_num,
+                                           const uint8_t* frame, const size_t length,
+                                           const uint64_t timestamp_ns, const bool keyframe,
+                                           const uint8_t* additional, const size_t additional_length,
+                                           const uint64_t add_id, const uint64_t duration_ns,
+                                           const int64_t discard_padding_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(frame == nullptr || length == 0) {
+      return fail(segment, ResultCode::BadParam, "AddGenericFrame: the frame is empty");
+    }
+    if(segment->GetTrackByNumber(track_num) == nullptr) {
+      return fail(segment, ResultCode::BadParam, "AddGenericFrame: no track with this number");
+    }
+    if(additional == nullptr && additional_length != 0) {
+      return fail(segment, ResultCode::BadParam, "AddGenericFrame: the addition is missing");
+    }
+
+    mkvmuxer::Frame generic;
+    if(!generic.Init(frame, length)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddGenericFrame: Frame::Init returned false");
+    }
+    if(additional_length != 0 && !generic.AddAdditionalData(additional, additional_length, add_id)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddGenericFrame: Frame::AddAdditionalData returned false");
+    }
+    generic.set_track_number(track_num);
+    generic.set_timestamp(timestamp_ns);
+    generic.set_is_key(keyframe);
+    // mkvmuxer only writes a BlockDuration if one was set
+    if(duration_ns != 0) { generic.set_duration(duration_ns); }
+    generic.set_discard_padding(discard_padding_ns);
+
+    bool success = segment->AddGenericFrame(&generic);
+    if(!success) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddGenericFrame: Segment::AddGenericFrame returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  // Adds a frame of a video track as a BlockGroup with a ReferenceBlock for each of the `reference_count` frames it
+  // references, given as offsets in nanoseconds from `timestamp_ns`, which may not be after it. Otherwise this is
+  // mux_segment_add_generic_frame for a frame that isn't a keyframe and has no DiscardPadding.
+  ResultCode mux_segment_add_referenced_frame(MuxSegmentPtr segment, TrackNum track_num,
+                                              const uint8_t* frame, const size_t length,
+                                              const uint64_t timestamp_ns,
+                                              const uint8_t* additional, const size_t additional_length,
+                                              const uint64_t add_id, const uint64_t duration_ns,
+                                              const int64_t* references_ns, const size_t reference_count) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+    if(frame == nullptr || length == 0) {
+      return fail(segment, ResultCode::BadParam, "AddReferencedFrame: the frame is empty");
+    }
+    const mkvmuxer::Track* track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) {
+      return fail(segment, ResultCode::BadParam, "AddReferencedFrame: no video track with this number");
+    }
+    if(additional == nullptr && additional_length != 0) {
+      return fail(segment, ResultCode::BadParam, "AddReferencedFrame: the addition is missing");
+    }
+    if(references_ns == nullptr || reference_count == 0) {
+      return fail(segment, ResultCode::BadParam, "AddReferencedFrame: there are no references");
+    }
+
+    // Offsets between the truncated timecodes, so that they lead to the references' blocks
+    const uint64_t timecode_scale = segment->GetSegmentInfo()->timecode_scale();
+    const int64_t timecode = static_cast<int64_t>(timestamp_ns / timecode_scale);
+    std::vector<int64_t> references(reference_count);
+    for(size_t i = 0; i < reference_count; i++) {
+      const int64_t reference_ns = references_ns[i];
+      if(reference_ns > 0 || (reference_ns < 0 && static_cast<uint64_t>(-(reference_ns + 1)) >= timestamp_ns)) {
+        return fail(segment, ResultCode::BadParam, "AddReferencedFrame: a reference is after the frame, or before 0");
+      }
+      references[i] = static_cast<int64_t>((timestamp_ns + reference_ns) / timecode_scale) - timecode;
+    }
+
+    mkvmuxer::Frame block;
+    if(!block.Init(frame, length)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: Frame::Init returned false");
+    }
+    if(additional_length != 0 && !block.AddAdditionalData(additional, additional_length, add_id)) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "AddReferencedFrame: Frame::AddAdditionalData returned false");
+    }
+    block.set_track_number(track_num);
+    block.set_timestamp(timestamp_ns);
+    block.set_is_key(false);
+    if(duration_ns != 0) { block.set_duration(duration_ns); }
+
+    // What Segment::AddGenericFrame does for a video frame, up to adding it to the cluster
+    if(!(segment->*exposed(SegmentCheckHeaderInfo()))()) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: the header couldn't be written");
+    }
+    uint64_t& last_timestamp = segment->*exposed(SegmentLastTimestamp());
+    if(timestamp_ns < last_timestamp) {
+      return fail(segment, ResultCode::BadParam, "AddReferencedFrame: the frame is before the last one");
+    }
+    mkvmuxer::Cluster** clusters = segment->*exposed(SegmentClusterList());
+    const int32_t cluster_count = segment->*exposed(SegmentClusterListSize());
+    if(cluster_count > 0 &&
+       static_cast<uint64_t>(timecode) - clusters[cluster_count - 1]->timecode() > mkvmuxer::kMaxBlockTimecode) {
+      segment->*exposed(SegmentForceNewCluster()) = true;
+    }
+    if(!(segment->*exposed(SegmentDoNewClusterProcessing()))(track_num, timestamp_ns, false)) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: starting a cluster failed");
+    }
+
+    // What Cluster::AddFrame does, for a cluster that doesn't hold back frames to give the last one a duration
+    const int32_t cluster_index = segment->*exposed(SegmentClusterListSize()) - 1;
+    mkvmuxer::Cluster* cluster =
+        cluster_index >= 0 ? (segment->*exposed(SegmentClusterList()))[cluster_index] : nullptr;
+    if(cluster == nullptr || cluster->write_last_frame_with_duration()) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: there is no cluster to write to");
+    }
+    const int64_t relative_timecode = cluster->GetRelativeTimecode(timecode);
+    if(relative_timecode < 0 || relative_timecode > mkvmuxer::kMaxBlockTimecode) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: the frame is outside the cluster");
+    }
+    if(!(cluster->*exposed(ClusterPreWriteBlock()))()) {
+      return fail(segment, ResultCode::UnknownLibwebmError,
+                  "AddReferencedFrame: the cluster header couldn't be written");
+    }
+    const uint64_t size = write_referenced_block(cluster->*exposed(ClusterWriter()), block, relative_timecode,
+                                                 timecode_scale, references.data(), references.size());
+    if(size == 0) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddReferencedFrame: writing the BlockGroup failed");
+    }
+    (cluster->*exposed(ClusterPostWriteBlock()))(size);
+
+    // Frames that aren't keyframes don't get cue points
+    if(segment->cues_track() == track_num) { segment->*exposed(SegmentNewCuepoint()) = false; }
+    last_timestamp = timestamp_ns;
+    (segment->*exposed(SegmentLastTrackTimestamp()))[track_num - 1] = timestamp_ns;
+    segment->*exposed(SegmentLastBlockDuration()) = block.duration();
+    (segment->*exposed(SegmentTrackFramesWritten()))[track_num - 1]++;
+    return ResultCode::Ok;
   }
 
   ResultCode mux_segment_add_frame(MuxSegmentPtr segment, TrackNum track_num,
                              const uint8_t* frame, const size_t length,
                              const uint64_t timestamp_ns, const bool keyframe) {
     if(segment == nullptr) { return ResultCode::BadParam; }
+    if(frame == nullptr || length == 0) { return fail(segment, ResultCode::BadParam, "AddFrame: the frame is empty"); }
+    if(segment->GetTrackByNumber(track_num) == nullptr) {
+      return fail(segment, ResultCode::BadParam, "AddFrame: no track with this number");
+    }
 
     bool success = segment->AddFrame(frame, length, track_num, timestamp_ns, keyframe);
-    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+    if(!success) {
+      return fail(segment, ResultCode::UnknownLibwebmError, "AddFrame: Segment::AddFrame returned false");
+    }
+    return ResultCode::Ok;
+  }
+
+  // Checks that scalars of every type used here cross the FFI boundary intact, as arguments and as fields: each
+  // argument must equal the matching field of `probe`, whose fields are then replaced with values derived from them.
+  struct AbiProbe {
+    uint8_t u8;
+    bool flag;
+    int32_t i32;
+    uint32_t u32;
+    int64_t i64;
+    uint64_t u64;
+    size_t size;
+    float f32;
+    double f64;
+    const uint8_t* ptr;
+  };
+
+  ResultCode mux_abi_probe(AbiProbe* probe, uint8_t u8, bool flag, int32_t i32, uint32_t u32, int64_t i64,
+                           uint64_t u64, size_t size, float f32, double f64, const uint8_t* ptr) {
+    if(probe == nullptr) { return ResultCode::BadParam; }
+    if(probe->u8 != u8 || probe->flag != flag || probe->i32 != i32 || probe->u32 != u32 || probe->i64 != i64 ||
+       probe->u64 != u64 || probe->size != size || probe->f32 != f32 || probe->f64 != f64 || probe->ptr != ptr) {
+      return ResultCode::BadParam;
+    }
+
+    probe->u8 = u8 + 1;
+    probe->flag = !flag;
+    probe->i32 = -i32;
+    probe->u32 = ~u32;
+    probe->i64 = -i64;
+    probe->u64 = ~u64;
+    probe->size = size * 2;
+    probe->f32 = f32 * 2;
+    probe->f64 = -f64;
+    probe->ptr = ptr + 1;
+    return ResultCode::Ok;
+  }
+
+  const char* mux_abi_binding(size_t index, const char** name_out);
+
+  // Every function above, and the ABI helpers themselves
+  static const abi::Binding MUX_BINDINGS[] = {
+    WEBM_ABI_BINDING(mux_new_writer),
+    WEBM_ABI_BINDING(mux_delete_writer),
+    WEBM_ABI_BINDING(mux_libwebm_version),
+    WEBM_ABI_BINDING(mux_make_uid),
+    WEBM_ABI_BINDING(mux_segment_last_error),
+    WEBM_ABI_BINDING(mux_new_segment),
+    WEBM_ABI_BINDING(mux_initialize_segment),
+    WEBM_ABI_BINDING(mux_segment_cluster_count),
+    WEBM_ABI_BINDING(mux_segment_current_cluster_size),
+    WEBM_ABI_BINDING(mux_set_writing_app),
+    WEBM_ABI_BINDING(mux_set_date_utc),
+    WEBM_ABI_BINDING(mux_set_timecode_scale),
+    WEBM_ABI_BINDING(mux_set_live),
+    WEBM_ABI_BINDING(mux_segment_set_max_cluster_duration),
+    WEBM_ABI_BINDING(mux_segment_force_new_cluster),
+    WEBM_ABI_BINDING(mux_segment_set_hold_audio),
+    WEBM_ABI_BINDING(mux_segment_add_chapter),
+    WEBM_ABI_BINDING(mux_segment_add_tag),
+    WEBM_ABI_BINDING(mux_segment_add_simple_tag),
+    WEBM_ABI_BINDING(mux_finalize_segment),
+    WEBM_ABI_BINDING(mux_delete_segment),
+    WEBM_ABI_BINDING(mux_segment_set_codec_private),
+    WEBM_ABI_BINDING(mux_segment_set_codec_delay),
+    WEBM_ABI_BINDING(mux_segment_set_track_uid),
+    WEBM_ABI_BINDING(mux_segment_get_track_uid),
+    WEBM_ABI_BINDING(mux_segment_set_track_name),
+    WEBM_ABI_BINDING(mux_segment_set_track_language),
+    WEBM_ABI_BINDING(mux_segment_set_track_language_bcp47),
+    WEBM_ABI_BINDING(mux_set_output_sample_rate),
+    WEBM_ABI_BINDING(mux_set_audio_bit_depth),
+    WEBM_ABI_BINDING(mux_segment_set_codec_name),
+    WEBM_ABI_BINDING(mux_segment_set_seek_pre_roll),
+    WEBM_ABI_BINDING(mux_segment_set_default_duration),
+    WEBM_ABI_BINDING(mux_segment_set_track_default),
+    WEBM_ABI_BINDING(mux_segment_set_track_enabled),
+    WEBM_ABI_BINDING(mux_segment_set_track_forced),
+    WEBM_ABI_BINDING(mux_segment_add_content_encryption),
+    WEBM_ABI_BINDING(mux_segment_set_cues_track),
+    WEBM_ABI_BINDING(mux_segment_add_video_track),
+    WEBM_ABI_BINDING(mux_segment_add_audio_track),
+    WEBM_ABI_BINDING(mux_segment_remove_track),
+    WEBM_ABI_BINDING(mux_set_color),
+    WEBM_ABI_BINDING(mux_set_chroma_siting),
+    WEBM_ABI_BINDING(mux_color_description_is_valid),
+    WEBM_ABI_BINDING(mux_set_color_description),
+    WEBM_ABI_BINDING(mux_set_color_code_points),
+    WEBM_ABI_BINDING(mux_set_content_light_level),
+    WEBM_ABI_BINDING(mux_set_mastering_metadata),
+    WEBM_ABI_BINDING(mux_set_display_size),
+    WEBM_ABI_BINDING(mux_set_stereo_mode),
+    WEBM_ABI_BINDING(mux_set_aspect_ratio_type),
+    WEBM_ABI_BINDING(mux_set_interlacing),
+    WEBM_ABI_BINDING(mux_set_projection),
+    WEBM_ABI_BINDING(mux_set_frame_rate),
+    WEBM_ABI_BINDING(mux_enable_alpha),
+    WEBM_ABI_BINDING(mux_set_pixel_crop),
+    WEBM_ABI_BINDING(mux_set_pixel_aspect_ratio),
+    WEBM_ABI_BINDING(mux_segment_add_frame_with_discard_padding),
+    WEBM_ABI_BINDING(mux_segment_add_generic_frame),
+    WEBM_ABI_BINDING(mux_segment_add_referenced_frame),
+    WEBM_ABI_BINDING(mux_segment_add_frame),
+    WEBM_ABI_BINDING(mux_abi_probe),
+    WEBM_ABI_BINDING(mux_abi_binding),
+  };
+
+  // Describes the signature of the `index`th function in MUX_BINDINGS, or returns null past the end.
+  const char* mux_abi_binding(size_t index, const char** name_out) {
+    static std::vector<std::string> signatures;
+    return abi::describe_binding(MUX_BINDINGS, signatures, index, name_out);
   }
 
 }
@@ -0,0 +1,1204 @@
+#include "mkvparser/mkvparser.h"
+#include "mkvparser/mkvreader.h"
+#include "common/webmids.h"
+
+#include "abi.h"
+
+#include <stdint.h>
+#include <assert.h>
+
+#include <algorithm>
+#include <utility>
+#include <vector>
+
+extern "C" {
+  enum class ParseResultCode: int32_t {
+    Ok = 0,
+    BadParam = -1,
+    InvalidFile = -2,
+    BufferNotFull = -3,
+  };
+
+  struct FfiMkvReader: public mkvparser::IMkvReader {
+  public:
+    typedef bool (*ReadFun)(void*, uint64_t, size_t, unsigned char*);
+    typedef bool (*LengthFun)(void*, int64_t*, int64_t*);
+
+    ReadFun   read_   = nullptr;
+    LengthFun length_ = nullptr;
+
+    mutable void* user_data = nullptr;
+
+    FfiMkvReader() = default;
+    virtual ~FfiMkvReader() = default;
+
+    int Read(long long position, long length, unsigned char* buffer) override final {
+      assert(this->read_ != nullptr);
+
+      if(position < 0 || length < 0 || (length > 0 && buffer == nullptr)) { return -1; }
+      if(length == 0) { return 0; }
+
+      return this->read_(this->user_data, static_cast<uint64_t>(position),
+                         static_cast<size_t>(length), buffer) ? 0 : -1;
+    }
+    int Length(long long* total, long long* available) override final {
+      assert(this->length_ != nullptr);
+
+      int64_t total_out = -1;
+      int64_t available_out = -1;
+      if(!this->length_(this->user_data, &total_out, &available_out)) { return -1; }
+
+      if(total != nullptr) { *total = total_out; }
+      if(available != nullptr) { *available = available_out; }
+      return 0;
+    }
+  };
+
+  struct ParserBlockAddition {
+    uint64_t id;
+    int64_t pos;
+    int64_t len;
+  };
+
+  struct FfiParser {
+    FfiMkvReader reader;
+    mkvparser::EBMLHeader ebml_header;
+    mkvparser::Segment* segment = nullptr;
+
+    // Payload ranges of the BlockGroups in `groups_cluster`, in file order
+    const mkvparser::Cluster* groups_cluster = nullptr;
+    std::vector<std::pair<long long, long long>> groups;
+
+    // Elements of the BlockGroup of `extras_entry` that mkvparser doesn't keep
+    const mkvparser::BlockEntry* extras_entry = nullptr;
+    std::vector<ParserBlockAddition> additions;
+    bool has_discard_padding = false;
+    int64_t discard_padding = 0;
+
+    FfiParser() = default;
+    ~FfiParser() {
+      delete this->segment;
+    }
+  };
+  typedef FfiParser* ParserPtr;
+
+  static ParseResultCode parse_status(long long status) {
+    if(status == 0) { return ParseResultCode::Ok; }
+    if(status == mkvparser::E_BUFFER_NOT_FULL || status > 0) { return ParseResultCode::BufferNotFull; }
+    return ParseResultCode::InvalidFile;
+  }
+
+  // mkvparser skips some elements we need, so the following helpers walk the raw EBML instead.
+
+  // Finds where the payload of the element at `element_start` begins. Its size may be unknown.
+  static bool element_payload_start(mkvparser::IMkvReader* reader, long long element_start, long long* start_out) {
+    long len = 0;
+    if(mkvparser::ReadID(reader, element_start, len) < 0) { return false; }
+    long long pos = element_start + len;
+    if(mkvparser::ReadUInt(reader, pos, len) < 0) { return false; }
+
+    *start_out = pos + len;
+    return true;
+  }
+
+  // Iterates over the child elements in a range of the file.
+  struct ChildElements {
+    mkvparser::IMkvReader* reader;
+    long long next;
+    long long stop;
+    bool failed = false;
+
+    // The current child
+    long long id = 0;
+    long long pos = 0;
+    long long size = 0;
+
+    ChildElements(mkvparser::IMkvReader* reader_, long long start, long long stop_)
+      : reader(reader_), next(start), stop(stop_) {}
+
+    // Moves to the next child. Returns false at the end of the range, or on error (setting `failed`).
+    bool Next() {
+      if(this->failed || this->next >= this->stop) { return false; }
+
+      this->pos = this->next;
+      if(mkvparser::ParseElementHeader(this->reader, this->pos, this->stop, this->id, this->size) < 0) {
+        this->failed = true;
+        return false;
+      }
+      this->next = this->pos + this->size;
+      return true;
+    }
+  };
+
+  ParserPtr parser_new(FfiMkvReader::ReadFun read, FfiMkvReader::LengthFun length, void* user_data) {
+    if(read == nullptr || length == nullptr) {
+      return nullptr;
+    }
+
+    FfiParser* parser = new FfiParser;
+    parser->reader.read_ = read;
+    parser->reader.length_ = length;
+    parser->reader.user_data = user_data;
+
+    return parser;
+  }
+
+  void parser_delete(ParserPtr parser) {
+    delete parser;
+  }
+
+  // The version of mkvparser, which is versioned separately from mkvmuxer. Null pointers are skipped.
+  void parser_libwebm_version(int32_t* major, int32_t* minor, int32_t* build, int32_t* revision) {
+    int version[4] = {0, 0, 0, 0};
+    mkvparser::GetVersion(version[0], version[1], version[2], version[3]);
+
+    if(major != nullptr) { *major = version[0]; }
+    if(minor != nullptr) { *minor = version[1]; }
+    if(build != nullptr) { *build = version[2]; }
+    if(revision != nullptr) { *revision = version[3]; }
+  }
+
+  // With incomplete input, this returns `BufferNotFull` and may be called again once more is available.
+  ParseResultCode parser_open(ParserPtr parser) {
+    if(parser == nullptr) { return ParseResultCode::BadParam; }
+
+    if(parser->segment == nullptr) {
+      long long pos = 0;
+      long long status = parser->ebml_header.Parse(&parser->reader, pos);
+      if(status != 0) { return parse_status(status); }
+
+      mkvparser::Segment* segment = nullptr;
+      status = mkvparser::Segment::CreateInstance(&parser->reader, pos, segment);
+      if(status != 0 || segment == nullptr) {
+        delete segment;
+        return status == 0 ? ParseResultCode::InvalidFile : parse_status(status);
+      }
+      parser->segment = segment;
+    }
+
+    mkvparser::Segment* segment = parser->segment;
+    const long long status = segment->ParseHeaders();
+    if(status != 0) { return parse_status(status); }
+    if(segment->GetInfo() == nullptr || segment->GetTracks() == nullptr) { return ParseResultCode::InvalidFile; }
+
+    return ParseResultCode::Ok;
+  }
+
+  // Not in libwebm's list of IDs
+  static const long long kLanguageIETFId = 0x22B59D;
+
+  struct ParserTrackInfo {
+    uint64_t number;
+    uint64_t uid;
+    int64_t track_type;
+    const char* codec_id;
+    const uint8_t* codec_private;
+    size_t codec_private_len;
+    uint64_t default_duration;
+    uint64_t codec_delay;
+    uint64_t seek_pre_roll;
+    uint64_t max_block_addition_id;
+    // null if absent
+    const char* name;
+    const char* language;
+    // payload range of the LanguageIETF element, which mkvparser skips; -1 if absent
+    int64_t language_ietf_pos;
+    int64_t language_ietf_len;
+
+    // video only, zero otherwise
+    int64_t width;
+    int64_t height;
+    // the width and height if the track has no DisplayWidth or DisplayHeight
+    int64_t display_width;
+    int64_t display_height;
+
+    // audio only, zero otherwise
+    double sampling_rate;
+    int64_t channels;
+    int64_t bit_depth;
+  };
+
+  uint64_t parser_track_count(ParserPtr parser) {
+    if(parser == nullptr || parser->segment == nullptr) { return 0; }
+    const mkvparser::Tracks* tracks = parser->segment->GetTracks();
+    if(tracks == nullptr) { return 0; }
+
+    return tracks->GetTracksCount();
+  }
+
+  ParseResultCode parser_track_info(ParserPtr parser, uint64_t index, ParserTrackInfo* info_out) {
+    if(parser == nullptr || parser->segment == nullptr || info_out == nullptr) { return ParseResultCode::BadParam; }
+    const mkvparser::Tracks* tracks = parser->segment->GetTracks();
+    if(tracks == nullptr || index >= tracks->GetTracksCount()) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Track* track = tracks->GetTrackByIndex(static_cast<unsigned long>(index));
+    if(track == nullptr) { return ParseResultCode::InvalidFile; }
+
+    ParserTrackInfo info = {};
+    info.number = static_cast<uint64_t>(track->GetNumber());
+    info.uid = track->GetUid();
+    info.track_type = track->GetType();
+    info.codec_id = track->GetCodecId();
+    size_t codec_private_len = 0;
+    info.codec_private = track->GetCodecPrivate(codec_private_len);
+    info.codec_private_len = info.codec_private == nullptr ? 0 : codec_private_len;
+    info.default_duration = track->GetDefaultDuration();
+    info.codec_delay = track->GetCodecDelay();
+    info.seek_pre_roll = track->GetSeekPreRoll();
+    info.name = track->GetNameAsUTF8();
+    info.language = track->GetLanguage();
+    info.language_ietf_pos = -1;
+    info.language_ietf_len = -1;
+
+    long long start = 0;
+    if(!element_payload_start(&parser->reader, track->m_element_start, &start)) { return ParseResultCode::InvalidFile; }
+    ChildElements children(&parser->reader, start, track->m_element_start + track->m_element_size);
+    while(children.Next()) {
+      if(children.id == libwebm::kMkvMaxBlockAdditionID) {
+        const long long max_id = mkvparser::UnserializeUInt(&parser->reader, children.pos, children.size);
+        if(max_id < 0) { return ParseResultCode::InvalidFile; }
+        info.max_block_addition_id = static_cast<uint64_t>(max_id);
+      } else if(children.id == kLanguageIETFId) {
+        info.language_ietf_pos = children.pos;
+        info.language_ietf_len = children.size;
+      }
+    }
+    if(children.failed) { return ParseResultCode::InvalidFile; }
+
+    if(track->GetType() == mkvparser::Track::kVideo) {
+      auto video = static_cast<const mkvparser::VideoTrack*>(track);
+      info.width = video->GetWidth();
+      info.height = video->GetHeight();
+      info.display_width = video->GetDisplayWidth();
+      info.display_height = video->GetDisplayHeight();
+    } else if(track->GetType() == mkvparser::Track::kAudio) {
+      auto audio = static_cast<const mkvparser::AudioTrack*>(track);
+      info.sampling_rate = audio->GetSamplingRate();
+      info.channels = audio->GetChannels();
+      info.bit_depth = audio->GetBitDepth();
+    }
+
+    *info_out = info;
+    return ParseResultCode::Ok;
+  }
+
+  static const mkvparser::Track* find_track(ParserPtr parser, uint64_t track_number) {
+    const mkvparser::Tracks* tracks = parser->segment->GetTracks();
+    if(tracks == nullptr) { return nullptr; }
+    return tracks->GetTrackByNumber(static_cast<long>(track_number));
+  }
+
+  // Integer fields are -1 and float fields are negative if absent.
+  struct ParserColourInfo {
+    int64_t matrix_coefficients;
+    int64_t bits_per_channel;
+    int64_t chroma_subsampling_horz;
+    int64_t chroma_subsampling_vert;
+    int64_t cb_subsampling_horz;
+    int64_t cb_subsampling_vert;
+    int64_t chroma_siting_horz;
+    int64_t chroma_siting_vert;
+    int64_t range;
+    int64_t transfer_characteristics;
+    int64_t primaries;
+    int64_t max_cll;
+    int64_t max_fall;
+
+    bool has_mastering_metadata;
+    // r, g, b and white point, as (x, y) pairs
+    float chromaticities[4][2];
+    float luminance_max;
+    float luminance_min;
+  };
+
+  static int64_t colour_value(long long value) {
+    return value == mkvparser::Colour::kValueNotPresent ? -1 : value;
+  }
+
+  static float mastering_value(float value) {
+    return value == mkvparser::MasteringMetadata::kValueNotPresent ? -1.0f : value;
+  }
+
+  static void chromaticity(const mkvparser::PrimaryChromaticity* in, float out[2]) {
+    out[0] = in == nullptr ? -1.0f : mastering_value(in->x);
+    out[1] = in == nullptr ? -1.0f : mastering_value(in->y);
+  }
+
+  // `*present_out` is false if the track has no Colour element (including all non-video tracks).
+  ParseResultCode parser_track_colour(ParserPtr parser, uint64_t track_number, ParserColourInfo* info_out,
+                                      bool* present_out) {
+    if(parser == nullptr || parser->segment == nullptr || info_out == nullptr || present_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+    const mkvparser::Track* track = find_track(parser, track_number);
+    if(track == nullptr) { return ParseResultCode::BadParam; }
+
+    *present_out = false;
+    if(track->GetType() != mkvparser::Track::kVideo) { return ParseResultCode::Ok; }
+    const mkvparser::Colour* colour = static_cast<const mkvparser::VideoTrack*>(track)->GetColour();
+    if(colour == nullptr) { return ParseResultCode::Ok; }
+
+    ParserColourInfo info = {};
+    info.matrix_coefficients = colour_value(colour->matrix_coefficients);
+    info.bits_per_channel = colour_value(colour->bits_per_channel);
+    info.chroma_subsampling_horz = colour_value(colour->chroma_subsampling_horz);
+    info.chroma_subsampling_vert = colour_value(colour->chroma_subsampling_vert);
+    info.cb_subsampling_horz = colour_value(colour->cb_subsampling_horz);
+    info.cb_subsampling_vert = colour_value(colour->cb_subsampling_vert);
+    info.chroma_siting_horz = colour_value(colour->chroma_siting_horz);
+    info.chroma_siting_vert = colour_value(colour->chroma_siting_vert);
+    info.range = colour_value(colour->range);
+    info.transfer_characteristics = colour_value(colour->transfer_characteristics);
+    info.primaries = colour_value(colour->primaries);
+    info.max_cll = colour_value(colour->max_cll);
+    info.max_fall = colour_value(colour->max_fall);
+
+    const mkvparser::MasteringMetadata* mastering = colour->mastering_metadata;
+    if(mastering != nullptr) {
+      info.has_mastering_metadata = true;
+      chromaticity(mastering->r, info.chromaticities[0]);
+      chromaticity(mastering->g, info.chromaticities[1]);
+      chromaticity(mastering->b, info.chromaticities[2]);
+      chromaticity(mastering->white_point, info.chromaticities[3]);
+      info.luminance_max = mastering_value(mastering->luminance_max);
+      info.luminance_min = mastering_value(mastering->luminance_min);
+    }
+
+    *info_out = info;
+    *present_out = true;
+    return ParseResultCode::Ok;
+  }
+
+  struct ParserProjectionInfo {
+    int64_t projection_type;
+    const uint8_t* private_data;
+    size_t private_data_len;
+
+    bool has_pose_yaw;
+    float pose_yaw;
+    bool has_pose_pitch;
+    float pose_pitch;
+    bool has_pose_roll;
+    float pose_roll;
+  };
+
+  // `*present_out` is false if the track has no Projection element (including all non-video tracks).
+  ParseResultCode parser_track_projection(ParserPtr parser, uint64_t track_number, ParserProjectionInfo* info_out,
+                                          bool* present_out) {
+    if(parser == nullptr || parser->segment == nullptr || info_out == nullptr || present_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+    const mkvparser::Track* track = find_track(parser, track_number);
+    if(track == nullptr) { return ParseResultCode::BadParam; }
+
+    *present_out = false;
+    if(track->GetType() != mkvparser::Track::kVideo) { return ParseResultCode::Ok; }
+    const mkvparser::Projection* projection = static_cast<const mkvparser::VideoTrack*>(track)->GetProjection();
+    if(projection == nullptr || projection->type == mkvparser::Projection::kTypeNotPresent) {
+      return ParseResultCode::Ok;
+    }
+
+    const float not_present = mkvparser::Projection::kValueNotPresent;
+    ParserProjectionInfo info = {};
+    info.projection_type = projection->type;
+    info.private_data = projection->private_data;
+    info.private_data_len = projection->private_data == nullptr ? 0 : projection->private_data_length;
+    info.has_pose_yaw = projection->pose_yaw != not_present;
+    info.pose_yaw = projection->pose_yaw;
+    info.has_pose_pitch = projection->pose_pitch != not_present;
+    info.pose_pitch = projection->pose_pitch;
+    info.has_pose_roll = projection->pose_roll != not_present;
+    info.pose_roll = projection->pose_roll;
+
+    *info_out = info;
+    *present_out = true;
+    return ParseResultCode::Ok;
+  }
+
+  struct ParserSegmentInfo {
+    int64_t timecode_scale;
+    // -1 if absent
+    int64_t duration_ns;
+    // null if absent
+    const char* muxing_app;
+    const char* writing_app;
+    const char* title;
+
+    bool has_date_utc;
+    // nanoseconds since 2001-01-01T00:00:00 UTC
+    int64_t date_utc;
+    bool has_segment_uid;
+    uint8_t segment_uid[16];
+  };
+
+  // Not in libwebm's list of IDs
+  static const long long kSegmentUIDId = 0x73A4;
+
+  // mkvparser skips DateUTC and SegmentUID, so we pick them out of the Info element ourselves.
+  static ParseResultCode read_info_ids(FfiParser* parser, const mkvparser::SegmentInfo* info,
+                                       ParserSegmentInfo* info_out) {
+    long long pos = info->m_start;
+    const long long stop = info->m_start + info->m_size;
+
+    while(pos < stop) {
+      long long id = 0;
+      long long size = 0;
+      const long status = mkvparser::ParseElementHeader(&parser->reader, pos, stop, id, size);
+      if(status < 0) { return ParseResultCode::InvalidFile; }
+
+      if(id == libwebm::kMkvDateUTC) {
+        if(size != 8) { return ParseResultCode::InvalidFile; }
+        long long date = 0;
+        if(mkvparser::UnserializeInt(&parser->reader, pos, size, date) < 0) { return ParseResultCode::InvalidFile; }
+        info_out->has_date_utc = true;
+        info_out->date_utc = date;
+      } else if(id == kSegmentUIDId) {
+        if(size != sizeof(info_out->segment_uid)) { return ParseResultCode::InvalidFile; }
+        if(parser->reader.Read(pos, static_cast<long>(size), info_out->segment_uid) < 0) {
+          return ParseResultCode::InvalidFile;
+        }
+        info_out->has_segment_uid = true;
+      }
+
+      pos += size;
+    }
+
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_segment_info(ParserPtr parser, ParserSegmentInfo* info_out) {
+    if(parser == nullptr || parser->segment == nullptr || info_out == nullptr) { return ParseResultCode::BadParam; }
+    const mkvparser::SegmentInfo* segment_info = parser->segment->GetInfo();
+    if(segment_info == nullptr) { return ParseResultCode::InvalidFile; }
+
+    ParserSegmentInfo info = {};
+    info.timecode_scale = segment_info->GetTimeCodeScale();
+    info.duration_ns = segment_info->GetDuration();
+    info.muxing_app = segment_info->GetMuxingAppAsUTF8();
+    info.writing_app = segment_info->GetWritingAppAsUTF8();
+    info.title = segment_info->GetTitleAsUTF8();
+
+    const ParseResultCode result = read_info_ids(parser, segment_info, &info);
+    if(result != ParseResultCode::Ok) { return result; }
+
+    *info_out = info;
+    return ParseResultCode::Ok;
+  }
+
+  struct ParserEbmlHeader {
+    int64_t version;
+    int64_t read_version;
+    int64_t max_id_length;
+    int64_t max_size_length;
+    // null if absent
+    const char* doc_type;
+    int64_t doc_type_version;
+    int64_t doc_type_read_version;
+  };
+
+  ParseResultCode parser_ebml_header(ParserPtr parser, ParserEbmlHeader* header_out) {
+    if(parser == nullptr || parser->segment == nullptr || header_out == nullptr) { return ParseResultCode::BadParam; }
+    const mkvparser::EBMLHeader& header = parser->ebml_header;
+
+    header_out->version = header.m_version;
+    header_out->read_version = header.m_readVersion;
+    header_out->max_id_length = header.m_maxIdLength;
+    header_out->max_size_length = header.m_maxSizeLength;
+    header_out->doc_type = header.m_docType;
+    header_out->doc_type_version = header.m_docTypeVersion;
+    header_out->doc_type_read_version = header.m_docTypeReadVersion;
+    return ParseResultCode::Ok;
+  }
+
+  // Makes sure the Cues element's header is parsed, if the file has one. Files written by a seekable muxer
+  // usually keep it after the clusters, in which case we find it via the SeekHead.
+  static ParseResultCode find_cues(mkvparser::Segment* segment, const mkvparser::Cues** cues_out) {
+    if(segment->GetCues() == nullptr) {
+      const mkvparser::SeekHead* seek_head = segment->GetSeekHead();
+      const int count = seek_head == nullptr ? 0 : seek_head->GetCount();
+
+      for(int i = 0; i < count; ++i) {
+        const mkvparser::SeekHead::Entry* entry = seek_head->GetEntry(i);
+        if(entry == nullptr || entry->id != libwebm::kMkvCues) { continue; }
+
+        long long pos = 0;
+        long len = 0;
+        const long status = segment->ParseCues(entry->pos, pos, len);
+        if(status < 0) { return parse_status(status); }
+        break;
+      }
+    }
+
+    *cues_out = segment->GetCues();
+    return ParseResultCode::Ok;
+  }
+
+  const uint64_t ELEMENT_CHAPTERS = 0;
+  const uint64_t ELEMENT_TAGS = 1;
+  const uint64_t ELEMENT_CUES = 2;
+  const uint64_t ELEMENT_SEGMENT = 3;
+  const uint64_t ELEMENT_ATTACHMENTS = 4;
+  const uint64_t ELEMENT_SEEK_HEAD = 5;
+
+  // Not in webmids.h, as WebM doesn't allow attachments
+  static const long long kAttachmentsId = 0x1941A469;
+
+  // Finds the payload of the element with the given ID via the SeekHead, for elements that mkvparser skips, or only
+  // looks for before the first cluster. `*start_out` is left alone if the SeekHead doesn't list one.
+  static ParseResultCode indexed_payload(ParserPtr parser, long long element_id, int64_t* start_out,
+                                         int64_t* size_out) {
+    mkvparser::Segment* segment = parser->segment;
+    const mkvparser::SeekHead* seek_head = segment->GetSeekHead();
+    const int count = seek_head == nullptr ? 0 : seek_head->GetCount();
+    const long long stop = segment->m_size < 0 ? -1 : segment->m_start + segment->m_size;
+
+    for(int i = 0; i < count; ++i) {
+      const mkvparser::SeekHead::Entry* entry = seek_head->GetEntry(i);
+      if(entry == nullptr || entry->id != element_id) { continue; }
+
+      long long pos = segment->m_start + entry->pos;
+      long long id = 0;
+      long long size = 0;
+      const long status = mkvparser::ParseElementHeader(&parser->reader, pos, stop, id, size);
+      if(status < 0) { return parse_status(status); }
+      if(id != element_id) { return ParseResultCode::InvalidFile; }
+
+      *start_out = pos;
+      *size_out = size;
+      break;
+    }
+    return ParseResultCode::Ok;
+  }
+
+  // Finds the payload of a top-level element that mkvparser or the SeekHead has located, for parsing by the caller.
+  // `*start_out` is -1 if the file has no such element, or it is not listed in the SeekHead.
+  ParseResultCode parser_element_payload(ParserPtr parser, uint64_t element, int64_t* start_out, int64_t* size_out) {
+    if(parser == nullptr || parser->segment == nullptr || start_out == nullptr || size_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+    *start_out = -1;
+    *size_out = 0;
+
+    switch(element) {
+    case ELEMENT_CHAPTERS: {
+      const mkvparser::Chapters* chapters = parser->segment->GetChapters();
+      if(chapters == nullptr) { return indexed_payload(parser, libwebm::kMkvChapters, start_out, size_out); }
+      *start_out = chapters->m_start;
+      *size_out = chapters->m_size;
+      break;
+    }
+    case ELEMENT_TAGS: {
+      const mkvparser::Tags* tags = parser->segment->GetTags();
+      if(tags == nullptr) { return indexed_payload(parser, libwebm::kMkvTags, start_out, size_out); }
+      *start_out = tags->m_start;
+      *size_out = tags->m_size;
+      break;
+    }
+    case ELEMENT_CUES: {
+      const mkvparser::Cues* cues = nullptr;
+      const ParseResultCode result = find_cues(parser->segment, &cues);
+      if(result != ParseResultCode::Ok) { return result; }
+      if(cues != nullptr) {
+        *start_out = cues->m_start;
+        *size_out = cues->m_size;
+      }
+      break;
+    }
+    case ELEMENT_SEGMENT: {
+      const mkvparser::Segment* segment = parser->segment;
+      *start_out = segment->m_start;
+      *size_out = segment->m_size < 0 ? 0 : segment->m_size;
+      // mkvparser ignores the declared size while the length of the file is unknown, or if the file is cut short
+      if(segment->m_size < 0) {
+        long len = 0;
+        if(mkvparser::ReadID(&parser->reader, segment->m_element_start, len) < 0) { break; }
+        long long size_pos = segment->m_element_start + len;
+        const long long size = mkvparser::ReadUInt(&parser->reader, size_pos, len);
+        if(size > 0 && size != (1LL << (7 * len)) - 1) { *size_out = size; }
+      }
+      break;
+    }
+    case ELEMENT_ATTACHMENTS: {
+      // mkvparser skips Attachments entirely
+      return indexed_payload(parser, kAttachmentsId, start_out, size_out);
+    }
+    case ELEMENT_SEEK_HEAD: {
+      const mkvparser::SeekHead* seek_head = parser->segment->GetSeekHead();
+      if(seek_head != nullptr) {
+        *start_out = seek_head->m_start;
+        *size_out = seek_head->m_size;
+      }
+      break;
+    }
+    default: return ParseResultCode::BadParam;
+    }
+
+    return ParseResultCode::Ok;
+  }
+
+  typedef const mkvparser::Cluster* ClusterPtr;
+  typedef const mkvparser::BlockEntry* BlockEntryPtr;
+
+  // Loads the next cluster from the file, if any. Returns `Ok` with `*loaded_out` set to false
+  // once there are no more clusters.
+  static ParseResultCode load_cluster(mkvparser::Segment* segment, bool* loaded_out) {
+    long long pos = 0;
+    long len = 0;
+    const long status = segment->LoadCluster(pos, len);
+
+    *loaded_out = status == 0;
+    return status > 0 ? ParseResultCode::Ok : parse_status(status);
+  }
+
+  // Whether the element at the absolute position `pos` is a cluster that runs past the end of the file. mkvparser
+  // takes such a cluster for the end of the clusters, which would lose the blocks of a recording cut off mid-write.
+  static bool is_truncated_cluster(mkvparser::IMkvReader* reader, long long pos) {
+    long long total = 0;
+    long long available = 0;
+    if(reader->Length(&total, &available) < 0 || total < 0 || pos >= total) { return false; }
+
+    long len = 0;
+    if(mkvparser::ReadID(reader, pos, len) != libwebm::kMkvCluster) { return false; }
+    long long size_pos = pos + len;
+    const long long size = mkvparser::ReadUInt(reader, size_pos, len);
+    const long long unknown_size = (1LL << (7 * len)) - 1;
+    return size >= 0 && size != unknown_size && size_pos + len + size > total;
+  }
+
+  ParseResultCode parser_first_cluster(ParserPtr parser, ClusterPtr* cluster_out) {
+    if(parser == nullptr || parser->segment == nullptr || cluster_out == nullptr) { return ParseResultCode::BadParam; }
+    mkvparser::Segment* segment = parser->segment;
+
+    const mkvparser::Cluster* cluster = segment->GetFirst();
+    if(cluster == nullptr || cluster->EOS()) {
+      bool loaded = false;
+      const ParseResultCode result = load_cluster(segment, &loaded);
+      if(result != ParseResultCode::Ok) { return result; }
+      cluster = loaded ? segment->GetFirst() : nullptr;
+    }
+
+    *cluster_out = (cluster == nullptr || cluster->EOS()) ? nullptr : cluster;
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_next_cluster(ParserPtr parser, ClusterPtr current, ClusterPtr* cluster_out) {
+    if(parser == nullptr || parser->segment == nullptr || current == nullptr || cluster_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+    if(current->EOS()) { return ParseResultCode::BadParam; }
+    mkvparser::Segment* segment = parser->segment;
+    // Nothing can follow a cluster cut off by the end of the file, and mkvparser can't walk past one
+    if(is_truncated_cluster(segment->m_pReader, current->m_element_start)) {
+      *cluster_out = nullptr;
+      return ParseResultCode::Ok;
+    }
+
+    // `GetNext` only walks clusters that have already been loaded, and reports end-of-stream
+    // once it runs out; it is up to us to load more.
+    const mkvparser::Cluster* next = segment->GetNext(current);
+    if(next == nullptr || next->EOS()) {
+      bool loaded = false;
+      const ParseResultCode result = load_cluster(segment, &loaded);
+      if(result != ParseResultCode::Ok) { return result; }
+      if(loaded) {
+        next = segment->GetNext(current);
+      } else {
+        // The complete blocks of a cluster cut off by the end of the file can still be read
+        const long long next_pos = current->m_element_start + current->GetElementSize();
+        if(is_truncated_cluster(segment->m_pReader, next_pos)) {
+          next = segment->FindOrPreloadCluster(next_pos - segment->m_start);
+        }
+      }
+    }
+
+    *cluster_out = (next == nullptr || next->EOS()) ? nullptr : next;
+    return ParseResultCode::Ok;
+  }
+
+  // Finds or creates the cluster whose element starts at the absolute position `pos`, for resuming parsing
+  // past a damaged part of the file. The caller must make sure that there is a cluster there.
+  ParseResultCode parser_cluster_at(ParserPtr parser, int64_t pos, ClusterPtr* cluster_out) {
+    if(parser == nullptr || parser->segment == nullptr || cluster_out == nullptr) { return ParseResultCode::BadParam; }
+    mkvparser::Segment* segment = parser->segment;
+    if(pos < segment->m_start) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Cluster* cluster = segment->FindOrPreloadCluster(pos - segment->m_start);
+    *cluster_out = (cluster == nullptr || cluster->EOS()) ? nullptr : cluster;
+    return ParseResultCode::Ok;
+  }
+
+  // Parses the header of the Cues element at the absolute position `pos`, for files without a SeekHead to find it by.
+  ParseResultCode parser_cues_at(ParserPtr parser, int64_t pos) {
+    if(parser == nullptr || parser->segment == nullptr) { return ParseResultCode::BadParam; }
+    mkvparser::Segment* segment = parser->segment;
+    if(pos < segment->m_start) { return ParseResultCode::BadParam; }
+    if(segment->GetCues() != nullptr) { return ParseResultCode::Ok; }
+
+    long long cues_pos = 0;
+    long len = 0;
+    return parse_status(segment->ParseCues(pos - segment->m_start, cues_pos, len));
+  }
+
+  // Finds the last cluster listed in the SeekHead, which muxers rarely do. `*pos_out` is the absolute position of its
+  // element, or -1 if the SeekHead lists no clusters.
+  ParseResultCode parser_last_indexed_cluster(ParserPtr parser, int64_t* pos_out) {
+    if(parser == nullptr || parser->segment == nullptr || pos_out == nullptr) { return ParseResultCode::BadParam; }
+    *pos_out = -1;
+
+    const mkvparser::SeekHead* seek_head = parser->segment->GetSeekHead();
+    const int count = seek_head == nullptr ? 0 : seek_head->GetCount();
+    for(int i = 0; i < count; ++i) {
+      const mkvparser::SeekHead::Entry* entry = seek_head->GetEntry(i);
+      if(entry == nullptr || entry->id != libwebm::kMkvCluster) { continue; }
+      *pos_out = std::max<int64_t>(*pos_out, parser->segment->m_start + entry->pos);
+    }
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_cluster_first_entry(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr* entry_out) {
+    if(parser == nullptr || cluster == nullptr || entry_out == nullptr) { return ParseResultCode::BadParam; }
+
+    const mkvparser::BlockEntry* entry = nullptr;
+    const long status = cluster->GetFirst(entry);
+    if(status < 0) { return parse_status(status); }
+
+    *entry_out = (entry == nullptr || entry->EOS()) ? nullptr : entry;
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_cluster_next_entry(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr current,
+                                            BlockEntryPtr* entry_out) {
+    if(parser == nullptr || cluster == nullptr || current == nullptr || entry_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const mkvparser::BlockEntry* next = nullptr;
+    const long status = cluster->GetNext(current, next);
+    if(status < 0) { return parse_status(status); }
+
+    *entry_out = (next == nullptr || next->EOS()) ? nullptr : next;
+    return ParseResultCode::Ok;
+  }
+
+  struct ParserClusterInfo {
+    int64_t time_ns;
+    int64_t element_start;
+    int64_t element_size;
+  };
+
+  ParseResultCode parser_cluster_info(ParserPtr parser, ClusterPtr cluster, ParserClusterInfo* info_out) {
+    if(parser == nullptr || cluster == nullptr || info_out == nullptr) { return ParseResultCode::BadParam; }
+    if(cluster->EOS()) { return ParseResultCode::BadParam; }
+
+    ParserClusterInfo info = {};
+    info.time_ns = cluster->GetTime();
+    info.element_start = cluster->m_element_start;
+    info.element_size = cluster->GetElementSize();
+
+    *info_out = info;
+    return ParseResultCode::Ok;
+  }
+
+  // Makes sure the Cues element is fully loaded, if the file has one.
+  static ParseResultCode load_cues(mkvparser::Segment* segment, const mkvparser::Cues** cues_out) {
+    const ParseResultCode result = find_cues(segment, cues_out);
+    if(result != ParseResultCode::Ok) { return result; }
+
+    if(*cues_out != nullptr) {
+      while((*cues_out)->LoadCuePoint()) {}
+    }
+    return ParseResultCode::Ok;
+  }
+
+  // Finds the block referenced by the last cue point at or before `time_ns` for the given track.
+  // `*entry_out` is null if the file has no usable cue for it.
+  ParseResultCode parser_cue_seek(ParserPtr parser, uint64_t track_number, int64_t time_ns,
+                                  ClusterPtr* cluster_out, BlockEntryPtr* entry_out) {
+    if(parser == nullptr || parser->segment == nullptr || cluster_out == nullptr || entry_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+    mkvparser::Segment* segment = parser->segment;
+    *cluster_out = nullptr;
+    *entry_out = nullptr;
+
+    const mkvparser::Track* track = segment->GetTracks()->GetTrackByNumber(static_cast<long>(track_number));
+    if(track == nullptr) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Cues* cues = nullptr;
+    const ParseResultCode result = load_cues(segment, &cues);
+    if(result != ParseResultCode::Ok || cues == nullptr) { return result; }
+
+    const mkvparser::CuePoint* cue_point = nullptr;
+    const mkvparser::CuePoint::TrackPosition* track_position = nullptr;
+    if(!cues->Find(time_ns < 0 ? 0 : time_ns, track, cue_point, track_position)) { return ParseResultCode::Ok; }
+    if(cue_point == nullptr || track_position == nullptr) { return ParseResultCode::Ok; }
+
+    const mkvparser::BlockEntry* entry = cues->GetBlock(cue_point, track_position);
+    if(entry == nullptr || entry->EOS() || entry->GetCluster() == nullptr) { return ParseResultCode::Ok; }
+
+    *cluster_out = entry->GetCluster();
+    *entry_out = entry;
+    return ParseResultCode::Ok;
+  }
+
+  struct ParserBlockInfo {
+    uint64_t track_number;
+    int64_t time_ns;
+    bool keyframe;
+    int32_t frame_count;
+    // From BlockDuration, -1 if absent (always for SimpleBlocks)
+    int64_t duration_ns;
+  };
+
+  ParseResultCode parser_block_info(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr entry,
+                                    ParserBlockInfo* info_out) {
+    if(parser == nullptr || cluster == nullptr || entry == nullptr || info_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const mkvparser::Block* block = entry->GetBlock();
+    if(block == nullptr) { return ParseResultCode::InvalidFile; }
+
+    ParserBlockInfo info = {};
+    info.track_number = static_cast<uint64_t>(block->GetTrackNumber());
+    info.time_ns = block->GetTime(cluster);
+    info.keyframe = block->IsKey();
+    info.frame_count = block->GetFrameCount();
+    info.duration_ns = -1;
+
+    if(entry->GetKind() == mkvparser::BlockEntry::kBlockGroup) {
+      const long long duration = static_cast<const mkvparser::BlockGroup*>(entry)->GetDurationTimeCode();
+      const mkvparser::SegmentInfo* segment_info = cluster->m_pSegment->GetInfo();
+      if(duration >= 0 && segment_info != nullptr) {
+        info.duration_ns = duration * segment_info->GetTimeCodeScale();
+      }
+    }
+
+    *info_out = info;
+    return ParseResultCode::Ok;
+  }
+
+  // Finds the payload range of the BlockGroup containing `block`.
+  static bool find_block_group(ParserPtr parser, ClusterPtr cluster, const mkvparser::Block* block,
+                               std::pair<long long, long long>* group_out) {
+    if(parser->groups_cluster != cluster) {
+      parser->groups_cluster = nullptr;
+      parser->groups.clear();
+
+      long long start = 0;
+      if(!element_payload_start(&parser->reader, cluster->m_element_start, &start)) { return false; }
+
+      // The size of a cluster may be unknown, in which case it ends at the next top-level element. When streaming,
+      // it may also not have fully arrived yet, so we only cache complete clusters.
+      const long long size = cluster->GetElementSize();
+      long long stop = 0;
+      long long available = 0;
+      if(parser->reader.Length(&stop, &available) < 0) { return false; }
+      const bool complete = size >= 0 || stop >= 0;
+      if(size >= 0) {
+        stop = cluster->m_element_start + size;
+      } else if(stop < 0) {
+        stop = available;
+      }
+
+      ChildElements children(&parser->reader, start, stop);
+      while(children.Next()) {
+        if(children.id == libwebm::kMkvCluster || children.id == libwebm::kMkvCues) { break; }
+        if(children.id == libwebm::kMkvBlockGroup) {
+          parser->groups.push_back(std::make_pair(children.pos, children.pos + children.size));
+        }
+      }
+      // A partially arrived element at the end is expected when streaming
+      if(children.failed && complete) { return false; }
+      if(complete) { parser->groups_cluster = cluster; }
+    }
+
+    // The last group starting before the block is the only one that can contain it
+    auto group = std::upper_bound(parser->groups.begin(), parser->groups.end(),
+                                  std::make_pair(block->m_start, block->m_start));
+    if(group == parser->groups.begin()) { return false; }
+    --group;
+    if(block->m_start >= group->second) { return false; }
+
+    *group_out = *group;
+    return true;
+  }
+
+  // Reads the BlockAdditions and DiscardPadding of the given entry. mkvparser ignores the former, and reports an
+  // absent DiscardPadding as zero.
+  static ParseResultCode load_block_extras(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr entry) {
+    if(parser->extras_entry == entry) { return ParseResultCode::Ok; }
+    parser->extras_entry = nullptr;
+    parser->additions.clear();
+    parser->has_discard_padding = false;
+    parser->discard_padding = 0;
+
+    // SimpleBlocks have neither
+    if(entry->GetKind() == mkvparser::BlockEntry::kBlockGroup) {
+      const mkvparser::Block* block = entry->GetBlock();
+      std::pair<long long, long long> group;
+      if(block == nullptr || !find_block_group(parser, cluster, block, &group)) { return ParseResultCode::InvalidFile; }
+
+      mkvparser::IMkvReader* reader = &parser->reader;
+      ChildElements group_children(reader, group.first, group.second);
+      while(group_children.Next()) {
+        if(group_children.id == libwebm::kMkvDiscardPadding) {
+          long long padding = 0;
+          if(mkvparser::UnserializeInt(reader, group_children.pos, group_children.size, padding) < 0) {
+            return ParseResultCode::InvalidFile;
+          }
+          parser->has_discard_padding = true;
+          parser->discard_padding = padding;
+          continue;
+        }
+        if(group_children.id != libwebm::kMkvBlockAdditions) { continue; }
+
+        ChildElements additions(reader, group_children.pos, group_children.pos + group_children.size);
+        while(additions.Next()) {
+          if(additions.id != libwebm::kMkvBlockMore) { continue; }
+
+          // BlockAddID defaults to 1
+          ParserBlockAddition addition = { 1, -1, 0 };
+          ChildElements more(reader, additions.pos, additions.pos + additions.size);
+          while(more.Next()) {
+            if(more.id == libwebm::kMkvBlockAddID) {
+              const long long id = mkvparser::UnserializeUInt(reader, more.pos, more.size);
+              if(id < 0) { return ParseResultCode::InvalidFile; }
+              addition.id = static_cast<uint64_t>(id);
+            } else if(more.id == libwebm::kMkvBlockAdditional) {
+              addition.pos = more.pos;
+              addition.len = more.size;
+            }
+          }
+          if(more.failed) { return ParseResultCode::InvalidFile; }
+
+          if(addition.pos >= 0) { parser->additions.push_back(addition); }
+        }
+        if(additions.failed) { return ParseResultCode::InvalidFile; }
+      }
+      if(group_children.failed) { return ParseResultCode::InvalidFile; }
+    }
+
+    parser->extras_entry = entry;
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_block_addition_count(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr entry,
+                                              uint64_t* count_out) {
+    if(parser == nullptr || cluster == nullptr || entry == nullptr || count_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const ParseResultCode result = load_block_extras(parser, cluster, entry);
+    if(result != ParseResultCode::Ok) { return result; }
+
+    *count_out = parser->additions.size();
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_block_addition(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr entry, uint64_t index,
+                                        ParserBlockAddition* addition_out) {
+    if(parser == nullptr || cluster == nullptr || entry == nullptr || addition_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const ParseResultCode result = load_block_extras(parser, cluster, entry);
+    if(result != ParseResultCode::Ok) { return result; }
+    if(index >= parser->additions.size()) { return ParseResultCode::BadParam; }
+
+    *addition_out = parser->additions[index];
+    return ParseResultCode::Ok;
+  }
+
+  // `*present_out` is false if the block has no DiscardPadding element.
+  ParseResultCode parser_block_discard_padding(ParserPtr parser, ClusterPtr cluster, BlockEntryPtr entry,
+                                               bool* present_out, int64_t* padding_out) {
+    if(parser == nullptr || cluster == nullptr || entry == nullptr ||
+       present_out == nullptr || padding_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const ParseResultCode result = load_block_extras(parser, cluster, entry);
+    if(result != ParseResultCode::Ok) { return result; }
+
+    *present_out = parser->has_discard_padding;
+    *padding_out = parser->discard_padding;
+    return ParseResultCode::Ok;
+  }
+
+  ParseResultCode parser_block_frame(ParserPtr parser, BlockEntryPtr entry, int32_t index,
+                                     int64_t* pos_out, int64_t* len_out) {
+    if(parser == nullptr || entry == nullptr || pos_out == nullptr || len_out == nullptr) {
+      return ParseResultCode::BadParam;
+    }
+
+    const mkvparser::Block* block = entry->GetBlock();
+    if(block == nullptr) { return ParseResultCode::InvalidFile; }
+    if(index < 0 || index >= block->GetFrameCount()) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Block::Frame& frame = block->GetFrame(index);
+    // mkvparser only checks blocks against their cluster, which may itself run past the data there is
+    long long total = 0;
+    long long available = 0;
+    if(parser->reader.Length(&total, &available) < 0) { return ParseResultCode::InvalidFile; }
+    if(frame.pos + frame.len > available) {
+      return total < 0 ? ParseResultCode::BufferNotFull : ParseResultCode::InvalidFile;
+    }
+
+    *pos_out = frame.pos;
+    *len_out = frame.len;
+    return ParseResultCode::Ok;
+  }
+
+  // Reads the payload of a frame into `buffer`, which must hold at least the frame's length as given by
+  // `parser_block_frame`.
+  ParseResultCode parser_read_frame(ParserPtr parser, BlockEntryPtr entry, int32_t index, uint8_t* buffer,
+                                    size_t buffer_len) {
+    if(parser == nullptr || entry == nullptr || buffer == nullptr) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Block* block = entry->GetBlock();
+    if(block == nullptr) { return ParseResultCode::InvalidFile; }
+    if(index < 0 || index >= block->GetFrameCount()) { return ParseResultCode::BadParam; }
+
+    const mkvparser::Block::Frame& frame = block->GetFrame(index);
+    if(frame.len < 0 || static_cast<unsigned long long>(frame.len) > buffer_len) { return ParseResultCode::BadParam; }
+
+    const long status = frame.Read(&parser->reader, buffer);
+    return status < 0 ? ParseResultCode::InvalidFile : ParseResultCode::Ok;
+  }
+
+  const char* parser_abi_binding(size_t index, const char** name_out);
+  const char* parser_abi_field(size_t index, const char** field_out, size_t* offset_out, size_t* size_out,
+                               size_t* type_size_out);
+
+  // Every function above, and the ABI helpers themselves
+  static const abi::Binding PARSER_BINDINGS[] = {
+    WEBM_ABI_BINDING(parser_new),
+    WEBM_ABI_BINDING(parser_delete),
+    WEBM_ABI_BINDING(parser_libwebm_version),
+    WEBM_ABI_BINDING(parser_open),
+    WEBM_ABI_BINDING(parser_track_count),
+    WEBM_ABI_BINDING(parser_track_info),
+    WEBM_ABI_BINDING(parser_track_colour),
+    WEBM_ABI_BINDING(parser_track_projection),
+    WEBM_ABI_BINDING(parser_segment_info),
+    WEBM_ABI_BINDING(parser_ebml_header),
+    WEBM_ABI_BINDING(parser_element_payload),
+    WEBM_ABI_BINDING(parser_first_cluster),
+    WEBM_ABI_BINDING(parser_next_cluster),
+    WEBM_ABI_BINDING(parser_cluster_at),
+    WEBM_ABI_BINDING(parser_cues_at),
+    WEBM_ABI_BINDING(parser_last_indexed_cluster),
+    WEBM_ABI_BINDING(parser_cluster_first_entry),
+    WEBM_ABI_BINDING(parser_cluster_next_entry),
+    WEBM_ABI_BINDING(parser_cluster_info),
+    WEBM_ABI_BINDING(parser_cue_seek),
+    WEBM_ABI_BINDING(parser_block_info),
+    WEBM_ABI_BINDING(parser_block_addition_count),
+    WEBM_ABI_BINDING(parser_block_addition),
+    WEBM_ABI_BINDING(parser_block_discard_padding),
+    WEBM_ABI_BINDING(parser_block_frame),
+    WEBM_ABI_BINDING(parser_read_frame),
+    WEBM_ABI_BINDING(parser_abi_binding),
+    WEBM_ABI_BINDING(parser_abi_field),
+  };
+
+  // Every field of the structs filled in for Rust, in order
+  static const abi::Field PARSER_FIELDS[] = {
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, number),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, uid),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, track_type),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, codec_id),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, codec_private),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, codec_private_len),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, default_duration),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, codec_delay),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, seek_pre_roll),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, max_block_addition_id),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, name),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, language),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, language_ietf_pos),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, language_ietf_len),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, width),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, height),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, display_width),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, display_height),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, sampling_rate),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, channels),
+    WEBM_ABI_FIELD(TrackInfo, ParserTrackInfo, bit_depth),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, matrix_coefficients),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, bits_per_channel),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, chroma_subsampling_horz),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, chroma_subsampling_vert),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, cb_subsampling_horz),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, cb_subsampling_vert),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, chroma_siting_horz),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, chroma_siting_vert),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, range),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, transfer_characteristics),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, primaries),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, max_cll),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, max_fall),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, has_mastering_metadata),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, chromaticities),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, luminance_max),
+    WEBM_ABI_FIELD(ColourInfo, ParserColourInfo, luminance_min),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, projection_type),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, private_data),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, private_data_len),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, has_pose_yaw),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, pose_yaw),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, has_pose_pitch),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, pose_pitch),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, has_pose_roll),
+    WEBM_ABI_FIELD(ProjectionInfo, ParserProjectionInfo, pose_roll),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, version),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, read_version),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, max_id_length),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, max_size_length),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, doc_type),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, doc_type_version),
+    WEBM_ABI_FIELD(EbmlHeader, ParserEbmlHeader, doc_type_read_version),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, timecode_scale),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, duration_ns),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, muxing_app),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, writing_app),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, title),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, has_date_utc),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, date_utc),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, has_segment_uid),
+    WEBM_ABI_FIELD(SegmentInfo, ParserSegmentInfo, segment_uid),
+    WEBM_ABI_FIELD(ClusterInfo, ParserClusterInfo, time_ns),
+    WEBM_ABI_FIELD(ClusterInfo, ParserClusterInfo, element_start),
+    WEBM_ABI_FIELD(ClusterInfo, ParserClusterInfo, element_size),
+    WEBM_ABI_FIELD(BlockInfo, ParserBlockInfo, track_number),
+    WEBM_ABI_FIELD(BlockInfo, ParserBlockInfo, time_ns),
+    WEBM_ABI_FIELD(BlockInfo, ParserBlockInfo, keyframe),
+    WEBM_ABI_FIELD(BlockInfo, ParserBlockInfo, frame_count),
+    WEBM_ABI_FIELD(BlockInfo, ParserBlockInfo, duration_ns),
+    WEBM_ABI_FIELD(BlockAddition, ParserBlockAddition, id),
+    WEBM_ABI_FIELD(BlockAddition, ParserBlockAddition, pos),
+    WEBM_ABI_FIELD(BlockAddition, ParserBlockAddition, len),
+  };
+
+  // Describes the signature of the `index`th function in PARSER_BINDINGS, or returns null past the end.
+  const char* parser_abi_binding(size_t index, const char** name_out) {
+    static std::vector<std::string> signatures;
+    return abi::describe_binding(PARSER_BINDINGS, signatures, index, name_out);
+  }
+
+  // Describes the `index`th field in PARSER_FIELDS, returning the name of its struct, or null past the end. Null
+  // pointers are skipped.
+  const char* parser_abi_field(size_t index, const char** field_out, size_t* offset_out, size_t* size_out,
+                               size_t* type_size_out) {
+    if(index >= sizeof(PARSER_FIELDS) / sizeof(PARSER_FIELDS[0])) { return nullptr; }
+
+    const abi::Field& field = PARSER_FIELDS[index];
+    if(field_out != nullptr) { *field_out = field.name; }
+    if(offset_out != nullptr) { *offset_out = field.offset; }
+    if(size_out != nullptr) { *size_out = field.size; }
+    if(type_size_out != nullptr) { *type_size_out = field.type_size; }
+    return field.type;
+  }
+
+}
@@ -1,5 +1,28 @@
+/// Declares the functions of the C++ adapter in an `extern "C"` block. For tests, it also lists their link names and
+/// descriptions of their signatures as `BINDINGS`, which are checked against the adapter's own in [`abi`].
+macro_rules! bindings {
+    ($(
+        $(#[doc = $doc:literal])*
+        #[link_name = $link:literal]
+        pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;
+    )*) => {
+        #[link(name = "webmadapter", kind = "static")]
+        extern "C" {
+            $(
+                $(#[doc = $doc])*
+                #[link_name = $link]
+                pub fn $name($($arg: $ty),*) $(-> $ret)?;
+            )*
+        }
+
+        #[cfg(test)]
+        pub(crate) const BINDINGS: &[(&str, fn() -> String)] =
+            &[$(($link, <unsafe extern "C" fn($($ty),*) $(-> $ret)? as crate::abi::Abi>::describe)),*];
+    };
+}
+
 pub mod mux {
-    use core::ffi::{c_char, c_void};
+    use core::ffi::{c_char, c_uint, c_void};
     use core::ptr::NonNull;
 
     #[repr(C)]
@@ -41,6 +64,9 @@ pub mod mux {
     pub const VP9_CODEC_ID: u32 = 1;
     pub const AV1_CODEC_ID: u32 = 2;
 
+    // encryption
+    pub const AES_CTR_CIPHER_MODE: u32 = 1;
+
     #[repr(C)]
     pub struct Segment {
         _opaque_c_aligned: *mut c_void,
@@ -48,8 +74,23 @@ pub mod mux {
     pub type SegmentMutPtr = *mut Segment;
     pub type SegmentNonNullPtr = NonNull<Segment>;
 
-    #[link(name = "webmadapter", kind = "static")]
-    extern "C" {
+    /// Passed through [`abi_probe`] to check that each scalar type crosses the FFI boundary intact.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct AbiProbe {
+        pub u8: u8,
+        pub flag: bool,
+        pub i32: i32,
+        pub u32: u32,
+        pub i64: i64,
+        pub u64: u64,
+        pub size: usize,
+        pub f32: f32,
+        pub f64: f64,
+        pub ptr: *const u8,
+    }
+
+    bindings! {
         #[link_name = "mux_new_writer"]
         pub fn new_writer(
             write: Option<WriterWriteFn>,
@@ -61,10 +102,24 @@ pub mod mux {
         #[link_name = "mux_delete_writer"]
         pub fn delete_writer(writer: WriterMutPtr);
 
+        #[link_name = "mux_libwebm_version"]
+        pub fn libwebm_version(major: *mut i32, minor: *mut i32, build: *mut i32, revision: *mut i32);
+        #[link_name = "mux_make_uid"]
+        pub fn make_uid(seed: *mut c_uint) -> u64;
+
         #[link_name = "mux_new_segment"]
         pub fn new_segment() -> SegmentMutPtr;
         #[link_name = "mux_initialize_segment"]
         pub fn initialize_segment(segment: SegmentMutPtr, writer: WriterMutPtr) -> ResultCode;
+        /// Copies why the last failed call on `segment` failed into `buffer`, truncated to `len` bytes including a nul
+        /// terminator, and returns the message's full length. This is 0 if no call has failed.
+        #[link_name = "mux_segment_last_error"]
+        pub fn segment_last_error(segment: SegmentMutPtr, buffer: *mut c_char, len: usize) -> usize;
+        #[link_name = "mux_segment_cluster_count"]
+        pub fn segment_cluster_count(segment: SegmentMutPtr) -> u64;
+        /// Fails with [`ResultCode::BadParam`] if no cluster has been started yet.
+        #[link_name = "mux_segment_current_cluster_size"]
+        pub fn segment_current_cluster_size(segment: SegmentMutPtr, size_out: *mut u64) -> ResultCode;
         #[link_name = "mux_set_color"]
         pub fn mux_set_color(
             segment: SegmentMutPtr,
@@ -74,8 +129,186 @@ pub mod mux {
             sampling_vert: u8,
             color_range: u8,
         ) -> ResultCode;
+        /// Sets the chroma siting of a video track in each direction, leaving it out where it is 0 (unspecified).
+        /// Fails for a siting above 2.
+        #[link_name = "mux_set_chroma_siting"]
+        pub fn mux_set_chroma_siting(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            siting_horz: u8,
+            siting_vert: u8,
+        ) -> ResultCode;
+        /// Whether `libwebm` can write the given color primaries, transfer characteristics, matrix coefficients,
+        /// range and chroma siting, as [`mux_set_color_description`] takes them.
+        #[link_name = "mux_color_description_is_valid"]
+        pub fn mux_color_description_is_valid(
+            primaries: u8,
+            transfer_characteristics: u8,
+            matrix_coefficients: u8,
+            color_range: u8,
+            siting_horz: u8,
+            siting_vert: u8,
+        ) -> bool;
+        /// Sets the color primaries, transfer characteristics and matrix coefficients (ISO/IEC 23091-4 code points),
+        /// range and chroma siting of a video track, keeping its other color information. A chroma siting that is 0
+        /// (unspecified) is left out. Fails if `libwebm` can't write one of the values.
+        #[link_name = "mux_set_color_description"]
+        pub fn mux_set_color_description(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            primaries: u8,
+            transfer_characteristics: u8,
+            matrix_coefficients: u8,
+            color_range: u8,
+            siting_horz: u8,
+            siting_vert: u8,
+        ) -> ResultCode;
+        /// Sets the color primaries, transfer characteristics and matrix coefficients (ISO/IEC 23091-4 code points) of
+        /// a video track, leaving out each one that is 2 (unspecified) and keeping its other color information. Fails
+        /// if `libwebm` can't write one of the values.
+        #[link_name = "mux_set_color_code_points"]
+        pub fn mux_set_color_code_points(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            primaries: u8,
+            transfer_characteristics: u8,
+            matrix_coefficients: u8,
+        ) -> ResultCode;
+        /// Sets the `DisplayWidth` and `DisplayHeight` of a video track. Fails if either is zero.
+        #[link_name = "mux_set_display_size"]
+        pub fn mux_set_display_size(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            display_width: u32,
+            display_height: u32,
+        ) -> ResultCode;
+        /// Sets the Matroska `StereoMode` of a video track. Fails for the modes that WebM doesn't allow.
+        #[link_name = "mux_set_stereo_mode"]
+        pub fn mux_set_stereo_mode(segment: SegmentMutPtr, video_track_num: TrackNum, stereo_mode: u8) -> ResultCode;
+        /// Sets the Matroska `AspectRatioType` of a video track: 0 for free resizing, 1 to keep the aspect ratio, or 2
+        /// for a fixed size.
+        #[link_name = "mux_set_aspect_ratio_type"]
+        pub fn mux_set_aspect_ratio_type(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            aspect_ratio_type: u8,
+        ) -> ResultCode;
+        /// Sets the Matroska `OutputSamplingFrequency` of an audio track, the rate its decoder outputs at, in Hz. Fails
+        /// unless it is positive and finite.
+        #[link_name = "mux_set_output_sample_rate"]
+        pub fn mux_set_output_sample_rate(
+            segment: SegmentMutPtr,
+            audio_track_num: TrackNum,
+            output_sample_rate: f64,
+        ) -> ResultCode;
+        /// Sets the Matroska `BitDepth` of an audio track, the number of bits per sample. Fails for 0.
+        #[link_name = "mux_set_audio_bit_depth"]
+        pub fn mux_set_audio_bit_depth(segment: SegmentMutPtr, audio_track_num: TrackNum, bit_depth: u64) -> ResultCode;
+        /// Sets the Matroska `FlagInterlaced` (0 to 2) and `FieldOrder` of a video track. Each is left out while it is
+        /// the default: 0 for the flag, and 2 for the field order.
+        #[link_name = "mux_set_interlacing"]
+        pub fn mux_set_interlacing(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            flag_interlaced: u8,
+            field_order: u8,
+        ) -> ResultCode;
+        /// Sets the `Projection` of a video track: its `ProjectionType` (0 to 3), `ProjectionPrivate` unless
+        /// `private_data` is null, and pose. `private_data` must be null exactly when `private_data_length` is 0.
+        #[link_name = "mux_set_projection"]
+        pub fn mux_set_projection(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            projection_type: u8,
+            private_data: *const u8,
+            private_data_length: usize,
+            pose_yaw: f32,
+            pose_pitch: f32,
+            pose_roll: f32,
+        ) -> ResultCode;
+        /// Sets the `FrameRate` of a video track. Fails unless it is finite and positive as an `f32`.
+        #[link_name = "mux_set_frame_rate"]
+        pub fn mux_set_frame_rate(segment: SegmentMutPtr, video_track_num: TrackNum, frame_rate: f64) -> ResultCode;
+        /// Sets the `AlphaMode` of a video track, and raises its `MaxBlockAdditionID` to at least 1.
+        #[link_name = "mux_enable_alpha"]
+        pub fn mux_enable_alpha(segment: SegmentMutPtr, video_track_num: TrackNum) -> ResultCode;
+        /// Sets how many pixels players crop off each side of a video track's frames. Fails unless some of the frame
+        /// is left in both directions.
+        #[link_name = "mux_set_pixel_crop"]
+        pub fn mux_set_pixel_crop(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            top: u32,
+            bottom: u32,
+            left: u32,
+            right: u32,
+        ) -> ResultCode;
+        /// Sets the `MaxCLL` and `MaxFALL` of a video track, in cd/m², keeping its other color information.
+        #[link_name = "mux_set_content_light_level"]
+        pub fn mux_set_content_light_level(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            max_cll: u16,
+            max_fall: u16,
+        ) -> ResultCode;
+        /// Sets the SMPTE 2086 mastering metadata of a video track, keeping its other color information.
+        /// `chromaticities` points to the x and y of the red, green and blue primaries and the white point, in that
+        /// order. A chromaticity or luminance is left out if it is negative. Fails if `libwebm` can't write a value.
+        #[link_name = "mux_set_mastering_metadata"]
+        pub fn mux_set_mastering_metadata(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            chromaticities: *const [f32; 8],
+            luminance_max: f32,
+            luminance_min: f32,
+        ) -> ResultCode;
+        /// Sets the display size of a video track from the aspect ratio of its pixels: its cropped width, scaled and
+        /// rounded to the nearest even number, by its cropped height.
+        #[link_name = "mux_set_pixel_aspect_ratio"]
+        pub fn mux_set_pixel_aspect_ratio(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            par_num: u32,
+            par_den: u32,
+        ) -> ResultCode;
         #[link_name = "mux_set_writing_app"]
-        pub fn mux_set_writing_app(segment: SegmentMutPtr, name: *const c_char);
+        pub fn mux_set_writing_app(segment: SegmentMutPtr, name: *const c_char) -> ResultCode;
+        /// Sets the segment's `DateUTC`, in nanoseconds from 2001-01-01T00:00:00 UTC.
+        #[link_name = "mux_set_date_utc"]
+        pub fn set_date_utc(segment: SegmentMutPtr, date_utc: i64) -> ResultCode;
+        #[link_name = "mux_set_timecode_scale"]
+        pub fn set_timecode_scale(segment: SegmentMutPtr, timecode_scale: u64) -> ResultCode;
+        /// A live segment is written for streaming: without cues, and without going back to fill in its size or
+        /// duration.
+        #[link_name = "mux_set_live"]
+        pub fn set_live(segment: SegmentMutPtr, live: bool) -> ResultCode;
+        /// Starts a new cluster once the current one spans `max_ns`. 0 leaves the duration unbounded.
+        #[link_name = "mux_segment_set_max_cluster_duration"]
+        pub fn segment_set_max_cluster_duration(segment: SegmentMutPtr, max_ns: u64) -> ResultCode;
+        /// Starts a new cluster for the next frame, which also writes out any audio held back for a video frame.
+        #[link_name = "mux_segment_force_new_cluster"]
+        pub fn segment_force_new_cluster(segment: SegmentMutPtr) -> ResultCode;
+        /// Sets whether audio frames are held back until the next video frame, so that the audio up to a video
+        /// keyframe goes in the keyframe's cluster. Adding a video track turns this on.
+        #[link_name = "mux_segment_set_hold_audio"]
+        pub fn segment_set_hold_audio(segment: SegmentMutPtr, hold: bool) -> ResultCode;
+        /// Adds a chapter with one title. `language` may be null, for the default of `eng`. The `TimecodeScale` must
+        /// be set before any chapters are added.
+        #[link_name = "mux_segment_add_chapter"]
+        pub fn segment_add_chapter(
+            segment: SegmentMutPtr,
+            id: *const c_char,
+            start_ns: u64,
+            end_ns: u64,
+            title: *const c_char,
+            language: *const c_char,
+        ) -> ResultCode;
+        /// Adds an empty tag, to which [`segment_add_simple_tag`] adds.
+        #[link_name = "mux_segment_add_tag"]
+        pub fn segment_add_tag(segment: SegmentMutPtr) -> ResultCode;
+        /// Fails with [`ResultCode::BadParam`] if no tag has been added yet.
+        #[link_name = "mux_segment_add_simple_tag"]
+        pub fn segment_add_simple_tag(segment: SegmentMutPtr, name: *const c_char, value: *const c_char) -> ResultCode;
         #[link_name = "mux_finalize_segment"]
         pub fn finalize_segment(segment: SegmentMutPtr, duration: u64) -> ResultCode;
         #[link_name = "mux_delete_segment"]
@@ -108,6 +341,118 @@ pub mod mux {
             timestamp_ns: u64,
             keyframe: bool,
         ) -> ResultCode;
+        #[link_name = "mux_segment_add_frame_with_discard_padding"]
+        pub fn segment_add_frame_with_discard_padding(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            frame: *const u8,
+            length: usize,
+            timestamp_ns: u64,
+            keyframe: bool,
+            discard_padding_ns: i64,
+        ) -> ResultCode;
+        /// Writes the frame as a `BlockGroup` if it has an addition, a duration, or a discard padding. `additional`
+        /// may be null if `additional_length` is 0, in which case there is no addition, and a `duration_ns` of 0
+        /// means there is no `BlockDuration`.
+        #[link_name = "mux_segment_add_generic_frame"]
+        pub fn segment_add_generic_frame(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            frame: *const u8,
+            length: usize,
+            timestamp_ns: u64,
+            keyframe: bool,
+            additional: *const u8,
+            additional_length: usize,
+            add_id: u64,
+            duration_ns: u64,
+            discard_padding_ns: i64,
+        ) -> ResultCode;
+        /// Writes a frame of a video track as a `BlockGroup` with a `ReferenceBlock` for each of the `reference_count`
+        /// offsets at `references_ns`, in nanoseconds from `timestamp_ns`, to the frames it references. Each must be
+        /// at or before the frame, and not before 0. The frame isn't a keyframe, and the other parameters are those
+        /// of `segment_add_generic_frame`.
+        #[link_name = "mux_segment_add_referenced_frame"]
+        pub fn segment_add_referenced_frame(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            frame: *const u8,
+            length: usize,
+            timestamp_ns: u64,
+            additional: *const u8,
+            additional_length: usize,
+            add_id: u64,
+            duration_ns: u64,
+            references_ns: *const i64,
+            reference_count: usize,
+        ) -> ResultCode;
+        #[link_name = "mux_segment_set_codec_delay"]
+        pub fn segment_set_codec_delay(segment: SegmentMutPtr, track_num: TrackNum, codec_delay_ns: u64) -> ResultCode;
+        /// Sets the track's `TrackUID`. Fails for 0, or a UID that another track of the segment has.
+        #[link_name = "mux_segment_set_track_uid"]
+        pub fn segment_set_track_uid(segment: SegmentMutPtr, track_num: TrackNum, uid: u64) -> ResultCode;
+        /// Gets the track's `TrackUID`, which `libwebm` generates when the track is added.
+        #[link_name = "mux_segment_get_track_uid"]
+        pub fn segment_get_track_uid(segment: SegmentMutPtr, track_num: TrackNum, uid_out: *mut u64) -> ResultCode;
+        #[link_name = "mux_segment_set_track_name"]
+        pub fn segment_set_track_name(segment: SegmentMutPtr, track_num: TrackNum, name: *const c_char) -> ResultCode;
+        #[link_name = "mux_segment_set_track_language"]
+        pub fn segment_set_track_language(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            language: *const c_char,
+        ) -> ResultCode;
+        /// Sets the track's `LanguageBCP47`, a BCP 47 language tag such as `en-GB`, which players prefer to its
+        /// `Language`.
+        #[link_name = "mux_segment_set_track_language_bcp47"]
+        pub fn segment_set_track_language_bcp47(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            language: *const c_char,
+        ) -> ResultCode;
+        /// Sets the track's `CodecName`, a human-readable name for its codec. An empty name isn't written.
+        #[link_name = "mux_segment_set_codec_name"]
+        pub fn segment_set_codec_name(segment: SegmentMutPtr, track_num: TrackNum, name: *const c_char) -> ResultCode;
+        #[link_name = "mux_segment_set_seek_pre_roll"]
+        pub fn segment_set_seek_pre_roll(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            seek_pre_roll_ns: u64,
+        ) -> ResultCode;
+        /// Sets the track's `DefaultDuration`, the duration of each of its frames, in nanoseconds. Fails for 0.
+        #[link_name = "mux_segment_set_default_duration"]
+        pub fn segment_set_default_duration(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            default_duration_ns: u64,
+        ) -> ResultCode;
+        /// Sets the track's `FlagDefault`, which is only written when cleared, as Matroska takes it to be set when
+        /// missing.
+        #[link_name = "mux_segment_set_track_default"]
+        pub fn segment_set_track_default(segment: SegmentMutPtr, track_num: TrackNum, flag_default: bool) -> ResultCode;
+        /// Sets the track's `FlagEnabled`, which is only written when cleared, as Matroska takes it to be set when
+        /// missing.
+        #[link_name = "mux_segment_set_track_enabled"]
+        pub fn segment_set_track_enabled(segment: SegmentMutPtr, track_num: TrackNum, flag_enabled: bool) -> ResultCode;
+        /// Sets the track's `FlagForced`, which is only written when set, as Matroska takes it to be cleared when
+        /// missing.
+        #[link_name = "mux_segment_set_track_forced"]
+        pub fn segment_set_track_forced(segment: SegmentMutPtr, track_num: TrackNum, flag_forced: bool) -> ResultCode;
+        /// Marks the track's frames as encrypted with AES, in whole frames, under the given key ID. Only
+        /// [`AES_CTR_CIPHER_MODE`] is supported, and a track can only be encrypted once.
+        #[link_name = "mux_segment_add_content_encryption"]
+        pub fn segment_add_content_encryption(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            key_id: *const u8,
+            key_id_len: usize,
+            cipher_mode: u32,
+        ) -> ResultCode;
+        /// Removes the track with the given number, which must be the one added last, as if it had never been added.
+        #[link_name = "mux_segment_remove_track"]
+        pub fn segment_remove_track(segment: SegmentMutPtr, track_num: TrackNum) -> ResultCode;
+        #[link_name = "mux_segment_set_cues_track"]
+        pub fn segment_set_cues_track(segment: SegmentMutPtr, track_num: TrackNum) -> ResultCode;
         #[link_name = "mux_segment_set_codec_private"]
         pub fn segment_set_codec_private(
             segment: SegmentMutPtr,
@@ -115,6 +460,374 @@ pub mod mux {
             data: *const u8,
             len: i32,
         ) -> ResultCode;
+
+        /// Fails with [`ResultCode::BadParam`] unless each argument equals the matching field of `probe`. Otherwise,
+        /// replaces the fields with `u8 + 1`, `!flag`, `-i32`, `!u32`, `-i64`, `!u64`, `size * 2`, `f32 * 2`, `-f64`
+        /// and `ptr + 1`.
+        #[link_name = "mux_abi_probe"]
+        pub fn abi_probe(
+            probe: *mut AbiProbe,
+            u8: u8,
+            flag: bool,
+            i32: i32,
+            u32: u32,
+            i64: i64,
+            u64: u64,
+            size: usize,
+            f32: f32,
+            f64: f64,
+            ptr: *const u8,
+        ) -> ResultCode;
+        /// Describes the signature of the `index`th exported `mux_` function in the notation of `abi.h`, or returns
+        /// null past the end. The strings live as long as the program.
+        #[link_name = "mux_abi_binding"]
+        pub fn abi_binding(index: usize, name_out: *mut *const c_char) -> *const c_char;
+    }
+}
+
+pub mod parser {
+    use core::ffi::{c_char, c_void};
+    use core::ptr::NonNull;
+
+    #[repr(C)]
+    pub struct Parser {
+        _opaque_c_aligned: *mut c_void,
+    }
+    pub type ParserMutPtr = *mut Parser;
+    pub type ParserNonNullPtr = NonNull<Parser>;
+
+    #[repr(C)]
+    pub struct Cluster {
+        _opaque_c_aligned: *mut c_void,
+    }
+    pub type ClusterPtr = *const Cluster;
+
+    #[repr(C)]
+    pub struct BlockEntry {
+        _opaque_c_aligned: *mut c_void,
+    }
+    pub type BlockEntryPtr = *const BlockEntry;
+
+    /// Reads exactly `len` bytes at the given absolute position into the buffer.
+    pub type ReaderReadFn = extern "C" fn(*mut c_void, u64, usize, *mut u8) -> bool;
+    /// Reports the total length of the source (or -1 if unknown) and how many bytes are currently available.
+    pub type ReaderLengthFn = extern "C" fn(*mut c_void, *mut i64, *mut i64) -> bool;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(i32)]
+    pub enum ResultCode {
+        /// The function completed without error
+        Ok = 0,
+
+        /// An invalid parameter was passed (e.g. a null pointer or an out-of-range index)
+        BadParam = -1,
+
+        /// `libwebm` could not parse the data. This includes failed reads from the underlying source.
+        InvalidFile = -2,
+
+        /// More data is needed before parsing can continue.
+        BufferNotFull = -3,
+    }
+
+    pub const TRACK_TYPE_VIDEO: i64 = 1;
+    pub const TRACK_TYPE_AUDIO: i64 = 2;
+
+    // top-level elements located by the parser, or listed in the SeekHead
+    pub const ELEMENT_CHAPTERS: u64 = 0;
+    pub const ELEMENT_TAGS: u64 = 1;
+    pub const ELEMENT_CUES: u64 = 2;
+    /// The size is as declared, even past the end of a file that was cut short, and 0 if the segment's size is
+    /// unknown
+    pub const ELEMENT_SEGMENT: u64 = 3;
+    /// Only found through the `SeekHead`
+    pub const ELEMENT_ATTACHMENTS: u64 = 4;
+    pub const ELEMENT_SEEK_HEAD: u64 = 5;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct TrackInfo {
+        pub number: u64,
+        pub uid: u64,
+        pub track_type: i64,
+        pub codec_id: *const c_char,
+        pub codec_private: *const u8,
+        pub codec_private_len: usize,
+        pub default_duration: u64,
+        pub codec_delay: u64,
+        pub seek_pre_roll: u64,
+        pub max_block_addition_id: u64,
+        /// Null if absent
+        pub name: *const c_char,
+        /// Null if absent
+        pub language: *const c_char,
+        /// Payload range of the `LanguageIETF` element, -1 if absent
+        pub language_ietf_pos: i64,
+        pub language_ietf_len: i64,
+
+        /// Video only, zero otherwise
+        pub width: i64,
+        /// Video only, zero otherwise
+        pub height: i64,
+        /// Video only, zero otherwise; `width` if the track has no `DisplayWidth`
+        pub display_width: i64,
+        /// Video only, zero otherwise; `height` if the track has no `DisplayHeight`
+        pub display_height: i64,
+
+        /// Audio only, zero otherwise
+        pub sampling_rate: f64,
+        /// Audio only, zero otherwise
+        pub channels: i64,
+        /// Audio only, zero otherwise
+        pub bit_depth: i64,
+    }
+
+    /// Integer fields are -1 and float fields are negative if absent.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct ColourInfo {
+        pub matrix_coefficients: i64,
+        pub bits_per_channel: i64,
+        pub chroma_subsampling_horz: i64,
+        pub chroma_subsampling_vert: i64,
+        pub cb_subsampling_horz: i64,
+        pub cb_subsampling_vert: i64,
+        pub chroma_siting_horz: i64,
+        pub chroma_siting_vert: i64,
+        pub range: i64,
+        pub transfer_characteristics: i64,
+        pub primaries: i64,
+        pub max_cll: i64,
+        pub max_fall: i64,
+
+        pub has_mastering_metadata: bool,
+        /// Red, green, blue and white point, as (x, y) pairs
+        pub chromaticities: [[f32; 2]; 4],
+        pub luminance_max: f32,
+        pub luminance_min: f32,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct ProjectionInfo {
+        pub projection_type: i64,
+        pub private_data: *const u8,
+        pub private_data_len: usize,
+
+        pub has_pose_yaw: bool,
+        pub pose_yaw: f32,
+        pub has_pose_pitch: bool,
+        pub pose_pitch: f32,
+        pub has_pose_roll: bool,
+        pub pose_roll: f32,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct EbmlHeader {
+        pub version: i64,
+        pub read_version: i64,
+        pub max_id_length: i64,
+        pub max_size_length: i64,
+        /// Null if absent
+        pub doc_type: *const c_char,
+        pub doc_type_version: i64,
+        pub doc_type_read_version: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct SegmentInfo {
+        pub timecode_scale: i64,
+        /// -1 if absent
+        pub duration_ns: i64,
+        /// Null if absent
+        pub muxing_app: *const c_char,
+        /// Null if absent
+        pub writing_app: *const c_char,
+        /// Null if absent
+        pub title: *const c_char,
+
+        pub has_date_utc: bool,
+        /// Nanoseconds since 2001-01-01T00:00:00 UTC
+        pub date_utc: i64,
+        pub has_segment_uid: bool,
+        pub segment_uid: [u8; 16],
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct ClusterInfo {
+        pub time_ns: i64,
+        /// Absolute position of the start of the Cluster element
+        pub element_start: i64,
+        pub element_size: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct BlockInfo {
+        pub track_number: u64,
+        pub time_ns: i64,
+        pub keyframe: bool,
+        pub frame_count: i32,
+        /// From BlockDuration, -1 if absent (always for SimpleBlocks)
+        pub duration_ns: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct BlockAddition {
+        pub id: u64,
+        /// Absolute position of the BlockAdditional payload
+        pub pos: i64,
+        pub len: i64,
+    }
+
+    bindings! {
+        #[link_name = "parser_new"]
+        pub fn new_parser(
+            read: Option<ReaderReadFn>,
+            length: Option<ReaderLengthFn>,
+            user_data: *mut c_void,
+        ) -> ParserMutPtr;
+        #[link_name = "parser_delete"]
+        pub fn delete_parser(parser: ParserMutPtr);
+        #[link_name = "parser_libwebm_version"]
+        pub fn libwebm_version(major: *mut i32, minor: *mut i32, build: *mut i32, revision: *mut i32);
+        #[link_name = "parser_open"]
+        pub fn open(parser: ParserMutPtr) -> ResultCode;
+
+        #[link_name = "parser_segment_info"]
+        pub fn segment_info(parser: ParserMutPtr, info_out: *mut SegmentInfo) -> ResultCode;
+        #[link_name = "parser_ebml_header"]
+        pub fn ebml_header(parser: ParserMutPtr, header_out: *mut EbmlHeader) -> ResultCode;
+
+        #[link_name = "parser_track_count"]
+        pub fn track_count(parser: ParserMutPtr) -> u64;
+        #[link_name = "parser_track_info"]
+        pub fn track_info(parser: ParserMutPtr, index: u64, info_out: *mut TrackInfo) -> ResultCode;
+        #[link_name = "parser_track_colour"]
+        pub fn track_colour(
+            parser: ParserMutPtr,
+            track_number: u64,
+            info_out: *mut ColourInfo,
+            present_out: *mut bool,
+        ) -> ResultCode;
+        #[link_name = "parser_track_projection"]
+        pub fn track_projection(
+            parser: ParserMutPtr,
+            track_number: u64,
+            info_out: *mut ProjectionInfo,
+            present_out: *mut bool,
+        ) -> ResultCode;
+
+        #[link_name = "parser_element_payload"]
+        pub fn element_payload(
+            parser: ParserMutPtr,
+            element: u64,
+            start_out: *mut i64,
+            size_out: *mut i64,
+        ) -> ResultCode;
+
+        #[link_name = "parser_first_cluster"]
+        pub fn first_cluster(parser: ParserMutPtr, cluster_out: *mut ClusterPtr) -> ResultCode;
+        #[link_name = "parser_cluster_at"]
+        pub fn cluster_at(parser: ParserMutPtr, pos: i64, cluster_out: *mut ClusterPtr) -> ResultCode;
+        #[link_name = "parser_cues_at"]
+        pub fn cues_at(parser: ParserMutPtr, pos: i64) -> ResultCode;
+        #[link_name = "parser_last_indexed_cluster"]
+        pub fn last_indexed_cluster(parser: ParserMutPtr, pos_out: *mut i64) -> ResultCode;
+        #[link_name = "parser_next_cluster"]
+        pub fn next_cluster(
+            parser: ParserMutPtr,
+            current: ClusterPtr,
+            cluster_out: *mut ClusterPtr,
+        ) -> ResultCode;
+        #[link_name = "parser_cluster_first_entry"]
+        pub fn cluster_first_entry(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            entry_out: *mut BlockEntryPtr,
+        ) -> ResultCode;
+        #[link_name = "parser_cluster_next_entry"]
+        pub fn cluster_next_entry(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            current: BlockEntryPtr,
+            entry_out: *mut BlockEntryPtr,
+        ) -> ResultCode;
+        #[link_name = "parser_cluster_info"]
+        pub fn cluster_info(parser: ParserMutPtr, cluster: ClusterPtr, info_out: *mut ClusterInfo) -> ResultCode;
+        #[link_name = "parser_cue_seek"]
+        pub fn cue_seek(
+            parser: ParserMutPtr,
+            track_number: u64,
+            time_ns: i64,
+            cluster_out: *mut ClusterPtr,
+            entry_out: *mut BlockEntryPtr,
+        ) -> ResultCode;
+        #[link_name = "parser_block_info"]
+        pub fn block_info(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            entry: BlockEntryPtr,
+            info_out: *mut BlockInfo,
+        ) -> ResultCode;
+        #[link_name = "parser_block_addition_count"]
+        pub fn block_addition_count(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            entry: BlockEntryPtr,
+            count_out: *mut u64,
+        ) -> ResultCode;
+        #[link_name = "parser_block_addition"]
+        pub fn block_addition(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            entry: BlockEntryPtr,
+            index: u64,
+            addition_out: *mut BlockAddition,
+        ) -> ResultCode;
+        #[link_name = "parser_block_discard_padding"]
+        pub fn block_discard_padding(
+            parser: ParserMutPtr,
+            cluster: ClusterPtr,
+            entry: BlockEntryPtr,
+            present_out: *mut bool,
+            padding_out: *mut i64,
+        ) -> ResultCode;
+        #[link_name = "parser_block_frame"]
+        pub fn block_frame(
+            parser: ParserMutPtr,
+            entry: BlockEntryPtr,
+            index: i32,
+            pos_out: *mut i64,
+            len_out: *mut i64,
+        ) -> ResultCode;
+        /// `buffer_len` must be at least the frame's length, as given by [`block_frame`].
+        #[link_name = "parser_read_frame"]
+        pub fn read_frame(
+            parser: ParserMutPtr,
+            entry: BlockEntryPtr,
+            index: i32,
+            buffer: *mut u8,
+            buffer_len: usize,
+        ) -> ResultCode;
+
+        /// Describes the signature of the `index`th exported `parser_` function in the notation of `abi.h`, or
+        /// returns null past the end. The strings live as long as the program.
+        #[link_name = "parser_abi_binding"]
+        pub fn abi_binding(index: usize, name_out: *mut *const c_char) -> *const c_char;
+        /// Describes the `index`th field of the structs above, returning the name of its struct, or null past the
+        /// end. Null pointers are skipped.
+        #[link_name = "parser_abi_field"]
+        pub fn abi_field(
+            index: usize,
+            field_out: *mut *const c_char,
+            offset_out: *mut usize,
+            size_out: *mut usize,
+            type_size_out: *mut usize,
+        ) -> *const c_char;
     }
 }
 
@@ -126,3 +839,703 @@ fn smoke_test() {
         mux::delete_segment(segment);
     }
 }
+
+/// Test callbacks muxing into a `(Vec<u8>, usize)` of the output and the write position, and parsing from a `Vec<u8>`.
+#[cfg(test)]
+extern "C" fn mem_write(data: *mut core::ffi::c_void, buf: *const core::ffi::c_void, len: usize) -> bool {
+    let (out, pos) = unsafe { &mut *data.cast::<(Vec<u8>, usize)>() };
+    let buf = unsafe { core::slice::from_raw_parts(buf.cast::<u8>(), len) };
+    let end = *pos + len;
+    if out.len() < end {
+        out.resize(end, 0);
+    }
+    out[*pos..end].copy_from_slice(buf);
+    *pos = end;
+    true
+}
+
+#[cfg(test)]
+extern "C" fn mem_get_pos(data: *mut core::ffi::c_void) -> u64 {
+    unsafe { (*data.cast::<(Vec<u8>, usize)>()).1 as u64 }
+}
+
+#[cfg(test)]
+extern "C" fn mem_set_pos(data: *mut core::ffi::c_void, pos: u64) -> bool {
+    unsafe { (*data.cast::<(Vec<u8>, usize)>()).1 = pos as usize };
+    true
+}
+
+#[cfg(test)]
+extern "C" fn mem_read(data: *mut core::ffi::c_void, pos: u64, len: usize, buf: *mut u8) -> bool {
+    let file = unsafe { &*data.cast::<Vec<u8>>() };
+    let Some(src) = file.get(pos as usize..pos as usize + len) else {
+        return false;
+    };
+    unsafe { core::slice::from_raw_parts_mut(buf, len) }.copy_from_slice(src);
+    true
+}
+
+#[cfg(test)]
+extern "C" fn mem_length(data: *mut core::ffi::c_void, total: *mut i64, available: *mut i64) -> bool {
+    let file = unsafe { &*data.cast::<Vec<u8>>() };
+    unsafe {
+        *total = file.len() as i64;
+        *available = file.len() as i64;
+    }
+    true
+}
+
+#[test]
+fn parser_smoke_test() {
+    use core::ptr::null;
+
+    let frames: [&[u8]; 3] = [b"first frame", b"second", b"third frame!"];
+    let mut output: (Vec<u8>, usize) = (Vec::new(), 0);
+    unsafe {
+        let writer = mux::new_writer(
+            Some(mem_write),
+            Some(mem_get_pos),
+            Some(mem_set_pos),
+            None,
+            core::ptr::from_mut(&mut output).cast(),
+        );
+        let segment = mux::new_segment();
+        assert_eq!(mux::initialize_segment(segment, writer), mux::ResultCode::Ok);
+        let mut track = 0;
+        let result = mux::segment_add_video_track(segment, 64, 48, 0, mux::VP9_CODEC_ID, &mut track);
+        assert_eq!(result, mux::ResultCode::Ok);
+        for (i, frame) in frames.iter().enumerate() {
+            let timestamp = i as u64 * 1_000_000;
+            let result = mux::segment_add_frame(segment, track, frame.as_ptr(), frame.len(), timestamp, i == 0);
+            assert_eq!(result, mux::ResultCode::Ok);
+        }
+        assert_eq!(mux::finalize_segment(segment, 0), mux::ResultCode::Ok);
+        mux::delete_segment(segment);
+        mux::delete_writer(writer);
+    }
+
+    let mut file = output.0;
+    unsafe {
+        let parser = parser::new_parser(Some(mem_read), Some(mem_length), core::ptr::from_mut(&mut file).cast());
+        assert!(!parser.is_null());
+        assert_eq!(parser::open(parser), parser::ResultCode::Ok);
+        assert_eq!(parser::track_count(parser), 1);
+
+        let mut cluster = null();
+        assert_eq!(parser::first_cluster(parser, &mut cluster), parser::ResultCode::Ok);
+        let mut entry = null();
+        assert_eq!(parser::cluster_first_entry(parser, cluster, &mut entry), parser::ResultCode::Ok);
+
+        let mut read_frames = Vec::new();
+        while !entry.is_null() {
+            let (mut pos, mut len) = (0, 0);
+            assert_eq!(parser::block_frame(parser, entry, 0, &mut pos, &mut len), parser::ResultCode::Ok);
+            let mut buffer = vec![0; len as usize];
+            let result = parser::read_frame(parser, entry, 0, buffer.as_mut_ptr(), buffer.len());
+            assert_eq!(result, parser::ResultCode::Ok);
+            // A buffer that is too small is rejected rather than overrun
+            let result = parser::read_frame(parser, entry, 0, buffer.as_mut_ptr(), buffer.len() - 1);
+            assert_eq!(result, parser::ResultCode::BadParam);
+            read_frames.push(buffer);
+
+            let current = entry;
+            assert_eq!(parser::cluster_next_entry(parser, cluster, current, &mut entry), parser::ResultCode::Ok);
+        }
+        assert_eq!(read_frames, frames);
+        parser::delete_parser(parser);
+
+        // Null handles are errors rather than crashes
+        assert_eq!(parser::open(core::ptr::null_mut()), parser::ResultCode::BadParam);
+        assert_eq!(parser::track_count(core::ptr::null_mut()), 0);
+        let mut cluster = null();
+        assert_eq!(parser::first_cluster(core::ptr::null_mut(), &mut cluster), parser::ResultCode::BadParam);
+        let result = parser::read_frame(core::ptr::null_mut(), null(), 0, core::ptr::null_mut(), 0);
+        assert_eq!(result, parser::ResultCode::BadParam);
+        assert!(parser::new_parser(None, None, core::ptr::null_mut()).is_null());
+    }
+}
+
+/// Calls each binding with null handles or out-pointers, unknown track numbers and empty data, which must be reported
+/// as errors rather than crash. Each binding gets a line per bad argument here.
+#[test]
+fn rejects_invalid_arguments() {
+    use core::mem::zeroed;
+    use core::ptr::{null, null_mut};
+
+    macro_rules! assert_results {
+        ($($call:expr => $expected:expr,)*) => {
+            $(assert_eq!(unsafe { $call }, $expected, "{}", stringify!($call));)*
+        };
+    }
+
+    let mut output: (Vec<u8>, usize) = (Vec::new(), 0);
+    let output_ptr = core::ptr::from_mut(&mut output).cast();
+    let writer = unsafe { mux::new_writer(Some(mem_write), Some(mem_get_pos), Some(mem_set_pos), None, output_ptr) };
+    let segment = unsafe { mux::new_segment() };
+    let (mut track, mut seed, mut size) = (0, 1, 0);
+    unsafe {
+        assert_eq!(mux::initialize_segment(segment, writer), mux::ResultCode::Ok);
+        let result = mux::segment_add_video_track(segment, 64, 48, 0, mux::VP9_CODEC_ID, &mut track);
+        assert_eq!(result, mux::ResultCode::Ok);
+    }
+    let unknown = track + 100;
+    let (frame, name) = (b"frame", c"name");
+    // The DCI-P3 primaries with a D65 white point
+    let chromaticities = [0.68, 0.32, 0.265, 0.69, 0.15, 0.06, 0.3127, 0.329];
+
+    {
+        use mux::ResultCode::BadParam;
+        use mux::*;
+        assert_results! {
+            new_writer(None, Some(mem_get_pos), None, None, null_mut()).is_null() => true,
+            new_writer(Some(mem_write), None, None, None, null_mut()).is_null() => true,
+            make_uid(null_mut()) => 0,
+            make_uid(&mut seed) != 0 => true,
+            initialize_segment(null_mut(), writer) => BadParam,
+            initialize_segment(segment, null_mut()) => BadParam,
+            mux_set_writing_app(null_mut(), name.as_ptr()) => BadParam,
+            mux_set_writing_app(segment, null()) => BadParam,
+            segment_last_error(null_mut(), null_mut(), 0) => 0,
+            segment_last_error(segment, null_mut(), 0) => 0,
+            segment_cluster_count(null_mut()) => 0,
+            segment_current_cluster_size(null_mut(), &mut size) => BadParam,
+            segment_current_cluster_size(segment, null_mut()) => BadParam,
+            segment_current_cluster_size(segment, &mut size) => BadParam,
+            mux_set_color(null_mut(), track, 8, 1, 1, 0) => BadParam,
+            mux_set_color(segment, unknown, 8, 1, 1, 0) => BadParam,
+            mux_set_chroma_siting(null_mut(), track, 1, 2) => BadParam,
+            mux_set_chroma_siting(segment, unknown, 1, 2) => BadParam,
+            mux_set_chroma_siting(segment, track, 3, 2) => BadParam,
+            mux_set_chroma_siting(segment, track, 1, 3) => BadParam,
+            mux_set_color_description(null_mut(), track, 1, 1, 1, 1, 1, 2) => BadParam,
+            mux_set_color_description(segment, unknown, 1, 1, 1, 1, 1, 2) => BadParam,
+            mux_set_color_description(segment, track, 200, 1, 1, 1, 1, 2) => BadParam,
+            mux_set_color_description(segment, track, 1, 1, 1, 1, 3, 2) => BadParam,
+            mux_color_description_is_valid(200, 1, 1, 1, 1, 2) => false,
+            mux_color_description_is_valid(1, 1, 1, 1, 3, 2) => false,
+            mux_color_description_is_valid(1, 13, 0, 2, 0, 0) => true,
+            mux_set_color_code_points(null_mut(), track, 1, 1, 1) => BadParam,
+            mux_set_color_code_points(segment, unknown, 1, 1, 1) => BadParam,
+            mux_set_color_code_points(segment, track, 11, 1, 1) => BadParam,
+            mux_set_color_code_points(segment, track, 1, 19, 1) => BadParam,
+            mux_set_color_code_points(segment, track, 1, 1, 11) => BadParam,
+            mux_set_display_size(null_mut(), track, 85, 48) => BadParam,
+            mux_set_display_size(segment, unknown, 85, 48) => BadParam,
+            mux_set_display_size(segment, track, 0, 48) => BadParam,
+            mux_set_display_size(segment, track, 85, 0) => BadParam,
+            mux_set_stereo_mode(null_mut(), track, 1) => BadParam,
+            mux_set_stereo_mode(segment, unknown, 1) => BadParam,
+            mux_set_stereo_mode(segment, track, 4) => BadParam,
+            mux_set_stereo_mode(segment, track, 15) => BadParam,
+            mux_set_output_sample_rate(null_mut(), track, 48_000.0) => BadParam,
+            mux_set_output_sample_rate(segment, unknown, 48_000.0) => BadParam,
+            mux_set_output_sample_rate(segment, track, 48_000.0) => BadParam,
+            mux_set_audio_bit_depth(null_mut(), track, 16) => BadParam,
+            mux_set_audio_bit_depth(segment, unknown, 16) => BadParam,
+            mux_set_audio_bit_depth(segment, track, 16) => BadParam,
+            mux_set_aspect_ratio_type(null_mut(), track, 1) => BadParam,
+            mux_set_aspect_ratio_type(segment, unknown, 1) => BadParam,
+            mux_set_aspect_ratio_type(segment, track, 3) => BadParam,
+            mux_set_interlacing(null_mut(), track, 2, 0) => BadParam,
+            mux_set_interlacing(segment, unknown, 2, 0) => BadParam,
+            mux_set_interlacing(segment, track, 3, 0) => BadParam,
+            mux_set_interlacing(segment, track, 1, 3) => BadParam,
+            mux_set_projection(null_mut(), track, 1, null(), 0, 0.0, 0.0, 0.0) => BadParam,
+            mux_set_projection(segment, unknown, 1, null(), 0, 0.0, 0.0, 0.0) => BadParam,
+            mux_set_projection(segment, track, 4, null(), 0, 0.0, 0.0, 0.0) => BadParam,
+            mux_set_projection(segment, track, 1, null(), 4, 0.0, 0.0, 0.0) => BadParam,
+            mux_set_projection(segment, track, 1, frame.as_ptr(), 0, 0.0, 0.0, 0.0) => BadParam,
+            mux_set_frame_rate(null_mut(), track, 30.0) => BadParam,
+            mux_set_frame_rate(segment, unknown, 30.0) => BadParam,
+            mux_set_frame_rate(segment, track, 0.0) => BadParam,
+            mux_set_frame_rate(segment, track, -30.0) => BadParam,
+            mux_set_frame_rate(segment, track, f64::NAN) => BadParam,
+            mux_set_frame_rate(segment, track, f64::INFINITY) => BadParam,
+            mux_set_frame_rate(segment, track, 1e300) => BadParam,
+            mux_set_frame_rate(segment, track, 1e-300) => BadParam,
+            mux_enable_alpha(null_mut(), track) => BadParam,
+            mux_enable_alpha(segment, unknown) => BadParam,
+            mux_set_pixel_crop(null_mut(), track, 0, 8, 0, 0) => BadParam,
+            mux_set_pixel_crop(segment, unknown, 0, 8, 0, 0) => BadParam,
+            mux_set_pixel_crop(segment, track, 24, 24, 0, 0) => BadParam,
+            mux_set_pixel_crop(segment, track, 0, 0, 64, 0) => BadParam,
+            mux_set_pixel_crop(segment, track, 0, 0, u32::MAX, u32::MAX) => BadParam,
+            mux_set_content_light_level(null_mut(), track, 1000, 400) => BadParam,
+            mux_set_content_light_level(segment, unknown, 1000, 400) => BadParam,
+            mux_set_mastering_metadata(null_mut(), track, &chromaticities, 1000.0, 0.01) => BadParam,
+            mux_set_mastering_metadata(segment, unknown, &chromaticities, 1000.0, 0.01) => BadParam,
+            mux_set_mastering_metadata(segment, track, null(), 1000.0, 0.01) => BadParam,
+            mux_set_mastering_metadata(segment, track, &[1.5; 8], 1000.0, 0.01) => BadParam,
+            mux_set_mastering_metadata(segment, track, &chromaticities, 100_000.0, 0.01) => BadParam,
+            mux_set_mastering_metadata(segment, track, &chromaticities, 1.0, 10.0) => BadParam,
+            mux_set_mastering_metadata(segment, track, &chromaticities, 1000.0, -1.0) => BadParam,
+            mux_set_pixel_aspect_ratio(null_mut(), track, 10, 11) => BadParam,
+            mux_set_pixel_aspect_ratio(segment, unknown, 10, 11) => BadParam,
+            mux_set_pixel_aspect_ratio(segment, track, 0, 11) => BadParam,
+            mux_set_pixel_aspect_ratio(segment, track, 10, 0) => BadParam,
+            segment_add_video_track(null_mut(), 64, 48, 0, VP9_CODEC_ID, &mut track) => BadParam,
+            segment_add_video_track(segment, 64, 48, 0, VP9_CODEC_ID, null_mut()) => BadParam,
+            segment_add_video_track(segment, 64, 48, 0, 99, &mut track) => BadParam,
+            segment_add_audio_track(null_mut(), 48000, 2, 0, OPUS_CODEC_ID, &mut track) => BadParam,
+            segment_add_audio_track(segment, 48000, 2, 0, OPUS_CODEC_ID, null_mut()) => BadParam,
+            segment_add_audio_track(segment, 48000, 2, 0, 99, &mut track) => BadParam,
+            segment_add_frame(null_mut(), track, frame.as_ptr(), frame.len(), 0, true) => BadParam,
+            segment_add_frame(segment, unknown, frame.as_ptr(), frame.len(), 0, true) => BadParam,
+            segment_add_frame(segment, track, null(), frame.len(), 0, true) => BadParam,
+            segment_add_frame(segment, track, frame.as_ptr(), 0, 0, true) => BadParam,
+            segment_add_frame_with_discard_padding(null_mut(), track, frame.as_ptr(), 5, 0, true, 0) => BadParam,
+            segment_add_frame_with_discard_padding(segment, unknown, frame.as_ptr(), 5, 0, true, 0) => BadParam,
+            segment_add_frame_with_discard_padding(segment, track, null(), 5, 0, true, 0) => BadParam,
+            segment_add_frame_with_discard_padding(segment, track, frame.as_ptr(), 0, 0, true, 0) => BadParam,
+            segment_add_generic_frame(null_mut(), track, frame.as_ptr(), 5, 0, true, null(), 0, 0, 0, 0) => BadParam,
+            segment_add_generic_frame(segment, unknown, frame.as_ptr(), 5, 0, true, null(), 0, 0, 0, 0) => BadParam,
+            segment_add_generic_frame(segment, track, null(), 5, 0, true, null(), 0, 0, 0, 0) => BadParam,
+            segment_add_generic_frame(segment, track, frame.as_ptr(), 0, 0, true, null(), 0, 0, 0, 0) => BadParam,
+            segment_add_generic_frame(segment, track, frame.as_ptr(), 5, 0, true, null(), 5, 1, 0, 0) => BadParam,
+            segment_add_referenced_frame(null_mut(), track, frame.as_ptr(), 5, 10, null(), 0, 0, 0, &-1, 1) => BadParam,
+            segment_add_referenced_frame(segment, unknown, frame.as_ptr(), 5, 10, null(), 0, 0, 0, &-1, 1) => BadParam,
+            segment_add_referenced_frame(segment, track, null(), 5, 10, null(), 0, 0, 0, &-1, 1) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 0, 10, null(), 0, 0, 0, &-1, 1) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 5, 10, null(), 5, 1, 0, &-1, 1) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 5, 10, null(), 0, 0, 0, null(), 1) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 5, 10, null(), 0, 0, 0, &-1, 0) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 5, 10, null(), 0, 0, 0, &1, 1) => BadParam,
+            segment_add_referenced_frame(segment, track, frame.as_ptr(), 5, 10, null(), 0, 0, 0, &-11, 1) => BadParam,
+            segment_set_codec_delay(null_mut(), track, 0) => BadParam,
+            segment_set_codec_delay(segment, unknown, 0) => BadParam,
+            segment_set_track_name(null_mut(), track, name.as_ptr()) => BadParam,
+            segment_set_track_name(segment, unknown, name.as_ptr()) => BadParam,
+            segment_set_track_name(segment, track, null()) => BadParam,
+            segment_set_track_language(null_mut(), track, name.as_ptr()) => BadParam,
+            segment_set_track_language(segment, unknown, name.as_ptr()) => BadParam,
+            segment_set_track_language(segment, track, null()) => BadParam,
+            segment_set_track_language_bcp47(null_mut(), track, name.as_ptr()) => BadParam,
+            segment_set_track_language_bcp47(segment, unknown, name.as_ptr()) => BadParam,
+            segment_set_track_language_bcp47(segment, track, null()) => BadParam,
+            segment_set_codec_name(null_mut(), track, name.as_ptr()) => BadParam,
+            segment_set_codec_name(segment, unknown, name.as_ptr()) => BadParam,
+            segment_set_codec_name(segment, track, null()) => BadParam,
+            segment_set_seek_pre_roll(null_mut(), track, 0) => BadParam,
+            segment_set_seek_pre_roll(segment, unknown, 0) => BadParam,
+            segment_set_default_duration(null_mut(), track, 20_000_000) => BadParam,
+            segment_set_default_duration(segment, unknown, 20_000_000) => BadParam,
+            segment_set_default_duration(segment, track, 0) => BadParam,
+            segment_set_track_default(null_mut(), track, false) => BadParam,
+            segment_set_track_default(segment, unknown, false) => BadParam,
+            segment_set_track_enabled(null_mut(), track, false) => BadParam,
+            segment_set_track_enabled(segment, unknown, false) => BadParam,
+            segment_set_track_forced(null_mut(), track, true) => BadParam,
+            segment_set_track_forced(segment, unknown, true) => BadParam,
+            segment_set_track_uid(null_mut(), track, 1) => BadParam,
+            segment_set_track_uid(segment, unknown, 1) => BadParam,
+            segment_set_track_uid(segment, track, 0) => BadParam,
+            segment_get_track_uid(null_mut(), track, &mut size) => BadParam,
+            segment_get_track_uid(segment, unknown, &mut size) => BadParam,
+            segment_get_track_uid(segment, track, null_mut()) => BadParam,
+            segment_add_content_encryption(null_mut(), track, frame.as_ptr(), 5, AES_CTR_CIPHER_MODE) => BadParam,
+            segment_add_content_encryption(segment, unknown, frame.as_ptr(), 5, AES_CTR_CIPHER_MODE) => BadParam,
+            segment_add_content_encryption(segment, track, null(), 5, AES_CTR_CIPHER_MODE) => BadParam,
+            segment_add_content_encryption(segment, track, frame.as_ptr(), 0, AES_CTR_CIPHER_MODE) => BadParam,
+            segment_add_content_encryption(segment, track, frame.as_ptr(), 5, 0) => BadParam,
+            segment_set_cues_track(null_mut(), track) => BadParam,
+            segment_set_cues_track(segment, unknown) => BadParam,
+            segment_remove_track(null_mut(), track) => BadParam,
+            segment_remove_track(segment, unknown) => BadParam,
+            set_date_utc(null_mut(), 0) => BadParam,
+            set_timecode_scale(null_mut(), 1_000_000) => BadParam,
+            set_timecode_scale(segment, 0) => BadParam,
+            set_live(null_mut(), true) => BadParam,
+            segment_set_max_cluster_duration(null_mut(), 0) => BadParam,
+            segment_force_new_cluster(null_mut()) => BadParam,
+            segment_set_hold_audio(null_mut(), false) => BadParam,
+            segment_add_chapter(null_mut(), name.as_ptr(), 0, 1, name.as_ptr(), null()) => BadParam,
+            segment_add_chapter(segment, null(), 0, 1, name.as_ptr(), null()) => BadParam,
+            segment_add_chapter(segment, name.as_ptr(), 0, 1, null(), null()) => BadParam,
+            segment_add_chapter(segment, name.as_ptr(), 1, 0, name.as_ptr(), null()) => BadParam,
+            segment_add_chapter(segment, name.as_ptr(), 0, u64::MAX, name.as_ptr(), null()) => BadParam,
+            segment_add_tag(null_mut()) => BadParam,
+            segment_add_simple_tag(segment, name.as_ptr(), name.as_ptr()) => BadParam,
+            segment_add_simple_tag(null_mut(), name.as_ptr(), name.as_ptr()) => BadParam,
+            segment_add_simple_tag(segment, null(), name.as_ptr()) => BadParam,
+            segment_add_simple_tag(segment, name.as_ptr(), null()) => BadParam,
+            segment_set_codec_private(null_mut(), track, frame.as_ptr(), 5) => BadParam,
+            segment_set_codec_private(segment, unknown, frame.as_ptr(), 5) => BadParam,
+            segment_set_codec_private(segment, track, null(), 5) => BadParam,
+            segment_set_codec_private(segment, track, frame.as_ptr(), -1) => BadParam,
+            finalize_segment(null_mut(), 0) => BadParam,
+            abi_probe(null_mut(), 0, false, 0, 0, 0, 0, 0, 0.0, 0.0, null()) => BadParam,
+            abi_binding(usize::MAX, null_mut()).is_null() => true,
+        }
+    }
+
+    // The segment describes its last failure, truncated to fit the buffer
+    let mut message = [0u8; 64];
+    let len = unsafe { mux::segment_last_error(segment, message.as_mut_ptr().cast(), message.len()) };
+    assert_eq!(&message[..len], b"SetCodecPrivate: invalid data");
+    let len = unsafe { mux::segment_last_error(segment, message.as_mut_ptr().cast(), 4) };
+    assert_eq!((len, &message[..4]), (29, &b"Set\0"[..]));
+    unsafe {
+        // The segment is still usable after all of the above
+        let result = mux::segment_add_frame(segment, track, frame.as_ptr(), frame.len(), 0, true);
+        assert_eq!(result, mux::ResultCode::Ok);
+        assert_eq!(mux::finalize_segment(segment, 0), mux::ResultCode::Ok);
+        mux::delete_segment(segment);
+        mux::delete_writer(writer);
+        mux::delete_segment(null_mut());
+        mux::delete_writer(null_mut());
+    }
+
+    let mut file = output.0;
+    let mut empty = Vec::<u8>::new();
+    let (file_ptr, empty_ptr) = (core::ptr::from_mut(&mut file).cast(), core::ptr::from_mut(&mut empty).cast());
+    let parser = unsafe { parser::new_parser(Some(mem_read), Some(mem_length), file_ptr) };
+    let unopened = unsafe { parser::new_parser(Some(mem_read), Some(mem_length), empty_ptr) };
+    let (mut cluster, mut entry) = (null(), null());
+    unsafe {
+        assert_eq!(parser::open(parser), parser::ResultCode::Ok);
+        assert_eq!(parser::first_cluster(parser, &mut cluster), parser::ResultCode::Ok);
+        assert_eq!(parser::cluster_first_entry(parser, cluster, &mut entry), parser::ResultCode::Ok);
+    }
+    let (mut pos, mut len, mut count, mut present) = (0, 0, 0, false);
+    let mut buffer = [0; 16];
+
+    {
+        use parser::ResultCode::BadParam;
+        use parser::*;
+        assert_results! {
+            new_parser(None, Some(mem_length), null_mut()).is_null() => true,
+            new_parser(Some(mem_read), None, null_mut()).is_null() => true,
+            open(null_mut()) => BadParam,
+            segment_info(null_mut(), &mut zeroed()) => BadParam,
+            segment_info(unopened, &mut zeroed()) => BadParam,
+            segment_info(parser, null_mut()) => BadParam,
+            ebml_header(null_mut(), &mut zeroed()) => BadParam,
+            ebml_header(parser, null_mut()) => BadParam,
+            track_count(null_mut()) => 0,
+            track_count(unopened) => 0,
+            track_info(null_mut(), 0, &mut zeroed()) => BadParam,
+            track_info(parser, 1, &mut zeroed()) => BadParam,
+            track_info(parser, 0, null_mut()) => BadParam,
+            track_colour(null_mut(), 1, &mut zeroed(), &mut present) => BadParam,
+            track_colour(parser, 100, &mut zeroed(), &mut present) => BadParam,
+            track_colour(parser, 1, null_mut(), &mut present) => BadParam,
+            track_colour(parser, 1, &mut zeroed(), null_mut()) => BadParam,
+            track_projection(null_mut(), 1, &mut zeroed(), &mut present) => BadParam,
+            track_projection(parser, 100, &mut zeroed(), &mut present) => BadParam,
+            track_projection(parser, 1, null_mut(), &mut present) => BadParam,
+            track_projection(parser, 1, &mut zeroed(), null_mut()) => BadParam,
+            element_payload(null_mut(), ELEMENT_CHAPTERS, &mut pos, &mut len) => BadParam,
+            element_payload(parser, 99, &mut pos, &mut len) => BadParam,
+            element_payload(parser, ELEMENT_CHAPTERS, null_mut(), &mut len) => BadParam,
+            element_payload(parser, ELEMENT_CHAPTERS, &mut pos, null_mut()) => BadParam,
+            first_cluster(null_mut(), &mut cluster) => BadParam,
+            first_cluster(unopened, &mut cluster) => BadParam,
+            first_cluster(parser, null_mut()) => BadParam,
+            cluster_at(null_mut(), 0, &mut cluster) => BadParam,
+            cluster_at(parser, -1, &mut cluster) => BadParam,
+            cluster_at(parser, 0, null_mut()) => BadParam,
+            cues_at(null_mut(), 0) => BadParam,
+            cues_at(parser, -1) => BadParam,
+            last_indexed_cluster(null_mut(), &mut pos) => BadParam,
+            last_indexed_cluster(parser, null_mut()) => BadParam,
+            next_cluster(null_mut(), cluster, &mut cluster) => BadParam,
+            next_cluster(parser, null(), &mut cluster) => BadParam,
+            next_cluster(parser, cluster, null_mut()) => BadParam,
+            cluster_first_entry(null_mut(), cluster, &mut entry) => BadParam,
+            cluster_first_entry(parser, null(), &mut entry) => BadParam,
+            cluster_first_entry(parser, cluster, null_mut()) => BadParam,
+            cluster_next_entry(null_mut(), cluster, entry, &mut entry) => BadParam,
+            cluster_next_entry(parser, null(), entry, &mut entry) => BadParam,
+            cluster_next_entry(parser, cluster, null(), &mut entry) => BadParam,
+            cluster_next_entry(parser, cluster, entry, null_mut()) => BadParam,
+            cluster_info(null_mut(), cluster, &mut zeroed()) => BadParam,
+            cluster_info(parser, null(), &mut zeroed()) => BadParam,
+            cluster_info(parser, cluster, null_mut()) => BadParam,
+            cue_seek(null_mut(), 1, 0, &mut cluster, &mut entry) => BadParam,
+            cue_seek(parser, 1, 0, null_mut(), &mut entry) => BadParam,
+            cue_seek(parser, 1, 0, &mut cluster, null_mut()) => BadParam,
+            block_info(null_mut(), cluster, entry, &mut zeroed()) => BadParam,
+            block_info(parser, null(), entry, &mut zeroed()) => BadParam,
+            block_info(parser, cluster, null(), &mut zeroed()) => BadParam,
+            block_info(parser, cluster, entry, null_mut()) => BadParam,
+            block_addition_count(null_mut(), cluster, entry, &mut count) => BadParam,
+            block_addition_count(parser, null(), entry, &mut count) => BadParam,
+            block_addition_count(parser, cluster, null(), &mut count) => BadParam,
+            block_addition_count(parser, cluster, entry, null_mut()) => BadParam,
+            block_addition(null_mut(), cluster, entry, 0, &mut zeroed()) => BadParam,
+            block_addition(parser, null(), entry, 0, &mut zeroed()) => BadParam,
+            block_addition(parser, cluster, null(), 0, &mut zeroed()) => BadParam,
+            block_addition(parser, cluster, entry, 0, null_mut()) => BadParam,
+            block_addition(parser, cluster, entry, 0, &mut zeroed()) => BadParam,
+            block_discard_padding(null_mut(), cluster, entry, &mut present, &mut pos) => BadParam,
+            block_discard_padding(parser, null(), entry, &mut present, &mut pos) => BadParam,
+            block_discard_padding(parser, cluster, null(), &mut present, &mut pos) => BadParam,
+            block_discard_padding(parser, cluster, entry, null_mut(), &mut pos) => BadParam,
+            block_discard_padding(parser, cluster, entry, &mut present, null_mut()) => BadParam,
+            block_frame(null_mut(), entry, 0, &mut pos, &mut len) => BadParam,
+            block_frame(parser, null(), 0, &mut pos, &mut len) => BadParam,
+            block_frame(parser, entry, -1, &mut pos, &mut len) => BadParam,
+            block_frame(parser, entry, 1, &mut pos, &mut len) => BadParam,
+            block_frame(parser, entry, 0, null_mut(), &mut len) => BadParam,
+            block_frame(parser, entry, 0, &mut pos, null_mut()) => BadParam,
+            read_frame(null_mut(), entry, 0, buffer.as_mut_ptr(), buffer.len()) => BadParam,
+            read_frame(parser, null(), 0, buffer.as_mut_ptr(), buffer.len()) => BadParam,
+            read_frame(parser, entry, 1, buffer.as_mut_ptr(), buffer.len()) => BadParam,
+            read_frame(parser, entry, 0, null_mut(), buffer.len()) => BadParam,
+            read_frame(parser, entry, 0, buffer.as_mut_ptr(), 0) => BadParam,
+            abi_binding(usize::MAX, null_mut()).is_null() => true,
+            abi_field(usize::MAX, null_mut(), null_mut(), null_mut(), null_mut()).is_null() => true,
+        }
+    }
+
+    unsafe {
+        parser::delete_parser(parser);
+        parser::delete_parser(unopened);
+        parser::delete_parser(null_mut());
+    }
+}
+
+/// Describes Rust types in the notation of abi.h, to check the declarations above against the adapter.
+#[cfg(test)]
+mod abi {
+    pub trait Abi {
+        fn write(out: &mut String);
+
+        fn describe() -> String {
+            let mut out = String::new();
+            Self::write(&mut out);
+            out
+        }
+    }
+
+    macro_rules! scalars {
+        ($($ty:ty => $kind:literal,)*) => {
+            $(impl Abi for $ty {
+                fn write(out: &mut String) {
+                    out.push($kind);
+                    out.push_str(&core::mem::size_of::<$ty>().to_string());
+                }
+            })*
+        };
+    }
+    scalars! {
+        bool => 'b',
+        i8 => 'i',
+        i16 => 'i',
+        i32 => 'i',
+        i64 => 'i',
+        isize => 'i',
+        u8 => 'u',
+        u16 => 'u',
+        u32 => 'u',
+        u64 => 'u',
+        usize => 'u',
+        f32 => 'f',
+        f64 => 'f',
+        super::mux::ResultCode => 'i',
+        super::parser::ResultCode => 'i',
+    }
+
+    impl Abi for () {
+        fn write(out: &mut String) {
+            out.push('v');
+        }
+    }
+
+    impl<T> Abi for *const T {
+        fn write(out: &mut String) {
+            out.push_str(&format!("p{}", core::mem::size_of::<Self>()));
+        }
+    }
+
+    impl<T> Abi for *mut T {
+        fn write(out: &mut String) {
+            out.push_str(&format!("p{}", core::mem::size_of::<Self>()));
+        }
+    }
+
+    macro_rules! functions {
+        ($($arg:ident)*) => {
+            impl<R: Abi, $($arg: Abi),*> Abi for unsafe extern "C" fn($($arg),*) -> R {
+                fn write(out: &mut String) {
+                    let args: &[fn(&mut String)] = &[$($arg::write),*];
+                    out.push('(');
+                    for (i, arg) in args.iter().enumerate() {
+                        if i != 0 {
+                            out.push(',');
+                        }
+                        arg(out);
+                    }
+                    out.push(')');
+                    R::write(out);
+                }
+            }
+
+            /// Callbacks
+            impl<R: Abi, $($arg: Abi),*> Abi for Option<extern "C" fn($($arg),*) -> R> {
+                fn write(out: &mut String) {
+                    out.push('*');
+                    <unsafe extern "C" fn($($arg),*) -> R>::write(out);
+                }
+            }
+        };
+    }
+    functions!();
+    functions!(A);
+    functions!(A B);
+    functions!(A B C);
+    functions!(A B C D);
+    functions!(A B C D E);
+    functions!(A B C D E F);
+    functions!(A B C D E F G);
+    functions!(A B C D E F G H);
+    functions!(A B C D E F G H I);
+    functions!(A B C D E F G H I J);
+    functions!(A B C D E F G H I J K);
+}
+
+/// Checks every declaration against the signature the adapter was compiled with, so that a mismatched integer width
+/// or a missed parameter fails here rather than corrupting memory.
+#[test]
+fn declarations_match_the_adapter() {
+    use core::ffi::{c_char, CStr};
+
+    type Describe = unsafe extern "C" fn(usize, *mut *const c_char) -> *const c_char;
+    let modules =
+        [("mux", mux::BINDINGS, mux::abi_binding as Describe), ("parser", parser::BINDINGS, parser::abi_binding)];
+    for (module, bindings, describe) in modules {
+        let mut exported = Vec::new();
+        loop {
+            let mut name = core::ptr::null();
+            let signature = unsafe { describe(exported.len(), &mut name) };
+            if signature.is_null() {
+                break;
+            }
+            let (name, signature) = unsafe { (CStr::from_ptr(name), CStr::from_ptr(signature)) };
+            exported.push((name.to_str().unwrap(), signature.to_str().unwrap()));
+        }
+
+        for &(name, describe) in bindings {
+            let Some(&(_, signature)) = exported.iter().find(|&&(exported, _)| exported == name) else {
+                panic!("{name} is declared in {module}, but isn't listed in the adapter's bindings");
+            };
+            assert_eq!(describe(), signature, "the declaration of {name} doesn't match the adapter");
+        }
+        for &(name, _) in &exported {
+            assert!(bindings.iter().any(|&(declared, _)| declared == name), "{name} isn't declared in {module}");
+        }
+    }
+}
+
+/// Checks the layout of each struct the parser fills in against the adapter's.
+#[test]
+fn structs_match_the_adapter() {
+    use core::ffi::CStr;
+    use core::mem::{offset_of, size_of};
+
+    fn field_size<T, F>(_: impl Fn(&T) -> &F) -> usize {
+        size_of::<F>()
+    }
+
+    macro_rules! fields {
+        ($($ty:ident { $($field:ident,)* })*) => {
+            vec![$($(
+                (
+                    stringify!($ty),
+                    stringify!($field),
+                    offset_of!(parser::$ty, $field),
+                    field_size(|info: &parser::$ty| &info.$field),
+                    size_of::<parser::$ty>(),
+                ),
+            )*)*]
+        };
+    }
+    let declared = fields! {
+        TrackInfo {
+            number, uid, track_type, codec_id, codec_private, codec_private_len, default_duration, codec_delay,
+            seek_pre_roll, max_block_addition_id, name, language, language_ietf_pos, language_ietf_len, width, height,
+            display_width, display_height, sampling_rate, channels, bit_depth,
+        }
+        ColourInfo {
+            matrix_coefficients, bits_per_channel, chroma_subsampling_horz, chroma_subsampling_vert,
+            cb_subsampling_horz, cb_subsampling_vert, chroma_siting_horz, chroma_siting_vert, range,
+            transfer_characteristics, primaries, max_cll, max_fall, has_mastering_metadata, chromaticities,
+            luminance_max, luminance_min,
+        }
+        ProjectionInfo {
+            projection_type, private_data, private_data_len, has_pose_yaw, pose_yaw, has_pose_pitch, pose_pitch,
+            has_pose_roll, pose_roll,
+        }
+        EbmlHeader {
+            version, read_version, max_id_length, max_size_length, doc_type, doc_type_version, doc_type_read_version,
+        }
+        SegmentInfo {
+            timecode_scale, duration_ns, muxing_app, writing_app, title, has_date_utc, date_utc, has_segment_uid,
+            segment_uid,
+        }
+        ClusterInfo {
+            time_ns, element_start, element_size,
+        }
+        BlockInfo {
+            track_number, time_ns, keyframe, frame_count, duration_ns,
+        }
+        BlockAddition {
+            id, pos, len,
+        }
+    };
+
+    let mut exported = Vec::new();
+    loop {
+        let (mut field, mut offset, mut size, mut type_size) = (core::ptr::null(), 0, 0, 0);
+        let ty = unsafe { parser::abi_field(exported.len(), &mut field, &mut offset, &mut size, &mut type_size) };
+        if ty.is_null() {
+            break;
+        }
+        let (ty, field) = unsafe { (CStr::from_ptr(ty), CStr::from_ptr(field)) };
+        exported.push((ty.to_str().unwrap(), field.to_str().unwrap(), offset, size, type_size));
+    }
+    assert_eq!(declared, exported);
+}
+
+/// Round-trips a struct of every scalar type through the adapter, both as fields and as arguments.
+#[test]
+fn round_trips_abi_probe() {
+    let data = [0u8; 2];
+    let mut probe = mux::AbiProbe {
+        u8: 0xA5,
+        flag: true,
+        i32: -0x1234_5678,
+        u32: 0x8765_4321,
+        i64: -0x1234_5678_9ABC_DEF0,
+        u64: 0xFEDC_BA98_7654_3210,
+        size: usize::MAX / 3,
+        f32: 1.5,
+        f64: -2.25,
+        ptr: data.as_ptr(),
+    };
+    let p = probe;
+    let result = unsafe {
+        mux::abi_probe(&mut probe, p.u8, p.flag, p.i32, p.u32, p.i64, p.u64, p.size, p.f32, p.f64, p.ptr)
+    };
+    assert_eq!(result, mux::ResultCode::Ok);
+
+    let expected = mux::AbiProbe {
+        u8: 0xA6,
+        flag: false,
+        i32: 0x1234_5678,
+        u32: !0x8765_4321,
+        i64: 0x1234_5678_9ABC_DEF0,
+        u64: !0xFEDC_BA98_7654_3210,
+        size: usize::MAX / 3 * 2,
+        f32: 3.0,
+        f64: 2.25,
+        ptr: data[1..].as_ptr(),
+    };
+    assert_eq!(probe, expected);
+
+    // Any argument that doesn't match its field is rejected
+    let result = unsafe {
+        mux::abi_probe(&mut probe, p.u8, p.flag, p.i32, p.u32, p.i64, p.u64, p.size, p.f32, p.f64, p.ptr)
+    };
+    assert_eq!(result, mux::ResultCode::BadParam);
+}